@@ -0,0 +1,118 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The stable library interface of the bicycle architecture compiler.
+//!
+//! This crate re-exports the parts of the `bicycle_*` crates that downstream code needs
+//! to compile PBC circuits and compute their numerics.
+//! Items are re-exported by name, so that additions to the underlying crates do not enter this interface by accident,
+//! and a breaking change to any item here requires a new major version.
+//! Everything else in the underlying crates may change in any release.
+
+pub use bicycle_cliffords::{
+    CompleteMeasurementTable, MeasurementChoices, MeasurementTableBuilder,
+};
+pub use bicycle_common::{
+    AutomorphismData, BicycleISA, Pauli, SGateData, TGateData, TGatePairData, TwoBases,
+};
+pub use bicycle_compiler::{
+    BlockRole, PathArchitecture, SGateLowering, deserialize_table,
+    language::{AnglePrecision, PbcOperation, check_barrier, check_operation},
+    operation::Operation,
+    qasm::PbcBuilder,
+    small_angle::check_accuracy,
+};
+pub use bicycle_numerics::{
+    IsaCounter, NumericsError, OutputData,
+    model::{
+        ErrorPrecision, FAKE_SLOW, GROSS_1E3, GROSS_1E4, InstructionCost, Model, ModelFile,
+        ModelFormat, TWO_GROSS_1E3, TWO_GROSS_1E4,
+    },
+    run_numerics,
+};
+
+/// Passes that simplify compiled instructions
+pub mod optimize {
+    pub use bicycle_compiler::optimize::{
+        Scheduler, remove_duplicate_measurements, remove_duplicate_measurements_chunked,
+        remove_trivial_automorphisms, schedule_chunked,
+    };
+}
+
+/// Compile a PBC operation to bicycle instructions on the blocks of `architecture`,
+/// synthesizing small-angle rotations up to `accuracy`
+pub fn compile(
+    op: &PbcOperation,
+    architecture: &PathArchitecture,
+    measurement_table: &CompleteMeasurementTable,
+    accuracy: AnglePrecision,
+) -> Vec<Operation> {
+    op.compile(architecture, measurement_table, accuracy)
+}
+
+/// Build the complete measurement table of a code from all of its native measurements,
+/// which takes minutes, so a table is usually generated once with `bicycle_compiler generate` and loaded with [`deserialize_table`]
+pub fn build_measurement_table(code: MeasurementChoices) -> CompleteMeasurementTable {
+    let mut builder = MeasurementTableBuilder::new(
+        bicycle_cliffords::native_measurement::NativeMeasurement::all(),
+        code.measurement(),
+    );
+    builder.build();
+    builder
+        .complete()
+        .expect("All native measurements should implement every Pauli measurement")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_signature() {
+        // Changing the signature of the stable interface is a breaking change
+        let _: fn(
+            &PbcOperation,
+            &PathArchitecture,
+            &CompleteMeasurementTable,
+            AnglePrecision,
+        ) -> Vec<Operation> = compile;
+    }
+
+    #[test]
+    fn optimize_and_run_numerics() {
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let chunks = vec![
+            vec![
+                vec![(0, BicycleISA::Automorphism(AutomorphismData::new(0, 0)))],
+                vec![(0, BicycleISA::Measure(z))],
+            ],
+            vec![vec![(0, BicycleISA::Measure(z))]],
+        ];
+        let optimized = optimize::remove_duplicate_measurements_chunked(
+            chunks
+                .into_iter()
+                .map(|chunk| optimize::remove_trivial_automorphisms(chunk).collect::<Vec<_>>()),
+        );
+
+        let architecture = PathArchitecture::for_qubits(11);
+        let outputs: Vec<OutputData> = run_numerics(optimized, architecture, GROSS_1E3)
+            .collect::<Result<_, NumericsError>>()
+            .unwrap();
+        assert_eq!(2, outputs.len());
+        assert_eq!(1, outputs[0].measurements);
+        assert_eq!(0, outputs[0].automorphisms);
+        // The repeated measurement was removed
+        assert_eq!(0, outputs[1].measurements);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured circuits whose locality resembles real programs, unlike uniformly random rotations.
+//!
+//! The circuits are built gate by gate with a [`PbcBuilder`], which absorbs the Clifford gates into the Pauli frame,
+//! such that the programs only consist of the non-Clifford rotations and CCZ gates.
+
+use std::f64::consts::PI;
+
+use bicycle_compiler::{language::PbcOperation, qasm::PbcBuilder};
+
+/// The quantum Fourier transform on `qubits` qubits, including the final swaps
+pub fn qft(qubits: usize) -> Vec<PbcOperation> {
+    let mut builder = PbcBuilder::new(qubits);
+    let mut gate = |name, operands: &[usize], params: &[f64]| {
+        builder
+            .gate(name, operands, params)
+            .expect("The gates of the QFT are supported");
+    };
+    for target in 0..qubits {
+        gate("h", &[target], &[]);
+        for control in target + 1..qubits {
+            let angle = PI / f64::powi(2.0, (control - target) as i32);
+            gate("cp", &[control, target], &[angle]);
+        }
+    }
+    for qubit in 0..qubits / 2 {
+        gate("swap", &[qubit, qubits - 1 - qubit], &[]);
+    }
+    builder.finish()
+}
+
+/// The interactions of a spin chain that Trotter steps simulate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinChain {
+    /// The transverse-field Ising model, H = Σ Z_i Z_{i+1} + Σ X_i
+    Ising,
+    /// The isotropic Heisenberg model, H = Σ X_i X_{i+1} + Y_i Y_{i+1} + Z_i Z_{i+1}
+    Heisenberg,
+}
+
+/// First-order Trotter steps of time `dt` of a spin chain with open boundaries on `qubits` qubits
+pub fn trotter(chain: SpinChain, qubits: usize, steps: usize, dt: f64) -> Vec<PbcOperation> {
+    let mut builder = PbcBuilder::new(qubits);
+    let mut gate = |name, operands: &[usize]| {
+        builder
+            .gate(name, operands, &[2.0 * dt])
+            .expect("The rotations of a Trotter step are supported");
+    };
+    for _ in 0..steps {
+        for qubit in 1..qubits {
+            let bond = [qubit - 1, qubit];
+            match chain {
+                SpinChain::Ising => gate("rzz", &bond),
+                SpinChain::Heisenberg => {
+                    gate("rxx", &bond);
+                    gate("ryy", &bond);
+                    gate("rzz", &bond);
+                }
+            }
+        }
+        if chain == SpinChain::Ising {
+            for qubit in 0..qubits {
+                gate("rx", &[qubit]);
+            }
+        }
+    }
+    builder.finish()
+}
+
+/// The ripple-carry adder of Cuccaro et al. (arXiv:quant-ph/0410184) that adds two registers of `bits` bits,
+/// `b += a`, on `2 * bits + 2` qubits.
+/// Qubit 0 is the incoming carry, then follow `a`, `b`, and the outgoing carry, each starting at the least significant bit.
+pub fn ripple_carry_adder(bits: usize) -> Vec<PbcOperation> {
+    assert!(bits > 0, "The registers of an adder need at least one bit");
+    let a = |i: usize| 1 + i;
+    let b = |i: usize| 1 + bits + i;
+    let carry = 2 * bits + 1;
+    let mut builder = PbcBuilder::new(2 * bits + 2);
+    let mut gate = |name, operands: &[usize]| {
+        builder
+            .gate(name, operands, &[])
+            .expect("The gates of the adder are supported");
+    };
+    // The carry into bit i is held by qubit 0 for the first bit and by a_{i-1} afterwards
+    let carry_in = |i: usize| if i == 0 { 0 } else { a(i - 1) };
+    for i in 0..bits {
+        // MAJ
+        gate("cx", &[a(i), b(i)]);
+        gate("cx", &[a(i), carry_in(i)]);
+        gate("ccx", &[carry_in(i), b(i), a(i)]);
+    }
+    gate("cx", &[a(bits - 1), carry]);
+    for i in (0..bits).rev() {
+        // UMA
+        gate("ccx", &[carry_in(i), b(i), a(i)]);
+        gate("cx", &[a(i), carry_in(i)]);
+        gate("cx", &[carry_in(i), b(i)]);
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::Pauli;
+
+    use super::*;
+
+    fn weights(ops: &[PbcOperation]) -> Vec<usize> {
+        ops.iter()
+            .map(|op| op.basis().iter().filter(|p| **p != Pauli::I).count())
+            .collect()
+    }
+
+    #[test]
+    fn qft_rotations() {
+        let ops = qft(5);
+        // One controlled phase per pair of qubits, and the Hadamard and swap gates are absorbed
+        assert_eq!(5 * 4 / 2, ops.len());
+        assert!(ops.iter().all(|op| op.basis().len() == 5));
+        assert!(
+            ops.iter()
+                .all(|op| matches!(op, PbcOperation::MultiRotation { terms } if terms.len() == 3))
+        );
+    }
+
+    #[test]
+    fn trotter_steps() {
+        let ising = trotter(SpinChain::Ising, 6, 2, 0.1);
+        assert_eq!(2 * (5 + 6), ising.len());
+        assert_eq!(vec![2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1], weights(&ising[..11]));
+
+        let heisenberg = trotter(SpinChain::Heisenberg, 6, 3, 0.1);
+        assert_eq!(3 * 3 * 5, heisenberg.len());
+        assert!(weights(&heisenberg).iter().all(|&weight| weight == 2));
+    }
+
+    #[test]
+    fn adder_toffolis() {
+        for bits in 1..5 {
+            let ops = ripple_carry_adder(bits);
+            // The CNOTs are absorbed, and every MAJ and UMA has a Toffoli,
+            // which is lowered to seven T rotations if the frame takes its qubits out of the Z basis
+            assert_eq!(2 * bits, ops.len());
+            assert!(ops.iter().all(|op| match op {
+                PbcOperation::Ccz { .. } => true,
+                PbcOperation::MultiRotation { terms } => terms.len() == 7,
+                _ => false,
+            }));
+            assert!(ops.iter().all(|op| op.basis().len() == 2 * bits + 2));
+        }
+    }
+}
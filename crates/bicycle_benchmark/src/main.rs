@@ -19,23 +19,86 @@ use std::{
 
 use log::debug;
 
-use bicycle_compiler::language::AnglePrecision;
-use clap::Parser;
+use bicycle_benchmark::{
+    circuits::{self, SpinChain},
+    random,
+};
+use bicycle_compiler::language::{AnglePrecision, PbcOperation};
+use clap::{Parser, ValueEnum};
+
+/// The kinds of circuits to generate
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Circuit {
+    /// An infinite stream of rotations by π/4 about uniformly random Paulis
+    #[default]
+    Random,
+    /// The quantum Fourier transform
+    Qft,
+    /// Trotter steps of the transverse-field Ising chain
+    Ising,
+    /// Trotter steps of the Heisenberg chain
+    Heisenberg,
+    /// A ripple-carry adder of two registers of `(qubits - 2) / 2` bits
+    Adder,
+}
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 struct Cli {
     /// Number of logical qubits
-    qubits: usize,
+    #[arg(
+        required_unless_present = "qubits_option",
+        conflicts_with = "qubits_option"
+    )]
+    qubits: Option<usize>,
+    /// Number of logical qubits, instead of the positional argument
+    #[arg(long = "qubits", id = "qubits_option", value_name = "QUBITS")]
+    qubits_option: Option<usize>,
+    /// The circuit to print
+    #[arg(long, value_enum, default_value_t)]
+    circuit: Circuit,
+    /// Seed of the random circuit, such that runs with the same seed print the same circuit [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Number of Trotter steps of the Ising and Heisenberg circuits
+    #[arg(long, default_value_t = 1)]
+    steps: usize,
+    /// Time of a Trotter step
+    #[arg(long, default_value_t = 0.1)]
+    dt: f64,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
     let cli = Cli::parse();
-    assert!(cli.qubits > 0);
+    let qubits = cli.qubits.or(cli.qubits_option).unwrap();
+    if qubits == 0 {
+        return Err("The circuit needs at least one qubit".into());
+    }
     let cliff_angle = AnglePrecision::PI / AnglePrecision::lit("4.0");
-    let mut measurements = bicycle_benchmark::random::random_rotations(cli.qubits, cliff_angle);
+    let mut measurements: Box<dyn Iterator<Item = PbcOperation>> = match cli.circuit {
+        Circuit::Random => match cli.seed {
+            Some(seed) => Box::new(random::random_rotations_seeded(qubits, cliff_angle, seed)),
+            None => Box::new(random::random_rotations(qubits, cliff_angle)),
+        },
+        Circuit::Qft => Box::new(circuits::qft(qubits).into_iter()),
+        Circuit::Ising => {
+            Box::new(circuits::trotter(SpinChain::Ising, qubits, cli.steps, cli.dt).into_iter())
+        }
+        Circuit::Heisenberg => Box::new(
+            circuits::trotter(SpinChain::Heisenberg, qubits, cli.steps, cli.dt).into_iter(),
+        ),
+        Circuit::Adder => {
+            if qubits < 4 || qubits % 2 != 0 {
+                return Err(format!(
+                    "An adder acts on an even number of at least 4 qubits, not {qubits}"
+                )
+                .into());
+            }
+            Box::new(circuits::ripple_carry_adder((qubits - 2) / 2).into_iter())
+        }
+    };
 
     let mut stdout = io::stdout();
     // Stop on first error
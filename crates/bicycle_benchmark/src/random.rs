@@ -15,21 +15,60 @@
 use bicycle_common::Pauli;
 use bicycle_compiler::language::{AnglePrecision, PbcOperation};
 
-use rand::distr::{Distribution, StandardUniform};
+use rand::{
+    Rng, SeedableRng,
+    distr::{Distribution, StandardUniform},
+    rngs::StdRng,
+};
 
 /// Generate random circuit with non-trivial rotations, equivalent to a Clifford+T circuit
 pub fn random_rotations(
     qubits: usize,
     angle: AnglePrecision,
 ) -> impl Iterator<Item = PbcOperation> {
-    random_pauli_strings(qubits)
-        .map(move |ps| PbcOperation::Rotation { basis: ps, angle })
+    random_rotations_with_rng(qubits, angle, rand::rng())
+}
+
+/// Generate the random rotations of a seed, which are the same in every run
+pub fn random_rotations_seeded(
+    qubits: usize,
+    angle: AnglePrecision,
+    seed: u64,
+) -> impl Iterator<Item = PbcOperation> {
+    random_rotations_with_rng(qubits, angle, StdRng::seed_from_u64(seed))
+}
+
+/// Generate random rotations using the given random number generator, e.g., to reproduce a circuit from a seed
+pub fn random_rotations_with_rng<R: Rng>(
+    qubits: usize,
+    angle: AnglePrecision,
+    rng: R,
+) -> impl Iterator<Item = PbcOperation> {
+    random_pauli_strings_with_rng(qubits, rng)
+        .map(move |ps| PbcOperation::Rotation {
+            basis: ps,
+            angle,
+            accuracy: None,
+        })
         .filter(|rotation| !rotation.basis().iter().all(|p| *p == Pauli::I))
 }
 
 /// Generate an infinite iterator of random measurements
 pub fn random_measurements(qubits: usize) -> impl Iterator<Item = PbcOperation> {
-    random_pauli_strings(qubits)
+    random_measurements_with_rng(qubits, rand::rng())
+}
+
+/// Generate the random measurements of a seed, which are the same in every run
+pub fn random_measurements_seeded(qubits: usize, seed: u64) -> impl Iterator<Item = PbcOperation> {
+    random_measurements_with_rng(qubits, StdRng::seed_from_u64(seed))
+}
+
+/// Generate random measurements using the given random number generator
+pub fn random_measurements_with_rng<R: Rng>(
+    qubits: usize,
+    rng: R,
+) -> impl Iterator<Item = PbcOperation> {
+    random_pauli_strings_with_rng(qubits, rng)
         .map(|ps| PbcOperation::Measurement {
             basis: ps,
             flip_result: false,
@@ -39,7 +78,22 @@ pub fn random_measurements(qubits: usize) -> impl Iterator<Item = PbcOperation>
 }
 
 pub fn random_pauli_strings(qubits: usize) -> impl Iterator<Item = Vec<Pauli>> {
-    random_paulis()
+    pauli_strings(random_paulis(), qubits)
+}
+
+pub fn random_pauli_strings_with_rng<R: Rng>(
+    qubits: usize,
+    rng: R,
+) -> impl Iterator<Item = Vec<Pauli>> {
+    pauli_strings(random_paulis_with_rng(rng), qubits)
+}
+
+/// Group the Paulis into strings on `qubits` qubits
+fn pauli_strings(
+    paulis: impl Iterator<Item = Pauli>,
+    qubits: usize,
+) -> impl Iterator<Item = Vec<Pauli>> {
+    paulis
         .scan(vec![], move |buf, p| {
             buf.push(p);
             if buf.len() == qubits {
@@ -54,13 +108,15 @@ pub fn random_pauli_strings(qubits: usize) -> impl Iterator<Item = Vec<Pauli>> {
 }
 
 fn random_paulis() -> impl Iterator<Item = Pauli> {
-    let rng = rand::rng();
+    random_paulis_with_rng(rand::rng())
+}
+
+fn random_paulis_with_rng<R: Rng>(rng: R) -> impl Iterator<Item = Pauli> {
     StandardUniform.sample_iter(rng)
 }
 
 #[cfg(test)]
 mod tests {
-
     use super::*;
 
     #[test]
@@ -68,6 +124,31 @@ mod tests {
         let _ps: Vec<_> = random_paulis().take(100).collect();
     }
 
+    #[test]
+    fn seeded_rotations_are_reproducible() {
+        let angle = AnglePrecision::lit("0.1");
+        let rotations = |seed| {
+            random_rotations_with_rng(20, angle, StdRng::seed_from_u64(seed))
+                .take(10)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rotations(1), rotations(1));
+        assert_ne!(rotations(1), rotations(2));
+        let seeded: Vec<_> = random_rotations_seeded(20, angle, 1).take(10).collect();
+        assert_eq!(rotations(1), seeded);
+    }
+
+    #[test]
+    fn seeded_measurements_are_reproducible() {
+        let measurements = |seed| {
+            random_measurements_seeded(20, seed)
+                .take(10)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(measurements(1), measurements(1));
+        assert_ne!(measurements(1), measurements(2));
+    }
+
     #[test]
     fn test_random_rotations() {
         for qubits in 1..100 {
@@ -77,6 +158,7 @@ mod tests {
                 if let PbcOperation::Rotation {
                     basis,
                     angle: rot_angle,
+                    ..
                 } = instruction
                 {
                     assert!(!basis.iter().all(|p| *p == Pauli::I));
@@ -0,0 +1,18 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! End-to-end tests of the command-line tools, see `tests/cli_integration.rs`.
+//!
+//! This package depends on the packages with a binary that also have a library,
+//! and the tests build the binaries of all of them before running them.
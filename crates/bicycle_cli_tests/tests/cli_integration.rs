@@ -0,0 +1,621 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run the `bicycle_benchmark | bicycle_compiler | bicycle_numerics` pipeline over pipes.
+//! Also check how the compiler and the numerics handle malformed input, bases that need padding, disabled automorphisms,
+//! operations on more blocks than the first one, an output that cannot be written, and the document formats of compiled programs.
+//! The structured circuits of the benchmark are compiled to a resource report.
+//! Finally, check the snapshot rows of the random-circuit numerics of the gross code.
+//!
+//! Cargo only builds the binaries of the package under test,
+//! so the binaries of the other packages are built with `cargo build` before the first test runs them.
+//! The gross code measurement table is generated once and kept in the target directory between runs,
+//! until the compiler is rebuilt.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use assert_cmd::{assert::OutputAssertExt, cargo::CommandCargoExt};
+
+const QUBITS: usize = 22;
+const ROWS: usize = 5;
+const HEADER: &str = "code,p,i,qubits,idles,t_injs,automorphisms,measurements,joint_measurements,measurement_depth,end_time,total_error";
+const PACKAGES: [&str; 4] = [
+    "bicycle_benchmark",
+    "bicycle_compiler",
+    "bicycle_numerics",
+    "bicycle_random_numerics",
+];
+
+/// Build the binaries of the workspace once, in the profile of this test
+fn build_binaries() {
+    static BUILT: OnceLock<()> = OnceLock::new();
+    BUILT.get_or_init(|| {
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let mut build = Command::new(cargo);
+        build
+            .arg("build")
+            .args(PACKAGES.iter().flat_map(|package| ["-p", package]))
+            .arg("--bins");
+        // The test profile shares the output directory of the dev profile
+        build.args([
+            "--profile",
+            if cfg!(debug_assertions) {
+                "test"
+            } else {
+                "release"
+            },
+        ]);
+        let status = build.status().unwrap();
+        assert!(status.success(), "Could not build the binaries: {status}");
+    });
+}
+
+fn binary(name: &str) -> Command {
+    build_binaries();
+    Command::cargo_bin(name).unwrap()
+}
+
+/// Generate the gross code measurement table, unless it is newer than the compiler, and share it between tests
+fn measurement_table() -> &'static PathBuf {
+    static TABLE: OnceLock<PathBuf> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_integration_table_gross");
+        let compiler = binary("bicycle_compiler");
+        let modified = |path: &std::path::Path| path.metadata().and_then(|m| m.modified()).ok();
+        let cached = modified(&path)
+            .zip(modified(std::path::Path::new(compiler.get_program())))
+            .is_some_and(|(table, compiler)| table > compiler);
+        if !cached {
+            // Generate into a temporary file so that an interrupted run does not leave a partial table
+            let partial = path.with_extension("partial");
+            assert_cmd::Command::from_std(compiler)
+                .args(["gross", "generate"])
+                .arg(&partial)
+                .assert()
+                .success();
+            std::fs::rename(&partial, &path).unwrap();
+        }
+        path
+    })
+}
+
+/// Pipe random rotations through the compiler and the numerics, and return the CSV output
+fn run_pipeline(numerics_args: &[&str]) -> String {
+    let mut benchmark = binary("bicycle_benchmark")
+        .arg(QUBITS.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut compiler = binary("bicycle_compiler")
+        .arg("gross")
+        .arg("--measurement-table")
+        .arg(measurement_table())
+        .stdin(benchmark.stdout.take().unwrap())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The numerics stop after `ROWS` rows, which closes the pipes of the previous stages
+    let output = binary("bicycle_numerics")
+        .arg(QUBITS.to_string())
+        .args(numerics_args)
+        .args(["--max-iter", &ROWS.to_string()])
+        .stdin(compiler.stdout.take().unwrap())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    compiler.wait().unwrap();
+    benchmark.wait().unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Parse the `total_error` column of each row
+fn total_errors(csv: &str) -> Vec<f64> {
+    csv.lines()
+        .skip(1)
+        .map(|row| row.rsplit(',').next().unwrap().parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn benchmark_compile_numerics() {
+    let csv = run_pipeline(&["gross_1e-3"]);
+
+    let mut lines = csv.lines();
+    assert_eq!(Some(HEADER), lines.next());
+    assert_eq!(ROWS, lines.clone().count());
+    for (i, row) in lines.enumerate() {
+        assert!(
+            row.starts_with(&format!("gross,0.001,{},{QUBITS},", i + 1)),
+            "Unexpected row {row}"
+        );
+    }
+
+    let errors = total_errors(&csv);
+    assert!(errors[0] > 0.0);
+    assert!(
+        errors.windows(2).all(|w| w[0] < w[1]),
+        "The total error should increase with every rotation: {errors:?}"
+    );
+}
+
+#[test]
+fn model_file_pipeline() {
+    let model_file = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../bicycle_numerics/models/gross_1e-3.json"
+    );
+    let csv = run_pipeline(&["--model-file", model_file]);
+
+    let mut lines = csv.lines();
+    assert_eq!(Some(HEADER), lines.next());
+    assert_eq!(ROWS, lines.count());
+    let errors = total_errors(&csv);
+    assert!(errors.windows(2).all(|w| w[0] < w[1]), "{errors:?}");
+}
+
+#[test]
+fn stim_export_pipeline() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_integration.stim");
+    let csv = run_pipeline(&["gross_1e-3", "--stim", path.to_str().unwrap()]);
+    assert_eq!(ROWS + 1, csv.lines().count());
+
+    let circuit = std::fs::read_to_string(&path).unwrap();
+    assert!(circuit.lines().any(|line| line.starts_with("MPP ")));
+    assert!(circuit.lines().any(|line| line.starts_with("DEPOLARIZE1(")));
+    assert!(circuit.ends_with("TICK\n"));
+}
+
+#[test]
+fn sampled_error_pipeline() {
+    let csv = run_pipeline(&["gross_1e-3", "--sample", "200", "--sample-seed", "1"]);
+    let mut lines = csv.lines();
+    assert_eq!(
+        Some(format!(
+            "{HEADER},sampled_error,sampled_error_low,sampled_error_high"
+        ))
+        .as_deref(),
+        lines.next()
+    );
+    let rows: Vec<_> = lines.collect();
+    assert_eq!(ROWS, rows.len());
+    // Only the last row has the sampled error of the program
+    assert!(rows[..ROWS - 1].iter().all(|row| row.ends_with(",,,")));
+    let sampled: Vec<f64> = rows[ROWS - 1]
+        .rsplit(',')
+        .take(3)
+        .map(|value| value.parse().unwrap())
+        .collect();
+    let (high, low, rate) = (sampled[0], sampled[1], sampled[2]);
+    assert!(low <= rate && rate <= high, "{sampled:?}");
+}
+
+#[test]
+fn malformed_input_keeps_prefix() {
+    let mut benchmark = binary("bicycle_benchmark")
+        .arg(QUBITS.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let valid: Vec<String> = BufReader::new(benchmark.stdout.take().unwrap())
+        .lines()
+        .take(2)
+        .map(Result::unwrap)
+        .collect();
+    benchmark.wait().unwrap();
+    let input = format!(
+        "{}\n{}\n{{\"Rotation\": oops\n{}\n",
+        valid[0], valid[1], valid[0]
+    );
+
+    let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+    let output = compiler
+        .arg("gross")
+        .arg("--measurement-table")
+        .arg(measurement_table())
+        .env("RUST_LOG", "off")
+        .write_stdin(input)
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+
+    // The operations before the malformed one are compiled and followed by the record
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(3, stdout.lines().count());
+    let record: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(2, record["index"]);
+    assert_eq!(3, record["line"]);
+
+    // Valid JSON of an operation that cannot be compiled is malformed as well
+    let identity =
+        serde_json::json!({"Measurement": {"basis": vec!["I"; QUBITS], "flip_result": false}});
+    let barrier = serde_json::json!({"Barrier": {"blocks": [7]}});
+    for (op, error) in [(identity, "identity"), (barrier, "block 7")] {
+        let output = assert_cmd::Command::from_std(binary("bicycle_compiler"))
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .env("RUST_LOG", "off")
+            .write_stdin(format!("{}\n{op}\n", valid[0]))
+            .assert()
+            .code(3)
+            .get_output()
+            .clone();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(2, stdout.lines().count());
+        let record: serde_json::Value =
+            serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+        assert_eq!(1, record["index"]);
+        assert!(record["error"].as_str().unwrap().contains(error));
+    }
+}
+
+#[test]
+fn structured_circuit_report() {
+    let benchmark = binary("bicycle_benchmark")
+        .args(["--circuit", "adder", "--qubits", "22"])
+        .output()
+        .unwrap();
+    assert!(benchmark.status.success());
+    assert_eq!(
+        20,
+        String::from_utf8_lossy(&benchmark.stdout).lines().count()
+    );
+
+    let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+    let output = compiler
+        .arg("gross")
+        .arg("--measurement-table")
+        .arg(measurement_table())
+        .args(["report", "--json"])
+        .env("RUST_LOG", "off")
+        .write_stdin(benchmark.stdout)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(20, report["operations"]);
+    assert_eq!(22, report["logical_qubits"]);
+    // Each Toffoli is lowered to seven T rotations
+    assert_eq!(20 * 7, report["t_count"]);
+    assert!(report["cycles"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn padding_is_opt_in() {
+    let rotation = r#"{"Rotation":{"basis":["X","I","I","I","I","I","I","I","I","I","I","Z"],"angle":"0.78539816339744830961566084581"}}"#;
+    let compile = |args: &[&str]| {
+        let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+        compiler
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .args(args)
+            .env("RUST_LOG", "info")
+            .write_stdin(rotation)
+            .assert()
+    };
+
+    let rejected = compile(&[]).code(3).get_output().clone();
+    let stdout = String::from_utf8(rejected.stdout).unwrap();
+    assert_eq!(1, stdout.lines().count());
+    let record: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(0, record["index"]);
+    assert!(record["error"].as_str().unwrap().contains("--pad"));
+
+    let padded = compile(&["--pad"]).success().get_output().clone();
+    assert_eq!(1, String::from_utf8(padded.stdout).unwrap().lines().count());
+    let stderr = String::from_utf8(padded.stderr).unwrap();
+    assert!(stderr.contains("Padded 1 operations on 12 qubits with identities on qubits 12..22"));
+}
+
+#[test]
+fn no_automorphisms_rejects_other_qubits() {
+    let measurement = |qubit: usize| {
+        let mut basis = ["I"; 11];
+        basis[qubit] = "X";
+        serde_json::json!({"Measurement": {"basis": basis, "flip_result": false}}).to_string()
+    };
+    let compile = |input: String| {
+        let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+        compiler
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .arg("--no-automorphisms")
+            .env("RUST_LOG", "off")
+            .write_stdin(input)
+            .assert()
+    };
+
+    // Only data qubit 5 is reachable with the identity automorphism
+    let output = compile(measurement(5)).success().get_output().clone();
+    assert!(
+        !String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Automorphism")
+    );
+
+    let input = format!("{}\n{}\n", measurement(5), measurement(0));
+    let rejected = compile(input).code(3).get_output().clone();
+    let stdout = String::from_utf8(rejected.stdout).unwrap();
+    assert_eq!(2, stdout.lines().count());
+    let record: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(1, record["index"]);
+}
+
+#[test]
+fn growing_architecture() {
+    let measurement = |qubits: usize| {
+        let mut basis = vec!["I"; qubits];
+        basis[0] = "Z";
+        basis[qubits - 1] = "Z";
+        serde_json::json!({"Measurement": {"basis": basis, "flip_result": false}}).to_string()
+    };
+    let input = format!("{}\n{}\n", measurement(11), measurement(22));
+    let compile = |args: &[&str]| {
+        let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+        compiler
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .args(args)
+            .env("RUST_LOG", "off")
+            .write_stdin(input.clone())
+            .assert()
+    };
+
+    let rejected = compile(&["--fixed-architecture"])
+        .code(3)
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(rejected.stdout).unwrap();
+    assert_eq!(2, stdout.lines().count());
+    let record: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(1, record["index"]);
+
+    // The record counts the operations of the input, including a permutation that is not compiled on its own
+    let permutation: Vec<usize> = (0..11).rev().collect();
+    let permute = serde_json::json!({"Permute": {"permutation": permutation}}).to_string();
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("growing_permuted.jsonl");
+    std::fs::write(
+        &path,
+        [measurement(11), permute, measurement(22)].join("\n"),
+    )
+    .unwrap();
+    let rejected = assert_cmd::Command::from_std(binary("bicycle_compiler"))
+        .arg("gross")
+        .arg("--measurement-table")
+        .arg(measurement_table())
+        .arg("--fixed-architecture")
+        .arg("--input")
+        .arg(&path)
+        .env("RUST_LOG", "off")
+        .assert()
+        .code(3)
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(rejected.stdout).unwrap();
+    let record: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(2, record["index"]);
+    assert_eq!(path.display().to_string(), record["input"]);
+
+    let compiled = compile(&[]).success().get_output().stdout.clone();
+    let second = String::from_utf8(compiled.clone())
+        .unwrap()
+        .lines()
+        .nth(1)
+        .unwrap()
+        .to_string();
+    assert!(second.starts_with(r#"[[[1,"CSSInitZero"]]"#), "{second}");
+
+    // The numerics grow from one block as well
+    let mut numerics = assert_cmd::Command::from_std(binary("bicycle_numerics"));
+    let output = numerics
+        .args(["11", "gross_1e-3"])
+        .write_stdin(compiled)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let rows: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|row| row.split(',').nth(3).unwrap().to_string())
+        .collect();
+    assert_eq!(vec!["11", "22"], rows);
+}
+
+/// An output that cannot be written is incomplete, so the compiler should fail instead of exiting with 0
+#[cfg(target_os = "linux")]
+#[test]
+fn unwritable_output_fails() {
+    let full = std::fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/full")
+        .unwrap();
+    let mut compiler = binary("bicycle_compiler")
+        .arg("gross")
+        .arg("--measurement-table")
+        .arg(measurement_table())
+        .env("RUST_LOG", "off")
+        .stdin(Stdio::piped())
+        .stdout(full)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let measurement = r#"{"Measurement":{"basis":["Z","I","I","I","I","I","I","I","I","I","I"],"flip_result":false}}"#;
+    compiler
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(measurement.as_bytes())
+        .unwrap();
+    let output = compiler.wait_with_output().unwrap();
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Cannot write the output"), "{stderr}");
+}
+
+#[test]
+fn empty_operation_is_rejected() {
+    let output = assert_cmd::Command::from_std(binary("bicycle_numerics"))
+        .args(["11", "gross_1e-3"])
+        .env("RUST_LOG", "off")
+        .write_stdin("[[]]\n")
+        .assert()
+        .code(1)
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Operation [] of input line 1"), "{stderr}");
+}
+
+#[test]
+fn document_formats_pipeline() {
+    let input: Vec<_> = (0..QUBITS)
+        .map(|qubit| {
+            let mut basis = vec!["I"; QUBITS];
+            basis[qubit] = "X";
+            basis[QUBITS - 1 - qubit] = "Z";
+            serde_json::json!({"Measurement": {"basis": basis, "flip_result": false}}).to_string()
+        })
+        .collect();
+    let numerics = |format: &str| {
+        let program = assert_cmd::Command::from_std(binary("bicycle_compiler"))
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .args(["--output-format", format])
+            .write_stdin(input.join("\n"))
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let output = assert_cmd::Command::from_std(binary("bicycle_numerics"))
+            .arg(QUBITS.to_string())
+            .arg("gross_1e-3")
+            .args(["--input-format", format])
+            .write_stdin(program)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).unwrap()
+    };
+
+    // The document formats give the same numerics as JSON lines
+    let jsonl = numerics("jsonl");
+    assert_eq!(QUBITS + 1, jsonl.lines().count());
+    assert_eq!(jsonl, numerics("msgpack"));
+    assert_eq!(jsonl, numerics("cbor"));
+}
+
+#[test]
+fn parallel_inputs_keep_their_blocks() {
+    let measurement = |qubits: usize| {
+        let mut basis = vec!["I"; qubits];
+        basis[0] = "Z";
+        serde_json::json!({"Measurement": {"basis": basis, "flip_result": false}}).to_string()
+    };
+    let input = |name: &str, qubits: &[usize]| {
+        let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+        let lines: Vec<_> = qubits.iter().map(|&qubits| measurement(qubits)).collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        path
+    };
+    let narrow = input("parallel_narrow.jsonl", &[11]);
+    let growing = input("parallel_growing.jsonl", &[11, 22]);
+    let unpadded = input("parallel_unpadded.jsonl", &[5]);
+    let compile = |inputs: &[&PathBuf], args: &[&str]| {
+        let mut compiler = assert_cmd::Command::from_std(binary("bicycle_compiler"));
+        compiler
+            .arg("gross")
+            .arg("--measurement-table")
+            .arg(measurement_table())
+            .args(args)
+            .args(
+                inputs
+                    .iter()
+                    .flat_map(|path| [PathBuf::from("--input"), (*path).clone()]),
+            )
+            .env("RUST_LOG", "off")
+            .assert()
+    };
+    let record = |output: &std::process::Output| -> serde_json::Value {
+        let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+        serde_json::from_str(stdout.lines().last().unwrap()).unwrap()
+    };
+
+    // A single input grows the architecture like stdin
+    compile(&[&growing], &[]).success();
+
+    // A program cannot act on the blocks of the next one
+    let rejected = compile(&[&growing, &narrow], &[])
+        .code(3)
+        .get_output()
+        .clone();
+    assert_eq!(1, record(&rejected)["index"]);
+    assert!(
+        record(&rejected)["error"]
+            .as_str()
+            .unwrap()
+            .contains("22 qubits")
+    );
+
+    // Programs are only padded to whole blocks with --pad
+    let rejected = compile(&[&unpadded, &narrow], &[])
+        .code(3)
+        .get_output()
+        .clone();
+    assert!(
+        record(&rejected)["error"]
+            .as_str()
+            .unwrap()
+            .contains("--pad")
+    );
+    let padded = compile(&[&unpadded, &narrow], &["--pad"])
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(2, String::from_utf8(padded.stdout).unwrap().lines().count());
+}
+
+#[test]
+fn snapshot_rows() {
+    binary("bicycle_random_numerics")
+        .args([
+            "--check-snapshots",
+            "--model",
+            "gross",
+            "--measurement-table",
+        ])
+        .arg(measurement_table())
+        .env("RUST_LOG", "off")
+        .assert()
+        .success();
+}
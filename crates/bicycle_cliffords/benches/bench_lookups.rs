@@ -23,8 +23,13 @@
 //!
 //! * **PauliString arithmetic** – `commutes_with`, `conjugate_with`,
 //!   `zero_pivot`, multiplication (XOR).
+//! * **PauliString statistics** – `weight`, `x_weight`, `z_weight`, `support`.
 //! * **Table lookups** – `implementation()` and `min_data()` for random
-//!   Pauli strings.
+//!   Pauli strings, with few strings that stay in the cache and with many
+//!   strings that do not, and with flattened chains.
+//! * **Table opening** – loading a cached table in the raw layout from a
+//!   file and from memory, compared to decoding it with `bitcode`, which
+//!   dominates the cold start of the compiler.
 //!
 //! Run with:
 //!
@@ -32,7 +37,9 @@
 //! cargo bench --package bicycle_cliffords --bench bench_lookups
 //! ```
 
+use std::fs::File;
 use std::hint::black_box;
+use std::io::{BufReader, BufWriter};
 use std::time::{Duration, Instant};
 
 use bicycle_cliffords::{
@@ -61,7 +68,7 @@ fn sample_paulis(n: usize) -> Vec<PauliString> {
         .map(|_| {
             state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
             let raw = ((state >> 16) as u32) % (4_u32.pow(12) - 1) + 1; // 1..4^12
-            PauliString(raw)
+            PauliString::new(raw).unwrap()
         })
         .collect()
 }
@@ -70,8 +77,8 @@ fn sample_paulis(n: usize) -> Vec<PauliString> {
 fn sample_11qubit_paulis(n: usize) -> Vec<PauliString> {
     sample_paulis(n)
         .into_iter()
-        .map(|p| PauliString(p.0 & !((1 << 12) | 1))) // zero out pivot bits
-        .filter(|p| p.0 != 0) // skip identity
+        .map(|p| p.zero_pivot())
+        .filter(|p| p.bits() != 0) // skip identity
         .collect()
 }
 
@@ -151,6 +158,32 @@ fn main() {
         },
     );
 
+    // These should stay on the popcount fast path, i.e., comparable to zero_pivot
+    bench("weight (4096)", 1, Duration::from_secs(2), || {
+        for p in &paulis {
+            black_box(p.weight());
+        }
+    });
+
+    bench(
+        "x_weight + z_weight (4096)",
+        1,
+        Duration::from_secs(2),
+        || {
+            for p in &paulis {
+                black_box(p.x_weight() + p.z_weight());
+            }
+        },
+    );
+
+    bench("support (4096)", 1, Duration::from_secs(2), || {
+        for p in &paulis {
+            for qubit in p.support() {
+                black_box(qubit);
+            }
+        }
+    });
+
     println!();
 
     // -- Table lookups (requires building the table first) ------------------
@@ -173,6 +206,48 @@ fn main() {
         },
     );
 
+    // Spread over the whole table, such that most lookups miss the cache
+    let cold_paulis = sample_paulis(1 << 16);
+
+    bench(
+        "implementation() (65536 cold lookups)",
+        1,
+        Duration::from_secs(3),
+        || {
+            for p in &cold_paulis {
+                black_box(table.implementation(*p));
+            }
+        },
+    );
+
+    let t = Instant::now();
+    let mut flat_table = table.clone();
+    flat_table.flatten_chains();
+    println!("  Flattened chains in {:.2?}", t.elapsed());
+
+    bench(
+        "implementation() flat (1024 lookups)",
+        1,
+        Duration::from_secs(3),
+        || {
+            for p in &lookup_paulis {
+                black_box(flat_table.implementation(*p));
+            }
+        },
+    );
+
+    bench(
+        "implementation() flat (65536 cold)",
+        1,
+        Duration::from_secs(3),
+        || {
+            for p in &cold_paulis {
+                black_box(flat_table.implementation(*p));
+            }
+        },
+    );
+    drop(flat_table);
+
     let min_data_paulis = sample_11qubit_paulis(1024);
     let k = min_data_paulis.len();
 
@@ -187,6 +262,51 @@ fn main() {
         },
     );
 
+    // -- Opening a cached table ---------------------------------------------
+    println!();
+    println!("[Opening a cached table]");
+    let mut raw = vec![];
+    table
+        .write_raw(&mut raw)
+        .expect("The table should be writable");
+    let encoded = bitcode::serialize(&table).expect("The table should be serializable");
+    let path = std::env::temp_dir().join("bench_lookups_table.raw");
+    std::fs::write(&path, &raw).expect("The table should be writable to a temporary file");
+    println!(
+        "  raw layout: {} MiB, bitcode: {} MiB",
+        raw.len() >> 20,
+        encoded.len() >> 20
+    );
+
+    bench("read_raw (from file)", 1, Duration::from_secs(5), || {
+        let file = BufReader::new(File::open(&path).unwrap());
+        black_box(CompleteMeasurementTable::read_raw(file).unwrap());
+    });
+
+    bench("open_mmap (from file)", 1, Duration::from_secs(5), || {
+        black_box(CompleteMeasurementTable::open_mmap(&path).unwrap());
+    });
+
+    bench("read_raw (from memory)", 1, Duration::from_secs(5), || {
+        black_box(CompleteMeasurementTable::read_raw(raw.as_slice()).unwrap());
+    });
+
+    bench(
+        "bitcode::deserialize (from memory)",
+        1,
+        Duration::from_secs(5),
+        || {
+            black_box(bitcode::deserialize::<CompleteMeasurementTable>(&encoded).unwrap());
+        },
+    );
+
+    bench("write_raw (to file)", 1, Duration::from_secs(5), || {
+        let file = BufWriter::new(File::create(&path).unwrap());
+        table.write_raw(file).unwrap();
+    });
+    std::fs::remove_file(&path).expect("The temporary file should be removable");
+    drop((raw, encoded));
+
     // -- Throughput estimate for BFS inner loop -----------------------------
     println!();
     println!("[BFS inner-loop throughput estimate]");
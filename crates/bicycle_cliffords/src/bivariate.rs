@@ -0,0 +1,664 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The automorphism action of a bivariate bicycle code, derived from its polynomials,
+//! as in [`notebooks/gross_code_automorphisms.ipynb`](https://github.com/qiskit-community/bicycle-architecture-compiler/blob/main/notebooks/gross_code_automorphisms.ipynb).
+//!
+//! The physical qubits of a code of order (l, m) form an L and an R block, each indexed by the monomials x^a y^b with x^l = y^m = 1,
+//! so a polynomial selects a set of qubits of a block.
+//! The X checks are X(mA, mB) and the Z checks are Z(mBᵀ, mAᵀ) for every monomial m, where ᵀ inverts the exponents.
+//!
+//! The logic unit is attached to a logical qubit, the pivot, given by the supports of its logical X and Z operators.
+//! A [`LogicalBasis`] extends it to the primal logical qubits, and the dual qubits follow from the ZX-duality of the code.
+//! The basis is not unique, but all choices differ by the same CNOT circuit on qubits 1 to 5 and 7 to 11,
+//! which does not change the native measurements up to relabeling the data qubits.
+//! Shifting the torus by x or y is an automorphism that maps the logical X operators to sums of logical X operators,
+//! which gives the matrices `mx` and `my` of a [`CodeMeasurement`].
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    ops::{Add, Mul},
+};
+
+use nalgebra::SMatrix;
+
+use crate::{CodeMeasurement, InvalidCodeMeasurement};
+
+/// Number of primal logical qubits of the codes that a [`CodeMeasurement`] describes
+const PRIMAL_QUBITS: usize = 6;
+
+/// A polynomial over F_2 in x and y with x^l = y^m = 1, i.e., a set of qubits of the L or R block of a code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Polynomial {
+    order: (usize, usize),
+    /// Bit `a m + b` is the coefficient of x^a y^b
+    bits: Vec<u64>,
+}
+
+impl Polynomial {
+    /// The zero polynomial of order (l, m)
+    pub fn zero(order: (usize, usize)) -> Self {
+        Self {
+            order,
+            bits: vec![0; (order.0 * order.1).div_ceil(64)],
+        }
+    }
+
+    /// The sum of the monomials x^a y^b of the terms (a, b), whose exponents may be negative.
+    /// A repeated term cancels.
+    pub fn new(order: (usize, usize), terms: &[(i64, i64)]) -> Self {
+        let mut polynomial = Self::zero(order);
+        for (a, b) in terms {
+            let a = a.rem_euclid(order.0 as i64) as usize;
+            let b = b.rem_euclid(order.1 as i64) as usize;
+            polynomial.flip(a * order.1 + b);
+        }
+        polynomial
+    }
+
+    /// The monomial x^a y^b
+    pub fn monomial(order: (usize, usize), a: i64, b: i64) -> Self {
+        Self::new(order, &[(a, b)])
+    }
+
+    /// All monomials x^a y^b of an order, by increasing a and then b
+    pub fn monomials(order: (usize, usize)) -> impl Iterator<Item = Self> {
+        (0..order.0)
+            .flat_map(move |a| (0..order.1).map(move |b| Self::monomial(order, a as i64, b as i64)))
+    }
+
+    /// The order (l, m) of x and y
+    pub fn order(&self) -> (usize, usize) {
+        self.order
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.bits[i / 64] ^= 1 << (i % 64);
+    }
+
+    /// The exponents (a, b) of the monomials x^a y^b of the polynomial
+    pub fn terms(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let m = self.order.1;
+        (0..self.order.0 * m)
+            .filter(|i| self.bits[i / 64] >> (i % 64) & 1 == 1)
+            .map(move |i| (i / m, i % m))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+
+    /// The polynomial with inverted exponents, i.e., x^a y^b becomes x^-a y^-b
+    pub fn transpose(&self) -> Self {
+        let terms: Vec<_> = self
+            .terms()
+            .map(|(a, b)| (-(a as i64), -(b as i64)))
+            .collect();
+        Self::new(self.order, &terms)
+    }
+
+    /// Whether the sets of qubits of both polynomials overlap in an odd number of qubits
+    fn overlaps_oddly(&self, other: &Self) -> bool {
+        let overlap: u32 = self
+            .bits
+            .iter()
+            .zip(&other.bits)
+            .map(|(a, b)| (a & b).count_ones())
+            .sum();
+        overlap % 2 == 1
+    }
+}
+
+impl Add for &Polynomial {
+    type Output = Polynomial;
+
+    fn add(self, rhs: &Polynomial) -> Polynomial {
+        assert_eq!(self.order, rhs.order, "Polynomials of different orders");
+        let bits = self
+            .bits
+            .iter()
+            .zip(&rhs.bits)
+            .map(|(a, b)| a ^ b)
+            .collect();
+        Polynomial {
+            order: self.order,
+            bits,
+        }
+    }
+}
+
+impl Mul for &Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, rhs: &Polynomial) -> Polynomial {
+        assert_eq!(self.order, rhs.order, "Polynomials of different orders");
+        let rhs_terms: Vec<_> = rhs.terms().collect();
+        let terms: Vec<_> = self
+            .terms()
+            .flat_map(|(a, b)| {
+                rhs_terms
+                    .iter()
+                    .map(move |(c, d)| ((a + c) as i64, (b + d) as i64))
+            })
+            .collect();
+        Polynomial::new(self.order, &terms)
+    }
+}
+
+/// The support of a Pauli operator of one type, X or Z, on the L and R blocks of a code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Support {
+    pub left: Polynomial,
+    pub right: Polynomial,
+}
+
+impl Support {
+    pub fn new(left: Polynomial, right: Polynomial) -> Self {
+        assert_eq!(left.order, right.order, "Blocks of different orders");
+        Self { left, right }
+    }
+
+    /// The support shifted by the monomials of `shift`
+    pub fn shifted(&self, shift: &Polynomial) -> Self {
+        Self::new(shift * &self.left, shift * &self.right)
+    }
+
+    /// Whether an X operator on this support anticommutes with a Z operator on `z`
+    pub fn anticommutes(&self, z: &Support) -> bool {
+        self.left.overlaps_oddly(&z.left) != self.right.overlaps_oddly(&z.right)
+    }
+
+    /// Both blocks as one vector, for Gaussian elimination
+    fn packed(&self) -> Vec<u64> {
+        let mut packed = vec![0; (2 * self.left.order.0 * self.left.order.1).div_ceil(64)];
+        let n = self.left.order.0 * self.left.order.1;
+        for (offset, block) in [(0, &self.left), (n, &self.right)] {
+            for i in (0..n).filter(|i| block.bits[i / 64] >> (i % 64) & 1 == 1) {
+                set(&mut packed, offset + i);
+            }
+        }
+        packed
+    }
+}
+
+fn set(bits: &mut Vec<u64>, i: usize) {
+    if i / 64 >= bits.len() {
+        bits.resize(i / 64 + 1, 0);
+    }
+    bits[i / 64] ^= 1 << (i % 64);
+}
+
+fn bit(bits: &[u64], i: usize) -> bool {
+    bits.get(i / 64)
+        .is_some_and(|word| word >> (i % 64) & 1 == 1)
+}
+
+fn xor(target: &mut Vec<u64>, source: &[u64]) {
+    if source.len() > target.len() {
+        target.resize(source.len(), 0);
+    }
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+/// Gaussian elimination over F_2 that remembers which added vectors sum to each row
+#[derive(Debug, Clone, Default)]
+struct Elimination {
+    /// Each row has zeros in the pivots of the rows before it, with the added vectors that sum to it
+    rows: Vec<(Vec<u64>, Vec<u64>)>,
+    pivots: Vec<usize>,
+    added: usize,
+}
+
+impl Elimination {
+    /// Reduce a vector by the rows, returning what is left and the added vectors that were subtracted
+    fn reduce(&self, vector: &[u64]) -> (Vec<u64>, Vec<u64>) {
+        let mut vector = vector.to_vec();
+        let mut combination = vec![];
+        for ((row, row_combination), pivot) in self.rows.iter().zip(&self.pivots) {
+            if bit(&vector, *pivot) {
+                xor(&mut vector, row);
+                xor(&mut combination, row_combination);
+            }
+        }
+        (vector, combination)
+    }
+
+    /// Add a vector, returning whether it is independent of the vectors before it
+    fn add(&mut self, vector: &[u64]) -> bool {
+        let (vector, mut combination) = self.reduce(vector);
+        set(&mut combination, self.added);
+        self.added += 1;
+        let pivot = (0..64 * vector.len()).find(|i| bit(&vector, *i));
+        if let Some(pivot) = pivot {
+            self.rows.push((vector, combination));
+            self.pivots.push(pivot);
+        }
+        pivot.is_some()
+    }
+}
+
+/// Logical operators that do not form a basis of the primal logical qubits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BasisError {
+    /// The operators of a qubit do not commute with the checks, or do not anticommute with each other
+    NotLogical(usize),
+    /// The X operator of a qubit anticommutes with the Z operator of another qubit
+    NotSymplectic { x: usize, z: usize },
+    /// The number of primal logical qubits, which should be 6
+    Qubits(usize),
+}
+
+impl Display for BasisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotLogical(qubit) => write!(f, "The operators of qubit {qubit} are not logical"),
+            Self::NotSymplectic { x, z } => write!(
+                f,
+                "The X operator of qubit {x} anticommutes with the Z operator of qubit {z}"
+            ),
+            Self::Qubits(qubits) => write!(
+                f,
+                "Found {qubits} primal logical qubits, expected {PRIMAL_QUBITS}"
+            ),
+        }
+    }
+}
+
+impl Error for BasisError {}
+
+/// A bivariate bicycle code, defined by its polynomials A and B
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BivariateBicycleCode {
+    a: Polynomial,
+    b: Polynomial,
+}
+
+impl BivariateBicycleCode {
+    pub fn new(a: Polynomial, b: Polynomial) -> Self {
+        assert_eq!(a.order, b.order, "Polynomials of different orders");
+        Self { a, b }
+    }
+
+    /// The [[144,12,12]] gross code with A = 1 + y + x³y⁻¹ and B = 1 + x + x⁻¹y⁻³, in the labeling of Tour de Gross
+    pub fn gross() -> Self {
+        Self::toric((12, 6))
+    }
+
+    /// The [[288,12,18]] two-gross code with the polynomials of the gross code on a larger torus
+    pub fn two_gross() -> Self {
+        Self::toric((12, 12))
+    }
+
+    fn toric(order: (usize, usize)) -> Self {
+        Self::new(
+            Polynomial::new(order, &[(0, 0), (0, 1), (3, -1)]),
+            Polynomial::new(order, &[(0, 0), (1, 0), (-1, -3)]),
+        )
+    }
+
+    /// The order (l, m) of x and y
+    pub fn order(&self) -> (usize, usize) {
+        self.a.order
+    }
+
+    /// The supports of the X checks
+    fn x_checks(&self) -> impl Iterator<Item = Support> + '_ {
+        Polynomial::monomials(self.order()).map(|m| Support::new(&m * &self.a, &m * &self.b))
+    }
+
+    /// The supports of the Z checks
+    fn z_checks(&self) -> impl Iterator<Item = Support> + '_ {
+        let (a, b) = (self.a.transpose(), self.b.transpose());
+        Polynomial::monomials(self.order()).map(move |m| Support::new(&m * &b, &m * &a))
+    }
+
+    /// Whether X and Z operators on these supports are a logical qubit,
+    /// i.e., commute with the checks and anticommute with each other
+    pub fn is_logical_qubit(&self, x: &Support, z: &Support) -> bool {
+        x.anticommutes(z)
+            && self.x_checks().all(|check| !check.anticommutes(z))
+            && self.z_checks().all(|check| !x.anticommutes(&check))
+    }
+
+    /// The basis whose qubit i has the pivot operators shifted by `x_shifts[i]` and `z_shifts[i]`
+    pub fn basis_with_shifts(
+        &self,
+        x: &Support,
+        z: &Support,
+        x_shifts: &[Polynomial],
+        z_shifts: &[Polynomial],
+    ) -> Result<LogicalBasis, BasisError> {
+        assert_eq!(
+            x_shifts.len(),
+            z_shifts.len(),
+            "One shift of X and Z per qubit"
+        );
+        let xs: Vec<_> = x_shifts.iter().map(|shift| x.shifted(shift)).collect();
+        let zs: Vec<_> = z_shifts.iter().map(|shift| z.shifted(shift)).collect();
+        for (qubit, (x, z)) in xs.iter().zip(&zs).enumerate() {
+            if !self.is_logical_qubit(x, z) {
+                return Err(BasisError::NotLogical(qubit));
+            }
+        }
+        LogicalBasis::new(xs, zs)
+    }
+
+    /// Some basis with the pivot as qubit 0.
+    /// The X operators are shifts of the pivot X that are independent of the X checks and of each other,
+    /// and the Z operators are the sums of shifts of the pivot Z that anticommute with one of them.
+    pub fn basis_with_pivot(&self, x: &Support, z: &Support) -> Result<LogicalBasis, BasisError> {
+        if !self.is_logical_qubit(x, z) {
+            return Err(BasisError::NotLogical(0));
+        }
+        let mut elimination = Elimination::default();
+        for check in self.x_checks() {
+            elimination.add(&check.packed());
+        }
+        let mut xs: Vec<Support> = vec![];
+        for m in Polynomial::monomials(self.order()) {
+            let shifted = x.shifted(&m);
+            // Only the pivot anticommutes with the pivot Z
+            if !xs.is_empty() && shifted.anticommutes(z) {
+                continue;
+            }
+            if elimination.add(&shifted.packed()) {
+                xs.push(shifted);
+            }
+        }
+
+        // Find sums of the shifted pivot Z that anticommute with exactly one of the X operators
+        let shifts: Vec<_> = Polynomial::monomials(self.order()).collect();
+        let mut anticommutations = Elimination::default();
+        for m in &shifts {
+            let shifted = z.shifted(m);
+            let mut row = vec![];
+            for (i, x) in xs.iter().enumerate() {
+                if x.anticommutes(&shifted) {
+                    set(&mut row, i);
+                }
+            }
+            anticommutations.add(&row);
+        }
+        let mut zs = vec![];
+        for i in 0..xs.len() {
+            let mut target = vec![];
+            set(&mut target, i);
+            let (rest, combination) = anticommutations.reduce(&target);
+            if rest.iter().any(|word| *word != 0) {
+                return Err(BasisError::NotLogical(i));
+            }
+            let shift = shifts
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| bit(&combination, *j))
+                .fold(Polynomial::zero(self.order()), |sum, (_, m)| &sum + m);
+            zs.push(z.shifted(&shift));
+        }
+        LogicalBasis::new(xs, zs)
+    }
+
+    /// The automorphism action of the code in some basis with the given pivot, see [`Self::basis_with_pivot`]
+    pub fn code_measurement(
+        &self,
+        x: &Support,
+        z: &Support,
+    ) -> Result<CodeMeasurement, Box<dyn Error>> {
+        Ok(self.basis_with_pivot(x, z)?.code_measurement()?)
+    }
+}
+
+/// The supports of the logical X and Z operators of the primal logical qubits, starting with the pivot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicalBasis {
+    xs: Vec<Support>,
+    zs: Vec<Support>,
+}
+
+impl LogicalBasis {
+    /// A symplectic basis of 6 qubits
+    pub fn new(xs: Vec<Support>, zs: Vec<Support>) -> Result<Self, BasisError> {
+        if xs.len() != PRIMAL_QUBITS || zs.len() != PRIMAL_QUBITS {
+            return Err(BasisError::Qubits(xs.len().min(zs.len())));
+        }
+        for (i, x) in xs.iter().enumerate() {
+            for (j, z) in zs.iter().enumerate() {
+                if i != j && x.anticommutes(z) {
+                    return Err(BasisError::NotSymplectic { x: i, z: j });
+                }
+            }
+        }
+        Ok(Self { xs, zs })
+    }
+
+    pub fn xs(&self) -> &[Support] {
+        &self.xs
+    }
+
+    pub fn zs(&self) -> &[Support] {
+        &self.zs
+    }
+
+    /// The matrix whose column i is the sum of logical X operators that shifting X_i by `shift` gives
+    pub fn action(&self, shift: &Polynomial) -> SMatrix<u32, 6, 6> {
+        SMatrix::from_fn(|j, i| self.xs[i].shifted(shift).anticommutes(&self.zs[j]) as u32)
+    }
+
+    /// The action of the shifts by x and y, which are checked like a code file, see [`CodeMeasurement::new`]
+    pub fn code_measurement(&self) -> Result<CodeMeasurement, InvalidCodeMeasurement> {
+        let order = self.xs[0].left.order;
+        CodeMeasurement::new(
+            self.action(&Polynomial::monomial(order, 1, 0)),
+            self.action(&Polynomial::monomial(order, 0, 1)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GROSS_MEASUREMENT, TWOGROSS_MEASUREMENT, check};
+
+    use super::*;
+
+    /// A polynomial from the exponents of its monomials
+    fn poly(order: (usize, usize), terms: &[(i64, i64)]) -> Polynomial {
+        Polynomial::new(order, terms)
+    }
+
+    /// The pivot of the logic unit of the gross code in Tour de Gross, with its shifts
+    fn gross_pivot() -> (Support, Support, Vec<Polynomial>, Vec<Polynomial>) {
+        let order = (12, 6);
+        let p = poly(order, &[(4, 0), (5, 0), (6, 1), (4, 2), (5, 4), (6, 5)]);
+        let q = poly(order, &[(3, 0), (4, 0), (3, 1), (3, 2), (4, 2), (3, 5)]);
+        let r = poly(order, &[(0, 0), (8, 0), (1, 1), (9, 1), (3, 4), (11, 4)]);
+        let s = poly(order, &[(1, 0), (9, 0), (4, 4), (8, 4), (0, 5), (8, 5)]);
+        let nu = Polynomial::monomial(order, 1, 1);
+        let x = Support::new(p, q);
+        let z = Support::new(&nu * &s.transpose(), &nu * &r.transpose());
+        let shifts = |exponents: [(i64, i64); 6]| {
+            exponents
+                .iter()
+                .map(|(a, b)| Polynomial::monomial(order, *a, *b))
+                .collect()
+        };
+        (
+            x,
+            z,
+            shifts([(0, 0), (3, 5), (11, 5), (10, 1), (5, 4), (4, 2)]),
+            shifts([(0, 0), (2, 4), (1, 2), (2, 5), (1, 1), (3, 1)]),
+        )
+    }
+
+    #[test]
+    fn polynomial_arithmetic() {
+        let order = (3, 2);
+        let x = Polynomial::monomial(order, 1, 0);
+        let one_plus_x = poly(order, &[(0, 0), (1, 0)]);
+        // (1 + x)^2 = 1 + x^2 over F_2
+        assert_eq!(poly(order, &[(0, 0), (2, 0)]), &one_plus_x * &one_plus_x);
+        assert_eq!(poly(order, &[(2, 0)]), x.transpose());
+        assert!((&one_plus_x + &one_plus_x).is_zero());
+        assert_eq!(
+            vec![(0, 1), (2, 0)],
+            poly(order, &[(-1, 0), (0, -1)]).terms().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn gross_from_shifts() {
+        let code = BivariateBicycleCode::gross();
+        let (x, z, x_shifts, z_shifts) = gross_pivot();
+        let basis = code
+            .basis_with_shifts(&x, &z, &x_shifts, &z_shifts)
+            .unwrap();
+        assert_eq!(GROSS_MEASUREMENT, basis.code_measurement().unwrap());
+    }
+
+    #[test]
+    fn two_gross_from_shifts() {
+        let order = (12, 12);
+        let p = poly(
+            order,
+            &[
+                (2, 0),
+                (2, 2),
+                (8, 2),
+                (9, 2),
+                (3, 3),
+                (4, 3),
+                (7, 4),
+                (8, 6),
+                (6, 7),
+                (7, 11),
+            ],
+        );
+        let q = poly(
+            order,
+            &[
+                (3, 2),
+                (5, 3),
+                (7, 3),
+                (11, 3),
+                (8, 4),
+                (8, 5),
+                (6, 7),
+                (4, 8),
+                (1, 9),
+                (1, 10),
+            ],
+        );
+        let r = poly(
+            order,
+            &[
+                (4, 2),
+                (11, 2),
+                (0, 5),
+                (1, 5),
+                (5, 5),
+                (6, 5),
+                (1, 8),
+                (8, 8),
+                (2, 11),
+                (10, 11),
+            ],
+        );
+        let s = poly(
+            order,
+            &[
+                (2, 6),
+                (2, 9),
+                (2, 10),
+                (11, 6),
+                (11, 9),
+                (11, 10),
+                (8, 7),
+                (11, 7),
+                (5, 8),
+                (11, 8),
+            ],
+        );
+        let nu = Polynomial::monomial(order, 1, 1);
+        let x = Support::new(p, q);
+        let z = Support::new(&nu * &s.transpose(), &nu * &r.transpose());
+        let shifts = |exponents: [(i64, i64); 6]| -> Vec<_> {
+            exponents
+                .iter()
+                .map(|(a, b)| Polynomial::monomial(order, *a, *b))
+                .collect()
+        };
+        let basis = BivariateBicycleCode::two_gross()
+            .basis_with_shifts(
+                &x,
+                &z,
+                &shifts([(0, 0), (0, 3), (3, 7), (11, 11), (2, 9), (7, 4)]),
+                &shifts([(0, 0), (1, 1), (4, 0), (5, 4), (4, 3), (3, 5)]),
+            )
+            .unwrap();
+        assert_eq!(TWOGROSS_MEASUREMENT, basis.code_measurement().unwrap());
+    }
+
+    #[test]
+    fn gross_from_pivot() {
+        let code = BivariateBicycleCode::gross();
+        let (x, z, _, _) = gross_pivot();
+        let basis = code.basis_with_pivot(&x, &z).unwrap();
+        assert_eq!(&x, &basis.xs()[0]);
+        for (x, z) in basis.xs().iter().zip(basis.zs()) {
+            assert!(code.is_logical_qubit(x, z));
+        }
+        // Another basis measures the same number of distinct Paulis
+        let measurement = code.code_measurement(&x, &z).unwrap();
+        assert_eq!(
+            Vec::<check::CheckFailure>::new(),
+            check::check(&measurement)
+        );
+    }
+
+    #[test]
+    fn invalid_bases() {
+        let code = BivariateBicycleCode::gross();
+        let (x, z, x_shifts, z_shifts) = gross_pivot();
+        // The checks are not logical operators
+        let check = code.x_checks().next().unwrap();
+        assert_eq!(
+            Err(BasisError::NotLogical(0)),
+            code.basis_with_pivot(&check, &z)
+        );
+        // Qubit 1 is not logical with the Z operator of the pivot, which anticommutes with the pivot X
+        let basis = code
+            .basis_with_shifts(&x, &z, &x_shifts, &z_shifts)
+            .unwrap();
+        let mut zs = basis.zs().to_vec();
+        zs[1] = zs[0].clone();
+        assert_eq!(
+            Err(BasisError::NotLogical(1)),
+            code.basis_with_shifts(
+                &x,
+                &z,
+                &x_shifts,
+                &[&z_shifts[..1], &z_shifts[..1], &z_shifts[2..]].concat()
+            )
+        );
+        assert_eq!(
+            Err(BasisError::NotSymplectic { x: 0, z: 1 }),
+            LogicalBasis::new(basis.xs().to_vec(), zs)
+        );
+        assert_eq!(
+            Err(BasisError::Qubits(1)),
+            code.basis_with_shifts(&x, &z, &x_shifts[..1], &z_shifts[..1])
+        );
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of the invariants that a [`CodeMeasurement`] should satisfy.
+
+use std::{collections::HashSet, fmt::Display};
+
+use bicycle_common::Pauli;
+use nalgebra::SMatrix;
+
+use crate::{CodeMeasurement, PauliString, native_measurement::NativeMeasurement};
+
+/// The expected number of distinct Paulis measured by native measurements
+pub const NR_NATIVE_MEASUREMENTS: usize = 540;
+/// The expected number of distinct native measurements on the data qubits
+pub const NR_NATIVE_ROTATIONS: usize = 511;
+
+type Matrix = SMatrix<u32, 6, 6>;
+
+/// An invariant that a [`CodeMeasurement`] fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckFailure {
+    /// The generator does not have order 6, the order is `None` if it is larger than 6
+    Order {
+        generator: &'static str,
+        order: Option<usize>,
+    },
+    /// The generators do not commute
+    NotCommuting,
+    /// A native measurement on one logical qubit has support on the other half of the block
+    SpillOver {
+        measurement: NativeMeasurement,
+        measures: PauliString,
+    },
+    /// The number of distinct native measurements
+    MeasurementCount(usize),
+    /// The number of distinct native measurements on the data qubits
+    RotationCount(usize),
+}
+
+impl Display for CheckFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckFailure::Order {
+                generator,
+                order: Some(order),
+            } => write!(f, "{generator} has order {order}, expected 6"),
+            CheckFailure::Order {
+                generator,
+                order: None,
+            } => write!(f, "{generator} has order larger than 6, expected 6"),
+            CheckFailure::NotCommuting => write!(f, "mx and my do not commute"),
+            CheckFailure::SpillOver {
+                measurement,
+                measures,
+            } => write!(
+                f,
+                "{measurement} spills over to the other block: {measures}"
+            ),
+            CheckFailure::MeasurementCount(count) => write!(
+                f,
+                "Found {count} distinct native measurements, expected {NR_NATIVE_MEASUREMENTS}"
+            ),
+            CheckFailure::RotationCount(count) => write!(
+                f,
+                "Found {count} distinct native rotations, expected {NR_NATIVE_ROTATIONS}"
+            ),
+        }
+    }
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    (a * b).map(|v| v % 2)
+}
+
+/// The order of a matrix over F_2, or `None` if it is larger than 6
+fn order(m: &Matrix) -> Option<usize> {
+    let mut power = *m;
+    for order in 1..=6 {
+        if power == Matrix::identity() {
+            return Some(order);
+        }
+        power = mul(&power, m);
+    }
+    None
+}
+
+/// Check all invariants of the automorphisms of a code and return the ones that fail
+pub fn check(code: &CodeMeasurement) -> Vec<CheckFailure> {
+    let mut failures = check_actions(code);
+
+    let measured: HashSet<_> = NativeMeasurement::all()
+        .iter()
+        .map(|measurement| code.measures(measurement))
+        .collect();
+    if measured.len() != NR_NATIVE_MEASUREMENTS {
+        failures.push(CheckFailure::MeasurementCount(measured.len()));
+    }
+    let rotations: HashSet<_> = measured.iter().map(|p| p.zero_pivot()).collect();
+    if rotations.len() != NR_NATIVE_ROTATIONS {
+        failures.push(CheckFailure::RotationCount(rotations.len()));
+    }
+
+    failures
+}
+
+/// Check the invariants that any automorphism action must satisfy, i.e.,
+/// that the generators have order 6 and commute and that the primal and dual logical qubits stay apart,
+/// but not the number of native measurements, which differs between codes
+pub fn check_actions(code: &CodeMeasurement) -> Vec<CheckFailure> {
+    let mut failures = vec![];
+
+    for (generator, m) in [("mx", &code.mx), ("my", &code.my)] {
+        let order = order(m);
+        if order != Some(6) {
+            failures.push(CheckFailure::Order { generator, order });
+        }
+    }
+    if mul(&code.mx, &code.my) != mul(&code.my, &code.mx) {
+        failures.push(CheckFailure::NotCommuting);
+    }
+
+    for (primal, dual) in [(true, false), (false, true)] {
+        for measurement in NativeMeasurement::with_support(primal, dual, None) {
+            let measures = code.measures(&measurement);
+            let paulis: [Pauli; 12] = measures.into();
+            let support = (
+                paulis[..6].iter().any(|p| *p != Pauli::I),
+                paulis[6..].iter().any(|p| *p != Pauli::I),
+            );
+            if support != (primal, dual) {
+                failures.push(CheckFailure::SpillOver {
+                    measurement,
+                    measures,
+                });
+            }
+        }
+    }
+
+    failures
+}
+
+/// Parse a 6x6 matrix over F_2 from its 36 entries in row-major order,
+/// separated by commas, semicolons, or whitespace
+pub fn parse_matrix(s: &str) -> Result<Matrix, String> {
+    let entries = s
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry {
+            "0" => Ok(0),
+            "1" => Ok(1),
+            _ => Err(format!("Matrix entries should be 0 or 1, found {entry}")),
+        })
+        .collect::<Result<Vec<u32>, _>>()?;
+    if entries.len() != 36 {
+        return Err(format!(
+            "A 6x6 matrix should have 36 entries, found {}",
+            entries.len()
+        ));
+    }
+    Ok(Matrix::from_row_slice(&entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GROSS_MEASUREMENT, TWOGROSS_MEASUREMENT};
+
+    use super::*;
+
+    #[test]
+    fn codes_pass() {
+        assert_eq!(Vec::<CheckFailure>::new(), check(&GROSS_MEASUREMENT));
+        assert_eq!(Vec::<CheckFailure>::new(), check(&TWOGROSS_MEASUREMENT));
+    }
+
+    #[test]
+    fn identity_fails() {
+        let code = CodeMeasurement {
+            mx: Matrix::identity(),
+            my: GROSS_MEASUREMENT.my,
+        };
+        let failures = check(&code);
+        assert_eq!(
+            CheckFailure::Order {
+                generator: "mx",
+                order: Some(1)
+            },
+            failures[0]
+        );
+        assert!(failures.contains(&CheckFailure::MeasurementCount(90)));
+    }
+
+    #[test]
+    fn parse_gross() {
+        let mx = "0 1 0 1 0 0; 0 1 0 0 0 1; 0 0 1 1 0 0; 1 1 0 1 1 0; 0 1 0 0 1 0; 1 1 1 1 0 1";
+        assert_eq!(Ok(GROSS_MEASUREMENT.mx), parse_matrix(mx));
+        assert!(parse_matrix("0 1").is_err());
+        assert!(parse_matrix(&mx.replace('0', "2")).is_err());
+    }
+}
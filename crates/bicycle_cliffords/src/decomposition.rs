@@ -12,14 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::{self, Read, Write},
+    num::NonZeroUsize,
+    ops::{ControlFlow, Deref, Range},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use crate::measurement::CodeMeasurement;
 use crate::pauli_string::PauliString;
-use crate::{native_measurement::NativeMeasurement, pauli_string};
-
-use bicycle_common::{AutomorphismData, BicycleISA, TwoBases};
-use log::{debug, error, info, trace, warn};
+use crate::{
+    native_measurement::{NativeFilter, NativeMeasurement},
+    pauli_string,
+};
+
+use bicycle_common::{AutomorphismData, BicycleISA, Pauli, TwoBases};
+use log::{debug, info, trace, warn};
+use memmap2::Mmap;
 use serde::{Deserialize, Serialize};
 
 // Defines a rotation that is implemented by a rotation conjugated with a base rotation.
@@ -98,16 +116,573 @@ impl NativeMeasurementImpl {
     }
 }
 
+/// A complete table, stored as a structure of arrays indexed by the measured PauliString.
+/// Walking a chain of conjugations only touches the `measurements` and `conjugated_with` arrays,
+/// while the costs are only needed to compare implementations.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompleteMeasurementTable {
-    measurements: Vec<MeasurementTableEntry>,
+    /// The measurement that is conjugated
+    measurements: TableArray<u32>,
+    /// The rotation that conjugates the measurement, or 0 (the identity) for native measurements
+    conjugated_with: TableArray<u32>,
+    costs: TableArray<u8>,
     native_measurements: HashMap<PauliString, NativeMeasurement>,
+    /// The chains of all measurements, if they are flattened, see [`Self::flatten_chains`].
+    /// They are not serialized since they can be recomputed from the other fields.
+    /// A table that is loaded from shards only has the chains of the loaded shards, see [`Self::from_shards`].
+    #[serde(skip)]
+    flat_chains: Option<FlatChains>,
+}
+
+/// An array of a [`CompleteMeasurementTable`] that is either in memory or mapped from a file,
+/// see [`CompleteMeasurementTable::open_mmap`]. Mapped arrays are paged in by the OS when they are first read.
+#[derive(Debug, Clone)]
+enum TableArray<T> {
+    Owned(Vec<T>),
+    Mapped { map: Arc<Mmap>, range: Range<usize> },
+}
+
+impl<T> From<Vec<T>> for TableArray<T> {
+    fn from(array: Vec<T>) -> Self {
+        Self::Owned(array)
+    }
+}
+
+impl<T: bytemuck::Pod> Deref for TableArray<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Self::Owned(array) => array,
+            Self::Mapped { map, range } => bytemuck::cast_slice(&map[range.clone()]),
+        }
+    }
+}
+
+impl<T: bytemuck::Pod + PartialEq> PartialEq for TableArray<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: bytemuck::Pod + Serialize> Serialize for TableArray<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for TableArray<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::Owned)
+    }
+}
+
+/// The flattened chains of conjugations, split into shards by the Paulis on the last qubits.
+/// Chains refer to the native measurements by their index in `natives`.
+#[derive(Debug, Clone)]
+struct FlatChains {
+    natives: Vec<NativeMeasurementImpl>,
+    shards: Vec<Option<TableShard>>,
+}
+
+/// The chains of conjugations of the PauliStrings in one shard, stored contiguously.
+/// The chain of the PauliString with local index `i` is `chains[offsets[i]..offsets[i + 1]]`,
+/// which starts with the base measurement followed by the rotations in the order that they conjugate it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableShard {
+    offsets: Vec<u32>,
+    chains: Vec<u16>,
+}
+
+impl TableShard {
+    /// Number of PauliStrings in a shard
+    const LEN: usize = CompleteMeasurementTable::ENTRIES / CompleteMeasurementTable::SHARDS;
+
+    /// Check that the shard has a chain for each of its PauliStrings
+    /// and that the chains only refer to the first `natives` native measurements
+    pub fn check(&self, natives: usize) -> Result<(), String> {
+        if self.offsets.len() != Self::LEN + 1 {
+            return Err(format!(
+                "A shard should have {} offsets, found {}",
+                Self::LEN + 1,
+                self.offsets.len()
+            ));
+        }
+        if self.offsets[0] != 0
+            || self.offsets.windows(2).any(|w| w[0] > w[1])
+            || self.offsets[Self::LEN] as usize != self.chains.len()
+        {
+            return Err(format!(
+                "The offsets of a shard should increase from 0 to the {} native measurements of its chains",
+                self.chains.len()
+            ));
+        }
+        if self.chains.iter().any(|i| usize::from(*i) >= natives) {
+            return Err("A chain refers to a missing native measurement".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl CompleteMeasurementTable {
+    fn from_entries(
+        entries: impl ExactSizeIterator<Item = MeasurementTableEntry>,
+        native_measurements: HashMap<PauliString, NativeMeasurement>,
+    ) -> Self {
+        let mut measurements = Vec::with_capacity(entries.len());
+        let mut conjugated_with = Vec::with_capacity(entries.len());
+        let mut costs = Vec::with_capacity(entries.len());
+        for entry in entries {
+            measurements.push(entry.measurement.0);
+            conjugated_with.push(entry.conjugated_with.map_or(0, |conj| conj.0));
+            costs.push(
+                u8::try_from(entry.cost).expect("The cost of a measurement should fit in a byte"),
+            );
+        }
+        Self {
+            measurements: measurements.into(),
+            conjugated_with: conjugated_with.into(),
+            costs: costs.into(),
+            native_measurements,
+            flat_chains: None,
+        }
+    }
+
+    /// Number of qubits, counted from the last one, whose Paulis select the shard of a PauliString
+    const SHARD_QUBITS: u32 = 3;
+    /// Number of shards of the flattened chains
+    pub const SHARDS: usize = 1 << (2 * Self::SHARD_QUBITS);
+
+    /// The shard of a PauliString and its index within the shard
+    fn shard_index(p: PauliString) -> (usize, usize) {
+        let low_qubits = 12 - Self::SHARD_QUBITS;
+        let low_mask = (1 << low_qubits) - 1;
+        let (x, z) = (p.0 & PauliString::ALL_QUBITS, p.0 >> 12);
+        let shard = (x >> low_qubits) | ((z >> low_qubits) << Self::SHARD_QUBITS);
+        let local = (x & low_mask) | ((z & low_mask) << low_qubits);
+        (shard as usize, local as usize)
+    }
+
+    /// The shards with the PauliStrings that are supported within `mask`, see [`PauliString::all_with_support`]
+    pub fn shards_with_support(mask: u32) -> impl Iterator<Item = usize> {
+        let low_qubits = 12 - Self::SHARD_QUBITS;
+        PauliString::all_with_support(mask & !((1 << low_qubits) - 1))
+            .map(|p| Self::shard_index(p).0)
+    }
+
+    /// The native measurements in the order that the chains of a [`TableShard`] refer to them
+    fn sorted_natives(
+        native_measurements: &HashMap<PauliString, NativeMeasurement>,
+    ) -> Vec<NativeMeasurementImpl> {
+        let mut natives: Vec<_> = native_measurements
+            .iter()
+            .map(|(p, native)| NativeMeasurementImpl::new(*native, *p))
+            .collect();
+        natives.sort_by_key(|native| native.measures());
+        natives
+    }
+
+    /// Precompute the chain of conjugations of every measurement,
+    /// such that [`Self::implementation`] does not need to walk the chain.
+    /// This takes 2 bytes per native measurement in a chain and 4 bytes per PauliString, on top of the table.
+    pub fn flatten_chains(&mut self) {
+        if self.flat_chains.is_some() {
+            return;
+        }
+        let shards = (0..Self::SHARDS).map(|i| Some(self.shard(i))).collect();
+        self.flat_chains = Some(FlatChains {
+            natives: Self::sorted_natives(&self.native_measurements),
+            shards,
+        });
+    }
+
+    /// The flattened chains of the PauliStrings in shard `i`, which can be loaded by [`Self::from_shards`]
+    pub fn shard(&self, i: usize) -> TableShard {
+        if let Some(shard) = self
+            .flat_chains
+            .as_ref()
+            .and_then(|flat| flat.shards[i].as_ref())
+        {
+            return shard.clone();
+        }
+        let index: HashMap<PauliString, u16> = Self::sorted_natives(&self.native_measurements)
+            .iter()
+            .enumerate()
+            .map(|(i, native)| {
+                let i = u16::try_from(i).expect("There should be few native measurements");
+                (native.measures(), i)
+            })
+            .collect();
+
+        // The PauliStrings of the shard, in the order of their local index
+        let low_qubits = 12 - Self::SHARD_QUBITS;
+        let high_mask = (1 << Self::SHARD_QUBITS) - 1;
+        let high = ((i as u32 & high_mask) << low_qubits)
+            | ((i as u32 >> Self::SHARD_QUBITS) << (12 + low_qubits));
+        let paulis =
+            PauliString::all_with_support((1 << low_qubits) - 1).map(|p| PauliString(p.0 | high));
+
+        let mut offsets = vec![0];
+        let mut chains = vec![];
+        let mut rotations = vec![];
+        for p in paulis {
+            // The identity and the measurements that are not implemented have an empty chain
+            if p.0 != 0 && self.costs[MeasurementTableBuilder::index(p)] != Self::MISSING {
+                rotations.clear();
+                let base = self.walk(MeasurementTableBuilder::index(p), &mut rotations, |p| {
+                    index[&p]
+                });
+                chains.push(index[&base]);
+                chains.extend(rotations.iter().rev());
+            }
+            offsets
+                .push(u32::try_from(chains.len()).expect("The chains should fit in u32 offsets"));
+        }
+        TableShard { offsets, chains }
+    }
+
+    /// A table that only has the chains of the given shards, e.g., to only load some shards of a file.
+    /// Only [`Self::implementation`] and [`Self::min_data`] of PauliStrings in these shards are supported,
+    /// other PauliStrings panic.
+    pub fn from_shards(
+        native_measurements: HashMap<PauliString, NativeMeasurement>,
+        shards: impl IntoIterator<Item = (usize, TableShard)>,
+    ) -> Self {
+        let mut flat = FlatChains {
+            natives: Self::sorted_natives(&native_measurements),
+            shards: vec![None; Self::SHARDS],
+        };
+        for (i, shard) in shards {
+            flat.shards[i] = Some(shard);
+        }
+        Self {
+            measurements: vec![].into(),
+            conjugated_with: vec![].into(),
+            costs: vec![].into(),
+            native_measurements,
+            flat_chains: Some(flat),
+        }
+    }
+
+    pub fn native_measurements(&self) -> &HashMap<PauliString, NativeMeasurement> {
+        &self.native_measurements
+    }
+
+    /// Number of entries of a table, one for each PauliString on 12 qubits
+    const ENTRIES: usize = 4usize.pow(12);
+
+    /// The first bytes of a table in the raw layout of [`Self::write_raw`], ending in the version of the layout
+    pub const RAW_MAGIC: &[u8; 8] = b"bicyraw1";
+
+    /// Write the table in a little-endian layout that [`Self::read_raw`] loads without decoding each entry:
+    /// [`Self::RAW_MAGIC`], the numbers of native measurements and of entries as `u64`s,
+    /// each native measurement as its measured PauliString and four bytes for its Paulis and automorphism,
+    /// and then the arrays of measurements, conjugations, and costs.
+    /// A table that is loaded from shards has no entries, so it cannot be written.
+    pub fn write_raw(&self, mut writer: impl Write) -> io::Result<()> {
+        if self.measurements.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A table that is loaded from shards cannot be written in the raw layout",
+            ));
+        }
+        writer.write_all(Self::RAW_MAGIC)?;
+        writer.write_all(&(self.native_measurements.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.measurements.len() as u64).to_le_bytes())?;
+        // Sorted such that the same table is always written to the same bytes
+        for native in Self::sorted_natives(&self.native_measurements) {
+            let (logical, automorphism) = (native.logical(), native.automorphism());
+            writer.write_all(&native.measures().0.to_le_bytes())?;
+            writer.write_all(&[
+                logical.get_basis_1() as u8,
+                logical.get_basis_7() as u8,
+                automorphism.get_x(),
+                automorphism.get_y(),
+            ])?;
+        }
+        for array in [&*self.measurements, &*self.conjugated_with] {
+            if cfg!(target_endian = "little") {
+                writer.write_all(bytemuck::cast_slice(array))?;
+            } else {
+                for value in array {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        writer.write_all(&self.costs)
+    }
+
+    /// Read the header of the raw layout up to the arrays: the native measurements and the number of entries
+    fn read_raw_header(
+        mut reader: impl Read,
+    ) -> io::Result<(HashMap<PauliString, NativeMeasurement>, usize)> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        let (name, version) = Self::RAW_MAGIC.split_at(7);
+        if magic[..7] != *name {
+            return Err(invalid(
+                "The measurement table is not in the raw layout".to_string(),
+            ));
+        }
+        if magic[7..] != *version {
+            return Err(invalid(format!(
+                "The measurement table has version {} of the raw layout, but only version {} is supported",
+                char::from(magic[7]).escape_default(),
+                char::from(version[0])
+            )));
+        }
+        let mut read_u64 = || -> io::Result<usize> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes) as usize)
+        };
+        let (natives, entries) = (read_u64()?, read_u64()?);
+        if entries != Self::ENTRIES {
+            return Err(invalid(format!(
+                "The measurement table should have {} entries, but its header has {entries}",
+                Self::ENTRIES
+            )));
+        }
+
+        let mut native_measurements = HashMap::new();
+        for _ in 0..natives {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            let measures = PauliString(u32::from_le_bytes(bytes[..4].try_into().unwrap()));
+            let pauli = |byte: u8| Pauli::try_from(usize::from(byte)).map_err(invalid);
+            let logical = TwoBases::new(pauli(bytes[4])?, pauli(bytes[5])?).ok_or_else(|| {
+                invalid(format!(
+                    "The native measurement of {measures} measures no logical qubit"
+                ))
+            })?;
+            let automorphism = AutomorphismData::new(bytes[6], bytes[7]);
+            let native = NativeMeasurement {
+                logical,
+                automorphism,
+            };
+            if native_measurements.insert(measures, native).is_some() {
+                return Err(invalid(format!(
+                    "The native measurement of {measures} appears more than once"
+                )));
+            }
+        }
+        Ok((native_measurements, entries))
+    }
+
+    /// Check that the chain of every measurement that the table implements ends at a native measurement,
+    /// such that looking up a measurement of a corrupt table cannot panic.
+    /// Every link of a chain goes to a cheaper measurement, since every native measurement costs at least 1,
+    /// so the chains cannot have cycles.
+    /// This reads every entry, so [`Self::open_mmap`] leaves it to the caller.
+    pub fn verify(&self) -> io::Result<()> {
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        let mut native = vec![false; self.costs.len()];
+        for p in self.native_measurements.keys() {
+            match native.get_mut(p.0 as usize) {
+                Some(is_native) => *is_native = true,
+                None => return invalid(format!("The native measurement {p} is not a PauliString")),
+            }
+        }
+        for i in 0..self.costs.len() {
+            let cost = self.costs[i];
+            if cost == Self::MISSING {
+                continue;
+            }
+            let (measurement, conjugate) = (self.measurements[i], self.conjugated_with[i]);
+            let measurement = measurement as usize;
+            if conjugate == 0 {
+                if measurement != i || (i != 0 && !native[i]) {
+                    return invalid(format!(
+                        "The measurement {} is not native, but is not conjugated either",
+                        PauliString(i as u32)
+                    ));
+                }
+                continue;
+            }
+            let linked = native.get(conjugate as usize) == Some(&true)
+                && measurement < self.costs.len()
+                && self.costs[measurement] < cost
+                && PauliString(measurement as u32)
+                    .conjugate_with(PauliString(conjugate).zero_pivot())
+                    == PauliString(i as u32);
+            if !linked {
+                return invalid(format!(
+                    "The measurement {} is not a cheaper measurement conjugated by a native measurement",
+                    PauliString(i as u32)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a table that is written by [`Self::write_raw`].
+    /// Each array is copied from the reader into one allocation instead of being decoded entry by entry,
+    /// see [`Self::open_mmap`] to look up the entries in the file without copying them.
+    /// A table with another version of the layout, with the wrong number of entries, with repeated native measurements,
+    /// or with more or fewer bytes than its header announces is rejected.
+    pub fn read_raw(mut reader: impl Read) -> io::Result<Self> {
+        let (native_measurements, entries) = Self::read_raw_header(&mut reader)?;
+        let mut read_u32s = || -> io::Result<Vec<u32>> {
+            let mut array = vec![0u32; entries];
+            reader.read_exact(bytemuck::cast_slice_mut(&mut array))?;
+            // This is a no-op on little-endian targets
+            array
+                .iter_mut()
+                .for_each(|value| *value = u32::from_le(*value));
+            Ok(array)
+        };
+        let measurements = read_u32s()?;
+        let conjugated_with = read_u32s()?;
+        let mut costs = vec![0; entries];
+        reader.read_exact(&mut costs)?;
+        if reader.read(&mut [0])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The measurement table has more bytes than its entries",
+            ));
+        }
+        let table = Self {
+            measurements: measurements.into(),
+            conjugated_with: conjugated_with.into(),
+            costs: costs.into(),
+            native_measurements,
+            flat_chains: None,
+        };
+        table.verify()?;
+        Ok(table)
+    }
+
+    /// Map a table that is written by [`Self::write_raw`] into memory without copying its arrays.
+    /// Opening only reads the header, so the pages of the arrays are read when they are first looked up,
+    /// and they are shared by the processes that map the same file.
+    /// The chains are not checked, use [`Self::verify`] for a file that may be corrupt;
+    /// looking up a measurement with a corrupt chain panics.
+    /// The file must not be modified while the table is open.
+    /// On big-endian targets, the table is read with [`Self::read_raw`] instead.
+    pub fn open_mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        if cfg!(target_endian = "big") {
+            return Self::read_raw(io::BufReader::new(file));
+        }
+        // SAFETY: The table is only sound as long as nobody modifies the file, which is documented above
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        let mut header = &map[..];
+        let (native_measurements, entries) = Self::read_raw_header(&mut header)?;
+        // The header is a multiple of 8 bytes and the map is page aligned, so the u32 arrays are aligned
+        let start = map.len() - header.len();
+        if header.len() != 9 * entries {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The measurement table should have {} bytes of entries, but has {}",
+                    9 * entries,
+                    header.len()
+                ),
+            ));
+        }
+        let (conjugations, costs) = (start + 4 * entries, start + 8 * entries);
+        let table = Self {
+            measurements: TableArray::Mapped {
+                map: Arc::clone(&map),
+                range: start..conjugations,
+            },
+            conjugated_with: TableArray::Mapped {
+                map: Arc::clone(&map),
+                range: conjugations..costs,
+            },
+            costs: TableArray::Mapped {
+                range: costs..map.len(),
+                map,
+            },
+            native_measurements,
+            flat_chains: None,
+        };
+        Ok(table)
+    }
+
+    /// Whether the arrays of the table are mapped from a file, see [`Self::open_mmap`]
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.measurements, TableArray::Mapped { .. })
+    }
+
+    /// The flattened chain of a PauliString, if the chains are flattened
+    fn flat_chain(&self, p: PauliString) -> Option<(&FlatChains, &[u16])> {
+        let flat = self.flat_chains.as_ref()?;
+        let (shard, i) = Self::shard_index(p);
+        let chains = flat.shards[shard].as_ref().unwrap_or_else(|| {
+            panic!("The shard {shard} of the measurement table with {p} is not loaded")
+        });
+        let chain = &chains.chains[chains.offsets[i] as usize..chains.offsets[i + 1] as usize];
+        Some((flat, chain))
+    }
+
+    /// The cost of the implementation of a PauliString under the [`CostModel`] of the table
+    fn cost(&self, p: PauliString) -> usize {
+        if !self.covers(p) {
+            return usize::MAX;
+        }
+        match self.costs.get(MeasurementTableBuilder::index(p)) {
+            Some(cost) => (*cost).into(),
+            // A table that is loaded from shards has no costs, so its chains are compared by their length
+            None => 2 * self.flat_chain(p).unwrap().1.len() - 1,
+        }
+    }
+
+    /// The cost of the PauliStrings that a partial table does not implement, see [`MeasurementTableBuilder::partial`]
+    const MISSING: u8 = u8::MAX;
+
+    /// Whether the table implements the measurement of a PauliString.
+    /// Only a partial table misses measurements, see [`MeasurementTableBuilder::partial`].
+    pub fn covers(&self, p: PauliString) -> bool {
+        match self.flat_chain(p) {
+            Some((_, chain)) => !chain.is_empty(),
+            None => self.costs[MeasurementTableBuilder::index(p)] != Self::MISSING,
+        }
+    }
+
+    /// Whether [`Self::min_data`] implements the data Paulis of `p` for some Pauli on the pivot
+    pub fn covers_data(&self, p: PauliString) -> bool {
+        [pauli_string::X1, pauli_string::Z1, pauli_string::Y1]
+            .into_iter()
+            .any(|pivot_pauli| self.covers(p * pivot_pauli))
+    }
+
+    /// Walk the chain of conjugations of the PauliString with index `i` and return its base measurement.
+    /// The conjugating rotations are pushed in the reverse order of applying them.
+    fn walk<T>(
+        &self,
+        mut i: usize,
+        rotations: &mut Vec<T>,
+        native: impl Fn(PauliString) -> T,
+    ) -> PauliString {
+        // Both loads of a link only depend on its index, so they are issued together.
+        // Every link lowers the cost, which is a byte, so longer chains have a cycle
+        for _ in 0..=u8::MAX {
+            let (measurement, conjugate) = (self.measurements[i], self.conjugated_with[i]);
+            if conjugate == 0 {
+                return PauliString(measurement);
+            }
+            rotations.push(native(PauliString(conjugate)));
+            i = measurement as usize;
+        }
+        panic!(
+            "The chain of {} in the measurement table has a cycle, the table is corrupt",
+            PauliString(i as u32)
+        )
+    }
+
     /// Look up the implementation for measuring a PauliString
-    fn get(&self, p: PauliString) -> Option<&MeasurementTableEntry> {
-        self.measurements.get(MeasurementTableBuilder::index(p))
+    fn get(&self, p: PauliString) -> Option<MeasurementTableEntry> {
+        let i = MeasurementTableBuilder::index(p);
+        if *self.costs.get(i)? == Self::MISSING {
+            return None;
+        }
+        Some(MeasurementTableEntry {
+            measurement: PauliString(self.measurements[i]),
+            conjugated_with: Some(PauliString(self.conjugated_with[i])).filter(|conj| conj.0 != 0),
+            cost: self.costs[i].into(),
+        })
     }
 
     /// Returns the a native measurement and its conjugating native measurements that implement rotations
@@ -116,31 +691,32 @@ impl CompleteMeasurementTable {
     pub fn implementation(&self, p: PauliString) -> MeasurementImpl {
         assert!(p.0 <= 4_u32.pow(12), "{}", p);
         assert!(p.0 != 0); // Cannot measure identity
+        assert!(
+            self.covers(p),
+            "The measurement table does not implement {p}"
+        );
 
-        let mut implementation = self.get(p).unwrap();
-
-        let mut rots = vec![];
-        while let Some(conjugate) = implementation.conjugated_with {
-            rots.push(conjugate);
-            implementation = self.get(implementation.measurement).unwrap();
+        if let Some((flat, chain)) = self.flat_chain(p) {
+            let (base, rotations) = chain.split_first().unwrap();
+            return MeasurementImpl {
+                measures: p,
+                base: flat.natives[usize::from(*base)],
+                rotations: rotations
+                    .iter()
+                    .map(|native| flat.natives[usize::from(*native)])
+                    .collect(),
+            };
         }
 
-        let base_meas = self
-            .native_measurements
-            .get(&implementation.measurement)
-            .unwrap();
-        let base_impl = NativeMeasurementImpl::new(*base_meas, implementation.measurement);
+        let i = MeasurementTableBuilder::index(p);
+        let native = |p: PauliString| {
+            NativeMeasurementImpl::new(*self.native_measurements.get(&p).unwrap(), p)
+        };
+        let mut native_rots = vec![];
+        let base = self.walk(i, &mut native_rots, native);
+        native_rots.reverse();
+        let base_impl = native(base);
 
-        let native_rots = rots
-            .into_iter()
-            .map(|p| {
-                self.native_measurements
-                    .get(&p)
-                    .map(|native| NativeMeasurementImpl::new(*native, p))
-                    .unwrap()
-            })
-            .rev()
-            .collect();
         MeasurementImpl {
             measures: p,
             base: base_impl,
@@ -148,9 +724,9 @@ impl CompleteMeasurementTable {
         }
     }
 
-    /// Minimize over the Pauli on the pivot to measure 11 qubits in the basis p.
+    /// Minimize the cost over the Pauli on the pivot to measure 11 qubits in the basis p, see [`CostModel`].
     /// This can be useful if you do not care about the basis of the pivot.
-    /// TODO: If this becomes the only method needed, then we can shrink table by factor 4.
+    /// If this is the only method needed, a [`MinimalMeasurementTable`] with a quarter of the entries is enough.
     pub fn min_data(&self, p: PauliString) -> MeasurementImpl {
         assert!(p.0 <= 4_u32.pow(12), "{}", p);
         assert!(
@@ -158,27 +734,396 @@ impl CompleteMeasurementTable {
             "Expected identity on pivot for {p}"
         );
 
-        // Find minimum-length implementation out of three options for the pivot.
-
-        [pauli_string::X1, pauli_string::Z1, pauli_string::Y1]
+        // Find the cheapest implementation out of three options for the pivot,
+        // e.g., the one with the least error if the table is built with a model of the error.
+        // Only the cheapest chain is walked.
+        let q = [pauli_string::X1, pauli_string::Z1, pauli_string::Y1]
             .into_iter()
             .map(|pivot_pauli| p * pivot_pauli) // insert pivot basis
-            .map(|q| self.implementation(q)) // look up implementation
-            .min_by_key(|meas_impl| meas_impl.rotations().len())
-            .unwrap()
+            .min_by_key(|q| self.cost(*q))
+            .unwrap();
+        self.implementation(q)
     }
 }
 
 impl TryFrom<MeasurementTableBuilder> for CompleteMeasurementTable {
-    type Error = String;
+    type Error = IncompleteTable;
 
     fn try_from(value: MeasurementTableBuilder) -> Result<Self, Self::Error> {
-        let measurements: Option<Vec<_>> = value.measurements.into_iter().collect();
-        Ok(CompleteMeasurementTable {
-            measurements: measurements.ok_or("All measurements should have an implementation")?,
-            native_measurements: value.native_measurements,
+        if value.len() < value.measurements.len() {
+            return Err(IncompleteTable {
+                missing: value.missing().collect(),
+                reached: value.len(),
+            });
+        }
+        Ok(value.partial())
+    }
+}
+
+/// The lookups of measurements on the data qubits that compiling needs,
+/// which both a [`CompleteMeasurementTable`] and a [`MinimalMeasurementTable`] implement
+pub trait MeasurementTable: Sync {
+    /// The cheapest implementation of measuring the data qubits in the basis p, see [`CompleteMeasurementTable::min_data`]
+    fn min_data(&self, p: PauliString) -> MeasurementImpl;
+
+    /// Whether [`Self::min_data`] implements the data Paulis of `p`, see [`CompleteMeasurementTable::covers_data`]
+    fn covers_data(&self, p: PauliString) -> bool;
+
+    fn native_measurements(&self) -> &HashMap<PauliString, NativeMeasurement>;
+}
+
+impl MeasurementTable for CompleteMeasurementTable {
+    fn min_data(&self, p: PauliString) -> MeasurementImpl {
+        CompleteMeasurementTable::min_data(self, p)
+    }
+
+    fn covers_data(&self, p: PauliString) -> bool {
+        CompleteMeasurementTable::covers_data(self, p)
+    }
+
+    fn native_measurements(&self) -> &HashMap<PauliString, NativeMeasurement> {
+        CompleteMeasurementTable::native_measurements(self)
+    }
+}
+
+/// A table with only the cheapest implementation of each of the 4^11 measurements on the data qubits,
+/// i.e., the choice of the Pauli on the pivot of [`CompleteMeasurementTable::min_data`] and its flattened chain.
+/// It takes about a third of the memory of the complete table, but it does not measure the pivot in a given basis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimalMeasurementTable {
+    native_measurements: HashMap<PauliString, NativeMeasurement>,
+    /// The native measurements in the order that the chains refer to them
+    natives: Vec<NativeMeasurementImpl>,
+    /// The Pauli on the pivot of each implementation, as an index into [`Self::PIVOTS`]
+    pivots: Vec<u8>,
+    /// The chains by the index of the data Paulis, see [`Self::index`], which are empty for the identity and missing measurements
+    chains: TableShard,
+}
+
+/// The serialized form of a [`MinimalMeasurementTable`], without the native measurements that are sorted when it is read
+#[derive(Serialize, Deserialize)]
+struct MinimalTableData<'a> {
+    native_measurements: Cow<'a, HashMap<PauliString, NativeMeasurement>>,
+    pivots: Cow<'a, [u8]>,
+    chains: Cow<'a, TableShard>,
+}
+
+impl MinimalMeasurementTable {
+    /// The Paulis on the pivot that [`CompleteMeasurementTable::min_data`] chooses from
+    const PIVOTS: [PauliString; 3] = [pauli_string::X1, pauli_string::Z1, pauli_string::Y1];
+
+    /// The first bytes of a table written by [`Self::write`]
+    pub const MAGIC: &[u8; 8] = b"bicymin1";
+
+    /// The index of a PauliString with the identity on the pivot among the 4^11 data Paulis
+    fn index(p: PauliString) -> usize {
+        let data = PauliString::ALL_QUBITS >> 1;
+        (((p.0 >> 1) & data) | (((p.0 >> 13) & data) << 11)) as usize
+    }
+
+    /// The data Pauli with index `i`, see [`Self::index`]
+    fn data_pauli(i: usize) -> PauliString {
+        let data = PauliString::ALL_QUBITS >> 1;
+        let i = i as u32;
+        PauliString(((i & data) << 1) | ((i >> 11) << 13))
+    }
+
+    /// Write [`Self::MAGIC`] followed by the table encoded by `bitcode`
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let data = MinimalTableData {
+            native_measurements: Cow::Borrowed(&self.native_measurements),
+            pivots: Cow::Borrowed(&self.pivots),
+            chains: Cow::Borrowed(&self.chains),
+        };
+        let encoded =
+            bitcode::serialize(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.write_all(Self::MAGIC)?;
+        writer.write_all(&encoded)
+    }
+
+    /// Read a table written by [`Self::write`]
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != Self::MAGIC {
+            return Err(invalid("Not a minimal measurement table".to_string()));
+        }
+        let mut encoded = vec![];
+        reader.read_to_end(&mut encoded)?;
+        let data: MinimalTableData =
+            bitcode::deserialize(&encoded).map_err(|e| invalid(e.to_string()))?;
+        let entries = 4usize.pow(PauliString::NR_DATA_QUBITS as u32);
+        if data.pivots.len() != entries || data.chains.offsets.len() != entries + 1 {
+            return Err(invalid(format!(
+                "The table should have {entries} measurements, found {}",
+                data.pivots.len()
+            )));
+        }
+        let offsets = &data.chains.offsets;
+        if offsets[0] != 0
+            || offsets.windows(2).any(|w| w[0] > w[1])
+            || offsets[entries] as usize != data.chains.chains.len()
+        {
+            return Err(invalid(format!(
+                "The offsets of the chains should increase from 0 to the {} native measurements of the chains",
+                data.chains.chains.len()
+            )));
+        }
+        if let Some(pivot) = data
+            .pivots
+            .iter()
+            .find(|pivot| usize::from(**pivot) >= Self::PIVOTS.len())
+        {
+            return Err(invalid(format!(
+                "The pivot Pauli {pivot} should be less than {}",
+                Self::PIVOTS.len()
+            )));
+        }
+        let natives = CompleteMeasurementTable::sorted_natives(&data.native_measurements);
+        if data
+            .chains
+            .chains
+            .iter()
+            .any(|i| usize::from(*i) >= natives.len())
+        {
+            return Err(invalid(
+                "A chain refers to a missing native measurement".to_string(),
+            ));
+        }
+        Ok(Self {
+            native_measurements: data.native_measurements.into_owned(),
+            natives,
+            pivots: data.pivots.into_owned(),
+            chains: data.chains.into_owned(),
         })
     }
+
+    fn chain(&self, p: PauliString) -> &[u16] {
+        let offsets = &self.chains.offsets;
+        let i = Self::index(p);
+        &self.chains.chains[offsets[i] as usize..offsets[i + 1] as usize]
+    }
+
+    /// The cheapest implementation of measuring the data qubits in the basis p, like [`CompleteMeasurementTable::min_data`]
+    pub fn min_data(&self, p: PauliString) -> MeasurementImpl {
+        assert!(
+            p.pivot_bits() == pauli_string::ID,
+            "Expected identity on pivot for {p}"
+        );
+        assert!(p.0 != 0); // Cannot measure identity
+        let chain = self.chain(p);
+        let (base, rotations) = chain
+            .split_first()
+            .unwrap_or_else(|| panic!("The measurement table does not implement {p}"));
+        MeasurementImpl {
+            measures: p * Self::PIVOTS[usize::from(self.pivots[Self::index(p)])],
+            base: self.natives[usize::from(*base)],
+            rotations: rotations
+                .iter()
+                .map(|native| self.natives[usize::from(*native)])
+                .collect(),
+        }
+    }
+
+    /// Whether [`Self::min_data`] implements the data Paulis of `p`, ignoring the Pauli on the pivot
+    pub fn covers_data(&self, p: PauliString) -> bool {
+        !self.chain(p.zero_pivot()).is_empty()
+    }
+
+    pub fn native_measurements(&self) -> &HashMap<PauliString, NativeMeasurement> {
+        &self.native_measurements
+    }
+}
+
+/// Keep the cheapest implementation of each measurement on the data qubits.
+/// The table must have all entries, or the chains of all shards if it is loaded from shards.
+impl From<&CompleteMeasurementTable> for MinimalMeasurementTable {
+    fn from(table: &CompleteMeasurementTable) -> Self {
+        let natives = CompleteMeasurementTable::sorted_natives(&table.native_measurements);
+        let index: HashMap<PauliString, u16> = natives
+            .iter()
+            .enumerate()
+            .map(|(i, native)| {
+                let i = u16::try_from(i).expect("There should be few native measurements");
+                (native.measures(), i)
+            })
+            .collect();
+
+        let entries = 4usize.pow(PauliString::NR_DATA_QUBITS as u32);
+        let mut pivots = Vec::with_capacity(entries);
+        let mut offsets = Vec::with_capacity(entries + 1);
+        offsets.push(0);
+        let mut chains = vec![];
+        for i in 0..entries {
+            let p = Self::data_pauli(i);
+            let mut pivot = 0;
+            if p.0 != 0 && table.covers_data(p) {
+                let implementation = table.min_data(p);
+                pivot = Self::PIVOTS
+                    .iter()
+                    .position(|pivot| *pivot == implementation.measures().pivot_bits())
+                    .expect("The implementation should measure the pivot")
+                    as u8;
+                chains.push(index[&implementation.base_measurement().measures()]);
+                chains.extend(
+                    implementation
+                        .rotations()
+                        .iter()
+                        .map(|rotation| index[&rotation.measures()]),
+                );
+            }
+            pivots.push(pivot);
+            offsets
+                .push(u32::try_from(chains.len()).expect("The chains should fit in u32 offsets"));
+        }
+        Self {
+            native_measurements: table.native_measurements.clone(),
+            natives,
+            pivots,
+            chains: TableShard { offsets, chains },
+        }
+    }
+}
+
+impl MeasurementTable for MinimalMeasurementTable {
+    fn min_data(&self, p: PauliString) -> MeasurementImpl {
+        MinimalMeasurementTable::min_data(self, p)
+    }
+
+    fn covers_data(&self, p: PauliString) -> bool {
+        MinimalMeasurementTable::covers_data(self, p)
+    }
+
+    fn native_measurements(&self) -> &HashMap<PauliString, NativeMeasurement> {
+        MinimalMeasurementTable::native_measurements(self)
+    }
+}
+
+/// The search did not reach all PauliStrings, e.g., for a custom [`CodeMeasurement`] or restricted native measurements.
+/// A table of the measurements that were found can still be used, see [`MeasurementTableBuilder::partial`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct IncompleteTable {
+    /// The PauliStrings whose measurement was not found
+    pub missing: Vec<PauliString>,
+    /// Number of PauliStrings whose measurement was found, including the identity
+    pub reached: usize,
+}
+
+// Millions of PauliStrings can be missing, so only their number is shown, e.g., when `main` returns the error
+impl std::fmt::Debug for IncompleteTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncompleteTable")
+            .field(
+                "missing",
+                &format_args!("[{} PauliStrings]", self.missing.len()),
+            )
+            .field("reached", &self.reached)
+            .finish()
+    }
+}
+
+impl Display for IncompleteTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The table is incomplete: only {} of {} Pauli measurements can be implemented",
+            self.reached,
+            self.reached + self.missing.len()
+        )
+    }
+}
+
+impl Error for IncompleteTable {}
+
+/// The cost of the measurements in a (partial) table relative to a reference table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostInflation {
+    /// Number of non-trivial Pauli measurements
+    pub paulis: usize,
+    /// Number of measurements found in the table
+    pub found: usize,
+    /// Total cost of the found measurements
+    pub cost: u64,
+    /// Total cost of the same measurements in the reference table
+    pub reference_cost: u64,
+}
+
+impl CostInflation {
+    /// Ratio of the total cost to the reference cost
+    pub fn ratio(&self) -> f64 {
+        self.cost as f64 / self.reference_cost as f64
+    }
+}
+
+/// Number of measurements to extend between reports of the progress of a build
+const PROGRESS_INTERVAL: usize = 1 << 14;
+
+/// Progress of building a table, see [`MeasurementTableBuilder::build_with_progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildProgress {
+    /// Iteration of the search, where each iteration finds measurements that take one more rotation
+    pub iteration: usize,
+    /// Number of measurements found so far
+    pub found: usize,
+    /// Number of measurements in a complete table
+    pub total: usize,
+}
+
+impl BuildProgress {
+    pub fn fraction(&self) -> f64 {
+        self.found as f64 / self.total as f64
+    }
+}
+
+/// The build was cancelled, see [`MeasurementTableBuilder::build_cancellable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildCancelled;
+
+impl Display for BuildCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Building the measurement table was cancelled")
+    }
+}
+
+impl Error for BuildCancelled {}
+
+/// Assigns a cost to each native measurement, such that [`MeasurementTableBuilder`] finds the implementation
+/// of each measurement with the least total cost of its native measurements.
+/// Each rotation takes two native measurements, so it costs twice as much as its native measurement.
+pub trait CostModel {
+    /// The cost of a native measurement, which must be at least 1.
+    /// The cost of each implementation must fit in a byte, less one for the measurements that a partial table misses.
+    fn cost(&self, native: &NativeMeasurement) -> u32;
+}
+
+/// Every native measurement costs 1, such that each measurement is implemented with the fewest rotations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UniformCost;
+
+impl CostModel for UniformCost {
+    fn cost(&self, _native: &NativeMeasurement) -> u32 {
+        1
+    }
+}
+
+/// The state of a search between two iterations, see [`MeasurementTableBuilder::checkpoint`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SearchState {
+    /// The last iteration that was completed
+    iteration: usize,
+    /// The measurements that were improved in the last iteration, to be conjugated in the next one
+    pending: Vec<PauliString>,
+    /// The highest cost of a measurement found so far
+    max_cost: u32,
+}
+
+/// A partial table with the state of its search, as written to a checkpoint file
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<'a> {
+    measurements: Cow<'a, [Option<MeasurementTableEntry>]>,
+    native_measurements: Cow<'a, HashMap<PauliString, NativeMeasurement>>,
+    state: Cow<'a, SearchState>,
 }
 
 #[derive(Debug)]
@@ -186,34 +1131,67 @@ pub struct MeasurementTableBuilder {
     measurements: Vec<Option<MeasurementTableEntry>>,
     native_measurements: HashMap<PauliString, NativeMeasurement>,
     len: usize, // Count how many Some entries there are in measurements
-    code: CodeMeasurement,
+    flatten_chains: bool,
+    /// Where to write the state of the search, and after how many iterations
+    checkpoint: Option<(PathBuf, NonZeroUsize)>,
+    /// The state to continue the search from, if the builder was resumed from a checkpoint
+    resumed: Option<SearchState>,
 }
 
 impl MeasurementTableBuilder {
     pub fn new(native_measurements: Vec<NativeMeasurement>, code: CodeMeasurement) -> Self {
+        Self::with_cost_model(native_measurements, code, &UniformCost)
+    }
+
+    /// Create a builder that minimizes the cost of the native measurements of each implementation under `model`,
+    /// instead of their number.
+    /// Of the native measurements that measure the same PauliString, the cheapest one is used.
+    pub fn with_cost_model(
+        native_measurements: Vec<NativeMeasurement>,
+        code: CodeMeasurement,
+        model: &impl CostModel,
+    ) -> Self {
         let len = 0;
         let measurements = vec![None; 4usize.pow(12)];
 
-        let native_lookup: HashMap<PauliString, NativeMeasurement> = native_measurements
-            .into_iter()
-            .map(|meas| (code.measures(&meas), meas))
-            .collect();
+        let mut native_lookup: HashMap<PauliString, (NativeMeasurement, u32)> = HashMap::new();
+        for meas in native_measurements {
+            let cost = model.cost(&meas);
+            assert!(
+                cost > 0,
+                "The native measurement {meas:?} should cost at least 1"
+            );
+            // Of equally cheap native measurements, the last one is kept
+            native_lookup
+                .entry(code.measures(&meas))
+                .and_modify(|cur| {
+                    if cur.1 >= cost {
+                        *cur = (meas, cost);
+                    }
+                })
+                .or_insert((meas, cost));
+        }
 
         let mut table = MeasurementTableBuilder {
             measurements,
             native_measurements: HashMap::new(), // Placeholder; set later.
             len,
-            code,
+            flatten_chains: false,
+            checkpoint: None,
+            resumed: None,
         };
 
-        for p in native_lookup.keys() {
+        for (p, (_, cost)) in &native_lookup {
             table.insert(MeasurementTableEntry {
                 measurement: *p,
                 conjugated_with: None,
-                cost: 1, // TODO: Adjust me depending on noise simulations!
+                cost: *cost,
             });
         }
-        table.native_measurements = native_lookup;
+        table.native_measurements = native_lookup
+            .into_iter()
+            .map(|(p, (meas, _))| (p, meas))
+            .collect();
 
         // Insert identity
         let identity = MeasurementTableEntry {
@@ -226,18 +1204,118 @@ impl MeasurementTableBuilder {
         table
     }
 
+    /// Create a builder with the native measurements that `filter` allows
+    pub fn with_filter(filter: &NativeFilter, code: CodeMeasurement) -> Self {
+        Self::new(filter.native_measurements(), code)
+    }
+
+    /// Flatten the chains of conjugations when completing the table, see [`CompleteMeasurementTable::flatten_chains`]
+    pub fn flatten_chains(mut self, flatten: bool) -> Self {
+        self.flatten_chains = flatten;
+        self
+    }
+
+    /// Write the partial table and the state of the search to `path` after every `every` iterations,
+    /// such that an interrupted build can continue from there, see [`Self::resume`].
+    /// A checkpoint that cannot be written is skipped with a warning.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, every: NonZeroUsize) -> Self {
+        self.checkpoint = Some((path.into(), every));
+        self
+    }
+
+    /// Continue the build that wrote a checkpoint to `path`, see [`Self::checkpoint`].
+    /// The native measurements and their costs are those of the interrupted build,
+    /// but the builder does not write checkpoints or flatten chains unless it is configured again.
+    pub fn resume(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let checkpoint: Checkpoint = bitcode::deserialize(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let measurements = checkpoint.measurements.into_owned();
+        if measurements.len() != 4usize.pow(12) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The checkpoint has {} measurements instead of {}",
+                    measurements.len(),
+                    4usize.pow(12)
+                ),
+            ));
+        }
+        let state = checkpoint.state.into_owned();
+        info!(
+            "Resuming the build of the measurement table after iteration {}",
+            state.iteration
+        );
+        Ok(Self {
+            len: measurements.iter().flatten().count(),
+            measurements,
+            native_measurements: checkpoint.native_measurements.into_owned(),
+            flatten_chains: false,
+            checkpoint: None,
+            resumed: Some(state),
+        })
+    }
+
+    /// Write a checkpoint to a temporary file first, such that an interruption cannot corrupt the previous checkpoint
+    fn write_checkpoint(&self, path: &Path, state: &SearchState) -> io::Result<()> {
+        let checkpoint = Checkpoint {
+            measurements: Cow::Borrowed(&self.measurements),
+            native_measurements: Cow::Borrowed(&self.native_measurements),
+            state: Cow::Borrowed(state),
+        };
+        let bytes = bitcode::serialize(&checkpoint)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, bytes)?;
+        std::fs::rename(&temporary, path)
+    }
+
     pub fn build(&mut self) {
+        self.build_with_progress(|_| {});
+    }
+
+    /// Build the table and report the progress regularly, e.g., to show a progress bar
+    pub fn build_with_progress(&mut self, mut progress: impl FnMut(BuildProgress)) {
+        let _ = self.search(|report| {
+            progress(report);
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// Build the table until `cancel` is set, e.g., by another thread or by an async task
+    /// that runs the build on a blocking thread.
+    /// The flag is checked whenever the progress is reported.
+    /// A cancelled build leaves the table incomplete, but it can continue from its last checkpoint, see [`Self::checkpoint`].
+    pub fn build_cancellable(
+        &mut self,
+        cancel: &AtomicBool,
+        mut progress: impl FnMut(BuildProgress),
+    ) -> Result<(), BuildCancelled> {
+        let flow = self.search(|report| {
+            progress(report);
+            if cancel.load(Ordering::Relaxed) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        match flow {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(BuildCancelled),
+        }
+    }
+
+    /// Search for the cheapest implementations until the table is complete or `progress` breaks
+    fn search(
+        &mut self,
+        mut progress: impl FnMut(BuildProgress) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         info!("Synthesizing all measurements from base measurements");
-        let base_measurements = NativeMeasurement::all();
 
         // 4^12 possible Pauli measurements on 12 qubits
         let nr_paulis: usize = 4_usize.pow(12);
 
-        let mut next_paulis = base_measurements
-            .iter()
-            .map(|m| self.code.measures(m))
-            .collect();
-
         // Create a set of base rotations
         // We pick the cheapest rotation for each paulistring, if there is duplication
         let mut base_rots: HashMap<PauliString, MeasurementTableEntry> = HashMap::new();
@@ -258,6 +1336,9 @@ impl MeasurementTableBuilder {
                 })
                 .or_insert(*native_impl);
         }
+        // Conjugate in a fixed order, so that the same one of equally cheap implementations is found on every run
+        let mut base_rots: Vec<_> = base_rots.into_iter().collect();
+        base_rots.sort_unstable_by_key(|(p, _)| *p);
 
         debug!(
             "Starting search with {} base measurements and {} base rotations",
@@ -268,8 +1349,39 @@ impl MeasurementTableBuilder {
             trace!("Native measurement: {:?}", meas.implements());
         }
 
-        let mut cur = 1; // Count loop iterations by the cost of the current rotation
-        while self.len() < nr_paulis {
+        // Implementations that are found later can still be cheaper if the native measurements cost differently,
+        // so the search continues until conjugating the pending measurements cannot improve the table
+        let min_rotation = base_rots
+            .iter()
+            .map(|(_, rot)| rot.cost())
+            .min()
+            .unwrap_or(0);
+        let SearchState {
+            iteration: mut cur, // Count loop iterations by the number of rotations
+            pending: mut next_paulis,
+            mut max_cost,
+        } = self.resumed.take().unwrap_or_else(|| SearchState {
+            iteration: 1,
+            pending: {
+                let mut pending: Vec<_> = self.native_measurements.keys().copied().collect();
+                pending.sort_unstable();
+                pending
+            },
+            max_cost: self
+                .native_impls()
+                .map(|meas| meas.cost())
+                .max()
+                .unwrap_or(0),
+        });
+
+        loop {
+            if self.len() == nr_paulis
+                && next_paulis
+                    .iter()
+                    .all(|p| self.get(*p).unwrap().cost() + 2 * min_rotation >= max_cost)
+            {
+                break;
+            }
             let prev_paulis = next_paulis;
             next_paulis = Vec::new();
 
@@ -277,7 +1389,10 @@ impl MeasurementTableBuilder {
             debug!("Iteration {cur}");
 
             // Conjugate all rotations of the cur cost by all base measurements to find new rotations
-            for prev_pauli in prev_paulis {
+            for (i, prev_pauli) in prev_paulis.into_iter().enumerate() {
+                if i > 0 && i % PROGRESS_INTERVAL == 0 {
+                    progress(self.progress(cur))?;
+                }
                 // Tight inner loop of fixed size, maybe optimize somehow by giving compiler hint?
                 for (rot_pauli, rot_impl) in base_rots.iter() {
                     let prev_meas = self.get(prev_pauli)
@@ -290,45 +1405,139 @@ impl MeasurementTableBuilder {
 
                     let new_pauli = new_rotation_impl.implements();
                     let existing = self.get(new_pauli);
-                    match existing {
-                        None => {
-                            self.insert(new_rotation_impl);
-                            next_paulis.push(new_pauli);
-                        }
-                        Some(existing_impl) => {
-                            if existing_impl.cost() > new_rotation_impl.cost() {
-                                self.insert(new_rotation_impl);
-                                next_paulis.push(new_pauli);
-                            }
-                        }
+                    if existing
+                        .is_none_or(|existing_impl| existing_impl.cost() > new_rotation_impl.cost())
+                    {
+                        max_cost = max_cost.max(new_rotation_impl.cost());
+                        self.insert(new_rotation_impl);
+                        next_paulis.push(new_pauli);
                     }
                 }
             }
 
             debug!("Found {} new operations of {} cost", next_paulis.len(), cur);
             debug!("Total operations found: {} / {}", self.len(), nr_paulis);
+            let checkpoint = self
+                .checkpoint
+                .as_ref()
+                .filter(|(_, every)| cur % every.get() == 0);
+            if let Some((path, _)) = checkpoint {
+                let state = SearchState {
+                    iteration: cur,
+                    pending: next_paulis,
+                    max_cost,
+                };
+                match self.write_checkpoint(path, &state) {
+                    Ok(()) => debug!("Wrote checkpoint of iteration {cur} to {}", path.display()),
+                    Err(e) => warn!("Could not write checkpoint to {}: {e}", path.display()),
+                }
+                next_paulis = state.pending;
+            }
+            progress(self.progress(cur))?;
 
-            if next_paulis.is_empty() {
-                error!(
+            if next_paulis.is_empty() && self.len() < nr_paulis {
+                warn!(
                     "Did not find new operations, aborting. Found {} / {} operations",
                     self.len(),
                     nr_paulis
                 );
                 for (index, meas_impl) in self.measurements.iter().enumerate() {
                     if meas_impl.is_none() {
-                        warn!("Did not find {}", PauliString(index as u32));
+                        trace!("Did not find {}", PauliString(index as u32));
                     }
                 }
                 break;
             }
         }
+        ControlFlow::Continue(())
+    }
+
+    fn progress(&self, iteration: usize) -> BuildProgress {
+        BuildProgress {
+            iteration,
+            found: self.len(),
+            total: self.measurements.len(),
+        }
     }
 
     /// Try to convert to a complete measurement table
-    pub fn complete(self) -> Result<CompleteMeasurementTable, String> {
+    pub fn complete(self) -> Result<CompleteMeasurementTable, IncompleteTable> {
         self.try_into()
     }
 
+    /// Convert to a table that only implements the measurements that were found,
+    /// e.g., to compile programs that only measure those, see [`CompleteMeasurementTable::covers`].
+    pub fn partial(self) -> CompleteMeasurementTable {
+        let measurements = self.measurements.into_iter().enumerate().map(|(i, found)| {
+            found
+                .map(Self::check_cost)
+                .unwrap_or(MeasurementTableEntry {
+                    measurement: PauliString(i as u32),
+                    conjugated_with: None,
+                    cost: CompleteMeasurementTable::MISSING.into(),
+                })
+        });
+        let mut table =
+            CompleteMeasurementTable::from_entries(measurements, self.native_measurements);
+        if self.flatten_chains {
+            table.flatten_chains();
+        }
+        table
+    }
+
+    /// Check that the cost of a found measurement is not taken for a missing one in the table
+    fn check_cost(entry: MeasurementTableEntry) -> MeasurementTableEntry {
+        assert!(
+            entry.cost < CompleteMeasurementTable::MISSING.into(),
+            "The cost {} of measuring {} should be less than {}, use cheaper native measurements in the cost model",
+            entry.cost,
+            entry.implements(),
+            CompleteMeasurementTable::MISSING
+        );
+        entry
+    }
+
+    /// The PauliStrings whose measurement was not found
+    fn missing(&self) -> impl Iterator<Item = PauliString> + '_ {
+        self.measurements
+            .iter()
+            .enumerate()
+            .filter(|(_, found)| found.is_none())
+            .map(|(i, _)| PauliString(i as u32))
+    }
+
+    /// Complete the table with the implementations of `fallback` for measurements that were not found.
+    /// Measurements that were found keep their implementation from this table.
+    pub fn complete_with(self, fallback: &CompleteMeasurementTable) -> CompleteMeasurementTable {
+        let measurements = self.measurements.into_iter().enumerate().map(|(i, found)| {
+            found
+                .map(Self::check_cost)
+                .or_else(|| fallback.get(PauliString(i as u32)))
+                .unwrap()
+        });
+        let mut native_measurements = fallback.native_measurements.clone();
+        native_measurements.extend(self.native_measurements);
+        let mut table = CompleteMeasurementTable::from_entries(measurements, native_measurements);
+        if self.flatten_chains {
+            table.flatten_chains();
+        }
+        table
+    }
+
+    /// Compare the cost of the measurements found so far to their cost in the `reference` table
+    pub fn cost_inflation(&self, reference: &CompleteMeasurementTable) -> CostInflation {
+        let found: Vec<_> = self.measurements.iter().skip(1).flatten().collect();
+        CostInflation {
+            paulis: self.measurements.len() - 1,
+            found: found.len(),
+            cost: found.iter().map(|entry| u64::from(entry.cost())).sum(),
+            reference_cost: found
+                .iter()
+                .map(|entry| u64::from(reference.get(entry.implements()).unwrap().cost()))
+                .sum(),
+        }
+    }
+
     fn index(p: PauliString) -> usize {
         let i = p.0 as usize;
 
@@ -373,6 +1582,8 @@ mod tests {
     use bicycle_common::Pauli::{I, X, Y, Z};
     use bicycle_common::{AutomorphismData, TwoBases};
 
+    use std::collections::HashSet;
+
     use crate::{GROSS_MEASUREMENT, TWOGROSS_MEASUREMENT};
 
     use super::*;
@@ -433,6 +1644,125 @@ mod tests {
         assert_eq!(Some(&p_impl), table.get(p));
     }
 
+    /// A partial table of all PauliStrings with two native measurements and the conjugation of one by the other,
+    /// and the offset of the entries in the raw layout
+    fn conjugated_table() -> (CompleteMeasurementTable, [MeasurementTableEntry; 3], usize) {
+        let native = |automorphism| NativeMeasurement {
+            automorphism,
+            logical: TwoBases::new(X, Y).unwrap(),
+        };
+        let p: PauliString = (&[Y, Y, I, I, I, Y, I, I, I, I, I, Z]).into();
+        let r: PauliString = (&[X, Z, I, I, I, I, I, I, I, I, I, I]).into();
+        let entries = [
+            MeasurementTableEntry {
+                measurement: p,
+                conjugated_with: None,
+                cost: 1,
+            },
+            MeasurementTableEntry {
+                measurement: r,
+                conjugated_with: None,
+                cost: 1,
+            },
+            MeasurementTableEntry {
+                measurement: p,
+                conjugated_with: Some(r),
+                cost: 3,
+            },
+        ];
+        let found: HashMap<_, _> = entries
+            .iter()
+            .map(|entry| (entry.implements().0, *entry))
+            .collect();
+        let table = CompleteMeasurementTable::from_entries(
+            (0..4u32.pow(12)).map(|i| {
+                found.get(&i).copied().unwrap_or(MeasurementTableEntry {
+                    measurement: PauliString(i),
+                    conjugated_with: None,
+                    cost: CompleteMeasurementTable::MISSING.into(),
+                })
+            }),
+            HashMap::from([
+                (p, native(AutomorphismData::new(2, 1))),
+                (r, native(AutomorphismData::new(0, 0))),
+            ]),
+        );
+        (table, entries, 24 + 2 * 8)
+    }
+
+    #[test]
+    fn raw_layout() -> io::Result<()> {
+        let (table, entries, header) = conjugated_table();
+        let mut written = vec![];
+        table.write_raw(&mut written)?;
+        assert_eq!(header + 9 * 4usize.pow(12), written.len());
+        let read = CompleteMeasurementTable::read_raw(written.as_slice())?;
+        assert_eq!(table.native_measurements, read.native_measurements);
+        assert_eq!(
+            (&table.measurements, &table.conjugated_with, &table.costs),
+            (&read.measurements, &read.conjugated_with, &read.costs)
+        );
+        let conjugated = entries[2].implements();
+        assert_eq!(
+            table.implementation(conjugated),
+            read.implementation(conjugated)
+        );
+
+        assert!(CompleteMeasurementTable::read_raw(&written[..written.len() - 1]).is_err());
+        assert!(CompleteMeasurementTable::read_raw(&b"bicyshrd"[..]).is_err());
+        let sharded = CompleteMeasurementTable::from_shards(HashMap::new(), []);
+        assert!(sharded.write_raw(vec![]).is_err());
+
+        // Corrupt copies of the table are rejected
+        let corrupt = |offset: usize, bytes: &[u8]| {
+            let mut corrupt = written.clone();
+            corrupt[offset..offset + bytes.len()].copy_from_slice(bytes);
+            CompleteMeasurementTable::read_raw(corrupt.as_slice())
+        };
+        assert!(corrupt(7, b"2").is_err());
+        let entries = 2u64.to_le_bytes();
+        assert!(corrupt(16, &entries).is_err());
+        // A native measurement that appears twice
+        let mut repeated = written[..8].to_vec();
+        repeated.extend(3u64.to_le_bytes());
+        repeated.extend(&written[16..header]);
+        repeated.extend(&written[24..32]);
+        repeated.extend(&written[header..]);
+        assert!(CompleteMeasurementTable::read_raw(repeated.as_slice()).is_err());
+        let measurement = header + 4 * conjugated.0 as usize;
+        // A link back to itself
+        assert!(corrupt(measurement, &conjugated.0.to_le_bytes()).is_err());
+        // A conjugation by a measurement that is not native
+        let conjugation = measurement + 4 * 4usize.pow(12);
+        assert!(corrupt(conjugation, &3u32.to_le_bytes()).is_err());
+        let mut longer = written.clone();
+        longer.push(0);
+        assert!(CompleteMeasurementTable::read_raw(longer.as_slice()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn shard_indices() {
+        let indices: HashSet<_> = PauliString::all_with_support(PauliString::ALL_QUBITS)
+            .map(CompleteMeasurementTable::shard_index)
+            .collect();
+        // Every PauliString has its own place in the shards
+        assert_eq!(4usize.pow(12), indices.len());
+        let shards = CompleteMeasurementTable::SHARDS;
+        assert!(
+            indices
+                .iter()
+                .all(|(shard, i)| *shard < shards && *i < 4usize.pow(12) / shards)
+        );
+
+        let shards_with_support = |qubits: u32| {
+            CompleteMeasurementTable::shards_with_support((1 << qubits) - 1).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![0], shards_with_support(9));
+        assert_eq!(4, shards_with_support(10).len());
+        assert_eq!(shards, shards_with_support(12).len());
+    }
+
     #[test]
     fn test_gross_table() -> Result<(), String> {
         table_tests(GROSS_MEASUREMENT)
@@ -444,13 +1774,82 @@ mod tests {
     }
 
     fn table_tests(m: CodeMeasurement) -> Result<(), String> {
-        let table: CompleteMeasurementTable = build_complete_table(m)?;
+        let table = build_complete_table(m).map_err(|e| e.to_string())?;
         check_correct_implementation(&table);
         check_native_measurements(&table, m);
+
+        let mut flat = table.clone();
+        flat.flatten_chains();
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS)
+            .skip(1)
+            .step_by(7)
+        {
+            assert_eq!(table.implementation(p), flat.implementation(p));
+        }
+
+        let minimal = MinimalMeasurementTable::from(&table);
+        for p in PauliString::all_with_support(PauliString::DATA_QUBITS)
+            .skip(1)
+            .step_by(7)
+        {
+            assert_eq!(table.min_data(p), minimal.min_data(p));
+        }
+
+        // Operations on the first 8 data qubits only need the first shard
+        let support = (1 << 9) - 1;
+        let shards: Vec<_> = CompleteMeasurementTable::shards_with_support(support).collect();
+        assert_eq!(vec![0], shards);
+        let partial = CompleteMeasurementTable::from_shards(
+            table.native_measurements().clone(),
+            shards.into_iter().map(|i| (i, table.shard(i))),
+        );
+        for p in PauliString::all_with_support(support).skip(1) {
+            assert_eq!(table.implementation(p), partial.implementation(p));
+            if p.pivot_bits() == pauli_string::ID {
+                assert_eq!(table.min_data(p), partial.min_data(p));
+            }
+        }
         Ok(())
     }
 
-    fn build_complete_table(m: CodeMeasurement) -> Result<CompleteMeasurementTable, String> {
+    #[test]
+    fn open_mapped_table() -> io::Result<()> {
+        let (table, entries, header) = conjugated_table();
+        let mut written = vec![];
+        table.write_raw(&mut written)?;
+        let path = std::env::temp_dir().join(format!("bicycle_mmap_{}.raw", std::process::id()));
+        std::fs::write(&path, &written)?;
+
+        let mapped = CompleteMeasurementTable::open_mmap(&path)?;
+        assert_eq!(cfg!(target_endian = "little"), mapped.is_mapped());
+        assert_eq!(table.native_measurements, mapped.native_measurements);
+        assert_eq!(
+            (&table.measurements, &table.conjugated_with, &table.costs),
+            (&mapped.measurements, &mapped.conjugated_with, &mapped.costs)
+        );
+        let conjugated = entries[2].implements();
+        assert_eq!(table.get(conjugated), mapped.clone().get(conjugated));
+        drop(mapped);
+
+        std::fs::write(&path, &written[..written.len() - 1])?;
+        let truncated = CompleteMeasurementTable::open_mmap(&path);
+        // A link back to itself
+        let measurement = header + 4 * conjugated.0 as usize;
+        written[measurement..measurement + 4].copy_from_slice(&conjugated.0.to_le_bytes());
+        std::fs::write(&path, &written)?;
+        // Opening does not read the chains, but verifying and looking up the measurement do
+        let cyclic = CompleteMeasurementTable::open_mmap(&path)?;
+        std::fs::remove_file(&path)?;
+        assert!(truncated.is_err());
+        assert!(cyclic.verify().is_err());
+        let lookup = std::panic::catch_unwind(|| cyclic.implementation(conjugated));
+        assert!(lookup.is_err());
+        Ok(())
+    }
+
+    fn build_complete_table(
+        m: CodeMeasurement,
+    ) -> Result<CompleteMeasurementTable, IncompleteTable> {
         let mut table = MeasurementTableBuilder::new(NativeMeasurement::all(), m);
         table.build();
 
@@ -463,8 +1862,7 @@ mod tests {
 
     fn check_correct_implementation(complete: &CompleteMeasurementTable) {
         // Check that the completed table gives correct implementations for each pauli string
-        for i in 1..4_u32.pow(12) {
-            let p = PauliString(i);
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS).skip(1) {
             let meas_impl = complete.implementation(p);
             let mut q = meas_impl.base_measurement().measures();
 
@@ -473,6 +1871,258 @@ mod tests {
             }
 
             assert_eq!(p, q);
+            // `min_data` relies on the cost to compare the number of rotations
+            let cost = complete.get(p).unwrap().cost() as usize;
+            assert_eq!(1 + 2 * meas_impl.rotations().len(), cost);
+        }
+    }
+
+    #[test]
+    fn identity_automorphism_table() {
+        let full = build_complete_table(GROSS_MEASUREMENT).unwrap();
+        let mut restricted = MeasurementTableBuilder::new(
+            NativeMeasurement::base_measurements().collect(),
+            GROSS_MEASUREMENT,
+        )
+        .flatten_chains(true);
+        restricted.build();
+
+        // Without automorphisms only the pivot and the dual qubit can be measured
+        let inflation = restricted.cost_inflation(&full);
+        assert_eq!(4usize.pow(12) - 1, inflation.paulis);
+        assert_eq!(15, inflation.found);
+        // These are native measurements in both tables
+        assert_eq!(1.0, inflation.ratio());
+
+        let table = restricted.complete_with(&full);
+        check_correct_implementation(&table);
+        for native in NativeMeasurement::base_measurements() {
+            let implementation = table.implementation(GROSS_MEASUREMENT.measures(&native));
+            assert_eq!(
+                native.automorphism,
+                implementation.base_measurement().automorphism()
+            );
+        }
+    }
+
+    #[test]
+    fn build_progress() {
+        let mut table = MeasurementTableBuilder::new(
+            NativeMeasurement::base_measurements().collect(),
+            GROSS_MEASUREMENT,
+        );
+        let mut reports = vec![];
+        table.build_with_progress(|progress| reports.push(progress));
+        assert!(!reports.is_empty());
+        assert!(
+            reports
+                .windows(2)
+                .all(|pair| pair[0].found <= pair[1].found)
+        );
+        let last = reports.last().unwrap();
+        assert_eq!(table.len(), last.found);
+        assert_eq!(4usize.pow(12), last.total);
+    }
+
+    #[test]
+    fn cancel_build() {
+        let cancel = AtomicBool::new(false);
+        let mut table = MeasurementTableBuilder::new(NativeMeasurement::all(), GROSS_MEASUREMENT);
+        let mut reports = 0;
+        let result = table.build_cancellable(&cancel, |_| {
+            reports += 1;
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(Err(BuildCancelled), result);
+        assert_eq!(1, reports);
+        assert!(table.complete().is_err());
+    }
+
+    #[test]
+    fn resume_build() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("bicycle_checkpoint_{}", std::process::id()));
+        let cancel = AtomicBool::new(false);
+        let mut table = MeasurementTableBuilder::new(NativeMeasurement::all(), GROSS_MEASUREMENT)
+            .checkpoint(&path, NonZeroUsize::new(1).unwrap());
+        // Cancel in the third iteration, after the checkpoint of the second one
+        let result = table.build_cancellable(&cancel, |progress| {
+            cancel.store(progress.iteration == 3, Ordering::Relaxed);
+        });
+        assert_eq!(Err(BuildCancelled), result);
+
+        let mut resumed = MeasurementTableBuilder::resume(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            Some(2),
+            resumed.resumed.as_ref().map(|state| state.iteration)
+        );
+        assert!(resumed.len() < table.len());
+        resumed.build();
+        let resumed = resumed.complete().unwrap();
+        check_correct_implementation(&resumed);
+
+        let full = build_complete_table(GROSS_MEASUREMENT).unwrap();
+        assert_eq!(full.costs, resumed.costs);
+        assert_eq!(full.native_measurements, resumed.native_measurements);
+
+        assert!(MeasurementTableBuilder::resume(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn filtered_tables() {
+        // Conjugating with the x shift by one reaches the other x shifts
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 1],
+            ..Default::default()
+        };
+        let mut table = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        table.build();
+        check_correct_implementation(&table.complete().unwrap());
+
+        // Too few automorphisms to reach all logical qubits
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let mut table = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        table.build();
+        let err = table.complete().unwrap_err();
+        assert_eq!(65536, err.reached);
+        assert_eq!(4usize.pow(12) - 65536, err.missing.len());
+        assert!(err.to_string().contains("only 65536 of 16777216"), "{err}");
+    }
+
+    #[test]
+    fn partial_tables() {
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let mut table = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        table.build();
+        let missing: HashSet<_> = table.missing().collect();
+        let mut partial = table.partial();
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS).skip(1) {
+            assert_eq!(!missing.contains(&p), partial.covers(p), "{p}");
+            if partial.covers(p) {
+                assert_eq!(p, partial.implementation(p).measures());
+            }
+        }
+        let data = || PauliString::all_with_support(PauliString::ALL_QUBITS & !1).skip(1);
+        let covered = data().find(|p| partial.covers_data(*p)).unwrap();
+        assert!(data().any(|p| !partial.covers_data(p)));
+
+        partial.flatten_chains();
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS).skip(1) {
+            assert_eq!(!missing.contains(&p), partial.covers(p), "{p}");
+        }
+        assert_eq!(covered, partial.min_data(covered).measures().zero_pivot());
+    }
+
+    #[test]
+    fn minimal_table() -> io::Result<()> {
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let mut builder = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        builder.build();
+        let table = builder.partial();
+        let minimal = MinimalMeasurementTable::from(&table);
+
+        let mut written = vec![];
+        minimal.write(&mut written)?;
+        let read = MinimalMeasurementTable::read(written.as_slice())?;
+        assert_eq!(minimal, read);
+        assert!(MinimalMeasurementTable::read(&written[..written.len() - 1]).is_err());
+        assert!(MinimalMeasurementTable::read(&b"bicyraw1"[..]).is_err());
+        let corrupt = |corrupt: &dyn Fn(&mut MinimalMeasurementTable)| {
+            let mut table = minimal.clone();
+            corrupt(&mut table);
+            let mut written = vec![];
+            table.write(&mut written).unwrap();
+            MinimalMeasurementTable::read(written.as_slice())
+        };
+        assert!(corrupt(&|table| table.pivots[1] = 3).is_err());
+        assert!(corrupt(&|table| table.chains.offsets[1] = u32::MAX).is_err());
+        assert!(corrupt(&|table| table.chains.offsets[0] = 1).is_err());
+        assert!(corrupt(&|table| *table.chains.offsets.last_mut().unwrap() += 1).is_err());
+        assert!(corrupt(&|table| table.chains.chains[0] = u16::MAX).is_err());
+
+        let mut covered = 0;
+        for p in PauliString::all_with_support(PauliString::DATA_QUBITS).skip(1) {
+            assert_eq!(table.covers_data(p), read.covers_data(p), "{p}");
+            if read.covers_data(p) {
+                assert_eq!(table.min_data(p), read.min_data(p));
+                covered += 1;
+            }
+        }
+        assert!(0 < covered && covered < 4usize.pow(11) - 1);
+        Ok(())
+    }
+
+    /// Native measurements with more automorphism generators cost more
+    struct GeneratorCost;
+
+    impl CostModel for GeneratorCost {
+        fn cost(&self, native: &NativeMeasurement) -> u32 {
+            4 + 2 * native.automorphism.nr_generators() as u32
+        }
+    }
+
+    #[test]
+    fn weighted_table() {
+        let uniform = build_complete_table(GROSS_MEASUREMENT).unwrap();
+        let mut builder = MeasurementTableBuilder::with_cost_model(
+            NativeMeasurement::all(),
+            GROSS_MEASUREMENT,
+            &GeneratorCost,
+        );
+        builder.build();
+        let weighted = builder.complete().unwrap();
+
+        let model_cost = |meas_impl: &MeasurementImpl| {
+            let native = |native: &NativeMeasurementImpl| {
+                GeneratorCost.cost(&NativeMeasurement {
+                    logical: native.logical(),
+                    automorphism: native.automorphism(),
+                })
+            };
+            let rotations: u32 = meas_impl.rotations().iter().map(native).sum();
+            native(meas_impl.base_measurement()) + 2 * rotations
+        };
+        let (mut total, mut reference) = (0, 0);
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS)
+            .skip(1)
+            .step_by(7)
+        {
+            let meas_impl = weighted.implementation(p);
+            let mut q = meas_impl.base_measurement().measures();
+            for rot in meas_impl.rotations() {
+                q = q.conjugate_with(rot.measures().zero_pivot());
+            }
+            assert_eq!(p, q);
+
+            let cost = model_cost(&meas_impl);
+            assert_eq!(cost, weighted.get(p).unwrap().cost());
+            let uniform_cost = model_cost(&uniform.implementation(p));
+            assert!(cost <= uniform_cost, "{p}: {cost} > {uniform_cost}");
+            total += cost;
+            reference += uniform_cost;
+        }
+        assert!(total < reference);
+
+        // The pivot is chosen by the cost of the model rather than by the number of rotations
+        for p in PauliString::all_with_support(PauliString::DATA_QUBITS)
+            .skip(1)
+            .step_by(101)
+        {
+            let cheapest = [pauli_string::X1, pauli_string::Z1, pauli_string::Y1]
+                .map(|pivot| weighted.get(p * pivot).unwrap().cost())
+                .into_iter()
+                .min();
+            assert_eq!(cheapest, Some(model_cost(&weighted.min_data(p))));
         }
     }
 
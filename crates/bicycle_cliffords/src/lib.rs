@@ -14,16 +14,23 @@
 
 pub mod measurement;
 pub use measurement::{
-    CodeMeasurement, MeasurementChoices, GROSS_MEASUREMENT, TWOGROSS_MEASUREMENT,
+    CodeMeasurement, GROSS_MEASUREMENT, InvalidCodeMeasurement, MeasurementChoices,
+    TWOGROSS_MEASUREMENT,
 };
 
+pub mod bivariate;
+pub mod check;
 pub mod native_measurement;
 mod pauli_string;
 
-pub use pauli_string::PauliString;
+pub use pauli_string::{InvalidPauliString, PauliString};
 
 pub mod decomposition;
-pub use decomposition::{CompleteMeasurementTable, MeasurementTableBuilder};
+pub use decomposition::{
+    BuildCancelled, BuildProgress, CompleteMeasurementTable, CostInflation, CostModel,
+    IncompleteTable, MeasurementTable, MeasurementTableBuilder, MinimalMeasurementTable,
+    UniformCost,
+};
 
 #[cfg(test)]
 mod tests {
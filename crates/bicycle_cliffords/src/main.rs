@@ -15,22 +15,74 @@
 use std::{
     error::Error,
     io::{BufWriter, Write},
+    path::PathBuf,
 };
 
 use log::{debug, info};
 
 use bicycle_cliffords::{
-    MeasurementChoices, MeasurementTableBuilder, PauliString, native_measurement::NativeMeasurement,
+    CodeMeasurement, MeasurementChoices, MeasurementTableBuilder, PauliString, check,
+    native_measurement::NativeFilter,
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOC: bicycle_common::alloc::CountingAllocator = bicycle_common::alloc::CountingAllocator;
 
 #[derive(Parser, Debug)]
 struct Cli {
     code: MeasurementChoices,
+    /// Read the automorphism matrices mx and my from a JSON or TOML file instead of those of the code
+    #[arg(long, value_name = "FILE")]
+    code_file: Option<PathBuf>,
     /// Do not optimize over choice of pivot basis. Result will be 12-qubit strings.
     #[arg(long)]
     no_optimize: bool,
+    /// Restrict the native measurements that the measurements are synthesized from
+    #[command(flatten)]
+    natives: NativeFilter,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+impl Cli {
+    /// The automorphism action of the code, or of the code file
+    fn measurement(&self) -> Result<CodeMeasurement, Box<dyn Error>> {
+        match &self.code_file {
+            Some(path) => CodeMeasurement::from_file(path),
+            None => Ok(self.code.measurement()),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Verify the invariants of the automorphisms of the code, or of a user-supplied pair of matrices.
+    /// Matrices are given by their 36 entries in row-major order, e.g., "0 1 0 1 0 0; 0 1 0 0 0 1; ...".
+    Check {
+        /// The matrix of the x automorphism generator
+        #[arg(long, requires = "my")]
+        mx: Option<String>,
+        /// The matrix of the y automorphism generator
+        #[arg(long, requires = "mx")]
+        my: Option<String>,
+    },
+}
+
+/// Print the failed invariants of a code and exit with an error if there are any
+fn check_code(code: &CodeMeasurement) {
+    let failures = check::check(code);
+    if failures.is_empty() {
+        println!("All checks passed");
+        return;
+    }
+    for failure in &failures {
+        println!("{failure}");
+    }
+    eprintln!("{} checks failed", failures.len());
+    std::process::exit(1);
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -38,9 +90,20 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
 
-    let mut table = MeasurementTableBuilder::new(NativeMeasurement::all(), cli.code.measurement());
+    if let Some(Commands::Check { mx, my }) = &cli.command {
+        let mut code = cli.measurement()?;
+        if let (Some(mx), Some(my)) = (mx, my) {
+            code.mx = check::parse_matrix(mx)?;
+            code.my = check::parse_matrix(my)?;
+        }
+        check_code(&code);
+        return Ok(());
+    }
+
+    let mut table = MeasurementTableBuilder::with_filter(&cli.natives, cli.measurement()?);
     table.build();
     let complete = table.complete()?;
+    bicycle_common::alloc::report_memory("table build");
     debug!("Done with finding costs");
 
     println!("Rotation,Base Meas,Rots len");
@@ -49,11 +112,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut buf_out = BufWriter::new(stdout);
     if !cli.no_optimize {
         info!("Optimizing over pivot measurement basis");
-        for i in 1..4_u32.pow(11) {
+        for p in PauliString::all_with_support(PauliString::DATA_QUBITS).skip(1) {
             // Find cheapest implementation for rotation
-            let x_bits = i & ((1 << 11) - 1);
-            let z_bits = i >> 11;
-            let p = PauliString((z_bits << 13) | (x_bits << 1));
             let meas_impl = complete.min_data(p);
             writeln!(
                 buf_out,
@@ -65,8 +125,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     } else {
         info!("Not optimizing over pivot qubit");
-        for i in 1..4_u32.pow(12) {
-            let p = PauliString(i);
+        for p in PauliString::all_with_support(PauliString::ALL_QUBITS).skip(1) {
             let meas_impl = complete.implementation(p);
             writeln!(
                 buf_out,
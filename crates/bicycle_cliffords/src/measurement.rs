@@ -12,12 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::{Debug, Display};
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+    path::Path,
+};
 
 use bicycle_common::{AutomorphismData, Pauli};
 use nalgebra::{SMatrix, Vector6, matrix, stack};
+use serde::{Deserialize, Serialize};
 
-use crate::{PauliString, native_measurement::NativeMeasurement};
+use crate::{
+    PauliString,
+    check::{self, CheckFailure},
+    native_measurement::NativeMeasurement,
+};
 use clap::ValueEnum;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -26,7 +35,87 @@ pub struct CodeMeasurement {
     pub my: SMatrix<u32, 6, 6>,
 }
 
+/// Matrices that are not the automorphism action of a code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidCodeMeasurement {
+    /// An entry of a matrix is not 0 or 1
+    Entry { matrix: &'static str, entry: u32 },
+    /// The matrices fail the invariants of [`check::check_actions`]
+    Check(Vec<CheckFailure>),
+}
+
+impl Display for InvalidCodeMeasurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Entry { matrix, entry } => {
+                write!(f, "Entries of {matrix} should be 0 or 1, found {entry}")
+            }
+            Self::Check(failures) => {
+                let failures: Vec<_> = failures.iter().map(|failure| failure.to_string()).collect();
+                write!(f, "Invalid automorphism action: {}", failures.join("; "))
+            }
+        }
+    }
+}
+
+impl Error for InvalidCodeMeasurement {}
+
+/// The matrices of a code file, each given by its rows
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CodeFile {
+    mx: [[u32; 6]; 6],
+    my: [[u32; 6]; 6],
+}
+
 impl CodeMeasurement {
+    /// The action of the automorphism generators `mx` and `my` of a code,
+    /// which must have order 6, commute, and keep the primal and dual logical qubits apart
+    pub fn new(
+        mx: SMatrix<u32, 6, 6>,
+        my: SMatrix<u32, 6, 6>,
+    ) -> Result<Self, InvalidCodeMeasurement> {
+        for (matrix, m) in [("mx", &mx), ("my", &my)] {
+            if let Some(entry) = m.iter().find(|entry| **entry > 1) {
+                return Err(InvalidCodeMeasurement::Entry {
+                    matrix,
+                    entry: *entry,
+                });
+            }
+        }
+        let code = Self { mx, my };
+        let failures = check::check_actions(&code);
+        if failures.is_empty() {
+            Ok(code)
+        } else {
+            Err(InvalidCodeMeasurement::Check(failures))
+        }
+    }
+
+    fn from_code_file(file: CodeFile) -> Result<Self, Box<dyn Error>> {
+        let matrix = |rows: [[u32; 6]; 6]| SMatrix::from_fn(|i, j| rows[i][j]);
+        Ok(Self::new(matrix(file.mx), matrix(file.my))?)
+    }
+
+    /// Read the matrices from JSON, e.g., `{"mx": [[0, 1, 0, 1, 0, 0], ...], "my": [...]}` with the rows of each matrix
+    pub fn from_json(s: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_code_file(serde_json::from_str(s)?)
+    }
+
+    /// Read the matrices from TOML, e.g., `mx = [[0, 1, 0, 1, 0, 0], ...]` with the rows of each matrix
+    pub fn from_toml(s: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_code_file(toml::from_str(s)?)
+    }
+
+    /// Read the matrices from a TOML file if it ends in `.toml` and from a JSON file otherwise
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => Self::from_toml(&contents),
+            _ => Self::from_json(&contents),
+        }
+    }
+
     /// The PauliString a NativeMeasurement measures
     #[allow(clippy::toplevel_ref_arg)]
     pub fn measures(&self, native_measurement: &NativeMeasurement) -> PauliString {
@@ -106,7 +195,8 @@ pub const TWOGROSS_MEASUREMENT: CodeMeasurement = CodeMeasurement {
     ],
 };
 
-#[derive(ValueEnum, Debug, Clone, Copy)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum MeasurementChoices {
     Gross,
     TwoGross,
@@ -119,6 +209,22 @@ impl MeasurementChoices {
             Self::TwoGross => TWOGROSS_MEASUREMENT,
         }
     }
+
+    /// Number of logical qubits per block that hold data.
+    /// Both codes encode 12 logical qubits, one of which is the pivot.
+    pub fn qubits_per_block(&self) -> usize {
+        match self {
+            Self::Gross | Self::TwoGross => PauliString::NR_DATA_QUBITS,
+        }
+    }
+
+    /// Number of physical qubits per block, i.e., the data and check qubits of the [[144,12,12]] or [[288,12,18]] code
+    pub fn physical_qubits_per_block(&self) -> usize {
+        match self {
+            Self::Gross => 288,
+            Self::TwoGross => 576,
+        }
+    }
 }
 
 impl Display for MeasurementChoices {
@@ -161,26 +267,12 @@ mod tests {
     }
 
     fn test_duality(code: CodeMeasurement) {
-        for pauli in [X, Y, Z] {
-            let logicals = [
-                TwoBases::new(pauli, I).unwrap(),
-                TwoBases::new(I, pauli).unwrap(),
-            ];
-
-            for (support_i, logical) in logicals.into_iter().enumerate() {
-                let expected_support = (support_i == 0, support_i == 1);
-                for x in 0..=5 {
-                    for y in 0..=5 {
-                        let automorphism = AutomorphismData::new(x, y);
-                        let native_meas = NativeMeasurement {
-                            logical,
-                            automorphism,
-                        };
-                        let paulis: [Pauli; 12] = code.measures(&native_meas).into();
-
-                        assert_eq!(expected_support, paulis_support(&paulis));
-                    }
-                }
+        for expected_support in [(true, false), (false, true)] {
+            let (primal, dual) = expected_support;
+            for native_meas in NativeMeasurement::with_support(primal, dual, None) {
+                let paulis: [Pauli; 12] = code.measures(&native_meas).into();
+
+                assert_eq!(expected_support, paulis_support(&paulis));
             }
         }
     }
@@ -301,6 +393,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn code_files() {
+        let rows = |m: &SMatrix<u32, 6, 6>| -> Vec<Vec<u32>> {
+            m.row_iter()
+                .map(|row| row.iter().copied().collect())
+                .collect()
+        };
+        let json = format!(
+            r#"{{"mx": {:?}, "my": {:?}}}"#,
+            rows(&TWOGROSS_MEASUREMENT.mx),
+            rows(&TWOGROSS_MEASUREMENT.my)
+        );
+        assert_eq!(
+            TWOGROSS_MEASUREMENT,
+            CodeMeasurement::from_json(&json).unwrap()
+        );
+        let toml = format!(
+            "mx = {:?}\nmy = {:?}\n",
+            rows(&GROSS_MEASUREMENT.mx),
+            rows(&GROSS_MEASUREMENT.my)
+        );
+        assert_eq!(
+            GROSS_MEASUREMENT,
+            CodeMeasurement::from_toml(&toml).unwrap()
+        );
+
+        // Matrices of different codes do not commute
+        let mixed = format!(
+            r#"{{"mx": {:?}, "my": {:?}}}"#,
+            rows(&GROSS_MEASUREMENT.mx),
+            rows(&TWOGROSS_MEASUREMENT.my)
+        );
+        let err = CodeMeasurement::from_json(&mixed).unwrap_err();
+        assert!(err.to_string().contains("do not commute"), "{err}");
+        let Err(InvalidCodeMeasurement::Check(failures)) =
+            CodeMeasurement::new(SMatrix::identity(), SMatrix::identity())
+        else {
+            panic!("The identity should fail the checks");
+        };
+        assert_eq!(
+            CheckFailure::Order {
+                generator: "mx",
+                order: Some(1)
+            },
+            failures[0]
+        );
+        let mut mx = GROSS_MEASUREMENT.mx;
+        mx[(0, 0)] = 2;
+        assert_eq!(
+            Err(InvalidCodeMeasurement::Entry {
+                matrix: "mx",
+                entry: 2
+            }),
+            CodeMeasurement::new(mx, GROSS_MEASUREMENT.my)
+        );
+        assert!(CodeMeasurement::from_json(r#"{"mx": [[0]], "my": []}"#).is_err());
+    }
+
     // Check that the order of the automorphism generators is 6
     #[test]
     fn automorphism_order() {
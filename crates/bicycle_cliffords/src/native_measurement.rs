@@ -19,7 +19,7 @@ use rand::distr::{Distribution, StandardUniform};
 use serde::{Deserialize, Serialize};
 
 /// A measurement that can be performed on the code by conjugating one base measurement with automorphisms.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NativeMeasurement {
     pub logical: TwoBases,
     pub automorphism: AutomorphismData,
@@ -38,21 +38,33 @@ impl NativeMeasurement {
 
     /// Construct all native measurements
     pub fn all() -> Vec<NativeMeasurement> {
-        let mut res = vec![];
-        for x in 0..=5 {
-            for y in 0..=5 {
-                let aut = AutomorphismData::new(x, y);
-
-                for base in NativeMeasurement::base_measurements() {
-                    res.push(NativeMeasurement {
-                        automorphism: aut,
-                        ..base
-                    });
-                }
-            }
-        }
+        AutomorphismData::all()
+            .flat_map(|automorphism| {
+                NativeMeasurement::base_measurements().map(move |base| NativeMeasurement {
+                    automorphism,
+                    ..base
+                })
+            })
+            .collect()
+    }
 
-        res
+    /// Construct all native measurements with the given support on the primal and dual logical qubit.
+    /// If `pauli` is given, the logical measurement must be that Pauli on each qubit in its support.
+    pub fn with_support(
+        primal: bool,
+        dual: bool,
+        pauli: Option<Pauli>,
+    ) -> impl Iterator<Item = NativeMeasurement> {
+        let matches = move |p: Pauli, support: bool| {
+            if support {
+                p != Pauli::I && pauli.is_none_or(|pauli| p == pauli)
+            } else {
+                p == Pauli::I
+            }
+        };
+        NativeMeasurement::all().into_iter().filter(move |meas| {
+            matches(meas.logical.get_basis_1(), primal) && matches(meas.logical.get_basis_7(), dual)
+        })
     }
 
     fn all_bases() -> Vec<TwoBases> {
@@ -80,6 +92,53 @@ impl NativeMeasurement {
     }
 }
 
+/// A subset of the native measurements, e.g., to model hardware that only supports some of them.
+/// An empty filter allows all native measurements.
+#[derive(Debug, Clone, Default, PartialEq, Eq, clap::Args)]
+pub struct NativeFilter {
+    /// Exclude native measurements with one of these Paulis on the primal or dual logical qubit
+    #[arg(long, value_delimiter = ',', value_parser = parse_pauli)]
+    pub exclude_basis: Vec<Pauli>,
+    /// Only allow automorphisms with one of these x shifts
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(0..6))]
+    pub automorphism_x: Vec<u8>,
+    /// Only allow automorphisms with one of these y shifts
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(0..6))]
+    pub automorphism_y: Vec<u8>,
+}
+
+impl NativeFilter {
+    /// Check if the filter allows all native measurements
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check if the filter allows a native measurement
+    pub fn allows(&self, meas: &NativeMeasurement) -> bool {
+        let logical = [meas.logical.get_basis_1(), meas.logical.get_basis_7()];
+        let shift_allowed = |allowed: &[u8], shift| allowed.is_empty() || allowed.contains(&shift);
+        !logical.iter().any(|p| self.exclude_basis.contains(p))
+            && shift_allowed(&self.automorphism_x, meas.automorphism.get_x())
+            && shift_allowed(&self.automorphism_y, meas.automorphism.get_y())
+    }
+
+    /// All native measurements that the filter allows
+    pub fn native_measurements(&self) -> Vec<NativeMeasurement> {
+        NativeMeasurement::all()
+            .into_iter()
+            .filter(|meas| self.allows(meas))
+            .collect()
+    }
+}
+
+fn parse_pauli(s: &str) -> Result<Pauli, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Pauli::try_from(&c),
+        _ => Err(format!("Expected a single Pauli, got {s}")),
+    }
+}
+
 impl Display for NativeMeasurement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -116,4 +175,48 @@ mod tests {
         let base: Vec<_> = NativeMeasurement::base_measurements().collect();
         assert_eq!(15, base.len())
     }
+
+    #[test]
+    fn support_patterns() {
+        let count =
+            |primal, dual, pauli| NativeMeasurement::with_support(primal, dual, pauli).count();
+        assert_eq!(0, count(false, false, None));
+        assert_eq!(3 * 36, count(true, false, None));
+        assert_eq!(3 * 36, count(false, true, None));
+        assert_eq!(9 * 36, count(true, true, None));
+        assert_eq!(36, count(true, false, Some(Pauli::X)));
+        assert_eq!(36, count(true, true, Some(Pauli::Y)));
+        assert_eq!(0, count(true, true, Some(Pauli::I)));
+        assert_eq!(
+            NativeMeasurement::all().len(),
+            count(true, false, None) + count(false, true, None) + count(true, true, None)
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            NativeMeasurement::all(),
+            NativeFilter::default().native_measurements()
+        );
+
+        let no_y = NativeFilter {
+            exclude_basis: vec![Pauli::Y],
+            ..Default::default()
+        };
+        // The bases in {I, X, Z}^2 other than the identity
+        assert_eq!(8 * 36, no_y.native_measurements().len());
+
+        let shifts = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let natives = shifts.native_measurements();
+        assert_eq!(2 * 6 * 15, natives.len());
+        assert!(
+            natives
+                .iter()
+                .all(|meas| [0, 3].contains(&meas.automorphism.get_x()))
+        );
+    }
 }
@@ -14,6 +14,7 @@
 
 use std::{
     array::TryFromSliceError,
+    error::Error,
     fmt,
     ops::{Index, Mul},
 };
@@ -26,10 +27,82 @@ use serde::{Deserialize, Serialize};
 /// Consider using bitvec's bitarray to store Pauli rotations instead of reimplementing the bit twiddling.
 /// We store the qubits in little-endian order, i.e.,
 /// the bits 0 and 12 store qubit 0's X and Z operators, respectively.
+///
+/// Use [`PauliString::new`] to construct one from its bits, which rejects values of 4^12 and above.
+/// Deserializing checks the bits in the same way.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub struct PauliString(pub u32);
+#[serde(try_from = "u32")]
+pub struct PauliString(pub(crate) u32);
+
+/// The bits do not represent a Pauli string on 12 qubits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPauliString(pub u32);
+
+impl fmt::Display for InvalidPauliString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Bits {:#x} are out of range for a Pauli string on 12 qubits, should be below 4^12",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidPauliString {}
+
+impl TryFrom<u32> for PauliString {
+    type Error = InvalidPauliString;
+
+    fn try_from(bits: u32) -> Result<Self, Self::Error> {
+        Self::new(bits)
+    }
+}
 
 impl PauliString {
+    /// Support mask of all 12 qubits
+    pub const ALL_QUBITS: u32 = QUBIT_MASK;
+    /// Support mask of the 11 data qubits, i.e., all qubits except the pivot
+    pub const DATA_QUBITS: u32 = QUBIT_MASK & !1;
+    /// Number of data qubits
+    pub const NR_DATA_QUBITS: usize = 11;
+
+    /// Iterate over all Pauli strings that are supported within `mask`, starting with the identity.
+    /// Bit i of `mask` selects qubit i. The strings are generated in increasing order.
+    pub fn all_with_support(mask: u32) -> impl Iterator<Item = PauliString> {
+        assert!(
+            mask <= QUBIT_MASK,
+            "The support mask {mask:#b} should select at most 12 qubits"
+        );
+        let bits = mask | (mask << 12);
+        let mut next = Some(0_u32);
+        std::iter::from_fn(move || {
+            let current = next?;
+            // Standard trick to step to the next subset of the bits
+            next = (current != bits).then(|| current.wrapping_sub(bits) & bits);
+            Some(PauliString(current))
+        })
+    }
+
+    /// Construct a Pauli string from its bits, where bits i and i + 12 store the X and Z operators of qubit i
+    pub fn new(bits: u32) -> Result<Self, InvalidPauliString> {
+        if bits < 1 << 24 {
+            Ok(PauliString(bits))
+        } else {
+            Err(InvalidPauliString(bits))
+        }
+    }
+
+    /// The bits of this Pauli string, see [`PauliString::new`]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// The bits of this Pauli string, which used to be the public field
+    #[deprecated(note = "Use `PauliString::bits`")]
+    pub fn as_u32(self) -> u32 {
+        self.bits()
+    }
+
     pub fn rotation(bits: u32) -> PauliString {
         let z_bits = bits >> 11;
         let x_bits = bits & ((1 << 11) - 1);
@@ -79,6 +152,39 @@ impl PauliString {
         self.has_pivot_support() && self.has_logical_support()
     }
 
+    /// Bit mask of the qubits with a non-identity Pauli
+    fn support_mask(self) -> u32 {
+        (self.0 | (self.0 >> 12)) & QUBIT_MASK
+    }
+
+    /// Number of qubits with a non-identity Pauli
+    pub fn weight(self) -> u32 {
+        self.support_mask().count_ones()
+    }
+
+    /// Number of qubits with an X or Y Pauli
+    pub fn x_weight(self) -> u32 {
+        (self.0 & QUBIT_MASK).count_ones()
+    }
+
+    /// Number of qubits with a Z or Y Pauli
+    pub fn z_weight(self) -> u32 {
+        ((self.0 >> 12) & QUBIT_MASK).count_ones()
+    }
+
+    /// The qubits with a non-identity Pauli, in increasing order
+    pub fn support(self) -> impl Iterator<Item = usize> {
+        let mut mask = self.support_mask();
+        std::iter::from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let qubit = mask.trailing_zeros();
+            mask &= mask - 1;
+            Some(qubit as usize)
+        })
+    }
+
     pub fn commutes_with(self, rhs: PauliString) -> bool {
         let self_z = self.0 >> 12;
         let self_x = self.0 ^ (self_z << 12);
@@ -118,6 +224,9 @@ impl PauliString {
     }
 }
 
+/// Bit mask of the X (or Z) bits of all 12 qubits
+const QUBIT_MASK: u32 = (1 << 12) - 1;
+
 pub const ID: PauliString = PauliString(0);
 pub const X1: PauliString = PauliString(1);
 pub const Z1: PauliString = PauliString(1 << 12);
@@ -135,11 +244,7 @@ impl Index<usize> for PauliString {
     type Output = bool;
 
     fn index(&self, i: usize) -> &Self::Output {
-        if self.get_bit(i) {
-            &true
-        } else {
-            &false
-        }
+        if self.get_bit(i) { &true } else { &false }
     }
 }
 
@@ -285,6 +390,56 @@ mod tests {
         assert_eq!(X2 * Z2, PauliString::rotation(1 | 1 << 11))
     }
 
+    #[test]
+    fn weights_and_support() {
+        let p: PauliString = (&[X, I, Y, Z, I, I, I, I, I, I, I, Z]).into();
+        assert_eq!(4, p.weight());
+        assert_eq!(2, p.x_weight());
+        assert_eq!(3, p.z_weight());
+        assert_eq!(vec![0, 2, 3, 11], p.support().collect::<Vec<_>>());
+
+        assert_eq!(0, ID.weight());
+        assert_eq!(None, ID.support().next());
+        assert_eq!(1, Y1.weight());
+    }
+
+    #[test]
+    fn all_with_support_mask() {
+        let all: Vec<_> = PauliString::all_with_support(0b101).collect();
+        assert_eq!(16, all.len());
+        assert_eq!(ID, all[0]);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+        assert!(all.iter().all(|p| p.support().all(|q| q == 0 || q == 2)));
+
+        let data = PauliString::all_with_support(PauliString::DATA_QUBITS);
+        assert!(
+            data.skip(1)
+                .all(|p| !p.has_pivot_support() && p.has_logical_support())
+        );
+        assert_eq!(
+            4_usize.pow(12),
+            PauliString::all_with_support(PauliString::ALL_QUBITS).count()
+        );
+    }
+
+    #[test]
+    fn checked_construction() {
+        assert_eq!(Ok(Y1), PauliString::new(Y1.bits()));
+        assert!(PauliString::new(4_u32.pow(12) - 1).is_ok());
+        assert_eq!(
+            Err(InvalidPauliString(4_u32.pow(12))),
+            PauliString::new(4_u32.pow(12))
+        );
+
+        let deserialize = |bits: u32| serde_json::from_str::<PauliString>(&bits.to_string());
+        assert_eq!(Y1, deserialize(Y1.bits()).unwrap());
+        assert_eq!(
+            Y1,
+            bitcode::deserialize(&bitcode::serialize(&Y1).unwrap()).unwrap()
+        );
+        assert!(deserialize(4_u32.pow(12)).is_err());
+    }
+
     #[test]
     fn check_commutes() {
         assert!(X1.commutes_with(X1));
@@ -0,0 +1,135 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Allocation tracking for reporting peak memory usage.
+//!
+//! Binaries opt in by installing [`CountingAllocator`] as the global allocator,
+//! which they do when built with their `memory-profile` feature.
+//! Without it installed, all counters stay at zero.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Wraps the system allocator and counts the bytes that are currently allocated
+/// as well as the peak since the last reset.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record_alloc(size: usize) {
+        let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(size: usize) {
+        CURRENT.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        Self::record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            Self::record_dealloc(layout.size());
+            Self::record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Bytes currently allocated through the [`CountingAllocator`]
+pub fn current_bytes() -> usize {
+    CURRENT.load(Ordering::Relaxed)
+}
+
+/// Peak number of bytes allocated since the last [`take_peak`]
+pub fn peak_bytes() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Return the peak since the last call and reset it to the current usage.
+/// Call this at the end of each stage to obtain the peak memory of that stage.
+pub fn take_peak() -> usize {
+    PEAK.swap(current_bytes(), Ordering::Relaxed)
+}
+
+/// Format a number of bytes in MiB for reporting
+pub fn format_mib(bytes: usize) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Whether the [`CountingAllocator`] is installed, i.e., whether it has counted any allocation
+pub fn is_counting() -> bool {
+    peak_bytes() > 0
+}
+
+/// Log the peak memory of the stage that just finished and return it, see [`take_peak`],
+/// or `None` if the [`CountingAllocator`] is not installed
+pub fn report_memory(stage: &str) -> Option<usize> {
+    if !is_counting() {
+        return None;
+    }
+    let peak = take_peak();
+    log::info!("Peak memory during {stage}: {}", format_mib(peak));
+    Some(peak)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_direct_allocations() {
+        let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+        let before = current_bytes();
+        unsafe {
+            let ptr = CountingAllocator.alloc(layout);
+            assert!(!ptr.is_null());
+            assert!(current_bytes() >= before + (1 << 20));
+            assert!(peak_bytes() >= before + (1 << 20));
+            CountingAllocator.dealloc(ptr, layout);
+        }
+        assert!(take_peak() >= 1 << 20);
+    }
+
+    #[test]
+    fn format_in_mib() {
+        assert_eq!("1.5 MiB", format_mib(3 << 19));
+    }
+}
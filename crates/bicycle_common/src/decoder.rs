@@ -0,0 +1,516 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of syndromes of binary parity checks by belief propagation and ordered statistics (BP+OSD).
+//!
+//! Belief propagation is run with the normalized min-sum rule on the Tanner graph of the checks.
+//! If its hard decision does not reproduce the syndrome, the columns are ordered by their posterior reliability
+//! and the syndrome is solved on the most likely information set (OSD-0).
+//! With an OSD order w > 0, all 2^w assignments of the w most likely columns outside the information set are tried as well,
+//! keeping the solution of least weight under the prior (OSD-E).
+//! See Roffe et al., [arXiv:2005.07016](https://arxiv.org/abs/2005.07016).
+//!
+//! [`sample_logical_error_rate`] estimates the logical error rate of one type of errors of a CSS code
+//! under independent bit flips, by Monte Carlo sampling.
+
+use rand::Rng;
+
+/// A binary matrix that stores the columns of the ones in each row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseBinaryMatrix {
+    cols: usize,
+    row_ones: Vec<Vec<usize>>,
+}
+
+impl SparseBinaryMatrix {
+    /// A matrix with `cols` columns whose rows have ones in the given columns
+    pub fn new(cols: usize, rows: Vec<Vec<usize>>) -> Self {
+        let row_ones = rows
+            .into_iter()
+            .map(|mut row| {
+                row.sort_unstable();
+                row.dedup();
+                if let Some(j) = row.last() {
+                    assert!(*j < cols, "Column {j} is outside the {cols} columns");
+                }
+                row
+            })
+            .collect();
+        Self { cols, row_ones }
+    }
+
+    /// A matrix from its rows of zeros and ones
+    pub fn from_dense(cols: usize, rows: &[Vec<u8>]) -> Self {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                assert_eq!(cols, row.len(), "Each row should have {cols} entries");
+                (0..cols).filter(|j| row[*j] & 1 == 1).collect()
+            })
+            .collect();
+        Self::new(cols, rows)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.row_ones.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The columns of the ones in row `i`
+    pub fn row(&self, i: usize) -> &[usize] {
+        &self.row_ones[i]
+    }
+
+    /// The syndrome of `error`, i.e., the product of this matrix and `error` over GF(2)
+    pub fn syndrome(&self, error: &[u8]) -> Vec<u8> {
+        assert_eq!(self.cols, error.len());
+        self.row_ones
+            .iter()
+            .map(|row| row.iter().fold(0, |parity, j| parity ^ (error[*j] & 1)))
+            .collect()
+    }
+}
+
+/// A row of `cols` bits packed into words, with ones in the given columns
+fn packed(cols: usize, ones: impl IntoIterator<Item = usize>) -> Vec<u64> {
+    let mut row = vec![0; cols.div_ceil(64)];
+    for j in ones {
+        row[j / 64] ^= 1 << (j % 64);
+    }
+    row
+}
+
+fn bit(row: &[u64], j: usize) -> bool {
+    row[j / 64] >> (j % 64) & 1 == 1
+}
+
+fn xor(target: &mut [u64], source: &[u64]) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+/// The row space of a binary matrix, for testing whether a vector is a combination of its rows
+#[derive(Debug, Clone)]
+pub struct RowSpace {
+    /// Each row has zeros in the pivots of the rows before it
+    basis: Vec<Vec<u64>>,
+    /// The leading column of each row of the basis
+    pivots: Vec<usize>,
+}
+
+impl RowSpace {
+    pub fn new(matrix: &SparseBinaryMatrix) -> Self {
+        let mut space = Self {
+            basis: vec![],
+            pivots: vec![],
+        };
+        for i in 0..matrix.rows() {
+            let mut row = packed(matrix.cols(), matrix.row(i).iter().copied());
+            space.reduce(&mut row);
+            if let Some(pivot) = (0..matrix.cols()).find(|j| bit(&row, *j)) {
+                space.basis.push(row);
+                space.pivots.push(pivot);
+            }
+        }
+        space
+    }
+
+    /// Clear the pivots of `row` by adding rows of the basis
+    fn reduce(&self, row: &mut [u64]) {
+        for (b, pivot) in self.basis.iter().zip(&self.pivots) {
+            if bit(row, *pivot) {
+                xor(row, b);
+            }
+        }
+    }
+
+    /// Dimension of the row space
+    pub fn rank(&self) -> usize {
+        self.pivots.len()
+    }
+
+    /// Whether `vector` is a sum of rows of the matrix
+    pub fn contains(&self, vector: &[u8]) -> bool {
+        let mut row = packed(
+            vector.len(),
+            (0..vector.len()).filter(|j| vector[*j] & 1 == 1),
+        );
+        // A nonzero sum of rows of the basis has a one in the pivot of its first row
+        self.reduce(&mut row);
+        row.iter().all(|word| *word == 0)
+    }
+}
+
+/// Decodes syndromes of a parity-check matrix by BP+OSD, see the [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct BpOsdDecoder<'a> {
+    checks: &'a SparseBinaryMatrix,
+    /// Log-likelihood ratio ln((1 - p)/p) of each bit
+    priors: Vec<f64>,
+    max_iterations: usize,
+    scaling: f64,
+    osd_order: usize,
+}
+
+impl<'a> BpOsdDecoder<'a> {
+    /// The default number of belief propagation iterations
+    pub const MAX_ITERATIONS: usize = 100;
+    /// The default normalization of the min-sum rule
+    pub const SCALING: f64 = 0.625;
+    /// The default order of the ordered statistics decoding
+    pub const OSD_ORDER: usize = 7;
+
+    /// A decoder for bits that flip independently with probability `error_rate`
+    pub fn new(checks: &'a SparseBinaryMatrix, error_rate: f64) -> Self {
+        assert!(
+            0.0 < error_rate && error_rate < 0.5,
+            "The error rate should be in (0, 0.5), not {error_rate}"
+        );
+        Self {
+            checks,
+            priors: vec![((1.0 - error_rate) / error_rate).ln(); checks.cols()],
+            max_iterations: Self::MAX_ITERATIONS,
+            scaling: Self::SCALING,
+            osd_order: Self::OSD_ORDER,
+        }
+    }
+
+    pub fn with_max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    /// Try all assignments of the `osd_order` most likely columns outside the information set, or none for OSD-0
+    pub fn with_osd_order(self, osd_order: usize) -> Self {
+        assert!(osd_order < 24, "An OSD order of {osd_order} is too large");
+        Self { osd_order, ..self }
+    }
+
+    /// An error of least weight that is consistent with `syndrome`, up to the heuristics of BP+OSD.
+    /// If no error has the syndrome, the hard decision of belief propagation is returned.
+    pub fn decode(&self, syndrome: &[u8]) -> Vec<u8> {
+        assert_eq!(self.checks.rows(), syndrome.len());
+        let (decision, posteriors) = self.propagate(syndrome);
+        if self.checks.syndrome(&decision) == syndrome {
+            return decision;
+        }
+        self.osd(syndrome, &posteriors).unwrap_or(decision)
+    }
+
+    /// Run normalized min-sum belief propagation and return the hard decision and the posterior log-likelihood ratios
+    fn propagate(&self, syndrome: &[u8]) -> (Vec<u8>, Vec<f64>) {
+        let checks = self.checks;
+        // The messages on the edges of each check, in the order of the columns of its row
+        let mut to_check: Vec<Vec<f64>> = (0..checks.rows())
+            .map(|i| checks.row(i).iter().map(|j| self.priors[*j]).collect())
+            .collect();
+        let mut to_bit: Vec<Vec<f64>> = to_check.iter().map(|m| vec![0.0; m.len()]).collect();
+        let mut posteriors = self.priors.clone();
+        let mut decision = vec![0; checks.cols()];
+        for _ in 0..self.max_iterations {
+            for (i, messages) in to_check.iter().enumerate() {
+                let sign = if syndrome[i] & 1 == 1 { -1.0 } else { 1.0 };
+                let parity = messages.iter().fold(sign, |s, m| s * m.signum());
+                // The smallest two magnitudes, such that each edge can exclude its own
+                let (mut min1, mut min2, mut argmin) = (f64::INFINITY, f64::INFINITY, 0);
+                for (k, m) in messages.iter().enumerate() {
+                    let m = m.abs();
+                    if m < min1 {
+                        (min2, min1, argmin) = (min1, m, k);
+                    } else if m < min2 {
+                        min2 = m;
+                    }
+                }
+                for (k, m) in messages.iter().enumerate() {
+                    let magnitude = if k == argmin { min2 } else { min1 };
+                    to_bit[i][k] = self.scaling * parity * m.signum() * magnitude;
+                }
+            }
+            posteriors.copy_from_slice(&self.priors);
+            for (i, row) in (0..checks.rows()).map(|i| (i, checks.row(i))) {
+                for (k, j) in row.iter().enumerate() {
+                    posteriors[*j] += to_bit[i][k];
+                }
+            }
+            for (bit, posterior) in decision.iter_mut().zip(&posteriors) {
+                *bit = u8::from(*posterior < 0.0);
+            }
+            if checks.syndrome(&decision) == syndrome {
+                break;
+            }
+            for (i, row) in (0..checks.rows()).map(|i| (i, checks.row(i))) {
+                for (k, j) in row.iter().enumerate() {
+                    to_check[i][k] = posteriors[*j] - to_bit[i][k];
+                }
+            }
+        }
+        (decision, posteriors)
+    }
+
+    /// Solve the syndrome on the most reliable information set, or `None` if the syndrome has no solution
+    fn osd(&self, syndrome: &[u8], posteriors: &[f64]) -> Option<Vec<u8>> {
+        let checks = self.checks;
+        let cols = checks.cols();
+        // The most likely flipped bits first
+        let mut order: Vec<usize> = (0..cols).collect();
+        order.sort_by(|a, b| posteriors[*a].total_cmp(&posteriors[*b]));
+        let mut position = vec![0; cols];
+        for (k, j) in order.iter().enumerate() {
+            position[*j] = k;
+        }
+
+        // The permuted checks with the syndrome as an extra column
+        let mut rows: Vec<_> = (0..checks.rows())
+            .map(|i| {
+                let syndrome_bit = (syndrome[i] & 1 == 1).then_some(cols);
+                packed(
+                    cols + 1,
+                    checks
+                        .row(i)
+                        .iter()
+                        .map(|j| position[*j])
+                        .chain(syndrome_bit),
+                )
+            })
+            .collect();
+        // Reduce to row echelon form, where the pivots are the information set
+        let mut pivots = vec![];
+        for k in 0..cols {
+            let rank = pivots.len();
+            let Some(found) = (rank..rows.len()).find(|r| bit(&rows[*r], k)) else {
+                continue;
+            };
+            rows.swap(rank, found);
+            let pivot_row = rows[rank].clone();
+            for (r, row) in rows.iter_mut().enumerate() {
+                if r != rank && bit(row, k) {
+                    xor(row, &pivot_row);
+                }
+            }
+            pivots.push(k);
+        }
+        if rows[pivots.len()..].iter().any(|row| bit(row, cols)) {
+            return None;
+        }
+
+        // The most likely columns outside the information set
+        let free: Vec<usize> = (0..cols)
+            .filter(|k| !pivots.contains(k))
+            .take(self.osd_order)
+            .collect();
+        let mut best: Option<(f64, Vec<u8>)> = None;
+        for mask in 0..1usize << free.len() {
+            let mut error = vec![0; cols];
+            for (t, k) in free.iter().enumerate() {
+                if mask >> t & 1 == 1 {
+                    error[order[*k]] = 1;
+                }
+            }
+            for (r, pivot) in pivots.iter().enumerate() {
+                let flipped = free
+                    .iter()
+                    .enumerate()
+                    .filter(|(t, k)| mask >> t & 1 == 1 && bit(&rows[r], **k))
+                    .count();
+                error[order[*pivot]] = u8::from(bit(&rows[r], cols)) ^ (flipped % 2) as u8;
+            }
+            let weight: f64 = (0..cols)
+                .filter(|j| error[*j] == 1)
+                .map(|j| self.priors[j])
+                .sum();
+            if best.as_ref().is_none_or(|(least, _)| weight < *least) {
+                best = Some((weight, error));
+            }
+        }
+        best.map(|(_, error)| error)
+    }
+}
+
+/// Decode `syndrome` of the checks `h` by BP+OSD with the default parameters,
+/// for bits that flip independently with probability [`DEFAULT_ERROR_RATE`]
+pub fn decode(h: &SparseBinaryMatrix, syndrome: &[u8]) -> Vec<u8> {
+    BpOsdDecoder::new(h, DEFAULT_ERROR_RATE).decode(syndrome)
+}
+
+/// The prior error rate of [`decode`]
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+
+/// Number of sampled shots and of logical failures among them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogicalErrorRate {
+    pub shots: usize,
+    pub failures: usize,
+}
+
+impl LogicalErrorRate {
+    /// The fraction of failed shots
+    pub fn rate(&self) -> f64 {
+        self.failures as f64 / self.shots as f64
+    }
+
+    /// The Wilson score interval of the logical error rate, for `z` standard deviations, e.g., 1.96 for 95% confidence
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        let n = self.shots as f64;
+        let p = self.rate();
+        let denominator = 1.0 + z * z / n;
+        let center = (p + z * z / (2.0 * n)) / denominator;
+        let half_width = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+        (
+            (center - half_width).max(0.0),
+            (center + half_width).min(1.0),
+        )
+    }
+}
+
+/// Estimate the logical error rate of one type of errors of a CSS code under independent bit flips with probability `error_rate`.
+///
+/// The errors are detected by `checks`, e.g., the Z checks for X errors,
+/// and are harmless if they are sums of the `stabilizers` of the same type, e.g., the X checks.
+/// A shot fails if the decoded error differs from the sampled error by anything other than a stabilizer.
+pub fn sample_logical_error_rate<R: Rng>(
+    checks: &SparseBinaryMatrix,
+    stabilizers: &SparseBinaryMatrix,
+    error_rate: f64,
+    shots: usize,
+    rng: &mut R,
+) -> LogicalErrorRate {
+    assert_eq!(
+        checks.cols(),
+        stabilizers.cols(),
+        "The checks and stabilizers should act on the same bits"
+    );
+    let decoder = BpOsdDecoder::new(checks, error_rate);
+    let stabilizers = RowSpace::new(stabilizers);
+    let mut result = LogicalErrorRate { shots, failures: 0 };
+    for _ in 0..shots {
+        let error: Vec<u8> = (0..checks.cols())
+            .map(|_| u8::from(rng.random_bool(error_rate)))
+            .collect();
+        let decoded = decoder.decode(&checks.syndrome(&error));
+        let residual: Vec<u8> = error.iter().zip(&decoded).map(|(e, d)| e ^ d).collect();
+        if checks.syndrome(&residual).contains(&1) || !stabilizers.contains(&residual) {
+            result.failures += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    /// The checks of the [7,4] Hamming code, which are the X and the Z checks of the Steane code
+    fn hamming() -> SparseBinaryMatrix {
+        SparseBinaryMatrix::from_dense(
+            7,
+            &[
+                vec![1, 0, 1, 0, 1, 0, 1],
+                vec![0, 1, 1, 0, 0, 1, 1],
+                vec![0, 0, 0, 1, 1, 1, 1],
+            ],
+        )
+    }
+
+    #[test]
+    fn repetition_code() {
+        let h = SparseBinaryMatrix::new(5, (0..4).map(|i| vec![i, i + 1]).collect());
+        for flipped in 0..5 {
+            let mut error = vec![0; 5];
+            error[flipped] = 1;
+            assert_eq!(error, decode(&h, &h.syndrome(&error)));
+        }
+        // Two flips are closer to three flips on the other side
+        let error = vec![0, 0, 0, 1, 1];
+        assert_eq!(error, decode(&h, &h.syndrome(&error)));
+    }
+
+    #[test]
+    fn row_space() {
+        let h = hamming();
+        let space = RowSpace::new(&h);
+        assert_eq!(3, space.rank());
+        let sum: Vec<u8> = (0..7)
+            .map(|j| u8::from(h.row(0).contains(&j)) ^ u8::from(h.row(2).contains(&j)))
+            .collect();
+        assert!(space.contains(&sum));
+        assert!(space.contains(&[0; 7]));
+        // The logical operator of the Steane code
+        assert!(!space.contains(&[1; 7]));
+    }
+
+    #[test]
+    fn osd_finds_least_weight() {
+        let h = hamming();
+        // Belief propagation converges to errors of weight 4 on this small code, so only OSD is used
+        let decoder = BpOsdDecoder::new(&h, 0.05).with_max_iterations(0);
+        for flipped in 0..7 {
+            let mut error = vec![0; 7];
+            error[flipped] = 1;
+            assert_eq!(error, decoder.decode(&h.syndrome(&error)));
+        }
+    }
+
+    #[test]
+    fn inconsistent_syndrome() {
+        let h = SparseBinaryMatrix::from_dense(2, &[vec![1, 1], vec![1, 1]]);
+        let decoder = BpOsdDecoder::new(&h, 0.1).with_max_iterations(5);
+        // No error has this syndrome, so the decoder falls back to belief propagation
+        let decoded = decoder.decode(&[1, 0]);
+        assert_eq!(2, decoded.len());
+    }
+
+    /// The plaquettes and the stars of the toric code on an `l` by `l` torus,
+    /// with the horizontal edges before the vertical edges
+    fn toric(l: usize) -> (SparseBinaryMatrix, SparseBinaryMatrix) {
+        let h = |x: usize, y: usize| (y % l) * l + x % l;
+        let v = |x: usize, y: usize| l * l + (y % l) * l + x % l;
+        let sites = || (0..l).flat_map(move |y| (0..l).map(move |x| (x, y)));
+        let plaquettes = sites()
+            .map(|(x, y)| vec![h(x, y), h(x, y + 1), v(x, y), v(x + 1, y)])
+            .collect();
+        let stars = sites()
+            .map(|(x, y)| vec![h(x, y), h(x + l - 1, y), v(x, y), v(x, y + l - 1)])
+            .collect();
+        (
+            SparseBinaryMatrix::new(2 * l * l, plaquettes),
+            SparseBinaryMatrix::new(2 * l * l, stars),
+        )
+    }
+
+    #[test]
+    fn toric_logical_error_rate() {
+        let (plaquettes, stars) = toric(5);
+        let space = RowSpace::new(&stars);
+        assert_eq!(24, space.rank());
+        let mut rng = StdRng::seed_from_u64(0);
+        let noiseless = sample_logical_error_rate(&plaquettes, &stars, 1e-9, 100, &mut rng);
+        assert_eq!(0, noiseless.failures);
+
+        // Errors of weight 2 are corrected, so failures need at least 3 of the 50 bits to flip
+        let low = sample_logical_error_rate(&plaquettes, &stars, 0.01, 2000, &mut rng);
+        let high = sample_logical_error_rate(&plaquettes, &stars, 0.08, 2000, &mut rng);
+        assert!(low.rate() < 0.01, "{low:?}");
+        assert!(low.rate() < high.rate(), "{low:?} {high:?}");
+        let (lower, upper) = high.confidence_interval(1.96);
+        assert!(lower < high.rate() && high.rate() < upper);
+    }
+}
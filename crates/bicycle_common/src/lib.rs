@@ -17,10 +17,17 @@ use std::{
     ops::{Mul, MulAssign},
 };
 
-use rand::distr::{Distribution, StandardUniform};
+use rand::{
+    SeedableRng,
+    distr::{Distribution, StandardUniform},
+    rngs::StdRng,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub mod alloc;
+pub mod decoder;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
 pub enum Pauli {
     #[default]
     I,
@@ -100,17 +107,48 @@ impl TryFrom<usize> for Pauli {
 /// chosen because its elements are the easiest to implement as circuits. Thus,
 /// `nr_generators` gives an indication of resources required to implement a particular
 /// shift automorphism as a product of elementary elements.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
 pub struct AutomorphismData {
     x: u8,
     y: u8,
 }
 
 impl AutomorphismData {
+    /// Number of elements of the group
+    pub const GROUP_ORDER: usize = 36;
+
     pub fn new(x: u8, y: u8) -> Self {
         Self { x: x % 6, y: y % 6 }
     }
 
+    /// All elements of the group, ordered by the x and then by the y shift, starting with the identity
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..6).flat_map(|x| (0..6).map(move |y| Self::new(x, y)))
+    }
+
+    /// `n` elements sampled uniformly with the given seed, such that tests and benchmarks are reproducible
+    pub fn sample(n: usize, seed: u64) -> Vec<Self> {
+        StandardUniform
+            .sample_iter(StdRng::seed_from_u64(seed))
+            .take(n)
+            .collect()
+    }
+
+    /// The smallest k > 0 such that the k-th power of this element is the identity, which divides 6
+    pub fn order(&self) -> u8 {
+        (1..=6)
+            .find(|k| self.pow(*k).is_id())
+            .expect("The order of an element of Z6 x Z6 divides 6")
+    }
+
+    /// The k-th power of this element, i.e., the shifts multiplied by k
+    pub fn pow(&self, k: u8) -> Self {
+        Self::new(
+            ((u16::from(self.x) * u16::from(k)) % 6) as u8,
+            ((u16::from(self.y) * u16::from(k)) % 6) as u8,
+        )
+    }
+
     pub fn get_x(&self) -> u8 {
         self.x
     }
@@ -132,6 +170,23 @@ impl AutomorphismData {
         }
     }
 
+    /// Decompose this automorphism into a product of `nr_generators` elementary shifts.
+    /// The shifts commute, so they may be applied in any order.
+    pub fn decompose(&self) -> Vec<ElementaryShift> {
+        match self.nr_generators() {
+            0 => vec![],
+            1 => vec![ElementaryShift(*self)],
+            _ => {
+                let first = ElementaryShift::all()
+                    .find(|first| (*self * first.0.inv()).nr_generators() == 1)
+                    .expect(
+                        "Every shift automorphism is a product of at most two elementary shifts",
+                    );
+                vec![first, ElementaryShift(*self * first.0.inv())]
+            }
+        }
+    }
+
     /// Compute the inverse automorphism
     pub fn inv(&self) -> Self {
         AutomorphismData::new(6 - self.x, 6 - self.y)
@@ -156,6 +211,30 @@ impl MulAssign for AutomorphismData {
     }
 }
 
+/// A basic shift of Yod+25, i.e., an element of the generating set or its inverse,
+/// which hardware implements in a single step
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ElementaryShift(AutomorphismData);
+
+impl ElementaryShift {
+    /// All twelve basic shifts, ordered as in `AutomorphismData::all`
+    pub fn all() -> impl Iterator<Item = Self> {
+        AutomorphismData::all()
+            .filter(|aut| aut.nr_generators() == 1)
+            .map(Self)
+    }
+
+    pub fn automorphism(&self) -> AutomorphismData {
+        self.0
+    }
+}
+
+impl From<ElementaryShift> for AutomorphismData {
+    fn from(shift: ElementaryShift) -> Self {
+        shift.0
+    }
+}
+
 impl Distribution<AutomorphismData> for StandardUniform {
     fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> AutomorphismData {
         let x = rng.random_range(0..=5);
@@ -184,7 +263,7 @@ impl ParallelMeasureData {
 }
 
 /// Measure in two bases, one of which must not be identity
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
 pub struct TwoBases {
     p1: Pauli,
     p7: Pauli,
@@ -251,6 +330,22 @@ impl Distribution<TGateData> for StandardUniform {
     }
 }
 
+/// Store the two T gates of a [`BicycleISA::TGatePair`], which are applied in order
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub struct TGatePairData {
+    pub first: TGateData,
+    pub second: TGateData,
+}
+
+impl TGatePairData {
+    pub fn new(first: TGateData, second: TGateData) -> Self {
+        Self { first, second }
+    }
+}
+
+/// Store what kind of S gate is being implemented, like a [`TGateData`] where the adjoint is a rotation by -π/2
+pub type SGateData = TGateData;
+
 /// See Yod+25 Sec. 1.2 for a description of the bicycle architecture.
 /// A convention used here for variants carrying data is:
 /// `VariantName(VariantNameData)`.
@@ -280,6 +375,24 @@ pub enum BicycleISA {
     // Magic
     InitT,            // Initialization into 12 physical-noise |T> states
     TGate(TGateData), // Apply exp(iπ/8 P), with P in {X, X', Z, Z'}
+    // Apply two T gates in one injection step, consuming two |T> states of the same initialization
+    TGatePair(TGatePairData),
+    SGate(SGateData), // Apply exp(iπ/4 P) by catalysis with a |Y> state, with P in {X, X', Z, Z'}
+
+    // Scheduling
+    // Idle for the given number of cycles. Delays on several blocks in one operation synchronize the blocks.
+    Delay(u64),
+}
+
+impl BicycleISA {
+    /// Number of T gates that this instruction applies
+    pub fn t_count(&self) -> u64 {
+        match self {
+            BicycleISA::TGate(_) => 1,
+            BicycleISA::TGatePair(_) => 2,
+            _ => 0,
+        }
+    }
 }
 
 impl Display for BicycleISA {
@@ -309,6 +422,20 @@ impl Display for BicycleISA {
                 write!(f, "{dagger}")?;
                 write!(f, ")")
             }
+            BicycleISA::TGatePair(pair) => {
+                let [first, second] = [pair.first, pair.second].map(|basis| {
+                    let prime = if basis.primed { "'" } else { "" };
+                    let dagger = if basis.adjoint { "†" } else { "" };
+                    format!("{}{prime}{dagger}", basis.get_basis())
+                });
+                write!(f, "T2({first},{second})")
+            }
+            BicycleISA::SGate(basis) => {
+                let prime = if basis.primed { "'" } else { "" };
+                let dagger = if basis.adjoint { "†" } else { "" };
+                write!(f, "S({}{prime}{dagger})", basis.get_basis())
+            }
+            BicycleISA::Delay(cycles) => write!(f, "delay({cycles})"),
         }
     }
 }
@@ -360,31 +487,72 @@ mod tests {
         // - If element is a generator, or inverse, then it requires one generator.
         // - Otherwise, expect that two generators are required.
         // - Compare this with nr_generators (or nr_generators_old).
-        for x_exponent in 0..6 {
-            for y_exponent in 0..6 {
-                let el = AutomorphismData::new(x_exponent, y_exponent);
-                let n = el.nr_generators();
-                // According to the rules the identity should require two "basic shifts".
-                // But in fact, it requires zero basic shifts.
-                if el.is_id() {
-                    assert!(n == 0);
+        for el in AutomorphismData::all() {
+            let n = el.nr_generators();
+            // According to the rules the identity should require two "basic shifts".
+            // But in fact, it requires zero basic shifts.
+            if el.is_id() {
+                assert!(n == 0);
+            }
+            // If `el` is a generator or its inverse, then it is already a basic shift.
+            // Therefore it can be implemented with one basic shift.
+            else if generators.contains(&el) {
+                if n != 1 {
+                    println!("Fail 1: {:?}", &el);
                 }
-                // If `el` is a generator or its inverse, then it is already a basic shift.
-                // Therefore it can be implemented with one basic shift.
-                else if generators.contains(&el) {
-                    if n != 1 {
-                        println!("Fail 1: {:?}", &el);
-                    }
-                    assert!(n == 1)
-                } else {
-                    // Yod+25 asserts that the only other possibility is n == 2.
-                    // We believe this. We only check that our matching rules believe it too.
-                    if n != 2 {
-                        println!("Fail 2: {:?}", &el);
-                    }
-                    assert!(n == 2)
+                assert!(n == 1)
+            } else {
+                // Yod+25 asserts that the only other possibility is n == 2.
+                // We believe this. We only check that our matching rules believe it too.
+                if n != 2 {
+                    println!("Fail 2: {:?}", &el);
                 }
+                assert!(n == 2)
             }
         }
     }
+
+    #[test]
+    fn decompose_automorphisms() {
+        assert_eq!(12, ElementaryShift::all().count());
+        for el in AutomorphismData::all() {
+            let shifts = el.decompose();
+            assert_eq!(el.nr_generators(), shifts.len() as u64);
+            let product = shifts
+                .into_iter()
+                .fold(AutomorphismData::default(), |acc, shift| acc * shift.into());
+            assert_eq!(el, product);
+        }
+    }
+
+    #[test]
+    fn automorphism_group() {
+        let all: Vec<_> = AutomorphismData::all().collect();
+        assert_eq!(AutomorphismData::GROUP_ORDER, all.len());
+        assert!(all[0].is_id());
+        for (i, el) in all.iter().enumerate() {
+            assert!(!all[..i].contains(el));
+            assert!(el.pow(el.order()).is_id());
+            assert_eq!(el.inv(), el.pow(el.order() - 1));
+            assert_eq!(*el * *el, el.pow(2));
+        }
+
+        let sample = AutomorphismData::sample(100, 42);
+        assert_eq!(100, sample.len());
+        assert_eq!(sample, AutomorphismData::sample(100, 42));
+        assert_ne!(sample, AutomorphismData::sample(100, 43));
+    }
+
+    #[test]
+    fn automorphism_order() {
+        let all: Vec<_> = AutomorphismData::all().collect();
+        let orders = |order| all.iter().filter(|el| el.order() == order).count();
+        // Z6 x Z6 = (Z2 x Z2) x (Z3 x Z3)
+        assert_eq!([1, 3, 8, 24], [1, 2, 3, 6].map(orders));
+        assert_eq!(1, AutomorphismData::new(0, 0).order());
+        assert_eq!(6, AutomorphismData::new(1, 0).order());
+        assert_eq!(6, AutomorphismData::new(0, 1).order());
+        assert_eq!(3, AutomorphismData::new(2, 4).order());
+        assert_eq!(6, AutomorphismData::new(3, 2).order());
+    }
 }
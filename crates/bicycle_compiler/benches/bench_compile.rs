@@ -83,11 +83,12 @@ fn bench_compile(c: &mut Criterion) {
     // Note: Since the angle is fixed, the small angle synthesis will not be measure since it will be cached.
     let mut group = c.benchmark_group("rotation (dense)");
     for m in 1..20 {
-        let arch = PathArchitecture { data_blocks: m };
+        let arch = PathArchitecture::new(m);
         let basis = dense_m_block_basis(m);
         let op = PbcOperation::Rotation {
             basis,
             angle: AnglePrecision::lit("0.1"),
+            accuracy: None,
         };
         group.throughput(criterion::Throughput::Elements(m as u64));
         group.bench_with_input(BenchmarkId::from_parameter(m), &op, |b, s| {
@@ -99,7 +100,7 @@ fn bench_compile(c: &mut Criterion) {
     // Dense measurements
     let mut group = c.benchmark_group("measurement (dense)");
     for m in 1..20 {
-        let arch = PathArchitecture { data_blocks: m };
+        let arch = PathArchitecture::new(m);
         let basis = dense_m_block_basis(m);
         let op = PbcOperation::Measurement {
             basis,
@@ -115,7 +116,7 @@ fn bench_compile(c: &mut Criterion) {
     // Native measurements
     let mut group = c.benchmark_group("measurement (native)");
     for m in 1..20 {
-        let arch = PathArchitecture { data_blocks: m };
+        let arch = PathArchitecture::new(m);
         let basis = sparse_m_block_basis(m);
         let op = PbcOperation::Measurement {
             basis,
@@ -0,0 +1,410 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lower bounds on the cost of PBC operations, to compare the compiled cost against,
+//! and the structure of input programs that determines their cost.
+
+use bicycle_common::{BicycleISA, Pauli};
+use serde::Serialize;
+
+use crate::{
+    architecture::Architecture,
+    language::{AnglePrecision, PbcOperation},
+    operation::Operation,
+    small_angle::{S_ANGLE, T_ANGLE},
+};
+
+/// The cost of an operation in the resources that we bound from below
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Cost {
+    /// Number of joint measurements between two blocks
+    pub joint_measurements: usize,
+    pub t_gates: usize,
+}
+
+impl Cost {
+    /// Count the cost of compiled operations
+    pub fn of_compiled(compiled: &[Operation]) -> Self {
+        let instructions = compiled.iter().flatten().map(|(_, isa)| isa);
+        let (mut joint, mut t_gates) = (0, 0);
+        for isa in instructions {
+            match isa {
+                BicycleISA::JointMeasure(_) => joint += 1,
+                BicycleISA::TGate(_) | BicycleISA::TGatePair(_) => {
+                    t_gates += isa.t_count() as usize
+                }
+                _ => {}
+            }
+        }
+        Self {
+            // Each joint measurement consists of an instruction on both blocks
+            joint_measurements: joint / 2,
+            t_gates,
+        }
+    }
+}
+
+/// Blocks that a Pauli acts on nontrivially
+fn support(basis: &[Pauli], qubits_per_block: usize) -> Vec<usize> {
+    basis
+        .chunks(qubits_per_block)
+        .enumerate()
+        .filter(|(_, block)| block.iter().any(|p| *p != Pauli::I))
+        .map(|(block_i, _)| block_i)
+        .collect()
+}
+
+/// Minimum number of joint measurements to measure a Pauli:
+/// the joint measurements have to connect the blocks of its support,
+/// which takes at least the smallest tree of connections that reaches them.
+/// Rotations also have to reach a magic state factory.
+fn min_joint_measurements(
+    basis: &[Pauli],
+    architecture: &dyn Architecture,
+    rotation: bool,
+) -> usize {
+    let support = support(basis, architecture.qubits_per_block());
+    let Some(&first) = support.first() else {
+        return 0;
+    };
+    let root = if rotation {
+        architecture.nearest_magic_block(&support)
+    } else {
+        first
+    };
+    architecture.ghz_tree(root, &support).len()
+}
+
+/// Minimum T count to synthesize a rotation about `angle` up to `accuracy`.
+///
+/// Rotations about a multiple of the T angle are synthesized exactly.
+/// For other angles we count single-qubit Clifford+T unitaries:
+/// there are 24 (3 2^t - 2) of them with T count at most t, up to a phase,
+/// and each is within `accuracy` of Z rotations in an interval of angles of at most 4 `accuracy`.
+/// Covering all angles therefore needs a T count of at least log2((pi / (2 accuracy) / 24 + 2) / 3),
+/// which bounds the T count of a generic angle.
+pub fn min_t_count(angle: AnglePrecision, accuracy: AnglePrecision) -> usize {
+    let t_multiple = angle / T_ANGLE;
+    if t_multiple.frac() == AnglePrecision::ZERO {
+        return usize::from(t_multiple.int().to_num::<i64>() % 2 != 0);
+    }
+    let unitaries = std::f64::consts::PI / (2.0 * accuracy.to_num::<f64>());
+    ((unitaries / 24.0 + 2.0) / 3.0).log2().ceil().max(0.0) as usize
+}
+
+/// Lower bound on the cost of compiling an operation,
+/// where `accuracy` is the synthesis accuracy unless the operation overrides it
+pub fn lower_bound(
+    op: &PbcOperation,
+    architecture: &dyn Architecture,
+    accuracy: AnglePrecision,
+) -> Cost {
+    let accuracy = op.accuracy(accuracy);
+    let rotation = |basis: &[Pauli], angle| Cost {
+        joint_measurements: min_joint_measurements(basis, architecture, true),
+        t_gates: min_t_count(angle, accuracy),
+    };
+    match op {
+        PbcOperation::Measurement { basis, .. } => Cost {
+            joint_measurements: min_joint_measurements(basis, architecture, false),
+            t_gates: 0,
+        },
+        PbcOperation::Rotation { basis, angle, .. } => rotation(basis, *angle),
+        // The measurement can share the joint measurements of the rotation
+        PbcOperation::RotateMeasure { basis, angle, .. } => rotation(basis, *angle),
+        // Every term needs its own T gates but the terms may share joint measurements
+        PbcOperation::MultiRotation { terms } => terms
+            .iter()
+            .map(|(basis, angle)| rotation(basis, *angle))
+            .fold(Cost::default(), |total, term| Cost {
+                joint_measurements: total.joint_measurements.max(term.joint_measurements),
+                t_gates: total.t_gates + term.t_gates,
+            }),
+        // A CCZ gate needs at least 4 T gates when it may use measurements and feedback,
+        // and none when it consumes a stored CCZ state
+        PbcOperation::Ccz { basis } => Cost {
+            joint_measurements: min_joint_measurements(basis, architecture, true),
+            t_gates: if architecture.storage_blocks() > 0 {
+                0
+            } else {
+                4
+            },
+        },
+        PbcOperation::Clifford {
+            basis,
+            quarter_turns,
+        } => match PbcOperation::clifford_angle(*quarter_turns) {
+            Some(angle) => rotation(basis, angle),
+            None => Cost::default(),
+        },
+        PbcOperation::Barrier { .. }
+        | PbcOperation::Delay { .. }
+        | PbcOperation::Permute { .. } => Cost::default(),
+    }
+}
+
+/// The achieved cost of an operation compared to its lower bound
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CostRatio {
+    pub index: usize,
+    pub joint_measurements: usize,
+    pub min_joint_measurements: usize,
+    pub t_gates: usize,
+    pub min_t_gates: usize,
+    /// The synthesis accuracy of the operation, after any override of the operation itself
+    pub accuracy: AnglePrecision,
+    /// At least one of the resources exceeds its lower bound by more than the threshold ratio
+    pub flagged: bool,
+}
+
+impl CostRatio {
+    /// Compare the `achieved` cost of operation `index` at synthesis `accuracy` to its `bound`,
+    /// flagging it if a resource is more than `threshold` times its lower bound
+    pub fn new(
+        index: usize,
+        achieved: Cost,
+        bound: Cost,
+        accuracy: AnglePrecision,
+        threshold: f64,
+    ) -> Self {
+        let exceeds = |achieved: usize, bound: usize| achieved as f64 > threshold * bound as f64;
+        Self {
+            index,
+            joint_measurements: achieved.joint_measurements,
+            min_joint_measurements: bound.joint_measurements,
+            t_gates: achieved.t_gates,
+            min_t_gates: bound.t_gates,
+            accuracy,
+            flagged: exceeds(achieved.joint_measurements, bound.joint_measurements)
+                || exceeds(achieved.t_gates, bound.t_gates),
+        }
+    }
+}
+
+/// The structure of an input operation, which predicts its cost before compiling it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OperationProfile {
+    pub index: usize,
+    /// Number of blocks that the operation acts on
+    pub blocks: usize,
+    /// Number of qubits that the operation acts on
+    pub weight: usize,
+    /// The operation needs no T gates
+    pub clifford: bool,
+    /// Index of the layer of mutually commuting operations, see [`CircuitProfile::add`]
+    pub layer: usize,
+}
+
+/// Check if a rotation about `angle` is a Clifford rotation
+fn clifford_angle(angle: AnglePrecision) -> bool {
+    let t_multiple = angle / T_ANGLE;
+    angle.abs() == S_ANGLE
+        || (t_multiple.frac() == AnglePrecision::ZERO && t_multiple.int().to_num::<i64>() % 2 == 0)
+}
+
+/// Aggregate statistics of the operations of a program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CircuitProfile {
+    pub operations: usize,
+    /// Operations on more than one block
+    pub multi_block: usize,
+    /// Operations that need T gates
+    pub non_clifford: usize,
+    /// Number of layers of mutually commuting operations
+    pub layers: usize,
+    /// The operations of the last layer
+    layer: Vec<PbcOperation>,
+}
+
+impl CircuitProfile {
+    /// Profile the next operation of the program.
+    /// Layers are formed greedily: an operation starts a new layer unless it commutes with every operation of the last layer.
+    pub fn add(&mut self, op: &PbcOperation, qubits_per_block: usize) -> OperationProfile {
+        let mut support = vec![];
+        for basis in op.bases() {
+            support.resize(support.len().max(basis.len()), false);
+            for (qubit, p) in basis.iter().enumerate() {
+                support[qubit] |= *p != Pauli::I;
+            }
+        }
+        let blocks = support
+            .chunks(qubits_per_block)
+            .filter(|block| block.contains(&true))
+            .count();
+        let clifford = match op {
+            PbcOperation::Rotation { angle, .. } | PbcOperation::RotateMeasure { angle, .. } => {
+                clifford_angle(*angle)
+            }
+            PbcOperation::MultiRotation { terms } => {
+                terms.iter().all(|(_, angle)| clifford_angle(*angle))
+            }
+            PbcOperation::Ccz { .. } => false,
+            PbcOperation::Measurement { .. }
+            | PbcOperation::Clifford { .. }
+            | PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. } => true,
+        };
+        if self.layers == 0 || !self.layer.iter().all(|other| op.commutes_with(other)) {
+            self.layers += 1;
+            self.layer.clear();
+        }
+        self.layer.push(op.clone());
+
+        let profile = OperationProfile {
+            index: self.operations,
+            blocks,
+            weight: support.iter().filter(|used| **used).count(),
+            clifford,
+            layer: self.layers - 1,
+        };
+        self.operations += 1;
+        self.multi_block += usize::from(blocks > 1);
+        self.non_clifford += usize::from(!clifford);
+        profile
+    }
+
+    /// Fraction of the operations that act on more than one block
+    pub fn multi_block_fraction(&self) -> f64 {
+        self.multi_block as f64 / self.operations.max(1) as f64
+    }
+
+    /// Fraction of the operations that need T gates
+    pub fn t_fraction(&self) -> f64 {
+        self.non_clifford as f64 / self.operations.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::PathArchitecture;
+
+    use Pauli::{I, X, Z};
+
+    #[test]
+    fn joint_measurement_bounds() {
+        let architecture = PathArchitecture::new(3);
+        let basis = [vec![X; 11], vec![I; 11], vec![Z; 11]].concat();
+        let measurement = PbcOperation::Measurement {
+            basis: basis.clone(),
+            flip_result: false,
+        };
+        assert_eq!(
+            2,
+            lower_bound(&measurement, &architecture, T_ANGLE).joint_measurements
+        );
+
+        // The factory is next to the last block
+        let first_block = [vec![X; 11], vec![I; 22]].concat();
+        let rotation = PbcOperation::Rotation {
+            basis: first_block.clone(),
+            angle: T_ANGLE,
+            accuracy: None,
+        };
+        let bound = lower_bound(&rotation, &architecture, T_ANGLE);
+        assert_eq!(
+            Cost {
+                joint_measurements: 2,
+                t_gates: 1
+            },
+            bound
+        );
+        let measurement = PbcOperation::Measurement {
+            basis: first_block,
+            flip_result: false,
+        };
+        assert_eq!(
+            0,
+            lower_bound(&measurement, &architecture, T_ANGLE).joint_measurements
+        );
+    }
+
+    #[test]
+    fn t_count_bounds() {
+        let accuracy = AnglePrecision::lit("1e-9");
+        assert_eq!(1, min_t_count(T_ANGLE, accuracy));
+        assert_eq!(1, min_t_count(-T_ANGLE, accuracy));
+        assert_eq!(0, min_t_count(2 * T_ANGLE, accuracy));
+        assert_eq!(0, min_t_count(AnglePrecision::ZERO, accuracy));
+        // log2((pi / 2e-9 / 24 + 2) / 3) = 24.4
+        assert_eq!(25, min_t_count(AnglePrecision::lit("0.1"), accuracy));
+        assert!(
+            min_t_count(AnglePrecision::lit("0.1"), AnglePrecision::lit("1e-3"))
+                < min_t_count(AnglePrecision::lit("0.1"), accuracy)
+        );
+    }
+
+    #[test]
+    fn circuit_profile() {
+        let mut profile = CircuitProfile::default();
+        let two_blocks = [vec![Z, I, Z], vec![I; 8], vec![X], vec![I; 10]].concat();
+        let rotation = PbcOperation::Rotation {
+            basis: two_blocks.clone(),
+            angle: T_ANGLE,
+            accuracy: None,
+        };
+        assert_eq!(
+            OperationProfile {
+                index: 0,
+                blocks: 2,
+                weight: 3,
+                clifford: false,
+                layer: 0,
+            },
+            profile.add(&rotation, 11)
+        );
+        let commuting = PbcOperation::Rotation {
+            basis: [vec![Z; 11], vec![I; 11]].concat(),
+            angle: S_ANGLE,
+            accuracy: None,
+        };
+        let op = profile.add(&commuting, 11);
+        assert!(op.clifford);
+        assert_eq!((1, 11, 0), (op.blocks, op.weight, op.layer));
+        let anticommuting = PbcOperation::Measurement {
+            basis: [vec![X], vec![I; 21]].concat(),
+            flip_result: false,
+        };
+        assert_eq!(1, profile.add(&anticommuting, 11).layer);
+        assert_eq!(2, profile.add(&PbcOperation::ccz([0, 1, 2], 22), 11).layer);
+
+        assert_eq!(
+            (4, 1, 2, 3),
+            (
+                profile.operations,
+                profile.multi_block,
+                profile.non_clifford,
+                profile.layers
+            )
+        );
+        assert_eq!(0.25, profile.multi_block_fraction());
+        assert_eq!(0.5, profile.t_fraction());
+    }
+
+    #[test]
+    fn flag_ratios() {
+        let bound = Cost {
+            joint_measurements: 2,
+            t_gates: 1,
+        };
+        let achieved = Cost {
+            joint_measurements: 4,
+            t_gates: 1,
+        };
+        let accuracy = AnglePrecision::lit("1e-9");
+        assert!(!CostRatio::new(0, achieved, bound, accuracy, 2.0).flagged);
+        assert!(CostRatio::new(0, achieved, bound, accuracy, 1.5).flagged);
+    }
+}
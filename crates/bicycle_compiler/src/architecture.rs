@@ -12,35 +12,685 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    collections::{BTreeSet, VecDeque},
+    ops::Range,
+};
+
+use bicycle_cliffords::PauliString;
+use bicycle_common::BicycleISA;
+use clap::ValueEnum;
+
 use crate::operation::Operation;
 
-/// Consists of blocks plus one magic state factory at the end of the path
+/// Number of data qubits in a block of the gross and two-gross codes
+pub const QUBITS_PER_BLOCK: usize = PauliString::NR_DATA_QUBITS;
+
+/// What a block of the architecture is used for
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockRole {
+    /// Holds data qubits of the program
+    Data,
+    /// Holds data qubits and is adjacent to a magic state factory
+    Magic,
+    /// Holds magic states that a factory prepared ahead of time
+    Storage,
+}
+
+/// How rotations by π/2 are implemented
+#[derive(ValueEnum, Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum SGateLowering {
+    /// Leave the S gate to the Clifford corrections, which are not compiled
+    #[default]
+    Frame,
+    /// Apply the S gate to the magic block with a catalytic |Y> state next to the factory
+    Catalysis,
+}
+
+/// How the T gates of a synthesized rotation are injected into the magic block
+#[derive(ValueEnum, Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum TInjection {
+    /// Inject each T gate with its own |T> state
+    #[default]
+    Single,
+    /// Inject consecutive T gates in pairs, which share the initialization of their |T> states
+    Pair,
+}
+
+/// Consists of blocks on a path plus a magic state factory next to the magic block,
+/// and optionally more factories next to other blocks.
+/// Each factory can buffer prepared magic states in storage blocks,
+/// which are numbered after the data blocks.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PathArchitecture {
     pub data_blocks: usize,
+    /// Number of logical data qubits in each block
+    pub qubits_per_block: usize,
+    /// The block that is adjacent to the magic state factory
+    pub magic_block: usize,
+    /// The blocks that are adjacent to the other magic state factories
+    pub more_magic_blocks: Vec<usize>,
+    /// Number of storage blocks of each factory
+    pub storage_blocks: usize,
+    /// How rotations by π/2 are implemented
+    pub s_gates: SGateLowering,
+    /// How the T gates of synthesized rotations are injected
+    pub t_injection: TInjection,
 }
 
 impl PathArchitecture {
+    /// An architecture with the factory next to the last block
+    pub fn new(data_blocks: usize) -> Self {
+        Self {
+            data_blocks,
+            qubits_per_block: QUBITS_PER_BLOCK,
+            magic_block: data_blocks.saturating_sub(1),
+            more_magic_blocks: vec![],
+            storage_blocks: 0,
+            s_gates: SGateLowering::default(),
+            t_injection: TInjection::default(),
+        }
+    }
+
     pub fn for_qubits(qubits: usize) -> Self {
-        let data_blocks = qubits.div_ceil(11);
+        Self::for_qubits_per_block(qubits, QUBITS_PER_BLOCK)
+    }
+
+    /// Enough blocks of `qubits_per_block` data qubits to hold `qubits` qubits
+    pub fn for_qubits_per_block(qubits: usize, qubits_per_block: usize) -> Self {
+        assert!(
+            qubits_per_block > 0,
+            "A block should hold at least one qubit"
+        );
+        let data_blocks = qubits.div_ceil(qubits_per_block);
+
+        Self {
+            qubits_per_block,
+            ..Self::new(data_blocks)
+        }
+    }
+
+    /// Place the factory next to the given block instead
+    pub fn with_magic_block(self, magic_block: usize) -> Self {
+        assert!(
+            magic_block < self.data_blocks,
+            "The magic block {magic_block} should be one of the {} data blocks",
+            self.data_blocks
+        );
+        Self {
+            magic_block,
+            ..self
+        }
+    }
 
-        Self { data_blocks }
+    /// Place the factory next to the central block, which minimizes the longest GHZ state
+    pub fn centered(self) -> Self {
+        let center = self.data_blocks.saturating_sub(1) / 2;
+        self.with_magic_block(center)
+    }
+
+    /// Place factories at both ends of the path
+    pub fn two_sided(self) -> Self {
+        let last = self.data_blocks.saturating_sub(1);
+        Self {
+            magic_block: last,
+            more_magic_blocks: if last > 0 { vec![0] } else { vec![] },
+            ..self
+        }
+    }
+
+    /// Place a factory next to each of the given blocks, the first of which is the magic block
+    pub fn with_magic_blocks(self, magic_blocks: &[usize]) -> Self {
+        let (&first, more) = magic_blocks
+            .split_first()
+            .expect("There should be at least one magic block");
+        for (i, magic) in more.iter().enumerate() {
+            assert!(
+                *magic < self.data_blocks,
+                "The magic block {magic} should be one of the {} data blocks",
+                self.data_blocks
+            );
+            assert!(
+                *magic != first && !more[..i].contains(magic),
+                "The magic block {magic} should have a single factory"
+            );
+        }
+        Self {
+            more_magic_blocks: more.to_vec(),
+            ..self.with_magic_block(first)
+        }
+    }
+
+    /// Give each factory `storage_blocks` blocks to store prepared magic states in
+    pub fn with_storage_blocks(self, storage_blocks: usize) -> Self {
+        Self {
+            storage_blocks,
+            ..self
+        }
+    }
+
+    /// Implement rotations by π/2 in the given way
+    pub fn with_s_gates(self, s_gates: SGateLowering) -> Self {
+        Self { s_gates, ..self }
+    }
+
+    /// Inject the T gates of synthesized rotations in the given way
+    pub fn with_t_injection(self, t_injection: TInjection) -> Self {
+        Self {
+            t_injection,
+            ..self
+        }
+    }
+
+    /// Add data blocks at the end of the path until it holds `qubits` qubits,
+    /// and return the instructions that initialize the new blocks in |0>.
+    /// The factories stay next to the same blocks.
+    pub fn grow(&mut self, qubits: usize) -> Vec<Operation> {
+        assert_eq!(
+            0, self.storage_blocks,
+            "The storage blocks are numbered after the data blocks, so the path cannot grow"
+        );
+        let data_blocks = qubits.div_ceil(self.qubits_per_block).max(self.data_blocks);
+        let init = (self.data_blocks..data_blocks)
+            .map(|block| vec![(block, BicycleISA::CSSInitZero)])
+            .collect();
+        self.data_blocks = data_blocks;
+        init
+    }
+
+    /// Add the block after the last data block if `op` initializes it, see [`Self::grow`].
+    /// Returns whether the architecture grew.
+    pub fn grow_for(&mut self, op: &Operation) -> bool {
+        match op.as_slice() {
+            [(block, BicycleISA::CSSInitZero | BicycleISA::CSSInitPlus)]
+                if *block == self.data_blocks && self.storage_blocks == 0 =>
+            {
+                self.data_blocks += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn magic_block(&self) -> usize {
+        self.magic_block
+    }
+
+    /// All blocks that are adjacent to a factory
+    pub fn magic_blocks(&self) -> impl Iterator<Item = usize> {
+        std::iter::once(self.magic_block).chain(self.more_magic_blocks.iter().copied())
     }
 
     pub fn data_blocks(&self) -> usize {
         self.data_blocks
     }
 
+    pub fn storage_blocks(&self) -> usize {
+        self.storage_blocks
+    }
+
+    /// The storage blocks of the factory next to `magic_block`
+    pub fn storage_of(&self, magic_block: usize) -> Range<usize> {
+        match self.magic_blocks().position(|magic| magic == magic_block) {
+            Some(factory) => {
+                let first = self.data_blocks + factory * self.storage_blocks;
+                first..first + self.storage_blocks
+            }
+            None => 0..0,
+        }
+    }
+
+    /// Number of data and storage blocks
+    pub fn total_blocks(&self) -> usize {
+        self.data_blocks + self.magic_blocks().count() * self.storage_blocks
+    }
+
+    /// The role of a block, or `None` if it is not part of the architecture
+    pub fn role(&self, block: usize) -> Option<BlockRole> {
+        if block < self.data_blocks {
+            if self.magic_blocks().any(|magic| magic == block) {
+                Some(BlockRole::Magic)
+            } else {
+                Some(BlockRole::Data)
+            }
+        } else if block < self.total_blocks() {
+            Some(BlockRole::Storage)
+        } else {
+            None
+        }
+    }
+
+    pub fn qubits_per_block(&self) -> usize {
+        self.qubits_per_block
+    }
+
     pub fn qubits(&self) -> usize {
-        self.data_blocks * 11
+        self.data_blocks * self.qubits_per_block
     }
 
+    /// Check that an operation acts on a single block, on successive data blocks,
+    /// or on a magic block and one of its storage blocks, see [`Architecture::validate_operation`]
     pub fn validate_operation(&self, op: &Operation) -> bool {
-        // Check that operations act on successive blocks
-        if op.len() == 1 {
-            true
-        } else {
-            op[0].0.abs_diff(op[1].0) == 1
+        Architecture::validate_operation(self, op)
+    }
+}
+
+/// How the data blocks of an architecture are connected by joint measurements.
+///
+/// The blocks, factories, and storage blocks are those of a [`PathArchitecture`],
+/// whose path is replaced by the connections of [`Architecture::neighbors`].
+/// GHZ states are prepared on the smallest tree of connections that reaches all blocks of an operation.
+pub trait Architecture {
+    /// The blocks, factories, and storage blocks, regardless of how the data blocks are connected
+    fn layout(&self) -> &PathArchitecture;
+
+    /// The data blocks that are connected to `block`
+    fn neighbors(&self, block: usize) -> Vec<usize>;
+
+    fn data_blocks(&self) -> usize {
+        self.layout().data_blocks()
+    }
+
+    fn qubits_per_block(&self) -> usize {
+        self.layout().qubits_per_block()
+    }
+
+    fn magic_block(&self) -> usize {
+        self.layout().magic_block()
+    }
+
+    fn storage_blocks(&self) -> usize {
+        self.layout().storage_blocks()
+    }
+
+    fn storage_of(&self, magic_block: usize) -> Range<usize> {
+        self.layout().storage_of(magic_block)
+    }
+
+    fn total_blocks(&self) -> usize {
+        self.layout().total_blocks()
+    }
+
+    fn s_gates(&self) -> SGateLowering {
+        self.layout().s_gates
+    }
+
+    fn t_injection(&self) -> TInjection {
+        self.layout().t_injection
+    }
+
+    /// The next block on a shortest route from each data block to `root`, or `None` for `root`.
+    fn towards(&self, root: usize) -> Vec<Option<usize>> {
+        let mut next = vec![None; self.data_blocks()];
+        let mut reached = vec![false; self.data_blocks()];
+        reached[root] = true;
+        let mut queue = VecDeque::from([root]);
+        while let Some(block) = queue.pop_front() {
+            for neighbor in self.neighbors(block) {
+                if !reached[neighbor] {
+                    reached[neighbor] = true;
+                    next[neighbor] = Some(block);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        assert!(
+            reached.iter().all(|reached| *reached),
+            "The data blocks should be connected"
+        );
+        next
+    }
+
+    /// The blocks on a shortest route from `from` to `to`, including both
+    fn route(&self, from: usize, to: usize) -> Vec<usize> {
+        let next = self.towards(to);
+        let mut route = vec![from];
+        while let Some(block) = next[*route.last().unwrap()] {
+            route.push(block);
+        }
+        route
+    }
+
+    /// Number of joint measurements on a shortest route between two blocks
+    fn distance(&self, from: usize, to: usize) -> usize {
+        self.route(from, to).len() - 1
+    }
+
+    /// The connections, as pairs of increasing blocks in increasing order,
+    /// of a tree that reaches `root` and `blocks` along the shortest routes to `root`.
+    /// If the data blocks are connected as a tree, this is the smallest such tree.
+    fn ghz_tree(&self, root: usize, blocks: &[usize]) -> Vec<(usize, usize)> {
+        let next = self.towards(root);
+        let mut edges = BTreeSet::new();
+        for &block in blocks {
+            let mut block = block;
+            while let Some(towards) = next[block] {
+                if !edges.insert((block.min(towards), block.max(towards))) {
+                    break;
+                }
+                block = towards;
+            }
+        }
+        edges.into_iter().collect()
+    }
+
+    /// The magic block that requires the smallest GHZ state to reach `blocks`.
+    /// Ties are broken in favor of the first factory.
+    fn nearest_magic_block(&self, blocks: &[usize]) -> usize {
+        self.layout()
+            .magic_blocks()
+            .min_by_key(|&magic| self.ghz_tree(magic, blocks).len())
+            .unwrap()
+    }
+
+    /// Check that an operation acts on a single block, on connected data blocks,
+    /// or on a magic block and one of its storage blocks.
+    /// A barrier of delays may act on any blocks, and an empty operation is invalid.
+    fn validate_operation(&self, op: &Operation) -> bool {
+        if op
+            .iter()
+            .any(|(block_i, _)| *block_i >= self.total_blocks())
+        {
+            return false;
+        }
+        if !op.is_empty()
+            && op
+                .iter()
+                .all(|(_, instr)| matches!(instr, BicycleISA::Delay(_)))
+        {
+            return true;
+        }
+        match op.as_slice() {
+            [_] => true,
+            [(first, _), (second, _)]
+                if *first < self.data_blocks() && *second < self.data_blocks() =>
+            {
+                self.neighbors(*first).contains(second)
+            }
+            [(first, _), (second, _)] => {
+                self.storage_of(*first).contains(second) || self.storage_of(*second).contains(first)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Architecture for PathArchitecture {
+    fn layout(&self) -> &PathArchitecture {
+        self
+    }
+
+    fn neighbors(&self, block: usize) -> Vec<usize> {
+        let mut neighbors = vec![];
+        neighbors.extend(block.checked_sub(1));
+        neighbors.extend((block + 1 < self.data_blocks).then_some(block + 1));
+        neighbors
+    }
+}
+
+/// The blocks of a [`PathArchitecture`] where the last block is also connected to the first
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RingArchitecture {
+    pub path: PathArchitecture,
+}
+
+impl RingArchitecture {
+    pub fn new(path: PathArchitecture) -> Self {
+        Self { path }
+    }
+}
+
+impl Architecture for RingArchitecture {
+    fn layout(&self) -> &PathArchitecture {
+        &self.path
+    }
+
+    fn neighbors(&self, block: usize) -> Vec<usize> {
+        let n = self.path.data_blocks;
+        let mut neighbors = vec![(block + n - 1) % n, (block + 1) % n];
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors.retain(|neighbor| *neighbor != block);
+        neighbors
+    }
+
+    /// The ring without the longest arc between two of the blocks, which is the smallest tree.
+    /// Of equally long arcs, the one across the ends of the path is left out.
+    fn ghz_tree(&self, root: usize, blocks: &[usize]) -> Vec<(usize, usize)> {
+        let n = self.path.data_blocks;
+        let mut terminals: Vec<_> = blocks.iter().copied().chain([root]).collect();
+        terminals.sort_unstable();
+        terminals.dedup();
+        // Each arc starts at a terminal and ends at the next one, wrapping around the ends
+        let arcs: Vec<_> = terminals
+            .iter()
+            .copied()
+            .zip(terminals.iter().skip(1).copied().chain([terminals[0] + n]))
+            .collect();
+        let longest = arcs
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (start, end))| end - start)
+            .map(|(arc, _)| arc)
+            .unwrap();
+        let mut edges: Vec<_> = arcs
+            .into_iter()
+            .enumerate()
+            .filter(|(arc, _)| *arc != longest)
+            .flat_map(|(_, (start, end))| start..end)
+            .map(|block| {
+                let (a, b) = (block % n, (block + 1) % n);
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+}
+
+/// The blocks of a [`PathArchitecture`] connected as a complete tree in which each block has `arity` children.
+/// The blocks are numbered in breadth-first order, so block 0 is the root.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TreeArchitecture {
+    pub path: PathArchitecture,
+    pub arity: usize,
+}
+
+impl TreeArchitecture {
+    pub fn new(path: PathArchitecture, arity: usize) -> Self {
+        assert!(
+            arity > 0,
+            "A block of the tree should have at least one child"
+        );
+        Self { path, arity }
+    }
+
+    /// Place the factory next to the root, which minimizes the longest GHZ state
+    pub fn rooted(self) -> Self {
+        Self {
+            path: self.path.with_magic_block(0),
+            ..self
+        }
+    }
+
+    pub fn parent(&self, block: usize) -> Option<usize> {
+        block.checked_sub(1).map(|block| block / self.arity)
+    }
+}
+
+impl Architecture for TreeArchitecture {
+    fn layout(&self) -> &PathArchitecture {
+        &self.path
+    }
+
+    fn neighbors(&self, block: usize) -> Vec<usize> {
+        let children = block * self.arity + 1..(block + 1) * self.arity + 1;
+        self.parent(block)
+            .into_iter()
+            .chain(children.take_while(|child| *child < self.path.data_blocks))
+            .collect()
+    }
+}
+
+/// How the data blocks are connected
+#[derive(ValueEnum, Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Topology {
+    /// Each block is connected to the previous and next block
+    #[default]
+    Path,
+    /// A path whose last block is also connected to the first
+    Ring,
+    /// A binary tree in breadth-first order
+    Tree,
+}
+
+impl Topology {
+    /// Connect the blocks of `path` in this topology
+    pub fn connect(self, path: PathArchitecture) -> Box<dyn Architecture> {
+        match self {
+            Topology::Path => Box::new(path),
+            Topology::Ring => Box::new(RingArchitecture::new(path)),
+            Topology::Tree => Box::new(TreeArchitecture::new(path, 2)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bicycle_common::{
+        BicycleISA::{CSSInitZero, Delay, JointMeasure, Measure},
+        Pauli, TwoBases,
+    };
+
+    #[test]
+    fn grow_path() {
+        let mut architecture = PathArchitecture::new(2).two_sided();
+        let init = architecture.grow(4 * QUBITS_PER_BLOCK - 1);
+        assert_eq!(vec![vec![(2, CSSInitZero)], vec![(3, CSSInitZero)]], init);
+        assert_eq!(4, architecture.data_blocks());
+        assert_eq!(vec![1, 0], architecture.magic_blocks().collect::<Vec<_>>());
+        // Fewer qubits never shrink the path
+        assert!(architecture.grow(QUBITS_PER_BLOCK).is_empty());
+
+        let mut replayed = PathArchitecture::new(2);
+        for op in &init {
+            assert!(replayed.grow_for(op));
         }
+        assert_eq!(4, replayed.data_blocks());
+        // Only the block after the last one grows the path
+        assert!(!replayed.grow_for(&vec![(5, CSSInitZero)]));
+        assert!(!replayed.grow_for(&vec![(1, CSSInitZero)]));
+    }
+
+    #[test]
+    fn ring_routes() {
+        let ring = RingArchitecture::new(PathArchitecture::new(6));
+        assert_eq!(vec![1, 5], ring.neighbors(0));
+        assert_eq!(vec![0, 5, 4], ring.route(0, 4));
+        assert_eq!(3, ring.distance(1, 4));
+        // The arc from 5 to 1 across the ends is shorter than the one through the middle
+        assert_eq!(vec![(0, 1), (0, 5)], ring.ghz_tree(5, &[1]));
+        assert_eq!(
+            vec![(0, 1), (1, 2), (2, 3)],
+            ring.ghz_tree(0, &[3]),
+            "Equally long arcs should avoid the ends of the path"
+        );
+        assert_eq!(4, ring.ghz_tree(0, &[2, 4]).len());
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        assert!(ring.validate_operation(&vec![(5, JointMeasure(z)), (0, JointMeasure(z))]));
+        assert!(!ring.validate_operation(&vec![(4, JointMeasure(z)), (0, JointMeasure(z))]));
+    }
+
+    #[test]
+    fn tree_routes() {
+        let tree = TreeArchitecture::new(PathArchitecture::new(7), 2).rooted();
+        assert_eq!(0, tree.magic_block());
+        assert_eq!(vec![1, 2], tree.neighbors(0));
+        assert_eq!(vec![1], tree.neighbors(4));
+        assert_eq!(vec![3, 1, 0, 2, 6], tree.route(3, 6));
+        assert_eq!(4, tree.distance(3, 6));
+        assert_eq!(
+            vec![(0, 1), (0, 2), (1, 3), (2, 6)],
+            tree.ghz_tree(0, &[3, 6])
+        );
+        // The leaves 3 and 4 share a parent, so they do not need the root
+        assert_eq!(vec![(1, 3), (1, 4)], tree.ghz_tree(3, &[4]));
+
+        let two_factories = TreeArchitecture::new(PathArchitecture::new(7).two_sided(), 2);
+        assert_eq!(6, two_factories.nearest_magic_block(&[2, 5]));
+        assert_eq!(0, two_factories.nearest_magic_block(&[1, 2]));
+    }
+
+    #[test]
+    fn many_factories() {
+        let architecture = PathArchitecture::new(9)
+            .with_magic_blocks(&[8, 0, 4])
+            .with_storage_blocks(1);
+        assert_eq!(
+            vec![8, 0, 4],
+            architecture.magic_blocks().collect::<Vec<_>>()
+        );
+        assert_eq!(0, architecture.nearest_magic_block(&[1]));
+        assert_eq!(4, architecture.nearest_magic_block(&[3, 5]));
+        assert_eq!(8, architecture.nearest_magic_block(&[7]));
+        assert_eq!(12, architecture.total_blocks());
+        assert_eq!(11..12, architecture.storage_of(4));
+        assert_eq!(Some(BlockRole::Magic), architecture.role(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "should have a single factory")]
+    fn duplicate_factories() {
+        PathArchitecture::new(3).with_magic_blocks(&[1, 2, 1]);
+    }
+
+    #[test]
+    fn storage_roles() {
+        let architecture = PathArchitecture::new(3).two_sided().with_storage_blocks(2);
+        assert_eq!(7, architecture.total_blocks());
+        assert_eq!(3..5, architecture.storage_of(2));
+        assert_eq!(5..7, architecture.storage_of(0));
+        assert_eq!(0..0, architecture.storage_of(1));
+
+        let roles: Vec<_> = (0..8).map(|block| architecture.role(block)).collect();
+        use BlockRole::*;
+        assert_eq!(
+            vec![
+                Some(Magic),
+                Some(Data),
+                Some(Magic),
+                Some(Storage),
+                Some(Storage),
+                Some(Storage),
+                Some(Storage),
+                None
+            ],
+            roles
+        );
+    }
+
+    #[test]
+    fn storage_connectivity() {
+        let architecture = PathArchitecture::new(3).two_sided().with_storage_blocks(2);
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let joint = |first, second| vec![(first, JointMeasure(z)), (second, JointMeasure(z))];
+        assert!(architecture.validate_operation(&vec![(6, Measure(z))]));
+        assert!(architecture.validate_operation(&joint(2, 4)));
+        assert!(architecture.validate_operation(&joint(5, 0)));
+        // Storage blocks only connect to their own factory
+        assert!(!architecture.validate_operation(&joint(0, 4)));
+        assert!(!architecture.validate_operation(&joint(1, 3)));
+        assert!(!architecture.validate_operation(&joint(3, 4)));
+        assert!(!architecture.validate_operation(&vec![(7, Measure(z))]));
+        // Barriers synchronize any blocks
+        assert!(architecture.validate_operation(&vec![
+            (0, Delay(0)),
+            (4, Delay(0)),
+            (6, Delay(0))
+        ]));
+        assert!(!architecture.validate_operation(&vec![(0, Delay(0)), (7, Delay(0))]));
+        assert!(!architecture.validate_operation(&vec![]));
     }
 }
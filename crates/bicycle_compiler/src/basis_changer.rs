@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bicycle_common::{BicycleISA, Pauli, TGateData, TwoBases};
+use bicycle_common::{BicycleISA, Pauli, SGateData, TGateData, TGatePairData, TwoBases};
 
 /// An object that permutes the non-trivial Pauli basis of the pivot qubit
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -42,6 +42,25 @@ impl BasisChanger {
                 )
                 .unwrap(),
             ),
+            BicycleISA::TGatePair(pair) => {
+                let [first, second] = [pair.first, pair.second].map(|data| {
+                    TGateData::new(
+                        self.change_pauli(data.get_basis()),
+                        data.primed,
+                        data.adjoint,
+                    )
+                    .unwrap()
+                });
+                BicycleISA::TGatePair(TGatePairData::new(first, second))
+            }
+            BicycleISA::SGate(data) => BicycleISA::SGate(
+                SGateData::new(
+                    self.change_pauli(data.get_basis()),
+                    data.primed,
+                    data.adjoint,
+                )
+                .unwrap(),
+            ),
             BicycleISA::Automorphism(_) => instr,
             _ => unimplemented!(),
         }
@@ -75,8 +94,8 @@ impl Default for BasisChanger {
 mod tests {
     use super::*;
 
-    use bicycle_common::AutomorphismData;
     use Pauli::{X, Y, Z};
+    use bicycle_common::AutomorphismData;
 
     #[test]
     fn test_change_pauli() {
@@ -0,0 +1,210 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Distribution of a total synthesis error budget over the rotations of a program.
+//!
+//! A rotation about a multiple of the T angle is compiled exactly and spends no budget.
+//! Every other rotation is synthesized to an accuracy that is taken from the budget:
+//! if the number N of such rotations is known, each rotation gets budget/N,
+//! and otherwise the k-th rotation gets budget · 6/(π² k²), which sums to at most the budget for any number of rotations.
+//! Shares above [`MAX_ACCURACY`], the largest accuracy that synthesis supports, are reduced to it.
+
+use crate::{
+    language::{AnglePrecision, PbcOperation},
+    small_angle::{MAX_ACCURACY, T_ANGLE},
+};
+
+/// 6/π², such that the sum of 6/(π² k²) over all k ≥ 1 is 1
+const BASEL: AnglePrecision = AnglePrecision::lit("0.607927101854026628663276779258");
+
+/// Sets the accuracy of each synthesized rotation without an explicit accuracy from a total error budget
+#[derive(Debug, Clone)]
+pub struct ErrorBudget {
+    total: AnglePrecision,
+    rotations: Option<usize>,
+    default: AnglePrecision,
+    assigned: usize,
+    spent: AnglePrecision,
+}
+
+impl ErrorBudget {
+    /// Distribute `total` over `rotations` synthesized rotations, or over a stream of unknown length if `None`.
+    /// Multi-rotations have no accuracy of their own and their terms are synthesized with `default`.
+    pub fn new(total: AnglePrecision, rotations: Option<usize>, default: AnglePrecision) -> Self {
+        Self {
+            total,
+            rotations,
+            default,
+            assigned: 0,
+            spent: AnglePrecision::ZERO,
+        }
+    }
+
+    /// Set the accuracy of the synthesized rotation of `op`, if any, and add it to the spent budget.
+    /// Rotations with an explicit accuracy keep it, but it is still added to the spent budget.
+    pub fn assign(&mut self, op: PbcOperation) -> PbcOperation {
+        match op {
+            PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy,
+            } => PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy: self.accuracy(angle, accuracy),
+            },
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy,
+            } => PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy: self.accuracy(angle, accuracy),
+            },
+            PbcOperation::MultiRotation { terms } => {
+                for (_, angle) in &terms {
+                    if !is_exact(*angle) {
+                        self.spent += self.default;
+                    }
+                }
+                PbcOperation::MultiRotation { terms }
+            }
+            op => op,
+        }
+    }
+
+    fn accuracy(
+        &mut self,
+        angle: AnglePrecision,
+        accuracy: Option<AnglePrecision>,
+    ) -> Option<AnglePrecision> {
+        if is_exact(angle) {
+            return accuracy;
+        }
+        let accuracy = accuracy.unwrap_or_else(|| {
+            self.assigned += 1;
+            let share = match self.rotations {
+                Some(rotations) => self.total / rotations.max(1) as i128,
+                None => {
+                    let k = self.assigned as i128;
+                    self.total * BASEL / k / k
+                }
+            };
+            share.min(MAX_ACCURACY)
+        });
+        self.spent += accuracy;
+        Some(accuracy)
+    }
+
+    /// Number of rotations whose accuracy was taken from the budget
+    pub fn assigned(&self) -> usize {
+        self.assigned
+    }
+
+    /// Sum of the accuracies of all synthesized rotations, including explicit accuracies and multi-rotation terms
+    pub fn spent(&self) -> AnglePrecision {
+        self.spent
+    }
+
+    /// The total budget
+    pub fn total(&self) -> AnglePrecision {
+        self.total
+    }
+}
+
+/// A rotation about a multiple of the T angle needs no synthesis
+fn is_exact(angle: AnglePrecision) -> bool {
+    (angle / T_ANGLE).frac() == AnglePrecision::ZERO
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::Pauli;
+
+    use super::*;
+
+    use Pauli::{X, Z};
+
+    fn rotation(angle: AnglePrecision) -> PbcOperation {
+        PbcOperation::Rotation {
+            basis: vec![X, Z],
+            angle,
+            accuracy: None,
+        }
+    }
+
+    #[test]
+    fn known_rotations() {
+        let total = AnglePrecision::lit("1e-3");
+        let mut budget = ErrorBudget::new(total, Some(4), AnglePrecision::lit("1e-9"));
+        let op = budget.assign(rotation(T_ANGLE));
+        assert_eq!(rotation(T_ANGLE), op);
+        for _ in 0..4 {
+            let op = budget.assign(rotation(AnglePrecision::lit("0.1")));
+            assert_eq!(total / 4, op.accuracy(AnglePrecision::ZERO));
+        }
+        assert_eq!(4, budget.assigned());
+        assert!(budget.spent() <= total);
+    }
+
+    #[test]
+    fn streaming_rotations() {
+        let total = AnglePrecision::lit("1e-3");
+        let mut budget = ErrorBudget::new(total, None, AnglePrecision::lit("1e-9"));
+        let mut previous = total;
+        for _ in 0..1000 {
+            let op = budget.assign(rotation(AnglePrecision::lit("0.1")));
+            let accuracy = op.accuracy(AnglePrecision::ZERO);
+            assert!(AnglePrecision::ZERO < accuracy && accuracy < previous);
+            previous = accuracy;
+        }
+        assert!(budget.spent() <= total);
+        // The tail of the series after 1000 terms is 6/(1000 π²) of the budget
+        assert!(budget.spent() > total * AnglePrecision::lit("0.998"));
+    }
+
+    #[test]
+    fn large_budgets() {
+        let total = AnglePrecision::lit("0.5");
+        for rotations in [Some(1), None] {
+            let mut budget = ErrorBudget::new(total, rotations, AnglePrecision::lit("1e-9"));
+            let op = budget.assign(rotation(AnglePrecision::lit("0.1")));
+            assert_eq!(MAX_ACCURACY, op.accuracy(AnglePrecision::ZERO));
+        }
+    }
+
+    #[test]
+    fn explicit_and_multi_rotations() {
+        let default = AnglePrecision::lit("1e-9");
+        let explicit = AnglePrecision::lit("1e-5");
+        let mut budget = ErrorBudget::new(AnglePrecision::lit("1e-3"), Some(1), default);
+        let op = budget.assign(PbcOperation::Rotation {
+            basis: vec![X, Z],
+            angle: AnglePrecision::lit("0.1"),
+            accuracy: Some(explicit),
+        });
+        assert_eq!(explicit, op.accuracy(default));
+        budget.assign(PbcOperation::MultiRotation {
+            terms: vec![
+                (vec![X, Z], AnglePrecision::lit("0.1")),
+                (vec![Z, X], T_ANGLE),
+            ],
+        });
+        assert_eq!(0, budget.assigned());
+        assert_eq!(explicit + default, budget.spent());
+    }
+}
@@ -0,0 +1,552 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The command-line arguments of the compiler, grouped by the stage that they configure
+
+use std::{error, num::NonZeroUsize, path::PathBuf, sync::Mutex};
+
+use bicycle_cliffords::{
+    CodeMeasurement, CompleteMeasurementTable, IncompleteTable, MeasurementChoices,
+    MeasurementTableBuilder, UniformCost,
+    native_measurement::{NativeFilter, NativeMeasurement},
+};
+use bicycle_compiler::{
+    CliffordFrame, PathArchitecture, SGateLowering, TInjection, Topology,
+    budget::ErrorBudget,
+    language::{AnglePrecision, Interleaving},
+    model::{CostMetric, GROSS_1E3, GROSS_1E4, Model, TWO_GROSS_1E3, TWO_GROSS_1E4, TableCost},
+    operation::OutputFormat,
+    qre::CountSource,
+    small_angle,
+    snap::AngleSnapper,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::warn;
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::input::InputFormat;
+
+#[derive(Parser)]
+#[command(version, about, long_about=None)]
+pub struct Cli {
+    /// Select the bicycle code (either gross or two-gross)
+    pub code: MeasurementChoices,
+    /// Read the automorphism matrices mx and my of the code from a JSON or TOML file,
+    /// e.g., to explore the automorphisms of other bivariate bicycle codes
+    #[arg(long, value_name = "FILE")]
+    pub code_file: Option<PathBuf>,
+    #[command(subcommand)]
+    pub commands: Option<Commands>,
+    #[command(flatten)]
+    pub table: TableArgs,
+    #[command(flatten)]
+    pub synthesis: SynthesisArgs,
+    #[command(flatten)]
+    pub layout: LayoutArgs,
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub passes: PassArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Number of threads that compile, where parsing, compiling, and optimizing get a thread of their own
+    /// in the order of compiling, optimizing, and parsing, and serializing runs on the main thread.
+    /// The stages are connected by bounded queues, such that a slow stage holds back the stages before it.
+    #[arg(short = 'j', long, default_value_t = NonZeroUsize::MIN, conflicts_with_all = ["observables", "feed_forward"])]
+    pub threads: NonZeroUsize,
+}
+
+/// How the Clifford synthesis table is built or loaded
+#[derive(Args)]
+pub struct TableArgs {
+    /// Read a cached Clifford synthesis table from the given file name
+    #[arg(long)]
+    pub measurement_table: Option<String>,
+    /// Check every chain of a raw measurement table (see `generate --raw`) before compiling,
+    /// which reads the whole file instead of only the looked-up pages
+    #[arg(long, requires = "measurement_table")]
+    pub verify_table: bool,
+    /// Restrict the native measurements that the Clifford synthesis table is built from
+    #[command(flatten)]
+    pub natives: NativeFilter,
+    /// Keep a Clifford synthesis table that cannot implement all measurements, e.g., for restricted native measurements,
+    /// instead of failing. Operations that need a missing measurement are rejected.
+    #[arg(long, conflicts_with = "measurement_table")]
+    pub allow_incomplete: bool,
+    /// Build the Clifford synthesis table with the fewest native measurements per measurement,
+    /// or with the least error or time of their instructions under the built-in model of the code
+    #[arg(long, value_enum, default_value_t)]
+    pub table_cost: TableCostChoice,
+    /// Physical noise rate of the built-in model that weights the native measurements for `--table-cost`
+    #[arg(long, value_enum, default_value_t)]
+    pub table_noise: NoiseRate,
+    /// Only use native measurements with the identity automorphism, for debugging.
+    /// Operations that cannot be compiled without automorphisms are rejected.
+    #[arg(long)]
+    pub no_automorphisms: bool,
+    /// Precompute the Clifford synthesis of every measurement for faster lookups, at the cost of memory
+    #[arg(long)]
+    pub flatten_chains: bool,
+    /// Only load the shards of a sharded measurement table (see `generate --sharded`)
+    /// that are needed for operations on the first given number of qubits of each block.
+    /// Operations on other qubits are rejected.
+    #[arg(long, requires = "measurement_table", conflicts_with = "no_automorphisms", value_parser = clap::value_parser!(u32).range(1..=11))]
+    pub table_qubits: Option<u32>,
+}
+
+/// How rotations are synthesized
+#[derive(Args)]
+pub struct SynthesisArgs {
+    /// The accuracy of small angle synthesis
+    #[arg(short, long, default_value_t = AnglePrecision::lit("1e-9"), value_parser = parse_accuracy)]
+    pub accuracy: AnglePrecision,
+    /// Keep the synthesized small angles in the given file between runs.
+    /// A cache of another version or synthesizer is replaced.
+    #[arg(long, value_name = "FILE")]
+    pub angle_cache: Option<PathBuf>,
+    /// The maximum number of angles in the angle cache, the least recently used angles are evicted
+    #[arg(long, default_value_t = 100_000, requires = "angle_cache")]
+    pub angle_cache_capacity: usize,
+    /// Drop the Clifford corrections of synthesized rotations, including the S gates of `--s-gates frame`,
+    /// instead of commuting them past later operations by conjugating their bases.
+    /// The output then keeps the bases of the input, as the numerics of the paper assume, but does not implement it
+    #[arg(long)]
+    pub no_clifford_frame: bool,
+    /// Randomly round rotations to a neighbouring multiple of the T angle, such that the angle is unbiased,
+    /// if the diamond-norm distance of the averaged channel to the rotation is at most this bound
+    #[arg(long, value_name = "MAX_ERROR")]
+    pub snap_angles: Option<f64>,
+    /// Seed of the randomized rounding of angles
+    #[arg(long, default_value_t = 0, requires = "snap_angles")]
+    pub snap_seed: u64,
+    /// Distribute this total synthesis error over the rotations that are not multiples of the T angle,
+    /// instead of synthesizing each of them with `--accuracy`.
+    /// Rotations with an explicit accuracy keep it, and the terms of multi-rotations use `--accuracy`.
+    #[arg(long, value_name = "BUDGET", value_parser = parse_budget)]
+    pub error_budget: Option<AnglePrecision>,
+    /// The number of synthesized rotations of the program, such that each gets an equal share of `--error-budget`.
+    /// Without it, the k-th rotation gets a share of 6/(π² k²), which stays within the budget for any number of rotations.
+    #[arg(long, value_name = "N", requires = "error_budget")]
+    pub rotations: Option<usize>,
+}
+
+/// The blocks of the architecture and how operations use them
+#[derive(Args)]
+pub struct LayoutArgs {
+    /// The indices of the blocks next to magic state factories (defaults to the last block).
+    /// Each rotation uses the factory that is nearest to its support.
+    #[arg(long, value_delimiter = ',', conflicts_with = "two_sided")]
+    pub magic_block: Vec<usize>,
+    /// Place magic state factories at both ends of the path.
+    /// Each rotation uses the factory that is nearest to its support.
+    #[arg(long)]
+    pub two_sided: bool,
+    /// Number of storage blocks next to each magic state factory.
+    /// CCZ gates then consume a stored CCZ state instead of being lowered to seven T rotations.
+    #[arg(long, default_value_t = 0)]
+    pub storage_blocks: usize,
+    /// Reject operations on more qubits than the first operation, instead of adding blocks to the end of the path.
+    /// Architectures with storage blocks, and rings, never grow.
+    #[arg(long)]
+    pub fixed_architecture: bool,
+    /// How the data blocks are connected.
+    /// The factory of a tree is next to its root, unless `--magic-block` or `--two-sided` is given.
+    #[arg(long, value_enum, default_value_t)]
+    pub topology: Topology,
+    /// How to implement rotations by π/2
+    #[arg(long, value_enum, default_value_t)]
+    pub s_gates: SGateLowering,
+    /// How to inject the T gates of synthesized rotations into the magic block.
+    /// Pairs of T gates are not checked by `--verify-t`.
+    #[arg(long, value_enum, default_value_t, conflicts_with = "verify_t")]
+    pub t_injection: TInjection,
+}
+
+/// How the input programs are read and ordered
+#[derive(Args)]
+pub struct InputArgs {
+    /// Read operations from the given file instead of stdin.
+    /// When given multiple times, the programs run in parallel on their own blocks.
+    #[arg(long = "input", value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+    /// The format of the input programs
+    #[arg(long, value_enum, default_value_t)]
+    pub input_format: InputFormat,
+    /// How to interleave the operations of multiple inputs
+    #[arg(long, value_enum, default_value_t)]
+    pub interleave: Interleaving,
+    /// Pad operations with identities to fill whole blocks of 11 qubits.
+    /// Without this flag, operations whose number of qubits is not a multiple of 11 are rejected.
+    #[arg(long)]
+    pub pad: bool,
+    /// Number of upcoming operations that may be reordered according to their `priority` and `deadline` hints.
+    /// Operations without hints keep their order.
+    #[arg(long, default_value_t = 16)]
+    pub schedule_window: usize,
+    /// Fuse rotations that are immediately followed by a measurement in the same basis.
+    /// A fused pair is output as a single line.
+    #[arg(long)]
+    pub fuse: bool,
+}
+
+/// The passes on the compiled instructions
+#[derive(Args)]
+pub struct PassArgs {
+    /// Keep the basis of each block applied between operations and only change it where needed.
+    /// The basis is restored in a final output line.
+    #[arg(long)]
+    pub cache_bases: bool,
+    /// Shorten runs of automorphisms and measurements on a single block within each output line,
+    /// by merging their automorphisms and dropping repeated measurements
+    #[arg(long)]
+    pub compress_blocks: bool,
+    /// Check each T injection by repeating the measurement of the pivot of the magic block
+    /// until it is measured the given odd number of times, for a majority vote on the outcome of the gadget
+    #[arg(long, value_name = "REPETITIONS", value_parser = parse_repetitions, conflicts_with = "observables")]
+    pub verify_t: Option<usize>,
+    /// Lower each automorphism into the sequence of elementary shifts of the generating set
+    /// that hardware executes, with one operation per shift
+    #[arg(long)]
+    pub decompose_automorphisms: bool,
+    /// Reorder the operations of each output line into time steps,
+    /// such that operations on disjoint blocks that can run in parallel are adjacent
+    #[arg(long, conflicts_with_all = ["observables", "feed_forward"])]
+    pub schedule: bool,
+    /// Corrupt each compiled instruction with this probability, to test downstream validation.
+    /// Corrupted instructions are moved to another block or measure in another basis.
+    #[arg(long, value_name = "RATE")]
+    pub inject_faults: Option<f64>,
+    /// Seed of the fault injection
+    #[arg(long, default_value_t = 0, requires = "inject_faults")]
+    pub fault_seed: u64,
+    /// Write the injected faults to the given file, as JSON lines
+    #[arg(long, value_name = "FILE", requires = "inject_faults")]
+    pub fault_log: Option<PathBuf>,
+    /// Check that the output implements the program by simulating it on a stabilizer tableau of the blocks,
+    /// where the T and S gates are only checked to act on the Pauli of a rotation.
+    /// Stops at the first operation whose instructions do not implement it.
+    #[arg(long)]
+    pub verify: bool,
+}
+
+/// What is written to stdout and to the report files
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Write an intermediate representation of the program instead of compiling it,
+    /// without preparing a measurement table
+    #[arg(long, value_enum)]
+    pub emit: Option<Emit>,
+    /// Write the compiled program as JSON lines while compiling, or as a versioned MessagePack or CBOR document
+    /// with the architecture once compilation finishes
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+    /// Tag each operation with its start cycle and duration under the built-in model of the code,
+    /// and insert a `SyndromeCycle` before an operation on each block that idles until it starts.
+    /// Each output line becomes a list of `{"start", "cycles", "operation"}` objects.
+    #[arg(long, conflicts_with = "output_format")]
+    pub explicit_schedule: bool,
+    /// Physical noise rate of the built-in model that times the operations for `--explicit-schedule`
+    #[arg(long, value_enum, default_value_t, requires = "explicit_schedule")]
+    pub schedule_noise: NoiseRate,
+    /// Write the cost of each operation and its lower bound to the given file, as JSON lines
+    #[arg(long, value_name = "FILE")]
+    pub lower_bounds: Option<PathBuf>,
+    /// Flag operations that use more than this many times the lower bound of a resource
+    #[arg(long, default_value_t = 2.0, requires = "lower_bounds")]
+    pub flag_ratio: f64,
+    /// Write the logical counts of the program to the given file, in the format of the Azure Quantum Resource Estimator
+    #[arg(long, value_name = "FILE")]
+    pub qre_counts: Option<PathBuf>,
+    /// Take the logical counts from the input program or from the compiled instructions
+    #[arg(long, value_enum, default_value_t, requires = "qre_counts")]
+    pub qre_source: CountSource,
+    /// Write the compiled measurement outcomes whose parity is the outcome of each measurement of the program
+    /// to the given file, as JSON lines
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["inject_faults", "compress_blocks"])]
+    pub observables: Option<PathBuf>,
+    /// Write each injected T or S gate with the compiled measurement outcomes whose parity conditions it
+    /// to the given file, as JSON lines
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["inject_faults", "compress_blocks", "verify_t"])]
+    pub feed_forward: Option<PathBuf>,
+    /// Report the wall-clock time of parsing, table lookups, GHZ construction, synthesis, and each optimizer pass,
+    /// aggregated over all operations, to stderr
+    #[arg(long)]
+    pub explain: bool,
+}
+
+impl Cli {
+    /// The automorphism action of the code, or of `--code-file`
+    pub fn measurement(&self) -> Result<CodeMeasurement, Box<dyn error::Error>> {
+        match &self.code_file {
+            Some(path) => CodeMeasurement::from_file(path),
+            None => Ok(self.code.measurement()),
+        }
+    }
+
+    /// A builder of the Clifford synthesis table from the given native measurements, weighted by `--table-cost`
+    pub fn table_builder(
+        &self,
+        natives: Vec<NativeMeasurement>,
+    ) -> Result<MeasurementTableBuilder, Box<dyn error::Error>> {
+        let code = self.measurement()?;
+        let model = builtin_model(self.code, self.table.table_noise);
+        Ok(match self.table.table_cost {
+            TableCostChoice::Count => {
+                MeasurementTableBuilder::with_cost_model(natives, code, &UniformCost)
+            }
+            TableCostChoice::Error => MeasurementTableBuilder::with_cost_model(
+                natives,
+                code,
+                &TableCost::new(model, CostMetric::Error),
+            ),
+            TableCostChoice::Time => MeasurementTableBuilder::with_cost_model(
+                natives,
+                code,
+                &TableCost::new(model, CostMetric::Time),
+            ),
+        })
+    }
+
+    /// Complete the table, or keep the measurements that were found if incomplete tables are allowed
+    pub fn complete(
+        &self,
+        builder: MeasurementTableBuilder,
+    ) -> Result<CompleteMeasurementTable, IncompleteTable> {
+        if !self.table.allow_incomplete {
+            return builder.complete();
+        }
+        if builder.len() < 4usize.pow(12) {
+            warn!(
+                "The measurement table is incomplete: only {} of {} Pauli measurements can be implemented",
+                builder.len(),
+                4usize.pow(12)
+            );
+        }
+        Ok(builder.partial())
+    }
+
+    /// The randomized rounding of `--snap-angles`
+    pub fn snapper(&self) -> Option<Mutex<AngleSnapper<StdRng>>> {
+        self.synthesis.snap_angles.map(|max_error| {
+            Mutex::new(AngleSnapper::new(
+                max_error,
+                StdRng::seed_from_u64(self.synthesis.snap_seed),
+            ))
+        })
+    }
+
+    /// The distribution of `--error-budget` over the rotations
+    pub fn error_budget(&self) -> Option<Mutex<ErrorBudget>> {
+        let synthesis = &self.synthesis;
+        synthesis.error_budget.map(|total| {
+            Mutex::new(ErrorBudget::new(
+                total,
+                synthesis.rotations,
+                synthesis.accuracy,
+            ))
+        })
+    }
+
+    /// The Clifford frame, unless `--no-clifford-frame` is given
+    pub fn frame(&self) -> Option<Mutex<CliffordFrame>> {
+        (!self.synthesis.no_clifford_frame)
+            .then(|| Mutex::new(CliffordFrame::new(self.layout.s_gates)))
+    }
+
+    /// The architecture for programs on the given number of qubits
+    pub fn architecture(&self, qubits: usize) -> Result<PathArchitecture, String> {
+        let layout = &self.layout;
+        let architecture =
+            PathArchitecture::for_qubits_per_block(qubits, self.code.qubits_per_block());
+        for (i, &magic) in layout.magic_block.iter().enumerate() {
+            if magic >= architecture.data_blocks() {
+                return Err(format!(
+                    "The magic block {magic} should be one of the {} data blocks of the first operation",
+                    architecture.data_blocks()
+                ));
+            }
+            if layout.magic_block[..i].contains(&magic) {
+                return Err(format!("The magic block {magic} is given more than once"));
+            }
+        }
+        let architecture = match layout.magic_block.as_slice() {
+            [] if layout.two_sided => architecture.two_sided(),
+            // See `TreeArchitecture::rooted`
+            [] if layout.topology == Topology::Tree => architecture.with_magic_block(0),
+            [] => architecture,
+            magic_blocks => architecture.with_magic_blocks(magic_blocks),
+        };
+        Ok(architecture
+            .with_storage_blocks(layout.storage_blocks)
+            .with_s_gates(layout.s_gates)
+            .with_t_injection(layout.t_injection))
+    }
+}
+
+/// Caching commands
+#[derive(Subcommand, Clone, PartialEq)]
+pub enum Commands {
+    /// Generate Clifford measurement table and save to file name
+    Generate {
+        /// The file name to output to
+        measurement_table: String,
+        /// Store the table in shards, such that `--table-qubits` can load only a fraction of it
+        #[arg(long)]
+        sharded: bool,
+        /// Store the table in a raw layout that is mapped into memory without decoding, at the cost of a larger file
+        #[arg(long, conflicts_with = "sharded")]
+        raw: bool,
+        /// Store only the cheapest implementation of each measurement on the data qubits, which is all that compiling needs,
+        /// in a table of about a third of the size
+        #[arg(long, conflicts_with_all = ["sharded", "raw"])]
+        minimal: bool,
+        /// Write the partial table to this file during the build, such that an interrupted build can be resumed
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<PathBuf>,
+        /// Number of iterations of the search between checkpoints
+        #[arg(long, default_value_t = NonZeroUsize::MIN)]
+        checkpoint_every: NonZeroUsize,
+        /// Continue the build from a checkpoint, with its native measurements and costs.
+        /// Further checkpoints are written to the same file unless `--checkpoint` is given
+        #[arg(long, value_name = "FILE")]
+        resume: Option<PathBuf>,
+    },
+    /// Compile a program with parametric angles from stdin once,
+    /// and write the program for each binding of values to the parameters to its own file
+    Bind {
+        /// Values of the parameters as JSON lines, e.g., `{"gamma":"0.3","beta":"-0.1"}`
+        #[arg(long, value_name = "FILE")]
+        bindings: PathBuf,
+        /// The program for line `i` of the bindings is written to `<OUTPUT_DIR>/<i>.jsonl`
+        #[arg(long)]
+        output_dir: PathBuf,
+    },
+    /// Report the blocks, weight, and Clifford-ness of each operation of a program, and whether it commutes with the previous ones,
+    /// without compiling it
+    Analyze {
+        /// The program, in the format of `--input-format`
+        circuit: PathBuf,
+    },
+    /// Report the T count of synthesizing a rotation over a range of accuracies
+    SynthSweep {
+        /// The angle of the rotation
+        #[arg(long, allow_hyphen_values = true)]
+        angle: AnglePrecision,
+        /// The largest accuracy, at most 0.1
+        #[arg(long, default_value_t = AnglePrecision::lit("1e-2"), value_parser = parse_accuracy)]
+        from: AnglePrecision,
+        /// The smallest accuracy
+        #[arg(long, default_value_t = AnglePrecision::lit("1e-12"), value_parser = parse_accuracy)]
+        to: AnglePrecision,
+        /// Number of accuracies per factor of 10
+        #[arg(long, default_value_t = 1)]
+        per_decade: usize,
+        /// Reuse the T counts in the given file and add the newly synthesized ones, as JSON lines
+        #[arg(long, value_name = "FILE")]
+        cache: Option<PathBuf>,
+        /// Print the T counts as an aligned table or as CSV
+        #[arg(long, value_enum, default_value_t)]
+        format: SweepFormat,
+    },
+    /// Compile the program and print a summary of its resources with the time and logical error that a model estimates,
+    /// instead of the instructions
+    Report {
+        /// Physical noise rate of the built-in model of the code
+        #[arg(long, value_enum, default_value_t)]
+        noise: NoiseRate,
+        /// Read the cost of each instruction from a JSON or TOML model file instead of a built-in model
+        #[arg(long, value_name = "FILE", conflicts_with = "noise")]
+        model_file: Option<PathBuf>,
+        /// Duration of a syndrome cycle in microseconds, to estimate the wall-clock time
+        #[arg(long, default_value_t = 1.0)]
+        cycle_time: f64,
+        /// Print the report as JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// What to write instead of the compiled program
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The PBC operations that would be compiled, after the passes on them, as lines of text
+    PbcText,
+}
+
+/// The physical noise rate of the built-in models of `report` and `--table-cost`
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseRate {
+    #[default]
+    #[clap(name = "1e-3")]
+    P1e3,
+    #[clap(name = "1e-4")]
+    P1e4,
+}
+
+/// What the Clifford synthesis table minimizes for each measurement
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableCostChoice {
+    /// The number of native measurements
+    #[default]
+    Count,
+    /// The logical error of the instructions
+    Error,
+    /// The time of the instructions
+    Time,
+}
+
+/// The output format of `synth-sweep`
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SweepFormat {
+    #[default]
+    Table,
+    Csv,
+}
+
+/// The built-in model of a code at a physical noise rate
+pub fn builtin_model(code: MeasurementChoices, noise: NoiseRate) -> Model {
+    match (code, noise) {
+        (MeasurementChoices::Gross, NoiseRate::P1e3) => GROSS_1E3,
+        (MeasurementChoices::Gross, NoiseRate::P1e4) => GROSS_1E4,
+        (MeasurementChoices::TwoGross, NoiseRate::P1e3) => TWO_GROSS_1E3,
+        (MeasurementChoices::TwoGross, NoiseRate::P1e4) => TWO_GROSS_1E4,
+    }
+}
+
+/// Parse an accuracy of small angle synthesis
+fn parse_accuracy(s: &str) -> Result<AnglePrecision, String> {
+    let accuracy: AnglePrecision = s.parse().map_err(|e| format!("{e}"))?;
+    small_angle::check_accuracy(accuracy).map(|()| accuracy)
+}
+
+fn parse_budget(s: &str) -> Result<AnglePrecision, String> {
+    let budget: AnglePrecision = s.parse().map_err(|e| format!("{e}"))?;
+    if budget > 0 {
+        Ok(budget)
+    } else {
+        Err(format!("The error budget {budget} should be positive"))
+    }
+}
+
+/// Parse an odd number of repetitions for a majority vote
+fn parse_repetitions(s: &str) -> Result<usize, String> {
+    let repetitions: usize = s.parse().map_err(|e| format!("{e}"))?;
+    if repetitions % 2 == 1 {
+        Ok(repetitions)
+    } else {
+        Err(format!(
+            "A majority vote needs an odd number of repetitions, not {repetitions}"
+        ))
+    }
+}
@@ -12,36 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bicycle_cliffords::decomposition::NativeMeasurementImpl;
-use bicycle_cliffords::{CompleteMeasurementTable, PauliString};
-use bicycle_common::{BicycleISA, Pauli, TGateData, TwoBases};
-
-use crate::language::AnglePrecision;
-use crate::small_angle::SingleRotation;
-use crate::{architecture::PathArchitecture, operation::Operation};
+use bicycle_cliffords::decomposition::{MeasurementImpl, NativeMeasurementImpl};
+use bicycle_cliffords::{MeasurementTable, PauliString};
+use bicycle_common::{BicycleISA, Pauli, SGateData, TGateData, TGatePairData, TwoBases};
+
+use crate::language::{AnglePrecision, PbcOperation};
+use crate::small_angle::{S_ANGLE, SingleRotation};
+use crate::{
+    architecture::{Architecture, SGateLowering, TInjection},
+    explain::{self, Stage},
+    operation::Operation,
+};
 
 use crate::basis_changer::BasisChanger;
 use crate::small_angle;
+use std::cmp::Ordering;
 
-use BicycleISA::{JointMeasure, Measure, TGate};
+use BicycleISA::{Delay, JointMeasure, Measure, SGate, TGate, TGatePair};
 
-/// Construct GHZ state on a path architecture from start to end
-fn ghz_meas(start: usize, blocks: usize) -> Vec<Operation> {
-    assert!(blocks > 0);
-    let end = start + blocks;
+/// Construct a GHZ state on `root` and `blocks` by joint measurements along the tree of [`Architecture::ghz_tree`].
+/// The measurements are grouped such that each group acts on disjoint blocks,
+/// e.g., alternating even then odd blocks on a path.
+fn ghz_meas(architecture: &dyn Architecture, root: usize, blocks: &[usize]) -> Vec<Operation> {
     let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
 
-    let mut ops = vec![];
-    // Perform ZZ measurements on adjacent blocks. Alternating even then odd blocks.
-    for r in (start..(end - 1))
-        .step_by(2)
-        .chain(((start + 1)..(end - 1)).step_by(2))
-    {
-        let op = vec![(r, JointMeasure(z1)), (r + 1, JointMeasure(z1))];
-        ops.push(op);
+    // The groups that already measure each block
+    let mut busy = vec![vec![]; architecture.data_blocks()];
+    let mut groups: Vec<Vec<Operation>> = vec![];
+    for (first, second) in architecture.ghz_tree(root, blocks) {
+        let group = (0..)
+            .find(|group| !busy[first].contains(group) && !busy[second].contains(group))
+            .unwrap();
+        if group == groups.len() {
+            groups.push(vec![]);
+        }
+        groups[group].push(vec![(first, JointMeasure(z1)), (second, JointMeasure(z1))]);
+        busy[first].push(group);
+        busy[second].push(group);
     }
 
-    ops
+    groups.concat()
 }
 
 /// Compile a native measurement, including conjugating state preparation and measurement
@@ -57,20 +67,31 @@ fn rotation_instructions(native_measurement: &NativeMeasurementImpl) -> [Bicycle
     ops
 }
 
-/// Extend basis to a multiple of 11
-fn extend_basis<T>(basis: T) -> Vec<Pauli>
+/// Extend basis to whole blocks of the architecture
+fn extend_basis<T>(basis: T, architecture: &dyn Architecture) -> Vec<Pauli>
 where
     T: IntoIterator<Item = Pauli>,
 {
+    let block_qubits = architecture.qubits_per_block();
+    // The measurement tables implement Pauli measurements on a fixed number of data qubits
+    assert_eq!(
+        PauliString::NR_DATA_QUBITS,
+        block_qubits,
+        "The measurement table supports blocks of {} qubits, not {block_qubits}",
+        PauliString::NR_DATA_QUBITS
+    );
     let mut basis: Vec<Pauli> = basis.into_iter().collect();
-    while basis.len() % 11 != 0 {
-        basis.push(Pauli::I);
-    }
-
-    assert!(basis.len() % 11 == 0);
+    basis.resize(basis.len().next_multiple_of(block_qubits), Pauli::I);
     basis
 }
 
+/// The rotations of a measurement implementation in the order that they are applied.
+/// The first rotation of the implementation conjugates the base measurement first,
+/// so it is applied last, right before the base measurement.
+fn applied_rotations(meas_impl: &MeasurementImpl) -> Vec<NativeMeasurementImpl> {
+    meas_impl.rotations().iter().rev().copied().collect()
+}
+
 fn select_basis_change(p_expected: Pauli, p_pivot: Pauli) -> BasisChanger {
     match (p_expected, p_pivot) {
         (Pauli::Z, Pauli::Z) | (Pauli::X, Pauli::X) | (Pauli::Y, Pauli::Y) => {
@@ -87,33 +108,230 @@ fn select_basis_change(p_expected: Pauli, p_pivot: Pauli) -> BasisChanger {
     }
 }
 
-/// Stores the basis change that is applied to each block
+/// Stores the basis change that is applied to each data block
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct BlockBases(pub Vec<BasisChanger>);
 
 impl BlockBases {
+    /// Change the basis of instructions on data blocks, and keep those on storage blocks
     fn change_basis(&self, op: Operation) -> Operation {
         op.into_iter()
-            .map(|(block_i, isa)| (block_i, self.0[block_i].change_isa(isa)))
+            .map(|(block_i, isa)| match self.0.get(block_i) {
+                Some(changer) => (block_i, changer.change_isa(isa)),
+                None => (block_i, isa),
+            })
             .collect()
     }
 }
 
+/// An operation lowered to instructions, excluding the rotations that change the basis of each block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Lowered {
+    /// The rotations that must be applied to each block in order, or `None` if the operation has no support on it
+    chains: Vec<Option<Vec<NativeMeasurementImpl>>>,
+    /// The instructions that are conjugated by the basis rotations
+    core: Vec<Operation>,
+    /// Indices of the instructions of `core` whose outcomes XOR to the outcome of a measurement,
+    /// or empty if the operation is not a measurement
+    outcomes: Vec<usize>,
+}
+
+/// The rotations that change the basis of each block, kept applied between operations.
+///
+/// Consecutive operations that require the same basis on a block, or that do not act on a block,
+/// can then skip undoing and redoing the rotations on that block.
+/// Call [`BlockBasisState::finish`] to restore the original basis of all blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockBasisState {
+    applied: Vec<Vec<NativeMeasurementImpl>>,
+}
+
+impl BlockBasisState {
+    pub fn new(blocks: usize) -> Self {
+        Self {
+            applied: vec![vec![]; blocks],
+        }
+    }
+
+    /// Emit the instructions of a lowered operation, changing the basis of blocks where needed.
+    pub(crate) fn apply(&mut self, lowered: Lowered) -> Vec<Operation> {
+        self.apply_observed(lowered).0
+    }
+
+    /// Like [`Self::apply`], but also return the indices of the emitted instructions
+    /// whose outcomes XOR to the outcome of a measurement
+    pub(crate) fn apply_observed(&mut self, lowered: Lowered) -> (Vec<Operation>, Vec<usize>) {
+        let mut ops = self.transition(&lowered.chains);
+        let outcomes = lowered.outcomes.iter().map(|i| ops.len() + i).collect();
+        ops.extend(lowered.core);
+        (ops, outcomes)
+    }
+
+    /// Change the basis of each block to the given rotations.
+    /// Rotations that are shared with those already applied are kept.
+    fn transition(&mut self, chains: &[Option<Vec<NativeMeasurementImpl>>]) -> Vec<Operation> {
+        if self.applied.len() < chains.len() {
+            self.applied.resize(chains.len(), vec![]);
+        }
+
+        let mut ops = vec![];
+        for (block_i, (applied, next)) in self.applied.iter_mut().zip(chains).enumerate() {
+            // Blocks without support keep their current basis
+            let Some(next) = next else {
+                continue;
+            };
+            let shared = applied
+                .iter()
+                .zip(next)
+                .take_while(|(rot0, rot1)| rot0 == rot1)
+                .count();
+            // Undo only the rotations that are not shared
+            for nat_measure in applied[shared..].iter().rev().chain(&next[shared..]) {
+                ops.extend(
+                    rotation_instructions(nat_measure)
+                        .into_iter()
+                        .map(|op| vec![(block_i, op)]),
+                );
+            }
+            *applied = next.clone();
+        }
+        ops
+    }
+
+    /// Undo all basis rotations
+    pub fn finish(&mut self) -> Vec<Operation> {
+        let blocks = self.applied.len();
+        self.transition(&vec![Some(vec![]); blocks])
+    }
+
+    /// Check if all blocks are in their original basis
+    pub fn is_clear(&self) -> bool {
+        self.applied.iter().all(|chain| chain.is_empty())
+    }
+}
+
 /// Compile a Pauli measurement to ISA instructions
 pub fn compile_measurement(
-    architecture: &PathArchitecture,
-    measurement_table: &CompleteMeasurementTable,
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+) -> Vec<Operation> {
+    observe_measurement(architecture, measurement_table, basis).0
+}
+
+/// Compile a Pauli measurement to ISA instructions,
+/// and return the indices of the instructions whose outcomes XOR to the outcome of the measurement
+pub fn observe_measurement(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
     basis: Vec<Pauli>,
+) -> (Vec<Operation>, Vec<usize>) {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let (mut ops, outcomes) =
+        state.apply_observed(lower_measurement(architecture, measurement_table, basis));
+    ops.extend(state.finish());
+    (ops, outcomes)
+}
+
+/// Compile a Pauli rotation that is immediately followed by a measurement in the same basis.
+///
+/// Both operations require the same rotations to change the basis of each block,
+/// and the GHZ state of the rotation already carries the basis to the pivot of the magic block,
+/// so the basis is measured there instead of uncomputing the GHZ state and preparing another one.
+/// Returns the instructions, the number of instructions that were saved by fusing,
+/// and the indices of the instructions whose outcomes XOR to the outcome of the measurement.
+pub fn observe_rotation_measurement(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+    angle: AnglePrecision,
+    accuracy: AnglePrecision,
+) -> (Vec<Operation>, usize, Vec<usize>) {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let (fused, saved) =
+        lower_rotation_measurement(architecture, measurement_table, basis, angle, accuracy);
+    let (mut ops, outcomes) = state.apply_observed(fused);
+    let post = state.finish();
+    // The measurement on its own would also change the basis of the blocks and restore it
+    let saved = saved + 2 * post.len();
+    ops.extend(post);
+    (ops, saved, outcomes)
+}
+
+/// Synchronize the given blocks, or all blocks, by an operation of empty delays on them
+pub fn compile_barrier(
+    architecture: &dyn Architecture,
+    blocks: Option<&[usize]>,
 ) -> Vec<Operation> {
+    let blocks: Vec<usize> = match blocks {
+        Some(blocks) => blocks.to_vec(),
+        None => (0..architecture.total_blocks()).collect(),
+    };
+    assert!(
+        blocks
+            .iter()
+            .all(|block| *block < architecture.total_blocks()),
+        "A barrier on blocks {blocks:?} needs an architecture of more than {} blocks",
+        architecture.total_blocks()
+    );
+    if blocks.is_empty() {
+        return vec![];
+    }
+    vec![blocks.into_iter().map(|block| (block, Delay(0))).collect()]
+}
+
+/// Idle each block for the given number of cycles
+pub fn compile_delay(architecture: &dyn Architecture, cycles: u64) -> Vec<Operation> {
+    (0..architecture.total_blocks())
+        .map(|block| vec![(block, Delay(cycles))])
+        .collect()
+}
+
+/// Lower a Pauli rotation followed by a measurement in the same basis, see [`observe_rotation_measurement`].
+/// Also returns the number of instructions of [`lower_measurement`] that fusing saves.
+pub(crate) fn lower_rotation_measurement(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+    angle: AnglePrecision,
+    accuracy: AnglePrecision,
+) -> (Lowered, usize) {
+    let site = injection_site(architecture, measurement_table, basis, None);
+    let injected = rotate_magic_block(
+        architecture.s_gates(),
+        architecture.t_injection(),
+        site.magic,
+        angle,
+        accuracy,
+    );
+    // The measurement on its own prepares and measures every pivot,
+    // and repeats the native measurements and a GHZ state on the support
+    let support: Vec<_> = (0..site.chains.len())
+        .filter(|block_i| site.chains[*block_i].is_some())
+        .collect();
+    let saved = 2 * architecture.data_blocks()
+        + site.native_instructions
+        + ghz_meas(architecture, support[0], &support).len();
+    (site.fill_measured(injected), saved)
+}
+
+/// Lower a Pauli measurement, excluding the rotations that change the basis of each block
+pub(crate) fn lower_measurement(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+) -> Lowered {
     let mut ops: Vec<Operation> = vec![];
     let n = architecture.data_blocks();
 
     let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
     let y1 = TwoBases::new(Pauli::Y, Pauli::I).unwrap();
 
-    let basis = extend_basis(basis);
+    let basis = extend_basis(basis, architecture);
 
     // Find implementation for each block
-    let block_instrs = basis.chunks_exact(11).map(|paulis| {
+    let block_qubits = architecture.qubits_per_block();
+    let block_instrs = basis.chunks_exact(block_qubits).map(|paulis| {
         // Only apply a controlled-Pauli if its non-trivial
         if paulis.iter().all(|p| *p == Pauli::I) {
             (None, BasisChanger::default())
@@ -121,7 +339,7 @@ pub fn compile_measurement(
             let mut ps = vec![Pauli::I];
             ps.extend_from_slice(paulis);
             let p: PauliString = (&ps[..]).try_into().unwrap();
-            let meas_impl = measurement_table.min_data(p);
+            let meas_impl = explain::time(Stage::TableLookup, || measurement_table.min_data(p));
 
             // Y |-> p_pivot.
             let p_pivot = meas_impl.measures().get_pauli(0);
@@ -134,53 +352,76 @@ pub fn compile_measurement(
     let block_basis = BlockBases(basis_changes);
     assert!(meas_impls.len() <= n);
 
-    // Apply rotations to blocks that have nontrivial rotations (requires use of pivot)
-    for (block_i, meas_impl) in meas_impls
+    // Rotations on blocks that have nontrivial rotations (requires use of pivot)
+    let chains: Vec<_> = meas_impls
         .iter()
-        .enumerate()
-        .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
-    {
-        for nat_measure in meas_impl.rotations() {
-            ops.extend(
-                rotation_instructions(nat_measure)
-                    .into_iter()
-                    .map(|op| vec![(block_i, op)]),
-            )
-        }
-    }
+        .map(|opt| opt.as_ref().map(applied_rotations))
+        .collect();
 
     // Prepare initial state
+    // Pivots with a native measurement start in Z, like the GHZ measurements, such that they do not rotate their blocks,
+    // see [`injection_site`]
     // TODO: Prepare state only on qubits that are in the range of the measurement
+    let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
     ops.extend(
         (0..n)
-            .map(|block_i| vec![(block_i, Measure(x1))])
+            .map(|block_i| match meas_impls[block_i] {
+                Some(_) => vec![(block_i, Measure(z1))],
+                None => vec![(block_i, Measure(x1))],
+            })
             .map(|o| block_basis.change_basis(o)),
     );
 
     // Apply native measurements on nontrivial blocks
     // Do _not_ change basis
+    let mut outcomes = vec![];
     for (block_i, meas_impl) in meas_impls
         .iter()
         .enumerate()
         .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
     {
+        // The outcome of the native measurement between the automorphisms
+        outcomes.push(ops.len() + 1);
         for isa in meas_impl.base_measurement().implementation() {
             ops.push(vec![(block_i, isa)]);
         }
     }
 
-    // Find the range for which we need to prepare a GHZ state
-    let first_nontrivial = meas_impls.iter().position(|rot| !rot.is_none()).unwrap();
-    let last_nontrivial = meas_impls.iter().rposition(|rot| !rot.is_none()).unwrap();
-    let mut middle_ops = ghz_meas(first_nontrivial, last_nontrivial - first_nontrivial + 1);
+    // Prepare a GHZ state that connects the nontrivial blocks
+    let support: Vec<_> = meas_impls
+        .iter()
+        .enumerate()
+        .filter(|(_, opt)| opt.is_some())
+        .map(|(block_i, _)| block_i)
+        .collect();
+    let mut middle_ops = explain::time(Stage::Ghz, || ghz_meas(architecture, support[0], &support));
+    let mut ghz_blocks: Vec<_> = middle_ops
+        .iter()
+        .flatten()
+        .map(|(block_i, _)| *block_i)
+        .chain([support[0]])
+        .collect();
+    ghz_blocks.sort_unstable();
+    ghz_blocks.dedup();
 
     // Uncompute GHZ
+    let uncompute = ops.len() + middle_ops.len();
     for (block_i, opt) in meas_impls.iter().enumerate() {
         match opt {
             None => middle_ops.push(vec![(block_i, Measure(x1))]), // was trivial
             Some(_) => middle_ops.push(vec![(block_i, Measure(y1))]),
         }
     }
+    // The measured Pauli times the pivots of the GHZ state in Y (or X where the measurement is trivial)
+    // commutes with the ZZ measurements of the GHZ state, so the outcome is the product of the native measurements,
+    // the final pivot measurements, and the initial pivot measurements of trivial blocks inside the GHZ state.
+    for block_i in ghz_blocks {
+        if meas_impls[block_i].is_none() {
+            outcomes.push(block_i);
+        }
+        outcomes.push(uncompute + block_i);
+    }
+    outcomes.sort_unstable();
     // Change basis on middle ops
     ops.extend(
         middle_ops
@@ -188,193 +429,817 @@ pub fn compile_measurement(
             .map(|op| block_basis.change_basis(op)),
     );
 
-    // Undo rotations on non-trivial blocks
-    for (block_i, meas_impl) in meas_impls
-        .iter()
-        .enumerate()
-        .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
-    {
-        for nat_measure in meas_impl.rotations().iter().rev() {
-            ops.extend(
-                rotation_instructions(nat_measure)
-                    .into_iter()
-                    .map(|op| vec![(block_i, op)]),
-            )
-        }
+    Lowered {
+        chains,
+        core: ops,
+        outcomes,
     }
-
-    ops
 }
 
 /// Compile a Pauli rotation of some rational angle to Operations
 pub fn compile_rotation(
-    architecture: &PathArchitecture,
-    measurement_table: &CompleteMeasurementTable,
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+    angle: AnglePrecision,
+    accuracy: AnglePrecision,
+) -> Vec<Operation> {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let mut ops = state.apply(lower_rotation(
+        architecture,
+        measurement_table,
+        basis,
+        angle,
+        accuracy,
+    ));
+    ops.extend(state.finish());
+    ops
+}
+
+/// Compile a CCZ gate on the qubits where `basis` is Z by consuming a CCZ state from a storage block
+pub fn compile_ccz_state(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: &[Pauli],
+) -> Vec<Operation> {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let mut ops = vec![];
+    for lowered in lower_ccz_state(architecture, measurement_table, basis) {
+        ops.extend(state.apply(lowered));
+    }
+    ops.extend(state.finish());
+    ops
+}
+
+/// Compile a set of mutually commuting Pauli rotations that share their basis rotations.
+///
+/// Because the rotations commute, they can be applied in any order.
+/// We order them such that blocks that require the same pre-rotations are adjacent,
+/// and then skip the post-rotations of one rotation that would be undone by the pre-rotations of the next.
+/// Each rotation still prepares and uncomputes its own GHZ state,
+/// because the GHZ state carries the Pauli of the rotation to the magic block, which injects one rotation at a time.
+pub fn compile_multi_rotation(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    terms: &[(Vec<Pauli>, AnglePrecision)],
+    accuracy: AnglePrecision,
+) -> Vec<Operation> {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let mut ops = vec![];
+    for lowered in lower_multi_rotation(architecture, measurement_table, terms, accuracy) {
+        ops.extend(state.apply(lowered));
+    }
+    ops.extend(state.finish());
+    ops
+}
+
+/// Lower mutually commuting rotations, ordered such that consecutive rotations share basis rotations
+pub(crate) fn lower_multi_rotation(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    terms: &[(Vec<Pauli>, AnglePrecision)],
+    accuracy: AnglePrecision,
+) -> Vec<Lowered> {
+    let mut lowered: Vec<_> = terms
+        .iter()
+        .map(|(basis, angle)| {
+            lower_rotation(
+                architecture,
+                measurement_table,
+                basis.to_vec(),
+                *angle,
+                accuracy,
+            )
+        })
+        .collect();
+    lowered.sort_by_cached_key(|lowered| {
+        lowered
+            .chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .as_ref()
+                    .map(|chain| chain.iter().map(|rot| rot.measures()).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>()
+    });
+    lowered
+}
+
+/// Lower a Pauli rotation, excluding the rotations that change the basis of each block
+pub(crate) fn lower_rotation(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
     basis: Vec<Pauli>,
     angle: AnglePrecision,
     accuracy: AnglePrecision,
+) -> Lowered {
+    lower_injection(architecture, measurement_table, basis, None, |magic| {
+        rotate_magic_block(
+            architecture.s_gates(),
+            architecture.t_injection(),
+            magic,
+            angle,
+            accuracy,
+        )
+    })
+}
+
+/// The instructions that rotate the `magic` block about X by `angle`
+fn rotate_magic_block(
+    s_gates: SGateLowering,
+    t_injection: TInjection,
+    magic: usize,
+    angle: AnglePrecision,
+    accuracy: AnglePrecision,
 ) -> Vec<Operation> {
+    if s_gates == SGateLowering::Catalysis && angle.abs() == S_ANGLE {
+        let sgate_data = SGateData::new(Pauli::X, false, angle.is_negative()).unwrap();
+        return vec![vec![(magic, SGate(sgate_data))]];
+    }
+    // Apply small-angle X(φ) rotation on the magic block
+    // The Clifford corrections of the synthesis are powers of S about X, which are not compiled,
+    // but are tracked by a `CliffordFrame` that conjugates the later operations
+    let (rots, _cliffords) = small_angle::synthesize_angle_x(angle, accuracy);
+    let tgates: Vec<_> = rots
+        .into_iter()
+        .map(|rot| {
+            match rot {
+                SingleRotation::Z { dagger } => TGateData::new(Pauli::Z, false, dagger),
+                SingleRotation::X { dagger } => TGateData::new(Pauli::X, false, dagger),
+            }
+            .unwrap()
+        })
+        .collect();
+    inject_t_gates(t_injection, magic, &tgates)
+}
+
+/// The instructions that inject the T gates into the `magic` block in order.
+/// Only the T gates of one synthesized rotation are paired,
+/// because every rotation brings its basis to the magic block on its own.
+fn inject_t_gates(t_injection: TInjection, magic: usize, tgates: &[TGateData]) -> Vec<Operation> {
+    match t_injection {
+        TInjection::Single => tgates
+            .iter()
+            .map(|tgate_data| vec![(magic, TGate(*tgate_data))])
+            .collect(),
+        // An odd T gate at the end is injected on its own
+        TInjection::Pair => tgates
+            .chunks(2)
+            .map(|chunk| match *chunk {
+                [first, second] => vec![(magic, TGatePair(TGatePairData::new(first, second)))],
+                [single] => vec![(magic, TGate(single))],
+                _ => unreachable!("Chunks hold one or two T gates"),
+            })
+            .collect(),
+    }
+}
+
+/// A compiled Pauli rotation whose angle is not known yet.
+///
+/// Only the rotation of the magic block depends on the angle,
+/// so the instructions around it are compiled once and reused for every angle by [`RotationTemplate::finalize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationTemplate {
+    before: Vec<Operation>,
+    magic: usize,
+    block_basis: BlockBases,
+    after: Vec<Operation>,
+    s_gates: SGateLowering,
+    t_injection: TInjection,
+}
+
+impl RotationTemplate {
+    /// Compile the rotation about `angle`, which is identical to [`compile_rotation`]
+    pub fn finalize(&self, angle: AnglePrecision, accuracy: AnglePrecision) -> Vec<Operation> {
+        let mut ops = self.before.clone();
+        ops.extend(
+            rotate_magic_block(self.s_gates, self.t_injection, self.magic, angle, accuracy)
+                .into_iter()
+                .map(|op| self.block_basis.change_basis(op)),
+        );
+        ops.extend(self.after.iter().cloned());
+        ops
+    }
+}
+
+/// Compile a Pauli rotation up to the rotation of the magic block, see [`RotationTemplate`]
+pub fn compile_rotation_template(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+) -> RotationTemplate {
+    let mut state = BlockBasisState::new(architecture.data_blocks());
+    let site = injection_site(architecture, measurement_table, basis, None);
+    let before = state.apply(Lowered {
+        chains: site.chains,
+        core: site.before,
+        outcomes: vec![],
+    });
+    let mut after = site.after;
+    after.extend(state.finish());
+    RotationTemplate {
+        before,
+        magic: site.magic,
+        block_basis: site.block_basis,
+        after,
+        s_gates: architecture.s_gates(),
+        t_injection: architecture.t_injection(),
+    }
+}
+
+/// Lower a CCZ gate on the qubits where `basis` is Z by consuming a CCZ state,
+/// excluding the rotations that change the basis of each block.
+///
+/// The state is stored in the first storage block of the factory nearest to the controls.
+/// Each control is measured jointly with a qubit of the state through the magic block,
+/// and the state is then measured in the X basis.
+/// The resulting Clifford corrections are tracked in the Pauli frame and are not compiled.
+pub(crate) fn lower_ccz_state(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: &[Pauli],
+) -> Vec<Lowered> {
+    let block_qubits = architecture.qubits_per_block();
+    let controls: Vec<_> = basis
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| (*p == Pauli::Z).then_some(i))
+        .collect();
+    assert_eq!(3, controls.len(), "A CCZ gate has three controls");
+    let control_blocks: Vec<_> = controls
+        .iter()
+        .map(|control| control / block_qubits)
+        .collect();
+    let magic = architecture.nearest_magic_block(&control_blocks);
+    let storage = architecture.storage_of(magic).start;
+    assert!(
+        storage < architecture.total_blocks(),
+        "Consuming a CCZ state requires a storage block"
+    );
+
+    let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
+    let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+    let mut lowered: Vec<_> = controls
+        .iter()
+        .map(|control| {
+            let mut control_basis = vec![Pauli::I; basis.len()];
+            control_basis[*control] = Pauli::Z;
+            lower_injection(
+                architecture,
+                measurement_table,
+                control_basis,
+                Some(magic),
+                |magic| vec![vec![(magic, JointMeasure(x1)), (storage, JointMeasure(z1))]],
+            )
+        })
+        .collect();
+    let last = lowered.last_mut().unwrap();
+    last.core
+        .extend((0..controls.len()).map(|_| vec![(storage, Measure(x1))]));
+    lowered
+}
+
+/// Lower a Pauli-controlled injection into the magic block, excluding the rotations that change the basis of each block.
+/// Uses the factory that is nearest to the support of `basis`, unless `magic` is given.
+fn lower_injection(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+    magic: Option<usize>,
+    inject: impl FnOnce(usize) -> Vec<Operation>,
+) -> Lowered {
+    let site = injection_site(architecture, measurement_table, basis, magic);
+    let injected = inject(site.magic);
+    site.fill(injected)
+}
+
+/// A lowered injection without the instructions on the magic block
+struct InjectionSite {
+    chains: Vec<Option<Vec<NativeMeasurementImpl>>>,
+    /// The instructions up to and including the GHZ state
+    before: Vec<Operation>,
+    /// The number of instructions of the native measurements in `before`
+    native_instructions: usize,
+    magic: usize,
+    block_basis: BlockBases,
+    /// The instructions that uncompute the GHZ state, where the magic block is last
+    after: Vec<Operation>,
+    /// The instructions of `before` and of `after` whose outcomes XOR to the outcome of measuring the basis
+    /// if the magic block is measured in X instead of Z, see [`InjectionSite::fill_measured`]
+    outcomes: (Vec<usize>, Vec<usize>),
+}
+
+impl InjectionSite {
+    /// Insert the instructions on the magic block, in the basis of the GHZ state
+    fn fill(self, injected: Vec<Operation>) -> Lowered {
+        let mut core = self.before;
+        core.extend(
+            injected
+                .into_iter()
+                .map(|op| self.block_basis.change_basis(op)),
+        );
+        core.extend(self.after);
+        Lowered {
+            chains: self.chains,
+            core,
+            outcomes: vec![],
+        }
+    }
+
+    /// Insert the instructions on the magic block like [`Self::fill`], and then measure the basis.
+    /// The pivot of the magic block carries the basis in X once the GHZ state is prepared,
+    /// and X commutes with the rotation of the magic block,
+    /// so measuring the pivot in X instead of Z measures the basis after the injection.
+    fn fill_measured(mut self, injected: Vec<Operation>) -> Lowered {
+        let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
+        *self.after.last_mut().unwrap() = self
+            .block_basis
+            .change_basis(vec![(self.magic, Measure(x1))]);
+        let (before, after) = std::mem::take(&mut self.outcomes);
+        let uncompute = self.before.len() + injected.len();
+        let mut lowered = self.fill(injected);
+        lowered.outcomes = before
+            .into_iter()
+            .chain(after.into_iter().map(|i| uncompute + i))
+            .collect();
+        lowered
+    }
+}
+
+/// Lower a Pauli-controlled injection up to the instructions on the magic block,
+/// see [`lower_injection`]
+fn injection_site(
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    basis: Vec<Pauli>,
+    magic: Option<usize>,
+) -> InjectionSite {
     let mut ops: Vec<Operation> = vec![];
     let n = architecture.data_blocks();
     assert!(n > 0);
-    let basis = extend_basis(basis);
+    let basis = extend_basis(basis, architecture);
 
     let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
     let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
     let y1 = TwoBases::new(Pauli::Y, Pauli::I).unwrap();
 
     // Find implementation for each block
-    let block_instrs = basis.chunks_exact(11).enumerate().map(|(block_i, paulis)| {
-        // Only apply a controlled-Pauli if its non-trivial
-        if paulis.iter().all(|p| *p == Pauli::I) {
-            (None, BasisChanger::default())
-        } else {
-            let mut ps = vec![Pauli::I];
-            ps.extend_from_slice(paulis);
-            let p: PauliString = (&ps[..]).try_into().unwrap();
-            let meas_impl = measurement_table.min_data(p);
+    let meas_impls: Vec<_> = basis
+        .chunks_exact(architecture.qubits_per_block())
+        .map(|paulis| {
+            // Only apply a controlled-Pauli if its non-trivial
+            if paulis.iter().all(|p| *p == Pauli::I) {
+                None
+            } else {
+                let mut ps = vec![Pauli::I];
+                ps.extend_from_slice(paulis);
+                let p: PauliString = (&ps[..]).try_into().unwrap();
+                Some(explain::time(Stage::TableLookup, || {
+                    measurement_table.min_data(p)
+                }))
+            }
+        })
+        .collect();
+    assert!(meas_impls.len() <= n);
 
-            let p_pivot = meas_impl.measures().get_pauli(0);
+    // Use the factory that is nearest to the support
+    let support: Vec<_> = meas_impls
+        .iter()
+        .enumerate()
+        .filter(|(_, opt)| opt.is_some())
+        .map(|(block_i, _)| block_i)
+        .collect();
+    let magic = magic.unwrap_or_else(|| match support.is_empty() {
+        false => architecture.nearest_magic_block(&support),
+        true => architecture.magic_block(),
+    });
+    assert!(magic < n);
 
-            let changer = if block_i < n - 1 {
+    let basis_changes = meas_impls
+        .iter()
+        .enumerate()
+        .map(|(block_i, opt)| {
+            let Some(meas_impl) = opt else {
+                return BasisChanger::default();
+            };
+            let p_pivot = meas_impl.measures().get_pauli(0);
+            if block_i != magic {
                 // Y |-> p_pivot.
                 select_basis_change(Pauli::Y, p_pivot)
             } else {
                 // magic module next to factory
                 // X |-> p_pivot
                 select_basis_change(Pauli::X, p_pivot)
-            };
-
-            (Some(measurement_table.min_data(p)), (changer))
-        }
-    });
-    let (meas_impls, basis_changes): (Vec<_>, Vec<_>) = block_instrs.unzip();
+            }
+        })
+        .collect();
     let block_basis = BlockBases(basis_changes);
-    assert!(meas_impls.len() <= n);
 
-    // Apply pre-rotations on all blocks if they are non-trivial
-    for (block_i, meas_impl) in meas_impls
+    // Pre-rotations on all blocks if they are non-trivial
+    let chains: Vec<_> = meas_impls
         .iter()
-        .enumerate()
-        // Skip None values
-        .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
-    {
-        for nat_measure in meas_impl.rotations() {
-            ops.extend(
-                rotation_instructions(nat_measure)
-                    .into_iter()
-                    .map(|op| vec![(block_i, op)]),
-            )
-        }
-    }
+        .map(|opt| opt.as_ref().map(applied_rotations))
+        .collect();
 
     // Prepare pivot qubits
-
+    // The pivot of the magic block must carry the Pauli in X once the GHZ state is prepared,
+    // which its native measurement takes care of, if it has one.
+    // Pivots with a native measurement start in Z, like the GHZ measurements,
+    // such that only the pivot of the magic block rotates the blocks when it is uncomputed, about the joint Pauli.
+    // Starting in X would rotate each block about its own Pauli instead.
     ops.extend(
-        (0..(n - 1))
-            .map(|block_i| vec![(block_i, Measure(x1))])
-            .chain(std::iter::once(vec![(n - 1, Measure(y1))]))
+        (0..n)
+            .map(|block_i| {
+                if matches!(meas_impls.get(block_i), Some(Some(_))) {
+                    vec![(block_i, Measure(z1))]
+                } else {
+                    vec![(block_i, Measure(x1))]
+                }
+            })
             .map(|op| block_basis.change_basis(op)),
     );
 
     // Apply native measurements on nontrivial blocks
     // Do _not_ apply basis change
+    let mut before_outcomes = vec![];
     for (block_i, meas_impl) in meas_impls
         .iter()
         .enumerate()
         .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
     {
+        // The outcome of the native measurement between the automorphisms
+        before_outcomes.push(ops.len() + 1);
         for isa in meas_impl.base_measurement().implementation() {
             ops.push(vec![(block_i, isa)]);
         }
     }
+    let native_instructions = ops.len() - n;
 
-    // Find the range for which we need to prepare a GHZ state
-    let first_nontrivial = meas_impls
+    // Prepare GHZ on the support and the magic block
+    let ghz = explain::time(Stage::Ghz, || ghz_meas(architecture, magic, &support));
+    let mut ghz_blocks: Vec<_> = ghz
         .iter()
-        .position(|support| !support.is_none())
-        .unwrap_or(n - 1);
-    // Prepare GHZ up to and including the magic block
-    let mut middle_ops = ghz_meas(first_nontrivial, n - first_nontrivial);
-
-    // Apply small-angle X(φ) rotation on block n
-    // TODO: Ignore compile-time Clifford corrections
-    let (rots, _cliffords) = small_angle::synthesize_angle_x(angle, accuracy);
-    for rot in rots {
-        let tgate_data = match rot {
-            SingleRotation::Z { dagger } => TGateData::new(Pauli::Z, false, dagger),
-            SingleRotation::X { dagger } => TGateData::new(Pauli::X, false, dagger),
-        }
-        .unwrap();
-        middle_ops.push(vec![(n - 1, TGate(tgate_data))]);
-    }
+        .flatten()
+        .map(|(block_i, _)| *block_i)
+        .chain([magic])
+        .collect();
+    ghz_blocks.sort_unstable();
+    ghz_blocks.dedup();
+    ops.extend(ghz.into_iter().map(|op| block_basis.change_basis(op)));
 
     // Uncompute GHZ state by local measurements on all data blocks (even if they had trivial rotations)
-    for (block_i, opt) in meas_impls.iter().enumerate().take(n - 1) {
+    let mut middle_ops = vec![];
+    for (block_i, opt) in meas_impls
+        .iter()
+        .enumerate()
+        .filter(|(block_i, _)| *block_i != magic)
+    {
         match opt {
             None => middle_ops.push(vec![(block_i, Measure(x1))]),
             Some(_) => middle_ops.push(vec![(block_i, Measure(y1))]),
         }
     }
-    // The last block uncomputes by Z measurement
-    middle_ops.push(vec![(n - 1, Measure(z1))]);
+    // The magic block uncomputes by Y measurement, which leaves a rotation by π/4 about the Pauli,
+    // as a |Y> state would
+    middle_ops.push(vec![(magic, Measure(y1))]);
+
+    // As for a measurement, the basis is the product of the native measurements, the final pivot measurements,
+    // and the initial pivot measurements of trivial blocks inside the GHZ state
+    let mut after_outcomes = vec![];
+    for block_i in ghz_blocks {
+        if !matches!(meas_impls.get(block_i), Some(Some(_))) {
+            before_outcomes.push(block_i);
+        }
+        // The magic block is uncomputed last
+        after_outcomes.push(match block_i.cmp(&magic) {
+            Ordering::Less => block_i,
+            Ordering::Equal => middle_ops.len() - 1,
+            Ordering::Greater => block_i - 1,
+        });
+    }
+    before_outcomes.sort_unstable();
+    after_outcomes.sort_unstable();
 
     // Change basis on middle_ops
-    ops.extend(
-        middle_ops
-            .into_iter()
-            .map(|op| block_basis.change_basis(op)),
-    );
+    let after = middle_ops
+        .into_iter()
+        .map(|op| block_basis.change_basis(op))
+        .collect();
+
+    InjectionSite {
+        chains,
+        before: ops,
+        native_instructions,
+        magic,
+        block_basis,
+        after,
+        outcomes: (before_outcomes, after_outcomes),
+    }
+}
 
-    // Undo rotations on non-trivial blocks
-    for (block_i, meas_impl) in meas_impls
-        .iter()
-        .enumerate()
-        .filter_map(|(i, opt)| opt.as_ref().map(|val| (i, val)))
-    {
-        for nat_measure in meas_impl.rotations().iter().rev() {
-            ops.extend(
-                rotation_instructions(nat_measure)
-                    .into_iter()
-                    .map(|op| vec![(block_i, op)]),
-            )
+/// A Pauli string with the power of i that it is multiplied by
+type PhasedPauli = (Vec<Pauli>, u8);
+
+/// Multiply two Pauli strings, where the shorter string is padded with identities
+fn multiply((lhs, lhs_phase): &PhasedPauli, (rhs, rhs_phase): &PhasedPauli) -> PhasedPauli {
+    let mut phase = lhs_phase + rhs_phase;
+    let product = (0..lhs.len().max(rhs.len()))
+        .map(|i| {
+            let lhs = lhs.get(i).copied().unwrap_or(Pauli::I);
+            let rhs = rhs.get(i).copied().unwrap_or(Pauli::I);
+            match (lhs, rhs) {
+                (Pauli::I, p) | (p, Pauli::I) => p,
+                (p, q) if p == q => Pauli::I,
+                (p, q) => {
+                    // XY = iZ, YZ = iX, and ZX = iY
+                    let cyclic = matches!(
+                        (p, q),
+                        (Pauli::X, Pauli::Y) | (Pauli::Y, Pauli::Z) | (Pauli::Z, Pauli::X)
+                    );
+                    phase += if cyclic { 1 } else { 3 };
+                    let (r, s) = p.anticommuting().unwrap();
+                    if r == q { s } else { r }
+                }
+            }
+        })
+        .collect();
+    (product, phase % 4)
+}
+
+/// The Clifford corrections of small-angle synthesis, commuted past all later operations.
+///
+/// A synthesized rotation about P is followed by a rotation about P by a multiple of π/2, which is not compiled,
+/// e.g., the S gates of [`SGateLowering::Frame`].
+/// The frame tracks the product F of these corrections, and conjugates each later operation about Q to an operation about F†QF,
+/// such that the compiled program is equivalent to the input up to F at the end of the program.
+/// Pauli corrections are left to the Pauli frame.
+#[derive(Debug, Clone, Default)]
+pub struct CliffordFrame {
+    /// The images F†X_iF and F†Z_iF of the qubits that are not fixed by F
+    images: Vec<(PhasedPauli, PhasedPauli)>,
+    s_gates: SGateLowering,
+    /// Number of rotations whose corrections were added to the frame
+    corrections: usize,
+}
+
+impl CliffordFrame {
+    pub fn new(s_gates: SGateLowering) -> Self {
+        Self {
+            s_gates,
+            ..Default::default()
         }
     }
 
-    ops
+    /// Number of rotations whose Clifford corrections were commuted past later operations
+    pub fn corrections(&self) -> usize {
+        self.corrections
+    }
+
+    /// Conjugate `op` by the frame and add the Clifford corrections of its rotations to the frame.
+    /// The operations should be relabeled by [`crate::language::relabel_permutations`] before.
+    pub fn track(&mut self, op: PbcOperation, accuracy: AnglePrecision) -> PbcOperation {
+        let accuracy = op.accuracy(accuracy);
+        let op = self.conjugate_op(op);
+        match &op {
+            PbcOperation::Rotation { basis, angle, .. }
+            | PbcOperation::RotateMeasure { basis, angle, .. } => {
+                self.correct(basis, *angle, accuracy)
+            }
+            PbcOperation::MultiRotation { terms } => {
+                for (basis, angle) in terms {
+                    self.correct(basis, *angle, accuracy);
+                }
+            }
+            _ => {}
+        }
+        op
+    }
+
+    /// Like [`Self::track`], but absorb a [`PbcOperation::Clifford`] into the frame instead of returning it
+    pub fn fold(&mut self, op: PbcOperation, accuracy: AnglePrecision) -> Option<PbcOperation> {
+        match op {
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => {
+                self.absorb(&basis, quarter_turns);
+                None
+            }
+            op => Some(self.track(op, accuracy)),
+        }
+    }
+
+    /// Add the rotation about `basis` by `quarter_turns` times [`S_ANGLE`] to the frame, instead of compiling it.
+    /// Later operations are conjugated by the rotation as well.
+    pub fn absorb(&mut self, basis: &[Pauli], quarter_turns: u8) {
+        let (basis, negated) = self.conjugate(basis);
+        let quarter_turns = quarter_turns % 4;
+        let quarter_turns = if negated {
+            (4 - quarter_turns) % 4
+        } else {
+            quarter_turns
+        };
+        self.rotate(&basis, quarter_turns);
+    }
+
+    /// Conjugate `op` by the frame, without adding the corrections of its rotations to the frame
+    pub fn conjugate_op(&self, op: PbcOperation) -> PbcOperation {
+        match op {
+            PbcOperation::Measurement { basis, flip_result } => {
+                let (basis, negated) = self.conjugate(&basis);
+                PbcOperation::Measurement {
+                    basis,
+                    flip_result: flip_result ^ negated,
+                }
+            }
+            PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy,
+            } => {
+                let (basis, negated) = self.conjugate(&basis);
+                let angle = if negated { -angle } else { angle };
+                PbcOperation::Rotation {
+                    basis,
+                    angle,
+                    accuracy,
+                }
+            }
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy,
+            } => {
+                let (basis, negated) = self.conjugate(&basis);
+                let angle = if negated { -angle } else { angle };
+                PbcOperation::RotateMeasure {
+                    basis,
+                    angle,
+                    flip_result: flip_result ^ negated,
+                    accuracy,
+                }
+            }
+            PbcOperation::MultiRotation { terms } => PbcOperation::MultiRotation {
+                terms: self.conjugate_terms(&terms),
+            },
+            PbcOperation::Ccz { basis } => {
+                let fixed = basis.iter().enumerate().all(|(i, p)| {
+                    let z = sparse(i, Pauli::Z, basis.len()).0;
+                    *p == Pauli::I || self.conjugate(&z) == (z, false)
+                });
+                if fixed {
+                    PbcOperation::Ccz { basis }
+                } else {
+                    PbcOperation::MultiRotation {
+                        terms: self.conjugate_terms(&crate::language::ccz_rotations(&basis)),
+                    }
+                }
+            }
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => {
+                let (basis, negated) = self.conjugate(&basis);
+                PbcOperation::Clifford {
+                    basis,
+                    quarter_turns: if negated {
+                        (4 - quarter_turns % 4) % 4
+                    } else {
+                        quarter_turns % 4
+                    },
+                }
+            }
+            PbcOperation::Permute { .. } => {
+                panic!("Permutations should be relabeled before tracking the Clifford frame")
+            }
+            op @ (PbcOperation::Barrier { .. } | PbcOperation::Delay { .. }) => op,
+        }
+    }
+
+    fn conjugate_terms(
+        &self,
+        terms: &[(Vec<Pauli>, AnglePrecision)],
+    ) -> Vec<(Vec<Pauli>, AnglePrecision)> {
+        terms
+            .iter()
+            .map(|(basis, angle)| {
+                let (basis, negated) = self.conjugate(basis);
+                (basis, if negated { -*angle } else { *angle })
+            })
+            .collect()
+    }
+
+    /// The basis F†QF and whether its sign is negative
+    fn conjugate(&self, basis: &[Pauli]) -> (Vec<Pauli>, bool) {
+        let mut image = (vec![Pauli::I; basis.len()], 0);
+        for (i, p) in basis.iter().enumerate() {
+            let Some((x, z)) = self.images.get(i) else {
+                image.0.resize(image.0.len().max(i + 1), Pauli::I);
+                image.0[i] = *p;
+                continue;
+            };
+            image = match p {
+                Pauli::I => image,
+                Pauli::X => multiply(&image, x),
+                Pauli::Z => multiply(&image, z),
+                // Y = iXZ
+                Pauli::Y => {
+                    let (y, phase) = multiply(x, z);
+                    multiply(&image, &(y, phase + 1))
+                }
+            };
+        }
+        let (mut image, phase) = image;
+        debug_assert!(phase % 2 == 0, "The image of a Pauli should be Hermitian");
+        // Keep the width of the basis unless the image is supported beyond it
+        let used = image
+            .iter()
+            .rposition(|p| *p != Pauli::I)
+            .map_or(0, |i| i + 1);
+        image.truncate(basis.len().max(used));
+        (image, phase == 2)
+    }
+
+    /// Add the Clifford correction of the synthesized rotation about `basis` to the frame
+    fn correct(&mut self, basis: &[Pauli], angle: AnglePrecision, accuracy: AnglePrecision) {
+        if self.s_gates == SGateLowering::Catalysis && angle.abs() == S_ANGLE {
+            return;
+        }
+        let (_rots, cliffords) = small_angle::synthesize_angle(angle, accuracy);
+        let quarter_turns = small_angle::s_power(&cliffords).expect(
+            "The Clifford corrections of a synthesized Z rotation should preserve the Z axis",
+        );
+        if quarter_turns == 0 {
+            return;
+        }
+        self.corrections += 1;
+        self.rotate(basis, quarter_turns);
+    }
+
+    /// Conjugate the images by the rotation about `basis` by `quarter_turns` times [`S_ANGLE`]
+    fn rotate(&mut self, basis: &[Pauli], quarter_turns: u8) {
+        if quarter_turns == 0 {
+            return;
+        }
+        // Conjugating by the rotation R = exp(-ikπ/4 P) maps an anticommuting Q to i^k P^k Q,
+        // which is i^k PQ for odd k
+        let rotation = (basis.to_vec(), quarter_turns);
+        let conjugate = |image: &mut PhasedPauli| {
+            if crate::language::commutes(&image.0, basis) {
+                return;
+            }
+            *image = if quarter_turns == 2 {
+                (std::mem::take(&mut image.0), (image.1 + 2) % 4)
+            } else {
+                multiply(&rotation, image)
+            };
+        };
+        while self.images.len() < basis.len() {
+            let i = self.images.len();
+            self.images.push((
+                sparse(i, Pauli::X, basis.len()),
+                sparse(i, Pauli::Z, basis.len()),
+            ));
+        }
+        for (x, z) in self.images.iter_mut() {
+            conjugate(x);
+            conjugate(z);
+        }
+    }
+}
+
+/// A Pauli on qubit `i` of `qubits` qubits, without a phase
+fn sparse(i: usize, p: Pauli, qubits: usize) -> PhasedPauli {
+    let mut basis = vec![Pauli::I; qubits];
+    basis[i] = p;
+    (basis, 0)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::sync::LazyLock;
-
-    use crate::operation::Operations;
+    use crate::{
+        architecture::{PathArchitecture, RingArchitecture},
+        language::PbcOperation,
+        operation::Operations,
+        test::GROSS_TABLE,
+    };
 
     use super::*;
 
     use bicycle_common::Pauli::{I, X, Y, Z};
 
-    use bicycle_cliffords::{
-        GROSS_MEASUREMENT, MeasurementTableBuilder, native_measurement::NativeMeasurement,
-    };
     use rand::{
+        SeedableRng,
         distr::{Distribution, StandardUniform},
+        rngs::StdRng,
         seq::IndexedRandom,
     };
 
     const ACCURACY: AnglePrecision = AnglePrecision::lit("1e-10");
 
-    static GROSS_TABLE: LazyLock<CompleteMeasurementTable> = LazyLock::new(|| {
-        let mut builder = MeasurementTableBuilder::new(NativeMeasurement::all(), GROSS_MEASUREMENT);
-        builder.build();
-        builder.complete().expect("Table building should succeed")
-    });
-    // static GROSS_TABLE: LazyLock<CompleteMeasurementTable> = LazyLock::new(|| {
-    //     let table_path = Path::new("../../data/table_gross");
-    //     crate::deserialize_table(table_path).expect("Should be able to deserialize table")
-    // });
-
     /// Convert a native measurement to a list of Operations
     fn native_instructions(
         block: usize,
@@ -389,26 +1254,11 @@ mod tests {
 
     /// Find a random minimal implementation (as given by the measurement table) of a native measurement.
     fn random_min_native_measurement(
-        measurement_table: &CompleteMeasurementTable,
+        measurement_table: &dyn MeasurementTable,
     ) -> NativeMeasurementImpl {
         let mut native_measurements = vec![];
         // Generate 4^11 Paulis
-        for i in 1..4_usize.pow(11) {
-            let mut bits = i;
-            let mut ps: Vec<Pauli> = vec![Pauli::I];
-            for _ in 0..11 {
-                let p_bits = bits & 3;
-                bits >>= 2;
-                ps.push(
-                    p_bits
-                        .try_into()
-                        .expect("Should be able to convert 2 bits to Pauli"),
-                );
-            }
-
-            let pauli_arr: [Pauli; 12] = ps.try_into().unwrap();
-            let p: PauliString = (&pauli_arr).into();
-
+        for p in PauliString::all_with_support(PauliString::DATA_QUBITS).skip(1) {
             let meas_impl = measurement_table.min_data(p);
             if meas_impl.rotations().is_empty() {
                 native_measurements.push(*meas_impl.base_measurement());
@@ -440,28 +1290,405 @@ mod tests {
         StandardUniform
             .sample_iter(rand::rng())
             .map(|p: PauliString| p.zero_pivot())
-            .filter(|p| p.0 != 0)
+            .filter(|p| p.bits() != 0)
+    }
+
+    #[test]
+    fn rotations_are_applied_in_order() {
+        let architecture = PathArchitecture::new(1);
+        let mut chains = 0;
+        for p in random_nontrivial_paulistrings().take(1000) {
+            let meas_impl = GROSS_TABLE.min_data(p);
+            let basis: Vec<_> = (1..12).map(|qubit| p.get_pauli(qubit)).collect();
+            let lowered = lower_measurement(&architecture, &*GROSS_TABLE, basis);
+            let applied = lowered.chains[0].as_ref().unwrap();
+            // Measuring after a rotation measures the Pauli conjugated by it,
+            // so the rotation that is applied last conjugates the base measurement first
+            let measured = applied
+                .iter()
+                .rev()
+                .fold(meas_impl.base_measurement().measures(), |measured, rot| {
+                    measured.conjugate_with(rot.measures().zero_pivot())
+                });
+            assert_eq!(meas_impl.measures(), measured, "{p}");
+            if applied.len() > 1 {
+                chains += 1;
+            }
+        }
+        assert!(chains > 0);
+    }
+
+    #[test]
+    fn magic_pivot_without_support() {
+        // The rotation only acts on block 0, next to the factory at block 1
+        let architecture = PathArchitecture::new(2);
+        let mut basis = vec![I; 22];
+        basis[0] = X;
+        let site = injection_site(&architecture, &*GROSS_TABLE, basis, None);
+        assert_eq!(1, site.magic);
+        let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
+        let magic_preps: Vec<_> = site
+            .before
+            .iter()
+            .flatten()
+            .filter(|(block, isa)| *block == 1 && matches!(isa, Measure(_)))
+            .collect();
+        assert_eq!(Some(&&(1, Measure(x1))), magic_preps.first());
+    }
+
+    #[test]
+    fn clifford_frame_conjugates_later_operations() {
+        let measure = |basis: Vec<Pauli>| PbcOperation::Measurement {
+            basis,
+            flip_result: false,
+        };
+        let mut frame = CliffordFrame::new(SGateLowering::Frame);
+        for op in PbcOperation::s(0, 1) {
+            frame.track(op, ACCURACY);
+        }
+        // Measuring X after S measures S†XS = -Y
+        let expected = PbcOperation::Measurement {
+            basis: vec![Y],
+            flip_result: true,
+        };
+        assert_eq!(expected, frame.track(measure(vec![X]), ACCURACY));
+        assert_eq!(1, frame.corrections());
+
+        let mut frame = CliffordFrame::new(SGateLowering::Frame);
+        for op in PbcOperation::h(0, 2)
+            .into_iter()
+            .chain(PbcOperation::cx(0, 1, 2))
+        {
+            frame.track(op, ACCURACY);
+        }
+        // Conjugating by CX and then by H
+        assert_eq!(
+            measure(vec![X, Z]),
+            frame.track(measure(vec![I, Z]), ACCURACY)
+        );
+        assert_eq!(
+            measure(vec![Z, X]),
+            frame.track(measure(vec![X, I]), ACCURACY)
+        );
+        let ccz = frame.track(PbcOperation::ccz([0, 1, 2], 3), ACCURACY);
+        let PbcOperation::MultiRotation { terms } = ccz else {
+            panic!("A CCZ on a rotated qubit should be lowered to rotations, not {ccz}");
+        };
+        assert_eq!(7, terms.len());
+
+        // A CCZ is kept if its controls keep their Z bases
+        let mut frame = CliffordFrame::new(SGateLowering::Frame);
+        let rotation = |basis: Vec<Pauli>, angle| PbcOperation::Rotation {
+            basis,
+            angle,
+            accuracy: None,
+        };
+        frame.track(
+            rotation(vec![Z, X, I, I], small_angle::T_ANGLE * 4),
+            ACCURACY,
+        );
+        let ccz = PbcOperation::ccz([0, 2, 3], 4);
+        assert_eq!(ccz, frame.track(ccz.clone(), ACCURACY));
+        // A rotation by π flips the sign of anticommuting Paulis
+        assert_eq!(
+            rotation(vec![Z, Z, I, I], -small_angle::T_ANGLE),
+            frame.track(rotation(vec![Z, Z, I, I], small_angle::T_ANGLE), ACCURACY)
+        );
+
+        let mut catalysis = CliffordFrame::new(SGateLowering::Catalysis);
+        for op in PbcOperation::s(0, 1) {
+            catalysis.track(op, ACCURACY);
+        }
+        assert_eq!(
+            measure(vec![X]),
+            catalysis.track(measure(vec![X]), ACCURACY)
+        );
+    }
+
+    /// Amplitudes of a state of a few qubits, as real and imaginary parts
+    type State = Vec<(f64, f64)>;
+
+    /// The index that `basis` maps `index` to, with the power of i of the amplitude
+    fn apply_pauli(basis: &[Pauli], index: usize) -> (usize, u32) {
+        let mut phase = 0;
+        let mut image = index;
+        for (qubit, p) in basis.iter().enumerate() {
+            let bit = index >> qubit & 1 == 1;
+            // Y = iXZ
+            phase += match (p, bit) {
+                (I | X, _) | (Z, false) => 0,
+                (Z, true) => 2,
+                (Y, false) => 1,
+                (Y, true) => 3,
+            };
+            if matches!(p, X | Y) {
+                image ^= 1 << qubit;
+            }
+        }
+        (image, phase % 4)
+    }
+
+    /// Apply exp(-iθP)
+    fn rotate_state(state: &mut State, basis: &[Pauli], theta: f64) {
+        let (cos, sin) = (theta.cos(), theta.sin());
+        let before = state.clone();
+        for (index, &(re, im)) in before.iter().enumerate() {
+            let (image, phase) = apply_pauli(basis, index);
+            // -i sin θ i^phase times the amplitude
+            let (re, im) = match phase {
+                0 => (im, -re),
+                1 => (re, im),
+                2 => (-im, re),
+                _ => (-re, -im),
+            };
+            state[image].0 += sin * re;
+            state[image].1 += sin * im;
+        }
+        for (amplitude, (re, im)) in state.iter_mut().zip(before) {
+            amplitude.0 += (cos - 1.0) * re;
+            amplitude.1 += (cos - 1.0) * im;
+        }
+    }
+
+    /// The expectation value of a Pauli
+    fn expectation(state: &State, basis: &[Pauli]) -> f64 {
+        state
+            .iter()
+            .enumerate()
+            .map(|(index, &(re, im))| {
+                let (image, phase) = apply_pauli(basis, index);
+                let (re, im) = match phase {
+                    0 => (re, im),
+                    1 => (-im, re),
+                    2 => (-re, -im),
+                    _ => (im, -re),
+                };
+                state[image].0 * re + state[image].1 * im
+            })
+            .sum()
+    }
+
+    #[test]
+    fn clifford_frame_matches_input_on_several_blocks() {
+        // The rotations act on two qubits of each of two blocks,
+        // which are simulated in a Bell pair with a reference qubit each
+        let qubits = [0, 1, 11, 12];
+        let local = |basis: &[Pauli]| -> Vec<Pauli> {
+            assert!(
+                basis
+                    .iter()
+                    .enumerate()
+                    .all(|(i, p)| *p == I || qubits.contains(&i))
+            );
+            qubits
+                .iter()
+                .map(|&i| basis.get(i).copied().unwrap_or(I))
+                .collect()
+        };
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut random_basis = || loop {
+            let mut basis = vec![I; 22];
+            for &i in &qubits {
+                basis[i] = *[I, X, Y, Z].choose(&mut rng).unwrap();
+            }
+            if local(&basis).iter().any(|p| *p != I) {
+                return basis;
+            }
+        };
+        let t = small_angle::T_ANGLE;
+        // Multiples of the T angle are synthesized as at most one T gate and a correction of 1, 2, or 3 quarter turns
+        let mut angles = vec![3 * t, -3 * t, 5 * t, 7 * t, t, small_angle::S_ANGLE];
+        if cfg!(feature = "rsgridsynth") {
+            angles.push(AnglePrecision::lit("0.1"));
+        }
+        let mut ops = vec![];
+        for (i, angle) in angles.into_iter().enumerate() {
+            ops.push(PbcOperation::Rotation {
+                basis: random_basis(),
+                angle,
+                accuracy: None,
+            });
+            ops.push(PbcOperation::Clifford {
+                basis: random_basis(),
+                quarter_turns: [1, 3][i % 2],
+            });
+        }
+        assert!(ops.iter().any(|op| match op {
+            PbcOperation::Rotation { basis, .. } =>
+                basis[..11].iter().any(|p| *p != I) && basis[11..].iter().any(|p| *p != I),
+            _ => false,
+        }));
+
+        let quarter = std::f64::consts::FRAC_PI_4;
+        // Each qubit starts in a Bell pair with its reference, such that the states hold the whole unitaries
+        let n = qubits.len();
+        let bell = |state: &mut State| {
+            for qubit in 0..n {
+                let single = |p: Pauli, at: usize| {
+                    let mut basis = vec![I; 2 * n];
+                    basis[at] = p;
+                    basis
+                };
+                // exp(-iπ/4 Y) prepares |+>, and CNOT = exp(iπ/4 (I - Z)(I - X)) entangles it
+                rotate_state(state, &single(Y, qubit), quarter);
+                rotate_state(state, &single(Z, qubit), quarter);
+                rotate_state(state, &single(X, n + qubit), quarter);
+                let mut zx = single(Z, qubit);
+                zx[n + qubit] = X;
+                rotate_state(state, &zx, -quarter);
+            }
+        };
+        let mut input: State = vec![(0.0, 0.0); 1 << (2 * n)];
+        input[0] = (1.0, 0.0);
+        bell(&mut input);
+        let mut compiled = input.clone();
+
+        // The input applies the exact rotations, where the angle of exp(-iθ/2 P) is θ
+        let half = |angle: AnglePrecision| angle.to_num::<f64>() / 2.0;
+        for op in &ops {
+            let (basis, theta) = match op {
+                PbcOperation::Rotation { basis, angle, .. } => (basis, half(*angle)),
+                PbcOperation::Clifford {
+                    basis,
+                    quarter_turns,
+                } => (basis, f64::from(*quarter_turns) * quarter),
+                _ => unreachable!(),
+            };
+            rotate_state(&mut input, &local(basis), theta);
+        }
+        // The compiled rotations drop the Clifford corrections of their synthesis, and the Cliffords are not compiled
+        let mut frame = CliffordFrame::new(SGateLowering::Frame);
+        for op in &ops {
+            let Some(op) = frame.fold(op.clone(), ACCURACY) else {
+                continue;
+            };
+            let PbcOperation::Rotation { basis, angle, .. } = op else {
+                panic!("A rotation should stay a rotation, not {op}");
+            };
+            let (_, cliffords) = small_angle::synthesize_angle(angle, ACCURACY);
+            let quarter_turns = small_angle::s_power(&cliffords).unwrap();
+            let theta = half(angle) - f64::from(quarter_turns) * quarter;
+            rotate_state(&mut compiled, &local(&basis), theta);
+        }
+        assert_ne!(0, frame.corrections());
+
+        // The input is the compiled program followed by the frame F,
+        // so measuring a Pauli Q of the input measures F†QF of the compiled program
+        let paulis = (0..1usize << (2 * n)).map(|index| -> Vec<Pauli> {
+            (0..n)
+                .map(|qubit| [I, X, Y, Z][index >> (2 * qubit) & 3])
+                .collect()
+        });
+        let mut differs = false;
+        for data in paulis {
+            let mut program = vec![I; 22];
+            for (&i, p) in qubits.iter().zip(&data) {
+                program[i] = *p;
+            }
+            let (image, negated) = frame.conjugate(&program);
+            let sign = if negated { -1.0 } else { 1.0 };
+            for references in 0..1usize << (2 * n) {
+                let references: Vec<_> = (0..n)
+                    .map(|qubit| [I, X, Y, Z][references >> (2 * qubit) & 3])
+                    .collect();
+                let expected = expectation(&input, &[&data[..], &references].concat());
+                let actual = expectation(&compiled, &[&local(&image)[..], &references].concat());
+                assert!(
+                    (expected - sign * actual).abs() < 1e-9,
+                    "{data:?} {references:?}: {expected} instead of {}",
+                    sign * actual
+                );
+                differs |= (expected - expectation(&compiled, &[&data[..], &references].concat()))
+                    .abs()
+                    > 1e-9;
+            }
+        }
+        // Without the frame at the end, the compiled program is not the input
+        assert!(differs);
+    }
+
+    #[test]
+    fn explicit_cliffords_are_folded() {
+        let op: PbcOperation =
+            serde_json::from_str(r#"{"Clifford":{"basis":["Z"],"quarter_turns":1}}"#).unwrap();
+        let mut frame = CliffordFrame::new(SGateLowering::Frame);
+        assert_eq!(None, frame.fold(op, ACCURACY));
+        assert_eq!(0, frame.corrections());
+        // Measuring X after S measures S†XS = -Y
+        let measurement = PbcOperation::Measurement {
+            basis: vec![X],
+            flip_result: false,
+        };
+        assert_eq!(
+            Some(PbcOperation::Measurement {
+                basis: vec![Y],
+                flip_result: true,
+            }),
+            frame.fold(measurement, ACCURACY)
+        );
+        // Later Cliffords are conjugated by the frame before they are absorbed
+        assert_eq!(
+            None,
+            frame.fold(
+                PbcOperation::Clifford {
+                    basis: vec![X],
+                    quarter_turns: 3,
+                },
+                ACCURACY
+            )
+        );
+
+        // Without a frame, a Clifford is compiled like a rotation by the S angle
+        let arch = PathArchitecture::for_qubits(11).with_s_gates(SGateLowering::Catalysis);
+        let clifford = |quarter_turns| PbcOperation::Clifford {
+            basis: vec![Z; 11],
+            quarter_turns,
+        };
+        let rotation = PbcOperation::Rotation {
+            basis: vec![Z; 11],
+            angle: -S_ANGLE,
+            accuracy: None,
+        };
+        assert_eq!(
+            rotation.compile(&arch, &*GROSS_TABLE, ACCURACY),
+            clifford(3).compile(&arch, &*GROSS_TABLE, ACCURACY)
+        );
+        assert!(
+            clifford(2)
+                .compile(&arch, &*GROSS_TABLE, ACCURACY)
+                .is_empty()
+        );
     }
 
     #[test]
     fn test_extend_basis() {
+        let arch = PathArchitecture::new(1);
         let mut basis = vec![Y];
-        basis = extend_basis(basis);
+        basis = extend_basis(basis, &arch);
         let expected = vec![Y, I, I, I, I, I, I, I, I, I, I];
         assert_eq!(expected, basis);
 
         let mut basis = vec![I, I, I, I, I, Y];
-        basis = extend_basis(basis);
+        basis = extend_basis(basis, &arch);
         let expected = vec![I, I, I, I, I, Y, I, I, I, I, I];
         assert_eq!(expected, basis);
     }
 
+    #[test]
+    #[should_panic(expected = "supports blocks of 11 qubits")]
+    fn reject_other_block_sizes() {
+        let arch = PathArchitecture::for_qubits_per_block(24, 12);
+        assert_eq!(2, arch.data_blocks());
+        assert_eq!(24, arch.qubits());
+        extend_basis(vec![Y], &arch);
+    }
+
     #[test]
     fn test_ghz_meas() {
         let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
-        let arch = PathArchitecture { data_blocks: 2 };
+        let arch = PathArchitecture::new(2);
 
-        let ops = ghz_meas(0, arch.data_blocks());
+        let ops = ghz_meas(&arch, 0, &[arch.data_blocks() - 1]);
 
         // One joint operation
         let joint_ops: Vec<_> = ops.iter().filter(|op| op.len() == 2).collect();
@@ -471,6 +1698,56 @@ mod tests {
         assert_eq!(&zz_meas, joint_ops[0]);
     }
 
+    #[test]
+    fn timing_operations() {
+        let arch = PathArchitecture::new(3).with_storage_blocks(1);
+        assert_eq!(
+            vec![vec![(0, Delay(0)), (2, Delay(0))]],
+            compile_barrier(&arch, Some(&[0, 2]))
+        );
+        // A barrier without blocks also synchronizes the storage block
+        assert_eq!(
+            vec![(0..4).map(|block| (block, Delay(0))).collect::<Vec<_>>()],
+            compile_barrier(&arch, None)
+        );
+        assert!(compile_barrier(&arch, Some(&[])).is_empty());
+        let delays = compile_delay(&arch, 100);
+        assert_eq!(4, delays.len());
+        assert!(
+            delays
+                .iter()
+                .all(|op| op.len() == 1 && op[0].1 == Delay(100))
+        );
+    }
+
+    #[test]
+    fn paired_t_injections() {
+        let tgates: Vec<_> = [(Z, false), (X, true), (Z, false)]
+            .into_iter()
+            .map(|(p, dagger)| TGateData::new(p, false, dagger).unwrap())
+            .collect();
+        assert_eq!(
+            tgates
+                .iter()
+                .map(|tgate| vec![(1, TGate(*tgate))])
+                .collect::<Vec<_>>(),
+            inject_t_gates(TInjection::Single, 1, &tgates)
+        );
+        assert_eq!(
+            vec![
+                vec![(1, TGatePair(TGatePairData::new(tgates[0], tgates[1])))],
+                vec![(1, TGate(tgates[2]))],
+            ],
+            inject_t_gates(TInjection::Pair, 1, &tgates)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "needs an architecture of more than 2 blocks")]
+    fn barrier_outside_architecture() {
+        compile_barrier(&PathArchitecture::new(2), Some(&[1, 2]));
+    }
+
     #[test]
     fn basis_change() {
         for p_expected in [X, Y, Z] {
@@ -494,7 +1771,7 @@ mod tests {
 
         /// State prep for nontrivial measurement
         fn prep() -> impl Iterator<Item = Operation> {
-            std::iter::repeat(Measure(TwoBases::new(Pauli::X, Pauli::I).unwrap()))
+            std::iter::repeat(Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap()))
                 .enumerate()
                 .map(|e| vec![e])
         }
@@ -508,11 +1785,11 @@ mod tests {
 
         #[test]
         fn compile_native_joint_measurement() -> Result<(), Box<dyn Error>> {
-            let arch = PathArchitecture { data_blocks: 2 };
-            let meas0 = random_min_native_measurement(&GROSS_TABLE);
+            let arch = PathArchitecture::new(2);
+            let meas0 = random_min_native_measurement(&*GROSS_TABLE);
             let basis0: [Pauli; 12] = meas0.measures().into();
             let basis_change0 = select_basis_change(Y, basis0[0]);
-            let meas1 = random_min_native_measurement(&GROSS_TABLE);
+            let meas1 = random_min_native_measurement(&*GROSS_TABLE);
             let basis1: [Pauli; 12] = meas1.measures().into();
             let basis_change1 = select_basis_change(Y, basis1[0]);
             let block_bases = BlockBases(vec![basis_change0, basis_change1]);
@@ -523,7 +1800,7 @@ mod tests {
                 .chain(basis1[1..].iter())
                 .copied()
                 .collect();
-            let ops = Operations(compile_measurement(&arch, &GROSS_TABLE, basis));
+            let ops = Operations(compile_measurement(&arch, &*GROSS_TABLE, basis));
             println!("Compiled: {ops}");
 
             // One joint operation
@@ -537,7 +1814,7 @@ mod tests {
             expected.append(&mut native_instructions(0, &meas0));
             expected.append(&mut native_instructions(1, &meas1));
             expected.extend(
-                ghz_meas(0, arch.data_blocks())
+                ghz_meas(&arch, 0, &[arch.data_blocks() - 1])
                     .into_iter()
                     .map(|o| block_bases.change_basis(o)),
             );
@@ -559,9 +1836,7 @@ mod tests {
         #[test]
         fn compile_multiblock() -> Result<(), Box<dyn Error>> {
             for blocks in 2..10 {
-                let arch = PathArchitecture {
-                    data_blocks: blocks,
-                };
+                let arch = PathArchitecture::new(blocks);
                 // Requires 1 rotation
                 let ps: Vec<_> = random_nontrivial_paulistrings().take(blocks).collect();
                 let implementations: Vec<_> = ps.iter().map(|p| GROSS_TABLE.min_data(*p)).collect();
@@ -580,14 +1855,14 @@ mod tests {
                     .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
                     .collect();
 
-                let ops = Operations(compile_measurement(&arch, &GROSS_TABLE, basis));
+                let ops = Operations(compile_measurement(&arch, &*GROSS_TABLE, basis));
                 println!("Compiled: {ops}");
 
                 let mut expected: Vec<Operation> = vec![];
 
                 // pre-rotations
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    for rot in meas_impl.rotations() {
+                    for rot in meas_impl.rotations().iter().rev() {
                         let operations = rotation_instructions(rot)
                             .into_iter()
                             .map(|instr| vec![(block_i, instr)]);
@@ -599,19 +1874,17 @@ mod tests {
 
                 // measurements
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    expected.extend(
-                        native_instructions(block_i, meas_impl.base_measurement()).into_iter(),
-                    );
+                    expected.extend(native_instructions(block_i, meas_impl.base_measurement()));
                 }
                 expected.extend(
-                    ghz_meas(0, arch.data_blocks())
+                    ghz_meas(&arch, 0, &[arch.data_blocks() - 1])
                         .into_iter()
                         .map(|op| block_basis.change_basis(op)),
                 );
                 expected.extend(unprep().take(blocks).map(|op| block_basis.change_basis(op)));
                 // post-rotations
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    for rot in meas_impl.rotations().iter().rev() {
+                    for rot in meas_impl.rotations() {
                         let operations = rotation_instructions(rot)
                             .into_iter()
                             .map(|instr| vec![(block_i, instr)]);
@@ -630,6 +1903,27 @@ mod tests {
 
             Ok(())
         }
+
+        #[test]
+        fn observe_measurement_outcomes() {
+            let arch = PathArchitecture::new(3);
+            let data = || {
+                let paulis: [Pauli; 12] = random_min_native_measurement(&*GROSS_TABLE)
+                    .measures()
+                    .into();
+                paulis[1..].to_vec()
+            };
+            // Support on the outer blocks, so the middle block is part of the GHZ state
+            let basis = [data(), vec![I; 11], data()].concat();
+            let (ops, outcomes) = observe_measurement(&arch, &*GROSS_TABLE, basis.clone());
+            assert_eq!(compile_measurement(&arch, &*GROSS_TABLE, basis), ops);
+
+            // Two native measurements, three final pivot measurements, and the initial pivot measurement of the middle block
+            assert_eq!(6, outcomes.len());
+            for i in outcomes {
+                assert!(matches!(ops[i][..], [(_, Measure(_))]), "{:?}", ops[i]);
+            }
+        }
     }
 
     mod rotation {
@@ -638,28 +1932,338 @@ mod tests {
 
         use super::*;
 
+        #[test]
+        fn compile_centered_magic_block() {
+            let arch = PathArchitecture::new(3).centered();
+            assert_eq!(1, arch.magic_block());
+            // Only the last block has support
+            let mut basis = vec![Pauli::I; 22];
+            basis.extend(
+                <[Pauli; 12]>::from(random_nontrivial_paulistrings().next().unwrap())
+                    .into_iter()
+                    .skip(1),
+            );
+
+            let ops = compile_rotation(&arch, &*GROSS_TABLE, basis, small_angle::T_ANGLE, ACCURACY);
+
+            let t_blocks: Vec<_> = ops
+                .iter()
+                .flatten()
+                .filter(|(_, isa)| matches!(isa, TGate(_)))
+                .map(|(block_i, _)| *block_i)
+                .collect();
+            assert_eq!(vec![1], t_blocks);
+            // The GHZ state only spans the magic block and the last block
+            let joint_ops: Vec<_> = ops.iter().filter(|op| op.len() == 2).collect();
+            assert_eq!(1, joint_ops.len());
+            assert_eq!(1, joint_ops[0][0].0);
+            assert_eq!(2, joint_ops[0][1].0);
+        }
+
+        #[test]
+        fn compile_two_sided_nearest_factory() {
+            let arch = PathArchitecture::new(3).two_sided();
+            assert_eq!(vec![2, 0], arch.magic_blocks().collect::<Vec<_>>());
+            // Only the first block has support
+            let mut basis: Vec<Pauli> =
+                <[Pauli; 12]>::from(random_nontrivial_paulistrings().next().unwrap())
+                    .into_iter()
+                    .skip(1)
+                    .collect();
+            basis.extend([Pauli::I; 22]);
+
+            let ops = compile_rotation(&arch, &*GROSS_TABLE, basis, small_angle::T_ANGLE, ACCURACY);
+
+            // The first factory is used and no GHZ state is needed
+            assert!(
+                ops.iter()
+                    .flatten()
+                    .filter(|(_, isa)| matches!(isa, TGate(_)))
+                    .all(|(block_i, _)| *block_i == 0)
+            );
+            assert!(ops.iter().all(|op| op.len() == 1));
+        }
+
+        #[test]
+        fn compile_many_factories_in_parallel() {
+            let arch = PathArchitecture::new(5).with_magic_blocks(&[4, 0, 2]);
+            let mut paulis = random_nontrivial_paulistrings().map(|p| {
+                <[Pauli; 12]>::from(p)
+                    .into_iter()
+                    .skip(1)
+                    .collect::<Vec<_>>()
+            });
+            // Rotations on the first, center, and last blocks each use their own factory
+            for block in [0, 2, 4] {
+                let mut basis = vec![Pauli::I; 11 * block];
+                basis.extend(paulis.next().unwrap());
+                basis.resize(55, Pauli::I);
+
+                let ops =
+                    compile_rotation(&arch, &*GROSS_TABLE, basis, small_angle::T_ANGLE, ACCURACY);
+
+                assert!(
+                    ops.iter()
+                        .flatten()
+                        .filter(|(_, isa)| matches!(isa, TGate(_)))
+                        .all(|(block_i, _)| *block_i == block)
+                );
+                assert!(ops.iter().all(|op| op.len() == 1));
+            }
+        }
+
+        #[test]
+        fn compile_ring_across_ends() {
+            let ring = RingArchitecture::new(PathArchitecture::new(4));
+            // The first and last blocks have support
+            let mut paulis = random_nontrivial_paulistrings().map(|p| {
+                <[Pauli; 12]>::from(p)
+                    .into_iter()
+                    .skip(1)
+                    .collect::<Vec<_>>()
+            });
+            let mut basis = paulis.next().unwrap();
+            basis.extend([Pauli::I; 22]);
+            basis.extend(paulis.next().unwrap());
+
+            let ops = compile_measurement(&ring, &*GROSS_TABLE, basis);
+
+            // A single joint measurement connects the ends of the ring
+            let joint_ops: Vec<_> = ops.iter().filter(|op| op.len() == 2).collect();
+            assert_eq!(1, joint_ops.len());
+            assert_eq!(
+                vec![0, 3],
+                joint_ops[0]
+                    .iter()
+                    .map(|(block_i, _)| *block_i)
+                    .collect::<Vec<_>>()
+            );
+            assert!(joint_ops.iter().all(|op| ring.validate_operation(op)));
+        }
+
+        #[test]
+        fn cached_bases_skip_basis_changes() {
+            let arch = PathArchitecture::new(3);
+            let basis: Vec<Pauli> = random_nontrivial_paulistrings()
+                .take(3)
+                .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
+                .collect();
+
+            let separate = compile_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                basis.clone(),
+                small_angle::T_ANGLE,
+                ACCURACY,
+            );
+            let mut state = BlockBasisState::new(arch.data_blocks());
+            let mut cached = vec![];
+            for _ in 0..2 {
+                cached.extend(state.apply(lower_rotation(
+                    &arch,
+                    &*GROSS_TABLE,
+                    basis.clone(),
+                    small_angle::T_ANGLE,
+                    ACCURACY,
+                )));
+            }
+            assert!(!state.is_clear());
+            let post = state.finish();
+            assert!(state.is_clear());
+            // The second rotation needs no basis changes at all
+            assert_eq!(
+                2 * separate.len() - 2 * post.len(),
+                cached.len() + post.len()
+            );
+        }
+
+        #[test]
+        fn compile_fused_rotation_measurement() {
+            let arch = PathArchitecture::new(3);
+            let basis: Vec<Pauli> = random_nontrivial_paulistrings()
+                .take(3)
+                .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
+                .collect();
+
+            let mut separate = compile_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                basis.clone(),
+                small_angle::T_ANGLE,
+                ACCURACY,
+            );
+            separate.extend(compile_measurement(&arch, &*GROSS_TABLE, basis.clone()));
+            let (fused, saved, outcomes) = observe_rotation_measurement(
+                &arch,
+                &*GROSS_TABLE,
+                basis.clone(),
+                small_angle::T_ANGLE,
+                ACCURACY,
+            );
+            assert_eq!(separate.len() - saved, fused.len());
+
+            // The fused lowering is the rotation, where the pivot of the magic block is measured in X instead of Z
+            let rotation = compile_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                basis.clone(),
+                small_angle::T_ANGLE,
+                ACCURACY,
+            );
+            assert_eq!(rotation.len(), fused.len());
+            let differs: Vec<_> = (0..fused.len())
+                .filter(|i| rotation[*i] != fused[*i])
+                .collect();
+            let [i] = differs[..] else {
+                panic!("The fused lowering differs at {differs:?}");
+            };
+            let [(magic, Measure(_))] = rotation[i][..] else {
+                panic!("The fused lowering differs at {:?}", rotation[i]);
+            };
+            assert_eq!(arch.magic_block(), magic);
+
+            // As many outcomes as for the measurement on its own, which include the measurement of the magic block
+            let (_, measured) = observe_measurement(&arch, &*GROSS_TABLE, basis);
+            assert_eq!(measured.len(), outcomes.len());
+            assert!(outcomes.contains(&i));
+            for i in outcomes {
+                assert!(matches!(fused[i][..], [(_, Measure(_))]), "{:?}", fused[i]);
+            }
+        }
+
+        #[test]
+        fn compile_multi_rotation_single_term() {
+            let arch = PathArchitecture::new(2);
+            let basis: Vec<Pauli> = random_nontrivial_paulistrings()
+                .take(2)
+                .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
+                .collect();
+
+            let single = compile_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                basis.clone(),
+                small_angle::T_ANGLE,
+                ACCURACY,
+            );
+            let multi = compile_multi_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                &[(basis, small_angle::T_ANGLE)],
+                ACCURACY,
+            );
+            assert_eq!(single, multi);
+        }
+
+        #[test]
+        fn compile_multi_rotation_shares_basis_rotations() {
+            let arch = PathArchitecture::new(2);
+            let basis: Vec<Pauli> = random_nontrivial_paulistrings()
+                .take(2)
+                .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
+                .collect();
+            let terms = [
+                (basis.clone(), small_angle::T_ANGLE),
+                (basis.clone(), -small_angle::T_ANGLE),
+            ];
+
+            let separate: Vec<Operation> = terms
+                .iter()
+                .flat_map(|(basis, angle)| {
+                    compile_rotation(&arch, &*GROSS_TABLE, basis.clone(), *angle, ACCURACY)
+                })
+                .collect();
+            let joint = compile_multi_rotation(&arch, &*GROSS_TABLE, &terms, ACCURACY);
+
+            // The post-rotations of the first term and pre-rotations of the second term cancel
+            let nr_rotations: usize = basis
+                .chunks(11)
+                .map(|paulis| {
+                    let mut ps = vec![Pauli::I];
+                    ps.extend_from_slice(paulis);
+                    let p: PauliString = (&ps[..]).try_into().unwrap();
+                    GROSS_TABLE.min_data(p).rotations().len()
+                })
+                .sum();
+            assert_eq!(separate.len() - 2 * 5 * nr_rotations, joint.len());
+        }
+
+        #[test]
+        fn compile_s_gate_by_catalysis() {
+            let basis: Vec<Pauli> = random_nontrivial_paulistrings()
+                .take(2)
+                .flat_map(|p| <[Pauli; 12]>::from(p).into_iter().skip(1))
+                .collect();
+            let arch = PathArchitecture::new(2);
+            let frame = compile_rotation(&arch, &*GROSS_TABLE, basis.clone(), -S_ANGLE, ACCURACY);
+            let arch = arch.with_s_gates(SGateLowering::Catalysis);
+            let catalyzed = compile_rotation(&arch, &*GROSS_TABLE, basis, -S_ANGLE, ACCURACY);
+
+            // The S gate replaces nothing in the frame lowering
+            assert_eq!(frame.len() + 1, catalyzed.len());
+            let s_gates: Vec<_> = catalyzed
+                .iter()
+                .flatten()
+                .filter(|(_, isa)| matches!(isa, SGate(_)))
+                .collect();
+            assert_eq!(1, s_gates.len());
+            let (block_i, SGate(data)) = s_gates[0] else {
+                unreachable!()
+            };
+            assert_eq!(arch.magic_block(), *block_i);
+            assert!(data.adjoint);
+        }
+
+        #[test]
+        fn compile_ccz_lowerings() {
+            let basis = PbcOperation::ccz([0, 5, 15], 22).basis().clone();
+
+            let arch = PathArchitecture::new(2);
+            let rotations = compile_multi_rotation(
+                &arch,
+                &*GROSS_TABLE,
+                &crate::language::ccz_rotations(&basis),
+                ACCURACY,
+            );
+            let is_t = |isa: &BicycleISA| matches!(isa, TGate(_));
+            assert_eq!(
+                7,
+                rotations
+                    .iter()
+                    .flatten()
+                    .filter(|(_, isa)| is_t(isa))
+                    .count()
+            );
+
+            let arch = arch.with_storage_blocks(1);
+            let consumed = compile_ccz_state(&arch, &*GROSS_TABLE, &basis);
+            assert!(consumed.iter().all(|op| arch.validate_operation(op)));
+            assert!(!consumed.iter().flatten().any(|(_, isa)| is_t(isa)));
+            // Three joint measurements with the state and three X measurements of the state
+            let on_storage: Vec<_> = consumed
+                .iter()
+                .filter(|op| op.iter().any(|(block_i, _)| *block_i == 2))
+                .collect();
+            assert_eq!(6, on_storage.len());
+            assert_eq!(3, on_storage.iter().filter(|op| op.len() == 2).count());
+        }
+
         /// State prep for nontrivial rotation
         fn prep(blocks: usize) -> impl Iterator<Item = Operation> {
-            let y1 = TwoBases::new(Pauli::Y, Pauli::I).unwrap();
-            let x1 = TwoBases::new(Pauli::X, Pauli::I).unwrap();
-            let mut out = vec![x1; blocks];
-            out[blocks - 1] = y1;
-            out.into_iter().map(Measure).enumerate().map(|e| vec![e])
+            let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+            (0..blocks).map(move |block| vec![(block, Measure(z1))])
         }
 
         /// State measurement for nontrivial rotation
         fn unprep(blocks: usize) -> impl Iterator<Item = Operation> {
             let y1 = TwoBases::new(Pauli::Y, Pauli::I).unwrap();
-            let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
-            let mut out = vec![y1; blocks];
-            out[blocks - 1] = z1;
-            out.into_iter().map(Measure).enumerate().map(|e| vec![e])
+            (0..blocks).map(move |block| vec![(block, Measure(y1))])
         }
 
         #[test]
         fn compile_native_rotation() -> Result<(), Box<dyn Error>> {
-            let arch = PathArchitecture { data_blocks: 1 };
-            let meas = random_min_native_measurement(&GROSS_TABLE);
+            let arch = PathArchitecture::new(1);
+            let meas = random_min_native_measurement(&*GROSS_TABLE);
 
             let ps: [Pauli; 12] = meas.measures().into();
             let basis_change0 = select_basis_change(X, ps[0]);
@@ -669,7 +2273,7 @@ mod tests {
 
             let ops = Operations(compile_rotation(
                 &arch,
-                &GROSS_TABLE,
+                &*GROSS_TABLE,
                 basis,
                 small_angle::T_ANGLE,
                 ACCURACY,
@@ -694,9 +2298,7 @@ mod tests {
         #[test]
         fn compile_multiblock() -> Result<(), Box<dyn Error>> {
             for blocks in 2..10 {
-                let arch = PathArchitecture {
-                    data_blocks: blocks,
-                };
+                let arch = PathArchitecture::new(blocks);
                 let ps: Vec<_> = random_nontrivial_paulistrings().take(blocks).collect();
                 let implementations: Vec<_> = ps.iter().map(|p| GROSS_TABLE.min_data(*p)).collect();
                 let block_bases: Vec<_> = implementations
@@ -721,7 +2323,7 @@ mod tests {
 
                 let ops = Operations(compile_rotation(
                     &arch,
-                    &GROSS_TABLE,
+                    &*GROSS_TABLE,
                     basis,
                     small_angle::T_ANGLE,
                     ACCURACY,
@@ -732,7 +2334,7 @@ mod tests {
 
                 // pre-rotations
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    for rot in meas_impl.rotations() {
+                    for rot in meas_impl.rotations().iter().rev() {
                         let operations = rotation_instructions(rot)
                             .into_iter()
                             .map(|instr| vec![(block_i, instr)]);
@@ -744,12 +2346,10 @@ mod tests {
 
                 // measurements
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    expected.extend(
-                        native_instructions(block_i, meas_impl.base_measurement()).into_iter(),
-                    );
+                    expected.extend(native_instructions(block_i, meas_impl.base_measurement()));
                 }
 
-                let mut middle_ops = ghz_meas(0, arch.data_blocks());
+                let mut middle_ops = ghz_meas(&arch, 0, &[arch.data_blocks() - 1]);
                 middle_ops.push(vec![(
                     blocks - 1,
                     TGate(TGateData::new(Pauli::X, false, false).unwrap()),
@@ -763,7 +2363,7 @@ mod tests {
 
                 // post-rotations
                 for (block_i, meas_impl) in implementations.iter().enumerate() {
-                    for rot in meas_impl.rotations().iter().rev() {
+                    for rot in meas_impl.rotations() {
                         let operations = rotation_instructions(rot)
                             .into_iter()
                             .map(|instr| vec![(block_i, instr)]);
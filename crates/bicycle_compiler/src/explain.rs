@@ -0,0 +1,161 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wall-clock time spent in each stage of compilation, aggregated over all operations.
+//!
+//! Timing is off by default, such that each timed call only costs an atomic load.
+//! Stages can be nested, e.g., the table lookups, GHZ states, and synthesis are part of compiling the operations.
+
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// A stage of compilation whose time is aggregated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Parsing and checking the input operations
+    Parse,
+    /// Compiling the operations, including the nested stages below
+    Compile,
+    /// Looking up the Clifford synthesis of the Paulis on a block
+    TableLookup,
+    /// Constructing the GHZ states across blocks
+    Ghz,
+    /// Synthesizing small-angle rotations that are not cached
+    Synthesis,
+    /// Removing trivial automorphisms
+    TrivialAutomorphisms,
+    /// Merging single-block runs, see `--compress-blocks`
+    CompressBlocks,
+    /// Removing duplicate measurements
+    DuplicateMeasurements,
+    /// Reordering operations into time steps, see `--schedule`
+    Schedule,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::Parse,
+        Stage::Compile,
+        Stage::TableLookup,
+        Stage::Ghz,
+        Stage::Synthesis,
+        Stage::TrivialAutomorphisms,
+        Stage::CompressBlocks,
+        Stage::DuplicateMeasurements,
+        Stage::Schedule,
+    ];
+
+    /// Whether the stage is part of [`Stage::Compile`]
+    pub fn nested(self) -> bool {
+        matches!(self, Stage::TableLookup | Stage::Ghz | Stage::Synthesis)
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Parse => "parse",
+            Stage::Compile => "compile",
+            Stage::TableLookup => "table lookup",
+            Stage::Ghz => "GHZ construction",
+            Stage::Synthesis => "synthesis",
+            Stage::TrivialAutomorphisms => "trivial automorphisms pass",
+            Stage::CompressBlocks => "compress blocks pass",
+            Stage::DuplicateMeasurements => "duplicate measurements pass",
+            Stage::Schedule => "schedule pass",
+        };
+        write!(f, "{name}")
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NANOS: [AtomicU64; Stage::ALL.len()] = [const { AtomicU64::new(0) }; Stage::ALL.len()];
+static CALLS: [AtomicU64; Stage::ALL.len()] = [const { AtomicU64::new(0) }; Stage::ALL.len()];
+
+/// Start aggregating the time of each stage
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f` and add its wall-clock time to `stage`, if timing is enabled
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    NANOS[stage as usize].fetch_add(nanos, Ordering::Relaxed);
+    CALLS[stage as usize].fetch_add(1, Ordering::Relaxed);
+    result
+}
+
+/// The aggregated time of a stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageTime {
+    pub stage: Stage,
+    pub total: Duration,
+    /// Number of timed calls
+    pub calls: u64,
+}
+
+impl StageTime {
+    /// Average time per call
+    pub fn average(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.total.as_nanos() / u128::from(calls)) as u64),
+        }
+    }
+}
+
+/// The aggregated times of the stages that were timed at least once
+pub fn times() -> Vec<StageTime> {
+    Stage::ALL
+        .into_iter()
+        .map(|stage| StageTime {
+            stage,
+            total: Duration::from_nanos(NANOS[stage as usize].load(Ordering::Relaxed)),
+            calls: CALLS[stage as usize].load(Ordering::Relaxed),
+        })
+        .filter(|time| time.calls > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_stages() {
+        enable();
+        let value = time(Stage::Ghz, || {
+            std::thread::sleep(Duration::from_millis(2));
+            3
+        });
+        assert_eq!(3, value);
+        time(Stage::Ghz, || ());
+        let ghz = times()
+            .into_iter()
+            .find(|time| time.stage == Stage::Ghz)
+            .unwrap();
+        assert!(ghz.calls >= 2);
+        assert!(ghz.total >= Duration::from_millis(2));
+        assert!(Stage::Ghz.nested());
+        assert!(!Stage::Parse.nested());
+    }
+}
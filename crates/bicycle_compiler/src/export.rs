@@ -0,0 +1,493 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export compiled programs as Stim circuits on the logical qubits of the blocks.
+//!
+//! Block `b` holds the Stim qubits `12b` to `12b + 11`, numbered as in a [`PauliString`], so the pivot is qubit `12b`.
+//! Automorphisms are not written as gates but tracked per block,
+//! such that each measurement becomes the `MPP` of the logical Pauli that it measures before the automorphisms,
+//! as given by [`CodeMeasurement::measures`].
+//! Initializing a block starts its tracking over.
+//! Joint measurements on several blocks in one operation are paired in order into one `MPP`.
+//! S gates become `SPP` rotations, while T gates and T state preparations are not Clifford
+//! and are written as comments.
+//! The Paulis of the measurements and rotations are correct up to their sign.
+//!
+//! With a [`StimNoise`], every instruction is followed by a `DEPOLARIZE1` of the qubits of its block,
+//! and the blocks that do not take part in an operation idle for its duration.
+//! The probability that a block has a logical error is split evenly over its twelve qubits.
+//! Every operation ends with a `TICK`.
+//!
+//! The [`LogicalWalker`] that gives the action of each instruction on the logical qubits
+//! is shared with the verifier in [`crate::verify`] and the fault sampler of the numerics.
+
+use std::fmt::Write;
+
+use bicycle_cliffords::{
+    PauliString, measurement::CodeMeasurement, native_measurement::NativeMeasurement,
+};
+use bicycle_common::{AutomorphismData, BicycleISA, Pauli, TGateData, TwoBases};
+
+use crate::operation::Operation;
+
+/// Number of Stim qubits of each block, i.e., the data qubits and the pivot
+const QUBITS: usize = PauliString::NR_DATA_QUBITS + 1;
+
+/// The timing and logical error of instructions, e.g., of a noise model of the numerics
+pub trait StimNoise {
+    /// Number of cycles that an instruction takes
+    fn duration(&self, instruction: &BicycleISA) -> u64;
+
+    /// Probability that an instruction causes a logical error on its block
+    fn depolarization(&self, instruction: &BicycleISA) -> f64;
+
+    /// Probability that a block that idles for `cycles` has a logical error
+    fn idle_depolarization(&self, cycles: u64) -> f64;
+}
+
+/// A logical Pauli of a block, which the bases on its pivot measure or rotate about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogicalPauli {
+    pub block: usize,
+    /// The bases on the pivot
+    pub bases: TwoBases,
+    /// The logical Pauli that the bases act on after the automorphisms of the block, see [`CodeMeasurement::measures`]
+    pub pauli: PauliString,
+}
+
+/// The action of a compiled instruction on the logical qubits of the blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogicalGate {
+    /// Prepare every logical qubit of a block in the +1 eigenstate of Z or X
+    Init(usize, Pauli),
+    /// Prepare a T state on a block
+    InitT(usize),
+    /// Measure every logical qubit of a block in the Z or X basis
+    Destructive(usize, Pauli),
+    /// Measure the product of logical Paulis of blocks
+    Measure(Vec<LogicalPauli>),
+    /// A transversal CX from the `control` to the `target` block,
+    /// which is a CX on each pair of logical qubits only if both blocks have the same automorphisms
+    Cx {
+        control: usize,
+        target: usize,
+        aligned: bool,
+    },
+    /// An S gate about a logical Pauli, or its adjoint
+    S { pauli: LogicalPauli, adjoint: bool },
+    /// A T gate about a logical Pauli, or its adjoint
+    T { pauli: LogicalPauli, adjoint: bool },
+}
+
+/// Walks the instructions of compiled operations and tracks the automorphisms of the blocks,
+/// such that each instruction acts on the logical qubits as given by [`LogicalGate`]
+#[derive(Debug, Clone)]
+pub struct LogicalWalker {
+    code: CodeMeasurement,
+    /// The product of the automorphisms on each block since it was initialized
+    automorphisms: Vec<AutomorphismData>,
+    /// The first half of a joint measurement
+    joint: Option<(usize, TwoBases)>,
+    /// The first block of a joint Bell initialization or transversal CX
+    joint_gate: Option<usize>,
+}
+
+impl LogicalWalker {
+    /// Walk operations on `blocks` blocks of a code. Instructions on later blocks add them.
+    pub fn new(code: CodeMeasurement, blocks: usize) -> Self {
+        Self {
+            code,
+            automorphisms: vec![AutomorphismData::default(); blocks],
+            joint: None,
+            joint_gate: None,
+        }
+    }
+
+    /// Number of blocks that the walker knows
+    pub fn blocks(&self) -> usize {
+        self.automorphisms.len()
+    }
+
+    /// The logical Pauli that `bases` on the pivot of `block` act on
+    pub fn logical(&self, block: usize, bases: TwoBases) -> LogicalPauli {
+        let automorphism = self.automorphisms.get(block).copied().unwrap_or_default();
+        let pauli = self.code.measures(&NativeMeasurement {
+            logical: bases,
+            automorphism,
+        });
+        LogicalPauli {
+            block,
+            bases,
+            pauli,
+        }
+    }
+
+    /// The gates of the instructions of an operation
+    pub fn gates(&mut self, op: &Operation) -> Vec<LogicalGate> {
+        op.iter()
+            .flat_map(|(block, instruction)| self.instruction(*block, instruction))
+            .collect()
+    }
+
+    /// The gates of an instruction on a block.
+    /// The joint instructions are paired in order, such that the second of a pair gives their gates.
+    pub fn instruction(&mut self, block: usize, instruction: &BicycleISA) -> Vec<LogicalGate> {
+        if block >= self.automorphisms.len() {
+            self.automorphisms
+                .resize(block + 1, AutomorphismData::default());
+        }
+        match instruction {
+            BicycleISA::SyndromeCycle | BicycleISA::Delay(_) => vec![],
+            BicycleISA::CSSInitZero | BicycleISA::CSSInitPlus => {
+                self.automorphisms[block] = AutomorphismData::default();
+                let p = match instruction {
+                    BicycleISA::CSSInitZero => Pauli::Z,
+                    _ => Pauli::X,
+                };
+                vec![LogicalGate::Init(block, p)]
+            }
+            BicycleISA::InitT => vec![LogicalGate::InitT(block)],
+            BicycleISA::DestructiveZ => vec![LogicalGate::Destructive(block, Pauli::Z)],
+            BicycleISA::DestructiveX => vec![LogicalGate::Destructive(block, Pauli::X)],
+            BicycleISA::Automorphism(aut) => {
+                self.automorphisms[block] *= *aut;
+                vec![]
+            }
+            BicycleISA::Measure(bases) => {
+                vec![LogicalGate::Measure(vec![self.logical(block, *bases)])]
+            }
+            BicycleISA::JointMeasure(bases) => match self.joint.take() {
+                Some((first, first_bases)) => vec![LogicalGate::Measure(vec![
+                    self.logical(first, first_bases),
+                    self.logical(block, *bases),
+                ])],
+                None => {
+                    self.joint = Some((block, *bases));
+                    vec![]
+                }
+            },
+            BicycleISA::ParallelMeasure(data) => {
+                let p = data.get_basis();
+                [TwoBases::new(p, Pauli::I), TwoBases::new(Pauli::I, p)]
+                    .into_iter()
+                    .map(|bases| LogicalGate::Measure(vec![self.logical(block, bases.unwrap())]))
+                    .collect()
+            }
+            BicycleISA::JointBellInit | BicycleISA::JointTransversalCX => {
+                // The first block of a pair is the control of the transversal CX
+                let Some(control) = self.joint_gate.take() else {
+                    self.joint_gate = Some(block);
+                    return vec![];
+                };
+                let mut gates = vec![];
+                if *instruction == BicycleISA::JointBellInit {
+                    self.automorphisms[control] = AutomorphismData::default();
+                    self.automorphisms[block] = AutomorphismData::default();
+                    gates.push(LogicalGate::Init(control, Pauli::X));
+                    gates.push(LogicalGate::Init(block, Pauli::Z));
+                }
+                gates.push(LogicalGate::Cx {
+                    control,
+                    target: block,
+                    aligned: self.automorphisms[control] == self.automorphisms[block],
+                });
+                gates
+            }
+            BicycleISA::TGate(data) => vec![self.t_gate(block, data)],
+            BicycleISA::TGatePair(pair) => {
+                vec![
+                    self.t_gate(block, &pair.first),
+                    self.t_gate(block, &pair.second),
+                ]
+            }
+            BicycleISA::SGate(data) => vec![LogicalGate::S {
+                pauli: self.logical(block, pivot_bases(data.get_basis(), data.primed)),
+                adjoint: data.adjoint,
+            }],
+        }
+    }
+
+    fn t_gate(&self, block: usize, data: &TGateData) -> LogicalGate {
+        LogicalGate::T {
+            pauli: self.logical(block, pivot_bases(data.get_basis(), data.primed)),
+            adjoint: data.adjoint,
+        }
+    }
+}
+
+/// Writes compiled operations as a Stim circuit
+pub struct StimExporter<'a> {
+    noise: Option<&'a dyn StimNoise>,
+    walker: LogicalWalker,
+}
+
+impl<'a> StimExporter<'a> {
+    /// Export operations on `blocks` blocks of a code without noise.
+    /// Operations on later blocks add them.
+    pub fn new(code: CodeMeasurement, blocks: usize) -> Self {
+        Self {
+            noise: None,
+            walker: LogicalWalker::new(code, blocks),
+        }
+    }
+
+    /// Add the noise of `noise` after each instruction and to idling blocks
+    pub fn with_noise(self, noise: &'a dyn StimNoise) -> Self {
+        Self {
+            noise: Some(noise),
+            ..self
+        }
+    }
+
+    /// The Stim instructions of an operation, one per line
+    pub fn export(&mut self, op: &Operation) -> String {
+        let mut out = String::new();
+        for gate in self.walker.gates(op) {
+            match gate {
+                LogicalGate::Init(block, Pauli::Z) => {
+                    writeln!(out, "R {}", targets(block)).unwrap()
+                }
+                LogicalGate::Init(block, _) => writeln!(out, "RX {}", targets(block)).unwrap(),
+                LogicalGate::InitT(block) => writeln!(out, "# InitT {}", targets(block)).unwrap(),
+                LogicalGate::Destructive(block, Pauli::Z) => {
+                    writeln!(out, "M {}", targets(block)).unwrap()
+                }
+                LogicalGate::Destructive(block, _) => {
+                    writeln!(out, "MX {}", targets(block)).unwrap()
+                }
+                LogicalGate::Measure(paulis) => writeln!(out, "MPP {}", product(&paulis)).unwrap(),
+                // Assumes that both blocks have the same automorphisms
+                LogicalGate::Cx {
+                    control, target, ..
+                } => writeln!(out, "CX {}", cx_targets(control, target)).unwrap(),
+                LogicalGate::S { pauli, adjoint } => {
+                    // SPP applies exp(-iπ/4 P) up to a global phase, and the S gate exp(iπ/4 P)
+                    let gate = if adjoint { "SPP" } else { "SPP_DAG" };
+                    writeln!(out, "{gate} {}", product(&[pauli])).unwrap();
+                }
+                // A T gate is not a Stim gate, so it is written as a comment
+                LogicalGate::T { pauli, adjoint } => {
+                    let dagger = if adjoint { "_DAG" } else { "" };
+                    writeln!(out, "# T{dagger} {}", product(&[pauli])).unwrap();
+                }
+            }
+        }
+
+        if let Some(noise) = self.noise {
+            let duration = op
+                .iter()
+                .map(|(_, instruction)| noise.duration(instruction))
+                .max()
+                .unwrap_or(0);
+            for (block, instruction) in op {
+                depolarize(&mut out, *block, noise.depolarization(instruction));
+            }
+            let idle = noise.idle_depolarization(duration);
+            for block in 0..self.walker.blocks() {
+                if op.iter().all(|(b, _)| *b != block) {
+                    depolarize(&mut out, block, idle);
+                }
+            }
+        }
+        out.push_str("TICK\n");
+        out
+    }
+}
+
+/// The Stim Pauli product of logical Paulis of blocks
+fn product(paulis: &[LogicalPauli]) -> String {
+    paulis
+        .iter()
+        .flat_map(|logical| {
+            (0..QUBITS).filter_map(move |i| {
+                let p = match logical.pauli.get_pauli(i) {
+                    Pauli::I => return None,
+                    Pauli::X => 'X',
+                    Pauli::Y => 'Y',
+                    Pauli::Z => 'Z',
+                };
+                Some(format!("{p}{}", logical.block * QUBITS + i))
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("*")
+}
+
+/// Export a compiled program as a Stim circuit, optionally with noise
+pub fn to_stim(
+    ops: impl IntoIterator<Item = Operation>,
+    code: CodeMeasurement,
+    blocks: usize,
+    noise: Option<&dyn StimNoise>,
+) -> String {
+    let mut exporter = StimExporter::new(code, blocks);
+    if let Some(noise) = noise {
+        exporter = exporter.with_noise(noise);
+    }
+    ops.into_iter().map(|op| exporter.export(&op)).collect()
+}
+
+/// The bases that act as `p` on the primed or unprimed pivot
+pub fn pivot_bases(p: Pauli, primed: bool) -> TwoBases {
+    let bases = if primed {
+        TwoBases::new(Pauli::I, p)
+    } else {
+        TwoBases::new(p, Pauli::I)
+    };
+    bases.unwrap()
+}
+
+/// All Stim qubits of a block
+fn targets(block: usize) -> String {
+    (block * QUBITS..(block + 1) * QUBITS)
+        .map(|q| q.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Pairs of the Stim qubits of a transversal CX from `control` to `target`
+fn cx_targets(control: usize, target: usize) -> String {
+    (0..QUBITS)
+        .map(|i| format!("{} {}", control * QUBITS + i, target * QUBITS + i))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Depolarize the qubits of a block such that it has an error with about probability `error`
+fn depolarize(out: &mut String, block: usize, error: f64) {
+    if error > 0.0 {
+        // DEPOLARIZE1 takes probabilities up to 3/4
+        let p = (error / QUBITS as f64).min(0.75);
+        writeln!(out, "DEPOLARIZE1({p}) {}", targets(block)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_cliffords::measurement::GROSS_MEASUREMENT;
+    use bicycle_common::{ParallelMeasureData, TGateData};
+
+    use super::*;
+
+    use BicycleISA::{
+        Automorphism, CSSInitZero, JointBellInit, JointMeasure, JointTransversalCX, Measure,
+        ParallelMeasure, SGate,
+    };
+
+    struct Uniform;
+
+    impl StimNoise for Uniform {
+        fn duration(&self, instruction: &BicycleISA) -> u64 {
+            match instruction {
+                Automorphism(_) => 2,
+                _ => 10,
+            }
+        }
+
+        fn depolarization(&self, _: &BicycleISA) -> f64 {
+            1.2e-3
+        }
+
+        fn idle_depolarization(&self, cycles: u64) -> f64 {
+            cycles as f64 * 1.2e-6
+        }
+    }
+
+    #[test]
+    fn measurements_on_blocks() {
+        let z1 = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let x = ParallelMeasureData::new(Pauli::X).unwrap();
+        let ops = vec![
+            vec![(1, Measure(z1))],
+            vec![(0, JointMeasure(z1)), (1, JointMeasure(z1))],
+            vec![(0, ParallelMeasure(x))],
+        ];
+        let circuit = to_stim(ops, GROSS_MEASUREMENT, 2, None);
+        assert_eq!(
+            "MPP Z12\nTICK\nMPP Z0*Z12\nTICK\nMPP X0\nMPP X6\nTICK\n",
+            circuit
+        );
+    }
+
+    #[test]
+    fn automorphisms_conjugate_measurements() {
+        let aut = AutomorphismData::new(1, 2);
+        let x7 = TwoBases::new(Pauli::I, Pauli::X).unwrap();
+        let mut exporter = StimExporter::new(GROSS_MEASUREMENT, 1);
+        assert_eq!("TICK\n", exporter.export(&vec![(0, Automorphism(aut))]));
+
+        let measures = GROSS_MEASUREMENT.measures(&NativeMeasurement {
+            logical: x7,
+            automorphism: aut,
+        });
+        let expected: Vec<_> = measures.support().map(|q| format!("X{q}")).collect();
+        let circuit = exporter.export(&vec![(0, Measure(x7))]);
+        assert_eq!(format!("MPP {}\nTICK\n", expected.join("*")), circuit);
+
+        // Initializing the block starts over
+        exporter.export(&vec![(0, CSSInitZero)]);
+        assert_eq!("MPP X6\nTICK\n", exporter.export(&vec![(0, Measure(x7))]));
+    }
+
+    #[test]
+    fn joint_instructions_pair_in_order() {
+        let mut walker = LogicalWalker::new(GROSS_MEASUREMENT, 2);
+        let bell = vec![(1, JointBellInit), (0, JointBellInit)];
+        assert_eq!(
+            vec![
+                LogicalGate::Init(1, Pauli::X),
+                LogicalGate::Init(0, Pauli::Z),
+                LogicalGate::Cx {
+                    control: 1,
+                    target: 0,
+                    aligned: true,
+                },
+            ],
+            walker.gates(&bell)
+        );
+        // A transversal CX after an automorphism on one block does not act on pairs of logical qubits
+        walker.gates(&vec![(0, Automorphism(AutomorphismData::new(1, 0)))]);
+        let cx = walker.gates(&vec![(0, JointTransversalCX), (1, JointTransversalCX)]);
+        assert_eq!(
+            vec![LogicalGate::Cx {
+                control: 0,
+                target: 1,
+                aligned: false,
+            }],
+            cx
+        );
+        assert_eq!(2, walker.blocks());
+        walker.gates(&vec![(3, CSSInitZero)]);
+        assert_eq!(4, walker.blocks());
+    }
+
+    #[test]
+    fn noise_on_active_and_idle_blocks() {
+        let s = TGateData::new(Pauli::Z, true, false).unwrap();
+        let mut exporter = StimExporter::new(GROSS_MEASUREMENT, 2).with_noise(&Uniform);
+        let circuit = exporter.export(&vec![(1, SGate(s))]);
+        let lines: Vec<_> = circuit.lines().collect();
+        assert_eq!("SPP_DAG Z18", lines[0]);
+        assert_eq!(
+            format!("DEPOLARIZE1({}) {}", 1.2e-3 / 12.0, targets(1)),
+            lines[1]
+        );
+        // Block 0 idles for the 10 cycles of the S gate
+        assert_eq!(
+            format!("DEPOLARIZE1({}) {}", 1.2e-5 / 12.0, targets(0)),
+            lines[2]
+        );
+        assert_eq!("TICK", lines[3]);
+    }
+}
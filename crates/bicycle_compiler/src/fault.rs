@@ -0,0 +1,225 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Random corruption of compiled instructions,
+//! to test that downstream validation catches realistic mistakes of a compiler.
+
+use bicycle_common::{BicycleISA, TwoBases};
+use rand::{
+    Rng,
+    distr::{Distribution, StandardUniform},
+};
+use serde::Serialize;
+
+use crate::operation::Operation;
+
+/// What part of an instruction was corrupted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FaultKind {
+    /// The instruction was moved to another block
+    Block,
+    /// The measurement basis was changed
+    Basis,
+}
+
+/// A corrupted instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Fault {
+    /// Index of the compiled PBC operation, i.e., the output line
+    pub operation: usize,
+    /// Index of the corrupted operation within the line
+    pub instruction: usize,
+    pub kind: FaultKind,
+    pub original: (usize, BicycleISA),
+    pub corrupted: (usize, BicycleISA),
+}
+
+/// Corrupts each compiled instruction independently with a fixed probability
+pub struct FaultInjector<R> {
+    rate: f64,
+    data_blocks: usize,
+    rng: R,
+    operations: usize,
+}
+
+impl<R: Rng> FaultInjector<R> {
+    pub fn new(rate: f64, data_blocks: usize, rng: R) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "The fault rate {rate} should be a probability"
+        );
+        Self {
+            rate,
+            data_blocks,
+            rng,
+            operations: 0,
+        }
+    }
+
+    /// Also move instructions to the data blocks that were added to a growing architecture
+    pub fn grow(&mut self, data_blocks: usize) {
+        self.data_blocks = self.data_blocks.max(data_blocks);
+    }
+
+    /// Corrupt the compiled instructions of the next PBC operation in place and return the faults.
+    /// Measurements get a different basis; other instructions, or measurements on a single block
+    /// architecture, are moved to a different block.
+    pub fn inject(&mut self, compiled: &mut [Operation]) -> Vec<Fault> {
+        let mut faults = vec![];
+        for (instruction, op) in compiled.iter_mut().enumerate() {
+            if !self.rng.random_bool(self.rate) {
+                continue;
+            }
+            let position = self.rng.random_range(0..op.len());
+            let original = op[position];
+            let Some((kind, corrupted)) = self.corrupt(original) else {
+                continue;
+            };
+            op[position] = corrupted;
+            faults.push(Fault {
+                operation: self.operations,
+                instruction,
+                kind,
+                original,
+                corrupted,
+            });
+        }
+        self.operations += 1;
+        faults
+    }
+
+    fn corrupt(
+        &mut self,
+        (block, isa): (usize, BicycleISA),
+    ) -> Option<(FaultKind, (usize, BicycleISA))> {
+        let basis = match isa {
+            BicycleISA::Measure(bases) | BicycleISA::JointMeasure(bases) => Some(bases),
+            _ => None,
+        };
+        match basis {
+            Some(bases) if self.data_blocks == 1 || self.rng.random_bool(0.5) => {
+                let new_bases = self.other_bases(bases);
+                let corrupted = match isa {
+                    BicycleISA::Measure(_) => BicycleISA::Measure(new_bases),
+                    _ => BicycleISA::JointMeasure(new_bases),
+                };
+                Some((FaultKind::Basis, (block, corrupted)))
+            }
+            // There is no other block to move to
+            _ if self.data_blocks == 1 => None,
+            _ => {
+                let offset = self.rng.random_range(1..self.data_blocks);
+                let new_block = (block + offset) % self.data_blocks;
+                Some((FaultKind::Block, (new_block, isa)))
+            }
+        }
+    }
+
+    fn other_bases(&mut self, bases: TwoBases) -> TwoBases {
+        loop {
+            let new_bases: TwoBases = StandardUniform.sample(&mut self.rng);
+            if new_bases != bases {
+                return new_bases;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{AutomorphismData, Pauli};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::PathArchitecture;
+
+    fn compiled() -> Vec<Operation> {
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        vec![
+            vec![(0, BicycleISA::Automorphism(AutomorphismData::new(1, 2)))],
+            vec![(0, BicycleISA::Measure(z))],
+            vec![
+                (0, BicycleISA::JointMeasure(z)),
+                (1, BicycleISA::JointMeasure(z)),
+            ],
+        ]
+    }
+
+    #[test]
+    fn corrupts_every_instruction() {
+        let mut injector = FaultInjector::new(1.0, 3, StdRng::seed_from_u64(1));
+        let original = compiled();
+        let mut corrupted = original.clone();
+        let faults = injector.inject(&mut corrupted);
+
+        assert_eq!(3, faults.len());
+        for fault in &faults {
+            assert_eq!(0, fault.operation);
+            let op = &corrupted[fault.instruction];
+            assert!(op.contains(&fault.corrupted));
+            assert_ne!(original[fault.instruction], *op);
+            match fault.kind {
+                FaultKind::Block => {
+                    assert_eq!(fault.original.1, fault.corrupted.1);
+                    assert!(fault.corrupted.0 < 3);
+                }
+                FaultKind::Basis => assert_eq!(fault.original.0, fault.corrupted.0),
+            }
+        }
+        // The automorphism can only be moved
+        assert_eq!(FaultKind::Block, faults[0].kind);
+
+        assert!(
+            injector
+                .inject(&mut corrupted)
+                .iter()
+                .all(|f| f.operation == 1)
+        );
+    }
+
+    #[test]
+    fn validation_catches_some_block_faults() {
+        let architecture = PathArchitecture::new(3);
+        let joint = compiled()[2].clone();
+        assert!(architecture.validate_operation(&joint));
+
+        // Moving one side of the joint measurement to block 2 is caught,
+        // but moving block 0 to block 2 still acts on neighbouring blocks
+        let mut moved = joint.clone();
+        moved[1].0 = 2;
+        assert!(!architecture.validate_operation(&moved));
+        let mut moved = joint;
+        moved[0].0 = 2;
+        assert!(architecture.validate_operation(&moved));
+    }
+
+    #[test]
+    fn zero_rate_is_faithful() {
+        let mut injector = FaultInjector::new(0.0, 3, StdRng::seed_from_u64(1));
+        let mut ops = compiled();
+        assert!(injector.inject(&mut ops).is_empty());
+        assert_eq!(compiled(), ops);
+    }
+
+    #[test]
+    fn single_block_changes_bases() {
+        let mut injector = FaultInjector::new(1.0, 1, StdRng::seed_from_u64(2));
+        let mut ops = compiled();
+        let faults = injector.inject(&mut ops);
+        // The automorphism cannot be corrupted on a single block
+        assert_eq!(2, faults.len());
+        assert!(faults.iter().all(|f| f.kind == FaultKind::Basis));
+    }
+}
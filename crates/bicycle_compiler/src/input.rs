@@ -0,0 +1,418 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading the input programs, checking their operations, and the passes on them before compiling
+
+use std::{
+    collections::BTreeMap,
+    error,
+    fs::File,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use bicycle_common::Pauli;
+use bicycle_compiler::{
+    CliffordFrame,
+    budget::ErrorBudget,
+    explain::{self, Stage},
+    language::{self, HintedOperation, PbcOperation, StreamedOperation},
+    qasm,
+    snap::AngleSnapper,
+    stim,
+};
+use clap::ValueEnum;
+use log::error;
+use rand::rngs::StdRng;
+use serde::Serialize;
+use serde_json::Deserializer;
+
+use crate::cli::Cli;
+
+/// Exit code when the input contains a malformed operation
+pub const EXIT_MALFORMED_INPUT: i32 = 3;
+
+/// Machine-readable record of a malformed input operation, written to stdout after the compiled prefix
+#[derive(Debug, Serialize)]
+pub struct MalformedInput {
+    /// The input file, if not stdin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    /// Index of the malformed operation in the input, counting from 0
+    index: usize,
+    /// Location of a syntax error in the input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+    error: String,
+}
+
+impl MalformedInput {
+    /// Report the malformed input as the last line of stdout and exit.
+    /// Everything that was written to stdout before is kept as a valid prefix of the output.
+    pub fn exit(&self) -> ! {
+        error!(
+            "Stopped at malformed operation {}: {}",
+            self.index, self.error
+        );
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", serde_json::to_string(self).unwrap());
+        let _ = stdout.flush();
+        std::process::exit(EXIT_MALFORMED_INPUT);
+    }
+}
+
+/// Checks on the input operations, which are reported when compilation finishes
+pub struct InputChecks {
+    /// Pad operations to whole blocks instead of rejecting them
+    pub pad: bool,
+    /// Number of qubits in a block of the selected code
+    pub qubits_per_block: usize,
+    /// Reject operations on qubits beyond the given number of qubits of each block
+    pub table_qubits: Option<usize>,
+    /// The input files of the programs, which are empty for stdin
+    pub inputs: Vec<String>,
+    /// The first malformed operation
+    pub malformed: Mutex<Option<MalformedInput>>,
+    /// Number of padded operations by their number of qubits
+    pub padded: Mutex<BTreeMap<usize, usize>>,
+}
+
+impl InputChecks {
+    pub fn new(cli: &Cli) -> Self {
+        Self {
+            pad: cli.input.pad,
+            qubits_per_block: cli.code.qubits_per_block(),
+            table_qubits: cli.table.table_qubits.map(|qubits| qubits as usize),
+            inputs: cli
+                .input
+                .inputs
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            malformed: Mutex::new(None),
+            padded: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record an operation that passed the checks of the input but cannot be compiled,
+    /// at its position in its input
+    pub fn uncompilable(&self, hinted: &HintedOperation, error: String) -> MalformedInput {
+        MalformedInput {
+            input: self.inputs.get(hinted.program).cloned(),
+            index: hinted.index,
+            line: None,
+            column: None,
+            error,
+        }
+    }
+
+    /// Parse operations in the given format
+    pub fn read<'a>(
+        &'a self,
+        reader: impl io::Read + Send + 'a,
+        input: Option<&Path>,
+        format: InputFormat,
+    ) -> Box<dyn Iterator<Item = HintedOperation> + Send + 'a> {
+        match format {
+            InputFormat::Pbc => Box::new(self.parse(reader, input)),
+            InputFormat::Stim => Box::new(self.parse_circuit(reader, input, |circuit| {
+                stim::parse_lazy(circuit)
+                    .map(|ops| Box::new(ops) as Box<dyn Iterator<Item = _> + Send>)
+                    .map_err(|e| (e.line, e.message))
+            })),
+            InputFormat::Qasm3 => Box::new(self.parse_circuit(reader, input, |circuit| {
+                qasm::parse(circuit)
+                    .map(|ops| Box::new(ops.into_iter()) as Box<dyn Iterator<Item = _> + Send>)
+                    .map_err(|e| (e.line, e.message))
+            })),
+            InputFormat::PbcText => Box::new(self.parse_text(reader, input)),
+        }
+    }
+
+    /// Parse operations from lines of PBC text, see [`PbcOperation::to_pbc_text`].
+    /// Stop at the first malformed line but still compile the operations before it.
+    fn parse_text<'a>(
+        &'a self,
+        reader: impl io::Read + Send + 'a,
+        input: Option<&Path>,
+    ) -> impl Iterator<Item = HintedOperation> + Send + 'a {
+        let input = input.map(|path| path.display().to_string());
+        let mut index = 0;
+        io::BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .map_while(move |(i, line)| {
+                let parsed = line
+                    .map_err(|e| e.to_string())
+                    .and_then(|line| language::parse_pbc_text_line(&line));
+                match parsed {
+                    Ok(None) => Some(None),
+                    Ok(Some(op)) => {
+                        index += 1;
+                        self.check(op.into(), index - 1, &input).map(Some)
+                    }
+                    Err(error) => {
+                        self.reject(MalformedInput {
+                            input: input.clone(),
+                            index,
+                            line: Some(i + 1),
+                            column: None,
+                            error,
+                        });
+                        None
+                    }
+                }
+            })
+            .flatten()
+    }
+
+    /// Parse operations from a stream of JSON values.
+    /// Stop at the first malformed operation but still compile the operations before it.
+    fn parse<'a>(
+        &'a self,
+        reader: impl io::Read + Send + 'a,
+        input: Option<&Path>,
+    ) -> impl Iterator<Item = HintedOperation> + Send + 'a {
+        let input = input.map(|path| path.display().to_string());
+        Deserializer::from_reader(reader)
+            .into_iter::<HintedOperation>()
+            .enumerate()
+            .map_while(move |(index, op)| match op {
+                Ok(hinted) => self.check(hinted, index, &input),
+                Err(e) => self.reject(MalformedInput {
+                    input: input.clone(),
+                    index,
+                    line: Some(e.line()),
+                    column: Some(e.column()),
+                    error: e.to_string(),
+                }),
+            })
+    }
+
+    /// Parse the operations of a circuit, reporting errors with their line.
+    /// The whole circuit is parsed first, because its number of qubits is only known at the end.
+    fn parse_circuit<'a>(
+        &'a self,
+        mut reader: impl io::Read,
+        input: Option<&Path>,
+        parse: impl FnOnce(
+            &str,
+        )
+            -> Result<Box<dyn Iterator<Item = PbcOperation> + Send>, (usize, String)>,
+    ) -> impl Iterator<Item = HintedOperation> + Send + 'a {
+        let input = input.map(|path| path.display().to_string());
+        let mut circuit = String::new();
+        let parsed = match reader.read_to_string(&mut circuit) {
+            Ok(_) => parse(&circuit).map_err(|(line, error)| (Some(line), error)),
+            Err(e) => Err((None, e.to_string())),
+        };
+        let ops = parsed.unwrap_or_else(|(line, error)| {
+            self.reject(MalformedInput {
+                input: input.clone(),
+                index: 0,
+                line,
+                column: None,
+                error,
+            });
+            Box::new(std::iter::empty())
+        });
+        ops.enumerate()
+            .map_while(move |(index, op)| self.check(op.into(), index, &input))
+    }
+
+    /// Check that the operation fills whole blocks, or pad it, and record its position in the input
+    fn check(
+        &self,
+        mut hinted: HintedOperation,
+        index: usize,
+        input: &Option<String>,
+    ) -> Option<HintedOperation> {
+        hinted.index = index;
+        if let Err(error) = language::check_operation(&hinted.op, self.qubits_per_block) {
+            return self.reject(MalformedInput {
+                input: input.clone(),
+                index,
+                line: None,
+                column: None,
+                error,
+            });
+        }
+        let qubits = hinted.op.basis().len();
+        if let Some(table_qubits) = self.table_qubits
+            && let Some(qubit) = hinted
+                .op
+                .basis()
+                .chunks(self.qubits_per_block)
+                .find_map(|block| block.iter().skip(table_qubits).position(|p| *p != Pauli::I))
+        {
+            return self.reject(MalformedInput {
+                input: input.clone(),
+                index,
+                line: None,
+                column: None,
+                error: format!(
+                    "The operation acts on qubit {} of a block, but the measurement table is only loaded for the first {table_qubits} qubits of each block",
+                    qubit + table_qubits
+                ),
+            });
+        }
+        if hinted.op.padding(self.qubits_per_block) == 0 {
+            Some(hinted)
+        } else if self.pad {
+            *self.padded.lock().unwrap().entry(qubits).or_insert(0) += 1;
+            Some(hinted)
+        } else {
+            self.reject(MalformedInput {
+                input: input.clone(),
+                index,
+                line: None,
+                column: None,
+                error: format!(
+                    "The basis of {qubits} qubits does not fill whole blocks of {} qubits, use --pad to pad it with identities",
+                    self.qubits_per_block
+                ),
+            })
+        }
+    }
+
+    /// Reject the operations of a program that act on more qubits than its first operation,
+    /// whose blocks are reserved for the program when it runs in parallel with others, see [`language::interleave`]
+    pub fn within_width<'a>(
+        &'a self,
+        program: impl Iterator<Item = HintedOperation> + Send + 'a,
+        input: &Path,
+    ) -> impl Iterator<Item = HintedOperation> + Send + 'a {
+        let input = Some(input.display().to_string());
+        let mut width = None;
+        program.enumerate().map_while(move |(index, hinted)| {
+            let qubits = hinted.op.basis().len();
+            let width = *width.get_or_insert(qubits.next_multiple_of(self.qubits_per_block));
+            if qubits <= width {
+                Some(hinted)
+            } else {
+                self.reject(MalformedInput {
+                    input: input.clone(),
+                    index,
+                    line: None,
+                    column: None,
+                    error: format!(
+                        "The operation acts on {qubits} qubits, but the program only has the {width} qubits of its first operation when it runs in parallel with other inputs"
+                    ),
+                })
+            }
+        })
+    }
+
+    /// Record the first malformed operation
+    fn reject(&self, malformed: MalformedInput) -> Option<HintedOperation> {
+        *self.malformed.lock().unwrap() = Some(malformed);
+        None
+    }
+}
+
+/// The format of the input programs
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    /// PBC operations as JSON lines
+    #[default]
+    Pbc,
+    /// A Stim circuit of Clifford gates and measurements
+    Stim,
+    /// An OpenQASM 3 circuit, whose Clifford gates are absorbed into the later operations
+    #[value(name = "qasm3")]
+    Qasm3,
+    /// PBC operations as lines of text, e.g., `rot XZIIY pi/4`, as written by `--emit pbc-text`
+    PbcText,
+}
+
+/// The PBC operations of the inputs after the passes on them, in the order that they are compiled,
+/// with their positions in the inputs
+pub fn pbc_operations<'a>(
+    cli: &'a Cli,
+    checks: &'a InputChecks,
+    snapper: &'a Option<Mutex<AngleSnapper<StdRng>>>,
+    budget: &'a Option<Mutex<ErrorBudget>>,
+    frame: &'a Option<Mutex<CliffordFrame>>,
+) -> Result<impl Iterator<Item = HintedOperation> + Send + 'a, Box<dyn error::Error>> {
+    let open = |path: &PathBuf| {
+        File::open(path).map_err(|e| format!("Cannot open input {}: {e}", path.display()))
+    };
+    // A single program keeps the qubits of its operations, so the architecture can grow with them
+    let ops: Box<dyn Iterator<Item = HintedOperation> + Send> =
+        if let [path] = &cli.input.inputs[..] {
+            checks.read(
+                io::BufReader::new(open(path)?),
+                Some(path),
+                cli.input.input_format,
+            )
+        } else if cli.input.inputs.is_empty() {
+            checks.read(
+                io::BufReader::new(io::stdin()),
+                None,
+                cli.input.input_format,
+            )
+        } else {
+            let programs = cli
+                .input
+                .inputs
+                .iter()
+                .map(|path| {
+                    let program = checks.read(
+                        io::BufReader::new(open(path)?),
+                        Some(path),
+                        cli.input.input_format,
+                    );
+                    Ok(checks.within_width(program, path))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Box::new(language::interleave(
+                programs,
+                cli.input.interleave,
+                checks.qubits_per_block,
+            ))
+        };
+    // Stop all programs at the first malformed operation
+    let mut ops = ops.take_while(|_| checks.malformed.lock().unwrap().is_none());
+    let ops = std::iter::from_fn(move || explain::time(Stage::Parse, || ops.next()));
+    let ops = language::prioritize(ops, cli.input.schedule_window);
+    let ops = language::relabel_permutations(ops);
+    let ops: Box<dyn Iterator<Item = HintedOperation> + Send> = if cli.input.fuse {
+        Box::new(language::fuse_rotation_measurements(ops))
+    } else {
+        Box::new(ops)
+    };
+    let ops = ops.map(|hinted| match snapper {
+        Some(snapper) => hinted.map_op(|op| snapper.lock().unwrap().snap(op)),
+        None => hinted,
+    });
+    // The frame corrections depend on the accuracies of the rotations
+    let ops = ops.map(|hinted| match budget {
+        Some(budget) => hinted.map_op(|op| budget.lock().unwrap().assign(op)),
+        None => hinted,
+    });
+    // Explicit Cliffords are absorbed into the frame, or compiled as S rotations without one
+    Ok(ops.filter_map(|hinted| match frame {
+        Some(frame) => {
+            let op = frame
+                .lock()
+                .unwrap()
+                .fold(hinted.op, cli.synthesis.accuracy)?;
+            Some(HintedOperation { op, ..hinted })
+        }
+        None => Some(hinted),
+    }))
+}
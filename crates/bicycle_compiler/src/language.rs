@@ -12,15 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::Display;
+use std::{collections::VecDeque, error::Error, fmt::Display, str::FromStr};
 
 use bicycle_common::Pauli;
 use fixed::types::I32F96;
 
-use bicycle_cliffords::CompleteMeasurementTable;
+use bicycle_cliffords::{MeasurementTable, PauliString};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
-use crate::{architecture::PathArchitecture, compile, operation::Operation};
+use crate::{
+    architecture::Architecture,
+    compile::{self, BlockBasisState},
+    operation::Operation,
+    small_angle,
+};
 
 pub type AnglePrecision = I32F96;
 
@@ -36,6 +42,50 @@ pub enum PbcOperation {
     Rotation {
         basis: Vec<Pauli>,
         angle: AnglePrecision,
+        /// Synthesis accuracy of this rotation, overriding the accuracy of the compiler
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        accuracy: Option<AnglePrecision>,
+    },
+    /// Simultaneous rotations about mutually commuting Paulis, e.g., a Trotter layer.
+    /// The terms share the rotations that change the basis of each block, but not their GHZ states,
+    /// because each GHZ state carries the Pauli of its term to the magic block
+    MultiRotation {
+        terms: Vec<(Vec<Pauli>, AnglePrecision)>,
+    },
+    /// A rotation immediately followed by a measurement in the same basis.
+    /// See [`fuse_rotation_measurements`].
+    RotateMeasure {
+        basis: Vec<Pauli>,
+        angle: AnglePrecision,
+        flip_result: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        accuracy: Option<AnglePrecision>,
+    },
+    /// A controlled-controlled-Z gate on the three qubits where the basis is Z,
+    /// e.g., `{"CCZ":{"basis":["Z","I","Z","Z"]}}`.
+    /// It is lowered to seven T rotations, or consumes a CCZ state if the architecture has storage blocks.
+    #[serde(rename = "CCZ")]
+    Ccz { basis: Vec<Pauli> },
+    /// Synchronize the given blocks, or all blocks, so that later instructions on them start together,
+    /// e.g., `{"Barrier":{"blocks":[0,2]}}` or `{"Barrier":{}}`
+    Barrier {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        blocks: Option<Vec<usize>>,
+    },
+    /// Idle all blocks for the given number of cycles, e.g., `{"Delay":{"cycles":100}}`
+    Delay { cycles: u64 },
+    /// Move the state of each qubit `i` to qubit `permutation[i]` within its block,
+    /// e.g., `{"Permute":{"permutation":[1,0]}}` swaps the first two qubits.
+    /// Qubits beyond the permutation keep their state.
+    /// The qubits are relabeled at compile time by [`relabel_permutations`], so the permutation costs no instructions.
+    Permute { permutation: Vec<usize> },
+    /// An exact rotation about `basis` by `quarter_turns` times the S angle, i.e., the Clifford gate exp(-iπ/4 P)^k,
+    /// e.g., `{"Clifford":{"basis":["Z","I"],"quarter_turns":1}}` is an S gate on the first qubit.
+    /// It is absorbed into the [`crate::CliffordFrame`] if the compiler tracks one, and otherwise compiled as an S rotation,
+    /// where two quarter turns are a Pauli gate that is left to the Pauli frame.
+    Clifford {
+        basis: Vec<Pauli>,
+        quarter_turns: u8,
     },
 }
 
@@ -44,38 +94,864 @@ impl PbcOperation {
         Self::Rotation {
             basis,
             angle: AnglePrecision::from_num(angle),
+            accuracy: None,
+        }
+    }
+
+    /// The angle of the S rotation that implements a [`PbcOperation::Clifford`] of `quarter_turns`,
+    /// or `None` if it is the identity or a Pauli gate
+    pub fn clifford_angle(quarter_turns: u8) -> Option<AnglePrecision> {
+        match quarter_turns % 4 {
+            1 => Some(small_angle::S_ANGLE),
+            3 => Some(-small_angle::S_ANGLE),
+            _ => None,
+        }
+    }
+
+    /// The synthesis accuracy of this operation, where `default` is the accuracy of the compiler
+    pub fn accuracy(&self, default: AnglePrecision) -> AnglePrecision {
+        match self {
+            PbcOperation::Rotation { accuracy, .. }
+            | PbcOperation::RotateMeasure { accuracy, .. } => accuracy.unwrap_or(default),
+            _ => default,
+        }
+    }
+
+    /// A CCZ gate on the given qubits of `qubits` qubits
+    pub fn ccz(controls: [usize; 3], qubits: usize) -> Self {
+        let mut basis = vec![Pauli::I; qubits];
+        for control in controls {
+            basis[control] = Pauli::Z;
         }
+        Self::Ccz { basis }
+    }
+
+    /// A CNOT gate with the given control and target qubits of `qubits` qubits
+    pub fn cx(control: usize, target: usize, qubits: usize) -> Vec<Self> {
+        Self::controlled(control, target, Pauli::X, qubits)
+    }
+
+    /// A CZ gate on the given qubits of `qubits` qubits
+    pub fn cz(control: usize, target: usize, qubits: usize) -> Vec<Self> {
+        Self::controlled(control, target, Pauli::Z, qubits)
+    }
+
+    /// A Hadamard gate on the given qubit of `qubits` qubits, as H = S SQRT_X S up to a global phase
+    pub fn h(qubit: usize, qubits: usize) -> Vec<Self> {
+        [Pauli::Z, Pauli::X, Pauli::Z]
+            .into_iter()
+            .map(|p| Self::Rotation {
+                basis: sparse_basis(&[(qubit, p)], qubits),
+                angle: small_angle::S_ANGLE,
+                accuracy: None,
+            })
+            .collect()
     }
+
+    /// An S gate on the given qubit of `qubits` qubits
+    pub fn s(qubit: usize, qubits: usize) -> Vec<Self> {
+        vec![Self::Rotation {
+            basis: sparse_basis(&[(qubit, Pauli::Z)], qubits),
+            angle: small_angle::S_ANGLE,
+            accuracy: None,
+        }]
+    }
+
+    /// A controlled P is exp(iπ/4 (1 - Z_c)(1 - P_t)) up to a global phase,
+    /// which is a single operation of three commuting rotations
+    fn controlled(control: usize, target: usize, p: Pauli, qubits: usize) -> Vec<Self> {
+        assert_ne!(control, target, "The control should differ from the target");
+        let s_angle = small_angle::S_ANGLE;
+        let terms = vec![
+            (sparse_basis(&[(control, Pauli::Z)], qubits), s_angle),
+            (sparse_basis(&[(target, p)], qubits), s_angle),
+            (
+                sparse_basis(&[(control, Pauli::Z), (target, p)], qubits),
+                -s_angle,
+            ),
+        ];
+        vec![Self::MultiRotation { terms }]
+    }
+
     pub fn compile(
         &self,
-        architecture: &PathArchitecture,
-        measurement_table: &CompleteMeasurementTable,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
         accuracy: AnglePrecision,
     ) -> Vec<Operation> {
+        let accuracy = self.accuracy(accuracy);
         match self {
             // TODO: use flip_result to flip the sign of measurements
             PbcOperation::Measurement { basis, .. } => {
                 compile::compile_measurement(architecture, measurement_table, basis.to_vec())
             }
-            PbcOperation::Rotation { basis, angle } => compile::compile_rotation(
+            PbcOperation::Rotation { basis, angle, .. } => compile::compile_rotation(
                 architecture,
                 measurement_table,
                 basis.to_vec(),
                 *angle,
                 accuracy,
             ),
+            PbcOperation::MultiRotation { terms } => {
+                self.assert_commuting_terms(terms);
+                compile::compile_multi_rotation(architecture, measurement_table, terms, accuracy)
+            }
+            PbcOperation::RotateMeasure { .. } => {
+                self.compile_with_savings(architecture, measurement_table, accuracy)
+                    .0
+            }
+            PbcOperation::Ccz { basis } if architecture.storage_blocks() > 0 => {
+                compile::compile_ccz_state(architecture, measurement_table, basis)
+            }
+            PbcOperation::Ccz { basis } => compile::compile_multi_rotation(
+                architecture,
+                measurement_table,
+                &ccz_rotations(basis),
+                accuracy,
+            ),
+            PbcOperation::Barrier { blocks } => {
+                compile::compile_barrier(architecture, blocks.as_deref())
+            }
+            PbcOperation::Delay { cycles } => compile::compile_delay(architecture, *cycles),
+            PbcOperation::Permute { .. } => vec![],
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => match Self::clifford_angle(*quarter_turns) {
+                Some(angle) => compile::compile_rotation(
+                    architecture,
+                    measurement_table,
+                    basis.to_vec(),
+                    angle,
+                    accuracy,
+                ),
+                None => vec![],
+            },
+        }
+    }
+
+    /// Compile this operation while keeping the basis of each block in `state` applied afterwards.
+    ///
+    /// Blocks whose basis does not change between consecutive operations skip the basis-change instructions.
+    /// Call [`BlockBasisState::finish`] after the last operation to restore the original basis.
+    pub fn compile_cached(
+        &self,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
+        accuracy: AnglePrecision,
+        state: &mut BlockBasisState,
+    ) -> Vec<Operation> {
+        self.compile_cached_observed(architecture, measurement_table, accuracy, state)
+            .0
+    }
+
+    /// Like [`Self::compile_cached`], but also return how many instructions were saved by a fused lowering,
+    /// and the indices of the instructions whose outcomes XOR to the outcome of this operation if it is a measurement
+    pub fn compile_cached_observed(
+        &self,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
+        accuracy: AnglePrecision,
+        state: &mut BlockBasisState,
+    ) -> (Vec<Operation>, usize, Option<Vec<usize>>) {
+        let accuracy = self.accuracy(accuracy);
+        let ops = match self {
+            // TODO: use flip_result to flip the sign of measurements
+            PbcOperation::Measurement { basis, .. } => {
+                let (ops, outcomes) = state.apply_observed(compile::lower_measurement(
+                    architecture,
+                    measurement_table,
+                    basis.to_vec(),
+                ));
+                return (ops, 0, Some(outcomes));
+            }
+            PbcOperation::Rotation { basis, angle, .. } => state.apply(compile::lower_rotation(
+                architecture,
+                measurement_table,
+                basis.to_vec(),
+                *angle,
+                accuracy,
+            )),
+            PbcOperation::MultiRotation { terms } => {
+                self.assert_commuting_terms(terms);
+                compile::lower_multi_rotation(architecture, measurement_table, terms, accuracy)
+                    .into_iter()
+                    .flat_map(|lowered| state.apply(lowered))
+                    .collect()
+            }
+            PbcOperation::RotateMeasure { basis, angle, .. } => {
+                let (fused, saved) = compile::lower_rotation_measurement(
+                    architecture,
+                    measurement_table,
+                    basis.to_vec(),
+                    *angle,
+                    accuracy,
+                );
+                let (ops, outcomes) = state.apply_observed(fused);
+                return (ops, saved, Some(outcomes));
+            }
+            PbcOperation::Ccz { basis } => {
+                let lowered = if architecture.storage_blocks() > 0 {
+                    compile::lower_ccz_state(architecture, measurement_table, basis)
+                } else {
+                    compile::lower_multi_rotation(
+                        architecture,
+                        measurement_table,
+                        &ccz_rotations(basis),
+                        accuracy,
+                    )
+                };
+                lowered
+                    .into_iter()
+                    .flat_map(|lowered| state.apply(lowered))
+                    .collect()
+            }
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => match Self::clifford_angle(*quarter_turns) {
+                Some(angle) => state.apply(compile::lower_rotation(
+                    architecture,
+                    measurement_table,
+                    basis.to_vec(),
+                    angle,
+                    accuracy,
+                )),
+                None => vec![],
+            },
+            // The bases of the blocks do not change while they wait or are relabeled
+            PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. } => {
+                self.compile(architecture, measurement_table, accuracy)
+            }
+        };
+        (ops, 0, None)
+    }
+
+    /// Compile this operation and report how many instructions were saved by a fused lowering
+    pub fn compile_with_savings(
+        &self,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
+        accuracy: AnglePrecision,
+    ) -> (Vec<Operation>, usize) {
+        let (ops, saved, _) = self.compile_observed(architecture, measurement_table, accuracy);
+        (ops, saved)
+    }
+
+    /// Like [`Self::compile_with_savings`], but also return the indices of the instructions
+    /// whose outcomes XOR to the outcome of this operation if it is a measurement
+    pub fn compile_observed(
+        &self,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
+        accuracy: AnglePrecision,
+    ) -> (Vec<Operation>, usize, Option<Vec<usize>>) {
+        let accuracy = self.accuracy(accuracy);
+        match self {
+            PbcOperation::Measurement { basis, .. } => {
+                let (ops, outcomes) =
+                    compile::observe_measurement(architecture, measurement_table, basis.to_vec());
+                (ops, 0, Some(outcomes))
+            }
+            PbcOperation::RotateMeasure { basis, angle, .. } => {
+                let (ops, saved, outcomes) = compile::observe_rotation_measurement(
+                    architecture,
+                    measurement_table,
+                    basis.to_vec(),
+                    *angle,
+                    accuracy,
+                );
+                (ops, saved, Some(outcomes))
+            }
+            _ => (
+                self.compile(architecture, measurement_table, accuracy),
+                0,
+                None,
+            ),
+        }
+    }
+
+    fn assert_commuting_terms(&self, terms: &[(Vec<Pauli>, AnglePrecision)]) {
+        assert!(
+            terms.iter().enumerate().all(|(i, (basis0, _))| terms[..i]
+                .iter()
+                .all(|(basis1, _)| commutes(basis0, basis1))),
+            "The terms of {self} should mutually commute"
+        );
+    }
+
+    /// Number of identities that the basis is padded with to fill whole blocks of `qubits_per_block` qubits
+    pub fn padding(&self, qubits_per_block: usize) -> usize {
+        self.basis().len().next_multiple_of(qubits_per_block) - self.basis().len()
+    }
+
+    /// Check if all the Paulis of this operation commute with those of `other`
+    pub fn commutes_with(&self, other: &PbcOperation) -> bool {
+        match (self, other) {
+            // Timing operations and permutations keep their place
+            (
+                PbcOperation::Barrier { .. }
+                | PbcOperation::Delay { .. }
+                | PbcOperation::Permute { .. },
+                _,
+            )
+            | (
+                _,
+                PbcOperation::Barrier { .. }
+                | PbcOperation::Delay { .. }
+                | PbcOperation::Permute { .. },
+            ) => false,
+            // A CCZ gate commutes with Paulis that are diagonal on its controls
+            (PbcOperation::Ccz { basis }, _) => {
+                other.bases().all(|basis1| diagonal_on(basis, basis1))
+            }
+            (_, PbcOperation::Ccz { basis }) => {
+                self.bases().all(|basis0| diagonal_on(basis, basis0))
+            }
+            _ => self
+                .bases()
+                .all(|basis0| other.bases().all(|basis1| commutes(basis0, basis1))),
+        }
+    }
+
+    /// The bases of the operation, which are the terms of a [`PbcOperation::MultiRotation`]
+    pub(crate) fn bases(&self) -> Box<dyn Iterator<Item = &Vec<Pauli>> + '_> {
+        match self {
+            PbcOperation::MultiRotation { terms } => Box::new(terms.iter().map(|(basis, _)| basis)),
+            _ => Box::new(std::iter::once(self.basis())),
+        }
+    }
+
+    /// Place the operation on qubits `offset..offset + n` of `qubits` qubits,
+    /// where `n` is the number of qubits of the operation,
+    /// and move the blocks of a barrier by the `offset / qubits_per_block` blocks before it
+    pub fn embed(self, offset: usize, qubits: usize, qubits_per_block: usize) -> Self {
+        match self {
+            PbcOperation::Barrier { blocks } => PbcOperation::Barrier {
+                blocks: blocks.map(|blocks| {
+                    blocks
+                        .into_iter()
+                        .map(|block| block + offset / qubits_per_block)
+                        .collect()
+                }),
+            },
+            PbcOperation::Permute { permutation } => PbcOperation::Permute {
+                permutation: (0..offset)
+                    .chain(permutation.into_iter().map(|qubit| qubit + offset))
+                    .collect(),
+            },
+            op => op.map_bases(|basis| {
+                assert!(
+                    offset + basis.len() <= qubits,
+                    "Cannot place {} qubits at offset {offset} of {qubits} qubits",
+                    basis.len()
+                );
+                let mut embedded = vec![Pauli::I; qubits];
+                embedded[offset..offset + basis.len()].copy_from_slice(&basis);
+                embedded
+            }),
         }
     }
 
+    /// Apply `f` to every basis of the operation
+    pub(crate) fn map_bases(self, mut f: impl FnMut(Vec<Pauli>) -> Vec<Pauli>) -> Self {
+        match self {
+            PbcOperation::Measurement { basis, flip_result } => PbcOperation::Measurement {
+                basis: f(basis),
+                flip_result,
+            },
+            PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy,
+            } => PbcOperation::Rotation {
+                basis: f(basis),
+                angle,
+                accuracy,
+            },
+            PbcOperation::MultiRotation { terms } => PbcOperation::MultiRotation {
+                terms: terms
+                    .into_iter()
+                    .map(|(basis, angle)| (f(basis), angle))
+                    .collect(),
+            },
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy,
+            } => PbcOperation::RotateMeasure {
+                basis: f(basis),
+                angle,
+                flip_result,
+                accuracy,
+            },
+            PbcOperation::Ccz { basis } => PbcOperation::Ccz { basis: f(basis) },
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => PbcOperation::Clifford {
+                basis: f(basis),
+                quarter_turns,
+            },
+            op @ (PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. }) => op,
+        }
+    }
+
+    /// The Paulis of the operation, which are empty for barriers, delays, and permutations
     pub fn basis(&self) -> &Vec<Pauli> {
+        const NO_BASIS: &Vec<Pauli> = &Vec::new();
         match self {
             PbcOperation::Measurement {
                 basis,
                 flip_result: _,
             } => basis,
-            PbcOperation::Rotation { basis, angle: _ } => basis,
+            PbcOperation::Rotation { basis, .. } => basis,
+            PbcOperation::RotateMeasure { basis, .. } => basis,
+            PbcOperation::Ccz { basis } => basis,
+            PbcOperation::Clifford { basis, .. } => basis,
+            // All terms act on the same qubits so we take the basis of the first
+            PbcOperation::MultiRotation { terms } => {
+                &terms
+                    .first()
+                    .expect("A MultiRotation should have at least one term")
+                    .0
+            }
+            PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. } => NO_BASIS,
         }
     }
+
+    /// The first Paulis of a block, with the identity on the pivot, that the measurement table does not implement.
+    /// Only a partial table misses measurements, see [`CompleteMeasurementTable::covers_data`].
+    pub fn uncovered(
+        &self,
+        measurement_table: &dyn MeasurementTable,
+        qubits_per_block: usize,
+    ) -> Option<PauliString> {
+        let bases: Vec<Vec<Pauli>> = match self {
+            PbcOperation::Ccz { basis } => ccz_rotations(basis)
+                .into_iter()
+                .map(|(basis, _)| basis)
+                .collect(),
+            _ => self.bases().cloned().collect(),
+        };
+        bases
+            .iter()
+            .flat_map(|basis| basis.chunks(qubits_per_block))
+            .filter(|block| block.iter().any(|p| *p != Pauli::I))
+            .map(|block| {
+                let mut paulis = [Pauli::I; 12];
+                paulis[1..=block.len()].copy_from_slice(block);
+                PauliString::from(&paulis)
+            })
+            .find(|p| !measurement_table.covers_data(*p))
+    }
+}
+
+/// Fuse each rotation that is immediately followed by a measurement in the same basis
+/// into a single [`PbcOperation::RotateMeasure`].
+/// A fused pair keeps the data of the rotation, see [`StreamedOperation`].
+pub fn fuse_rotation_measurements<T: StreamedOperation>(
+    ops: impl IntoIterator<Item = T>,
+) -> impl Iterator<Item = T> {
+    let mut ops = ops.into_iter().peekable();
+    std::iter::from_fn(move || {
+        let item = ops.next()?;
+        if let PbcOperation::Rotation {
+            basis,
+            angle,
+            accuracy,
+        } = item.op()
+        {
+            let fused = ops.next_if(|next| {
+                matches!(next.op(), PbcOperation::Measurement { basis: next_basis, .. } if next_basis == basis)
+            });
+            if let Some(PbcOperation::Measurement { flip_result, .. }) = fused.as_ref().map(T::op) {
+                let fused = PbcOperation::RotateMeasure {
+                    basis: basis.clone(),
+                    angle: *angle,
+                    flip_result: *flip_result,
+                    accuracy: *accuracy,
+                };
+                return Some(item.map_op(|_| fused));
+            }
+        }
+        Some(item)
+    })
+}
+
+/// Check that `permutation` is a permutation of its indices that keeps every qubit in its block of `qubits_per_block` qubits
+pub fn check_permutation(permutation: &[usize], qubits_per_block: usize) -> Result<(), String> {
+    let mut seen = vec![false; permutation.len()];
+    for (qubit, &target) in permutation.iter().enumerate() {
+        if target >= permutation.len() || seen[target] {
+            return Err(format!(
+                "The permutation {permutation:?} is not a permutation of its {} qubits",
+                permutation.len()
+            ));
+        }
+        seen[target] = true;
+        if target / qubits_per_block != qubit / qubits_per_block {
+            return Err(format!(
+                "The permutation moves qubit {qubit} to qubit {target} in another block of {qubits_per_block} qubits"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check an operation before it is compiled on blocks of `qubits_per_block` qubits:
+/// its permutation, the terms of a multi-rotation, its synthesis accuracy, and its basis, see [`check_basis`]
+pub fn check_operation(op: &PbcOperation, qubits_per_block: usize) -> Result<(), String> {
+    match op {
+        PbcOperation::Permute { permutation } => check_permutation(permutation, qubits_per_block),
+        PbcOperation::MultiRotation { terms } => check_multi_rotation(terms),
+        PbcOperation::Rotation {
+            accuracy: Some(accuracy),
+            ..
+        }
+        | PbcOperation::RotateMeasure {
+            accuracy: Some(accuracy),
+            ..
+        } => small_angle::check_accuracy(*accuracy),
+        _ => Ok(()),
+    }
+    .and_then(|()| check_basis(op))
+}
+
+/// Check that the basis of an operation acts on some qubit,
+/// and that the basis of a [`PbcOperation::Ccz`] is Z on exactly three qubits, see [`ccz_rotations`]
+pub fn check_basis(op: &PbcOperation) -> Result<(), String> {
+    let is_identity = |basis: &[Pauli]| basis.iter().all(|p| *p == Pauli::I);
+    match op {
+        PbcOperation::Measurement { basis, .. }
+        | PbcOperation::Rotation { basis, .. }
+        | PbcOperation::RotateMeasure { basis, .. }
+        | PbcOperation::Clifford { basis, .. }
+            if is_identity(basis) =>
+        {
+            Err("The basis of the operation is the identity on every qubit".to_string())
+        }
+        PbcOperation::MultiRotation { terms } => {
+            match terms.iter().position(|(basis, _)| is_identity(basis)) {
+                Some(i) => Err(format!(
+                    "Term {i} of the MultiRotation is the identity on every qubit"
+                )),
+                None => Ok(()),
+            }
+        }
+        PbcOperation::Ccz { basis }
+            if basis.iter().filter(|p| **p != Pauli::I).count() != 3
+                || basis.iter().any(|p| !matches!(p, Pauli::I | Pauli::Z)) =>
+        {
+            Err("A CCZ gate should have Z on exactly three qubits".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that the blocks of a [`PbcOperation::Barrier`] are among the `total_blocks` blocks of the architecture
+pub fn check_barrier(op: &PbcOperation, total_blocks: usize) -> Result<(), String> {
+    match op {
+        PbcOperation::Barrier {
+            blocks: Some(blocks),
+        } => match blocks.iter().find(|block| **block >= total_blocks) {
+            Some(block) => Err(format!(
+                "The barrier acts on block {block}, but the architecture has only {total_blocks} blocks"
+            )),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Check that the terms of a [`PbcOperation::MultiRotation`] are nonempty, act on the same number of qubits, and mutually commute
+pub fn check_multi_rotation(terms: &[(Vec<Pauli>, AnglePrecision)]) -> Result<(), String> {
+    let Some((first, _)) = terms.first() else {
+        return Err("A MultiRotation needs at least one term".to_string());
+    };
+    for (i, (basis, _)) in terms.iter().enumerate() {
+        if basis.len() != first.len() {
+            return Err(format!(
+                "Term {i} of the MultiRotation acts on {} qubits instead of the {} qubits of term 0",
+                basis.len(),
+                first.len()
+            ));
+        }
+        if let Some(j) = terms[..i]
+            .iter()
+            .position(|(other, _)| !commutes(basis, other))
+        {
+            return Err(format!(
+                "Terms {j} and {i} of the MultiRotation anticommute"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Relabel the qubits of the operations after each [`PbcOperation::Permute`] and remove the permutations.
+///
+/// The qubits that hold the states of the program qubits are tracked at compile time,
+/// so a permutation costs no instructions instead of the measurements of explicit SWAP gates.
+/// A basis is only extended with identities if the permutation moves one of its Paulis beyond its end.
+pub fn relabel_permutations<T: StreamedOperation>(
+    ops: impl IntoIterator<Item = T>,
+) -> impl Iterator<Item = T> {
+    // The qubit that holds the state of each program qubit, where missing qubits hold their own state
+    let mut locations: Vec<usize> = vec![];
+    ops.into_iter().filter_map(move |item| match item.op() {
+        PbcOperation::Permute { permutation } => {
+            let len = locations.len().max(permutation.len());
+            locations.extend(locations.len()..len);
+            let previous = locations.clone();
+            for (qubit, &target) in permutation.iter().enumerate() {
+                locations[target] = previous[qubit];
+            }
+            None
+        }
+        _ if locations.is_empty() => Some(item),
+        _ => Some(item.map_op(|op| {
+            op.map_bases(|basis| {
+                let width = basis.len();
+                let mut relabeled = vec![Pauli::I; width.max(locations.len())];
+                for (qubit, pauli) in basis.into_iter().enumerate() {
+                    relabeled[locations.get(qubit).copied().unwrap_or(qubit)] = pauli;
+                }
+                let used = relabeled
+                    .iter()
+                    .rposition(|pauli| *pauli != Pauli::I)
+                    .map_or(0, |qubit| qubit + 1);
+                relabeled.truncate(width.max(used));
+                relabeled
+            })
+        })),
+    })
+}
+
+/// A [`PbcOperation`] with optional scheduling hints for [`prioritize`].
+/// In JSON the hints are given next to the operation, e.g.,
+/// `{"Measurement":{"basis":["Z","I"],"flip_result":false},"priority":1}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HintedOperation {
+    #[serde(flatten)]
+    pub op: PbcOperation,
+    /// Operations are moved ahead of commuting operations with a lower priority
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+    /// The latest position in the output that other operations may delay this operation to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<usize>,
+    /// The position of the operation in its program, counting from 0, which the reader of the program sets
+    #[serde(skip)]
+    pub index: usize,
+    /// The program of the operation among the programs of [`interleave`]
+    #[serde(skip)]
+    pub program: usize,
+}
+
+fn is_default(priority: &i32) -> bool {
+    *priority == 0
+}
+
+impl From<PbcOperation> for HintedOperation {
+    fn from(op: PbcOperation) -> Self {
+        Self {
+            op,
+            priority: 0,
+            deadline: None,
+            index: 0,
+            program: 0,
+        }
+    }
+}
+
+/// An operation of a program that the passes on programs rewrite, such as [`relabel_permutations`],
+/// which keep the other data of the item, e.g., the position of a [`HintedOperation`] in its program
+pub trait StreamedOperation {
+    fn op(&self) -> &PbcOperation;
+
+    /// Rewrite the operation and keep the other data
+    fn map_op(self, f: impl FnOnce(PbcOperation) -> PbcOperation) -> Self;
+}
+
+impl StreamedOperation for PbcOperation {
+    fn op(&self) -> &PbcOperation {
+        self
+    }
+
+    fn map_op(self, f: impl FnOnce(PbcOperation) -> PbcOperation) -> Self {
+        f(self)
+    }
+}
+
+impl StreamedOperation for HintedOperation {
+    fn op(&self) -> &PbcOperation {
+        &self.op
+    }
+
+    fn map_op(self, f: impl FnOnce(PbcOperation) -> PbcOperation) -> Self {
+        Self {
+            op: f(self.op),
+            ..self
+        }
+    }
+}
+
+/// How to interleave the operations of programs that run in parallel, see [`interleave`]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interleaving {
+    /// Take one operation from each program in turn
+    #[default]
+    RoundRobin,
+    /// Take the next operation with the highest priority, and take turns between equal priorities
+    Priority,
+}
+
+/// Run programs in parallel on a single architecture.
+///
+/// Each program is placed on its own blocks of `qubits_per_block` qubits, in the given order,
+/// and the number of blocks of a program is set by its first operation.
+/// Later operations of a program must not act on more qubits than its first one,
+/// and a program whose first operation does not fill whole blocks is padded with identities,
+/// so callers should check the operations before, as the compiler does without `--pad`.
+/// The operations of the programs are interleaved in the given way.
+pub fn interleave<I>(
+    programs: Vec<I>,
+    interleaving: Interleaving,
+    qubits_per_block: usize,
+) -> impl Iterator<Item = HintedOperation>
+where
+    I: Iterator<Item = HintedOperation>,
+{
+    let mut programs: Vec<_> = programs.into_iter().map(Iterator::peekable).collect();
+    let mut offsets = Vec::with_capacity(programs.len());
+    let mut qubits = 0;
+    for program in &mut programs {
+        offsets.push(qubits);
+        let width = program.peek().map_or(0, |hinted| hinted.op.basis().len());
+        qubits += width.next_multiple_of(qubits_per_block);
+    }
+
+    // Index of the program that is next in turn
+    let mut turn = 0;
+    std::iter::from_fn(move || {
+        let nr_programs = programs.len();
+        let mut in_turn = (0..nr_programs).map(|i| (turn + i) % nr_programs);
+        let next = match interleaving {
+            Interleaving::RoundRobin => in_turn.find(|&i| programs[i].peek().is_some()),
+            Interleaving::Priority => in_turn
+                .filter_map(|i| programs[i].peek().map(|hinted| (i, hinted.priority)))
+                // The first program in turn with the highest priority
+                .rev()
+                .max_by_key(|&(_, priority)| priority)
+                .map(|(i, _)| i),
+        }?;
+        turn = (next + 1) % nr_programs;
+        let hinted = programs[next].next()?;
+        Some(HintedOperation {
+            op: hinted.op.embed(offsets[next], qubits, qubits_per_block),
+            program: next,
+            ..hinted
+        })
+    })
+}
+
+/// Reorder operations by their priority within a window of `window` upcoming operations.
+///
+/// An operation is moved ahead of the operations before it only if it commutes with all of them,
+/// they all have a lower priority, and none of them is delayed past its deadline.
+/// Operations with equal priorities keep their order, so operations without hints are not reordered.
+pub fn prioritize(
+    ops: impl IntoIterator<Item = HintedOperation>,
+    window: usize,
+) -> impl Iterator<Item = HintedOperation> {
+    let mut ops = ops.into_iter();
+    let mut buffer: VecDeque<HintedOperation> = VecDeque::new();
+    let mut emitted = 0;
+    std::iter::from_fn(move || {
+        buffer.extend(ops.by_ref().take(window.max(1) - buffer.len()));
+        let next = (1..buffer.len())
+            .filter(|&j| {
+                let candidate = &buffer[j];
+                buffer.range(..j).enumerate().all(|(k, before)| {
+                    before.priority < candidate.priority
+                        && before.op.commutes_with(&candidate.op)
+                        && before
+                            .deadline
+                            .is_none_or(|deadline| emitted + k < deadline)
+                })
+            })
+            // The first operation with the highest priority
+            .min_by_key(|&j| (-buffer[j].priority, j))
+            .unwrap_or(0);
+        emitted += 1;
+        buffer.remove(next)
+    })
+}
+
+/// The basis of `qubits` qubits with the given Paulis and the identity elsewhere
+fn sparse_basis(paulis: &[(usize, Pauli)], qubits: usize) -> Vec<Pauli> {
+    let mut basis = vec![Pauli::I; qubits];
+    for &(qubit, p) in paulis {
+        basis[qubit] = p;
+    }
+    basis
+}
+
+/// The seven T rotations that implement a CCZ gate on the qubits where `basis` is Z:
+/// CCZ = exp(iπ/8 (1 - Z₁)(1 - Z₂)(1 - Z₃)) up to a global phase
+pub fn ccz_rotations(basis: &[Pauli]) -> Vec<(Vec<Pauli>, AnglePrecision)> {
+    let controls: Vec<_> = basis
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| (*p != Pauli::I).then_some(i))
+        .collect();
+    assert!(
+        controls.len() == 3 && controls.iter().all(|&i| basis[i] == Pauli::Z),
+        "A CCZ gate should have Z on exactly three qubits"
+    );
+    // Each subset of the controls, with the sign of its rotation
+    (1..8usize)
+        .map(|subset| {
+            let mut term = vec![Pauli::I; basis.len()];
+            for (bit, control) in controls.iter().enumerate() {
+                if subset & (1 << bit) != 0 {
+                    term[*control] = Pauli::Z;
+                }
+            }
+            let angle = if subset.count_ones() % 2 == 1 {
+                small_angle::T_ANGLE
+            } else {
+                -small_angle::T_ANGLE
+            };
+            (term, angle)
+        })
+        .collect()
+}
+
+/// Check if the Paulis of `basis` are I or Z where `controls` is not the identity
+fn diagonal_on(controls: &[Pauli], basis: &[Pauli]) -> bool {
+    controls
+        .iter()
+        .zip(basis)
+        .all(|(control, p)| *control == Pauli::I || matches!(p, Pauli::I | Pauli::Z))
+}
+
+/// Check if two Paulis strings commute
+pub fn commutes(basis0: &[Pauli], basis1: &[Pauli]) -> bool {
+    let anticommuting = basis0
+        .iter()
+        .zip(basis1)
+        .filter(|(p0, p1)| **p0 != Pauli::I && **p1 != Pauli::I && p0 != p1)
+        .count();
+    anticommuting % 2 == 0
 }
 
 impl Display for PbcOperation {
@@ -97,7 +973,7 @@ impl Display for PbcOperation {
                     write!(f, "regular)")
                 }
             }
-            PbcOperation::Rotation { basis, angle } => {
+            PbcOperation::Rotation { basis, angle, .. } => {
                 write!(
                     f,
                     "Rotation([{}],{})",
@@ -109,6 +985,951 @@ impl Display for PbcOperation {
                     angle
                 )
             }
+            PbcOperation::MultiRotation { terms } => {
+                write!(
+                    f,
+                    "MultiRotation({})",
+                    terms
+                        .iter()
+                        .map(|(basis, angle)| format!(
+                            "[{}],{}",
+                            basis
+                                .iter()
+                                .map(|p| p.to_string())
+                                .collect::<Vec<_>>()
+                                .join(","),
+                            angle
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(";")
+                )
+            }
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                ..
+            } => {
+                write!(
+                    f,
+                    "RotateMeasure([{}],{},",
+                    basis
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    angle
+                )?;
+                if *flip_result {
+                    write!(f, "flipped)")
+                } else {
+                    write!(f, "regular)")
+                }
+            }
+            PbcOperation::Ccz { basis } => {
+                write!(
+                    f,
+                    "CCZ([{}])",
+                    basis
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )
+            }
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => {
+                write!(
+                    f,
+                    "Clifford([{}],{})",
+                    basis
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    quarter_turns
+                )
+            }
+            PbcOperation::Barrier { blocks: None } => write!(f, "Barrier"),
+            PbcOperation::Barrier {
+                blocks: Some(blocks),
+            } => write!(
+                f,
+                "Barrier({})",
+                blocks
+                    .iter()
+                    .map(|block| block.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            PbcOperation::Delay { cycles } => write!(f, "Delay({cycles})"),
+            PbcOperation::Permute { permutation } => write!(
+                f,
+                "Permute({})",
+                permutation
+                    .iter()
+                    .map(|qubit| qubit.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
         }
     }
 }
+
+/// A line of PBC text that cannot be parsed, see [`parse_pbc_text`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PbcTextError {
+    /// The line of the text, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for PbcTextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for PbcTextError {}
+
+/// The angles of the form `n*pi/d` that PBC text prints, where `pi/d` is the fixed-point constant
+const PI_FRACTIONS: [(u32, AnglePrecision); 4] = [
+    (1, AnglePrecision::PI),
+    (2, AnglePrecision::FRAC_PI_2),
+    (4, AnglePrecision::FRAC_PI_4),
+    (8, AnglePrecision::FRAC_PI_8),
+];
+
+/// Print an angle as `n*pi/d` if it is an exact multiple of `pi/d` for d in 1, 2, 4, or 8, and as a decimal otherwise
+fn format_angle(angle: AnglePrecision) -> String {
+    for (d, fraction) in PI_FRACTIONS {
+        let Some(n) = angle.checked_div(fraction).map(|n| n.round()) else {
+            continue;
+        };
+        if n.checked_mul(fraction) != Some(angle) {
+            continue;
+        }
+        let n: i64 = n.to_num();
+        let pi = if d == 1 {
+            "pi".to_string()
+        } else {
+            format!("pi/{d}")
+        };
+        return match n {
+            0 => "0".to_string(),
+            1 => pi,
+            -1 => format!("-{pi}"),
+            n => format!("{n}*{pi}"),
+        };
+    }
+    angle.to_string()
+}
+
+/// Parse an angle printed by [`format_angle`], i.e., `[-][n*]pi[/d]` or a decimal
+fn parse_angle(s: &str) -> Result<AnglePrecision, String> {
+    let invalid = || format!("Invalid angle {s}, expected a decimal or [-][n*]pi[/d]");
+    let Some((numerator, denominator)) = s.split_once("pi") else {
+        return AnglePrecision::from_str(s).map_err(|_| invalid());
+    };
+    let n: i64 = match numerator.strip_suffix('*') {
+        Some(n) => n.parse().map_err(|_| invalid())?,
+        None if numerator.is_empty() => 1,
+        None if numerator == "-" => -1,
+        None => return Err(invalid()),
+    };
+    let fraction = match denominator.strip_prefix('/') {
+        None if denominator.is_empty() => AnglePrecision::PI,
+        None => return Err(invalid()),
+        Some(d) => {
+            let d: u32 = d.parse().map_err(|_| invalid())?;
+            match PI_FRACTIONS.iter().find(|(fraction_d, _)| *fraction_d == d) {
+                Some((_, fraction)) => *fraction,
+                None => AnglePrecision::PI
+                    .checked_div_int(d.into())
+                    .ok_or_else(invalid)?,
+            }
+        }
+    };
+    fraction
+        .checked_mul_int(n.into())
+        .ok_or_else(|| format!("The angle {s} is out of range"))
+}
+
+fn format_paulis(basis: &[Pauli]) -> String {
+    basis.iter().map(|p| p.to_string()).collect()
+}
+
+fn parse_paulis(s: &str) -> Result<Vec<Pauli>, String> {
+    s.chars().map(|c| Pauli::try_from(&c)).collect()
+}
+
+fn format_list(items: &[usize]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_list(s: &str) -> Result<Vec<usize>, String> {
+    s.split(',')
+        .map(|item| item.parse().map_err(|_| format!("Invalid number {item}")))
+        .collect()
+}
+
+impl PbcOperation {
+    /// Print the operation as a line of PBC text, e.g., `rot XZIIY pi/4`, which [`PbcOperation::from_str`] parses back.
+    ///
+    /// Each operation starts with its keyword, followed by its basis as one letter per qubit and its arguments:
+    /// `rot <BASIS> <ANGLE> [accuracy=<ACCURACY>]`, `meas <BASIS> [flip]`,
+    /// `rotmeas <BASIS> <ANGLE> [flip] [accuracy=<ACCURACY>]`, `multirot <BASIS> <ANGLE> [<BASIS> <ANGLE> ...]`,
+    /// `ccz <BASIS>`, `clifford <BASIS> <QUARTER_TURNS>`, `barrier [<BLOCK>,...]`, `delay <CYCLES>`, and `permute <QUBIT>,...`.
+    /// Angles that are multiples of pi/8 are printed as, e.g., `-3*pi/8`, and other angles as decimals.
+    pub fn to_pbc_text(&self) -> String {
+        let accuracy = |accuracy: &Option<AnglePrecision>| match accuracy {
+            Some(accuracy) => format!(" accuracy={accuracy}"),
+            None => String::new(),
+        };
+        let flip = |flip_result: bool| if flip_result { " flip" } else { "" };
+        match self {
+            PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy: acc,
+            } => format!(
+                "rot {} {}{}",
+                format_paulis(basis),
+                format_angle(*angle),
+                accuracy(acc)
+            ),
+            PbcOperation::Measurement { basis, flip_result } => {
+                format!("meas {}{}", format_paulis(basis), flip(*flip_result))
+            }
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy: acc,
+            } => format!(
+                "rotmeas {} {}{}{}",
+                format_paulis(basis),
+                format_angle(*angle),
+                flip(*flip_result),
+                accuracy(acc)
+            ),
+            PbcOperation::MultiRotation { terms } => {
+                let mut line = "multirot".to_string();
+                for (basis, angle) in terms {
+                    line += &format!(" {} {}", format_paulis(basis), format_angle(*angle));
+                }
+                line
+            }
+            PbcOperation::Ccz { basis } => format!("ccz {}", format_paulis(basis)),
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => format!("clifford {} {quarter_turns}", format_paulis(basis)),
+            PbcOperation::Barrier { blocks: None } => "barrier".to_string(),
+            PbcOperation::Barrier {
+                blocks: Some(blocks),
+            } => format!("barrier {}", format_list(blocks)),
+            PbcOperation::Delay { cycles } => format!("delay {cycles}"),
+            PbcOperation::Permute { permutation } => {
+                format!("permute {}", format_list(permutation))
+            }
+        }
+    }
+}
+
+/// Parse a line of PBC text, see [`PbcOperation::to_pbc_text`]
+impl FromStr for PbcOperation {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().ok_or("Expected an operation")?;
+        let args: Vec<_> = tokens.collect();
+        // The arguments after the positional ones, which are a flag or an accuracy
+        let options = |positional: usize, flag: bool| {
+            let mut flip_result = false;
+            let mut accuracy = None;
+            for option in args.iter().skip(positional) {
+                if flag && *option == "flip" {
+                    flip_result = true;
+                } else if let Some(value) = option.strip_prefix("accuracy=") {
+                    let value = AnglePrecision::from_str(value)
+                        .map_err(|_| format!("Invalid accuracy {value}"))?;
+                    accuracy = Some(value);
+                } else {
+                    return Err(format!("Unexpected argument {option} of {keyword}"));
+                }
+            }
+            Ok((flip_result, accuracy))
+        };
+        let arity = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Expected {n} arguments of {keyword}, not {}",
+                    args.len()
+                ))
+            }
+        };
+        let positional = |n: usize| {
+            if args.len() >= n {
+                Ok(())
+            } else {
+                Err(format!("Expected at least {n} arguments of {keyword}"))
+            }
+        };
+        match keyword {
+            "rot" => {
+                positional(2)?;
+                let (_, accuracy) = options(2, false)?;
+                Ok(PbcOperation::Rotation {
+                    basis: parse_paulis(args[0])?,
+                    angle: parse_angle(args[1])?,
+                    accuracy,
+                })
+            }
+            "meas" => {
+                positional(1)?;
+                let (flip_result, accuracy) = options(1, true)?;
+                if accuracy.is_some() {
+                    return Err("A measurement has no accuracy".to_string());
+                }
+                Ok(PbcOperation::Measurement {
+                    basis: parse_paulis(args[0])?,
+                    flip_result,
+                })
+            }
+            "rotmeas" => {
+                positional(2)?;
+                let (flip_result, accuracy) = options(2, true)?;
+                Ok(PbcOperation::RotateMeasure {
+                    basis: parse_paulis(args[0])?,
+                    angle: parse_angle(args[1])?,
+                    flip_result,
+                    accuracy,
+                })
+            }
+            "multirot" => {
+                if args.is_empty() || args.len() % 2 != 0 {
+                    return Err("Expected pairs of a basis and an angle of multirot".to_string());
+                }
+                let terms = args
+                    .chunks(2)
+                    .map(|term| Ok((parse_paulis(term[0])?, parse_angle(term[1])?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(PbcOperation::MultiRotation { terms })
+            }
+            "ccz" => {
+                arity(1)?;
+                Ok(PbcOperation::Ccz {
+                    basis: parse_paulis(args[0])?,
+                })
+            }
+            "clifford" => {
+                arity(2)?;
+                Ok(PbcOperation::Clifford {
+                    basis: parse_paulis(args[0])?,
+                    quarter_turns: args[1]
+                        .parse()
+                        .map_err(|_| format!("Invalid number of quarter turns {}", args[1]))?,
+                })
+            }
+            "barrier" if args.is_empty() => Ok(PbcOperation::Barrier { blocks: None }),
+            "barrier" => {
+                arity(1)?;
+                Ok(PbcOperation::Barrier {
+                    blocks: Some(parse_list(args[0])?),
+                })
+            }
+            "delay" => {
+                arity(1)?;
+                Ok(PbcOperation::Delay {
+                    cycles: args[0]
+                        .parse()
+                        .map_err(|_| format!("Invalid number of cycles {}", args[0]))?,
+                })
+            }
+            "permute" => {
+                arity(1)?;
+                Ok(PbcOperation::Permute {
+                    permutation: parse_list(args[0])?,
+                })
+            }
+            keyword => Err(format!("Unknown operation {keyword}")),
+        }
+    }
+}
+
+/// Parse a line of PBC text, or `None` for a line without an operation.
+/// Everything after a `#` is a comment.
+pub fn parse_pbc_text_line(line: &str) -> Result<Option<PbcOperation>, String> {
+    let line = line.split_once('#').map_or(line, |(op, _)| op).trim();
+    if line.is_empty() {
+        Ok(None)
+    } else {
+        line.parse().map(Some)
+    }
+}
+
+/// Parse a program in PBC text, one operation per line, see [`PbcOperation::to_pbc_text`]
+pub fn parse_pbc_text(text: &str) -> Result<Vec<PbcOperation>, PbcTextError> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            parse_pbc_text_line(line)
+                .map_err(|message| PbcTextError {
+                    line: i + 1,
+                    message,
+                })
+                .transpose()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::{PathArchitecture, QUBITS_PER_BLOCK};
+    use bicycle_cliffords::CompleteMeasurementTable;
+
+    use Pauli::{I, X, Y, Z};
+
+    #[test]
+    fn pbc_text_round_trip() -> Result<(), PbcTextError> {
+        let text = "\
+rot XZIIY pi/4
+rot ZZ -3*pi/8 accuracy=0.000000000001
+rot XI 0.1
+meas ZX flip
+meas XI
+rotmeas YY -pi/2 flip accuracy=0.001
+multirot ZZI pi/4 IZZ 2*pi/4
+ccz ZIZZ
+clifford ZI 3
+barrier 0,2
+barrier
+delay 100
+permute 1,0
+";
+        let ops = parse_pbc_text(text)?;
+        assert_eq!(
+            PbcOperation::Rotation {
+                basis: vec![X, Z, I, I, Y],
+                angle: small_angle::T_ANGLE,
+                accuracy: None,
+            },
+            ops[0]
+        );
+        let PbcOperation::Rotation { angle, .. } = ops[2] else {
+            panic!("Expected a rotation, not {}", ops[2]);
+        };
+        assert_eq!(AnglePrecision::lit("0.1"), angle);
+        assert_eq!(
+            PbcOperation::Measurement {
+                basis: vec![Z, X],
+                flip_result: true
+            },
+            ops[3]
+        );
+        let printed: String = ops.iter().map(|op| op.to_pbc_text() + "\n").collect();
+        // `2*pi/4` is not rounded like `pi/2`, so it is printed as it is
+        assert_eq!(text, printed);
+        assert_eq!(ops, parse_pbc_text(&printed)?);
+
+        // Angles that are not multiples of the constants are printed exactly
+        let angle = AnglePrecision::PI / AnglePrecision::lit("4") + AnglePrecision::DELTA;
+        let op = PbcOperation::Rotation {
+            basis: vec![X],
+            angle,
+            accuracy: None,
+        };
+        assert_eq!(op, op.to_pbc_text().parse().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn pbc_text_errors() {
+        let text = "# A comment\n\nrot XZ pi/4 # T gate\nrot XQ pi\n";
+        assert_eq!(
+            2,
+            parse_pbc_text_line("rot XZ pi/4 # T gate")
+                .unwrap()
+                .unwrap()
+                .basis()
+                .len()
+        );
+        assert_eq!(Ok(None), parse_pbc_text_line("  # A comment"));
+        let err = parse_pbc_text(text).unwrap_err();
+        assert_eq!(4, err.line);
+        for invalid in [
+            "rot XZ",
+            "rot XZ pi/4 flip",
+            "meas XZ accuracy=0.1",
+            "frobnicate X",
+            "delay -1",
+            "multirot XZ",
+        ] {
+            assert!(invalid.parse::<PbcOperation>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn commuting_paulis() {
+        assert!(commutes(&[X, X], &[Z, Z]));
+        assert!(commutes(&[X, I], &[I, Z]));
+        assert!(!commutes(&[X, I], &[Z, I]));
+        assert!(commutes(&[X, Y, Z], &[Y, Y, Y]));
+        assert!(!commutes(&[X, Y, Z], &[Y, Y, I]));
+    }
+
+    #[test]
+    fn uncovered_blocks() {
+        use bicycle_cliffords::{
+            GROSS_MEASUREMENT, MeasurementTableBuilder, native_measurement::NativeFilter,
+        };
+
+        // Too few automorphisms to reach all logical qubits
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let mut builder = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        builder.build();
+        let table = builder.partial();
+
+        let mut basis = vec![I; 22];
+        basis[15] = Z;
+        let covered = PbcOperation::Measurement {
+            basis: basis.clone(),
+            flip_result: false,
+        };
+        assert_eq!(None, covered.uncovered(&table, QUBITS_PER_BLOCK));
+        basis[0] = Z;
+        let uncovered = PbcOperation::rotation(basis, 0.1);
+        assert_eq!(
+            Some(PauliString::from(&[I, Z, I, I, I, I, I, I, I, I, I, I])),
+            uncovered.uncovered(&table, QUBITS_PER_BLOCK)
+        );
+    }
+
+    #[test]
+    fn parse_ccz() -> Result<(), serde_json::Error> {
+        let op: PbcOperation = serde_json::from_str(r#"{"CCZ":{"basis":["Z","I","Z","Z"]}}"#)?;
+        assert_eq!(PbcOperation::ccz([0, 2, 3], 4), op);
+        assert_eq!("CCZ([Z,I,Z,Z])", op.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn clifford_gates_match_stim() -> Result<(), crate::stim::StimError> {
+        assert_eq!(crate::stim::parse("CX 0 1")?, PbcOperation::cx(0, 1, 2));
+        assert_eq!(crate::stim::parse("CZ 1 0")?, PbcOperation::cz(1, 0, 2));
+        assert_eq!(crate::stim::parse("H 2")?, PbcOperation::h(2, 3));
+        assert_eq!(crate::stim::parse("S 0")?, PbcOperation::s(0, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_timing_operations() -> Result<(), serde_json::Error> {
+        let barrier: PbcOperation = serde_json::from_str(r#"{"Barrier":{"blocks":[0,2]}}"#)?;
+        assert_eq!(
+            PbcOperation::Barrier {
+                blocks: Some(vec![0, 2])
+            },
+            barrier
+        );
+        assert_eq!("Barrier(0,2)", barrier.to_string());
+        let all: PbcOperation = serde_json::from_str(r#"{"Barrier":{}}"#)?;
+        assert_eq!(PbcOperation::Barrier { blocks: None }, all);
+        assert_eq!(r#"{"Barrier":{}}"#, serde_json::to_string(&all)?);
+        let delay: PbcOperation = serde_json::from_str(r#"{"Delay":{"cycles":100}}"#)?;
+        assert_eq!(PbcOperation::Delay { cycles: 100 }, delay);
+        assert_eq!("Delay(100)", delay.to_string());
+
+        // Timing operations act on no qubits and keep their place
+        assert!(barrier.basis().is_empty());
+        assert_eq!(0, delay.padding(11));
+        let rotation = PbcOperation::rotation(vec![Z, I], 0.125);
+        assert!(!rotation.commutes_with(&delay));
+        assert!(!barrier.commutes_with(&rotation));
+
+        // Blocks move along when a program is placed after another one
+        assert_eq!(
+            PbcOperation::Barrier {
+                blocks: Some(vec![1, 3])
+            },
+            barrier.clone().embed(11, 44, 11)
+        );
+        assert_eq!(all, all.clone().embed(11, 44, 11));
+
+        // Barriers on blocks beyond the architecture are malformed
+        assert_eq!(Ok(()), check_barrier(&barrier, 3));
+        assert!(check_barrier(&barrier, 2).is_err());
+        assert_eq!(Ok(()), check_barrier(&all, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn relabel_in_block_permutations() -> Result<(), serde_json::Error> {
+        let swap: PbcOperation = serde_json::from_str(r#"{"Permute":{"permutation":[1,0]}}"#)?;
+        assert_eq!(
+            PbcOperation::Permute {
+                permutation: vec![1, 0]
+            },
+            swap
+        );
+        assert_eq!("Permute(1,0)", swap.to_string());
+
+        let measurement = |basis| PbcOperation::Measurement {
+            basis,
+            flip_result: false,
+        };
+        let ops = vec![
+            measurement(vec![Z, I, X]),
+            swap.clone(),
+            measurement(vec![Z, I, X]),
+            PbcOperation::Permute {
+                permutation: vec![0, 2, 1],
+            },
+            PbcOperation::rotation(vec![Z, X, Y], 0.125),
+        ];
+        let relabeled: Vec<_> = relabel_permutations(ops).collect();
+        assert_eq!(
+            vec![
+                measurement(vec![Z, I, X]),
+                measurement(vec![I, Z, X]),
+                PbcOperation::rotation(vec![Y, Z, X], 0.125),
+            ],
+            relabeled
+        );
+
+        // The permutations are removed, so relabeling costs no instructions
+        let table = CompleteMeasurementTable::from_shards(Default::default(), []);
+        assert!(
+            swap.compile(
+                &PathArchitecture::new(2),
+                &table,
+                AnglePrecision::lit("1e-9")
+            )
+            .is_empty()
+        );
+        assert!(!swap.commutes_with(&measurement(vec![Z, I])));
+        assert_eq!(
+            PbcOperation::Permute {
+                permutation: [(0..11).collect(), vec![12, 11]].concat()
+            },
+            swap.embed(11, 22, 11)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn permutations_within_blocks() {
+        assert_eq!(Ok(()), check_permutation(&[1, 0, 2], 11));
+        assert!(check_permutation(&[0, 0], 11).is_err());
+        assert!(check_permutation(&[2, 0], 11).is_err());
+        let mut across_blocks: Vec<_> = (0..22).collect();
+        across_blocks.swap(0, 11);
+        assert!(check_permutation(&across_blocks, 11).is_err());
+    }
+
+    #[test]
+    fn malformed_multi_rotations() {
+        let angle = AnglePrecision::lit("0.125");
+        assert_eq!(
+            Ok(()),
+            check_multi_rotation(&[(vec![X, X], angle), (vec![Z, Z], angle)])
+        );
+        assert!(check_multi_rotation(&[]).is_err());
+        assert!(check_multi_rotation(&[(vec![X, X], angle), (vec![Z], angle)]).is_err());
+        assert!(check_multi_rotation(&[(vec![X, I], angle), (vec![Z, I], angle)]).is_err());
+    }
+
+    #[test]
+    fn identity_bases() {
+        let angle = AnglePrecision::lit("0.125");
+        assert_eq!(
+            Ok(()),
+            check_basis(&PbcOperation::Measurement {
+                basis: vec![I, Y],
+                flip_result: false,
+            })
+        );
+        assert!(
+            check_basis(&PbcOperation::Measurement {
+                basis: vec![I, I],
+                flip_result: false,
+            })
+            .is_err()
+        );
+        assert!(check_basis(&PbcOperation::rotation(vec![], 0.1)).is_err());
+        assert!(
+            check_basis(&PbcOperation::MultiRotation {
+                terms: vec![(vec![Z, I], angle), (vec![I, I], angle)],
+            })
+            .is_err()
+        );
+        let ccz = |basis: Vec<Pauli>| check_basis(&PbcOperation::Ccz { basis });
+        assert_eq!(Ok(()), ccz(vec![Z, I, Z, Z]));
+        assert!(ccz(vec![Z, Z, I, I]).is_err());
+        assert!(ccz(vec![Z, X, Z, I]).is_err());
+        assert_eq!(Ok(()), check_basis(&PbcOperation::Delay { cycles: 1 }));
+    }
+
+    #[test]
+    fn malformed_operations() {
+        let rotation = |accuracy: &str| PbcOperation::Rotation {
+            basis: vec![X, Z],
+            angle: AnglePrecision::lit("0.1"),
+            accuracy: Some(accuracy.parse().unwrap()),
+        };
+        assert_eq!(Ok(()), check_operation(&rotation("1e-9"), 11));
+        assert!(check_operation(&rotation("0.5"), 11).is_err());
+        assert!(check_operation(&PbcOperation::rotation(vec![I, I], 0.1), 11).is_err());
+        let permutation = PbcOperation::Permute {
+            permutation: vec![1, 0],
+        };
+        assert_eq!(Ok(()), check_operation(&permutation, 2));
+        assert!(check_operation(&permutation, 1).is_err());
+        let terms = PbcOperation::MultiRotation { terms: vec![] };
+        assert!(check_operation(&terms, 11).is_err());
+    }
+
+    #[test]
+    fn parse_accuracy_override() -> Result<(), serde_json::Error> {
+        let default = AnglePrecision::lit("1e-9");
+        let op: PbcOperation = serde_json::from_str(
+            r#"{"Rotation":{"basis":["X","Z"],"angle":"0.1","accuracy":"1e-12"}}"#,
+        )?;
+        assert_eq!(AnglePrecision::lit("1e-12"), op.accuracy(default));
+        assert_eq!(op, serde_json::from_str(&serde_json::to_string(&op)?)?);
+
+        let op: PbcOperation =
+            serde_json::from_str(r#"{"Rotation":{"basis":["X","Z"],"angle":"0.1"}}"#)?;
+        assert_eq!(default, op.accuracy(default));
+        assert!(!serde_json::to_string(&op)?.contains("accuracy"));
+
+        // The override is kept when fusing with a measurement
+        let measurement = PbcOperation::Measurement {
+            basis: vec![X, Z],
+            flip_result: false,
+        };
+        let rotation = PbcOperation::Rotation {
+            basis: vec![X, Z],
+            angle: AnglePrecision::lit("0.1"),
+            accuracy: Some(AnglePrecision::lit("1e-3")),
+        };
+        let fused: Vec<_> = fuse_rotation_measurements([rotation, measurement]).collect();
+        assert_eq!(AnglePrecision::lit("1e-3"), fused[0].accuracy(default));
+        Ok(())
+    }
+
+    #[test]
+    fn ccz_rotation_signs() {
+        let terms = ccz_rotations(&[Z, I, Z, Z]);
+        assert_eq!(7, terms.len());
+        for (basis, angle) in &terms {
+            let weight = basis.iter().filter(|p| **p == Z).count();
+            let expected = if weight % 2 == 1 { 1 } else { -1 };
+            assert_eq!(expected * small_angle::T_ANGLE, *angle);
+            assert_eq!(I, basis[1]);
+        }
+    }
+
+    #[test]
+    fn ccz_commutes_with_diagonal_paulis() {
+        let ccz = PbcOperation::ccz([0, 1, 2], 4);
+        assert!(ccz.commutes_with(&PbcOperation::rotation(vec![Z, I, Z, X], 0.125)));
+        assert!(ccz.commutes_with(&PbcOperation::ccz([1, 2, 3], 4)));
+        // X X commutes with Z Z but not with CCZ
+        let xx = PbcOperation::rotation(vec![X, X, I, I], 0.125);
+        assert!(!ccz.commutes_with(&xx));
+        assert!(!xx.commutes_with(&ccz));
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(0, PbcOperation::rotation(vec![X; 22], 0.125).padding(11));
+        assert_eq!(10, PbcOperation::rotation(vec![X; 12], 0.125).padding(11));
+        assert_eq!(1, PbcOperation::rotation(vec![X; 10], 0.125).padding(11));
+        assert_eq!(2, PbcOperation::rotation(vec![X; 10], 0.125).padding(12));
+    }
+
+    #[test]
+    fn parse_multi_rotation() -> Result<(), serde_json::Error> {
+        let op: PbcOperation = serde_json::from_str(
+            r#"{"MultiRotation":{"terms":[[["Z","Z"],"0.125"],[["X","X"],"-0.25"]]}}"#,
+        )?;
+        let expected = PbcOperation::MultiRotation {
+            terms: vec![
+                (vec![Z, Z], AnglePrecision::lit("0.125")),
+                (vec![X, X], AnglePrecision::lit("-0.25")),
+            ],
+        };
+        assert_eq!(expected, op);
+        assert_eq!(&vec![Z, Z], op.basis());
+        Ok(())
+    }
+
+    #[test]
+    fn fuse_rotation_then_measurement() {
+        let angle = AnglePrecision::lit("0.125");
+        let ops = vec![
+            PbcOperation::Rotation {
+                basis: vec![X, Z],
+                angle,
+                accuracy: None,
+            },
+            PbcOperation::Measurement {
+                basis: vec![X, Z],
+                flip_result: true,
+            },
+            PbcOperation::Rotation {
+                basis: vec![X, Z],
+                angle,
+                accuracy: None,
+            },
+            PbcOperation::Measurement {
+                basis: vec![Z, Z],
+                flip_result: false,
+            },
+        ];
+
+        let fused: Vec<_> = fuse_rotation_measurements(ops.clone()).collect();
+        assert_eq!(
+            vec![
+                PbcOperation::RotateMeasure {
+                    basis: vec![X, Z],
+                    angle,
+                    flip_result: true,
+                    accuracy: None,
+                },
+                ops[2].clone(),
+                ops[3].clone(),
+            ],
+            fused
+        );
+    }
+
+    #[test]
+    fn parse_hints() -> Result<(), serde_json::Error> {
+        let hinted: HintedOperation = serde_json::from_str(
+            r#"{"Measurement":{"basis":["Z","I"],"flip_result":false},"priority":2,"deadline":5}"#,
+        )?;
+        assert_eq!(2, hinted.priority);
+        assert_eq!(Some(5), hinted.deadline);
+
+        let plain: HintedOperation =
+            serde_json::from_str(r#"{"Rotation":{"basis":["X","X"],"angle":"0.125"}}"#)?;
+        assert_eq!(
+            HintedOperation::from(PbcOperation::rotation(vec![X, X], 0.125)),
+            plain
+        );
+        Ok(())
+    }
+
+    fn hinted(basis: Vec<Pauli>, priority: i32, deadline: Option<usize>) -> HintedOperation {
+        HintedOperation {
+            op: PbcOperation::Measurement {
+                basis,
+                flip_result: false,
+            },
+            priority,
+            deadline,
+            index: 0,
+            program: 0,
+        }
+    }
+
+    #[test]
+    fn prioritize_commuting_operations() {
+        let ops = vec![
+            hinted(vec![Z, I, I], 0, None),
+            hinted(vec![I, Z, I], 0, None),
+            hinted(vec![I, I, X], 1, None),
+        ];
+        let scheduled: Vec<_> = prioritize(ops.clone(), 8).map(|hinted| hinted.op).collect();
+        assert_eq!(ops[2].op, scheduled[0]);
+        assert_eq!(ops[0].op, scheduled[1]);
+        assert_eq!(ops[1].op, scheduled[2]);
+
+        // Without a window to look ahead the order is kept
+        let scheduled: Vec<_> = prioritize(ops.clone(), 1).map(|hinted| hinted.op).collect();
+        let expected: Vec<_> = ops.into_iter().map(|hinted| hinted.op).collect();
+        assert_eq!(expected, scheduled);
+    }
+
+    #[test]
+    fn prioritize_keeps_anticommuting_order() {
+        let ops = vec![hinted(vec![Z, I], 0, None), hinted(vec![X, I], 1, None)];
+        let scheduled: Vec<_> = prioritize(ops.clone(), 8).map(|hinted| hinted.op).collect();
+        let expected: Vec<_> = ops.into_iter().map(|hinted| hinted.op).collect();
+        assert_eq!(expected, scheduled);
+    }
+
+    #[test]
+    fn prioritize_respects_deadlines() {
+        let ops = vec![
+            hinted(vec![Z, I, I], 0, Some(1)),
+            hinted(vec![I, Z, I], 0, None),
+            hinted(vec![I, I, Z], 1, None),
+            hinted(vec![I, I, X], 2, None),
+        ];
+        let scheduled: Vec<_> = prioritize(ops.clone(), 8).map(|hinted| hinted.op).collect();
+        // The last operation anticommutes with the third so it has to wait for it,
+        // and then it cannot overtake the first because of its deadline.
+        let expected: Vec<_> = [2, 0, 3, 1].map(|i| ops[i].op.clone()).into();
+        assert_eq!(expected, scheduled);
+    }
+
+    #[test]
+    fn interleave_round_robin() {
+        let first = vec![hinted(vec![Z; 11], 0, None), hinted(vec![X; 11], 0, None)];
+        let second = vec![hinted(vec![Y; 5], 0, None)];
+
+        let interleaved: Vec<_> = interleave(
+            vec![first.into_iter(), second.into_iter()],
+            Interleaving::RoundRobin,
+            11,
+        )
+        .map(|hinted| hinted.op.basis().clone())
+        .collect();
+
+        let padded_y: Vec<_> = [vec![I; 11], vec![Y; 5], vec![I; 6]].concat();
+        assert_eq!(
+            vec![
+                [vec![Z; 11], vec![I; 11]].concat(),
+                padded_y,
+                [vec![X; 11], vec![I; 11]].concat(),
+            ],
+            interleaved
+        );
+    }
+
+    #[test]
+    fn interleave_by_priority() {
+        let first = vec![hinted(vec![Z; 11], 0, None), hinted(vec![X; 11], 0, None)];
+        let second = vec![hinted(vec![Y; 11], 0, None), hinted(vec![Z; 11], 1, None)];
+
+        let interleaved: Vec<_> = interleave(
+            vec![first.into_iter(), second.into_iter()],
+            Interleaving::Priority,
+            11,
+        )
+        .map(|hinted| (hinted.op.basis()[0], hinted.op.basis()[11], hinted.priority))
+        .collect();
+
+        // Take turns at equal priority until the second program has a higher priority
+        assert_eq!(
+            vec![(Z, I, 0), (I, Y, 0), (I, Z, 1), (X, I, 0)],
+            interleaved
+        );
+    }
+}
@@ -12,37 +12,214 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod analysis;
 mod architecture;
 mod basis_changer;
+pub mod budget;
 mod compile;
+pub mod explain;
+pub mod export;
+pub mod fault;
 pub mod language;
+pub mod model;
+pub mod observable;
 pub mod operation;
 pub mod optimize;
+pub mod parametric;
+pub mod pipeline;
+pub mod qasm;
+pub mod qre;
+pub mod report;
 pub mod small_angle;
+pub mod snap;
+pub mod stim;
+pub mod sweep;
+pub mod verify;
 
-use std::{error::Error, path::Path};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
 
-pub use architecture::PathArchitecture;
-use bicycle_cliffords::CompleteMeasurementTable;
+pub use architecture::{
+    Architecture, BlockRole, PathArchitecture, QUBITS_PER_BLOCK, RingArchitecture, SGateLowering,
+    TInjection, Topology, TreeArchitecture,
+};
+use bicycle_cliffords::{
+    CompleteMeasurementTable, MinimalMeasurementTable, PauliString, decomposition::TableShard,
+    native_measurement::NativeMeasurement,
+};
+pub use compile::{BlockBasisState, CliffordFrame};
+use serde::{Deserialize, Serialize};
 
+/// The first bytes of a table that is stored in shards, see [`serialize_shards`]
+const SHARDS_MAGIC: &[u8; 8] = b"bicyshrd";
+
+/// Follows [`SHARDS_MAGIC`] and the length of the header, and precedes the shards
+#[derive(Serialize, Deserialize)]
+struct ShardsHeader {
+    native_measurements: HashMap<PauliString, NativeMeasurement>,
+    /// Number of bytes of each shard
+    lengths: Vec<u64>,
+}
+
+/// Read a table that is written by `bitcode`, by [`serialize_shards`], or by [`CompleteMeasurementTable::write_raw`].
+/// A table in the raw layout is mapped into memory instead of read, see [`CompleteMeasurementTable::open_mmap`].
 pub fn deserialize_table(cache_path: &Path) -> Result<CompleteMeasurementTable, Box<dyn Error>> {
+    let mut magic = [0; 8];
+    if File::open(cache_path)?.read_exact(&mut magic).is_ok() {
+        if &magic == SHARDS_MAGIC {
+            return deserialize_shards(cache_path, PauliString::ALL_QUBITS);
+        }
+        if &magic == CompleteMeasurementTable::RAW_MAGIC {
+            return Ok(CompleteMeasurementTable::open_mmap(cache_path)?);
+        }
+        if &magic == MinimalMeasurementTable::MAGIC {
+            return Err(format!(
+                "{} is a minimal measurement table, read it with `deserialize_minimal_table`",
+                cache_path.display()
+            )
+            .into());
+        }
+    }
     let read = std::fs::read(cache_path)?;
     Ok(bitcode::deserialize::<CompleteMeasurementTable>(&read)?)
 }
 
+/// Read a table that is written by [`MinimalMeasurementTable::write`],
+/// or `None` if the file has another kind of table, which [`deserialize_table`] reads
+pub fn deserialize_minimal_table(
+    cache_path: &Path,
+) -> Result<Option<MinimalMeasurementTable>, Box<dyn Error>> {
+    let mut magic = [0; 8];
+    if File::open(cache_path)?.read_exact(&mut magic).is_err()
+        || &magic != MinimalMeasurementTable::MAGIC
+    {
+        return Ok(None);
+    }
+    let file = io::BufReader::new(File::open(cache_path)?);
+    Ok(Some(MinimalMeasurementTable::read(file)?))
+}
+
+/// Write the flattened chains of a table in shards,
+/// such that [`deserialize_shards`] can load only the shards that a program needs
+pub fn serialize_shards(
+    measurement_table: &CompleteMeasurementTable,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let shards: Vec<_> = (0..CompleteMeasurementTable::SHARDS)
+        .map(|i| {
+            bitcode::serialize(&measurement_table.shard(i)).expect("Shards should be serializable")
+        })
+        .collect();
+    let header = bitcode::serialize(&ShardsHeader {
+        native_measurements: measurement_table.native_measurements().clone(),
+        lengths: shards.iter().map(|shard| shard.len() as u64).collect(),
+    })
+    .expect("The header should be serializable");
+    writer.write_all(SHARDS_MAGIC)?;
+    writer.write_all(&(header.len() as u64).to_le_bytes())?;
+    writer.write_all(&header)?;
+    for shard in shards {
+        writer.write_all(&shard)?;
+    }
+    Ok(())
+}
+
+/// Read the shards of a table written by [`serialize_shards`] with the PauliStrings that are supported within `support`.
+/// The other shards are skipped without reading them.
+pub fn deserialize_shards(
+    cache_path: &Path,
+    support: u32,
+) -> Result<CompleteMeasurementTable, Box<dyn Error>> {
+    let mut file = File::open(cache_path)?;
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != SHARDS_MAGIC {
+        return Err(format!(
+            "{} is not a sharded measurement table, generate it with `generate --sharded`",
+            cache_path.display()
+        )
+        .into());
+    }
+    let size = file.metadata()?.len();
+    let mut length = [0; 8];
+    file.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    // Bound the lengths by the file, such that a corrupt header does not allocate more than the file
+    let too_short = || format!("{} is shorter than its header claims", cache_path.display());
+    if length > size.saturating_sub(file.stream_position()?) {
+        return Err(too_short().into());
+    }
+    let mut header = vec![0; length as usize];
+    file.read_exact(&mut header)?;
+    let header: ShardsHeader = bitcode::deserialize(&header)?;
+    if header.lengths.len() != CompleteMeasurementTable::SHARDS {
+        return Err(format!(
+            "{} should have {} shards, found {}",
+            cache_path.display(),
+            CompleteMeasurementTable::SHARDS,
+            header.lengths.len()
+        )
+        .into());
+    }
+
+    let start = file.stream_position()?;
+    let mut offsets = vec![start];
+    for length in &header.lengths {
+        let end = offsets[offsets.len() - 1]
+            .checked_add(*length)
+            .filter(|end| *end <= size)
+            .ok_or_else(too_short)?;
+        offsets.push(end);
+    }
+    let natives = header.native_measurements.len();
+    let shards = CompleteMeasurementTable::shards_with_support(support)
+        .map(|i| {
+            file.seek(SeekFrom::Start(offsets[i]))?;
+            let mut shard = vec![0; header.lengths[i] as usize];
+            file.read_exact(&mut shard)?;
+            let shard = bitcode::deserialize::<TableShard>(&shard)?;
+            shard
+                .check(natives)
+                .map_err(|e| format!("Shard {i} of {}: {e}", cache_path.display()))?;
+            Ok((i, shard))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    Ok(CompleteMeasurementTable::from_shards(
+        header.native_measurements,
+        shards,
+    ))
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
 
-    use std::error::Error;
+    use std::{error::Error, sync::LazyLock};
 
     use crate::language::{AnglePrecision, PbcOperation};
 
     use super::*;
     use bicycle_cliffords::{
-        MeasurementTableBuilder, TWOGROSS_MEASUREMENT, native_measurement::NativeMeasurement,
+        GROSS_MEASUREMENT, MeasurementTableBuilder, TWOGROSS_MEASUREMENT,
+        native_measurement::{NativeFilter, NativeMeasurement},
     };
     use operation::Operations;
 
+    /// The complete measurement table of the gross code, built once for all unit tests
+    pub(crate) static GROSS_TABLE: LazyLock<CompleteMeasurementTable> = LazyLock::new(|| {
+        let mut builder = MeasurementTableBuilder::new(NativeMeasurement::all(), GROSS_MEASUREMENT);
+        builder.build();
+        builder.complete().expect("Table building should succeed")
+    });
+    // static GROSS_TABLE: LazyLock<CompleteMeasurementTable> = LazyLock::new(|| {
+    //     let table_path = Path::new("../../data/table_gross");
+    //     crate::deserialize_table(table_path).expect("Should be able to deserialize table")
+    // });
+
     #[test]
     fn integration_test_rotation() -> Result<(), Box<dyn Error>> {
         let program = r#"[
@@ -75,7 +252,7 @@ mod test {
         builder.build();
         let measurement_table = builder.complete()?;
 
-        let architecture = PathArchitecture { data_blocks: 2 };
+        let architecture = PathArchitecture::new(2);
         let compiled: Vec<_> = parsed
             .into_iter()
             .flat_map(|op| {
@@ -92,4 +269,70 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn corrupt_shards() -> Result<(), Box<dyn Error>> {
+        let filter = NativeFilter {
+            automorphism_x: vec![0, 3],
+            ..Default::default()
+        };
+        let mut builder = MeasurementTableBuilder::with_filter(&filter, GROSS_MEASUREMENT);
+        builder.build();
+        let table = builder.partial();
+        let mut written = vec![];
+        serialize_shards(&table, &mut written)?;
+
+        let path = std::env::temp_dir().join(format!("bicycle_shards_{}", std::process::id()));
+        let read = |bytes: &[u8]| {
+            std::fs::write(&path, bytes)?;
+            deserialize_shards(&path, PauliString::ALL_QUBITS)
+        };
+        let p = *table.native_measurements().keys().next().unwrap();
+        assert_eq!(table.implementation(p), read(&written)?.implementation(p));
+
+        // The header claims more bytes than the file has
+        let mut long_header = written.clone();
+        long_header[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read(&long_header).is_err());
+        // The shards are longer than the file
+        assert!(read(&written[..written.len() - 1]).is_err());
+
+        let rewrite = |header: &ShardsHeader| {
+            let length = u64::from_le_bytes(written[8..16].try_into().unwrap()) as usize;
+            let encoded = bitcode::serialize(header).unwrap();
+            let mut bytes = written[..8].to_vec();
+            bytes.extend((encoded.len() as u64).to_le_bytes());
+            bytes.extend(encoded);
+            bytes.extend(&written[16 + length..]);
+            bytes
+        };
+        let lengths: Vec<u64> = (0..CompleteMeasurementTable::SHARDS)
+            .map(|i| bitcode::serialize(&table.shard(i)).unwrap().len() as u64)
+            .collect();
+        assert!(
+            read(&rewrite(&ShardsHeader {
+                native_measurements: table.native_measurements().clone(),
+                lengths: lengths.clone(),
+            }))
+            .is_ok()
+        );
+        // Too few shards
+        assert!(
+            read(&rewrite(&ShardsHeader {
+                native_measurements: table.native_measurements().clone(),
+                lengths: lengths[1..].to_vec(),
+            }))
+            .is_err()
+        );
+        // The chains refer to missing native measurements
+        assert!(
+            read(&rewrite(&ShardsHeader {
+                native_measurements: HashMap::new(),
+                lengths,
+            }))
+            .is_err()
+        );
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
 }
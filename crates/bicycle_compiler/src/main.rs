@@ -15,46 +15,51 @@
 use std::{
     env, error,
     fs::File,
-    io,
-    path::{Path, PathBuf},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::Instant,
 };
 
-use bicycle_cliffords::{
-    MeasurementChoices, MeasurementTableBuilder, native_measurement::NativeMeasurement,
+use bicycle_cliffords::MeasurementTable;
+use bicycle_compiler::{
+    CliffordFrame,
+    analysis::CircuitProfile,
+    budget::ErrorBudget,
+    explain,
+    language::{self, AnglePrecision},
+    operation::{CompiledProgram, ProgramArchitecture},
+    optimize,
+    parametric::{Bindings, ParametricOperation, Template},
+    pipeline::{CompilerPipeline, PipelineStage},
+    qre::LogicalCounts,
+    small_angle,
+    snap::AngleSnapper,
+    sweep::{self, SweepCache},
 };
-use bicycle_compiler::language::{AnglePrecision, PbcOperation};
-
-use io::Write;
-
-use bicycle_compiler::{PathArchitecture, optimize};
-use clap::{Parser, Subcommand};
-use log::{debug, info};
+use clap::{Parser, ValueEnum};
+use log::{error, info, warn};
+use rand::rngs::StdRng;
 use serde_json::Deserializer;
 
-#[derive(Parser)]
-#[command(version, about, long_about=None)]
-struct Cli {
-    /// Select the bicycle code (either gross or two-gross)
-    code: MeasurementChoices,
-    #[command(subcommand)]
-    commands: Option<Commands>,
-    /// Read a cached Clifford synthesis table from the given file name
-    #[arg(long)]
-    measurement_table: Option<String>,
-    /// The accuracy of small angle synthesis
-    #[arg(short, long, default_value_t = AnglePrecision::lit("1e-9"))]
-    accuracy: AnglePrecision,
-}
+use cli::{Cli, Commands, Emit, SweepFormat};
+use input::{InputChecks, pbc_operations};
+use output::{OutputSink, ReportModel, log_error_budget, report_stage_times};
+use passes::{ExpectedOperations, ObservableLog, Passes};
+use stage::CompileStage;
+use table::{generate, load_table};
 
-/// Caching commands
-#[derive(Subcommand, Clone, PartialEq, Eq)]
-enum Commands {
-    /// Generate Clifford measurement table and save to file name
-    Generate {
-        /// The file name to output to
-        measurement_table: String,
-    },
-}
+mod cli;
+mod input;
+mod output;
+mod passes;
+mod stage;
+mod table;
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOC: bicycle_common::alloc::CountingAllocator = bicycle_common::alloc::CountingAllocator;
 
 fn main() -> Result<(), Box<dyn error::Error>> {
     // By default log INFO.
@@ -65,108 +70,479 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     env_logger::init();
 
     let cli = Cli::parse();
+    if cli.output.explain {
+        explain::enable();
+    }
+    // Cold starts are dominated by loading the measurement table
+    let started = Instant::now();
 
-    if let Some(Commands::Generate {
-        measurement_table: cache_str,
-    }) = cli.commands
-    {
-        info!("Generating measurement table.");
-        let cache_path = Path::new(&cache_str);
-
-        // Ensure that we can write a file in the desired output directory.  To do this we
-        // write and delte an empty file in the parent directory of the full path of the
-        // (output) cache file.  We do this in order to fail early rather than computing the
-        // measurement table, only to find at the end that we cannot write the result.
-        match cache_path.parent() {
-            Some(cache_dir) => {
-                let temp_filename = "dummy_file_check";
-                let mut temp_file_path = PathBuf::from(cache_dir);
-                temp_file_path.push(temp_filename);
-                match File::create(&temp_file_path) {
-                    Ok(_) => {
-                        // Successfully created dummy file. Remove file.
-                        std::fs::remove_file(temp_file_path)?;
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "Cannot create measurement_table output file in the target directory: {e}"
-                        );
-                        std::process::exit(1);
-                    }
-                }
-            }
-            None => {
-                eprintln!("No parent directory found for {cache_str}");
-                std::process::exit(1);
-            }
+    match &cli.commands {
+        Some(Commands::SynthSweep {
+            angle,
+            from,
+            to,
+            per_decade,
+            cache,
+            format,
+        }) => return synth_sweep(*angle, *from, *to, *per_decade, cache.as_deref(), *format),
+        Some(Commands::Analyze { circuit }) => return analyze(&cli, circuit),
+        Some(Commands::Generate { .. }) => {
+            generate(&cli)?;
+            std::process::exit(0);
         }
+        _ => {}
+    }
 
-        // Create a builder and build the measurement table.
-        let mut builder =
-            MeasurementTableBuilder::new(NativeMeasurement::all(), cli.code.measurement());
-        builder.build();
-        let measurement_table = builder.complete()?;
-
-        // Serialize the measurement table and write to the cache file.
-        let serialized =
-            bitcode::serialize(&measurement_table).expect("The table should be serializable");
-        info!("Done generating measurement table, writing.");
-        let f = File::create(cache_path);
-        match f {
-            Ok(mut f) => {
-                f.write_all(&serialized)
-                    .expect("The serialized table should be writable to the cache");
-            }
-            Err(e) => {
-                eprintln!(
-                    "Cannot create  measurement_table output file in the target directory: {e}"
-                );
-                std::process::exit(1);
-            }
-        }
-        info!("Done writing measurement table, exiting.");
-        std::process::exit(0);
+    if cli.output.emit == Some(Emit::PbcText) {
+        return emit_pbc_text(&cli);
     }
 
-    // Generate measurement table, from cache if given or otherwise from scratch
-    let measurement_table = if let Some(cache_str) = cli.measurement_table {
-        let cache_path = Path::new(&cache_str);
-        bicycle_compiler::deserialize_table(cache_path)?
-    } else {
-        let mut builder =
-            MeasurementTableBuilder::new(NativeMeasurement::all(), cli.code.measurement());
-        builder.build();
-        builder.complete()?
-    };
+    // Fail on an unreadable model file before building the measurement table
+    let report_model = ReportModel::of(&cli)?;
+
+    if let Some(path) = &cli.synthesis.angle_cache {
+        read_angle_cache(path);
+    }
+
+    let measurement_table = load_table(&cli)?;
+    info!(
+        "Prepared the measurement table after {:.2?}",
+        started.elapsed()
+    );
+    bicycle_common::alloc::report_memory("measurement table");
+
+    if let Some(Commands::Bind {
+        bindings,
+        output_dir,
+    }) = &cli.commands
+    {
+        bind_parameters(&cli, &*measurement_table, bindings, output_dir)?;
+        if let Some(path) = &cli.synthesis.angle_cache {
+            write_angle_cache(path, cli.synthesis.angle_cache_capacity)?;
+        }
+        return Ok(());
+    }
 
-    let reader = io::stdin().lock();
+    compile(&cli, &*measurement_table, report_model.as_ref(), started)
+}
 
-    // Support some streaming input from Stdin
-    // The following works for (a weird version of) JSON:
-    let de = Deserializer::from_reader(reader);
-    let ops = de.into_iter::<PbcOperation>().map(|op| op.unwrap());
-    let mut ops = ops.peekable();
+/// Compile the input programs in the stages of a [`CompilerPipeline`] and write the output,
+/// which stops with exit code 3 at the first malformed operation
+fn compile(
+    cli: &Cli,
+    measurement_table: &dyn MeasurementTable,
+    report_model: Option<&ReportModel>,
+    started: Instant,
+) -> Result<(), Box<dyn error::Error>> {
+    let checks = InputChecks::new(cli);
+    let snapper = cli.snapper();
+    let budget = cli.error_budget();
+    let frame = cli.frame();
+    let mut ops = pbc_operations(cli, &checks, &snapper, &budget, &frame)?.peekable();
+    // Barriers and delays act on no qubits, so they cannot set the architecture
+    let mut timing_ops = vec![];
+    while let Some(hinted) = ops.next_if(|hinted| hinted.op.basis().is_empty()) {
+        timing_ops.push(hinted);
+    }
 
     // Set the architecture based on the first operation
-    let first_op = ops.peek();
-    let architecture = if let Some(op) = first_op {
-        PathArchitecture::for_qubits(op.basis().len())
-    } else {
+    let Some(first_op) = ops.peek() else {
         // No ops, may as well terminate now.
+        if let Some(malformed) = checks.malformed.lock().unwrap().take() {
+            malformed.exit();
+        }
         return Ok(());
     };
+    let architecture = cli.architecture(first_op.op.basis().len())?;
+    let ops = timing_ops.into_iter().chain(ops);
+
+    let output = &cli.output;
+    let observables = if output.observables.is_some() || output.feed_forward.is_some() {
+        Some(Mutex::new(ObservableLog::new(
+            output.observables.as_deref(),
+            output.feed_forward.as_deref(),
+        )?))
+    } else {
+        None
+    };
+    let expected = ExpectedOperations::default();
+    let mut passes = Passes::new(cli, measurement_table, &architecture)?;
+    let mut sink = OutputSink::new(
+        cli,
+        &architecture,
+        report_model,
+        observables.as_ref(),
+        &expected,
+        started,
+    )?;
+    let mut stage = CompileStage::new(cli, &checks, measurement_table, &expected, architecture)?;
 
-    let compiled = ops.map(|op| op.compile(&architecture, &measurement_table, cli.accuracy));
+    let written = thread::scope(|scope| {
+        let pipeline = CompilerPipeline::new(scope, cli.threads.get());
+        let ops = pipeline.stage(PipelineStage::Parse, ops);
 
-    let optimized_auts = compiled.map(optimize::remove_trivial_automorphisms);
-    let mut optimized_chunked_ops = optimize::remove_duplicate_measurements_chunked(optimized_auts);
-    let mut stdout = io::stdout();
-    // Stop on first error
-    let err: Result<(), io::Error> = optimized_chunked_ops.try_for_each(|chunk| {
-        let out = serde_json::to_string(&chunk)?;
-        writeln!(stdout, "{out}")
+        // A final `None` restores the cached bases after the last operation
+        let compiled = ops
+            .map(Some)
+            .chain(cli.passes.cache_bases.then_some(None))
+            .map_while(|hinted| stage.compile(hinted))
+            .map(|compiled| {
+                if let Some(observables) = &observables {
+                    observables
+                        .lock()
+                        .unwrap()
+                        .compiled(&compiled.chunk, compiled.measured);
+                }
+                (compiled.chunk, compiled.data_blocks)
+            });
+        let compiled = pipeline.stage(PipelineStage::Compile, compiled);
+        let mut optimized = pipeline.stage(PipelineStage::Optimize, passes.run(compiled));
+        // Stop on first error
+        optimized.try_for_each(|chunk| sink.write(chunk))
     });
-    debug!("Encountered error while writing to stdout: {err:?}");
+    bicycle_common::alloc::report_memory("compile");
+    let OutputSink {
+        compiled_counter,
+        verifier,
+        verified,
+        summary,
+        buffered,
+        explicit_scheduler,
+        ..
+    } = sink;
+    if let Some(verifier) = &verifier {
+        if let Err(err) = verified {
+            error!("The output does not implement the program at {err}");
+            return Err(err.into());
+        }
+        info!("Verified {} operations", verifier.checked());
+    }
+    // The output is incomplete if stdout cannot be written, e.g., when the reader closed it
+    written.map_err(|e| format!("Cannot write the output: {e}"))?;
+
+    if let Some(scheduler) = &explicit_scheduler {
+        info!("Scheduled the program in {} cycles", scheduler.end_time());
+    }
+    log_compile_stage(cli, &stage, &checks, &snapper, &budget, &frame);
+    let CompileStage {
+        architecture,
+        uncompilable,
+        lower_bounds,
+        program_counter,
+        ..
+    } = stage;
+    if let Some(chunks) = buffered {
+        let code = cli.code.to_possible_value().unwrap();
+        let program = CompiledProgram::new(
+            ProgramArchitecture::new(code.get_name(), cli.layout.topology, &architecture),
+            chunks,
+        );
+        let mut stdout = io::BufWriter::new(io::stdout().lock());
+        program.write(&mut stdout, output.output_format)?;
+        stdout.flush()?;
+    }
+    if output.explain {
+        report_stage_times(started.elapsed());
+    }
+    if let Some(report) = lower_bounds {
+        report.finish()?;
+    }
+    if let Some(path) = &output.qre_counts {
+        let counts = match program_counter {
+            Some(counter) => counter.counts(),
+            None => LogicalCounts {
+                num_qubits: architecture.qubits() as u64,
+                ..compiled_counter.as_ref().unwrap().counts()
+            },
+        };
+        serde_json::to_writer_pretty(File::create(path)?, &counts)?;
+        info!("Wrote the logical counts to {}", path.display());
+    }
+    passes.finish()?;
+    if let Some(observables) = observables {
+        observables.into_inner().unwrap().finish()?;
+    }
+    if let Some(path) = &cli.synthesis.angle_cache {
+        write_angle_cache(path, cli.synthesis.angle_cache_capacity)?;
+    }
+    if let Some(malformed) = uncompilable.or(checks.malformed.lock().unwrap().take()) {
+        malformed.exit();
+    }
+    if let (Some(report_model), Some(summary)) = (report_model, &summary) {
+        let architecture = cli.layout.topology.connect(architecture);
+        report_model.print(summary.finish(&*architecture, report_model.cycle_time))?;
+    }
+
+    Ok(())
+}
+
+/// Log what the passes on the operations and the compile stage did
+fn log_compile_stage(
+    cli: &Cli,
+    stage: &CompileStage,
+    checks: &InputChecks,
+    snapper: &Option<Mutex<AngleSnapper<StdRng>>>,
+    budget: &Option<Mutex<ErrorBudget>>,
+    frame: &Option<Mutex<CliffordFrame>>,
+) {
+    if cli.input.fuse {
+        info!(
+            "Fused {} rotation-measurement pairs, saving {} instructions",
+            stage.fused, stage.saved
+        );
+    }
+    if let Some(snapper) = snapper {
+        let snapper = snapper.lock().unwrap();
+        info!(
+            "Rounded {} rotations to multiples of the T angle, with a total diamond-norm error of at most {:.3e}",
+            snapper.snapped(),
+            snapper.budget()
+        );
+    }
+    log_error_budget(budget);
+    if stage.added_blocks > 0 {
+        info!(
+            "Added {} blocks to the architecture, which now has {} data blocks",
+            stage.added_blocks,
+            stage.architecture.data_blocks()
+        );
+    }
+    if let Some(frame) = frame {
+        info!(
+            "Commuted the Clifford corrections of {} rotations past later operations",
+            frame.lock().unwrap().corrections()
+        );
+    }
+    for (qubits, count) in checks.padded.lock().unwrap().iter() {
+        info!(
+            "Padded {count} operations on {qubits} qubits with identities on qubits {qubits}..{}",
+            qubits.next_multiple_of(checks.qubits_per_block)
+        );
+    }
+}
+
+/// Compile the parametric program on stdin to a template and finalize it for each line of values in `bindings`
+fn bind_parameters(
+    cli: &Cli,
+    measurement_table: &dyn MeasurementTable,
+    bindings: &Path,
+    output_dir: &Path,
+) -> Result<(), Box<dyn error::Error>> {
+    let ops = Deserializer::from_reader(io::stdin().lock())
+        .into_iter::<ParametricOperation>()
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first_op) = ops.iter().find(|op| !op.basis().is_empty()) else {
+        return Ok(());
+    };
+    let qubits_per_block = cli.code.qubits_per_block();
+    if let Some(index) = ops
+        .iter()
+        .position(|op| op.basis().len() % qubits_per_block != 0)
+        && !cli.input.pad
+    {
+        return Err(format!(
+            "The basis of operation {index} does not fill whole blocks of {qubits_per_block} qubits, use --pad to pad it with identities"
+        )
+        .into());
+    }
+    let architecture = cli.architecture(first_op.basis().len())?;
+
+    let start = Instant::now();
+    let architecture = cli.layout.topology.connect(architecture);
+    let template = Template::compile(
+        ops,
+        &*architecture,
+        measurement_table,
+        cli.synthesis.accuracy,
+    );
+    info!(
+        "Compiled the program with parameters {:?} in {:.2?}",
+        template.parameters(),
+        start.elapsed()
+    );
+
+    std::fs::create_dir_all(output_dir)?;
+    let start = Instant::now();
+    let mut finalized = 0;
+    let bindings = Deserializer::from_reader(io::BufReader::new(File::open(bindings)?));
+    for (index, values) in bindings.into_iter::<Bindings>().enumerate() {
+        let values = values?;
+        let compiled = template.finalize(&values)?;
+        let optimized = optimize::remove_duplicate_measurements_chunked(
+            compiled.map(optimize::remove_trivial_automorphisms),
+        );
+        let mut out = io::BufWriter::new(File::create(output_dir.join(format!("{index}.jsonl")))?);
+        for chunk in optimized {
+            serde_json::to_writer(&mut out, &chunk)?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        finalized += 1;
+    }
+    info!(
+        "Finalized the program for {finalized} bindings in {:.2?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// Print the profile of each operation of the program in `circuit` as JSON lines, followed by aggregate statistics
+fn analyze(cli: &Cli, circuit: &Path) -> Result<(), Box<dyn error::Error>> {
+    // No measurement table is loaded to restrict the qubits
+    let checks = InputChecks {
+        table_qubits: None,
+        ..InputChecks::new(cli)
+    };
+    let file =
+        File::open(circuit).map_err(|e| format!("Cannot open input {}: {e}", circuit.display()))?;
+    let ops = checks.read(
+        io::BufReader::new(file),
+        Some(circuit),
+        cli.input.input_format,
+    );
+    let ops = language::relabel_permutations(ops.map(|hinted| hinted.op));
+
+    let mut profile = CircuitProfile::default();
+    let mut stdout = io::stdout().lock();
+    for op in ops {
+        let op = profile.add(&op, checks.qubits_per_block);
+        serde_json::to_writer(&mut stdout, &op)?;
+        writeln!(stdout)?;
+    }
+    info!(
+        "{} operations in {} layers of commuting operations",
+        profile.operations, profile.layers
+    );
+    info!(
+        "{} operations ({:.1}%) act on more than one block",
+        profile.multi_block,
+        100.0 * profile.multi_block_fraction()
+    );
+    info!(
+        "{} operations ({:.1}%) need T gates",
+        profile.non_clifford,
+        100.0 * profile.t_fraction()
+    );
+    if let Some(malformed) = checks.malformed.lock().unwrap().take() {
+        malformed.exit();
+    }
+    Ok(())
+}
+
+/// Write the PBC operations that would be compiled as lines of text, see [`PbcOperation::to_pbc_text`]
+fn emit_pbc_text(cli: &Cli) -> Result<(), Box<dyn error::Error>> {
+    let checks = InputChecks::new(cli);
+    let snapper = cli.snapper();
+    let budget = cli.error_budget();
+    let frame = cli.frame();
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    let mut emitted = 0;
+    for hinted in pbc_operations(cli, &checks, &snapper, &budget, &frame)? {
+        writeln!(stdout, "{}", hinted.op.to_pbc_text())?;
+        emitted += 1;
+    }
+    stdout.flush()?;
+    info!("Wrote {emitted} operations as PBC text");
+    log_error_budget(&budget);
+    if let Some(malformed) = checks.malformed.lock().unwrap().take() {
+        malformed.exit();
+    }
+    Ok(())
+}
+
+/// Synthesize a rotation about `angle` for accuracies from `from` down to `to` and print the T counts
+fn synth_sweep(
+    angle: AnglePrecision,
+    from: AnglePrecision,
+    to: AnglePrecision,
+    per_decade: usize,
+    cache: Option<&Path>,
+    format: SweepFormat,
+) -> Result<(), Box<dyn error::Error>> {
+    if !(AnglePrecision::ZERO < to && to <= from && from <= AnglePrecision::lit("0.1")) {
+        return Err(format!("The accuracies should satisfy 0 < {to} <= {from} <= 0.1").into());
+    }
+    if per_decade == 0 {
+        return Err("There should be at least one accuracy per decade".into());
+    }
+    let mut sweep_cache = match cache {
+        Some(path) if path.exists() => SweepCache::read(io::BufReader::new(File::open(path)?))?,
+        _ => SweepCache::default(),
+    };
+    let points: Vec<_> = sweep::accuracies(from.to_num(), to.to_num(), per_decade)
+        .into_iter()
+        .map(|accuracy| sweep_cache.point(angle, accuracy))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        SweepFormat::Table => {
+            writeln!(
+                stdout,
+                "{:>10} {:>8} {:>12}",
+                "accuracy", "t_count", "min_t_count"
+            )?;
+            for point in &points {
+                writeln!(
+                    stdout,
+                    "{:>10.2e} {:>8} {:>12}",
+                    point.accuracy.to_num::<f64>(),
+                    point.t_count,
+                    point.min_t_count
+                )?;
+            }
+        }
+        SweepFormat::Csv => {
+            writeln!(stdout, "angle,accuracy,t_count,min_t_count")?;
+            for point in &points {
+                writeln!(
+                    stdout,
+                    "{},{:e},{},{}",
+                    point.angle,
+                    point.accuracy.to_num::<f64>(),
+                    point.t_count,
+                    point.min_t_count
+                )?;
+            }
+        }
+    }
+
+    if let Some(path) = cache {
+        let mut out = io::BufWriter::new(File::create(path)?);
+        sweep_cache.write(&mut out)?;
+        out.flush()?;
+        info!(
+            "Synthesized {} of {} accuracies, cached in {}",
+            sweep_cache.synthesized(),
+            points.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Read the synthesized angles of `--angle-cache`, if the file exists and is a cache of this version
+fn read_angle_cache(path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    match File::open(path)
+        .map_err(Into::into)
+        .and_then(|file| small_angle::read_cache(io::BufReader::new(file)))
+    {
+        Ok(read) => info!("Read {read} synthesized angles from {}", path.display()),
+        Err(e) => warn!("Ignoring the angle cache {}: {e}", path.display()),
+    }
+}
 
+/// Write the synthesized angles to `--angle-cache`, replacing the file only once it is complete
+fn write_angle_cache(path: &Path, capacity: usize) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    let mut out = io::BufWriter::new(File::create(&partial)?);
+    let written = small_angle::write_cache(&mut out, capacity)?;
+    out.flush()?;
+    drop(out);
+    std::fs::rename(&partial, path)?;
+    info!("Cached {written} synthesized angles in {}", path.display());
     Ok(())
 }
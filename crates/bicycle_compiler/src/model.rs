@@ -0,0 +1,658 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, error::Error, fmt, io::Read, path::Path, str::FromStr};
+
+use bicycle_cliffords::{CostModel, native_measurement::NativeMeasurement};
+use bicycle_common::BicycleISA;
+use fixed::types::U32F96;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::export::StimNoise;
+
+// Because we need to support precision up to 10^-20,
+// which is >2^-65
+pub type ErrorPrecision = U32F96;
+
+/// Classes of bicycle instructions that are assigned a cost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstructionClass {
+    SyndromeCycle,
+    CssInitZero,
+    CssInitPlus,
+    DestructiveZ,
+    DestructiveX,
+    Automorphism,
+    Measure,
+    JointMeasure,
+    ParallelMeasure,
+    JointBellInit,
+    JointTransversalCx,
+    InitT,
+    TGate,
+    /// Two T gates that share the preparation of their |T> states.
+    /// Without a cost in the model, e.g., a `t-gate-pair` entry of a model file, a pair costs two T gates in sequence
+    TGatePair,
+    SGate,
+    /// Idling for a given time, which is costed like the idles between instructions
+    Delay,
+}
+
+impl InstructionClass {
+    const COUNT: usize = 16;
+
+    const ALL: [Self; Self::COUNT] = [
+        Self::SyndromeCycle,
+        Self::CssInitZero,
+        Self::CssInitPlus,
+        Self::DestructiveZ,
+        Self::DestructiveX,
+        Self::Automorphism,
+        Self::Measure,
+        Self::JointMeasure,
+        Self::ParallelMeasure,
+        Self::JointBellInit,
+        Self::JointTransversalCx,
+        Self::InitT,
+        Self::TGate,
+        Self::TGatePair,
+        Self::SGate,
+        Self::Delay,
+    ];
+
+    pub fn of(instruction: &BicycleISA) -> Self {
+        match instruction {
+            BicycleISA::SyndromeCycle => Self::SyndromeCycle,
+            BicycleISA::CSSInitZero => Self::CssInitZero,
+            BicycleISA::CSSInitPlus => Self::CssInitPlus,
+            BicycleISA::DestructiveZ => Self::DestructiveZ,
+            BicycleISA::DestructiveX => Self::DestructiveX,
+            BicycleISA::Automorphism(_) => Self::Automorphism,
+            BicycleISA::Measure(_) => Self::Measure,
+            BicycleISA::JointMeasure(_) => Self::JointMeasure,
+            BicycleISA::ParallelMeasure(_) => Self::ParallelMeasure,
+            BicycleISA::JointBellInit => Self::JointBellInit,
+            BicycleISA::JointTransversalCX => Self::JointTransversalCx,
+            BicycleISA::InitT => Self::InitT,
+            BicycleISA::TGate(_) => Self::TGate,
+            BicycleISA::TGatePair(_) => Self::TGatePair,
+            BicycleISA::SGate(_) => Self::SGate,
+            BicycleISA::Delay(_) => Self::Delay,
+        }
+    }
+
+    /// Number of times that an instruction incurs the cost of its class.
+    /// Automorphisms are costed per generator and all other instructions once.
+    pub fn multiplicity(instruction: &BicycleISA) -> u64 {
+        match instruction {
+            BicycleISA::Automorphism(aut) => aut.nr_generators(),
+            _ => 1,
+        }
+    }
+
+    /// The name of the instructions of this class in the compiler output, e.g., `TGate`
+    pub fn instruction_name(&self) -> &'static str {
+        match self {
+            Self::SyndromeCycle => "SyndromeCycle",
+            Self::CssInitZero => "CSSInitZero",
+            Self::CssInitPlus => "CSSInitPlus",
+            Self::DestructiveZ => "DestructiveZ",
+            Self::DestructiveX => "DestructiveX",
+            Self::Automorphism => "Automorphism",
+            Self::Measure => "Measure",
+            Self::JointMeasure => "JointMeasure",
+            Self::ParallelMeasure => "ParallelMeasure",
+            Self::JointBellInit => "JointBellInit",
+            Self::JointTransversalCx => "JointTransversalCX",
+            Self::InitT => "InitT",
+            Self::TGate => "TGate",
+            Self::TGatePair => "TGatePair",
+            Self::SGate => "SGate",
+            Self::Delay => "Delay",
+        }
+    }
+}
+
+/// Parse the name of an instruction in the compiler output, e.g., `TGate`
+impl FromStr for InstructionClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|class| class.instruction_name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(Self::instruction_name).collect();
+                format!(
+                    "Unknown instruction {s}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for InstructionClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Assigns a timing and an error to each class of instructions
+pub trait InstructionCost {
+    /// Number of cycles that an instruction of the given class takes,
+    /// or a single generator for automorphisms
+    fn cycles(&self, class: InstructionClass) -> u64;
+
+    /// Logical error of an instruction of the given class,
+    /// or a single generator for automorphisms
+    fn error(&self, class: InstructionClass) -> ErrorPrecision;
+
+    /// Number of idle cycles and the accumulated error of idling for `time` cycles
+    fn idling_error(&self, time: u64) -> (u64, ErrorPrecision);
+
+    /// Time it takes to perform an instruction
+    fn timing(&self, instruction: &BicycleISA) -> u64 {
+        match instruction {
+            BicycleISA::Delay(cycles) => *cycles,
+            _ => {
+                InstructionClass::multiplicity(instruction)
+                    * self.cycles(InstructionClass::of(instruction))
+            }
+        }
+    }
+
+    fn instruction_error(&self, instruction: &BicycleISA) -> ErrorPrecision {
+        match instruction {
+            BicycleISA::Delay(cycles) => self.idling_error(*cycles).1,
+            _ => {
+                (InstructionClass::multiplicity(instruction) as u128)
+                    * self.error(InstructionClass::of(instruction))
+            }
+        }
+    }
+}
+
+/// The timing and error of a single instruction
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cost {
+    pub cycles: u64,
+    #[serde(deserialize_with = "deserialize_error")]
+    pub error: ErrorPrecision,
+}
+
+/// Parse errors from strings so that no precision is lost to floating point
+fn deserialize_error<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ErrorPrecision, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+/// Table of the cost of each instruction class
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Model {
+    idle: Cost,
+    costs: [Option<Cost>; InstructionClass::COUNT],
+}
+
+impl Model {
+    /// Create a model where idling for `idle.cycles` has error `idle.error`
+    /// and the given instructions have the given cost
+    pub const fn new(idle: Cost, instructions: &[(InstructionClass, Cost)]) -> Self {
+        let mut costs = [None; InstructionClass::COUNT];
+        let mut i = 0;
+        while i < instructions.len() {
+            let (class, cost) = instructions[i];
+            costs[class as usize] = Some(cost);
+            i += 1;
+        }
+        Self { idle, costs }
+    }
+
+    fn cost(&self, class: InstructionClass) -> Cost {
+        match (self.costs[class as usize], class) {
+            (Some(cost), _) => cost,
+            // There is no measured cost of injecting a pair of T gates,
+            // so without an explicit cost a pair costs two T gates in sequence
+            (None, InstructionClass::TGatePair) => {
+                let t_gate = self.cost(InstructionClass::TGate);
+                Cost {
+                    cycles: 2 * t_gate.cycles,
+                    error: 2 * t_gate.error,
+                }
+            }
+            (None, _) => panic!("The model has no cost for {class} instructions"),
+        }
+    }
+
+    /// Read the model of a model file, without its labels, see [`ModelFile::from_file`]
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(ModelFile::from_file(path)?.model())
+    }
+
+    /// Read the model of a model file in the given format, without its labels
+    pub fn from_reader(reader: impl Read, format: ModelFormat) -> Result<Self, Box<dyn Error>> {
+        Ok(ModelFile::from_reader(reader, format)?.model())
+    }
+
+    /// The model of T injections that are checked by a majority vote over `repetitions` measurements of the pivot,
+    /// e.g., by `bicycle_compiler --verify-t`.
+    /// The error of an injection becomes the probability that the majority of the outcomes is wrong,
+    /// assuming that the injection error is dominated by independent errors of the outcomes.
+    /// The repeated measurements are part of the compiled program, so their time and error are counted separately.
+    pub fn with_verified_t(mut self, repetitions: usize) -> Self {
+        let mut t_gate = self.cost(InstructionClass::TGate);
+        t_gate.error = majority_vote_error(t_gate.error, repetitions);
+        self.costs[InstructionClass::TGate as usize] = Some(t_gate);
+        self
+    }
+}
+
+/// Probability that the majority of `repetitions` independent outcomes is wrong,
+/// if each outcome is wrong with probability `error`
+pub fn majority_vote_error(error: ErrorPrecision, repetitions: usize) -> ErrorPrecision {
+    assert!(
+        repetitions % 2 == 1,
+        "A majority vote needs an odd number of repetitions, not {repetitions}"
+    );
+    let pow = |p: ErrorPrecision, k: usize| (0..k).fold(ErrorPrecision::ONE, |acc, _| acc * p);
+    let mut binomial: u128 = 1;
+    let mut total = ErrorPrecision::ZERO;
+    for wrong in 0..=repetitions {
+        if wrong > repetitions / 2 {
+            total += binomial
+                * pow(error, wrong)
+                * pow(ErrorPrecision::ONE - error, repetitions - wrong);
+        }
+        binomial = binomial * (repetitions - wrong) as u128 / (wrong + 1) as u128;
+    }
+    total
+}
+
+impl InstructionCost for Model {
+    fn cycles(&self, class: InstructionClass) -> u64 {
+        self.cost(class).cycles
+    }
+
+    fn error(&self, class: InstructionClass) -> ErrorPrecision {
+        self.cost(class).error
+    }
+
+    fn idling_error(&self, time: u64) -> (u64, ErrorPrecision) {
+        let idle_cycles = time.div_ceil(self.idle.cycles);
+        let idle_error = (idle_cycles as u128) * self.idle.error;
+        (idle_cycles, idle_error)
+    }
+}
+
+/// The noise of the instructions in a Stim circuit that [`crate::export`] writes
+impl StimNoise for Model {
+    fn duration(&self, instruction: &BicycleISA) -> u64 {
+        self.timing(instruction)
+    }
+
+    fn depolarization(&self, instruction: &BicycleISA) -> f64 {
+        self.instruction_error(instruction).to_num()
+    }
+
+    fn idle_depolarization(&self, cycles: u64) -> f64 {
+        self.idling_error(cycles).1.to_num()
+    }
+}
+
+/// The quantity of a model that [`TableCost`] weights the native measurements by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostMetric {
+    Error,
+    Time,
+}
+
+/// Weights the native measurements of a measurement table by the error or the time of their instructions under a model,
+/// see [`bicycle_cliffords::MeasurementTableBuilder::with_cost_model`].
+/// A native measurement without automorphisms costs [`Self::RESOLUTION`],
+/// and the automorphism generators of the others make them proportionally more expensive, rounded to an integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableCost<M> {
+    model: M,
+    metric: CostMetric,
+}
+
+impl<M> TableCost<M> {
+    /// The cost of a native measurement without automorphisms,
+    /// which is small enough that the cost of every implementation in a table fits in a byte
+    pub const RESOLUTION: u32 = 12;
+
+    pub fn new(model: M, metric: CostMetric) -> Self {
+        Self { model, metric }
+    }
+}
+
+impl<M: InstructionCost> CostModel for TableCost<M> {
+    fn cost(&self, native: &NativeMeasurement) -> u32 {
+        let cost = |instruction: &BicycleISA| match self.metric {
+            CostMetric::Error => self.model.instruction_error(instruction).to_num::<f64>(),
+            CostMetric::Time => self.model.timing(instruction) as f64,
+        };
+        let unit = cost(&BicycleISA::Measure(native.logical));
+        // A model without a cost of measurements weights all native measurements equally
+        if unit == 0.0 {
+            return 1;
+        }
+        let total: f64 = native.implementation().iter().map(cost).sum();
+        (f64::from(Self::RESOLUTION) * total / unit).round() as u32
+    }
+}
+
+/// A model file, which labels a [`Model`] and lists the cost of each instruction class
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelFile {
+    /// Name of the code, used to label the output
+    pub code: String,
+    /// Physical noise rate, used to label the output
+    pub p: f64,
+    idle: Cost,
+    instructions: HashMap<InstructionClass, Cost>,
+}
+
+/// The format of a [`ModelFile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFormat {
+    Json,
+    Toml,
+}
+
+impl ModelFormat {
+    /// TOML for files ending in `.toml` and JSON otherwise
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+impl ModelFile {
+    /// Read a model file in the format of its extension, see [`ModelFormat::of`]
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = std::fs::File::open(path)?;
+        Self::from_reader(file, ModelFormat::of(path))
+    }
+
+    pub fn from_reader(mut reader: impl Read, format: ModelFormat) -> Result<Self, Box<dyn Error>> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        match format {
+            ModelFormat::Json => Ok(serde_json::from_str(&contents)?),
+            ModelFormat::Toml => Ok(toml::from_str(&contents)?),
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        let instructions: Vec<_> = self
+            .instructions
+            .iter()
+            .map(|(c, cost)| (*c, *cost))
+            .collect();
+        Model::new(self.idle, &instructions)
+    }
+}
+
+const fn cost(cycles: u64, error: &str) -> Cost {
+    Cost {
+        cycles,
+        error: ErrorPrecision::lit(error),
+    }
+}
+
+/// Construct a model with the cost of the instructions that the compiler emits.
+/// Each automorphism generator is implemented by a single shift.
+/// The |Y> catalyst of S gates is kept next to the factory and needs no preparation,
+/// so an S gate costs a joint measurement with the magic block.
+/// Initializing a block that the compiler adds to the path costs an in-module measurement.
+/// A pair of T gates has no cost of its own, see [`InstructionClass::TGatePair`].
+const fn compiler_model(
+    idle: Cost,
+    shift: Cost,
+    inmodule: Cost,
+    intermodule: Cost,
+    t_inj: Cost,
+) -> Model {
+    Model::new(
+        idle,
+        &[
+            (InstructionClass::TGate, t_inj),
+            (InstructionClass::SGate, intermodule),
+            (InstructionClass::Automorphism, shift),
+            (InstructionClass::Measure, inmodule),
+            (InstructionClass::JointMeasure, intermodule),
+            (InstructionClass::CssInitZero, inmodule),
+            (InstructionClass::CssInitPlus, inmodule),
+        ],
+    )
+}
+
+pub const GROSS_1E3: Model = compiler_model(
+    cost(8, "1.61e-9"),
+    cost(12, "4.01e-7"),
+    cost(120, "1.11e-5"),
+    cost(120, "2.01e-3"),
+    cost(351 + 120, "2.01e-3"),
+);
+
+pub const GROSS_1E4: Model = compiler_model(
+    cost(8, "1.44e-15"),
+    cost(12, "6.07e-14"),
+    cost(120, "1.01e-09"),
+    cost(120, "4.81e-8"),
+    cost(109 + 120, "8.79e-7"),
+);
+
+pub const TWO_GROSS_1E3: Model = compiler_model(
+    cost(8, "8.20e-21"),
+    cost(12, "3.25e-15"),
+    cost(216, "1e-11"),
+    cost(216, "1e-9"),
+    cost(2167 + 216, "2.10e-8"),
+);
+
+pub const TWO_GROSS_1E4: Model = compiler_model(
+    cost(8, "5.29e-39"),
+    cost(12, "1.34e-37"),
+    cost(216, "1e-20"),
+    cost(216, "1e-18"),
+    cost(407 + 216, "1e-18"),
+);
+
+pub const FAKE_SLOW: Model = compiler_model(
+    cost(8, "0"),
+    cost(12, "0"),
+    cost(216, "0"),
+    cost(216, "0"),
+    cost(2167 + 216, "0"),
+);
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{AutomorphismData, Pauli, TGateData, TwoBases};
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn model_file_matches_builtin() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../bicycle_numerics/models/gross_1e-3.json");
+        let file = ModelFile::from_file(&path).unwrap();
+
+        assert_eq!("gross", file.code);
+        assert_eq!(1e-3, file.p);
+        let model = file.model();
+        for class in [
+            InstructionClass::TGate,
+            InstructionClass::SGate,
+            InstructionClass::Automorphism,
+            InstructionClass::Measure,
+            InstructionClass::JointMeasure,
+            InstructionClass::CssInitZero,
+            InstructionClass::CssInitPlus,
+        ] {
+            assert_eq!(GROSS_1E3.cycles(class), model.cycles(class));
+            assert_eq!(
+                GROSS_1E3.error(class).to_num::<f64>(),
+                model.error(class).to_num::<f64>()
+            );
+        }
+        assert_eq!(GROSS_1E3.idling_error(100), model.idling_error(100));
+    }
+
+    #[test]
+    fn toml_model_matches_json() {
+        let models = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bicycle_numerics/models");
+        let json = Model::from_path(&models.join("gross_1e-3.json")).unwrap();
+        let toml = Model::from_path(&models.join("gross_1e-3.toml")).unwrap();
+        assert_eq!(json, toml);
+
+        let inline = "code = \"custom\"\np = 0.0\nidle = { cycles = 1, error = \"0\" }\n[instructions]\nt-gate = { cycles = 2, error = \"1e-4\" }";
+        let file = ModelFile::from_reader(inline.as_bytes(), ModelFormat::Toml).unwrap();
+        assert_eq!("custom", file.code);
+        assert_eq!(2, file.model().cycles(InstructionClass::TGate));
+        assert!(Model::from_reader(inline.as_bytes(), ModelFormat::Json).is_err());
+    }
+
+    #[test]
+    fn automorphism_cost_per_generator() {
+        let shift = GROSS_1E3.cycles(InstructionClass::Automorphism);
+        let shift_error = GROSS_1E3.error(InstructionClass::Automorphism);
+        let single = BicycleISA::Automorphism(AutomorphismData::new(1, 0));
+        let three_five = BicycleISA::Automorphism(AutomorphismData::new(3, 5));
+        let pair = BicycleISA::Automorphism(AutomorphismData::new(1, 1));
+
+        assert_eq!(shift, GROSS_1E3.timing(&single));
+        assert_eq!(shift_error, GROSS_1E3.instruction_error(&single));
+        // (3, 5) is also implemented by a single generator
+        assert_eq!(GROSS_1E3.timing(&single), GROSS_1E3.timing(&three_five));
+        assert_eq!(
+            GROSS_1E3.instruction_error(&single),
+            GROSS_1E3.instruction_error(&three_five)
+        );
+        assert_eq!(2 * shift, GROSS_1E3.timing(&pair));
+        assert_eq!(2 * shift_error, GROSS_1E3.instruction_error(&pair));
+
+        let identity = BicycleISA::Automorphism(AutomorphismData::new(0, 0));
+        assert_eq!(0, GROSS_1E3.timing(&identity));
+        assert_eq!(ErrorPrecision::ZERO, GROSS_1E3.instruction_error(&identity));
+    }
+
+    #[test]
+    fn table_cost_per_generator() {
+        let native = |x, y| NativeMeasurement {
+            logical: TwoBases::new(Pauli::X, Pauli::I).unwrap(),
+            automorphism: AutomorphismData::new(x, y),
+        };
+        let time = TableCost::new(GROSS_1E3, CostMetric::Time);
+        assert_eq!(12, time.cost(&native(0, 0)));
+        // Two shifts of 12 cycles on top of a measurement of 120 cycles
+        assert_eq!(14, time.cost(&native(1, 0)));
+        assert_eq!(17, time.cost(&native(1, 1)));
+
+        let error = TableCost::new(GROSS_1E3, CostMetric::Error);
+        assert_eq!(12, error.cost(&native(0, 0)));
+        assert_eq!(13, error.cost(&native(1, 0)));
+        assert_eq!(14, error.cost(&native(1, 1)));
+
+        let free = TableCost::new(FAKE_SLOW, CostMetric::Error);
+        assert_eq!(1, free.cost(&native(1, 1)));
+    }
+
+    #[test]
+    fn verified_t_injections() {
+        let p = ErrorPrecision::lit("1e-3");
+        assert_eq!(p, majority_vote_error(p, 1));
+        // 3p^2(1 - p) + p^3
+        let expected = ErrorPrecision::lit("2.998e-6");
+        assert!(majority_vote_error(p, 3).abs_diff(expected) < ErrorPrecision::lit("1e-20"));
+        assert!(majority_vote_error(p, 5) < majority_vote_error(p, 3));
+
+        let model = GROSS_1E3.with_verified_t(3);
+        assert_eq!(
+            majority_vote_error(GROSS_1E3.error(InstructionClass::TGate), 3),
+            model.error(InstructionClass::TGate)
+        );
+        assert_eq!(
+            GROSS_1E3.cycles(InstructionClass::TGate),
+            model.cycles(InstructionClass::TGate)
+        );
+        assert_eq!(
+            GROSS_1E3.error(InstructionClass::Measure),
+            model.error(InstructionClass::Measure)
+        );
+    }
+
+    #[test]
+    fn t_gate_pair_cost() {
+        let (t_gate, pair) = (InstructionClass::TGate, InstructionClass::TGatePair);
+        assert_eq!(2 * GROSS_1E3.cycles(t_gate), GROSS_1E3.cycles(pair));
+        assert_eq!(2 * GROSS_1E3.error(t_gate), GROSS_1E3.error(pair));
+
+        let file = r#"
+            code = "gross"
+            p = 0.001
+            idle = { cycles = 8, error = "1.61e-9" }
+
+            [instructions]
+            t-gate = { cycles = 471, error = "2.01e-3" }
+            t-gate-pair = { cycles = 591, error = "4.02e-3" }
+        "#;
+        let model = Model::from_reader(file.as_bytes(), ModelFormat::Toml).unwrap();
+        assert_eq!(591, model.cycles(pair));
+        assert_eq!(ErrorPrecision::lit("4.02e-3"), model.error(pair));
+    }
+
+    #[test]
+    #[should_panic(expected = "no cost for SyndromeCycle")]
+    fn missing_cost() {
+        GROSS_1E3.timing(&BicycleISA::SyndromeCycle);
+    }
+
+    #[test]
+    fn errors_are_parsed_from_strings() {
+        let cost: Result<Cost, _> = serde_json::from_str(r#"{"cycles": 1, "error": 1e-3}"#);
+        assert!(cost.is_err());
+        let cost: Cost = serde_json::from_str(r#"{"cycles": 1, "error": "1e-3"}"#).unwrap();
+        assert_eq!(ErrorPrecision::lit("1e-3"), cost.error);
+    }
+
+    #[test]
+    fn parse_instruction_names() {
+        let instructions = [
+            BicycleISA::SyndromeCycle,
+            BicycleISA::Automorphism(AutomorphismData::new(1, 0)),
+            BicycleISA::JointTransversalCX,
+            BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap()),
+        ];
+        for instruction in instructions {
+            // The name of the instruction in the compiler output
+            let name = match serde_json::to_value(instruction).unwrap() {
+                Value::String(name) => name,
+                Value::Object(map) => map.keys().next().unwrap().clone(),
+                value => panic!("Unexpected serialization {value}"),
+            };
+            assert_eq!(Ok(InstructionClass::of(&instruction)), name.parse());
+        }
+        assert!("t-gate".parse::<InstructionClass>().is_err());
+    }
+}
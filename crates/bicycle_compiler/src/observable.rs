@@ -0,0 +1,288 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical observables of a compiled program,
+//! i.e., the measurement outcomes of compiled instructions whose parity is the outcome of a measurement of the input program.
+//!
+//! These annotate the compiled program like the `OBSERVABLE_INCLUDE` targets of a detector error model,
+//! so that decoders can be evaluated on compiled programs.
+//! The outcomes are up to the Pauli frame, which the compiler leaves to the control system.
+//!
+//! The same outcomes give the classical feed-forward of the compiled program, see [`Conditioned`].
+
+use std::collections::BTreeSet;
+
+use bicycle_common::BicycleISA;
+use serde::Serialize;
+
+use crate::operation::Operation;
+
+/// The outcome of a compiled measurement instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Outcome {
+    /// Index of the compiled PBC operation, i.e., the output line
+    pub operation: usize,
+    /// Index of the measurement within the line
+    pub instruction: usize,
+}
+
+/// A measurement of the input program as the parity of compiled outcomes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Observable {
+    /// Index of the measurement among the measurements of the input program
+    pub observable: usize,
+    pub outcomes: Vec<Outcome>,
+    /// The parity is flipped, see `flip_result` of the measurement
+    pub flip: bool,
+}
+
+/// A T or S gate that waits for earlier measurement outcomes.
+///
+/// An injection rotates the pivot of the magic block, which is entangled with the Pauli of the data blocks
+/// by the measurements that prepare the GHZ state before it.
+/// The parity of their outcomes decides the sign of the Pauli that the rotation acts on,
+/// so the control system applies the gate as its adjoint if the parity is odd.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Conditioned {
+    /// The conditioned gate
+    pub gate: Outcome,
+    /// The measurement outcomes whose parity conditions the gate
+    pub conditioned_on: Vec<Outcome>,
+}
+
+/// The outcomes that occur an odd number of times, since outcomes that occur twice cancel
+fn parity(outcomes: impl IntoIterator<Item = Outcome>) -> Vec<Outcome> {
+    let mut parity = BTreeSet::new();
+    for outcome in outcomes {
+        if !parity.remove(&outcome) {
+            parity.insert(outcome);
+        }
+    }
+    parity.into_iter().collect()
+}
+
+/// Follows the outcomes of compiled measurements through optimization passes that only remove instructions,
+/// such as [`crate::optimize::remove_duplicate_measurements_chunked`].
+///
+/// A measurement that is removed repeats the last measurement on its block, so it has the same outcome.
+#[derive(Debug, Clone, Default)]
+pub struct ObservableTracker {
+    /// The last measurement on each block that was kept
+    last_measured: Vec<Option<Outcome>>,
+    lines: usize,
+    observables: usize,
+    /// The conditioned gates of the last tracked line
+    conditioned: Vec<Conditioned>,
+}
+
+impl ObservableTracker {
+    /// Track the next output line, given the instructions before and after optimization.
+    /// If the line measures an observable, `measured` has the indices into `compiled` whose outcomes XOR
+    /// to the outcome of the measurement, and whether the outcome is flipped.
+    pub fn track(
+        &mut self,
+        compiled: &[Operation],
+        optimized: &[Operation],
+        measured: Option<(&[usize], bool)>,
+    ) -> Option<Observable> {
+        let line = self.lines;
+        self.lines += 1;
+
+        let mut kept = 0;
+        let outcomes: Vec<Option<Outcome>> = compiled
+            .iter()
+            .map(|op| {
+                let measure = match op[..] {
+                    [(block_i, BicycleISA::Measure(_))] => Some(block_i),
+                    _ => None,
+                };
+                if optimized.get(kept) == Some(op) {
+                    let outcome = Outcome {
+                        operation: line,
+                        instruction: kept,
+                    };
+                    kept += 1;
+                    if let Some(block_i) = measure {
+                        if self.last_measured.len() <= block_i {
+                            self.last_measured.resize(block_i + 1, None);
+                        }
+                        self.last_measured[block_i] = Some(outcome);
+                    }
+                    Some(outcome)
+                } else {
+                    measure.and_then(|block_i| self.last_measured.get(block_i).copied().flatten())
+                }
+            })
+            .collect();
+        assert_eq!(
+            optimized.len(),
+            kept,
+            "The optimized line should only remove instructions from the compiled line"
+        );
+
+        // Each run of gates of an injection is conditioned on the measurements since the previous run
+        self.conditioned.clear();
+        let mut since_injection = vec![];
+        let mut injecting = false;
+        for (op, outcome) in compiled.iter().zip(&outcomes) {
+            match op[..] {
+                [(_, BicycleISA::TGate(_) | BicycleISA::TGatePair(_) | BicycleISA::SGate(_))] => {
+                    injecting = true;
+                    self.conditioned.push(Conditioned {
+                        gate: outcome.expect("Gates should not be removed by optimization"),
+                        conditioned_on: parity(since_injection.iter().copied()),
+                    });
+                }
+                _ => {
+                    if injecting {
+                        since_injection.clear();
+                        injecting = false;
+                    }
+                    let measures = op.iter().any(|(_, isa)| {
+                        matches!(isa, BicycleISA::Measure(_) | BicycleISA::JointMeasure(_))
+                    });
+                    if measures {
+                        since_injection.extend(*outcome);
+                    }
+                }
+            }
+        }
+
+        let (indices, flip) = measured?;
+        let outcomes = indices
+            .iter()
+            .map(|i| outcomes[*i].expect("A removed measurement should repeat a kept one"));
+        let observable = Observable {
+            observable: self.observables,
+            outcomes: parity(outcomes),
+            flip,
+        };
+        self.observables += 1;
+        Some(observable)
+    }
+
+    /// Number of tracked observables
+    pub fn observables(&self) -> usize {
+        self.observables
+    }
+
+    /// The gates of the last tracked line with the outcomes that they are conditioned on
+    pub fn conditioned(&self) -> &[Conditioned] {
+        &self.conditioned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{AutomorphismData, Pauli, TGateData, TwoBases};
+
+    use super::*;
+
+    use BicycleISA::{Automorphism, Measure};
+
+    #[test]
+    fn track_removed_measurements() {
+        let x1 = Measure(TwoBases::new(Pauli::X, Pauli::I).unwrap());
+        let z1 = Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let trivial = Automorphism(AutomorphismData::new(0, 0));
+        let outcome = |operation, instruction| Outcome {
+            operation,
+            instruction,
+        };
+        let mut tracker = ObservableTracker::default();
+
+        let compiled = vec![vec![(0, x1)], vec![(1, z1)]];
+        let observable = tracker.track(&compiled, &compiled, Some((&[0, 1], false)));
+        assert_eq!(
+            Some(Observable {
+                observable: 0,
+                outcomes: vec![outcome(0, 0), outcome(0, 1)],
+                flip: false,
+            }),
+            observable
+        );
+
+        // A line without a measurement
+        let compiled = vec![vec![(0, trivial)]];
+        assert_eq!(None, tracker.track(&compiled, &[], None));
+
+        // The repeated measurements on both blocks are removed
+        let compiled = vec![vec![(0, x1)], vec![(1, z1)], vec![(1, x1)], vec![(1, z1)]];
+        let optimized = vec![vec![(1, x1)], vec![(1, z1)]];
+        let observable = tracker.track(&compiled, &optimized, Some((&[0, 1, 3], true)));
+        assert_eq!(
+            Some(Observable {
+                observable: 1,
+                outcomes: vec![outcome(0, 0), outcome(0, 1), outcome(2, 1)],
+                flip: true,
+            }),
+            observable
+        );
+
+        // The outcome of a repeated measurement cancels with the measurement it repeats
+        let compiled = vec![vec![(1, z1)]];
+        let observable = tracker.track(&compiled, &[], Some((&[0], false)));
+        assert_eq!(vec![outcome(2, 1)], observable.unwrap().outcomes);
+        let compiled = vec![vec![(0, x1)], vec![(0, x1)]];
+        let optimized = vec![vec![(0, x1)]];
+        let observable = tracker.track(&compiled, &optimized, Some((&[0, 1], false)));
+        assert!(observable.unwrap().outcomes.is_empty());
+    }
+
+    #[test]
+    fn condition_injected_gates() {
+        let x1 = Measure(TwoBases::new(Pauli::X, Pauli::I).unwrap());
+        let z1 = Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let joint = BicycleISA::JointMeasure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let t = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let outcome = |operation, instruction| Outcome {
+            operation,
+            instruction,
+        };
+        let mut tracker = ObservableTracker::default();
+        tracker.track(&[vec![(0, x1)]], &[vec![(0, x1)]], None);
+
+        // The repeated measurement of block 0 has the outcome of the previous line
+        let compiled = vec![
+            vec![(0, x1)],
+            vec![(1, z1)],
+            vec![(0, joint), (1, joint)],
+            vec![(1, t)],
+            vec![(1, t)],
+            vec![(0, z1)],
+            vec![(1, x1)],
+            vec![(1, t)],
+        ];
+        let optimized = compiled[1..].to_vec();
+        assert_eq!(None, tracker.track(&compiled, &optimized, None));
+        let conditioned_on = vec![outcome(0, 0), outcome(1, 0), outcome(1, 1)];
+        assert_eq!(
+            vec![
+                Conditioned {
+                    gate: outcome(1, 2),
+                    conditioned_on: conditioned_on.clone(),
+                },
+                Conditioned {
+                    gate: outcome(1, 3),
+                    conditioned_on,
+                },
+                Conditioned {
+                    gate: outcome(1, 6),
+                    conditioned_on: vec![outcome(1, 4), outcome(1, 5)],
+                },
+            ],
+            tracker.conditioned()
+        );
+    }
+}
@@ -12,11 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::Display;
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{BufRead, Write},
+};
 
 use bicycle_common::BicycleISA;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
+use crate::architecture::{PathArchitecture, Topology};
+
 // Could expand this into single block and joint block operations,
 // but I think, effectively, we want to just be able to verify if an operation fits the architecture.
 pub type Operation = Vec<(usize, BicycleISA)>;
@@ -49,3 +56,244 @@ impl Display for Operations {
         write!(f, "]")
     }
 }
+
+/// An operation with the cycle that it starts at and the number of cycles that it takes, see `optimize::ExplicitScheduler`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedOperation {
+    #[serde(rename = "start")]
+    pub start: u64,
+    #[serde(rename = "cycles")]
+    pub cycles: u64,
+    #[serde(rename = "operation")]
+    pub operation: Operation,
+}
+
+/// The encoding of a compiled program
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Each chunk of operations as a line of JSON, without the version and architecture,
+    /// which can be streamed
+    #[default]
+    Jsonl,
+    /// The whole program as a MessagePack document
+    Msgpack,
+    /// The whole program as a CBOR document
+    Cbor,
+}
+
+/// The architecture that a compiled program runs on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgramArchitecture {
+    /// The bicycle code of the blocks, e.g., `gross`
+    pub code: String,
+    /// How the data blocks are connected, e.g., `path`
+    pub topology: String,
+    pub data_blocks: usize,
+    /// The blocks that are adjacent to a magic state factory
+    pub magic_blocks: Vec<usize>,
+    /// Number of storage blocks of each factory, which are numbered after the data blocks
+    pub storage_blocks: usize,
+}
+
+impl ProgramArchitecture {
+    pub fn new(code: &str, topology: Topology, layout: &PathArchitecture) -> Self {
+        Self {
+            code: code.to_string(),
+            topology: topology
+                .to_possible_value()
+                .expect("Every topology has a name")
+                .get_name()
+                .to_string(),
+            data_blocks: layout.data_blocks(),
+            magic_blocks: layout.magic_blocks().collect(),
+            storage_blocks: layout.storage_blocks(),
+        }
+    }
+}
+
+/// A compiled program with the architecture it runs on.
+///
+/// Each chunk holds the operations of one output line of the compiler.
+/// The schema consists of the field names of this struct and of [`ProgramArchitecture`],
+/// and of the encoding of [`BicycleISA`] instructions, which serde writes as externally tagged enums
+/// named after the variants and which the `isa_encoding` test pins.
+/// `version` is increased whenever the schema changes.
+///
+/// The document formats are written and read as a whole,
+/// such that both the compiler and the reader hold the whole program in memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompiledProgram {
+    pub version: u32,
+    pub architecture: ProgramArchitecture,
+    pub chunks: Vec<Vec<Operation>>,
+}
+
+impl CompiledProgram {
+    /// The version of the schema that is written.
+    /// Version 2 adds the [`BicycleISA::TGatePair`] instruction.
+    pub const VERSION: u32 = 2;
+
+    pub fn new(architecture: ProgramArchitecture, chunks: Vec<Vec<Operation>>) -> Self {
+        Self {
+            version: Self::VERSION,
+            architecture,
+            chunks,
+        }
+    }
+
+    /// Write the program in the given format, where JSON lines only hold the chunks
+    pub fn write(
+        &self,
+        mut writer: impl Write,
+        format: OutputFormat,
+    ) -> Result<(), Box<dyn Error>> {
+        match format {
+            OutputFormat::Jsonl => {
+                for chunk in &self.chunks {
+                    serde_json::to_writer(&mut writer, chunk)?;
+                    writeln!(writer)?;
+                }
+            }
+            OutputFormat::Msgpack => rmp_serde::encode::write_named(&mut writer, self)?,
+            OutputFormat::Cbor => ciborium::into_writer(self, &mut writer)?,
+        }
+        Ok(())
+    }
+
+    /// Read a program that was written in the given format by a compiler of the same schema version.
+    /// JSON lines cannot be read, since they do not record the version and architecture.
+    pub fn read(reader: impl BufRead, format: OutputFormat) -> Result<Self, Box<dyn Error>> {
+        let program: Self = match format {
+            OutputFormat::Jsonl => {
+                return Err(
+                    "JSON lines do not record the version and architecture of a program".into(),
+                );
+            }
+            OutputFormat::Msgpack => rmp_serde::from_read(reader)?,
+            OutputFormat::Cbor => ciborium::from_reader(reader)?,
+        };
+        if program.version != Self::VERSION {
+            return Err(format!(
+                "Cannot read version {} of the program schema, only version {}",
+                program.version,
+                Self::VERSION
+            )
+            .into());
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{
+        AutomorphismData, ParallelMeasureData, Pauli, TGateData, TGatePairData, TwoBases,
+    };
+
+    use super::*;
+
+    /// The encoding of every instruction, which is part of the schema of [`CompiledProgram::VERSION`]
+    const ENCODED_ISA: &str = concat!(
+        r#"[[0,"SyndromeCycle"],[0,"CSSInitZero"],[0,"CSSInitPlus"],[0,"DestructiveZ"],[0,"DestructiveX"],"#,
+        r#"[0,{"Automorphism":{"x":2,"y":3}}],[0,{"Measure":{"p1":"X","p7":"I"}}],"#,
+        r#"[1,{"JointMeasure":{"p1":"Y","p7":"Z"}}],[0,{"ParallelMeasure":{"p":"Z"}}],"#,
+        r#"[0,"JointBellInit"],[0,"JointTransversalCX"],[0,"InitT"],"#,
+        r#"[0,{"TGate":{"basis":"X","primed":false,"adjoint":true}}],"#,
+        r#"[0,{"TGatePair":{"first":{"basis":"X","primed":false,"adjoint":true},"second":{"basis":"X","primed":false,"adjoint":true}}}],"#,
+        r#"[0,{"SGate":{"basis":"X","primed":false,"adjoint":true}}],[0,{"Delay":7}]]"#,
+    );
+
+    fn program() -> CompiledProgram {
+        let layout = PathArchitecture::for_qubits(22).with_magic_block(0);
+        let meas = BicycleISA::Measure(TwoBases::new(Pauli::X, Pauli::Z).unwrap());
+        let joint = BicycleISA::JointMeasure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let chunks = vec![
+            vec![
+                vec![(1, BicycleISA::Automorphism(AutomorphismData::new(2, 3)))],
+                vec![(0, joint), (1, joint)],
+            ],
+            vec![],
+            vec![vec![(0, meas)]],
+        ];
+        CompiledProgram::new(
+            ProgramArchitecture::new("gross", Topology::Path, &layout),
+            chunks,
+        )
+    }
+
+    #[test]
+    fn binary_formats_round_trip() -> Result<(), Box<dyn Error>> {
+        let program = program();
+        assert_eq!(vec![0], program.architecture.magic_blocks);
+        assert_eq!("path", program.architecture.topology);
+        for format in [OutputFormat::Msgpack, OutputFormat::Cbor] {
+            let mut bytes = vec![];
+            program.write(&mut bytes, format)?;
+            assert_eq!(program, CompiledProgram::read(bytes.as_slice(), format)?);
+        }
+
+        let mut newer = program.clone();
+        newer.version += 1;
+        let mut bytes = vec![];
+        newer.write(&mut bytes, OutputFormat::Cbor)?;
+        assert!(CompiledProgram::read(bytes.as_slice(), OutputFormat::Cbor).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn isa_encoding() -> Result<(), Box<dyn Error>> {
+        let t = TGateData::new(Pauli::X, false, true).unwrap();
+        let operation: Operation = vec![
+            (0, BicycleISA::SyndromeCycle),
+            (0, BicycleISA::CSSInitZero),
+            (0, BicycleISA::CSSInitPlus),
+            (0, BicycleISA::DestructiveZ),
+            (0, BicycleISA::DestructiveX),
+            (0, BicycleISA::Automorphism(AutomorphismData::new(2, 3))),
+            (
+                0,
+                BicycleISA::Measure(TwoBases::new(Pauli::X, Pauli::I).unwrap()),
+            ),
+            (
+                1,
+                BicycleISA::JointMeasure(TwoBases::new(Pauli::Y, Pauli::Z).unwrap()),
+            ),
+            (
+                0,
+                BicycleISA::ParallelMeasure(ParallelMeasureData::new(Pauli::Z).unwrap()),
+            ),
+            (0, BicycleISA::JointBellInit),
+            (0, BicycleISA::JointTransversalCX),
+            (0, BicycleISA::InitT),
+            (0, BicycleISA::TGate(t)),
+            (0, BicycleISA::TGatePair(TGatePairData::new(t, t))),
+            (0, BicycleISA::SGate(t)),
+            (0, BicycleISA::Delay(7)),
+        ];
+        assert_eq!(ENCODED_ISA, serde_json::to_string(&operation)?);
+
+        // The binary formats name the variants like JSON
+        let delay: Operation = vec![(1, BicycleISA::Delay(7))];
+        assert_eq!(
+            b"\x91\x92\x01\x81\xa5Delay\x07".as_slice(),
+            rmp_serde::to_vec_named(&delay)?
+        );
+        let mut cbor = vec![];
+        ciborium::into_writer(&delay, &mut cbor)?;
+        assert_eq!(b"\x81\x82\x01\xa1\x65Delay\x07".as_slice(), cbor);
+        Ok(())
+    }
+
+    #[test]
+    fn jsonl_holds_chunks() -> Result<(), Box<dyn Error>> {
+        let program = program();
+        let mut bytes = vec![];
+        program.write(&mut bytes, OutputFormat::Jsonl)?;
+        let lines: Vec<Vec<Operation>> = String::from_utf8(bytes)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(program.chunks, lines);
+        Ok(())
+    }
+}
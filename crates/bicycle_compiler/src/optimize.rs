@@ -12,9 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bicycle_common::{AutomorphismData, BicycleISA};
+use std::collections::HashMap;
 
-use crate::operation::Operation;
+use bicycle_cliffords::{MeasurementTable, PauliString, native_measurement::NativeMeasurement};
+use bicycle_common::{AutomorphismData, BicycleISA, Pauli, TwoBases};
+
+use crate::{
+    explain::{self, Stage},
+    model::InstructionCost,
+    operation::{Operation, TimedOperation},
+};
 
 /// Remove measurements that are repeated on the same block
 /// Note: This considers only single-block measurements for simplicity
@@ -31,24 +38,26 @@ pub fn remove_duplicate_measurements_chunked(
     let mut history: Vec<Option<BicycleISA>> = Vec::new();
 
     chunked_ops.into_iter().map(move |ops_chunk| {
-        ops_chunk
-            .into_iter()
-            .filter(|ops_list| {
-                for (i, instr) in ops_list {
-                    history.resize_with(history.len().max(i + 1), Default::default);
+        let ops_chunk = ops_chunk.into_iter();
+        explain::time(Stage::DuplicateMeasurements, || {
+            ops_chunk
+                .filter(|ops_list| {
+                    for (i, instr) in ops_list {
+                        history.resize_with(history.len().max(i + 1), Default::default);
 
-                    if let BicycleISA::Measure(_) = instr {
-                        if history[*i] == Some(*instr) {
-                            return false;
+                        if let BicycleISA::Measure(_) = instr {
+                            if history[*i] == Some(*instr) {
+                                return false;
+                            }
                         }
+                        // Copy seen instructions into history
+                        // Cannot reference because that would make instructions immutable
+                        history[*i] = Some(*instr);
                     }
-                    // Copy seen instructions into history
-                    // Cannot reference because that would make instructions immutable
-                    history[*i] = Some(*instr);
-                }
-                true
-            })
-            .collect()
+                    true
+                })
+                .collect()
+        })
     })
 }
 
@@ -62,12 +71,427 @@ pub fn remove_trivial_automorphisms(
     })
 }
 
+/// Lower each automorphism into the elementary shifts that hardware executes, see `AutomorphismData::decompose`.
+/// The number of automorphism generators is unchanged, since each shift is a single generator,
+/// and identity automorphisms are dropped.
+pub fn decompose_automorphisms(
+    ops: impl IntoIterator<Item = Operation>,
+) -> impl Iterator<Item = Operation> {
+    ops.into_iter().flat_map(|op| match op[..] {
+        [(block, BicycleISA::Automorphism(autdata))] => autdata
+            .decompose()
+            .into_iter()
+            .map(|shift| vec![(block, BicycleISA::Automorphism(shift.into()))])
+            .collect(),
+        _ => vec![op],
+    })
+}
+
+/// Check each T injection by measuring the pivot of the magic block `repetitions - 1` more times
+/// in the basis of the T gate, such that the outcome of the gadget is decided by a majority vote.
+/// This trades the time of the repeated measurements for a smaller injection error, see `--verified-t` of `bicycle_numerics`.
+pub fn verify_t_injections(
+    ops: impl IntoIterator<Item = Operation>,
+    repetitions: usize,
+) -> impl Iterator<Item = Operation> {
+    assert!(
+        repetitions % 2 == 1,
+        "A majority vote needs an odd number of repetitions, not {repetitions}"
+    );
+    ops.into_iter().flat_map(move |op| {
+        let checks = match op[..] {
+            [(block, BicycleISA::TGate(tgate))] => {
+                let (p1, p7) = if tgate.primed {
+                    (Pauli::I, tgate.get_basis())
+                } else {
+                    (tgate.get_basis(), Pauli::I)
+                };
+                let check = vec![(block, BicycleISA::Measure(TwoBases::new(p1, p7).unwrap()))];
+                vec![check; repetitions - 1]
+            }
+            _ => vec![],
+        };
+        std::iter::once(op).chain(checks)
+    })
+}
+
+/// Packs operations into time steps, such that the operations of a step act on disjoint blocks.
+///
+/// The operations on a block depend on each other in the order that they are given,
+/// and operations on disjoint blocks are independent.
+/// Each operation is scheduled in the first step after the last operation on any of its blocks,
+/// which is the longest path to it in the dependency DAG.
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    /// The first free step of each block
+    ready: Vec<u64>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedule an operation after the operations so far and return its step
+    pub fn step(&mut self, op: &Operation) -> u64 {
+        let step = op
+            .iter()
+            .map(|(block, _)| self.ready.get(*block).copied().unwrap_or(0))
+            .max()
+            .unwrap_or(0);
+        for (block, _) in op {
+            if *block >= self.ready.len() {
+                self.ready.resize(block + 1, 0);
+            }
+            self.ready[*block] = step + 1;
+        }
+        step
+    }
+
+    /// Number of steps of the operations so far
+    pub fn steps(&self) -> u64 {
+        self.ready.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Schedule operations and reorder them by their step.
+    /// The operations on each block keep their order, so the reordered operations are equivalent.
+    pub fn schedule(&mut self, ops: impl IntoIterator<Item = Operation>) -> Vec<Operation> {
+        let mut scheduled: Vec<_> = ops.into_iter().map(|op| (self.step(&op), op)).collect();
+        // The sort is stable, which keeps the order of the operations of a step
+        scheduled.sort_by_key(|(step, _)| *step);
+        scheduled.into_iter().map(|(_, op)| op).collect()
+    }
+}
+
+/// Reorder the operations of each chunk by their time step, see [`Scheduler`].
+/// The steps continue across chunks, but operations do not move to another chunk.
+pub fn schedule_chunked(
+    chunked_ops: impl IntoIterator<Item = impl IntoIterator<Item = Operation>>,
+) -> impl Iterator<Item = Vec<Operation>> {
+    let mut scheduler = Scheduler::new();
+    chunked_ops
+        .into_iter()
+        .map(move |chunk| explain::time(Stage::Schedule, || scheduler.schedule(chunk)))
+}
+
+/// Tags each operation with its start time under a model and makes the idles of the blocks explicit,
+/// such that the schedule that `bicycle_numerics` infers can be read from the output.
+///
+/// An operation starts once each of its blocks is done with its previous instructions,
+/// and takes the time of its slowest instruction.
+/// A block that waits for the other blocks of an operation idles, which is made explicit
+/// by a `SyndromeCycle` on the block for the cycles it waits, right before the operation.
+/// Blocks that are added to the architecture start once they are added, and the waiting of factories
+/// for stored magic states is not modeled.
+pub struct ExplicitScheduler<M> {
+    model: M,
+    /// The cycle at which each block is done with its previous instructions
+    times: Vec<u64>,
+}
+
+impl<M: InstructionCost> ExplicitScheduler<M> {
+    /// Schedule the operations on `blocks` blocks, which all start at cycle 0
+    pub fn new(model: M, blocks: usize) -> Self {
+        Self {
+            model,
+            times: vec![0; blocks],
+        }
+    }
+
+    /// Schedule operations after the operations so far, inserting the idles before them
+    pub fn schedule(&mut self, ops: impl IntoIterator<Item = Operation>) -> Vec<TimedOperation> {
+        let mut timed = vec![];
+        for op in ops {
+            let now = self.end_time();
+            for (block, _) in &op {
+                if *block >= self.times.len() {
+                    self.times.resize(block + 1, now);
+                }
+            }
+            let start = op
+                .iter()
+                .map(|(block, _)| self.times[*block])
+                .max()
+                .unwrap_or(now);
+            for (block, _) in &op {
+                let ready = self.times[*block];
+                if ready < start {
+                    timed.push(TimedOperation {
+                        start: ready,
+                        cycles: start - ready,
+                        operation: vec![(*block, BicycleISA::SyndromeCycle)],
+                    });
+                    // A block that occurs twice in the operation idles once
+                    self.times[*block] = start;
+                }
+            }
+            let mut cycles = 0;
+            for (block, instr) in &op {
+                let timing = self.model.timing(instr);
+                cycles = cycles.max(timing);
+                self.times[*block] = start + timing;
+            }
+            timed.push(TimedOperation {
+                start,
+                cycles,
+                operation: op,
+            });
+        }
+        timed
+    }
+
+    /// The cycle at which all blocks are done with the operations so far
+    pub fn end_time(&self) -> u64 {
+        self.times.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Shortens runs of consecutive automorphisms and measurements on a single block.
+///
+/// A run measures a sequence of Paulis and leaves the block conjugated by the product of its automorphisms.
+/// It is replaced by the native measurements of the table for these Paulis, with merged automorphisms in between,
+/// if that needs fewer instructions or automorphism generators.
+/// A measurement that repeats the previous measurement of the run is dropped.
+pub struct BlockCompressor {
+    measures: HashMap<NativeMeasurement, PauliString>,
+    implementations: HashMap<PauliString, NativeMeasurement>,
+    compressed: usize,
+}
+
+impl BlockCompressor {
+    pub fn new(measurement_table: &dyn MeasurementTable) -> Self {
+        let implementations = measurement_table.native_measurements().clone();
+        let measures = implementations
+            .iter()
+            .map(|(p, native)| (*native, *p))
+            .collect();
+        Self {
+            measures,
+            implementations,
+            compressed: 0,
+        }
+    }
+
+    /// Compress the runs of the operations, which do not extend across calls
+    pub fn compress(&mut self, ops: impl IntoIterator<Item = Operation>) -> Vec<Operation> {
+        let mut out = vec![];
+        let mut run: Vec<Operation> = vec![];
+        for op in ops {
+            match run_block(&op) {
+                Some(block) => {
+                    if run.first().is_some_and(|first| first[0].0 != block) {
+                        self.finish_run(&mut run, &mut out);
+                    }
+                    run.push(op);
+                }
+                None => {
+                    self.finish_run(&mut run, &mut out);
+                    out.push(op);
+                }
+            }
+        }
+        self.finish_run(&mut run, &mut out);
+        out
+    }
+
+    /// Number of runs that were replaced by a shorter run
+    pub fn compressed(&self) -> usize {
+        self.compressed
+    }
+
+    fn finish_run(&mut self, run: &mut Vec<Operation>, out: &mut Vec<Operation>) {
+        match self.shorten(run) {
+            Some(shorter) => {
+                self.compressed += 1;
+                out.extend(shorter);
+                run.clear();
+            }
+            None => out.append(run),
+        }
+    }
+
+    /// A cheaper run with the same measurements and the same automorphism at the end, if there is one
+    fn shorten(&self, run: &[Operation]) -> Option<Vec<Operation>> {
+        let block = run.first()?[0].0;
+        let mut automorphism = AutomorphismData::new(0, 0);
+        let mut measured: Vec<PauliString> = vec![];
+        for op in run {
+            match op[0].1 {
+                BicycleISA::Automorphism(aut) => automorphism *= aut,
+                BicycleISA::Measure(logical) => {
+                    // Measurements that are not in the table are left alone
+                    let p = *self.measures.get(&NativeMeasurement {
+                        logical,
+                        automorphism,
+                    })?;
+                    if measured.last() != Some(&p) {
+                        measured.push(p);
+                    }
+                }
+                _ => unreachable!("Runs only contain automorphisms and measurements"),
+            }
+        }
+
+        let mut shorter = vec![];
+        let mut current = AutomorphismData::new(0, 0);
+        for p in measured {
+            let native = self.implementations[&p];
+            push_automorphism(&mut shorter, block, native.automorphism * current.inv());
+            shorter.push(vec![(block, BicycleISA::Measure(native.logical))]);
+            current = native.automorphism;
+        }
+        push_automorphism(&mut shorter, block, automorphism * current.inv());
+
+        (run_cost(&shorter) < run_cost(run)).then_some(shorter)
+    }
+}
+
+/// The block of a single automorphism or measurement, which can be part of a run
+fn run_block(op: &Operation) -> Option<usize> {
+    match op[..] {
+        [(block, BicycleISA::Automorphism(_) | BicycleISA::Measure(_))] => Some(block),
+        _ => None,
+    }
+}
+
+fn push_automorphism(ops: &mut Vec<Operation>, block: usize, aut: AutomorphismData) {
+    if !aut.is_id() {
+        ops.push(vec![(block, BicycleISA::Automorphism(aut))]);
+    }
+}
+
+/// The number of instructions and automorphism generators of a run
+fn run_cost(run: &[Operation]) -> (usize, u64) {
+    let generators = run
+        .iter()
+        .map(|op| match op[0].1 {
+            BicycleISA::Automorphism(aut) => aut.nr_generators(),
+            _ => 0,
+        })
+        .sum();
+    (run.len(), generators)
+}
+
 #[cfg(test)]
 mod tests {
-    use bicycle_common::TwoBases;
+    use bicycle_cliffords::{CompleteMeasurementTable, GROSS_MEASUREMENT};
+    use bicycle_common::TGateData;
 
     use super::*;
-    use bicycle_common::Pauli::{X, Y, Z};
+    use crate::model::GROSS_1E3;
+    use bicycle_common::Pauli::{I, X, Y, Z};
+
+    #[test]
+    fn verified_t_injections() {
+        let tgate = BicycleISA::TGate(TGateData::new(X, true, false).unwrap());
+        let meas = BicycleISA::Measure(TwoBases::new(Z, I).unwrap());
+        let ops = vec![vec![(2, tgate)], vec![(0, meas)]];
+
+        let check = BicycleISA::Measure(TwoBases::new(I, X).unwrap());
+        let expected = vec![
+            vec![(2, tgate)],
+            vec![(2, check)],
+            vec![(2, check)],
+            vec![(0, meas)],
+        ];
+        assert_eq!(
+            expected,
+            verify_t_injections(ops.clone(), 3).collect::<Vec<_>>()
+        );
+        assert_eq!(ops, verify_t_injections(ops.clone(), 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn decomposed_automorphisms() {
+        let aut = |x, y| BicycleISA::Automorphism(AutomorphismData::new(x, y));
+        let meas = BicycleISA::Measure(TwoBases::new(Z, I).unwrap());
+        let ops = vec![
+            vec![(1, aut(2, 2))],
+            vec![(0, meas)],
+            vec![(0, aut(0, 5))],
+            vec![(2, aut(0, 0))],
+        ];
+
+        let decomposed: Vec<_> = decompose_automorphisms(ops).collect();
+        // The identity needs no shifts and is dropped
+        assert_eq!(4, decomposed.len());
+        assert_eq!(vec![(0, meas)], decomposed[2]);
+        assert_eq!(vec![(0, aut(0, 5))], decomposed[3]);
+        let product = decomposed[..2]
+            .iter()
+            .fold(AutomorphismData::default(), |acc, op| {
+                let [(1, BicycleISA::Automorphism(shift))] = op[..] else {
+                    panic!("Expected an automorphism on block 1, not {op:?}");
+                };
+                assert_eq!(1, shift.nr_generators());
+                acc * shift
+            });
+        assert_eq!(AutomorphismData::new(2, 2), product);
+    }
+
+    #[test]
+    fn explicit_idles() {
+        let meas = BicycleISA::Measure(TwoBases::new(X, Z).unwrap());
+        let joint = BicycleISA::JointMeasure(TwoBases::new(Z, I).unwrap());
+        let tgate = BicycleISA::TGate(TGateData::new(X, false, false).unwrap());
+        let mut scheduler = ExplicitScheduler::new(GROSS_1E3, 2);
+        let timed = scheduler.schedule(vec![
+            vec![(0, tgate)],
+            vec![(1, meas)],
+            vec![(0, joint), (1, joint)],
+        ]);
+        let timed: Vec<_> = timed
+            .into_iter()
+            .map(|timed| (timed.start, timed.cycles, timed.operation))
+            .collect();
+        let [t, m, j] = [tgate, meas, joint].map(|instr| GROSS_1E3.timing(&instr));
+        assert_eq!(
+            vec![
+                (0, t, vec![(0, tgate)]),
+                (0, m, vec![(1, meas)]),
+                (m, t - m, vec![(1, BicycleISA::SyndromeCycle)]),
+                (t, j, vec![(0, joint), (1, joint)]),
+            ],
+            timed
+        );
+        assert_eq!(t + j, scheduler.end_time());
+
+        // An added block starts at the end of the operations so far
+        let timed = scheduler.schedule(vec![vec![(2, meas)]]);
+        assert_eq!(t + j, timed[0].start);
+        assert_eq!(1, timed.len());
+    }
+
+    #[test]
+    fn schedule_disjoint_blocks() {
+        let meas = BicycleISA::Measure(TwoBases::new(X, Z).unwrap());
+        let joint = BicycleISA::JointMeasure(TwoBases::new(Z, I).unwrap());
+        let ops = vec![
+            vec![(0, meas)],
+            vec![(0, meas)],
+            vec![(1, meas)],
+            vec![(0, joint), (1, joint)],
+            vec![(2, meas)],
+        ];
+
+        let mut scheduler = Scheduler::new();
+        let scheduled = scheduler.schedule(ops.clone());
+        assert_eq!(3, scheduler.steps());
+        assert_eq!(
+            vec![
+                vec![(0, meas)],
+                vec![(1, meas)],
+                vec![(2, meas)],
+                vec![(0, meas)],
+                vec![(0, joint), (1, joint)],
+            ],
+            scheduled
+        );
+
+        // The steps continue in the next chunk
+        let chunks: Vec<_> = schedule_chunked([ops.clone(), vec![vec![(2, meas)]]]).collect();
+        assert_eq!(vec![scheduled, vec![vec![(2, meas)]]], chunks);
+    }
 
     #[test]
     fn remove_duplicate_meas() {
@@ -89,6 +513,68 @@ mod tests {
         assert_eq!(expected, res);
     }
 
+    fn native_table() -> CompleteMeasurementTable {
+        let natives = NativeMeasurement::all()
+            .into_iter()
+            .map(|native| (GROSS_MEASUREMENT.measures(&native), native))
+            .collect();
+        CompleteMeasurementTable::from_shards(natives, [])
+    }
+
+    fn implementation(block: usize, native: &NativeMeasurement) -> Vec<Operation> {
+        [
+            BicycleISA::Automorphism(native.automorphism),
+            BicycleISA::Measure(native.logical),
+            BicycleISA::Automorphism(native.automorphism.inv()),
+        ]
+        .map(|instr| vec![(block, instr)])
+        .to_vec()
+    }
+
+    #[test]
+    fn compress_blocks() {
+        let table = native_table();
+        let mut natives: Vec<_> = table
+            .native_measurements()
+            .values()
+            .filter(|native| !native.automorphism.is_id())
+            .copied()
+            .collect();
+        natives.sort_by_key(|native| (native.automorphism.get_x(), native.automorphism.get_y()));
+        let (first, second) = (natives[0], natives[natives.len() - 1]);
+        assert_ne!(first.automorphism, second.automorphism);
+
+        let mut compressor = BlockCompressor::new(&table);
+        let ops = [implementation(2, &first), implementation(2, &second)].concat();
+        let expected = vec![
+            vec![(2, BicycleISA::Automorphism(first.automorphism))],
+            vec![(2, BicycleISA::Measure(first.logical))],
+            vec![(
+                2,
+                BicycleISA::Automorphism(second.automorphism * first.automorphism.inv()),
+            )],
+            vec![(2, BicycleISA::Measure(second.logical))],
+            vec![(2, BicycleISA::Automorphism(second.automorphism.inv()))],
+        ];
+        assert_eq!(expected, compressor.compress(ops));
+
+        // Repeated measurements are dropped
+        let ops = [implementation(2, &first), implementation(2, &first)].concat();
+        assert_eq!(implementation(2, &first), compressor.compress(ops));
+        assert_eq!(2, compressor.compressed());
+
+        // Runs end at instructions on other blocks and are left alone if they cannot be shortened
+        let ops = [
+            implementation(0, &first),
+            implementation(1, &first),
+            vec![vec![(0, BicycleISA::Measure(first.logical))]],
+            implementation(0, &second),
+        ]
+        .concat();
+        assert_eq!(ops, compressor.compress(ops.clone()));
+        assert_eq!(2, compressor.compressed());
+    }
+
     #[test]
     fn remove_trivial_auts() {
         let nontrivial_aut = BicycleISA::Automorphism(AutomorphismData::new(3, 4));
@@ -0,0 +1,243 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing the compiled program, and the reports and logs on it
+
+use std::{
+    error,
+    io::{self, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bicycle_compiler::{
+    PathArchitecture,
+    budget::ErrorBudget,
+    explain,
+    model::{Model, ModelFile},
+    operation::{Operation, OutputFormat},
+    optimize,
+    qre::{CompiledCounter, CountSource},
+    report::{ReportBuilder, ResourceReport},
+    verify::{Verifier, VerifyError},
+};
+use clap::ValueEnum;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    cli::{Cli, Commands, NoiseRate, builtin_model},
+    passes::{ExpectedOperations, ObservableLog},
+};
+
+/// Checks, counts and writes the optimized chunks
+pub struct OutputSink<'a> {
+    started: Instant,
+    first_instruction: bool,
+    pub compiled_counter: Option<CompiledCounter>,
+    observables: Option<&'a Mutex<ObservableLog>>,
+    pub verifier: Option<Verifier>,
+    expected: &'a ExpectedOperations,
+    pub verified: Result<(), VerifyError>,
+    pub summary: Option<ReportBuilder<Model>>,
+    /// The output lines of a document format, which is written once the architecture is final
+    pub buffered: Option<Vec<Vec<Operation>>>,
+    pub explicit_scheduler: Option<optimize::ExplicitScheduler<Model>>,
+}
+
+impl<'a> OutputSink<'a> {
+    pub fn new(
+        cli: &Cli,
+        architecture: &PathArchitecture,
+        report_model: Option<&ReportModel>,
+        observables: Option<&'a Mutex<ObservableLog>>,
+        expected: &'a ExpectedOperations,
+        started: Instant,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let counting =
+            cli.output.qre_counts.is_some() && cli.output.qre_source == CountSource::Compiled;
+        let code = cli.measurement()?;
+        Ok(Self {
+            started,
+            first_instruction: true,
+            compiled_counter: counting.then(|| CompiledCounter::new(architecture.qubits())),
+            observables,
+            verifier: cli.passes.verify.then(|| Verifier::new(code, architecture)),
+            expected,
+            verified: Ok(()),
+            summary: report_model.map(|report| ReportBuilder::new(report.model, architecture)),
+            buffered: (cli.output.output_format != OutputFormat::Jsonl).then(Vec::new),
+            explicit_scheduler: cli.output.explicit_schedule.then(|| {
+                optimize::ExplicitScheduler::new(
+                    builtin_model(cli.code, cli.output.schedule_noise),
+                    architecture.total_blocks(),
+                )
+            }),
+        })
+    }
+
+    /// Write an optimized chunk, or stop with an error if the verifier rejects it
+    pub fn write(&mut self, chunk: Vec<Operation>) -> io::Result<()> {
+        if self.first_instruction && !chunk.is_empty() {
+            self.first_instruction = false;
+            info!(
+                "Wrote the first instruction after {:.2?}",
+                self.started.elapsed()
+            );
+        }
+        if let Some(counter) = self.compiled_counter.as_mut() {
+            counter.add(&chunk);
+        }
+        if let Some(observables) = self.observables {
+            observables.lock().unwrap().optimized(&chunk)?;
+        }
+        if let Some(verifier) = self.verifier.as_mut() {
+            let (op, restore) = self
+                .expected
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("Every output line is compiled from an operation");
+            self.verified = verifier.check_cached(op.as_ref(), &chunk, &restore);
+            if let Err(err) = &self.verified {
+                return Err(io::Error::other(err.clone()));
+            }
+        }
+        if let Some(summary) = self.summary.as_mut() {
+            summary.add(&chunk);
+            return Ok(());
+        }
+        if let Some(buffered) = self.buffered.as_mut() {
+            buffered.push(chunk);
+            return Ok(());
+        }
+        let out = match self.explicit_scheduler.as_mut() {
+            Some(scheduler) => serde_json::to_string(&scheduler.schedule(chunk))?,
+            None => serde_json::to_string(&chunk)?,
+        };
+        writeln!(io::stdout(), "{out}")
+    }
+}
+
+/// The model of `report` with the code and physical noise rate that label it
+pub struct ReportModel {
+    pub code: String,
+    pub p: f64,
+    pub model: Model,
+    pub cycle_time: f64,
+    pub json: bool,
+}
+
+impl ReportModel {
+    pub fn of(cli: &Cli) -> Result<Option<Self>, Box<dyn error::Error>> {
+        let Some(Commands::Report {
+            noise,
+            model_file,
+            cycle_time,
+            json,
+        }) = &cli.commands
+        else {
+            return Ok(None);
+        };
+        let (code, p, model) = match model_file {
+            Some(path) => {
+                let file = ModelFile::from_file(path)
+                    .map_err(|e| format!("Cannot read the model file {}: {e}", path.display()))?;
+                (file.code.clone(), file.p, file.model())
+            }
+            None => {
+                let model = builtin_model(cli.code, *noise);
+                let p = match noise {
+                    NoiseRate::P1e3 => 1e-3,
+                    NoiseRate::P1e4 => 1e-4,
+                };
+                let code = cli.code.to_possible_value().unwrap().get_name().to_string();
+                (code, p, model)
+            }
+        };
+        Ok(Some(Self {
+            code,
+            p,
+            model,
+            cycle_time: *cycle_time,
+            json: *json,
+        }))
+    }
+
+    /// Print the report to stdout
+    pub fn print(&self, report: ResourceReport) -> Result<(), Box<dyn error::Error>> {
+        #[derive(Serialize)]
+        struct Labeled<'a> {
+            code: &'a str,
+            p: f64,
+            #[serde(flatten)]
+            report: ResourceReport,
+        }
+        if self.json {
+            let labeled = Labeled {
+                code: &self.code,
+                p: self.p,
+                report,
+            };
+            println!("{}", serde_json::to_string_pretty(&labeled)?);
+        } else {
+            println!("{:<26} {:>16}", "Code", self.code);
+            println!("{:<26} {:>16.0e}", "Physical noise rate", self.p);
+            println!("{report}");
+        }
+        Ok(())
+    }
+}
+
+/// Log the synthesis error that was taken from `--error-budget`
+pub fn log_error_budget(budget: &Option<Mutex<ErrorBudget>>) {
+    let Some(budget) = budget else {
+        return;
+    };
+    let budget = budget.lock().unwrap();
+    info!(
+        "Took the accuracies of {} rotations from the error budget, with a total synthesis error of at most {:.3e}",
+        budget.assigned(),
+        budget.spent().to_num::<f64>()
+    );
+    if budget.spent() > budget.total() {
+        warn!(
+            "The synthesis error exceeds the error budget of {:.3e}, because of explicit accuracies, multi-rotations, or more than --rotations rotations",
+            budget.total().to_num::<f64>()
+        );
+    }
+}
+
+/// Print the aggregated time of each stage of compilation, see `--explain`
+pub fn report_stage_times(elapsed: Duration) {
+    eprintln!(
+        "{:<32} {:>12} {:>10} {:>12}",
+        "Stage", "Total", "Calls", "Average"
+    );
+    for time in explain::times() {
+        // The nested stages are part of compiling the operations
+        let stage = if time.stage.nested() {
+            format!("  {}", time.stage)
+        } else {
+            time.stage.to_string()
+        };
+        eprintln!(
+            "{stage:<32} {:>12.2?} {:>10} {:>12.2?}",
+            time.total,
+            time.calls,
+            time.average()
+        );
+    }
+    eprintln!("{:<32} {elapsed:>12.2?}", "total since start");
+}
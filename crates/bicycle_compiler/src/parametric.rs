@@ -0,0 +1,298 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rotations about multiples of symbolic parameters, e.g., the angles of a variational circuit.
+//!
+//! A program is compiled once to a [`Template`], in which only the rotations of the magic blocks depend on the parameters.
+//! Each binding of values to the parameters then only synthesizes those rotations, see [`Template::finalize`].
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt::Display,
+};
+
+use bicycle_cliffords::MeasurementTable;
+use bicycle_common::Pauli;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    architecture::Architecture,
+    compile::{self, RotationTemplate},
+    language::{AnglePrecision, PbcOperation},
+    operation::Operation,
+};
+
+/// A parameter multiplied by a scale, e.g., `{"param":"gamma","scale":"0.5"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parameter {
+    pub param: String,
+    #[serde(default = "unit_scale")]
+    pub scale: AnglePrecision,
+}
+
+fn unit_scale() -> AnglePrecision {
+    AnglePrecision::ONE
+}
+
+impl Parameter {
+    fn value(&self, bindings: &Bindings) -> Result<AnglePrecision, UnboundParameter> {
+        bindings
+            .get(&self.param)
+            .map(|value| self.scale * *value)
+            .ok_or_else(|| UnboundParameter(self.param.clone()))
+    }
+}
+
+/// The angle of a rotation, given by a value or by a scaled parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Angle {
+    Value(AnglePrecision),
+    Parameter(Parameter),
+}
+
+/// A [`PbcOperation`] whose rotation angle may be a scaled parameter, e.g.,
+/// `{"Rotation":{"basis":["Z","Z"],"angle":{"param":"gamma","scale":"0.5"}}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParametricOperation {
+    Rotation {
+        basis: Vec<Pauli>,
+        angle: Angle,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        accuracy: Option<AnglePrecision>,
+    },
+    #[serde(untagged)]
+    Fixed(PbcOperation),
+}
+
+/// Values of the parameters by name
+pub type Bindings = HashMap<String, AnglePrecision>;
+
+/// A parameter that is not bound to a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnboundParameter(pub String);
+
+impl Display for UnboundParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The parameter {} is not bound to a value", self.0)
+    }
+}
+
+impl Error for UnboundParameter {}
+
+impl ParametricOperation {
+    pub fn basis(&self) -> &[Pauli] {
+        match self {
+            ParametricOperation::Rotation { basis, .. } => basis,
+            ParametricOperation::Fixed(op) => op.basis(),
+        }
+    }
+
+    /// Substitute the values of the parameters
+    pub fn bind(self, bindings: &Bindings) -> Result<PbcOperation, UnboundParameter> {
+        match self {
+            ParametricOperation::Rotation {
+                basis,
+                angle,
+                accuracy,
+            } => Ok(PbcOperation::Rotation {
+                basis,
+                angle: match angle {
+                    Angle::Value(angle) => angle,
+                    Angle::Parameter(parameter) => parameter.value(bindings)?,
+                },
+                accuracy,
+            }),
+            ParametricOperation::Fixed(op) => Ok(op),
+        }
+    }
+}
+
+/// One output line of a [`Template`]
+#[derive(Debug, Clone)]
+enum Line {
+    Compiled(Vec<Operation>),
+    Rotation {
+        template: RotationTemplate,
+        angle: Parameter,
+        accuracy: AnglePrecision,
+    },
+}
+
+/// A program that is compiled up to the rotations of the magic blocks about parametric angles
+#[derive(Debug, Clone)]
+pub struct Template {
+    lines: Vec<Line>,
+}
+
+impl Template {
+    /// Compile all operations, except for the rotations about parametric angles of the magic blocks
+    pub fn compile(
+        ops: impl IntoIterator<Item = ParametricOperation>,
+        architecture: &dyn Architecture,
+        measurement_table: &dyn MeasurementTable,
+        accuracy: AnglePrecision,
+    ) -> Self {
+        let lines = ops
+            .into_iter()
+            .map(|op| match op {
+                ParametricOperation::Rotation {
+                    basis,
+                    angle: Angle::Parameter(angle),
+                    accuracy: op_accuracy,
+                } => Line::Rotation {
+                    template: compile::compile_rotation_template(
+                        architecture,
+                        measurement_table,
+                        basis,
+                    ),
+                    angle,
+                    accuracy: op_accuracy.unwrap_or(accuracy),
+                },
+                op => Line::Compiled(
+                    op.bind(&Bindings::new())
+                        .expect("Only rotations about parametric angles have parameters")
+                        .compile(architecture, measurement_table, accuracy),
+                ),
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// The names of the parameters of the program
+    pub fn parameters(&self) -> BTreeSet<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Rotation { angle, .. } => Some(angle.param.as_str()),
+                Line::Compiled(_) => None,
+            })
+            .collect()
+    }
+
+    /// Compile the program for the given values of the parameters, one output line per operation
+    pub fn finalize<'a>(
+        &'a self,
+        bindings: &'a Bindings,
+    ) -> Result<impl Iterator<Item = Vec<Operation>> + 'a, UnboundParameter> {
+        if let Some(unbound) = self
+            .parameters()
+            .into_iter()
+            .find(|param| !bindings.contains_key(*param))
+        {
+            return Err(UnboundParameter(unbound.to_string()));
+        }
+        Ok(self.lines.iter().map(|line| match line {
+            Line::Compiled(ops) => ops.clone(),
+            Line::Rotation {
+                template,
+                angle,
+                accuracy,
+            } => template.finalize(angle.value(bindings).unwrap(), *accuracy),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{architecture::PathArchitecture, small_angle::T_ANGLE, test::GROSS_TABLE};
+
+    use super::*;
+
+    use Pauli::{I, X, Z};
+
+    #[test]
+    fn parse_parametric_angles() -> Result<(), serde_json::Error> {
+        let op: ParametricOperation = serde_json::from_str(
+            r#"{"Rotation":{"basis":["Z","Z"],"angle":{"param":"gamma","scale":"0.5"}}}"#,
+        )?;
+        let bindings = Bindings::from([("gamma".to_string(), AnglePrecision::lit("0.25"))]);
+        assert_eq!(
+            PbcOperation::Rotation {
+                basis: vec![Z, Z],
+                angle: AnglePrecision::lit("0.125"),
+                accuracy: None,
+            },
+            op.clone().bind(&bindings).unwrap()
+        );
+        assert_eq!(
+            Err(UnboundParameter("gamma".to_string())),
+            op.bind(&Bindings::new())
+        );
+
+        let op: ParametricOperation =
+            serde_json::from_str(r#"{"Rotation":{"basis":["X"],"angle":{"param":"beta"}}}"#)?;
+        let bindings = Bindings::from([("beta".to_string(), AnglePrecision::lit("0.3"))]);
+        assert_eq!(
+            AnglePrecision::lit("0.3"),
+            match op.bind(&bindings).unwrap() {
+                PbcOperation::Rotation { angle, .. } => angle,
+                op => panic!("{op} should be a rotation"),
+            }
+        );
+
+        // Operations without parameters are parsed as usual
+        let json = r#"{"Measurement":{"basis":["Z","X"],"flip_result":false}}"#;
+        let op: ParametricOperation = serde_json::from_str(json)?;
+        assert_eq!(ParametricOperation::Fixed(serde_json::from_str(json)?), op);
+        let op: ParametricOperation =
+            serde_json::from_str(r#"{"Rotation":{"basis":["Z","X"],"angle":"0.1"}}"#)?;
+        assert_eq!(
+            PbcOperation::rotation(vec![Z, X], 0.1).basis(),
+            op.bind(&Bindings::new()).unwrap().basis()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finalize_template() {
+        let architecture = PathArchitecture::new(2);
+        let accuracy = AnglePrecision::lit("1e-9");
+        let basis = [vec![X; 11], vec![I; 10], vec![Z]].concat();
+        let measurement = PbcOperation::Measurement {
+            basis: basis.clone(),
+            flip_result: false,
+        };
+        let ops = vec![
+            ParametricOperation::Fixed(measurement.clone()),
+            ParametricOperation::Rotation {
+                basis: basis.clone(),
+                angle: Angle::Parameter(Parameter {
+                    param: "theta".to_string(),
+                    scale: AnglePrecision::lit("0.5"),
+                }),
+                accuracy: None,
+            },
+        ];
+        let template = Template::compile(ops, &architecture, &*GROSS_TABLE, accuracy);
+        assert_eq!(BTreeSet::from(["theta"]), template.parameters());
+
+        let bindings = Bindings::from([("theta".to_string(), 2 * T_ANGLE)]);
+        let compiled: Vec<_> = template.finalize(&bindings).unwrap().collect();
+        let rotation = PbcOperation::Rotation {
+            basis,
+            angle: T_ANGLE,
+            accuracy: None,
+        };
+        assert_eq!(
+            vec![
+                measurement.compile(&architecture, &*GROSS_TABLE, accuracy),
+                rotation.compile(&architecture, &*GROSS_TABLE, accuracy),
+            ],
+            compiled
+        );
+        assert!(template.finalize(&Bindings::new()).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The reports on the compiled operations and the optimization passes on them
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use bicycle_cliffords::MeasurementTable;
+use bicycle_compiler::{
+    Architecture, PathArchitecture,
+    analysis::{self, Cost, CostRatio},
+    explain::{self, Stage},
+    fault::FaultInjector,
+    language::{AnglePrecision, PbcOperation},
+    observable::ObservableTracker,
+    operation::Operation,
+    optimize,
+};
+use log::{debug, info};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::cli::Cli;
+
+/// Compares the compiled cost of each operation to its lower bound
+pub struct LowerBoundReport {
+    out: io::BufWriter<File>,
+    threshold: f64,
+    operations: usize,
+    flagged: usize,
+}
+
+impl LowerBoundReport {
+    pub fn new(path: &Path, threshold: f64) -> io::Result<Self> {
+        Ok(Self {
+            out: io::BufWriter::new(File::create(path)?),
+            threshold,
+            operations: 0,
+            flagged: 0,
+        })
+    }
+
+    pub fn add(
+        &mut self,
+        op: &PbcOperation,
+        compiled: &[Operation],
+        architecture: &dyn Architecture,
+        accuracy: AnglePrecision,
+    ) {
+        let ratio = CostRatio::new(
+            self.operations,
+            Cost::of_compiled(compiled),
+            analysis::lower_bound(op, architecture, accuracy),
+            op.accuracy(accuracy),
+            self.threshold,
+        );
+        self.operations += 1;
+        if ratio.flagged {
+            self.flagged += 1;
+            debug!(
+                "Operation {} is far from its lower bound: {ratio:?}",
+                ratio.index
+            );
+        }
+        serde_json::to_writer(&mut self.out, &ratio).unwrap();
+        writeln!(self.out).unwrap();
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        info!(
+            "{} of {} operations use more than {} times the lower bound of joint measurements or T gates",
+            self.flagged, self.operations, self.threshold
+        );
+        self.out.flush()
+    }
+}
+
+/// Injects faults into the compiled output and logs them
+pub struct FaultInjection {
+    injector: FaultInjector<StdRng>,
+    log: Option<io::BufWriter<File>>,
+    faults: usize,
+}
+
+impl FaultInjection {
+    pub fn new(injector: FaultInjector<StdRng>, log: Option<&Path>) -> io::Result<Self> {
+        Ok(Self {
+            injector,
+            log: log
+                .map(|path| File::create(path).map(io::BufWriter::new))
+                .transpose()?,
+            faults: 0,
+        })
+    }
+
+    pub fn inject(&mut self, compiled: &mut [Operation]) {
+        for fault in self.injector.inject(compiled) {
+            self.faults += 1;
+            debug!("Injected fault {fault:?}");
+            if let Some(log) = self.log.as_mut() {
+                serde_json::to_writer(&mut *log, &fault).unwrap();
+                writeln!(log).unwrap();
+            }
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        info!("Injected {} faults into the compiled output", self.faults);
+        match self.log {
+            Some(mut log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the compiled outcomes of each measurement of the input program,
+/// and the outcomes that condition each injected gate
+pub struct ObservableLog {
+    tracker: ObservableTracker,
+    out: Option<io::BufWriter<File>>,
+    feed_forward: Option<io::BufWriter<File>>,
+    /// Number of conditioned gates
+    conditioned: usize,
+    /// The last compiled line before optimization
+    compiled: Vec<Operation>,
+    /// The outcomes of the measurement of the last compiled line and whether it is flipped
+    measured: Option<(Vec<usize>, bool)>,
+}
+
+impl ObservableLog {
+    pub fn new(observables: Option<&Path>, feed_forward: Option<&Path>) -> io::Result<Self> {
+        let create = |path: Option<&Path>| {
+            path.map(|path| File::create(path).map(io::BufWriter::new))
+                .transpose()
+        };
+        Ok(Self {
+            tracker: ObservableTracker::default(),
+            out: create(observables)?,
+            feed_forward: create(feed_forward)?,
+            conditioned: 0,
+            compiled: vec![],
+            measured: None,
+        })
+    }
+
+    /// Remember a compiled line until it is optimized
+    pub fn compiled(&mut self, compiled: &[Operation], measured: Option<(Vec<usize>, bool)>) {
+        self.compiled = compiled.to_vec();
+        self.measured = measured;
+    }
+
+    pub fn optimized(&mut self, optimized: &[Operation]) -> io::Result<()> {
+        let measured = self.measured.take();
+        let measured = measured
+            .as_ref()
+            .map(|(outcomes, flip)| (outcomes.as_slice(), *flip));
+        let observable = self.tracker.track(&self.compiled, optimized, measured);
+        if let (Some(out), Some(observable)) = (self.out.as_mut(), observable) {
+            serde_json::to_writer(&mut *out, &observable)?;
+            writeln!(out)?;
+        }
+        if let Some(out) = self.feed_forward.as_mut() {
+            for conditioned in self.tracker.conditioned() {
+                serde_json::to_writer(&mut *out, conditioned)?;
+                writeln!(out)?;
+            }
+            self.conditioned += self.tracker.conditioned().len();
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(mut out) = self.out {
+            info!(
+                "Wrote the compiled outcomes of {} measurements",
+                self.tracker.observables()
+            );
+            out.flush()?;
+        }
+        if let Some(mut out) = self.feed_forward {
+            info!(
+                "Wrote the outcomes that condition {} injected gates",
+                self.conditioned
+            );
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The operations that the output lines implement, with the instructions that would restore the cached bases after them
+pub type ExpectedOperations = Mutex<VecDeque<(Option<PbcOperation>, Vec<Operation>)>>;
+
+/// The optimization passes on the compiled chunks, see [`optimize`]
+pub struct Passes {
+    compressor: Option<optimize::BlockCompressor>,
+    verify_t: Option<usize>,
+    decompose_automorphisms: bool,
+    scheduler: Option<optimize::Scheduler>,
+    fault_injection: Option<FaultInjection>,
+    /// The data blocks after compiling the chunk that the passes work on,
+    /// since the compile stage may run ahead and grow the architecture
+    data_blocks: AtomicUsize,
+}
+
+impl Passes {
+    pub fn new(
+        cli: &Cli,
+        measurement_table: &dyn MeasurementTable,
+        architecture: &PathArchitecture,
+    ) -> io::Result<Self> {
+        let fault_injection = match cli.passes.inject_faults {
+            Some(rate) => Some(FaultInjection::new(
+                FaultInjector::new(
+                    rate,
+                    architecture.data_blocks(),
+                    StdRng::seed_from_u64(cli.passes.fault_seed),
+                ),
+                cli.passes.fault_log.as_deref(),
+            )?),
+            None => None,
+        };
+        Ok(Self {
+            compressor: cli
+                .passes
+                .compress_blocks
+                .then(|| optimize::BlockCompressor::new(measurement_table)),
+            verify_t: cli.passes.verify_t,
+            decompose_automorphisms: cli.passes.decompose_automorphisms,
+            scheduler: cli.passes.schedule.then(optimize::Scheduler::new),
+            fault_injection,
+            data_blocks: AtomicUsize::new(0),
+        })
+    }
+
+    /// Optimize the compiled chunks, each with the number of data blocks after compiling it
+    pub fn run<'a>(
+        &'a mut self,
+        compiled: impl Iterator<Item = (Vec<Operation>, usize)> + 'a,
+    ) -> impl Iterator<Item = Vec<Operation>> + 'a {
+        let Self {
+            compressor,
+            verify_t,
+            decompose_automorphisms,
+            scheduler,
+            fault_injection,
+            data_blocks,
+        } = self;
+        let data_blocks = &*data_blocks;
+        let optimized_auts = compiled.map(move |(chunk, blocks)| {
+            data_blocks.store(blocks, Ordering::Relaxed);
+            let chunk: Vec<_> = explain::time(Stage::TrivialAutomorphisms, || {
+                optimize::remove_trivial_automorphisms(chunk).collect()
+            });
+            match compressor.as_mut() {
+                Some(compressor) => {
+                    explain::time(Stage::CompressBlocks, || compressor.compress(chunk))
+                }
+                None => chunk,
+            }
+        });
+        // The repeated measurements are inserted after removing duplicate measurements, which would remove them
+        optimize::remove_duplicate_measurements_chunked(optimized_auts).map(move |mut chunk| {
+            if let Some(repetitions) = *verify_t {
+                chunk = optimize::verify_t_injections(chunk, repetitions).collect();
+            }
+            if *decompose_automorphisms {
+                chunk = optimize::decompose_automorphisms(chunk).collect();
+            }
+            if let Some(scheduler) = scheduler.as_mut() {
+                chunk = explain::time(Stage::Schedule, || scheduler.schedule(chunk));
+            }
+            if let Some(fault_injection) = fault_injection.as_mut() {
+                fault_injection
+                    .injector
+                    .grow(data_blocks.load(Ordering::Relaxed));
+                fault_injection.inject(&mut chunk);
+            }
+            chunk
+        })
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(compressor) = &self.compressor {
+            info!(
+                "Shortened {} runs of single-block instructions",
+                compressor.compressed()
+            );
+        }
+        match self.fault_injection {
+            Some(fault_injection) => fault_injection.finish(),
+            None => Ok(()),
+        }
+    }
+}
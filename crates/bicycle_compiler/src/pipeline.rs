@@ -0,0 +1,183 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run the stages of compilation on their own threads, connected by bounded channels.
+//!
+//! Each stage is a lazy iterator that is driven by the stage after it, and the last stage,
+//! which serializes the output, runs on the calling thread.
+//! A stage on a thread of its own runs ahead by at most the capacity of its channel and then blocks,
+//! such that a slow stage holds back the stages before it instead of letting their output pile up in memory.
+
+use std::{
+    sync::mpsc,
+    thread::{self, Scope},
+};
+
+use log::debug;
+
+/// The stages that can run on a thread of their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineStage {
+    /// Reading, parsing, and rewriting the input operations
+    Parse,
+    /// Compiling each operation to instructions
+    Compile,
+    /// The optimization passes over the compiled instructions
+    Optimize,
+}
+
+impl PipelineStage {
+    /// The stages in the order in which they get a thread, starting with the stages that take the most time
+    pub const BY_PRIORITY: [PipelineStage; 3] = [
+        PipelineStage::Compile,
+        PipelineStage::Optimize,
+        PipelineStage::Parse,
+    ];
+}
+
+/// A stream of items from one stage to the next
+pub type Stream<'scope, T> = Box<dyn Iterator<Item = T> + Send + 'scope>;
+
+/// Spawns the stages of compilation on the threads of a [`Scope`]
+#[derive(Debug, Clone, Copy)]
+pub struct CompilerPipeline<'scope, 'env> {
+    scope: &'scope Scope<'scope, 'env>,
+    threads: usize,
+    capacity: usize,
+}
+
+impl<'scope, 'env> CompilerPipeline<'scope, 'env> {
+    /// Number of items that a stage may run ahead of the next stage
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    /// A pipeline on `threads` threads, including the calling thread
+    pub fn new(scope: &'scope Scope<'scope, 'env>, threads: usize) -> Self {
+        assert!(threads > 0, "Need at least one thread to compile");
+        Self {
+            scope,
+            threads,
+            capacity: Self::DEFAULT_CAPACITY,
+        }
+    }
+
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Stages need room for at least one item");
+        Self { capacity, ..self }
+    }
+
+    /// Whether `stage` gets a thread of its own, see [`PipelineStage::BY_PRIORITY`]
+    pub fn threaded(&self, stage: PipelineStage) -> bool {
+        let rank = PipelineStage::BY_PRIORITY
+            .iter()
+            .position(|s| *s == stage)
+            .unwrap();
+        rank + 1 < self.threads
+    }
+
+    /// End `stage` at `items`, which then run on a thread of their own if the stage gets one,
+    /// or otherwise lazily on the thread of the next stage.
+    /// The thread stops when the next stage drops the returned stream.
+    pub fn stage<T>(
+        &self,
+        stage: PipelineStage,
+        items: impl Iterator<Item = T> + Send + 'scope,
+    ) -> Stream<'scope, T>
+    where
+        T: Send + 'scope,
+    {
+        if !self.threaded(stage) {
+            return Box::new(items);
+        }
+        debug!("Running the {stage:?} stage on its own thread");
+        let (tx, rx) = mpsc::sync_channel(self.capacity);
+        thread::Builder::new()
+            .name(format!("{stage:?}").to_lowercase())
+            .spawn_scoped(self.scope, move || {
+                for item in items {
+                    if tx.send(item).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Cannot spawn a thread for a compiler stage");
+        Box::new(rx.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn threads_by_priority() {
+        thread::scope(|scope| {
+            let threaded = |threads| {
+                let pipeline = CompilerPipeline::new(scope, threads);
+                [
+                    PipelineStage::Parse,
+                    PipelineStage::Compile,
+                    PipelineStage::Optimize,
+                ]
+                .map(|stage| pipeline.threaded(stage))
+            };
+            assert_eq!([false, false, false], threaded(1));
+            assert_eq!([false, true, false], threaded(2));
+            assert_eq!([false, true, true], threaded(3));
+            assert_eq!([true, true, true], threaded(4));
+            assert_eq!([true, true, true], threaded(8));
+        });
+    }
+
+    #[test]
+    fn stages_preserve_order() {
+        for threads in 1..=4 {
+            let squares: Vec<usize> = thread::scope(|scope| {
+                let pipeline = CompilerPipeline::new(scope, threads).with_capacity(2);
+                let items = pipeline.stage(PipelineStage::Parse, 0..1000);
+                let items = pipeline.stage(PipelineStage::Compile, items.map(|i| i * i));
+                pipeline
+                    .stage(PipelineStage::Optimize, items.filter(|i| i % 2 == 0))
+                    .collect()
+            });
+            let expected: Vec<usize> = (0..1000).map(|i| i * i).filter(|i| i % 2 == 0).collect();
+            assert_eq!(expected, squares);
+        }
+    }
+
+    #[test]
+    fn stages_are_bounded() {
+        let produced = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let pipeline = CompilerPipeline::new(scope, 2).with_capacity(4);
+            let mut items = pipeline.stage(
+                PipelineStage::Compile,
+                (0..1000).inspect(|_| {
+                    produced.fetch_add(1, Ordering::Relaxed);
+                }),
+            );
+            assert_eq!(Some(0), items.next());
+            thread::sleep(std::time::Duration::from_millis(50));
+            // The consumed item, the items in the channel, and the item that waits to be sent
+            assert!(produced.load(Ordering::Relaxed) <= 1 + 4 + 1);
+            // Dropping the stream stops the stage
+            drop(items);
+        });
+        assert!(produced.load(Ordering::Relaxed) < 1000);
+    }
+}
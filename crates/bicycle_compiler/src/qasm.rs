@@ -0,0 +1,984 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import OpenQASM 3 circuits as PBC programs.
+//!
+//! Every gate is written as Pauli rotations. Following Litinski's transformation,
+//! the Clifford rotations are not output but absorbed into a [`CliffordFrame`] that conjugates the later rotations and measurements,
+//! such that the program only consists of non-Clifford rotations and measurements.
+//! Rotations about odd multiples of the T angle are split into a T rotation and an absorbed Clifford rotation.
+//! Resets become Z measurements, since the X that flips a -1 outcome back is left to the Pauli frame and is not output.
+//!
+//! The standard gates of `stdgates.inc` up to two qubits are supported, as well as `ccx`, `ccz`,
+//! and the two-qubit rotations `rxx`, `ryy`, and `rzz` of Qiskit, with broadcasting over registers.
+//! Barriers, delays, and global phases are skipped.
+//! Gate definitions, gate modifiers, and classical control flow are not supported.
+
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_PI_2, FRAC_PI_4, PI, TAU},
+};
+
+use bicycle_common::Pauli;
+
+use crate::{
+    compile::CliffordFrame,
+    language::{AnglePrecision, PbcOperation},
+    small_angle::T_ANGLE,
+    stim::{self, StimError},
+};
+
+/// An OpenQASM 3 circuit that cannot be imported, at the line where the statement starts
+pub type QasmError = StimError;
+
+/// A rotation about a Pauli on the operands of a gate, by an angle in radians
+type Term = (Vec<(usize, Pauli)>, f64);
+
+/// The Pauli rotations of a gate in time order, where the rotations of one step commute
+fn gate_rotations(name: &str, params: &[f64]) -> Option<(usize, Vec<Vec<Term>>)> {
+    use Pauli::{X, Y, Z};
+    let single = |rotations: &[(Pauli, f64)]| {
+        let steps = rotations
+            .iter()
+            .map(|&(p, angle)| vec![(vec![(0, p)], angle)])
+            .collect();
+        Some((1, steps))
+    };
+    // Controlled Paulis and phase gates also rotate the control
+    let controlled_step = |p: Pauli, angle: f64, phase: bool| {
+        stim::controlled_rotation(0, 1, p, phase)
+            .into_iter()
+            .map(|(basis, weight)| (basis, f64::from(weight) * angle / 2.0))
+            .collect::<Vec<_>>()
+    };
+    let controlled =
+        |p: Pauli, angle: f64, phase: bool| Some((2, vec![controlled_step(p, angle, phase)]));
+    match (name, params) {
+        ("id", []) => Some((1, vec![])),
+        ("x", []) => single(&[(X, PI)]),
+        ("y", []) => single(&[(Y, PI)]),
+        ("z", []) => single(&[(Z, PI)]),
+        ("h", []) => single(&[(Z, FRAC_PI_2), (X, FRAC_PI_2), (Z, FRAC_PI_2)]),
+        ("s", []) => single(&[(Z, FRAC_PI_2)]),
+        ("sdg", []) => single(&[(Z, -FRAC_PI_2)]),
+        ("t", []) => single(&[(Z, FRAC_PI_4)]),
+        ("tdg", []) => single(&[(Z, -FRAC_PI_4)]),
+        ("sx", []) => single(&[(X, FRAC_PI_2)]),
+        ("sxdg", []) => single(&[(X, -FRAC_PI_2)]),
+        ("rx", [theta]) => single(&[(X, *theta)]),
+        ("ry", [theta]) => single(&[(Y, *theta)]),
+        ("rz" | "p" | "phase" | "u1", [lambda]) => single(&[(Z, *lambda)]),
+        ("u2", [phi, lambda]) => single(&[(Z, *lambda), (Y, FRAC_PI_2), (Z, *phi)]),
+        ("u" | "U" | "u3", [theta, phi, lambda]) => single(&[(Z, *lambda), (Y, *theta), (Z, *phi)]),
+        ("cx" | "CX" | "cnot", []) => controlled(X, PI, true),
+        ("cy", []) => controlled(Y, PI, true),
+        ("cz", []) => controlled(Z, PI, true),
+        ("cp" | "cphase" | "cu1", [lambda]) => controlled(Z, *lambda, true),
+        ("crx", [theta]) => controlled(X, *theta, false),
+        ("cry", [theta]) => controlled(Y, *theta, false),
+        ("crz", [theta]) => controlled(Z, *theta, false),
+        // H is Z conjugated by a rotation about Y by π/4
+        ("ch", []) => Some((
+            2,
+            vec![
+                vec![(vec![(1, Y)], -FRAC_PI_4)],
+                controlled_step(Z, PI, true),
+                vec![(vec![(1, Y)], FRAC_PI_4)],
+            ],
+        )),
+        // U is e^(i(φ+λ)/2) Rz(φ) Ry(θ) Rz(λ), whose phase becomes a phase gate on the control
+        ("cu", [theta, phi, lambda, gamma]) => Some((
+            2,
+            vec![
+                vec![(vec![(0, Z)], gamma + (phi + lambda) / 2.0)],
+                controlled_step(Z, *lambda, false),
+                controlled_step(Y, *theta, false),
+                controlled_step(Z, *phi, false),
+            ],
+        )),
+        ("rxx", [theta]) => Some((2, vec![vec![(vec![(0, X), (1, X)], *theta)]])),
+        ("ryy", [theta]) => Some((2, vec![vec![(vec![(0, Y), (1, Y)], *theta)]])),
+        ("rzz", [theta]) => Some((2, vec![vec![(vec![(0, Z), (1, Z)], *theta)]])),
+        ("swap", []) => Some((
+            2,
+            vec![
+                stim::swap(0, 1)
+                    .into_iter()
+                    .map(|(basis, weight)| (basis, f64::from(weight) * FRAC_PI_2))
+                    .collect(),
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/// The number of qubits that a supported gate acts on
+fn arity(name: &str, params: &[f64]) -> Result<usize, String> {
+    match (name, params) {
+        ("ccx" | "ccz", []) => Ok(3),
+        _ => gate_rotations(name, params)
+            .map(|(arity, _)| arity)
+            .ok_or_else(|| format!("Unsupported gate {name} with {} parameters", params.len())),
+    }
+}
+
+/// Evaluate an angle expression of numbers, `pi`, `tau`, `+`, `-`, `*`, `/`, and parentheses
+fn evaluate(expression: &str) -> Result<f64, String> {
+    let tokens = tokenize(expression)?;
+    let mut position = 0;
+    let value = parse_sum(&tokens, &mut position)?;
+    match tokens.get(position) {
+        None if !value.is_finite() => Err(format!("The angle {expression} is not finite")),
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected {token:?} in {expression}")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Operator(char),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Operator(c));
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                // Exponents may have a sign, e.g., 1e-3
+                let exponent_sign = (c == '-' || c == '+') && number.ends_with(['e', 'E']);
+                if !(c.is_ascii_alphanumeric() || c == '.' || c == '_' || exponent_sign) {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            let value = number
+                .replace('_', "")
+                .parse()
+                .map_err(|_| format!("Invalid number {number}"))?;
+            tokens.push(Token::Number(value));
+        } else {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            let value = match name.as_str() {
+                "pi" | "π" => PI,
+                "tau" | "τ" => TAU,
+                "euler" => std::f64::consts::E,
+                "" => return Err(format!("Unexpected {c:?} in {expression}")),
+                _ => return Err(format!("Unknown constant {name} in {expression}")),
+            };
+            tokens.push(Token::Number(value));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_sum(tokens: &[Token], position: &mut usize) -> Result<f64, String> {
+    let mut value = parse_product(tokens, position)?;
+    while let Some(Token::Operator(op @ ('+' | '-'))) = tokens.get(*position) {
+        *position += 1;
+        let rhs = parse_product(tokens, position)?;
+        value = if *op == '+' { value + rhs } else { value - rhs };
+    }
+    Ok(value)
+}
+
+fn parse_product(tokens: &[Token], position: &mut usize) -> Result<f64, String> {
+    let mut value = parse_factor(tokens, position)?;
+    while let Some(Token::Operator(op @ ('*' | '/'))) = tokens.get(*position) {
+        *position += 1;
+        let rhs = parse_factor(tokens, position)?;
+        value = if *op == '*' { value * rhs } else { value / rhs };
+    }
+    Ok(value)
+}
+
+fn parse_factor(tokens: &[Token], position: &mut usize) -> Result<f64, String> {
+    let token = tokens.get(*position).cloned();
+    *position += 1;
+    match token {
+        Some(Token::Number(value)) => Ok(value),
+        Some(Token::Operator('-')) => Ok(-parse_factor(tokens, position)?),
+        Some(Token::Operator('+')) => parse_factor(tokens, position),
+        Some(Token::Operator('(')) => {
+            let value = parse_sum(tokens, position)?;
+            match tokens.get(*position) {
+                Some(Token::Operator(')')) => {
+                    *position += 1;
+                    Ok(value)
+                }
+                _ => Err("Unclosed parenthesis".to_string()),
+            }
+        }
+        Some(token) => Err(format!("Unexpected {token:?}")),
+        None => Err("Incomplete expression".to_string()),
+    }
+}
+
+/// The statements of a circuit without comments, with the line that each starts on
+fn statements(circuit: &str) -> Vec<(usize, String)> {
+    let mut statements = vec![];
+    let mut statement = String::new();
+    let mut start = None;
+    let mut line = 1;
+    let mut chars = circuit.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+                statement.push(' ');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                statement.push(' ');
+            }
+            ';' => {
+                statements.push((start.unwrap_or(line), statement.trim().to_string()));
+                statement.clear();
+                start = None;
+            }
+            c => {
+                if !c.is_whitespace() && start.is_none() {
+                    start = Some(line);
+                }
+                if c == '\n' {
+                    line += 1;
+                }
+                statement.push(c);
+            }
+        }
+    }
+    if !statement.trim().is_empty() {
+        statements.push((start.unwrap_or(line), statement.trim().to_string()));
+    }
+    statements
+}
+
+/// Builds a PBC program gate by gate, where the Clifford rotations are absorbed into a [`CliffordFrame`].
+///
+/// This is the backend of [`parse`], and can be driven by other front ends that produce
+/// the standard gates by name, e.g., the Qiskit bindings of `pyftir`.
+#[derive(Debug, Clone, Default)]
+pub struct PbcBuilder {
+    qubits: usize,
+    frame: CliffordFrame,
+    ops: Vec<PbcOperation>,
+}
+
+impl PbcBuilder {
+    pub fn new(qubits: usize) -> Self {
+        Self {
+            qubits,
+            ..Default::default()
+        }
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    /// Add qubits after the previous ones, returning the index of the first new qubit
+    pub fn add_qubits(&mut self, qubits: usize) -> usize {
+        self.qubits += qubits;
+        self.qubits - qubits
+    }
+
+    /// Apply a gate of `stdgates.inc`, `ccx`, or `ccz` by name on distinct qubits
+    pub fn gate(&mut self, name: &str, qubits: &[usize], params: &[f64]) -> Result<(), String> {
+        let arity = arity(name, params)?;
+        if qubits.len() != arity {
+            return Err(format!(
+                "The gate {name} acts on {arity} qubits, not {}",
+                qubits.len()
+            ));
+        }
+        if let Some(qubit) = qubits.iter().find(|&&q| q >= self.qubits) {
+            return Err(format!(
+                "Qubit {qubit} is out of range of the {} qubits",
+                self.qubits
+            ));
+        }
+        let mut sorted = qubits.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        if sorted.len() != qubits.len() {
+            return Err("The operands of a gate should be distinct qubits".to_string());
+        }
+
+        if matches!(name, "ccx" | "ccz") {
+            let target = qubits[2];
+            let h = |builder: &mut Self| {
+                if name == "ccx" {
+                    builder.apply_steps(&[target], &gate_rotations("h", &[]).unwrap().1);
+                }
+            };
+            h(self);
+            let op = PbcOperation::Ccz {
+                basis: self.basis(&qubits.iter().map(|&q| (q, Pauli::Z)).collect::<Vec<_>>()),
+            };
+            self.ops.push(self.frame.conjugate_op(op));
+            h(self);
+            return Ok(());
+        }
+        let (_, steps) = gate_rotations(name, params).unwrap();
+        self.apply_steps(qubits, &steps);
+        Ok(())
+    }
+
+    /// Output a Z measurement of a qubit, which also implements a reset
+    pub fn measure(&mut self, qubit: usize) {
+        let op = PbcOperation::Measurement {
+            basis: self.basis(&[(qubit, Pauli::Z)]),
+            flip_result: false,
+        };
+        self.ops.push(self.frame.conjugate_op(op));
+    }
+
+    /// The operations so far, which all act on every qubit
+    pub fn finish(self) -> Vec<PbcOperation> {
+        let qubits = self.qubits;
+        self.ops
+            .into_iter()
+            .map(|op| {
+                op.map_bases(|mut basis| {
+                    basis.resize(qubits, Pauli::I);
+                    basis
+                })
+            })
+            .collect()
+    }
+
+    /// A dense basis of the given Paulis on the qubits added so far
+    fn basis(&self, paulis: &[(usize, Pauli)]) -> Vec<Pauli> {
+        let mut basis = vec![Pauli::I; self.qubits];
+        for &(qubit, p) in paulis {
+            basis[qubit] = p;
+        }
+        basis
+    }
+
+    /// Absorb the Clifford part of commuting rotations and output the rest
+    fn rotate(&mut self, terms: Vec<(Vec<Pauli>, f64)>) {
+        let mut rotations = vec![];
+        for (basis, angle) in terms {
+            let angle = if angle.abs() > PI {
+                (angle + PI).rem_euclid(TAU) - PI
+            } else {
+                angle
+            };
+            // Angles that are close to a multiple of the T angle are rounded to it
+            let eighths = angle / FRAC_PI_4;
+            if (eighths - eighths.round()).abs() > 1e-9 {
+                rotations.push((basis, AnglePrecision::from_num(angle)));
+                continue;
+            }
+            let eighths = eighths.round() as i64;
+            // An odd multiple of the T angle is a rotation by ±T and quarter turns
+            let t: i64 = match eighths.rem_euclid(4) {
+                1 => 1,
+                3 => -1,
+                _ => 0,
+            };
+            match t {
+                1 => rotations.push((basis.clone(), T_ANGLE)),
+                -1 => rotations.push((basis.clone(), -T_ANGLE)),
+                _ => {}
+            }
+            let quarter_turns = ((eighths - t) / 2).rem_euclid(4) as u8;
+            self.frame.absorb(&basis, quarter_turns);
+        }
+        let op = match rotations.len() {
+            0 => return,
+            1 => {
+                let (basis, angle) = rotations.pop().unwrap();
+                PbcOperation::Rotation {
+                    basis,
+                    angle,
+                    accuracy: None,
+                }
+            }
+            _ => PbcOperation::MultiRotation { terms: rotations },
+        };
+        self.ops.push(self.frame.conjugate_op(op));
+    }
+
+    /// Apply the rotations of a gate on the given qubits
+    fn apply_steps(&mut self, qubits: &[usize], steps: &[Vec<Term>]) {
+        for step in steps {
+            let terms = step
+                .iter()
+                .map(|(paulis, angle)| {
+                    let paulis: Vec<_> = paulis.iter().map(|&(i, p)| (qubits[i], p)).collect();
+                    (self.basis(&paulis), *angle)
+                })
+                .collect();
+            self.rotate(terms);
+        }
+    }
+}
+
+/// The qubit registers of a circuit and the operations on them
+#[derive(Default)]
+struct Circuit {
+    /// The first qubit and the size of each register
+    registers: HashMap<String, (usize, usize)>,
+    builder: PbcBuilder,
+}
+
+impl Circuit {
+    /// Declare a register of `size` qubits after the previous ones
+    fn declare(&mut self, name: &str, size: usize) -> Result<(), String> {
+        if self.registers.contains_key(name) {
+            return Err(format!("The register {name} is declared twice"));
+        }
+        let first = self.builder.add_qubits(size);
+        self.registers.insert(name.to_string(), (first, size));
+        Ok(())
+    }
+
+    /// The qubits of an operand, e.g., `q[2]` or the whole register `q`
+    fn operand(&self, operand: &str) -> Result<Vec<usize>, String> {
+        let operand = operand.trim();
+        let (name, index) = match operand.split_once('[') {
+            Some((name, index)) => {
+                let index = index
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("Invalid operand {operand}"))?;
+                (name.trim(), Some(index.trim()))
+            }
+            None => (operand, None),
+        };
+        let &(first, size) = self
+            .registers
+            .get(name)
+            .ok_or_else(|| format!("Unknown qubit register {name}"))?;
+        match index {
+            None => Ok((first..first + size).collect()),
+            Some(index) => {
+                let index: usize = index
+                    .parse()
+                    .map_err(|_| format!("Unsupported index {index} of {name}"))?;
+                if index >= size {
+                    return Err(format!(
+                        "Qubit {index} is out of range of {name}, which has {size} qubits"
+                    ));
+                }
+                Ok(vec![first + index])
+            }
+        }
+    }
+
+    /// The qubits of each application of a gate, broadcasting over the registers
+    fn broadcast(&self, operands: &[&str], arity: usize) -> Result<Vec<Vec<usize>>, String> {
+        if operands.len() != arity {
+            return Err(format!(
+                "The gate acts on {arity} qubits, not {}",
+                operands.len()
+            ));
+        }
+        let qubits = operands
+            .iter()
+            .map(|operand| self.operand(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        let width = qubits.iter().map(Vec::len).max().unwrap_or(1);
+        if qubits.iter().any(|q| q.len() != 1 && q.len() != width) {
+            return Err("The registers of a gate should have the same size".to_string());
+        }
+        Ok((0..width)
+            .map(|i| qubits.iter().map(|q| q[i.min(q.len() - 1)]).collect())
+            .collect())
+    }
+
+    fn apply(&mut self, statement: &str) -> Result<(), String> {
+        let (keyword, rest) = statement
+            .split_once(|c: char| c.is_whitespace() || c == '[' || c == '(')
+            .map(|(keyword, _)| (keyword, statement[keyword.len()..].trim()))
+            .unwrap_or((statement, ""));
+        // Checked first, since a condition such as `if (c == 1) x q[1];` reads like an assignment or a gate
+        if matches!(
+            keyword,
+            "if" | "else"
+                | "for"
+                | "while"
+                | "switch"
+                | "case"
+                | "default"
+                | "break"
+                | "continue"
+                | "end"
+                | "box"
+        ) {
+            return Err(format!("Control flow is not supported: {statement}"));
+        }
+        if statement.contains('@') {
+            return Err(format!("Gate modifiers are not supported: {statement}"));
+        }
+        if statement.contains('{') {
+            return Err(format!(
+                "Gate definitions and control flow are not supported: {keyword}"
+            ));
+        }
+        // `c = measure q`, possibly declaring `c`
+        if let Some((_, rhs)) = statement.split_once('=') {
+            return match rhs.trim().strip_prefix("measure") {
+                Some(operand) => {
+                    for qubit in self.operand(operand)? {
+                        self.builder.measure(qubit);
+                    }
+                    Ok(())
+                }
+                None => Err(format!(
+                    "Classical assignments are not supported: {statement}"
+                )),
+            };
+        }
+        match keyword {
+            "OPENQASM" | "include" | "bit" | "creg" | "barrier" | "delay" | "gphase" => Ok(()),
+            "qubit" => {
+                let (size, name) = match rest.strip_prefix('[') {
+                    Some(rest) => {
+                        let (size, name) = rest
+                            .split_once(']')
+                            .ok_or_else(|| format!("Invalid declaration {statement}"))?;
+                        let size = size
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("Invalid register size {size}"))?;
+                        (size, name.trim())
+                    }
+                    None => (1, rest),
+                };
+                self.declare(name, size)
+            }
+            "qreg" => {
+                let (name, size) = rest
+                    .strip_suffix(']')
+                    .and_then(|rest| rest.split_once('['))
+                    .ok_or_else(|| format!("Invalid declaration {statement}"))?;
+                let size = size
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid register size {size}"))?;
+                self.declare(name.trim(), size)
+            }
+            "measure" => {
+                // `measure q -> c` or `measure q`
+                let operand = rest.split("->").next().unwrap();
+                for qubit in self.operand(operand)? {
+                    self.builder.measure(qubit);
+                }
+                Ok(())
+            }
+            "reset" => {
+                for qubit in self.operand(rest)? {
+                    self.builder.measure(qubit);
+                }
+                Ok(())
+            }
+            _ => self.gate(statement),
+        }
+    }
+
+    /// Apply a gate statement, e.g., `rz(pi/4) q[0]` or `cx q[0], q[1]`
+    fn gate(&mut self, statement: &str) -> Result<(), String> {
+        let (name, params, operands) = match statement.split_once('(') {
+            Some((name, rest)) if !name.contains(char::is_whitespace) => {
+                let (params, operands) = rest
+                    .rsplit_once(')')
+                    .ok_or_else(|| format!("Unclosed parenthesis in {statement}"))?;
+                let params = params
+                    .split(',')
+                    .map(evaluate)
+                    .collect::<Result<Vec<_>, _>>()?;
+                (name.trim(), params, operands.trim())
+            }
+            _ => {
+                let (name, operands) = statement
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((statement, ""));
+                (name, vec![], operands.trim())
+            }
+        };
+        let operands: Vec<&str> = operands.split(',').collect();
+        let arity = arity(name, &params)?;
+        for qubits in self.broadcast(&operands, arity)? {
+            self.builder.gate(name, &qubits, &params)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse an OpenQASM 3 circuit into PBC operations that all act on the qubits that the circuit declares
+pub fn parse(circuit: &str) -> Result<Vec<PbcOperation>, QasmError> {
+    let mut parsed = Circuit::default();
+    for (line, statement) in statements(circuit) {
+        if statement.is_empty() {
+            continue;
+        }
+        parsed
+            .apply(&statement)
+            .map_err(|message| QasmError { line, message })?;
+    }
+    Ok(parsed.builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    use Pauli::{I, X, Z};
+
+    fn measurement(basis: Vec<Pauli>, flip_result: bool) -> PbcOperation {
+        PbcOperation::Measurement { basis, flip_result }
+    }
+
+    #[test]
+    fn cliffords_are_absorbed() -> Result<(), QasmError> {
+        let circuit = r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[2] q;
+            bit[2] c;
+            h q[0];
+            cx q[0], q[1]; // A Bell pair
+            c = measure q;
+        "#;
+        let ops = parse(circuit)?;
+        // Measuring Z after the Clifford C is measuring C†ZC before it
+        assert_eq!(
+            vec![
+                measurement(vec![X, I], false),
+                measurement(vec![X, Z], false)
+            ],
+            ops
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_gates_are_conjugated() -> Result<(), QasmError> {
+        let circuit = "
+            qubit[2] q;
+            /* S then T is a rotation by 3π/4,
+               which is a T rotation and an absorbed S */
+            s q[1];
+            rz(3*pi/4) q[1];
+            x q[0];
+            cx q[1], q[0];
+            tdg q[0];
+            measure q[0] -> c[0];
+            measure q[1] -> c[1];
+        ";
+        let ops = parse(circuit)?;
+        assert_eq!(
+            vec![
+                PbcOperation::Rotation {
+                    basis: vec![I, Z],
+                    angle: -T_ANGLE,
+                    accuracy: None,
+                },
+                // X on q[0] negates Z on q[0], and CX maps it to Z on both qubits
+                PbcOperation::Rotation {
+                    basis: vec![Z, Z],
+                    angle: T_ANGLE,
+                    accuracy: None,
+                },
+                measurement(vec![Z, Z], true),
+                measurement(vec![I, Z], false),
+            ],
+            ops
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn small_angles_and_registers() -> Result<(), QasmError> {
+        let circuit = "
+            OPENQASM 2.0;
+            qreg a[1];
+            qreg b[2];
+            rz(0.1) b;
+            sx a[0];
+            ry(-1e-3) a[0];
+            reset b[1];
+        ";
+        let ops = parse(circuit)?;
+        let angle = |angle: f64| AnglePrecision::from_num(angle);
+        assert_eq!(
+            vec![
+                PbcOperation::Rotation {
+                    basis: vec![I, Z, I],
+                    angle: angle(0.1),
+                    accuracy: None,
+                },
+                PbcOperation::Rotation {
+                    basis: vec![I, I, Z],
+                    angle: angle(0.1),
+                    accuracy: None,
+                },
+                // SQRT_X maps Y to Z up to a sign
+                PbcOperation::Rotation {
+                    basis: vec![Z, I, I],
+                    angle: angle(1e-3),
+                    accuracy: None,
+                },
+                measurement(vec![I, I, Z], false),
+            ],
+            ops
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn toffoli() -> Result<(), QasmError> {
+        let ops = parse("qubit[3] q;\nccz q[0], q[1], q[2];\nccx q[2], q[1], q[0];")?;
+        assert_eq!(
+            PbcOperation::Ccz {
+                basis: vec![Z, Z, Z]
+            },
+            ops[0]
+        );
+        // The target of CCX is rotated by the absorbed Hadamard gates
+        let PbcOperation::MultiRotation { terms } = &ops[1] else {
+            panic!("A CCX should be conjugated to rotations");
+        };
+        assert_eq!(7, terms.len());
+        assert!(
+            terms
+                .iter()
+                .all(|(basis, angle)| basis[0] != Z && angle.abs() == T_ANGLE)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn builder_matches_parse() -> Result<(), Box<dyn Error>> {
+        let mut builder = PbcBuilder::new(2);
+        builder.gate("h", &[0], &[])?;
+        builder.gate("cx", &[0, 1], &[])?;
+        builder.gate("rz", &[1], &[FRAC_PI_4])?;
+        let first = builder.add_qubits(1);
+        builder.gate("ccz", &[0, 1, first], &[])?;
+        builder.measure(first);
+        assert!(builder.gate("cx", &[0, 0], &[]).is_err());
+        assert!(builder.gate("cx", &[0], &[]).is_err());
+        assert!(builder.gate("h", &[3], &[]).is_err());
+
+        let circuit = "qubit[2] q; h q[0]; cx q[0], q[1]; rz(pi/4) q[1]; qubit r; ccz q[0], q[1], r; measure r;";
+        assert_eq!(parse(circuit)?, builder.finish());
+        Ok(())
+    }
+
+    #[test]
+    fn two_qubit_rotations() -> Result<(), Box<dyn Error>> {
+        use Pauli::{I, X, Y, Z};
+        let ops =
+            parse("qubit[3] q; rzz(0.3) q[0], q[2]; rxx(pi/2) q[0], q[1]; rzz(0.3) q[0], q[2];")?;
+        // The quarter turn about XX is absorbed and conjugates the second ZZ rotation, which anticommutes with it
+        assert_eq!(
+            vec![
+                PbcOperation::rotation(vec![Z, I, Z], 0.3),
+                PbcOperation::rotation(vec![Y, X, Z], 0.3),
+            ],
+            ops
+        );
+        let ops = parse("qubit[2] q; rxx(0.3) q[0], q[1];")?;
+        assert_eq!(vec![PbcOperation::rotation(vec![X, X], 0.3)], ops);
+        Ok(())
+    }
+
+    type Complex = (f64, f64);
+
+    fn mul(a: Complex, b: Complex) -> Complex {
+        (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+    }
+
+    /// The two-qubit unitary of a gate, where qubit 0 is the least significant bit of the index
+    fn unitary(name: &str, params: &[f64]) -> [[Complex; 4]; 4] {
+        let (2, steps) = gate_rotations(name, params).unwrap() else {
+            panic!("{name} should act on two qubits");
+        };
+        let mut columns = [[(0.0, 0.0); 4]; 4];
+        for (column, state) in columns.iter_mut().enumerate() {
+            state[column] = (1.0, 0.0);
+            for (basis, angle) in steps.iter().flatten() {
+                // exp(-iθ/2 P) is cos(θ/2) - i sin(θ/2) P
+                let mut rotated = state.map(|a| mul(a, ((angle / 2.0).cos(), 0.0)));
+                for (index, &amplitude) in state.iter().enumerate() {
+                    let mut target = index;
+                    let mut phase = (0.0, -(angle / 2.0).sin());
+                    for &(qubit, p) in basis {
+                        let bit = index >> qubit & 1 == 1;
+                        if p != Z {
+                            target ^= 1 << qubit;
+                        }
+                        match (p, bit) {
+                            (Pauli::Y, false) => phase = mul(phase, (0.0, 1.0)),
+                            (Pauli::Y, true) => phase = mul(phase, (0.0, -1.0)),
+                            (Z, true) => phase = mul(phase, (-1.0, 0.0)),
+                            _ => {}
+                        }
+                    }
+                    let term = mul(phase, amplitude);
+                    rotated[target] = (rotated[target].0 + term.0, rotated[target].1 + term.1);
+                }
+                *state = rotated;
+            }
+        }
+        columns
+    }
+
+    /// Check that a gate applies `target` to qubit 1 if qubit 0 is set, up to a global phase
+    fn assert_controlled(name: &str, params: &[f64], target: [[Complex; 2]; 2]) {
+        let columns = unitary(name, params);
+        let phase = columns[0][0];
+        for (column, state) in columns.iter().enumerate() {
+            for (row, &amplitude) in state.iter().enumerate() {
+                let expected = match (column & 1, row & 1) {
+                    (1, 1) => target[row >> 1][column >> 1],
+                    (0, 0) if row == column => (1.0, 0.0),
+                    _ => (0.0, 0.0),
+                };
+                let expected = mul(phase, expected);
+                assert!(
+                    (amplitude.0 - expected.0).abs() < 1e-12
+                        && (amplitude.1 - expected.1).abs() < 1e-12,
+                    "{name}{params:?} at ({row}, {column}): {amplitude:?} != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn controlled_gates() {
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        assert_controlled("ch", &[], [[(h, 0.0), (h, 0.0)], [(h, 0.0), (-h, 0.0)]]);
+        assert_controlled(
+            "cx",
+            &[],
+            [[(0.0, 0.0), (1.0, 0.0)], [(1.0, 0.0), (0.0, 0.0)]],
+        );
+        let cis = |angle: f64| (angle.cos(), angle.sin());
+        let (theta, phi, lambda, gamma): (f64, f64, f64, f64) = (0.3, -1.2, 2.5, 0.7);
+        let (cos, sin) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+        let scale = |a: Complex, factor: f64| (a.0 * factor, a.1 * factor);
+        assert_controlled(
+            "cu",
+            &[theta, phi, lambda, gamma],
+            [
+                [scale(cis(gamma), cos), scale(cis(gamma + lambda), -sin)],
+                [
+                    scale(cis(gamma + phi), sin),
+                    scale(cis(gamma + phi + lambda), cos),
+                ],
+            ],
+        );
+        assert_eq!(Ok(2), arity("cu", &[theta, phi, lambda, gamma]));
+    }
+
+    #[test]
+    fn angle_expressions() {
+        assert_eq!(Ok(PI / 4.0), evaluate("pi/4"));
+        assert_eq!(Ok(-3.0 * PI / 8.0), evaluate("-3*pi/8"));
+        assert_eq!(Ok(0.5), evaluate("(1 - 0.5) * (2 / 2)"));
+        assert_eq!(Ok(TAU / 1e3), evaluate("τ / 1e3"));
+        assert!(evaluate("pi/").is_err());
+        assert!(evaluate("theta").is_err());
+        assert!(evaluate("pi/0").is_err());
+        assert!(evaluate("1e400").is_err());
+    }
+
+    #[test]
+    fn large_angles() -> Result<(), QasmError> {
+        // Angles are reduced to one turn before they are split into T and Clifford parts
+        let ops = parse("qubit[1] q; rz(1e30) q[0];")?;
+        let [PbcOperation::Rotation { angle, .. }] = &ops[..] else {
+            panic!("Expected a single rotation instead of {ops:?}");
+        };
+        assert!(angle.abs() <= AnglePrecision::from_num(PI));
+        assert_eq!(
+            parse("qubit[1] q; rz(pi/4) q[0];")?,
+            parse("qubit[1] q; rz(pi/4 + 1000*tau) q[0];")?
+        );
+        let error = parse("qubit[1] q; rz(pi/0) q[0];").unwrap_err();
+        assert_eq!(1, error.line);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_circuits() {
+        let line = |circuit| parse(circuit).unwrap_err().line;
+        assert_eq!(2, line("qubit[2] q;\nfoo q[0];"));
+        assert_eq!(3, line("qubit[2] q;\n// A comment\nh q[2];"));
+        assert_eq!(1, line("qubit q; ctrl @ x q, q;"));
+        assert_eq!(3, line("qubit[2] q;\n\ngate g a { h a; }"));
+        assert_eq!(1, line("qubit[2] q; cx q[0], q[0];"));
+        assert_eq!(1, line("h r;"));
+    }
+
+    #[test]
+    fn rejects_control_flow() {
+        let error = parse("qubit[2] q;\nbit c;\nif (c) x q[1];").unwrap_err();
+        assert_eq!(3, error.line);
+        assert!(
+            error.message.contains("Control flow is not supported"),
+            "{}",
+            error.message
+        );
+        let error = parse("qubit[2] q;\nbit c;\nif (c == 1) x q[1];").unwrap_err();
+        assert!(
+            error.message.contains("Control flow is not supported"),
+            "{}",
+            error.message
+        );
+        let error = parse("qubit[2] q;\nbit c;\nwhile (c) {\nx q[1];\n}").unwrap_err();
+        assert_eq!(3, error.line);
+        assert!(
+            error.message.contains("Control flow is not supported"),
+            "{}",
+            error.message
+        );
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical resource counts in the format of the Azure Quantum Resource Estimator (QRE),
+//! to compare estimates for the bicycle architecture with those of other tools.
+
+use bicycle_common::{BicycleISA, Pauli};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    language::{AnglePrecision, PbcOperation},
+    operation::Operation,
+    small_angle::T_ANGLE,
+};
+
+/// The logical counts that the QRE accepts as a known estimate of a program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogicalCounts {
+    pub num_qubits: u64,
+    pub t_count: u64,
+    /// Rotations that are not a multiple of the T angle
+    pub rotation_count: u64,
+    /// Number of layers of rotations on disjoint qubits
+    pub rotation_depth: u64,
+    pub ccz_count: u64,
+    pub ccix_count: u64,
+    pub measurement_count: u64,
+}
+
+/// Where the logical counts are taken from
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CountSource {
+    /// Count the operations of the PBC program
+    #[default]
+    Input,
+    /// Count the instructions that the program compiles to
+    Compiled,
+}
+
+/// Counts the operations of a PBC program
+#[derive(Debug, Clone, Default)]
+pub struct ProgramCounter {
+    counts: LogicalCounts,
+    /// The rotation layer of the last rotation on each qubit
+    layers: Vec<u64>,
+}
+
+impl ProgramCounter {
+    pub fn add(&mut self, op: &PbcOperation) {
+        self.counts.num_qubits = self.counts.num_qubits.max(op.basis().len() as u64);
+        match op {
+            PbcOperation::Measurement { .. } => self.counts.measurement_count += 1,
+            PbcOperation::Rotation { basis, angle, .. } => self.add_rotation(basis, *angle),
+            PbcOperation::MultiRotation { terms } => terms
+                .iter()
+                .for_each(|(basis, angle)| self.add_rotation(basis, *angle)),
+            PbcOperation::RotateMeasure { basis, angle, .. } => {
+                self.add_rotation(basis, *angle);
+                self.counts.measurement_count += 1;
+            }
+            PbcOperation::Ccz { .. } => self.counts.ccz_count += 1,
+            // Cliffords are not counted, like rotations about multiples of the S angle
+            PbcOperation::Clifford { .. }
+            | PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. } => {}
+        }
+    }
+
+    /// Count a rotation as a T gate, a Clifford, or an arbitrary rotation
+    fn add_rotation(&mut self, basis: &[Pauli], angle: AnglePrecision) {
+        let t_multiple = angle / T_ANGLE;
+        if t_multiple.frac() == AnglePrecision::ZERO {
+            if t_multiple.int().to_num::<i64>() % 2 != 0 {
+                self.counts.t_count += 1;
+            }
+            return;
+        }
+        self.counts.rotation_count += 1;
+
+        if self.layers.len() < basis.len() {
+            self.layers.resize(basis.len(), 0);
+        }
+        let support = || {
+            basis
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| **p != Pauli::I)
+                .map(|(qubit, _)| qubit)
+        };
+        let layer = 1 + support().map(|qubit| self.layers[qubit]).max().unwrap_or(0);
+        support().for_each(|qubit| self.layers[qubit] = layer);
+        self.counts.rotation_depth = self.counts.rotation_depth.max(layer);
+    }
+
+    pub fn counts(&self) -> LogicalCounts {
+        self.counts
+    }
+}
+
+/// Counts the compiled instructions of a program on `num_qubits` logical qubits.
+///
+/// All rotations are synthesized into T gates and all operations into measurements,
+/// so the count of rotations and CCZ gates is zero.
+#[derive(Debug, Clone)]
+pub struct CompiledCounter {
+    counts: LogicalCounts,
+}
+
+impl CompiledCounter {
+    pub fn new(num_qubits: usize) -> Self {
+        Self {
+            counts: LogicalCounts {
+                num_qubits: num_qubits as u64,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Count the operations of a chunk by their first instruction
+    pub fn add(&mut self, chunk: &[Operation]) {
+        for (_, isa) in chunk.iter().filter_map(|op| op.first()) {
+            match isa {
+                BicycleISA::TGate(_) | BicycleISA::TGatePair(_) => {
+                    self.counts.t_count += isa.t_count()
+                }
+                BicycleISA::Measure(_) | BicycleISA::JointMeasure(_) => {
+                    self.counts.measurement_count += 1
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn counts(&self) -> LogicalCounts {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{TGateData, TwoBases};
+
+    use super::*;
+
+    use Pauli::{I, X, Z};
+
+    #[test]
+    fn count_program() {
+        let mut counter = ProgramCounter::default();
+        let ops = [
+            PbcOperation::rotation(vec![X, I, I], 0.1),
+            PbcOperation::rotation(vec![I, Z, I], 0.2),
+            PbcOperation::rotation(vec![X, Z, I], 0.3),
+            PbcOperation::Rotation {
+                basis: vec![I, I, Z],
+                angle: -T_ANGLE,
+                accuracy: None,
+            },
+            // Clifford
+            PbcOperation::Rotation {
+                basis: vec![I, I, Z],
+                angle: 2 * T_ANGLE,
+                accuracy: None,
+            },
+            PbcOperation::ccz([0, 1, 2], 3),
+            PbcOperation::Measurement {
+                basis: vec![Z, I, I],
+                flip_result: false,
+            },
+        ];
+        ops.iter().for_each(|op| counter.add(op));
+
+        let expected = LogicalCounts {
+            num_qubits: 3,
+            t_count: 1,
+            rotation_count: 3,
+            rotation_depth: 2,
+            ccz_count: 1,
+            ccix_count: 0,
+            measurement_count: 1,
+        };
+        assert_eq!(expected, counter.counts());
+    }
+
+    #[test]
+    fn count_compiled() {
+        let z = TwoBases::new(Z, I).unwrap();
+        let t_gate = BicycleISA::TGate(TGateData::new(X, false, false).unwrap());
+        let chunk = vec![
+            vec![(0, BicycleISA::Measure(z))],
+            vec![
+                (0, BicycleISA::JointMeasure(z)),
+                (1, BicycleISA::JointMeasure(z)),
+            ],
+            vec![(1, t_gate)],
+        ];
+        let mut counter = CompiledCounter::new(22);
+        counter.add(&chunk);
+        counter.add(&chunk);
+
+        assert_eq!(22, counter.counts().num_qubits);
+        assert_eq!(2, counter.counts().t_count);
+        assert_eq!(4, counter.counts().measurement_count);
+    }
+
+    #[test]
+    fn qre_field_names() {
+        let json = serde_json::to_value(LogicalCounts::default()).unwrap();
+        let mut keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            vec![
+                "ccixCount",
+                "cczCount",
+                "measurementCount",
+                "numQubits",
+                "rotationCount",
+                "rotationDepth",
+                "tCount"
+            ],
+            keys
+        );
+    }
+}
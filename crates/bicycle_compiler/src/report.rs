@@ -0,0 +1,217 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A one-page summary of the resources of a compiled program, see `bicycle_compiler report`.
+//!
+//! The time and logical error are estimated as in `bicycle_numerics`: each operation starts when all of its blocks are free,
+//! the blocks that wait for it idle, and the error of the operation is counted once by its first instruction.
+//! Unlike the numerics, magic states are not stored ahead of time.
+
+use std::fmt::{self, Display};
+
+use bicycle_common::BicycleISA;
+use serde::Serialize;
+
+use crate::{
+    Architecture,
+    model::{ErrorPrecision, InstructionCost},
+    operation::Operation,
+};
+
+/// The resources of a compiled program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ResourceReport {
+    /// Number of PBC operations of the input program
+    pub operations: u64,
+    pub logical_qubits: usize,
+    /// Data blocks, magic blocks, and storage blocks
+    pub blocks: usize,
+    pub t_count: u64,
+    /// S gates by catalysis with a |Y> state
+    pub s_gates: u64,
+    pub automorphism_generators: u64,
+    pub joint_measurements: u64,
+    /// Measurements on a single block
+    pub local_measurements: u64,
+    /// The estimated time of the program in syndrome cycles
+    pub cycles: u64,
+    /// The estimated wall-clock time of the program
+    pub seconds: f64,
+    /// The estimated probability of a logical error
+    pub logical_error: f64,
+}
+
+impl Display for ResourceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<26} {:>16}", "PBC operations", self.operations)?;
+        writeln!(f, "{:<26} {:>16}", "Logical qubits", self.logical_qubits)?;
+        writeln!(f, "{:<26} {:>16}", "Blocks", self.blocks)?;
+        writeln!(f, "{:<26} {:>16}", "T count", self.t_count)?;
+        writeln!(f, "{:<26} {:>16}", "S gates", self.s_gates)?;
+        writeln!(
+            f,
+            "{:<26} {:>16}",
+            "Automorphism generators", self.automorphism_generators
+        )?;
+        writeln!(
+            f,
+            "{:<26} {:>16}",
+            "Joint measurements", self.joint_measurements
+        )?;
+        writeln!(
+            f,
+            "{:<26} {:>16}",
+            "Local measurements", self.local_measurements
+        )?;
+        writeln!(f, "{:<26} {:>16}", "Estimated cycles", self.cycles)?;
+        writeln!(
+            f,
+            "{:<26} {:>15.3e}s",
+            "Estimated wall-clock time", self.seconds
+        )?;
+        write!(
+            f,
+            "{:<26} {:>16.3e}",
+            "Estimated logical error", self.logical_error
+        )
+    }
+}
+
+/// Accumulates a [`ResourceReport`] from the compiled chunks of a program
+#[derive(Debug, Clone)]
+pub struct ReportBuilder<M> {
+    model: M,
+    report: ResourceReport,
+    /// The time at which each block is free
+    times: Vec<u64>,
+    error: ErrorPrecision,
+}
+
+impl<M: InstructionCost> ReportBuilder<M> {
+    /// Start with the blocks of `architecture`, which may grow as the program is compiled
+    pub fn new(model: M, architecture: &dyn Architecture) -> Self {
+        Self {
+            model,
+            report: ResourceReport::default(),
+            times: vec![0; architecture.total_blocks()],
+            error: ErrorPrecision::ZERO,
+        }
+    }
+
+    /// Add the compiled chunk of one input operation
+    pub fn add(&mut self, chunk: &[Operation]) {
+        self.report.operations += 1;
+        for op in chunk {
+            let Some((_, first)) = op.first() else {
+                continue;
+            };
+            match first {
+                BicycleISA::TGate(_) | BicycleISA::TGatePair(_) => {
+                    self.report.t_count += first.t_count()
+                }
+                BicycleISA::SGate(_) => self.report.s_gates += 1,
+                BicycleISA::Automorphism(aut) => {
+                    self.report.automorphism_generators += aut.nr_generators()
+                }
+                BicycleISA::Measure(_) => self.report.local_measurements += 1,
+                BicycleISA::JointMeasure(_) => self.report.joint_measurements += 1,
+                _ => {}
+            }
+            self.error = self
+                .error
+                .saturating_add(self.model.instruction_error(first));
+
+            // A block that the compiler added to the path starts when it is initialized
+            if let Some(last) = op.iter().map(|(block, _)| *block).max()
+                && last >= self.times.len()
+            {
+                let now = self.times.iter().copied().max().unwrap_or(0);
+                self.times.resize(last + 1, now);
+            }
+            let start = op
+                .iter()
+                .map(|(block, _)| self.times[*block])
+                .max()
+                .unwrap_or(0);
+            for (block, instruction) in op {
+                let (_, idle_error) = self.model.idling_error(start - self.times[*block]);
+                self.error = self.error.saturating_add(idle_error);
+                self.times[*block] = start.saturating_add(self.model.timing(instruction));
+            }
+        }
+    }
+
+    /// The report of the chunks so far on the final architecture, where a syndrome cycle takes `cycle_time` microseconds
+    pub fn finish(&self, architecture: &dyn Architecture, cycle_time: f64) -> ResourceReport {
+        let cycles = self.times.iter().copied().max().unwrap_or(0);
+        ResourceReport {
+            logical_qubits: architecture.layout().qubits(),
+            blocks: architecture.total_blocks(),
+            cycles,
+            seconds: cycles as f64 * cycle_time * 1e-6,
+            logical_error: self.error.to_num(),
+            ..self.report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{AutomorphismData, Pauli, TGateData, TwoBases};
+
+    use super::*;
+    use crate::{PathArchitecture, model::GROSS_1E3};
+
+    #[test]
+    fn counts_and_estimates() {
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let joint = BicycleISA::JointMeasure(z);
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let aut = BicycleISA::Automorphism(AutomorphismData::new(1, 2));
+        let architecture = PathArchitecture::new(2);
+        let mut builder = ReportBuilder::new(GROSS_1E3, &architecture);
+        builder.add(&[vec![(0, BicycleISA::Measure(z))], vec![(1, aut)]]);
+        builder.add(&[vec![(0, joint), (1, joint)], vec![(1, t_gate)]]);
+
+        let report = builder.finish(&architecture, 1.0);
+        assert_eq!(2, report.operations);
+        assert_eq!(22, report.logical_qubits);
+        assert_eq!(2, report.blocks);
+        assert_eq!(1, report.t_count);
+        assert_eq!(aut_generators(&aut), report.automorphism_generators);
+        assert_eq!(1, report.joint_measurements);
+        assert_eq!(1, report.local_measurements);
+
+        // The joint measurement waits for the slower of the measurement and the automorphism
+        let measure = GROSS_1E3.timing(&BicycleISA::Measure(z));
+        let shift = GROSS_1E3.timing(&aut);
+        let joint_end = measure.max(shift) + GROSS_1E3.timing(&joint);
+        assert_eq!(joint_end + GROSS_1E3.timing(&t_gate), report.cycles);
+        assert_eq!(report.cycles as f64 * 1e-6, report.seconds);
+
+        let error = [BicycleISA::Measure(z), aut, joint, t_gate]
+            .iter()
+            .map(|instruction| GROSS_1E3.instruction_error(instruction))
+            .sum::<ErrorPrecision>()
+            + GROSS_1E3.idling_error(measure.abs_diff(shift)).1;
+        assert_eq!(error.to_num::<f64>(), report.logical_error);
+    }
+
+    fn aut_generators(aut: &BicycleISA) -> u64 {
+        match aut {
+            BicycleISA::Automorphism(aut) => aut.nr_generators(),
+            _ => unreachable!(),
+        }
+    }
+}
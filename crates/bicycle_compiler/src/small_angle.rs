@@ -15,8 +15,12 @@
 use core::str;
 use std::{
     collections::HashMap,
-    io::{self, ErrorKind},
-    sync::{LazyLock, Mutex},
+    error::Error,
+    io::{self, ErrorKind, Read, Write},
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 #[cfg(not(feature = "rsgridsynth"))]
@@ -25,19 +29,151 @@ use std::process::Command;
 use bicycle_common::Pauli;
 use log::{debug, trace};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "rsgridsynth")]
 use rsgridsynth::{config::config_from_theta_epsilon, gridsynth::gridsynth_gates};
 
-use crate::language::AnglePrecision;
+use crate::{
+    explain::{self, Stage},
+    language::AnglePrecision,
+};
 
-type CacheHashMap =
-    HashMap<(AnglePrecision, AnglePrecision), (Vec<SingleRotation>, Vec<CliffordGate>)>;
+type CacheHashMap = HashMap<(AnglePrecision, AnglePrecision), CachedSynthesis>;
 static CACHE: LazyLock<Mutex<CacheHashMap>> = LazyLock::new(Default::default);
+/// The generation of the angle cache, which is one more than the generation of the cache that was read
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The first bytes of an angle cache, see [`write_cache`]
+const ANGLE_CACHE_MAGIC: &[u8; 8] = b"bicyangl";
+
+/// The version of the angle cache format.
+/// Increase it when the format or the synthesized sequences change, such that older caches are discarded.
+pub const ANGLE_CACHE_VERSION: u32 = 1;
+
+/// The synthesizer whose sequences are cached
+#[cfg(not(feature = "rsgridsynth"))]
+const SYNTHESIZER: &str = "gridsynth";
+#[cfg(feature = "rsgridsynth")]
+const SYNTHESIZER: &str = "rsgridsynth";
+
+#[derive(Debug, Clone)]
+struct CachedSynthesis {
+    synthesis: (Vec<SingleRotation>, Vec<CliffordGate>),
+    /// The generation in which the angle was last used
+    used: u64,
+}
+
+/// Follows [`ANGLE_CACHE_MAGIC`] in an angle cache
+#[derive(Serialize, Deserialize)]
+struct AngleCacheFile {
+    version: u32,
+    synthesizer: String,
+    /// Number of runs that wrote the cache before
+    generation: u64,
+    entries: Vec<CachedAngle>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedAngle {
+    angle: AnglePrecision,
+    accuracy: AnglePrecision,
+    rotations: Vec<SingleRotation>,
+    cliffords: Vec<CliffordGate>,
+    used: u64,
+}
+
+/// Add the synthesized angles of a cache written by [`write_cache`] to the in-memory cache
+/// and return how many were read.
+/// Caches of another version or synthesizer are rejected.
+pub fn read_cache(mut reader: impl Read) -> Result<usize, Box<dyn Error>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != ANGLE_CACHE_MAGIC {
+        return Err("Not an angle cache".into());
+    }
+    let mut serialized = vec![];
+    reader.read_to_end(&mut serialized)?;
+    let file: AngleCacheFile = bitcode::deserialize(&serialized)?;
+    if file.version != ANGLE_CACHE_VERSION {
+        return Err(format!(
+            "The angle cache has version {}, expected version {ANGLE_CACHE_VERSION}",
+            file.version
+        )
+        .into());
+    }
+    if file.synthesizer != SYNTHESIZER {
+        return Err(format!(
+            "The angle cache was synthesized by {}, not by {SYNTHESIZER}",
+            file.synthesizer
+        )
+        .into());
+    }
+
+    GENERATION.fetch_max(file.generation + 1, Ordering::Relaxed);
+    let mut cache = CACHE.try_lock().unwrap();
+    let read = file.entries.len();
+    for entry in file.entries {
+        cache
+            .entry((entry.angle, entry.accuracy))
+            .or_insert(CachedSynthesis {
+                synthesis: (entry.rotations, entry.cliffords),
+                used: entry.used,
+            });
+    }
+    Ok(read)
+}
+
+/// Write the in-memory cache of synthesized angles and return how many were written.
+/// If there are more than `capacity` angles, the angles that were used least recently are evicted.
+pub fn write_cache(mut writer: impl Write, capacity: usize) -> io::Result<usize> {
+    let mut entries: Vec<_> = CACHE
+        .try_lock()
+        .unwrap()
+        .iter()
+        .map(|(&(angle, accuracy), cached)| CachedAngle {
+            angle,
+            accuracy,
+            rotations: cached.synthesis.0.clone(),
+            cliffords: cached.synthesis.1.clone(),
+            used: cached.used,
+        })
+        .collect();
+    entries.sort_by_key(|entry| (std::cmp::Reverse(entry.used), entry.angle, entry.accuracy));
+    entries.truncate(capacity);
+    let written = entries.len();
+    let file = AngleCacheFile {
+        version: ANGLE_CACHE_VERSION,
+        synthesizer: SYNTHESIZER.to_string(),
+        generation: GENERATION.load(Ordering::Relaxed),
+        entries,
+    };
+    writer.write_all(ANGLE_CACHE_MAGIC)?;
+    writer
+        .write_all(&bitcode::serialize(&file).expect("The angle cache should be serializable"))?;
+    Ok(written)
+}
 
 /// The angle θ such that Z(θ) := exp(-iθ/2) diag(1, exp(iθ)) = T up to the global phase exp(-iθ/2).
 pub const T_ANGLE: AnglePrecision = AnglePrecision::FRAC_PI_4;
 
+/// The angle θ such that Z(θ) = S up to a global phase
+pub const S_ANGLE: AnglePrecision = AnglePrecision::FRAC_PI_2;
+
+/// The largest accuracy that [`synthesize_angle`] accepts
+pub const MAX_ACCURACY: AnglePrecision = AnglePrecision::lit("0.1");
+
+/// Check that a synthesis accuracy is positive and at most [`MAX_ACCURACY`]
+pub fn check_accuracy(accuracy: AnglePrecision) -> Result<(), String> {
+    if accuracy > 0 && accuracy <= MAX_ACCURACY {
+        Ok(())
+    } else {
+        Err(format!(
+            "The synthesis accuracy {accuracy} should be positive and at most {MAX_ACCURACY}"
+        ))
+    }
+}
+
 /// Synthesize a rotation e^{iθZ} in terms of T and T_X = HTH rotations, followed by Cliffords,
 /// up to a global phase.
 /// The required accuracy must be less than 0.1 and determines ‖e^{iθZ} - U‖ ≤ ε in operator norm.
@@ -46,7 +182,7 @@ pub fn synthesize_angle(
     theta: AnglePrecision,
     accuracy: AnglePrecision,
 ) -> (Vec<SingleRotation>, Vec<CliffordGate>) {
-    assert!(accuracy <= 1e-1);
+    assert!(accuracy <= MAX_ACCURACY);
 
     // Handle T gate special case. We only check for equality, and if not pass it to gridsynth.
     if theta.abs() == T_ANGLE {
@@ -58,6 +194,21 @@ pub fn synthesize_angle(
             vec![],
         );
     }
+    // The S gate is Clifford
+    if theta.abs() == S_ANGLE {
+        trace!("Angle equal to S: {theta}");
+        let powers = if theta.is_negative() { 3 } else { 1 };
+        return (vec![], vec![CliffordGate::S; powers]);
+    }
+    // Other multiples of the T angle need at most one T gate, e.g., after randomized rounding
+    let multiple = theta / T_ANGLE;
+    if multiple.frac() == AnglePrecision::ZERO {
+        trace!("Angle is a multiple of T: {theta}");
+        let eighths = multiple.int().to_num::<i64>().rem_euclid(8);
+        let t_gates = (eighths % 2 == 1).then_some(SingleRotation::Z { dagger: false });
+        let powers = (eighths / 2) as usize;
+        return (t_gates.into_iter().collect(), vec![CliffordGate::S; powers]);
+    }
     // Some notes for approximation guarantees and an implementation that suffers from rounding errors.
     // Since we don't care about the global phase, we can write Z(θ) = diag(1, exp(-i2θ))
     // and obtain ||Z(θ) - T|| = √(2(1-cos(2(π/4-θ))) ≤ ε
@@ -73,16 +224,22 @@ pub fn synthesize_angle(
     //     return (vec![SingleRotation::Z { dagger: theta.is_negative() }], vec![]);
     // }
 
-    if let Some(result) = CACHE.try_lock().unwrap().get(&(theta, accuracy)) {
+    if let Some(cached) = CACHE.try_lock().unwrap().get_mut(&(theta, accuracy)) {
         trace!("Cached angle: {theta}");
-        return result.clone();
+        cached.used = GENERATION.load(Ordering::Relaxed);
+        return cached.synthesis.clone();
     }
-    let res = synthesize_angle_direct(theta, accuracy);
-
-    CACHE
-        .try_lock()
-        .unwrap()
-        .insert((theta, accuracy), res.clone());
+    let res = explain::time(Stage::Synthesis, || {
+        synthesize_angle_direct(theta, accuracy)
+    });
+
+    CACHE.try_lock().unwrap().insert(
+        (theta, accuracy),
+        CachedSynthesis {
+            synthesis: res.clone(),
+            used: GENERATION.load(Ordering::Relaxed),
+        },
+    );
     res
 }
 
@@ -149,7 +306,7 @@ pub(crate) fn run_gridsynth(
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SingleRotation {
     Z { dagger: bool },
     X { dagger: bool },
@@ -181,7 +338,7 @@ impl SingleRotation {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CliffordGate {
     S,
     H,
@@ -206,6 +363,37 @@ impl TryFrom<char> for CliffordGate {
     }
 }
 
+/// The power `k` such that the Cliffords in time order equal S^k followed by a Pauli, up to a global phase,
+/// or `None` if they do not preserve the Z axis
+pub fn s_power(cliffords: &[CliffordGate]) -> Option<u8> {
+    // The images of Z and X under conjugation by the Cliffords, with their signs
+    let conjugate = |(p, negated): (Pauli, bool)| {
+        cliffords
+            .iter()
+            .fold((p, negated), |(p, negated), gate| match (gate, p) {
+                (CliffordGate::S, Pauli::X) => (Pauli::Y, negated),
+                (CliffordGate::S, Pauli::Y) => (Pauli::X, !negated),
+                (CliffordGate::H, Pauli::X) => (Pauli::Z, negated),
+                (CliffordGate::H, Pauli::Z) => (Pauli::X, negated),
+                (CliffordGate::H, Pauli::Y) => (Pauli::Y, !negated),
+                (CliffordGate::X, Pauli::Y | Pauli::Z) => (p, !negated),
+                _ => (p, negated),
+            })
+    };
+    let (z, flipped) = conjugate((Pauli::Z, false));
+    if z != Pauli::Z {
+        return None;
+    }
+    // Undo the X of X S^k, which negates the image of Y
+    match conjugate((Pauli::X, false)) {
+        (Pauli::X, false) => Some(0),
+        (Pauli::Y, negated) if negated == flipped => Some(1),
+        (Pauli::X, true) => Some(2),
+        (Pauli::Y, _) => Some(3),
+        _ => unreachable!("A Clifford that preserves the Z axis maps X to X or Y"),
+    }
+}
+
 /// Compile rotations up to global phase
 /// W gates are discarded
 fn compile_rots(gates: &str) -> Result<(Vec<SingleRotation>, Vec<CliffordGate>), io::Error> {
@@ -280,6 +468,15 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn accuracy_range() {
+        assert_eq!(Ok(()), check_accuracy(AnglePrecision::lit("1e-9")));
+        assert_eq!(Ok(()), check_accuracy(MAX_ACCURACY));
+        assert!(check_accuracy(AnglePrecision::lit("0.5")).is_err());
+        assert!(check_accuracy(AnglePrecision::ZERO).is_err());
+        assert!(check_accuracy(AnglePrecision::lit("-1e-3")).is_err());
+    }
+
     // #[test]
     // fn test_05_minus3() -> Result<(), Box<dyn Error>> {
     //     let test_str = "THTHTSHTSHTHTHTHTHTHTHTHTHTHTHTHTSHTHTHTSHTHTSHTHTHTSHTHTHTHTHTSHTHTSSS";
@@ -294,6 +491,96 @@ mod test {
     //     Ok(())
     // }
 
+    #[test]
+    fn synthesize_s_exactly() {
+        let accuracy = AnglePrecision::lit("1e-10");
+        assert_eq!(
+            (vec![], vec![CliffordGate::S]),
+            synthesize_angle(S_ANGLE, accuracy)
+        );
+        assert_eq!(
+            (vec![], vec![CliffordGate::S; 3]),
+            synthesize_angle(-S_ANGLE, accuracy)
+        );
+    }
+
+    #[test]
+    fn synthesize_t_multiples_exactly() {
+        let accuracy = AnglePrecision::lit("1e-10");
+        assert_eq!(
+            (vec![], vec![]),
+            synthesize_angle(AnglePrecision::ZERO, accuracy)
+        );
+        assert_eq!(
+            (vec![], vec![CliffordGate::S; 2]),
+            synthesize_angle(4 * T_ANGLE, accuracy)
+        );
+        assert_eq!(
+            (
+                vec![SingleRotation::Z { dagger: false }],
+                vec![CliffordGate::S; 2]
+            ),
+            synthesize_angle(-3 * T_ANGLE, accuracy)
+        );
+    }
+
+    #[test]
+    fn persistent_cache() -> Result<(), Box<dyn Error>> {
+        let recent = (AnglePrecision::lit("0.123"), AnglePrecision::lit("0.0123"));
+        let stale = (AnglePrecision::lit("0.123"), AnglePrecision::lit("0.0124"));
+        let synthesis = (
+            vec![SingleRotation::X { dagger: true }],
+            vec![CliffordGate::H],
+        );
+        {
+            let mut cache = CACHE.lock().unwrap();
+            let cached = |used| CachedSynthesis {
+                synthesis: synthesis.clone(),
+                used,
+            };
+            cache.insert(recent, cached(u64::MAX));
+            cache.insert(stale, cached(0));
+        }
+        let mut evicted = vec![];
+        assert_eq!(1, write_cache(&mut evicted, 1)?);
+        let mut all = vec![];
+        assert!(write_cache(&mut all, usize::MAX)? >= 2);
+
+        CACHE
+            .lock()
+            .unwrap()
+            .retain(|key, _| key != &recent && key != &stale);
+        assert_eq!(1, read_cache(&evicted[..])?);
+        assert!(!CACHE.lock().unwrap().contains_key(&stale));
+        assert!(read_cache(&all[..])? >= 2);
+        assert!(CACHE.lock().unwrap().contains_key(&stale));
+        assert_eq!(synthesis, synthesize_angle(recent.0, recent.1));
+
+        let mut outdated = ANGLE_CACHE_MAGIC.to_vec();
+        outdated.extend(bitcode::serialize(&AngleCacheFile {
+            version: ANGLE_CACHE_VERSION + 1,
+            synthesizer: SYNTHESIZER.to_string(),
+            generation: 0,
+            entries: vec![],
+        })?);
+        assert!(read_cache(&outdated[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn s_powers() {
+        use CliffordGate::{H, S, W, X};
+        assert_eq!(Some(0), s_power(&[]));
+        assert_eq!(Some(1), s_power(&[S, W, W]));
+        assert_eq!(Some(2), s_power(&[S, S]));
+        assert_eq!(Some(3), s_power(&[S, S, S]));
+        // S after X is S^3 followed by X up to a global phase
+        assert_eq!(Some(1), s_power(&[S, X]));
+        assert_eq!(Some(3), s_power(&[X, S]));
+        assert_eq!(Some(0), s_power(&[H, S, S, H, X]));
+        assert_eq!(None, s_power(&[H]));
+    }
+
     #[test]
     fn parse_ma_form_t_start() -> Result<(), Box<dyn Error>> {
         let ma = "THTSW";
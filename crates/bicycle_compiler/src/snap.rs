@@ -0,0 +1,193 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Randomized rounding of rotation angles to multiples of the T angle.
+//!
+//! A rotation about θ = (k + f) π/4 with 0 < f < 1 is replaced by a rotation about kπ/4 with probability 1 - f
+//! and about (k + 1)π/4 with probability f, such that the angle is unbiased.
+//! The rounded rotations need at most one T gate instead of a synthesized sequence,
+//! at the cost of a small error in the channel that is averaged over the random choices.
+
+use std::f64::consts::FRAC_PI_4;
+
+use rand::Rng;
+
+use crate::{
+    language::{AnglePrecision, PbcOperation},
+    small_angle::T_ANGLE,
+};
+
+/// Diamond-norm distance between a rotation by `fraction` of the T angle
+/// and its randomized rounding to either no rotation or a rotation by the T angle.
+///
+/// Both channels only change the coherences between the ±1 eigenspaces of the Pauli,
+/// so their distance is the difference of the phase factors of the coherences.
+pub fn rounding_error(fraction: f64) -> f64 {
+    let angle = fraction * FRAC_PI_4;
+    let re = (1.0 - fraction) + fraction * FRAC_PI_4.cos() - angle.cos();
+    let im = angle.sin() - fraction * FRAC_PI_4.sin();
+    re.hypot(im)
+}
+
+/// Rounds the angles of rotations whose rounding error is at most a given bound
+pub struct AngleSnapper<R> {
+    max_error: f64,
+    rng: R,
+    snapped: usize,
+    budget: f64,
+}
+
+impl<R: Rng> AngleSnapper<R> {
+    pub fn new(max_error: f64, rng: R) -> Self {
+        Self {
+            max_error,
+            rng,
+            snapped: 0,
+            budget: 0.0,
+        }
+    }
+
+    /// Round the rotations of an operation, leaving measurements and CCZ gates unchanged
+    pub fn snap(&mut self, op: PbcOperation) -> PbcOperation {
+        match op {
+            PbcOperation::Rotation {
+                basis,
+                angle,
+                accuracy,
+            } => PbcOperation::Rotation {
+                basis,
+                angle: self.snap_angle(angle),
+                accuracy,
+            },
+            PbcOperation::MultiRotation { terms } => PbcOperation::MultiRotation {
+                terms: terms
+                    .into_iter()
+                    .map(|(basis, angle)| (basis, self.snap_angle(angle)))
+                    .collect(),
+            },
+            PbcOperation::RotateMeasure {
+                basis,
+                angle,
+                flip_result,
+                accuracy,
+            } => PbcOperation::RotateMeasure {
+                basis,
+                angle: self.snap_angle(angle),
+                flip_result,
+                accuracy,
+            },
+            op => op,
+        }
+    }
+
+    fn snap_angle(&mut self, angle: AnglePrecision) -> AnglePrecision {
+        let multiple = angle / T_ANGLE;
+        let fraction = multiple.frac();
+        if fraction == AnglePrecision::ZERO {
+            return angle;
+        }
+        let error = rounding_error(fraction.to_num());
+        if error > self.max_error {
+            return angle;
+        }
+        self.snapped += 1;
+        self.budget += error;
+        let lower = multiple.floor() * T_ANGLE;
+        if self.rng.random_bool(fraction.to_num()) {
+            lower + T_ANGLE
+        } else {
+            lower
+        }
+    }
+
+    /// Number of rounded rotations
+    pub fn snapped(&self) -> usize {
+        self.snapped
+    }
+
+    /// Upper bound on the diamond-norm distance of the averaged program to the original program,
+    /// as the sum of the rounding errors of all rotations
+    pub fn budget(&self) -> f64 {
+        self.budget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::Pauli;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    use Pauli::{X, Z};
+
+    #[test]
+    fn rounding_errors() {
+        assert!(rounding_error(0.0) < 1e-15);
+        assert!(rounding_error(1.0) < 1e-15);
+        assert!((rounding_error(0.3) - rounding_error(0.7)).abs() < 1e-12);
+        // The error vanishes linearly with the distance to a multiple of the T angle
+        assert!(rounding_error(1e-3) < 1e-3);
+        assert!(rounding_error(1e-3) > 1e-4);
+        assert!(rounding_error(0.01) < rounding_error(0.1));
+        assert!(rounding_error(0.5) < 0.1);
+    }
+
+    #[test]
+    fn snap_unbiased() {
+        let angle = T_ANGLE * AnglePrecision::lit("-2.25");
+        let mut snapper = AngleSnapper::new(1.0, StdRng::seed_from_u64(0));
+        let samples = 10_000;
+        let mut upper = 0;
+        for _ in 0..samples {
+            let op = snapper.snap(PbcOperation::Rotation {
+                basis: vec![X, Z],
+                angle,
+                accuracy: None,
+            });
+            let PbcOperation::Rotation { angle: snapped, .. } = op else {
+                panic!("{op} should be a rotation");
+            };
+            if snapped == -2 * T_ANGLE {
+                upper += 1;
+            } else {
+                assert_eq!(-3 * T_ANGLE, snapped);
+            }
+        }
+        assert!((upper as f64 / samples as f64 - 0.75).abs() < 0.02);
+        assert_eq!(samples, snapper.snapped());
+        assert!((snapper.budget() - samples as f64 * rounding_error(0.75)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn snap_within_max_error() {
+        let mut snapper = AngleSnapper::new(rounding_error(0.01), StdRng::seed_from_u64(0));
+        let small = T_ANGLE * AnglePrecision::lit("0.005");
+        let large = AnglePrecision::lit("0.1");
+        let op = PbcOperation::MultiRotation {
+            terms: vec![
+                (vec![X, Z], small),
+                (vec![Z, Z], large),
+                (vec![Z, X], T_ANGLE),
+            ],
+        };
+        let PbcOperation::MultiRotation { terms } = snapper.snap(op) else {
+            panic!("The operation should stay a multi-rotation");
+        };
+        assert!(terms[0].1 == AnglePrecision::ZERO || terms[0].1 == T_ANGLE);
+        assert_eq!(large, terms[1].1);
+        assert_eq!(T_ANGLE, terms[2].1);
+        assert_eq!(1, snapper.snapped());
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The compile stage of the pipeline, which compiles each operation on an architecture that grows with the program
+
+use std::io;
+
+use bicycle_cliffords::MeasurementTable;
+use bicycle_common::{AutomorphismData, BicycleISA};
+use bicycle_compiler::{
+    BlockBasisState, PathArchitecture, Topology,
+    explain::{self, Stage},
+    language::{self, HintedOperation, PbcOperation},
+    operation::Operation,
+    qre::{CountSource, ProgramCounter},
+};
+
+use crate::{
+    cli::Cli,
+    input::{InputChecks, MalformedInput},
+    passes::{ExpectedOperations, LowerBoundReport},
+};
+
+/// The instructions of an operation
+pub struct CompiledChunk {
+    pub chunk: Vec<Operation>,
+    /// The outcomes of a measurement and whether they are flipped
+    pub measured: Option<(Vec<usize>, bool)>,
+    /// The data blocks of the architecture after compiling the operation
+    pub data_blocks: usize,
+}
+
+/// Compiles the operations one by one, and counts and reports on them
+pub struct CompileStage<'a> {
+    cli: &'a Cli,
+    checks: &'a InputChecks,
+    measurement_table: &'a dyn MeasurementTable,
+    expected: &'a ExpectedOperations,
+    pub architecture: PathArchitecture,
+    bases: BlockBasisState,
+    /// The first operation that cannot be compiled, because it needs automorphisms when they are disabled
+    /// or measurements that the table does not implement
+    pub uncompilable: Option<MalformedInput>,
+    /// Number of fused rotation-measurement pairs
+    pub fused: usize,
+    /// Number of instructions that fusing saved
+    pub saved: usize,
+    /// Number of blocks that were added to the architecture of the first operation
+    pub added_blocks: usize,
+    pub lower_bounds: Option<LowerBoundReport>,
+    pub program_counter: Option<ProgramCounter>,
+}
+
+impl<'a> CompileStage<'a> {
+    pub fn new(
+        cli: &'a Cli,
+        checks: &'a InputChecks,
+        measurement_table: &'a dyn MeasurementTable,
+        expected: &'a ExpectedOperations,
+        architecture: PathArchitecture,
+    ) -> io::Result<Self> {
+        let output = &cli.output;
+        Ok(Self {
+            cli,
+            checks,
+            measurement_table,
+            expected,
+            bases: BlockBasisState::new(architecture.data_blocks()),
+            architecture,
+            uncompilable: None,
+            fused: 0,
+            saved: 0,
+            added_blocks: 0,
+            lower_bounds: match &output.lower_bounds {
+                Some(path) => Some(LowerBoundReport::new(path, output.flag_ratio)?),
+                None => None,
+            },
+            program_counter: (output.qre_counts.is_some()
+                && output.qre_source == CountSource::Input)
+                .then(ProgramCounter::default),
+        })
+    }
+
+    /// Compile an operation, or restore the cached bases after the last operation for `None`.
+    /// Stops with `None` at the first operation that cannot be compiled.
+    pub fn compile(&mut self, hinted: Option<HintedOperation>) -> Option<CompiledChunk> {
+        let cli = self.cli;
+        let Some(hinted) = hinted else {
+            if cli.passes.verify {
+                self.expected.lock().unwrap().push_back((None, vec![]));
+            }
+            return Some(CompiledChunk {
+                chunk: self.bases.finish(),
+                measured: None,
+                data_blocks: self.architecture.data_blocks(),
+            });
+        };
+        let qubits = hinted.op.basis().len();
+        let init = if qubits <= self.architecture.qubits() {
+            vec![]
+        } else if cli.layout.fixed_architecture
+            || self.architecture.storage_blocks() > 0
+            || cli.layout.topology == Topology::Ring
+        {
+            let reason = if cli.layout.fixed_architecture {
+                "--fixed-architecture forbids adding blocks"
+            } else if cli.layout.topology == Topology::Ring {
+                "adding blocks would disconnect the ends of the ring"
+            } else {
+                "the storage blocks are numbered after the data blocks"
+            };
+            let error = format!(
+                "The operation acts on {qubits} qubits, but the architecture holds only {} qubits and {reason}",
+                self.architecture.qubits()
+            );
+            return self.reject(&hinted, error);
+        } else {
+            let init = self.architecture.grow(qubits);
+            self.added_blocks += init.len();
+            init
+        };
+        let architecture = cli.layout.topology.connect(self.architecture.clone());
+        if let Err(error) = language::check_barrier(&hinted.op, architecture.total_blocks()) {
+            return self.reject(&hinted, error);
+        }
+        if let Some(p) = hinted
+            .op
+            .uncovered(self.measurement_table, architecture.qubits_per_block())
+        {
+            let error = format!(
+                "The operation needs the measurement {p} on a block, which the incomplete measurement table does not implement"
+            );
+            return self.reject(&hinted, error);
+        }
+        let accuracy = cli.synthesis.accuracy;
+        let (compiled, op_saved, outcomes) = explain::time(Stage::Compile, || {
+            if cli.passes.cache_bases {
+                hinted.op.compile_cached_observed(
+                    &*architecture,
+                    self.measurement_table,
+                    accuracy,
+                    &mut self.bases,
+                )
+            } else {
+                hinted
+                    .op
+                    .compile_observed(&*architecture, self.measurement_table, accuracy)
+            }
+        });
+        if cli.table.no_automorphisms && uses_automorphisms(&compiled) {
+            let error = "The operation cannot be compiled without automorphisms, which are disabled by --no-automorphisms".to_string();
+            return self.reject(&hinted, error);
+        }
+        let op = hinted.op;
+        if let PbcOperation::RotateMeasure { .. } = op {
+            self.fused += 1;
+            self.saved += op_saved;
+        }
+        let measured = outcomes.map(|outcomes| match op {
+            PbcOperation::Measurement { flip_result, .. }
+            | PbcOperation::RotateMeasure { flip_result, .. } => (outcomes, flip_result),
+            _ => unreachable!("Only measurements have outcomes"),
+        });
+        if let Some(counter) = self.program_counter.as_mut() {
+            counter.add(&op);
+        }
+        if let Some(report) = self.lower_bounds.as_mut() {
+            report.add(&op, &compiled, &*architecture, accuracy);
+        }
+        if cli.passes.verify {
+            let restore = match cli.passes.cache_bases {
+                true => self.bases.clone().finish(),
+                false => vec![],
+            };
+            self.expected.lock().unwrap().push_back((Some(op), restore));
+        }
+        // The new blocks are initialized before the operation that needs them
+        let measured = measured
+            .map(|(outcomes, flip)| (outcomes.iter().map(|i| i + init.len()).collect(), flip));
+        Some(CompiledChunk {
+            chunk: init.into_iter().chain(compiled).collect(),
+            measured,
+            data_blocks: self.architecture.data_blocks(),
+        })
+    }
+
+    /// Record an operation that cannot be compiled, which stops the compile stage
+    fn reject(&mut self, hinted: &HintedOperation, error: String) -> Option<CompiledChunk> {
+        self.uncompilable = Some(self.checks.uncompilable(hinted, error));
+        None
+    }
+}
+
+/// Check if the compiled operations apply a non-trivial automorphism
+fn uses_automorphisms(compiled: &[Operation]) -> bool {
+    compiled.iter().flatten().any(|(_, isa)| {
+        matches!(isa, BicycleISA::Automorphism(aut) if *aut != AutomorphismData::new(0, 0))
+    })
+}
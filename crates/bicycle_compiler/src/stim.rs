@@ -0,0 +1,592 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import Stim circuits of Clifford gates and measurements as PBC programs.
+//!
+//! Each Clifford gate is written as Pauli rotations by ±π/2,
+//! and the rotations of the gates in one instruction that act on disjoint qubits are merged into a single [`PbcOperation::MultiRotation`].
+//! Measurements, resets, and Pauli product measurements (`MPP`) become Pauli measurements.
+//! A reset is only a measurement, since the Pauli that flips its outcome is left to the Pauli frame and is not output.
+//! Pauli gates, classically controlled Paulis, noise channels, and annotations such as `DETECTOR` do not change the cost and are skipped.
+//! The bodies of `REPEAT` blocks are repeated while the operations are read, see [`parse_lazy`].
+
+use std::{error::Error, fmt::Display, sync::Arc};
+
+use bicycle_common::Pauli;
+
+use crate::{language::PbcOperation, small_angle::S_ANGLE};
+
+/// A Stim or OpenQASM circuit that cannot be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StimError {
+    /// The line of the circuit, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for StimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for StimError {}
+
+/// A Pauli on some of the qubits
+type SparsePauli = Vec<(usize, Pauli)>;
+
+/// A rotation by `S_ANGLE` times the sign
+type Term = (SparsePauli, i32);
+
+enum SparseOperation {
+    /// Mutually commuting rotations
+    Rotations(Vec<Term>),
+    Measurement {
+        basis: SparsePauli,
+        flip_result: bool,
+    },
+}
+
+/// The largest number of qubits of a circuit, since every operation is written on all qubits of the circuit
+pub const MAX_QUBITS: usize = 1 << 20;
+
+/// An instruction of the circuit, where each gate is already written as operations
+enum Instruction {
+    Operation(SparseOperation),
+    Repeat {
+        count: usize,
+        body: Arc<[Instruction]>,
+    },
+}
+
+/// Parse a Stim circuit into PBC operations that all act on the qubits that the circuit uses
+pub fn parse(circuit: &str) -> Result<Vec<PbcOperation>, StimError> {
+    parse_lazy(circuit).map(Iterator::collect)
+}
+
+/// Parse a Stim circuit like [`parse`], but repeat the bodies of `REPEAT` blocks while the operations are read,
+/// such that long repetitions are not unrolled in memory
+pub fn parse_lazy(circuit: &str) -> Result<StimOperations, StimError> {
+    let mut lines = circuit.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut qubits = 0;
+    let instructions = parse_block(&mut lines, None, &mut qubits)?;
+    Ok(StimOperations {
+        qubits,
+        stack: vec![(instructions.into(), 0, 1)],
+    })
+}
+
+/// The operations of a Stim circuit, see [`parse_lazy`]
+pub struct StimOperations {
+    /// Number of qubits that the circuit uses
+    qubits: usize,
+    /// The blocks that are being repeated, with the index of their next instruction and their remaining repetitions
+    stack: Vec<(Arc<[Instruction]>, usize, usize)>,
+}
+
+impl StimOperations {
+    /// Number of qubits that each operation acts on
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+}
+
+/// The operation on `qubits` qubits
+fn dense_operation(op: &SparseOperation, qubits: usize) -> PbcOperation {
+    let dense = |paulis: &SparsePauli| {
+        let mut basis = vec![Pauli::I; qubits];
+        for (qubit, p) in paulis {
+            basis[*qubit] = *p;
+        }
+        basis
+    };
+    match op {
+        SparseOperation::Rotations(terms) => {
+            let mut terms: Vec<_> = terms
+                .iter()
+                .map(|(paulis, sign)| (dense(paulis), S_ANGLE * i128::from(*sign)))
+                .collect();
+            if terms.len() == 1 {
+                let (basis, angle) = terms.pop().unwrap();
+                PbcOperation::Rotation {
+                    basis,
+                    angle,
+                    accuracy: None,
+                }
+            } else {
+                PbcOperation::MultiRotation { terms }
+            }
+        }
+        SparseOperation::Measurement { basis, flip_result } => PbcOperation::Measurement {
+            basis: dense(basis),
+            flip_result: *flip_result,
+        },
+    }
+}
+
+impl Iterator for StimOperations {
+    type Item = PbcOperation;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (block, next, remaining) = self.stack.last_mut()?;
+            let Some(instruction) = block.get(*next) else {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.stack.pop();
+                } else {
+                    *next = 0;
+                }
+                continue;
+            };
+            *next += 1;
+            match instruction {
+                Instruction::Operation(op) => return Some(dense_operation(op, self.qubits)),
+                Instruction::Repeat { count, body } => {
+                    let repeated = (body.clone(), 0, *count);
+                    self.stack.push(repeated);
+                }
+            }
+        }
+    }
+}
+
+/// Parse instructions until the end of the circuit, or until the closing brace of the block that starts at `opened`.
+/// The number of qubits is raised to the qubits that the instructions act on.
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    opened: Option<usize>,
+    qubits: &mut usize,
+) -> Result<Vec<Instruction>, StimError> {
+    let mut instructions = vec![];
+    while let Some((line, text)) = lines.next() {
+        let text = text.split('#').next().unwrap().trim();
+        if text.is_empty() {
+            continue;
+        }
+        let error = |message: String| StimError { line, message };
+        if text == "}" {
+            return match opened {
+                Some(_) => Ok(instructions),
+                None => Err(error("Unmatched closing brace".to_string())),
+            };
+        }
+
+        let (text, block) = match text.strip_suffix('{') {
+            Some(text) => (text.trim(), true),
+            None => (text, false),
+        };
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap();
+        // Drop the arguments of the instruction, e.g., the probability of a noise channel
+        let name = name.split('(').next().unwrap().to_uppercase();
+        let targets: Vec<&str> = words.collect();
+
+        if block {
+            if name != "REPEAT" {
+                return Err(error(format!("Unexpected block after {name}")));
+            }
+            let count = match targets.as_slice() {
+                [count] => count.parse().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| error("REPEAT should have a single repetition count".to_string()))?;
+            let body = parse_block(lines, Some(line), qubits)?;
+            // Repeating nothing takes no operations, however often it is repeated
+            if count > 0 && !body.is_empty() {
+                instructions.push(Instruction::Repeat {
+                    count,
+                    body: body.into(),
+                });
+            }
+        } else {
+            for op in gate_operations(&name, &targets).map_err(error)? {
+                let last = match &op {
+                    SparseOperation::Rotations(terms) => {
+                        terms.iter().flat_map(|(p, _)| p).map(|(q, _)| *q).max()
+                    }
+                    SparseOperation::Measurement { basis, .. } => {
+                        basis.iter().map(|(q, _)| *q).max()
+                    }
+                };
+                *qubits = (*qubits).max(last.map_or(0, |qubit| qubit + 1));
+                instructions.push(Instruction::Operation(op));
+            }
+        }
+    }
+    match opened {
+        Some(line) => Err(StimError {
+            line,
+            message: "The block is not closed".to_string(),
+        }),
+        None => Ok(instructions),
+    }
+}
+
+/// The rotations of a single-qubit Clifford gate in time order, or `None` for a Pauli gate
+fn single_qubit_gate(name: &str) -> Option<Option<Vec<(Pauli, i32)>>> {
+    use Pauli::{X, Y, Z};
+    let rotations = match name {
+        "I" | "X" | "Y" | "Z" => return Some(None),
+        // H = S SQRT_X S up to a global phase
+        "H" => vec![(Z, 1), (X, 1), (Z, 1)],
+        "S" | "SQRT_Z" => vec![(Z, 1)],
+        "S_DAG" | "SQRT_Z_DAG" => vec![(Z, -1)],
+        "SQRT_X" => vec![(X, 1)],
+        "SQRT_X_DAG" => vec![(X, -1)],
+        "SQRT_Y" => vec![(Y, 1)],
+        "SQRT_Y_DAG" => vec![(Y, -1)],
+        _ => return None,
+    };
+    Some(Some(rotations))
+}
+
+/// The commuting rotations of a controlled rotation about `target` by an angle θ, in units of θ/2,
+/// i.e., exp(-iθ/4 (1 - Z_c) P_t) up to a global phase.
+/// With `phase`, the control is also rotated, such that θ = π is a controlled Pauli
+/// and the rotations are in units of [`S_ANGLE`].
+pub(crate) fn controlled_rotation(
+    control: usize,
+    target: usize,
+    p: Pauli,
+    phase: bool,
+) -> Vec<Term> {
+    let mut terms = Vec::with_capacity(3);
+    if phase {
+        terms.push((vec![(control, Pauli::Z)], 1));
+    }
+    terms.push((vec![(target, p)], 1));
+    terms.push((vec![(control, Pauli::Z), (target, p)], -1));
+    terms
+}
+
+/// The commuting rotations of a SWAP gate in units of [`S_ANGLE`],
+/// since SWAP is exp(iπ/4 (XX + YY + ZZ)) up to a global phase
+pub(crate) fn swap(first: usize, second: usize) -> Vec<Term> {
+    [Pauli::X, Pauli::Y, Pauli::Z]
+        .into_iter()
+        .map(|p| (vec![(first, p), (second, p)], -1))
+        .collect()
+}
+
+/// The commuting rotations of a two-qubit Clifford gate
+fn two_qubit_gate(name: &str, first: usize, second: usize) -> Option<Vec<Term>> {
+    use Pauli::{X, Y, Z};
+    match name {
+        "CX" | "CNOT" | "ZCX" => Some(controlled_rotation(first, second, X, true)),
+        "CY" | "ZCY" => Some(controlled_rotation(first, second, Y, true)),
+        "CZ" | "ZCZ" => Some(controlled_rotation(first, second, Z, true)),
+        "SWAP" => Some(swap(first, second)),
+        _ => None,
+    }
+}
+
+/// The basis of a measurement or reset instruction
+fn measurement_basis(name: &str) -> Option<Pauli> {
+    match name {
+        "M" | "MZ" | "MR" | "MRZ" | "R" | "RZ" => Some(Pauli::Z),
+        "MX" | "MRX" | "RX" => Some(Pauli::X),
+        "MY" | "MRY" | "RY" => Some(Pauli::Y),
+        _ => None,
+    }
+}
+
+/// Instructions that do not act on the qubits
+fn is_skipped(name: &str) -> bool {
+    matches!(
+        name,
+        "TICK"
+            | "DETECTOR"
+            | "OBSERVABLE_INCLUDE"
+            | "QUBIT_COORDS"
+            | "SHIFT_COORDS"
+            | "MPAD"
+            | "X_ERROR"
+            | "Y_ERROR"
+            | "Z_ERROR"
+            | "I_ERROR"
+            | "II_ERROR"
+            | "DEPOLARIZE1"
+            | "DEPOLARIZE2"
+            | "PAULI_CHANNEL_1"
+            | "PAULI_CHANNEL_2"
+            | "E"
+            | "CORRELATED_ERROR"
+            | "ELSE_CORRELATED_ERROR"
+            | "HERALDED_ERASE"
+            | "HERALDED_PAULI_CHANNEL_1"
+    )
+}
+
+/// A qubit target, which may be inverted, or `None` for a measurement record or sweep bit
+fn qubit_target(target: &str) -> Result<Option<(usize, bool)>, String> {
+    if target.starts_with("rec[") || target.starts_with("sweep[") {
+        return Ok(None);
+    }
+    let (target, inverted) = match target.strip_prefix('!') {
+        Some(target) => (target, true),
+        None => (target, false),
+    };
+    let qubit = parse_qubit(target)?;
+    Ok(Some((qubit, inverted)))
+}
+
+/// A qubit index below [`MAX_QUBITS`]
+fn parse_qubit(qubit: &str) -> Result<usize, String> {
+    match qubit.parse() {
+        Ok(qubit) if qubit < MAX_QUBITS => Ok(qubit),
+        Ok(_) => Err(format!(
+            "Qubit {qubit} is beyond the {MAX_QUBITS} supported qubits"
+        )),
+        Err(_) => Err(format!("Invalid qubit target {qubit}")),
+    }
+}
+
+/// A Pauli product target of `MPP`, e.g., `!X0*Z1`
+fn pauli_product(target: &str) -> Result<(SparsePauli, bool), String> {
+    let (target, inverted) = match target.strip_prefix('!') {
+        Some(target) => (target, true),
+        None => (target, false),
+    };
+    let mut paulis: SparsePauli = vec![];
+    for factor in target.split('*') {
+        let mut chars = factor.chars();
+        let p = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('X') => Pauli::X,
+            Some('Y') => Pauli::Y,
+            Some('Z') => Pauli::Z,
+            _ => return Err(format!("Invalid Pauli product {target}")),
+        };
+        let qubit = parse_qubit(chars.as_str())
+            .map_err(|e| format!("Invalid Pauli product {target}: {e}"))?;
+        if paulis.iter().any(|(q, _)| *q == qubit) {
+            return Err(format!("The Pauli product {target} repeats qubit {qubit}"));
+        }
+        paulis.push((qubit, p));
+    }
+    Ok((paulis, inverted))
+}
+
+/// Split the gates of an instruction into groups that act on disjoint qubits
+fn disjoint_groups<T>(gates: Vec<(Vec<usize>, T)>) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = vec![];
+    let mut used: Vec<usize> = vec![];
+    for (qubits, gate) in gates {
+        if groups.is_empty() || qubits.iter().any(|q| used.contains(q)) {
+            groups.push(vec![]);
+            used.clear();
+        }
+        used.extend(qubits);
+        groups.last_mut().unwrap().push(gate);
+    }
+    groups
+}
+
+/// The operations of a gate with the given targets
+fn gate_operations(name: &str, targets: &[&str]) -> Result<Vec<SparseOperation>, String> {
+    let mut ops = vec![];
+    if is_skipped(name) {
+        return Ok(ops);
+    }
+
+    if name == "MPP" {
+        for target in targets {
+            let (basis, flip_result) = pauli_product(target)?;
+            ops.push(SparseOperation::Measurement { basis, flip_result });
+        }
+        return Ok(ops);
+    }
+
+    let qubits = targets
+        .iter()
+        .map(|target| qubit_target(target))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(p) = measurement_basis(name) {
+        for (qubit, inverted) in qubits.into_iter().flatten() {
+            ops.push(SparseOperation::Measurement {
+                basis: vec![(qubit, p)],
+                flip_result: inverted,
+            });
+        }
+    } else if let Some(rotations) = single_qubit_gate(name) {
+        let Some(rotations) = rotations else {
+            return Ok(ops);
+        };
+        let gates = qubits
+            .into_iter()
+            .flatten()
+            .map(|(qubit, _)| (vec![qubit], qubit))
+            .collect();
+        for group in disjoint_groups(gates) {
+            for (p, sign) in &rotations {
+                let terms = group.iter().map(|q| (vec![(*q, *p)], *sign)).collect();
+                ops.push(SparseOperation::Rotations(terms));
+            }
+        }
+    } else if two_qubit_gate(name, 0, 1).is_some() {
+        if qubits.len() % 2 != 0 {
+            return Err(format!("{name} should have an even number of targets"));
+        }
+        let gates = qubits
+            .chunks_exact(2)
+            // Classically controlled Paulis only change the Pauli frame
+            .filter_map(|pair| match pair {
+                [Some((first, _)), Some((second, _))] => Some((*first, *second)),
+                _ => None,
+            })
+            .map(|(first, second)| {
+                if first == second {
+                    Err(format!("The operands of {name} should be distinct qubits"))
+                } else {
+                    Ok((vec![first, second], (first, second)))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        for group in disjoint_groups(gates) {
+            let terms = group
+                .into_iter()
+                .flat_map(|(first, second)| two_qubit_gate(name, first, second).unwrap())
+                .collect();
+            ops.push(SparseOperation::Rotations(terms));
+        }
+    } else {
+        return Err(format!("Unsupported Stim instruction {name}"));
+    }
+    Ok(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::AnglePrecision;
+
+    use Pauli::{I, X, Y, Z};
+
+    fn angle(sign: i32) -> AnglePrecision {
+        S_ANGLE * i128::from(sign)
+    }
+
+    #[test]
+    fn memory_experiment() -> Result<(), StimError> {
+        let circuit = "
+            # A repetition code
+            R 0 1 2
+            REPEAT 2 {
+                CX 0 1
+                CX 2 1
+                X_ERROR(0.01) 1
+                MR 1
+                DETECTOR(1, 0) rec[-1]
+                TICK
+            }
+            M 0 !2
+        ";
+        let ops = parse(circuit)?;
+        // Three resets, two rounds of two CX layers and a measurement, and two final measurements
+        assert_eq!(3 + 2 * 3 + 2, ops.len());
+        assert!(ops.iter().all(|op| op.basis().len() == 3));
+        assert_eq!(
+            PbcOperation::MultiRotation {
+                terms: vec![
+                    (vec![Z, I, I], angle(1)),
+                    (vec![I, X, I], angle(1)),
+                    (vec![Z, X, I], angle(-1)),
+                ]
+            },
+            ops[3]
+        );
+        assert_eq!(
+            PbcOperation::Measurement {
+                basis: vec![I, I, Z],
+                flip_result: true
+            },
+            ops[10]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn layers_act_on_disjoint_qubits() -> Result<(), StimError> {
+        // The second CZ overlaps with the first, so it is a separate layer
+        let ops = parse("CZ 0 1 2 3 1 2\nH 0 1")?;
+        assert_eq!(2 + 3, ops.len());
+        let PbcOperation::MultiRotation { terms } = &ops[0] else {
+            panic!("The first layer should be a multi-rotation");
+        };
+        assert_eq!(6, terms.len());
+        // H on two qubits is three layers of two rotations
+        let PbcOperation::MultiRotation { terms } = &ops[3] else {
+            panic!("H should be a multi-rotation");
+        };
+        assert_eq!((vec![X, I, I, I], angle(1)), terms[0]);
+        assert_eq!(2, terms.len());
+        Ok(())
+    }
+
+    #[test]
+    fn pauli_product_measurements() -> Result<(), StimError> {
+        let ops = parse("MPP X0*Y2 !Z1\nS_DAG 1")?;
+        assert_eq!(
+            vec![
+                PbcOperation::Measurement {
+                    basis: vec![X, I, Y],
+                    flip_result: false
+                },
+                PbcOperation::Measurement {
+                    basis: vec![I, Z, I],
+                    flip_result: true
+                },
+                PbcOperation::Rotation {
+                    basis: vec![I, Z, I],
+                    angle: angle(-1),
+                    accuracy: None,
+                },
+            ],
+            ops
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn classical_control_and_paulis_are_skipped() -> Result<(), StimError> {
+        let ops = parse("M 0\nCX rec[-1] 1\nX 0 1\nSWAP 0 1")?;
+        assert_eq!(2, ops.len());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_circuits() {
+        let line = |circuit| parse(circuit).unwrap_err().line;
+        assert_eq!(2, line("H 0\nT 0"));
+        assert_eq!(1, line("CX 0 1 2"));
+        assert_eq!(1, line("REPEAT 2 {\nH 0"));
+        assert_eq!(1, line("}"));
+        assert_eq!(1, line("MPP X0*Z0"));
+        assert_eq!(2, line("H 0\nCX 1 1"));
+        assert_eq!(1, line("H 5000000000"));
+        assert_eq!(1, line("MPP X0*Z5000000000"));
+    }
+
+    #[test]
+    fn repetitions_are_not_unrolled() -> Result<(), StimError> {
+        let mut ops =
+            parse_lazy("REPEAT 1000000000 {\n    H 0\n    REPEAT 1000000000 {\n    }\n}")?;
+        assert_eq!(1, ops.qubits());
+        let first: Vec<_> = ops.by_ref().take(6).collect();
+        assert_eq!(first[..3], first[3..]);
+        assert_eq!(Some(&first[0]), ops.next().as_ref());
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! T counts of small-angle synthesis over a range of accuracies,
+//! to choose an accuracy from the behavior of the synthesizer rather than from asymptotic bounds.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{analysis, language::AnglePrecision, small_angle};
+
+/// The T count of synthesizing a rotation about `angle` up to `accuracy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweepPoint {
+    pub angle: AnglePrecision,
+    pub accuracy: AnglePrecision,
+    pub t_count: usize,
+    /// The lower bound of [`analysis::min_t_count`]
+    pub min_t_count: usize,
+}
+
+/// Accuracies from `from` down to `to`, with `per_decade` accuracies per factor of 10
+pub fn accuracies(from: f64, to: f64, per_decade: usize) -> Vec<AnglePrecision> {
+    assert!(
+        0.0 < to && to <= from && from <= 0.1,
+        "The accuracies should satisfy 0 < {to} <= {from} <= 0.1"
+    );
+    assert!(
+        per_decade > 0,
+        "There should be at least one accuracy per decade"
+    );
+    let decades = from.log10() - to.log10();
+    let steps = (decades * per_decade as f64 + 1e-9).floor() as usize;
+    (0..=steps)
+        .map(|step| from * 10f64.powf(-(step as f64) / per_decade as f64))
+        .map(AnglePrecision::from_num)
+        .collect()
+}
+
+/// Synthesized T counts that persist between sweeps as JSON lines of [`SweepPoint`]s
+#[derive(Debug, Clone, Default)]
+pub struct SweepCache {
+    points: HashMap<(AnglePrecision, AnglePrecision), SweepPoint>,
+    /// Number of points that were synthesized rather than read from the cache
+    synthesized: usize,
+}
+
+impl SweepCache {
+    pub fn read(reader: impl BufRead) -> Result<Self, serde_json::Error> {
+        let mut cache = Self::default();
+        for line in reader.lines() {
+            let line = line.map_err(serde_json::Error::io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let point: SweepPoint = serde_json::from_str(&line)?;
+            cache.points.insert((point.angle, point.accuracy), point);
+        }
+        Ok(cache)
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut points: Vec<_> = self.points.values().collect();
+        points.sort_by_key(|point| (point.angle, point.accuracy));
+        for point in points {
+            serde_json::to_writer(&mut writer, point)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// The T count of a rotation about `angle` up to `accuracy`, synthesizing it if it is not cached
+    pub fn point(&mut self, angle: AnglePrecision, accuracy: AnglePrecision) -> SweepPoint {
+        *self.points.entry((angle, accuracy)).or_insert_with(|| {
+            self.synthesized += 1;
+            let (rots, _cliffords) = small_angle::synthesize_angle(angle, accuracy);
+            SweepPoint {
+                angle,
+                accuracy,
+                t_count: rots.len(),
+                min_t_count: analysis::min_t_count(angle, accuracy),
+            }
+        })
+    }
+
+    pub fn synthesized(&self) -> usize {
+        self.synthesized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::small_angle::T_ANGLE;
+
+    use super::*;
+
+    #[test]
+    fn accuracies_per_decade() {
+        let accuracies: Vec<f64> = accuracies(1e-2, 1e-4, 2)
+            .into_iter()
+            .map(|accuracy| accuracy.to_num())
+            .collect();
+        assert_eq!(5, accuracies.len());
+        for (accuracy, expected) in
+            accuracies
+                .iter()
+                .zip([1e-2, 10f64.powf(-2.5), 1e-3, 10f64.powf(-3.5), 1e-4])
+        {
+            assert!((accuracy / expected - 1.0).abs() < 1e-9);
+        }
+        assert_eq!(1, super::accuracies(1e-3, 1e-3, 3).len());
+    }
+
+    #[test]
+    fn cached_points() -> Result<(), serde_json::Error> {
+        let angle = AnglePrecision::lit("0.1");
+        let accuracy = AnglePrecision::lit("1e-3");
+        let cached = SweepPoint {
+            angle,
+            accuracy,
+            t_count: 30,
+            min_t_count: analysis::min_t_count(angle, accuracy),
+        };
+        let mut cache = SweepCache::read(serde_json::to_string(&cached)?.as_bytes())?;
+        assert_eq!(cached, cache.point(angle, accuracy));
+        // The T gate is synthesized exactly
+        assert_eq!(1, cache.point(T_ANGLE, accuracy).t_count);
+        assert_eq!(1, cache.synthesized());
+
+        let mut written = vec![];
+        cache.write(&mut written).map_err(serde_json::Error::io)?;
+        let cache = SweepCache::read(written.as_slice())?;
+        assert_eq!(2, cache.points.len());
+        Ok(())
+    }
+}
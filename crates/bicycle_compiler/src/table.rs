@@ -0,0 +1,199 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Building, loading, and writing the Clifford synthesis table
+
+use std::{
+    error,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use bicycle_cliffords::{
+    CompleteMeasurementTable, MeasurementTable, MeasurementTableBuilder, MinimalMeasurementTable,
+    native_measurement::NativeMeasurement,
+};
+use log::info;
+
+use crate::cli::{Cli, Commands, TableCostChoice};
+
+/// Generate the measurement table, from cache if given or otherwise from scratch,
+/// and apply `--no-automorphisms` and `--flatten-chains`
+fn complete_table(cli: &Cli) -> Result<CompleteMeasurementTable, Box<dyn error::Error>> {
+    let measurement_table = if let Some(cache_str) = &cli.table.measurement_table {
+        let cache_path = Path::new(&cache_str);
+        match cli.table.table_qubits {
+            Some(qubits) => {
+                // The pivot and the first data qubits
+                let support = (1 << (qubits + 1)) - 1;
+                let table = bicycle_compiler::deserialize_shards(cache_path, support)?;
+                info!(
+                    "Loaded {} of {} shards of the measurement table",
+                    CompleteMeasurementTable::shards_with_support(support).count(),
+                    CompleteMeasurementTable::SHARDS
+                );
+                table
+            }
+            None => {
+                let table = bicycle_compiler::deserialize_table(cache_path)?;
+                if cli.table.verify_table && table.is_mapped() {
+                    table.verify()?;
+                    info!("Verified the chains of the measurement table");
+                }
+                table
+            }
+        }
+    } else {
+        let mut builder = cli.table_builder(cli.table.natives.native_measurements())?;
+        builder.build();
+        cli.complete(builder)?
+    };
+    let mut measurement_table = if cli.table.no_automorphisms {
+        let mut builder = cli.table_builder(NativeMeasurement::base_measurements().collect())?;
+        builder.build();
+        let inflation = builder.cost_inflation(&measurement_table);
+        info!(
+            "Without automorphisms {} of {} measurements can be implemented, at {:.3} times their cost with automorphisms",
+            inflation.found,
+            inflation.paulis,
+            inflation.ratio()
+        );
+        builder.complete_with(&measurement_table)
+    } else {
+        measurement_table
+    };
+    if cli.table.flatten_chains {
+        measurement_table.flatten_chains();
+    }
+    Ok(measurement_table)
+}
+
+/// Load the measurement table of `--measurement-table`, or build a complete table
+pub fn load_table(cli: &Cli) -> Result<Box<dyn MeasurementTable>, Box<dyn error::Error>> {
+    if cli.table.measurement_table.is_some() {
+        if !cli.table.natives.is_empty() {
+            return Err("Native measurements cannot be restricted for a cached measurement table, generate a table with the restriction instead".into());
+        }
+        if cli.table.table_cost != TableCostChoice::Count {
+            return Err("The cost of a cached measurement table cannot be changed, generate a table with the cost instead".into());
+        }
+    }
+    // A minimal table replaces the complete table, see `generate --minimal`
+    let minimal_table = match &cli.table.measurement_table {
+        Some(cache_str) if cli.table.table_qubits.is_none() => {
+            bicycle_compiler::deserialize_minimal_table(Path::new(cache_str))?
+        }
+        _ => None,
+    };
+    Ok(match minimal_table {
+        Some(table) => {
+            if cli.table.no_automorphisms || cli.table.flatten_chains {
+                return Err("A minimal measurement table cannot be combined with `--no-automorphisms` or `--flatten-chains`, load a complete table instead".into());
+            }
+            Box::new(table)
+        }
+        None => Box::new(complete_table(cli)?),
+    })
+}
+
+/// Generate a measurement table and write it to the file of `generate`
+pub fn generate(cli: &Cli) -> Result<(), Box<dyn error::Error>> {
+    let Some(Commands::Generate {
+        measurement_table: cache_str,
+        sharded,
+        raw,
+        minimal,
+        checkpoint,
+        checkpoint_every,
+        resume,
+    }) = &cli.commands
+    else {
+        unreachable!("Only called for the generate command");
+    };
+    info!("Generating measurement table.");
+    let cache_path = Path::new(&cache_str);
+
+    // Ensure that we can write a file in the desired output directory.  To do this we
+    // write and delte an empty file in the parent directory of the full path of the
+    // (output) cache file.  We do this in order to fail early rather than computing the
+    // measurement table, only to find at the end that we cannot write the result.
+    match cache_path.parent() {
+        Some(cache_dir) => {
+            let temp_filename = "dummy_file_check";
+            let mut temp_file_path = PathBuf::from(cache_dir);
+            temp_file_path.push(temp_filename);
+            match File::create(&temp_file_path) {
+                Ok(_) => {
+                    // Successfully created dummy file. Remove file.
+                    std::fs::remove_file(temp_file_path)?;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Cannot create measurement_table output file in the target directory: {e}"
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            eprintln!("No parent directory found for {cache_str}");
+            std::process::exit(1);
+        }
+    }
+
+    // Create a builder, or continue an interrupted one, and build the measurement table.
+    let builder = match resume {
+        Some(path) => MeasurementTableBuilder::resume(path)?,
+        None => cli.table_builder(cli.table.natives.native_measurements())?,
+    };
+    let mut builder = match checkpoint.as_ref().or(resume.as_ref()) {
+        Some(path) => builder.checkpoint(path, *checkpoint_every),
+        None => builder,
+    };
+    builder.build();
+    let measurement_table = cli.complete(builder)?;
+    bicycle_common::alloc::report_memory("measurement table");
+
+    // Serialize the measurement table and write to the cache file.
+    let serialized = if *sharded {
+        let mut serialized = vec![];
+        bicycle_compiler::serialize_shards(&measurement_table, &mut serialized)?;
+        serialized
+    } else if *raw {
+        let mut serialized = vec![];
+        measurement_table.write_raw(&mut serialized)?;
+        serialized
+    } else if *minimal {
+        let mut serialized = vec![];
+        MinimalMeasurementTable::from(&measurement_table).write(&mut serialized)?;
+        serialized
+    } else {
+        bitcode::serialize(&measurement_table).expect("The table should be serializable")
+    };
+    info!("Done generating measurement table, writing.");
+    let f = File::create(cache_path);
+    match f {
+        Ok(mut f) => {
+            f.write_all(&serialized)
+                .expect("The serialized table should be writable to the cache");
+        }
+        Err(e) => {
+            eprintln!("Cannot create  measurement_table output file in the target directory: {e}");
+            std::process::exit(1);
+        }
+    }
+    info!("Done writing measurement table, exiting.");
+    Ok(())
+}
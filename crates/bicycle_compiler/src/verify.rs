@@ -0,0 +1,1120 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Check that compiled instructions implement the operations of the program, see `bicycle_compiler --verify`.
+//!
+//! The instructions are simulated on a stabilizer tableau of the logical qubits of all blocks,
+//! numbered as in [`crate::export`], whose [`LogicalWalker`] tracks the automorphisms of the blocks
+//! and gives the logical Pauli that each measurement acts on.
+//! Each logical data qubit of the program starts in a Bell pair with a reference qubit that no instruction touches,
+//! such that every change to the state of the program shows in the tableau.
+//! The outcomes of the measurements are not known, so the compiled program is checked up to Pauli corrections.
+//!
+//! T and S gates on the pivots, and joint measurements with a stored CCZ state, are not simulated.
+//! Instead, a run of them on one pivot, e.g., a synthesized small angle, is checked to act on the pivot
+//! while it carries the Pauli of a rotation of the operation and nothing else of the program.
+//! Every rotation whose angle is not a multiple of the S angle must be applied by at least one run.
+//!
+//! The runs are then simulated as the identity, which leaves the Clifford part of each injection:
+//! a rotation by π/4 about the joint Pauli of the rotation, in the direction of its angle,
+//! as if the magic block was in a |Y> state, up to the Clifford corrections that the compiler drops.
+//! Afterwards, the data qubits must hold the program as if only these rotations and the measurements of the program were applied,
+//! which a second tableau of the data and reference qubits tracks with the signs of the operations.
+//! A compiled rotation that rotates each block about its own part of the Pauli instead does not hold the program.
+//! This catches, e.g., instructions that measure the program and basis rotations that are not undone.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display},
+};
+
+use bicycle_cliffords::{MeasurementTable, PauliString, measurement::CodeMeasurement};
+use bicycle_common::{BicycleISA, Pauli, TwoBases};
+
+use crate::{
+    Architecture,
+    export::{LogicalGate, LogicalPauli, LogicalWalker, pivot_bases},
+    language::{AnglePrecision, PbcOperation, ccz_rotations},
+    operation::Operation,
+    small_angle::S_ANGLE,
+};
+
+/// Number of logical qubits of each block, i.e., the data qubits and the pivot
+const QUBITS: usize = PauliString::NR_DATA_QUBITS + 1;
+
+/// The Pauli of a rotation of an operation, whether a run must apply it,
+/// and the quarter turns of its Clifford part, which are 3 for a negative angle
+type Term = (Vec<Pauli>, bool, u8);
+
+/// Compiled instructions that do not implement their operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The index of the operation among the checked operations
+    pub operation: usize,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation {}: {}", self.operation, self.message)
+    }
+}
+
+impl Error for VerifyError {}
+
+/// A Pauli on the qubits of the tableau with its sign
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    x: Vec<u64>,
+    z: Vec<u64>,
+    negative: bool,
+}
+
+impl Row {
+    fn identity(words: usize) -> Self {
+        Self {
+            x: vec![0; words],
+            z: vec![0; words],
+            negative: false,
+        }
+    }
+
+    fn set(&mut self, qubit: usize, p: Pauli) {
+        let (word, bit) = (qubit / 64, 1 << (qubit % 64));
+        if matches!(p, Pauli::X | Pauli::Y) {
+            self.x[word] ^= bit;
+        }
+        if matches!(p, Pauli::Z | Pauli::Y) {
+            self.z[word] ^= bit;
+        }
+    }
+
+    fn get(&self, qubit: usize) -> Pauli {
+        let (word, bit) = (qubit / 64, qubit % 64);
+        match (self.x[word] >> bit & 1, self.z[word] >> bit & 1) {
+            (0, 0) => Pauli::I,
+            (1, 0) => Pauli::X,
+            (0, 1) => Pauli::Z,
+            _ => Pauli::Y,
+        }
+    }
+
+    fn anticommutes(&self, other: &Row) -> bool {
+        let ones: u32 = (0..self.x.len())
+            .map(|i| ((self.x[i] & other.z[i]) ^ (self.z[i] & other.x[i])).count_ones())
+            .sum();
+        ones % 2 == 1
+    }
+
+    /// Multiply by `other` from the right, and return whether the product has a factor of i left,
+    /// which is the case if the rows anticommute
+    fn multiply(&mut self, other: &Row) -> bool {
+        // XY = iZ, YZ = iX, and ZX = iY, and the other order has a factor of -i
+        let mut phase = 2 * u32::from(self.negative ^ other.negative);
+        for i in 0..self.x.len() {
+            let (x1, z1, x2, z2) = (self.x[i], self.z[i], other.x[i], other.z[i]);
+            let cyclic = (x1 & !z1 & x2 & z2) | (x1 & z1 & !x2 & z2) | (!x1 & z1 & x2 & !z2);
+            let anticyclic = (x1 & z1 & x2 & !z2) | (!x1 & z1 & x2 & z2) | (x1 & !z1 & !x2 & z2);
+            phase += cyclic.count_ones() + 3 * anticyclic.count_ones();
+            self.x[i] ^= x2;
+            self.z[i] ^= z2;
+        }
+        self.negative = phase % 4 >= 2;
+        phase % 2 == 1
+    }
+}
+
+/// The generators of a stabilizer state.
+/// The signs of the simulated instructions are not meaningful, since each measurement is taken to have the outcome +1
+#[derive(Debug, Clone, Default)]
+struct Tableau {
+    qubits: usize,
+    rows: Vec<Row>,
+}
+
+impl Tableau {
+    fn words(&self) -> usize {
+        self.qubits.div_ceil(64)
+    }
+
+    /// Add a qubit in |0> and return its index
+    fn add_qubit(&mut self) -> usize {
+        let qubit = self.qubits;
+        self.qubits += 1;
+        let words = self.words();
+        for row in self.rows.iter_mut() {
+            row.x.resize(words, 0);
+            row.z.resize(words, 0);
+        }
+        let mut z = Row::identity(words);
+        z.set(qubit, Pauli::Z);
+        self.rows.push(z);
+        qubit
+    }
+
+    fn pauli(&self, paulis: impl IntoIterator<Item = (usize, Pauli)>) -> Row {
+        let mut row = Row::identity(self.words());
+        for (qubit, p) in paulis {
+            row.set(qubit, p);
+        }
+        row
+    }
+
+    /// Measure a Pauli, where the outcome is taken to be +1
+    fn measure(&mut self, p: Row) {
+        let mut anticommuting = (0..self.rows.len()).filter(|i| self.rows[*i].anticommutes(&p));
+        let Some(first) = anticommuting.next() else {
+            return;
+        };
+        let pivot = self.rows[first].clone();
+        for i in anticommuting.collect::<Vec<_>>() {
+            self.rows[i].multiply(&pivot);
+        }
+        self.rows[first] = p;
+    }
+
+    /// Apply the rotation exp(-ikπ/4 P) for `quarter_turns` k, which maps an anticommuting stabilizer Q to Q(iP)^k.
+    /// Either direction is the same up to Pauli corrections.
+    fn rotate(&mut self, p: &Row, quarter_turns: u8) {
+        let quarter_turns = quarter_turns % 4;
+        for row in self.rows.iter_mut() {
+            if quarter_turns == 0 || !row.anticommutes(p) {
+                continue;
+            }
+            if quarter_turns == 2 {
+                row.negative ^= true;
+                continue;
+            }
+            // Q(iP) = iQP, which is i times a Hermitian Pauli up to a factor of i, and Q(iP)^3 = -iQP
+            let odd = row.multiply(p);
+            debug_assert!(odd, "The product of anticommuting Paulis has a factor of i");
+            row.negative ^= quarter_turns == 1;
+        }
+    }
+
+    fn cx(&mut self, control: usize, target: usize) {
+        let (cw, cb) = (control / 64, 1 << (control % 64));
+        let (tw, tb) = (target / 64, 1 << (target % 64));
+        for row in self.rows.iter_mut() {
+            if row.x[cw] & cb != 0 {
+                row.x[tw] ^= tb;
+            }
+            if row.z[tw] & tb != 0 {
+                row.z[cw] ^= cb;
+            }
+        }
+    }
+
+    /// Swap the qubit whose Paulis are `x` and `z`, e.g., a logical qubit of a block after automorphisms,
+    /// with the qubit `other`
+    fn swap(&mut self, x: &Row, z: &Row, other: usize) {
+        let other_x = self.pauli([(other, Pauli::X)]);
+        let other_z = self.pauli([(other, Pauli::Z)]);
+        for row in self.rows.iter_mut() {
+            let components = [
+                row.anticommutes(z),
+                row.anticommutes(x),
+                row.anticommutes(&other_z),
+                row.anticommutes(&other_x),
+            ];
+            for (present, pauli) in components.into_iter().zip([x, z, &other_x, &other_z]) {
+                if present {
+                    row.multiply(pauli);
+                }
+            }
+            for (present, pauli) in components.into_iter().zip([&other_x, &other_z, x, z]) {
+                if present {
+                    row.multiply(pauli);
+                }
+            }
+        }
+    }
+
+    /// Remove the last `count` qubits, which must not be entangled with the others
+    fn remove_last(&mut self, count: usize) {
+        let qubits = self.qubits - count;
+        let removed = |row: &Row, bit: usize| {
+            let (paulis, qubit) = if bit < count {
+                (&row.x, qubits + bit)
+            } else {
+                (&row.z, qubits + bit - count)
+            };
+            paulis[qubit / 64] & (1 << (qubit % 64)) != 0
+        };
+        // Bring the stabilizers on the removed qubits to the front, such that the others do not act on them
+        let mut rank = 0;
+        for bit in 0..2 * count {
+            let Some(found) = (rank..self.rows.len()).find(|i| removed(&self.rows[*i], bit)) else {
+                continue;
+            };
+            self.rows.swap(rank, found);
+            let pivot = self.rows[rank].clone();
+            for i in 0..self.rows.len() {
+                if i != rank && removed(&self.rows[i], bit) {
+                    self.rows[i].multiply(&pivot);
+                }
+            }
+            rank += 1;
+        }
+        assert_eq!(count, rank, "The removed qubits should be in a pure state");
+        self.rows.drain(..rank);
+        self.qubits = qubits;
+        let words = self.words();
+        for row in self.rows.iter_mut() {
+            row.x.truncate(words);
+            row.z.truncate(words);
+        }
+    }
+
+    /// Whether `p` is a stabilizer up to its sign, since the state is pure
+    fn stabilizes(&self, p: &Row) -> bool {
+        self.rows.iter().all(|row| !row.anticommutes(p))
+    }
+
+    /// Number of independent stabilizers that act only on the qubits of `mask`
+    fn stabilizers_on(&self, mask: &[u64]) -> usize {
+        // The stabilizers on the mask are the kernel of the restriction to the other qubits
+        let mut restricted: Vec<Vec<u64>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                (0..mask.len())
+                    .map(|i| row.x[i] & !mask[i])
+                    .chain((0..mask.len()).map(|i| row.z[i] & !mask[i]))
+                    .collect()
+            })
+            .collect();
+        let mut rank = 0;
+        for bit in 0..2 * 64 * mask.len() {
+            let (word, bit) = (bit / 64, 1 << (bit % 64));
+            let Some(found) = (rank..restricted.len()).find(|i| restricted[*i][word] & bit != 0)
+            else {
+                continue;
+            };
+            restricted.swap(rank, found);
+            let pivot = restricted[rank].clone();
+            for row in restricted.iter_mut().skip(rank + 1) {
+                if row[word] & bit != 0 {
+                    row.iter_mut().zip(&pivot).for_each(|(a, b)| *a ^= b);
+                }
+            }
+            rank += 1;
+        }
+        self.rows.len() - rank
+    }
+}
+
+/// Instructions on a pivot that are not simulated, e.g., the T gates of a synthesized angle.
+/// The pivot is swapped with `before` when the run starts, and `before` is in a Bell pair with `after` before that,
+/// such that `before` holds what the pivot carries at the start of the run
+/// and `after` is measured like the pivot after the run.
+struct Run {
+    block: usize,
+    /// The index of the first instruction of the run in the compiled operation
+    start: usize,
+    before: usize,
+    after: usize,
+}
+
+/// Simulates compiled operations and checks them against the operations of the program
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    tableau: Tableau,
+    /// The tableau qubit of the pivot of each block
+    blocks: Vec<usize>,
+    /// Tracks the automorphisms of the blocks
+    walker: LogicalWalker,
+    /// The reference qubit of each logical data qubit of the program
+    references: Vec<usize>,
+    /// The expected state of the program, where qubit `2 * i` is its data qubit `i` and qubit `2 * i + 1` is the reference
+    program: Tableau,
+    data_blocks: usize,
+    storage_blocks: usize,
+    checked: usize,
+}
+
+impl Verifier {
+    /// Start with the blocks of `architecture`, where the data qubits hold the program
+    pub fn new(code: CodeMeasurement, architecture: &dyn Architecture) -> Self {
+        let mut verifier = Self {
+            tableau: Tableau::default(),
+            blocks: vec![],
+            walker: LogicalWalker::new(code, architecture.total_blocks()),
+            references: vec![],
+            program: Tableau::default(),
+            data_blocks: architecture.data_blocks(),
+            storage_blocks: architecture.total_blocks() - architecture.data_blocks(),
+            checked: 0,
+        };
+        for block in 0..architecture.total_blocks() {
+            verifier.add_block(block < architecture.data_blocks());
+        }
+        verifier
+    }
+
+    /// Number of operations that were checked
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    fn add_block(&mut self, data: bool) {
+        let pivot = self.tableau.add_qubit();
+        self.blocks.push(pivot);
+        for _ in 1..QUBITS {
+            self.tableau.add_qubit();
+        }
+        if data {
+            for qubit in pivot + 1..pivot + QUBITS {
+                let reference = self.tableau.add_qubit();
+                let bell = self
+                    .tableau
+                    .pauli([(qubit, Pauli::X), (reference, Pauli::X)]);
+                self.tableau.measure(bell);
+                self.references.push(reference);
+
+                let data = self.program.add_qubit();
+                let reference = self.program.add_qubit();
+                let bell = self
+                    .program
+                    .pauli([(data, Pauli::X), (reference, Pauli::X)]);
+                self.program.measure(bell);
+            }
+        }
+    }
+
+    /// Check the compiled instructions of `op`, or instructions that must not change the program if there is no operation,
+    /// e.g., the final instructions that restore the cached bases of the blocks.
+    /// The blocks that an architecture adds as it grows hold new qubits of the program, so their initialization is skipped.
+    /// After an error, the simulated state is not meaningful anymore.
+    pub fn check(
+        &mut self,
+        op: Option<&PbcOperation>,
+        compiled: &[Operation],
+    ) -> Result<(), VerifyError> {
+        self.check_cached(op, compiled, &[])
+    }
+
+    /// Like [`Self::check`], but the blocks may be left in the bases of cached basis rotations,
+    /// which the instructions `restore` undo, e.g., [`crate::BlockBasisState::finish`] on a copy of the state
+    pub fn check_cached(
+        &mut self,
+        op: Option<&PbcOperation>,
+        compiled: &[Operation],
+        restore: &[Operation],
+    ) -> Result<(), VerifyError> {
+        let operation = self.checked;
+        self.checked += 1;
+        let fail = |message: String| VerifyError { operation, message };
+
+        let known = self.blocks.len();
+        let grown = compiled
+            .iter()
+            .flatten()
+            .map(|(block, _)| block + 1)
+            .max()
+            .unwrap_or(0);
+        if grown > known && self.storage_blocks > 0 {
+            return Err(fail(format!(
+                "instructions act on block {}, but the architecture has {known} blocks and does not grow",
+                grown - 1
+            )));
+        }
+        for _ in known..grown {
+            self.add_block(true);
+            self.data_blocks += 1;
+        }
+        let compiled = compiled.iter().enumerate().filter(|(_, op)| {
+            !matches!(op.as_slice(),
+                [(block, BicycleISA::CSSInitZero | BicycleISA::CSSInitPlus)] if *block >= known)
+        });
+
+        let (terms, measured) = self.expectation(op);
+        let mut runs = vec![];
+        let fused = matches!(op, Some(PbcOperation::RotateMeasure { .. }));
+        self.simulate(compiled, fused, &mut runs).map_err(fail)?;
+        let cached = (!restore.is_empty()).then(|| (self.tableau.clone(), self.walker.clone()));
+        self.simulate(restore.iter().enumerate(), false, &mut runs)
+            .map_err(fail)?;
+
+        let applied = self.finish_runs(&runs, &terms).map_err(fail)?;
+        self.remove_runs(&runs);
+        for (i, (basis, required, _)) in terms.iter().enumerate() {
+            if *required && !applied.contains(&i) {
+                return Err(fail(format!(
+                    "the rotation about {} is not applied",
+                    display_basis(basis)
+                )));
+            }
+        }
+        // Without its gates, each injection rotates by π/4 about its joint Pauli,
+        // e.g., a Clifford rotation is injected from a |Y> state without gates,
+        // but a fused rotation and measurement does not teleport the rotation and only measures
+        let unapplied = (0..terms.len()).filter(|i| !applied.contains(i));
+        let order: Vec<_> = applied.iter().copied().chain(unapplied).collect();
+        for i in order.iter().filter(|_| !fused) {
+            let (basis, _, quarter_turns) = &terms[*i];
+            let p = self.program_pauli(basis);
+            self.program.rotate(&p, *quarter_turns);
+        }
+        if let Some(basis) = measured {
+            if !self.tableau.stabilizes(&self.data(&basis)) {
+                return Err(fail(format!(
+                    "the measurement of {} is not measured",
+                    display_basis(&basis)
+                )));
+            }
+            let p = self.program_pauli(&basis);
+            self.program.measure(p);
+        }
+        if let Some(row) = self
+            .program
+            .rows
+            .iter()
+            .find(|row| !self.tableau.stabilizes(&self.embed(row)))
+        {
+            return Err(fail(format!(
+                "the data qubits do not hold the program afterwards, e.g., {} is not a stabilizer",
+                self.display_program(row)
+            )));
+        }
+
+        if let Some((tableau, walker)) = cached {
+            self.tableau = tableau;
+            self.walker = walker;
+            self.remove_runs(&runs);
+        }
+        Ok(())
+    }
+
+    /// Simulate instructions, where the gates on the pivots start runs
+    fn simulate<'a>(
+        &mut self,
+        compiled: impl IntoIterator<Item = (usize, &'a Operation)>,
+        fused: bool,
+        runs: &mut Vec<Run>,
+    ) -> Result<(), String> {
+        // The basis of the last gate of the run on each pivot, by the block and whether it is the primed pivot
+        let mut open: BTreeMap<(usize, bool), TwoBases> = BTreeMap::new();
+        for (index, op) in compiled {
+            for (block, instruction) in op {
+                let block = *block;
+                let gates = match instruction {
+                    BicycleISA::TGate(data) | BicycleISA::SGate(data) => vec![*data],
+                    BicycleISA::TGatePair(pair) => vec![pair.first, pair.second],
+                    _ => vec![],
+                };
+                if !gates.is_empty() {
+                    for data in gates {
+                        if !open.contains_key(&(block, data.primed)) {
+                            runs.push(self.start_run(block, data.primed, index));
+                        }
+                        open.insert(
+                            (block, data.primed),
+                            pivot_bases(data.get_basis(), data.primed),
+                        );
+                    }
+                    continue;
+                }
+                // The repeated measurements of a T gate for a majority vote belong to its run,
+                // see `optimize::verify_t_injections`,
+                // but a fused rotation and measurement ends the run by measuring the pivot in the basis of the gate
+                if let BicycleISA::Measure(bases) = instruction
+                    && open.get(&(block, bases.get_basis_7() != Pauli::I)) == Some(bases)
+                    && !fused
+                {
+                    continue;
+                }
+                open.retain(|(open_block, _), _| *open_block != block);
+                for gate in self.walker.instruction(block, instruction) {
+                    match gate {
+                        LogicalGate::Init(block, p) | LogicalGate::Destructive(block, p) => {
+                            self.measure_block(block, p)
+                        }
+                        LogicalGate::Measure(paulis) => {
+                            let stored = paulis
+                                .iter()
+                                .position(|logical| logical.block >= self.data_blocks);
+                            match (&paulis[..], stored) {
+                                // A stored CCZ state is consumed like a T gate on the other block
+                                ([first, second], Some(stored)) => {
+                                    let other = [second, first][stored];
+                                    let primed = other.bases.get_basis_7() != Pauli::I;
+                                    open.retain(|(open_block, _), _| *open_block != other.block);
+                                    runs.push(self.start_run(other.block, primed, index));
+                                }
+                                _ => {
+                                    let p = self.logical(&paulis);
+                                    self.tableau.measure(p);
+                                }
+                            }
+                        }
+                        LogicalGate::Cx {
+                            control,
+                            target,
+                            aligned,
+                        } => {
+                            if !aligned {
+                                return Err(format!(
+                                    "instruction {index} applies a transversal CX between blocks {control} and {target} with different automorphisms"
+                                ));
+                            }
+                            for i in 0..QUBITS {
+                                self.tableau
+                                    .cx(self.blocks[control] + i, self.blocks[target] + i);
+                            }
+                        }
+                        LogicalGate::InitT(_) => {
+                            return Err(format!(
+                                "instruction {index} prepares a T state, which is not supported"
+                            ));
+                        }
+                        LogicalGate::S { .. } | LogicalGate::T { .. } => unreachable!(),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The rotations of an operation, with whether they must be applied, and the Pauli that it measures
+    fn expectation(&self, op: Option<&PbcOperation>) -> (Vec<Term>, Option<Vec<Pauli>>) {
+        // Rotations by multiples of the S angle are Clifford and may be left to the frame
+        let quarter_turns = |angle: &AnglePrecision| if angle.is_negative() { 3 } else { 1 };
+        let term = |basis: &[Pauli], angle: &AnglePrecision| {
+            let required = (*angle / S_ANGLE).frac() != AnglePrecision::ZERO;
+            (basis.to_vec(), required, quarter_turns(angle))
+        };
+        let Some(op) = op else {
+            return (vec![], None);
+        };
+        match op {
+            PbcOperation::Measurement { basis, .. } => (vec![], Some(basis.clone())),
+            PbcOperation::Rotation { basis, angle, .. } => (vec![term(basis, angle)], None),
+            PbcOperation::RotateMeasure { basis, angle, .. } => {
+                (vec![term(basis, angle)], Some(basis.clone()))
+            }
+            PbcOperation::MultiRotation { terms } => (
+                terms
+                    .iter()
+                    .map(|(basis, angle)| term(basis, angle))
+                    .collect(),
+                None,
+            ),
+            // A stored CCZ state is consumed by injections about the controls
+            PbcOperation::Ccz { basis } => (
+                ccz_rotations(basis)
+                    .into_iter()
+                    .filter(|(term, _)| {
+                        self.storage_blocks == 0
+                            || term.iter().filter(|p| **p != Pauli::I).count() == 1
+                    })
+                    .map(|(basis, angle)| (basis, true, quarter_turns(&angle)))
+                    .collect(),
+                None,
+            ),
+            PbcOperation::Clifford {
+                basis,
+                quarter_turns,
+            } => match PbcOperation::clifford_angle(*quarter_turns) {
+                Some(angle) => (vec![term(basis, &angle)], None),
+                None => (vec![], None),
+            },
+            PbcOperation::Barrier { .. }
+            | PbcOperation::Delay { .. }
+            | PbcOperation::Permute { .. } => (vec![], None),
+        }
+    }
+
+    /// Start a run on the pivot of `block`, see [`Run`]
+    fn start_run(&mut self, block: usize, primed: bool, start: usize) -> Run {
+        let before = self.tableau.add_qubit();
+        let after = self.tableau.add_qubit();
+        let bell = self.tableau.pauli([(before, Pauli::X), (after, Pauli::X)]);
+        self.tableau.measure(bell);
+        let x = self.logical(&[self.walker.logical(block, pivot_bases(Pauli::X, primed))]);
+        let z = self.logical(&[self.walker.logical(block, pivot_bases(Pauli::Z, primed))]);
+        self.tableau.swap(&x, &z, before);
+        Run {
+            block,
+            start,
+            before,
+            after,
+        }
+    }
+
+    /// Check that each run applies a rotation of the operation,
+    /// and return the rotations that are applied in the order of their last run
+    fn finish_runs(&self, runs: &[Run], terms: &[Term]) -> Result<Vec<usize>, String> {
+        let mut applied = vec![];
+        // The Clifford part of the injections after a run rotates the Paulis that the pivot carried
+        let mut later: Vec<Row> = vec![];
+        // The Paulis of the program that the pivot carries correlate with the reference qubits,
+        // unlike the correlations with the data qubits that the Clifford part of an injection leaves
+        let references = self.reference_mask();
+        let measured = self.tableau.stabilizers_on(&references);
+        for run in runs.iter().rev() {
+            let Run {
+                block,
+                start,
+                before,
+                after,
+            } = *run;
+            let single =
+                |qubit| [Pauli::X, Pauli::Y, Pauli::Z].map(|p| self.tableau.pauli([(qubit, p)]));
+            if !single(after).iter().any(|p| self.tableau.stabilizes(p)) {
+                return Err(format!(
+                    "the pivot of block {block} is not measured after the gate of instruction {start}"
+                ));
+            }
+            let mut carried = vec![];
+            for (i, (basis, _, _)) in terms.iter().enumerate() {
+                let mut data = self.data(basis);
+                for q in later.iter().rev() {
+                    if data.anticommutes(q) {
+                        data.multiply(q);
+                    }
+                }
+                for mut p in single(before) {
+                    p.multiply(&data);
+                    if self.tableau.stabilizes(&p) && !carried.contains(&i) {
+                        carried.push(i);
+                    }
+                }
+            }
+            if carried.is_empty() {
+                return Err(format!(
+                    "instruction {start} applies a gate to block {block} whose pivot does not carry a rotation of the operation"
+                ));
+            }
+            let mut with_pivot = references.clone();
+            with_pivot.resize(with_pivot.len().max(before / 64 + 1), 0);
+            with_pivot[before / 64] |= 1 << (before % 64);
+            if self.tableau.stabilizers_on(&with_pivot) > measured + 1 {
+                return Err(format!(
+                    "the pivot of block {block} carries more than the Pauli of a rotation at instruction {start}"
+                ));
+            }
+            for i in carried {
+                if !applied.contains(&i) {
+                    applied.push(i);
+                    later.push(self.data(&terms[i].0));
+                }
+            }
+        }
+        applied.reverse();
+        Ok(applied)
+    }
+
+    /// Remove the qubits of the runs, such that their instructions act as the identity
+    fn remove_runs(&mut self, runs: &[Run]) {
+        for run in runs {
+            for p in [Pauli::X, Pauli::Z] {
+                let bell = self.tableau.pauli([(run.before, p), (run.after, p)]);
+                self.tableau.measure(bell);
+            }
+        }
+        self.tableau.remove_last(2 * runs.len());
+    }
+
+    /// The product of logical Paulis of blocks
+    fn logical(&self, paulis: &[LogicalPauli]) -> Row {
+        let paulis = paulis.iter().flat_map(|logical| {
+            let pivot = self.blocks[logical.block];
+            (0..QUBITS).map(move |i| (pivot + i, logical.pauli.get_pauli(i)))
+        });
+        self.tableau.pauli(paulis)
+    }
+
+    /// The tableau qubit of a logical data qubit of the program
+    fn data_qubit(&self, qubit: usize) -> usize {
+        let block_qubits = PauliString::NR_DATA_QUBITS;
+        self.blocks[qubit / block_qubits] + 1 + qubit % block_qubits
+    }
+
+    /// A Pauli of the program on the data qubits
+    fn data(&self, basis: &[Pauli]) -> Row {
+        self.tableau.pauli(
+            basis
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| **p != Pauli::I)
+                .map(|(qubit, p)| (self.data_qubit(qubit), *p)),
+        )
+    }
+
+    /// A Pauli of the program on the data qubits of the expected program
+    fn program_pauli(&self, basis: &[Pauli]) -> Row {
+        self.program.pauli(
+            basis
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| **p != Pauli::I)
+                .map(|(qubit, p)| (2 * qubit, *p)),
+        )
+    }
+
+    /// The Pauli on the qubits of the tableau of a Pauli on the qubits of the expected program
+    fn embed(&self, row: &Row) -> Row {
+        self.tableau.pauli((0..self.program.qubits).map(|qubit| {
+            let i = qubit / 2;
+            let target = if qubit % 2 == 0 {
+                self.data_qubit(i)
+            } else {
+                self.references[i]
+            };
+            (target, row.get(qubit))
+        }))
+    }
+
+    /// The Paulis of a stabilizer of the expected program on the data and reference qubits
+    fn display_program(&self, row: &Row) -> String {
+        let (data, references): (Vec<_>, Vec<_>) = (0..self.program.qubits)
+            .map(|qubit| row.get(qubit))
+            .enumerate()
+            .partition(|(qubit, _)| qubit % 2 == 0);
+        let paulis = |paulis: Vec<(usize, Pauli)>| -> Vec<_> {
+            paulis.into_iter().map(|(_, p)| p).collect()
+        };
+        format!(
+            "{} on the data and {} on the references",
+            display_basis(&paulis(data)),
+            display_basis(&paulis(references))
+        )
+    }
+
+    fn reference_mask(&self) -> Vec<u64> {
+        self.tableau
+            .pauli(self.references.iter().map(|qubit| (*qubit, Pauli::X)))
+            .x
+    }
+
+    /// Measure every logical qubit of a block in the basis `p`
+    fn measure_block(&mut self, block: usize, p: Pauli) {
+        for i in 0..QUBITS {
+            let p = self.tableau.pauli([(self.blocks[block] + i, p)]);
+            self.tableau.measure(p);
+        }
+    }
+}
+
+fn display_basis(basis: &[Pauli]) -> String {
+    let paulis: Vec<_> = basis.iter().map(|p| p.to_string()).collect();
+    format!("[{}]", paulis.join(","))
+}
+
+/// Compile each operation on its own and check it, e.g., to test changes to the compiler
+pub fn verify_compiled(
+    code: CodeMeasurement,
+    architecture: &dyn Architecture,
+    measurement_table: &dyn MeasurementTable,
+    ops: &[PbcOperation],
+    accuracy: AnglePrecision,
+) -> Result<(), VerifyError> {
+    let mut verifier = Verifier::new(code, architecture);
+    for op in ops {
+        let compiled = op.compile(architecture, measurement_table, accuracy);
+        verifier.check(Some(op), &compiled)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_cliffords::GROSS_MEASUREMENT;
+    use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+    use crate::{
+        BlockBasisState, CliffordFrame, PathArchitecture, SGateLowering, small_angle::T_ANGLE,
+        test::GROSS_TABLE,
+    };
+
+    use super::*;
+
+    use Pauli::{I, X, Y, Z};
+
+    const ACCURACY: AnglePrecision = AnglePrecision::lit("1e-10");
+
+    fn random_basis(rng: &mut StdRng, qubits: usize) -> Vec<Pauli> {
+        loop {
+            let basis: Vec<_> = (0..qubits)
+                .map(|_| *[I, X, Y, Z].choose(rng).unwrap())
+                .collect();
+            if basis.iter().any(|p| *p != I) {
+                return basis;
+            }
+        }
+    }
+
+    fn t_rotation(basis: Vec<Pauli>) -> PbcOperation {
+        PbcOperation::Rotation {
+            basis,
+            angle: T_ANGLE,
+            accuracy: None,
+        }
+    }
+
+    fn program(rng: &mut StdRng, qubits: usize) -> Vec<PbcOperation> {
+        let mut ops = vec![];
+        for i in 0..6 {
+            let angle = if i % 2 == 0 { T_ANGLE } else { -T_ANGLE };
+            ops.push(PbcOperation::Rotation {
+                basis: random_basis(rng, qubits),
+                angle,
+                accuracy: None,
+            });
+            ops.push(PbcOperation::Measurement {
+                basis: random_basis(rng, qubits),
+                flip_result: false,
+            });
+        }
+        let mut ccz = vec![I; qubits];
+        for control in [0, 5, qubits - 1] {
+            ccz[control] = Z;
+        }
+        ops.push(PbcOperation::Ccz { basis: ccz });
+        ops.push(PbcOperation::Clifford {
+            basis: random_basis(rng, qubits),
+            quarter_turns: 1,
+        });
+        ops.push(PbcOperation::RotateMeasure {
+            basis: random_basis(rng, qubits),
+            angle: T_ANGLE,
+            flip_result: false,
+            accuracy: None,
+        });
+        ops
+    }
+
+    #[test]
+    fn compiled_program_verifies() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let architectures = [
+            PathArchitecture::new(2),
+            PathArchitecture::new(3)
+                .with_storage_blocks(1)
+                .with_s_gates(SGateLowering::Catalysis),
+        ];
+        for architecture in architectures {
+            let ops = program(&mut rng, architecture.qubits());
+            verify_compiled(
+                GROSS_MEASUREMENT,
+                &architecture,
+                &*GROSS_TABLE,
+                &ops,
+                ACCURACY,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn clifford_frame_matches_compiled_cliffords() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let architecture = PathArchitecture::new(2).with_s_gates(SGateLowering::Catalysis);
+        let qubits = architecture.qubits();
+        let mut ops = vec![];
+        for _ in 0..4 {
+            ops.push(PbcOperation::Clifford {
+                basis: random_basis(&mut rng, qubits),
+                quarter_turns: 1,
+            });
+            ops.push(t_rotation(random_basis(&mut rng, qubits)));
+            ops.push(PbcOperation::Measurement {
+                basis: random_basis(&mut rng, qubits),
+                flip_result: false,
+            });
+        }
+        let run = |ops: &[PbcOperation]| {
+            let mut verifier = Verifier::new(GROSS_MEASUREMENT, &architecture);
+            for op in ops {
+                let compiled = op.compile(&architecture, &*GROSS_TABLE, ACCURACY);
+                verifier.check(Some(op), &compiled).unwrap();
+            }
+            verifier.program
+        };
+
+        // The frame absorbs the Cliffords and conjugates the later operations,
+        // so the program is the same once the Cliffords are applied at the end
+        let mut frame = CliffordFrame::new(SGateLowering::Catalysis);
+        let framed: Vec<_> = ops
+            .iter()
+            .filter_map(|op| frame.fold(op.clone(), ACCURACY))
+            .collect();
+        assert_eq!(8, framed.len());
+        assert_eq!(0, frame.corrections());
+        let cliffords = ops
+            .iter()
+            .filter(|op| matches!(op, PbcOperation::Clifford { .. }))
+            .cloned();
+        let compiled = run(&ops);
+        let equivalent =
+            |program: &Tableau| compiled.rows.iter().all(|row| program.stabilizes(row));
+        assert!(equivalent(&run(
+            &[framed.clone(), cliffords.collect()].concat()
+        )));
+        assert!(!equivalent(&run(&framed)));
+    }
+
+    #[test]
+    fn rotations_track_signs() {
+        let mut tableau = Tableau::default();
+        let qubit = tableau.add_qubit();
+        let x = tableau.pauli([(qubit, X)]);
+        let signed = |p: Pauli, negative: bool| Row {
+            negative,
+            ..tableau.pauli([(qubit, p)])
+        };
+        let (minus_y, plus_z, minus_z) = (signed(Y, true), signed(Z, false), signed(Z, true));
+        // exp(-iπ/4 X)|0> is the -1 eigenstate of Y
+        tableau.rotate(&x, 1);
+        assert_eq!(vec![minus_y], tableau.rows);
+        tableau.rotate(&x, 3);
+        assert_eq!(vec![plus_z], tableau.rows);
+        tableau.rotate(&x, 2);
+        assert_eq!(vec![minus_z], tableau.rows);
+    }
+
+    #[test]
+    fn rotations_on_several_blocks_are_joint() {
+        let architecture = PathArchitecture::new(2);
+        let mut basis = vec![I; 22];
+        basis[2] = X;
+        basis[13] = Z;
+        let on_block = |block: usize| {
+            let mut on_block = vec![I; basis.len()];
+            on_block[block * 11..][..11].copy_from_slice(&basis[block * 11..][..11]);
+            on_block
+        };
+        let clifford = |basis| PbcOperation::Clifford {
+            basis,
+            quarter_turns: 1,
+        };
+        let compile = |op: &PbcOperation| op.compile(&architecture, &*GROSS_TABLE, ACCURACY);
+        let check = |op: &PbcOperation, compiled: &[Operation]| {
+            Verifier::new(GROSS_MEASUREMENT, &architecture).check(Some(op), compiled)
+        };
+
+        let joint = clifford(basis.clone());
+        check(&joint, &compile(&joint)).unwrap();
+        // On one block, its own part of the Pauli is the joint Pauli
+        let first = clifford(on_block(0));
+        check(&first, &compile(&first)).unwrap();
+        // Rotating each block about its own part is not the rotation of the program on two blocks
+        let per_block: Vec<_> = (0..2)
+            .flat_map(|block| compile(&clifford(on_block(block))))
+            .collect();
+        let message = check(&joint, &per_block).unwrap_err().message;
+        assert!(message.contains("do not hold the program"), "{message}");
+    }
+
+    #[test]
+    fn cached_bases_verify() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let architecture = PathArchitecture::new(2);
+        let mut state = BlockBasisState::new(architecture.data_blocks());
+        let mut verifier = Verifier::new(GROSS_MEASUREMENT, &architecture);
+        for op in program(&mut rng, architecture.qubits()) {
+            let (compiled, _, _) =
+                op.compile_cached_observed(&architecture, &*GROSS_TABLE, ACCURACY, &mut state);
+            let restore = state.clone().finish();
+            verifier
+                .check_cached(Some(&op), &compiled, &restore)
+                .unwrap();
+        }
+        verifier.check(None, &state.finish()).unwrap();
+        assert_eq!(16, verifier.checked());
+    }
+
+    #[test]
+    fn repeated_t_measurements_verify() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let architecture = PathArchitecture::new(2);
+        let op = t_rotation(random_basis(&mut rng, architecture.qubits()));
+        let compiled: Vec<_> = crate::optimize::verify_t_injections(
+            op.compile(&architecture, &*GROSS_TABLE, ACCURACY),
+            3,
+        )
+        .collect();
+        Verifier::new(GROSS_MEASUREMENT, &architecture)
+            .check(Some(&op), &compiled)
+            .unwrap();
+    }
+
+    #[test]
+    fn grown_blocks_hold_new_qubits() {
+        let mut architecture = PathArchitecture::new(1);
+        let mut verifier = Verifier::new(GROSS_MEASUREMENT, &architecture);
+        let mut basis = vec![I; 22];
+        basis[3] = X;
+        basis[20] = Z;
+        let op = t_rotation(basis);
+        let mut compiled = architecture.grow(22);
+        compiled.extend(op.compile(&architecture, &*GROSS_TABLE, ACCURACY));
+        verifier.check(Some(&op), &compiled).unwrap();
+    }
+
+    #[test]
+    fn wrong_instructions_fail() {
+        let architecture = PathArchitecture::new(2);
+        let mut basis = vec![I; 22];
+        basis[1] = X;
+        basis[15] = Y;
+        let rotation = t_rotation(basis.clone());
+        let measurement = PbcOperation::Measurement {
+            basis: basis.clone(),
+            flip_result: false,
+        };
+        let compiled = rotation.compile(&architecture, &*GROSS_TABLE, ACCURACY);
+        let measured = measurement.compile(&architecture, &*GROSS_TABLE, ACCURACY);
+
+        let check = |op: &PbcOperation, compiled: &[Operation]| {
+            Verifier::new(GROSS_MEASUREMENT, &architecture).check(Some(op), compiled)
+        };
+        check(&rotation, &compiled).unwrap();
+        check(&measurement, &measured).unwrap();
+
+        // Another Pauli
+        let mut other = basis.clone();
+        other[15] = Z;
+        let message = check(&t_rotation(other.clone()), &compiled)
+            .unwrap_err()
+            .message;
+        assert!(message.contains("does not carry"), "{message}");
+        let other_measurement = PbcOperation::Measurement {
+            basis: other,
+            flip_result: false,
+        };
+        let message = check(&other_measurement, &measured).unwrap_err().message;
+        assert!(message.contains("is not measured"), "{message}");
+
+        // A rotation is not a measurement, and a measurement does not rotate
+        let message = check(&measurement, &compiled).unwrap_err().message;
+        assert!(message.contains("does not carry"), "{message}");
+        let message = check(&rotation, &measured).unwrap_err().message;
+        assert!(message.contains("is not applied"), "{message}");
+
+        // Without the joint measurement, the pivot of the magic block does not carry the Pauli on the other block
+        let dropped: Vec<_> = compiled.iter().filter(|op| op.len() < 2).cloned().collect();
+        assert!(dropped.len() < compiled.len());
+        assert!(check(&rotation, &dropped).is_err());
+
+        // Measuring a logical qubit next to the pivot measures the program
+        let mut measuring = compiled.clone();
+        let z7 = TwoBases::new(I, Z).unwrap();
+        measuring.push(vec![(0, BicycleISA::Measure(z7))]);
+        let message = check(&rotation, &measuring).unwrap_err().message;
+        assert!(message.contains("do not hold the program"), "{message}");
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark for the streaming numerics of `run_numerics`.
+//!
+//! Every instruction passes through the instruction counter and the timing loop,
+//! which contain `trace!` calls. Compare the throughput with and without the
+//! `strip-trace` feature to measure what the disabled log statements cost:
+//!
+//! ```sh
+//! cargo bench --package bicycle_numerics --bench bench_numerics
+//! cargo bench --package bicycle_numerics --bench bench_numerics -F strip-trace
+//! ```
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use bicycle_common::{AutomorphismData, BicycleISA, Pauli, TGateData, TwoBases};
+use bicycle_compiler::{PathArchitecture, operation::Operation};
+use bicycle_numerics::{accumulate::Accumulation, model::GROSS_1E3, run_numerics_with};
+
+const BLOCKS: usize = 10;
+const CHUNKS: usize = 10_000;
+
+/// A chunk resembling a compiled rotation: automorphisms and measurements on every block,
+/// joint measurements along the path, and a T gate next to the factory
+fn chunk() -> Vec<Operation> {
+    let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+    let aut = BicycleISA::Automorphism(AutomorphismData::new(2, 3));
+    let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+    let mut ops = vec![];
+    for block in 0..BLOCKS {
+        ops.push(vec![(block, aut)]);
+        ops.push(vec![(block, BicycleISA::Measure(z))]);
+        ops.push(vec![(block, aut)]);
+    }
+    for block in 1..BLOCKS {
+        ops.push(vec![
+            (block - 1, BicycleISA::JointMeasure(z)),
+            (block, BicycleISA::JointMeasure(z)),
+        ]);
+    }
+    ops.push(vec![(BLOCKS - 1, t_gate)]);
+    ops
+}
+
+/// Run the numerics over `CHUNKS` chunks repeatedly for at least `min_duration`
+/// and report the time per instruction.
+fn bench(name: &str, accumulation: Accumulation, min_duration: Duration) {
+    let chunk = chunk();
+    let instructions = CHUNKS * chunk.len();
+    let architecture = PathArchitecture::new(BLOCKS);
+
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < min_duration {
+        let chunks = std::iter::repeat_n(chunk.clone(), CHUNKS);
+        let last = run_numerics_with(chunks, architecture.clone(), GROSS_1E3, accumulation).last();
+        black_box(last);
+        runs += 1;
+    }
+    let per_instruction = start.elapsed() / (runs * instructions) as u32;
+    println!("  {name:<10} {per_instruction:>10.3?} per instruction ({runs} runs)");
+}
+
+fn main() {
+    let strip_trace = if cfg!(feature = "strip-trace") {
+        "stripped"
+    } else {
+        "compiled in"
+    };
+    println!("run_numerics, trace logging {strip_trace}");
+    let min_duration = Duration::from_secs(3);
+    bench("fixed", Accumulation::Fixed, min_duration);
+    bench("kahan", Accumulation::Kahan, min_duration);
+    bench("rational", Accumulation::Rational, min_duration);
+}
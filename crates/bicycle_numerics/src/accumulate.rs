@@ -0,0 +1,167 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Backends for accumulating the total error of long programs.
+//!
+//! Each error term is an [`ErrorPrecision`], so it is rounded to a multiple of 2^-96 by the model.
+//! Adding fixed-point numbers is exact, so the fixed-point sum only differs from the exact rational sum
+//! when it would overflow, and a Kahan-summed `f64` approximates both to within a few ulps.
+
+use clap::ValueEnum;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::model::ErrorPrecision;
+
+/// How to accumulate the total error
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Accumulation {
+    /// Sum in the fixed-point error precision
+    #[default]
+    Fixed,
+    /// Sum in compensated (Kahan-Babuska) floating point
+    Kahan,
+    /// Sum exactly as arbitrary-precision rationals
+    Rational,
+}
+
+/// A running sum of error terms
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorSum {
+    Fixed(ErrorPrecision),
+    Kahan { sum: f64, compensation: f64 },
+    Rational(BigRational),
+}
+
+impl ErrorSum {
+    pub fn new(accumulation: Accumulation) -> Self {
+        match accumulation {
+            Accumulation::Fixed => Self::Fixed(ErrorPrecision::ZERO),
+            Accumulation::Kahan => Self::Kahan {
+                sum: 0.0,
+                compensation: 0.0,
+            },
+            Accumulation::Rational => Self::Rational(BigRational::zero()),
+        }
+    }
+
+    /// Add an error term, or return `None` if the sum overflows
+    #[must_use]
+    pub fn add(&mut self, error: ErrorPrecision) -> Option<()> {
+        match self {
+            Self::Fixed(sum) => *sum = sum.checked_add(error)?,
+            Self::Kahan { sum, compensation } => {
+                let term: f64 = error.to_num();
+                let new_sum = *sum + term;
+                // Recover the low-order bits that were lost in the addition
+                *compensation += if sum.abs() >= term.abs() {
+                    (*sum - new_sum) + term
+                } else {
+                    (term - new_sum) + *sum
+                };
+                *sum = new_sum;
+            }
+            Self::Rational(sum) => {
+                *sum += BigRational::new(
+                    BigInt::from(error.to_bits()),
+                    BigInt::from(1) << ErrorPrecision::FRAC_NBITS,
+                )
+            }
+        }
+        Some(())
+    }
+
+    /// The total error, rounded to `f64`
+    pub fn total(&self) -> f64 {
+        match self {
+            Self::Fixed(sum) => sum.to_num(),
+            Self::Kahan { sum, compensation } => sum + compensation,
+            Self::Rational(sum) => sum.to_f64().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A million error terms of 1e-15 scale, with an occasional larger one
+    fn terms() -> impl Iterator<Item = ErrorPrecision> {
+        let small = ErrorPrecision::lit("1.234567e-15");
+        let large = ErrorPrecision::lit("3.1e-7");
+        (0..1_000_000u128).map(move |i| {
+            if i % 1000 == 0 {
+                large
+            } else {
+                small * (i % 7 + 1)
+            }
+        })
+    }
+
+    fn sum(accumulation: Accumulation) -> f64 {
+        let mut sum = ErrorSum::new(accumulation);
+        terms().for_each(|term| sum.add(term).unwrap());
+        sum.total()
+    }
+
+    #[test]
+    fn backends_agree_on_long_streams() {
+        let fixed = sum(Accumulation::Fixed);
+        let rational = sum(Accumulation::Rational);
+        let kahan = sum(Accumulation::Kahan);
+
+        // Fixed-point addition is exact
+        assert_eq!(rational, fixed);
+        assert!(
+            (kahan - rational).abs() <= 2.0 * f64::EPSILON * rational,
+            "{kahan} differs from {rational}"
+        );
+    }
+
+    #[test]
+    fn compensation_recovers_lost_terms() {
+        // Each small term is below half an ulp of the large one, so naive summation drops all of them
+        let large = ErrorPrecision::from_bits(1 << (ErrorPrecision::FRAC_NBITS - 10));
+        let small = ErrorPrecision::from_bits(1 << (ErrorPrecision::FRAC_NBITS - 70));
+        let terms = || std::iter::once(large).chain(std::iter::repeat_n(small, 1 << 20));
+        let add_all = |accumulation| {
+            let mut sum = ErrorSum::new(accumulation);
+            terms().for_each(|term| sum.add(term).unwrap());
+            sum
+        };
+
+        // The exact sum is 2^-10 + 2^-50
+        let exact = BigRational::new((BigInt::from(1) << 40) + 1, BigInt::from(1) << 50);
+        assert_eq!(
+            ErrorSum::Rational(exact.clone()),
+            add_all(Accumulation::Rational)
+        );
+        let exact = exact.to_f64().unwrap();
+        assert_eq!(exact, add_all(Accumulation::Fixed).total());
+
+        let naive: f64 = terms().map(|term| term.to_num::<f64>()).sum();
+        assert_eq!(large.to_num::<f64>(), naive);
+        let kahan = add_all(Accumulation::Kahan).total();
+        assert_eq!(exact, kahan);
+        assert!((kahan - exact).abs() < (naive - exact).abs());
+    }
+
+    #[test]
+    fn empty_sums_are_zero() {
+        for accumulation in Accumulation::value_variants() {
+            assert_eq!(0.0, ErrorSum::new(*accumulation).total());
+        }
+    }
+}
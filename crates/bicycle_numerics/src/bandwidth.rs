@@ -0,0 +1,207 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rates of the classical control traffic of each block, i.e., of the instructions that the control stack issues
+//! and of the logical measurement outcomes that it reads out, to size the control electronics.
+
+use std::collections::BTreeMap;
+
+use bicycle_common::BicycleISA;
+use serde::Serialize;
+
+use crate::trace::TracedInstruction;
+
+/// Control instructions and measurement outcomes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub instructions: u64,
+    pub outcomes: u64,
+}
+
+impl Traffic {
+    /// The traffic of an instruction on one block.
+    /// Delays are not issued, and a joint measurement reads out its outcome from both blocks.
+    pub fn of(instr: &BicycleISA) -> Self {
+        let outcomes = match instr {
+            BicycleISA::Measure(_) | BicycleISA::JointMeasure(_) => 1,
+            BicycleISA::ParallelMeasure(_) => 2,
+            // Every logical qubit of the block is measured
+            BicycleISA::DestructiveZ | BicycleISA::DestructiveX => 12,
+            _ => 0,
+        };
+        Self {
+            instructions: u64::from(!matches!(instr, BicycleISA::Delay(_))),
+            outcomes,
+        }
+    }
+
+    fn add(&mut self, other: Self) {
+        self.instructions += other.instructions;
+        self.outcomes += other.outcomes;
+    }
+
+    fn max(self, other: Self) -> Self {
+        Self {
+            instructions: self.instructions.max(other.instructions),
+            outcomes: self.outcomes.max(other.outcomes),
+        }
+    }
+}
+
+/// The traffic of one block, whose instructions arrive in the order of their start times
+#[derive(Debug, Clone, Copy, Default)]
+struct BlockMeter {
+    total: Traffic,
+    bin: u64,
+    current: Traffic,
+    peak: Traffic,
+}
+
+/// Average and peak traffic per 1000 cycles of a block, or of all blocks together
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BandwidthRow {
+    /// The block, or none for all blocks together
+    pub block: Option<usize>,
+    pub instructions: u64,
+    pub outcomes: u64,
+    pub average_instructions_per_1k_cycles: f64,
+    pub peak_instructions_per_1k_cycles: f64,
+    pub average_outcomes_per_1k_cycles: f64,
+    pub peak_outcomes_per_1k_cycles: f64,
+}
+
+/// Counts the traffic of the traced instructions in bins of a fixed number of cycles by their start time.
+/// The peak rates are those of the busiest bin.
+#[derive(Debug, Clone)]
+pub struct BandwidthMeter {
+    bin_cycles: u64,
+    blocks: Vec<BlockMeter>,
+    bins: BTreeMap<u64, Traffic>,
+}
+
+impl BandwidthMeter {
+    pub fn new(blocks: usize, bin_cycles: u64) -> Self {
+        assert!(bin_cycles > 0, "A bin should span at least one cycle");
+        Self {
+            bin_cycles,
+            blocks: vec![BlockMeter::default(); blocks],
+            bins: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, traced: &TracedInstruction) {
+        let traffic = Traffic::of(&traced.instruction);
+        if traffic == Traffic::default() {
+            return;
+        }
+        let bin = traced.start / self.bin_cycles;
+        // Blocks that the compiler added to the path
+        if traced.block >= self.blocks.len() {
+            self.blocks.resize(traced.block + 1, BlockMeter::default());
+        }
+        let meter = &mut self.blocks[traced.block];
+        // The start times on a block do not decrease, so its earlier bins are complete
+        if bin > meter.bin {
+            meter.peak = meter.peak.max(meter.current);
+            meter.current = Traffic::default();
+            meter.bin = bin;
+        }
+        meter.current.add(traffic);
+        meter.total.add(traffic);
+        self.bins.entry(bin).or_default().add(traffic);
+    }
+
+    /// The rates of every block and of all blocks together, averaged over a program of `end_time` cycles
+    pub fn rows(&self, end_time: u64) -> Vec<BandwidthRow> {
+        let row = |block, total: Traffic, peak: Traffic| BandwidthRow {
+            block,
+            instructions: total.instructions,
+            outcomes: total.outcomes,
+            average_instructions_per_1k_cycles: rate(total.instructions, end_time),
+            peak_instructions_per_1k_cycles: rate(peak.instructions, self.bin_cycles),
+            average_outcomes_per_1k_cycles: rate(total.outcomes, end_time),
+            peak_outcomes_per_1k_cycles: rate(peak.outcomes, self.bin_cycles),
+        };
+        let mut rows: Vec<_> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(block, meter)| row(Some(block), meter.total, meter.peak.max(meter.current)))
+            .collect();
+        let (total, peak) = self.bins.values().fold(
+            (Traffic::default(), Traffic::default()),
+            |(mut total, peak), traffic| {
+                total.add(*traffic);
+                (total, peak.max(*traffic))
+            },
+        );
+        rows.push(row(None, total, peak));
+        rows
+    }
+}
+
+fn rate(count: u64, cycles: u64) -> f64 {
+    if cycles == 0 {
+        0.0
+    } else {
+        1000.0 * count as f64 / cycles as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{Pauli, TwoBases};
+
+    use super::*;
+
+    #[test]
+    fn average_and_peak_rates() {
+        let measure = BicycleISA::Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let mut meter = BandwidthMeter::new(2, 100);
+        let traced = [
+            (0, 0, measure),
+            (0, 10, BicycleISA::CSSInitZero),
+            (0, 250, measure),
+            (1, 50, BicycleISA::DestructiveX),
+            (1, 60, BicycleISA::Delay(100)),
+        ];
+        for (block, start, instruction) in traced {
+            meter.record(&TracedInstruction {
+                line: 1,
+                block,
+                start,
+                waited: 0,
+                instruction,
+            });
+        }
+
+        let rows = meter.rows(400);
+        assert_eq!(3, rows.len());
+        assert_eq!(Some(0), rows[0].block);
+        assert_eq!(3, rows[0].instructions);
+        assert_eq!(2, rows[0].outcomes);
+        assert_eq!(7.5, rows[0].average_instructions_per_1k_cycles);
+        assert_eq!(20.0, rows[0].peak_instructions_per_1k_cycles);
+        assert_eq!(10.0, rows[0].peak_outcomes_per_1k_cycles);
+        // The delay is not issued
+        assert_eq!(1, rows[1].instructions);
+        assert_eq!(120.0, rows[1].peak_outcomes_per_1k_cycles);
+
+        assert_eq!(None, rows[2].block);
+        assert_eq!(4, rows[2].instructions);
+        assert_eq!(14, rows[2].outcomes);
+        assert_eq!(30.0, rows[2].peak_instructions_per_1k_cycles);
+        assert_eq!(130.0, rows[2].peak_outcomes_per_1k_cycles);
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The error and time of a program split by the kind of instruction, to see what dominates the budget.
+
+use bicycle_common::BicycleISA;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::{ErrorPrecision, InstructionCost},
+    trace::TracedInstruction,
+};
+
+/// Kinds of instructions whose error and time are reported separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CostCategory {
+    /// Waiting between instructions, delays, and stored magic states
+    Idle,
+    /// Automorphisms
+    Shift,
+    /// Instructions on a single block, e.g., measurements and initializations
+    InModule,
+    /// Instructions on two blocks, e.g., joint measurements
+    InterModule,
+    /// T and S injections and the preparation of T states
+    Magic,
+}
+
+impl CostCategory {
+    pub const COUNT: usize = 5;
+
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Idle,
+        Self::Shift,
+        Self::InModule,
+        Self::InterModule,
+        Self::Magic,
+    ];
+
+    pub fn of(instruction: &BicycleISA) -> Self {
+        match instruction {
+            BicycleISA::Delay(_) => Self::Idle,
+            BicycleISA::Automorphism(_) => Self::Shift,
+            BicycleISA::SyndromeCycle
+            | BicycleISA::CSSInitZero
+            | BicycleISA::CSSInitPlus
+            | BicycleISA::DestructiveZ
+            | BicycleISA::DestructiveX
+            | BicycleISA::Measure(_)
+            | BicycleISA::ParallelMeasure(_) => Self::InModule,
+            BicycleISA::JointMeasure(_)
+            | BicycleISA::JointBellInit
+            | BicycleISA::JointTransversalCX => Self::InterModule,
+            BicycleISA::InitT
+            | BicycleISA::TGate(_)
+            | BicycleISA::TGatePair(_)
+            | BicycleISA::SGate(_) => Self::Magic,
+        }
+    }
+}
+
+/// The total error contributed by each [`CostCategory`], which sum to the total error
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorBreakdown {
+    errors: [ErrorPrecision; CostCategory::COUNT],
+}
+
+impl ErrorBreakdown {
+    /// Add an error to a category, or `None` if the error overflows
+    pub fn add(&mut self, category: CostCategory, error: ErrorPrecision) -> Option<()> {
+        let total = &mut self.errors[category as usize];
+        *total = total.checked_add(error)?;
+        Some(())
+    }
+
+    pub fn error(&self, category: CostCategory) -> f64 {
+        self.errors[category as usize].to_num()
+    }
+}
+
+/// The time that one block spends in each category
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BlockTimes {
+    pub block: usize,
+    pub idle: u64,
+    pub shift: u64,
+    pub in_module: u64,
+    pub inter_module: u64,
+    pub magic: u64,
+}
+
+impl BlockTimes {
+    fn add(&mut self, category: CostCategory, cycles: u64) {
+        let time = match category {
+            CostCategory::Idle => &mut self.idle,
+            CostCategory::Shift => &mut self.shift,
+            CostCategory::InModule => &mut self.in_module,
+            CostCategory::InterModule => &mut self.inter_module,
+            CostCategory::Magic => &mut self.magic,
+        };
+        *time += cycles;
+    }
+}
+
+/// Records the time of each block per category from the traced instructions
+#[derive(Debug, Clone, Default)]
+pub struct TimeBreakdown {
+    blocks: Vec<BlockTimes>,
+}
+
+impl TimeBreakdown {
+    pub fn record(&mut self, traced: &TracedInstruction, model: &impl InstructionCost) {
+        if traced.block >= self.blocks.len() {
+            let blocks = self.blocks.len()..=traced.block;
+            self.blocks.extend(blocks.map(|block| BlockTimes {
+                block,
+                ..Default::default()
+            }));
+        }
+        let times = &mut self.blocks[traced.block];
+        times.add(CostCategory::Idle, traced.waited);
+        times.add(
+            CostCategory::of(&traced.instruction),
+            model.timing(&traced.instruction),
+        );
+    }
+
+    /// The times of every block that has an instruction
+    pub fn blocks(&self) -> &[BlockTimes] {
+        &self.blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{Pauli, TGateData, TwoBases};
+
+    use super::*;
+    use crate::model::GROSS_1E3;
+
+    #[test]
+    fn times_per_category() {
+        let z = BicycleISA::JointMeasure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let t = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let traced = |block, waited, instruction| TracedInstruction {
+            line: 1,
+            block,
+            start: 0,
+            waited,
+            instruction,
+        };
+        let mut breakdown = TimeBreakdown::default();
+        breakdown.record(&traced(1, 0, z), &GROSS_1E3);
+        breakdown.record(&traced(1, 10, t), &GROSS_1E3);
+        breakdown.record(&traced(1, 0, BicycleISA::Delay(8)), &GROSS_1E3);
+
+        assert_eq!(
+            [
+                BlockTimes {
+                    block: 0,
+                    ..Default::default()
+                },
+                BlockTimes {
+                    block: 1,
+                    idle: 10 + 8,
+                    inter_module: 120,
+                    magic: 471,
+                    ..Default::default()
+                }
+            ],
+            breakdown.blocks()
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Numerics of one program compiled for both codes, to compare the codes side by side.
+
+use bicycle_cliffords::{CompleteMeasurementTable, MeasurementChoices};
+use bicycle_compiler::{
+    PathArchitecture,
+    language::{AnglePrecision, PbcOperation},
+    operation::Operation,
+    optimize,
+};
+use serde::Serialize;
+
+use crate::{
+    NumericsError, OutputData,
+    model::{GROSS_1E3, GROSS_1E4, Model, TWO_GROSS_1E3, TWO_GROSS_1E4},
+    run_numerics,
+};
+
+/// The built-in models of a code with their physical noise rates
+pub fn models(code: MeasurementChoices) -> [(f64, Model); 2] {
+    match code {
+        MeasurementChoices::Gross => [(1e-3, GROSS_1E3), (1e-4, GROSS_1E4)],
+        MeasurementChoices::TwoGross => [(1e-3, TWO_GROSS_1E3), (1e-4, TWO_GROSS_1E4)],
+    }
+}
+
+/// Totals of the numerics of a whole program under one model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub code: MeasurementChoices,
+    pub p: f64,
+    /// Number of program lines
+    pub lines: usize,
+    pub idles: u64,
+    pub t_injs: u64,
+    pub automorphisms: u64,
+    pub measurements: u64,
+    pub joint_measurements: u64,
+    pub measurement_depth: u64,
+    pub end_time: u64,
+    pub total_error: f64,
+}
+
+impl Comparison {
+    fn new(code: MeasurementChoices, p: f64, data: &[OutputData]) -> Self {
+        let last = data.last();
+        let sum = |count: fn(&OutputData) -> u64| data.iter().map(count).sum();
+        Self {
+            code,
+            p,
+            lines: data.len(),
+            idles: sum(|data| data.idles),
+            t_injs: sum(|data| data.t_injs),
+            automorphisms: sum(|data| data.automorphisms),
+            measurements: sum(|data| data.measurements),
+            joint_measurements: sum(|data| data.joint_measurements),
+            measurement_depth: last.map_or(0, |data| data.measurement_depth),
+            end_time: last.map_or(0, |data| data.end_time),
+            total_error: last.map_or(0.0, |data| data.total_error),
+        }
+    }
+}
+
+/// Compile the program with the measurement table of each code, as the compiler does by default,
+/// and compute its numerics under each built-in model of the code
+pub fn compare_codes<'a>(
+    ops: &[PbcOperation],
+    qubits: usize,
+    tables: impl IntoIterator<Item = (MeasurementChoices, &'a CompleteMeasurementTable)>,
+    accuracy: AnglePrecision,
+) -> Result<Vec<Comparison>, NumericsError> {
+    let mut comparisons = vec![];
+    for (code, table) in tables {
+        let architecture = PathArchitecture::for_qubits_per_block(qubits, code.qubits_per_block());
+        let compiled = ops
+            .iter()
+            .map(|op| op.compile(&architecture, table, accuracy))
+            .map(optimize::remove_trivial_automorphisms);
+        let chunks: Vec<Vec<Operation>> =
+            optimize::remove_duplicate_measurements_chunked(compiled).collect();
+        for (p, model) in models(code) {
+            let data = run_numerics(chunks.iter().cloned(), architecture.clone(), model)
+                .collect::<Result<Vec<_>, _>>()?;
+            comparisons.push(Comparison::new(code, p, &data));
+        }
+    }
+    Ok(comparisons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(i: usize, t_injs: u64, end_time: u64, total_error: f64) -> OutputData {
+        OutputData {
+            i,
+            qubits: 11,
+            idles: 1,
+            t_injs,
+            automorphisms: 2,
+            measurements: 3,
+            joint_measurements: 0,
+            measurement_depth: i as u64,
+            steps: 0,
+            end_time,
+            factory_time: 0,
+            storage_idles: 0,
+            total_error,
+            breakdown: Default::default(),
+        }
+    }
+
+    #[test]
+    fn totals_of_program() {
+        let comparison = Comparison::new(
+            MeasurementChoices::TwoGross,
+            1e-4,
+            &[data(1, 1, 10, 0.5), data(2, 0, 30, 0.75)],
+        );
+        let expected = Comparison {
+            code: MeasurementChoices::TwoGross,
+            p: 1e-4,
+            lines: 2,
+            idles: 2,
+            t_injs: 1,
+            automorphisms: 4,
+            measurements: 6,
+            joint_measurements: 0,
+            measurement_depth: 2,
+            end_time: 30,
+            total_error: 0.75,
+        };
+        assert_eq!(expected, comparison);
+
+        let empty = Comparison::new(MeasurementChoices::Gross, 1e-3, &[]);
+        assert_eq!(
+            (0, 0, 0.0),
+            (empty.lines, empty.end_time, empty.total_error)
+        );
+    }
+
+    #[test]
+    fn empty_program() {
+        let table = CompleteMeasurementTable::from_shards(Default::default(), []);
+        let comparisons = compare_codes(
+            &[],
+            22,
+            [
+                (MeasurementChoices::Gross, &table),
+                (MeasurementChoices::TwoGross, &table),
+            ],
+            AnglePrecision::lit("1e-9"),
+        )
+        .unwrap();
+        let models: Vec<_> = comparisons
+            .iter()
+            .map(|comparison| (comparison.code, comparison.p))
+            .collect();
+        assert_eq!(
+            vec![
+                (MeasurementChoices::Gross, 1e-3),
+                (MeasurementChoices::Gross, 1e-4),
+                (MeasurementChoices::TwoGross, 1e-3),
+                (MeasurementChoices::TwoGross, 1e-4),
+            ],
+            models
+        );
+    }
+}
@@ -12,35 +12,118 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{error::Error, fmt, iter::Sum, ops::AddAssign};
+
 use bicycle_common::BicycleISA;
 
-use bicycle_compiler::{operation::Operation, PathArchitecture};
+use accumulate::{Accumulation, ErrorSum};
+use bicycle_compiler::{PathArchitecture, operation::Operation, optimize::Scheduler};
+use breakdown::{CostCategory, ErrorBreakdown};
 use log::trace;
-use model::Model;
+use model::{InstructionClass, InstructionCost};
 use serde::{Deserialize, Serialize};
+use storage::MagicStorage;
+use trace::TracedInstruction;
+
+pub mod accumulate;
+pub mod bandwidth;
+pub mod breakdown;
+pub mod compare;
+pub mod pareto;
+pub mod parse;
+pub mod predict;
+pub mod reorder;
+pub mod sim;
+pub mod storage;
+pub mod trace;
+pub mod window;
 
-pub mod model;
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
-struct IsaCounter {
+/// The cost model of the instructions, which lives in the compiler such that it can estimate resources
+pub use bicycle_compiler::model;
+
+/// Counts of bicycle instructions in a stream of operations.
+///
+/// An operation is counted once by its first instruction, so a joint measurement on two blocks counts once,
+/// and an automorphism counts the number of generators that implement it.
+/// The numerics also count the idles that they insert between instructions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IsaCounter {
     pub idles: u64,
     pub t_injs: u64,
+    /// S gates by catalysis with a |Y> state
+    #[serde(default)]
+    pub s_gates: u64,
     pub automorphisms: u64,
     pub measurements: u64,
     pub joint_measurements: u64,
+    /// Other instructions, e.g., initializing the blocks that the compiler adds to the path
+    pub other: u64,
 }
 
 impl IsaCounter {
-    fn add(&mut self, instr: &BicycleISA) {
+    /// Count a single instruction
+    pub fn add(&mut self, instr: &BicycleISA) {
         trace!("Adding: {instr}");
         match instr {
-            BicycleISA::TGate(_) => self.t_injs += 1,
+            BicycleISA::TGate(_) | BicycleISA::TGatePair(_) => self.t_injs += instr.t_count(),
+            BicycleISA::SGate(_) => self.s_gates += 1,
             BicycleISA::Automorphism(autdata) => self.automorphisms += autdata.nr_generators(),
             BicycleISA::Measure(_) => self.measurements += 1,
             BicycleISA::JointMeasure(_) => self.joint_measurements += 1,
-            _ => unreachable!("There should not be any other instructions, {}", instr),
+            _ => self.other += 1,
         }
         trace!("Now at: {:?}", &self);
     }
+
+    /// Count an operation by its first instruction
+    pub fn add_operation(&mut self, op: &Operation) {
+        if let Some((_, instr)) = op.first() {
+            self.add(instr);
+        }
+    }
+
+    /// Count the operations of a chunk, e.g., of one line of compiler output
+    pub fn of_chunk(chunk: &[Operation]) -> Self {
+        let mut counter = Self::default();
+        chunk.iter().for_each(|op| counter.add_operation(op));
+        counter
+    }
+
+    /// Add the counts of another counter
+    pub fn merge(&mut self, other: &Self) {
+        self.idles += other.idles;
+        self.t_injs += other.t_injs;
+        self.s_gates += other.s_gates;
+        self.automorphisms += other.automorphisms;
+        self.measurements += other.measurements;
+        self.joint_measurements += other.joint_measurements;
+        self.other += other.other;
+    }
+
+    /// Total of the counts up to and including each chunk
+    pub fn cumulative(
+        chunks: impl IntoIterator<Item = impl AsRef<[Operation]>>,
+    ) -> impl Iterator<Item = Self> {
+        chunks.into_iter().scan(Self::default(), |total, chunk| {
+            total.merge(&Self::of_chunk(chunk.as_ref()));
+            Some(*total)
+        })
+    }
+}
+
+impl AddAssign for IsaCounter {
+    fn add_assign(&mut self, rhs: Self) {
+        self.merge(&rhs);
+    }
+}
+
+impl Sum for IsaCounter {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, counter| {
+            total += counter;
+            total
+        })
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -53,75 +136,651 @@ pub struct OutputData {
     pub measurements: u64,
     pub joint_measurements: u64,
     pub measurement_depth: u64,
+    /// The number of time steps when operations on disjoint blocks run in parallel,
+    /// see [`bicycle_compiler::optimize::Scheduler`]
+    #[serde(default)]
+    pub steps: u64,
     pub end_time: u64,
+    /// The longest time that any magic state factory has spent injecting magic states
+    pub factory_time: u64,
+    /// Idle cycles of the magic states that were taken from storage blocks
+    pub storage_idles: u64,
     pub total_error: f64,
+    /// The total error split by the kind of instruction
+    #[serde(skip)]
+    pub breakdown: ErrorBreakdown,
 }
 
+/// An error while computing the numerics of a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericsError {
+    /// A count, time, or the total error no longer fits its type
+    Overflow {
+        /// The input line, counting from 1
+        line: usize,
+        quantity: &'static str,
+    },
+}
+
+impl fmt::Display for NumericsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow { line, quantity } => {
+                write!(f, "The {quantity} overflowed at input line {line}")
+            }
+        }
+    }
+}
+
+impl Error for NumericsError {}
+
+/// Compute the numerics after each chunk of operations.
+/// The iterator ends after the first error.
 pub fn run_numerics(
     chunked_ops: impl Iterator<Item = Vec<Operation>>,
     architecture: PathArchitecture,
-    model: Model,
-) -> impl Iterator<Item = OutputData> {
-    let data_blocks = architecture.data_blocks();
-    let qubits = architecture.qubits();
-
-    let mut depths: Vec<u64> = vec![0; data_blocks];
-    let mut times: Vec<u64> = vec![0; data_blocks];
-    let mut total_error = model::ErrorPrecision::ZERO;
-    chunked_ops.enumerate().map(move |(i, ops)| {
-        trace!("Ops: {ops:?}");
-        let mut counter: IsaCounter = Default::default();
-        // Accumulate counts. Or use a fold.
-        ops.iter().for_each(|instr| counter.add(&instr[0].1));
-
-        // Compute the new depths and timing for each block
-        for op in ops {
-            // Find the max depth/time between blocks
-            let mut max_depth = 0;
-            let mut max_time = 0;
-            for (block_i, _) in op.iter() {
-                max_depth = max_depth.max(depths[*block_i]);
-                max_time = max_time.max(times[*block_i]);
-            }
+    model: impl InstructionCost,
+) -> impl Iterator<Item = Result<OutputData, NumericsError>> {
+    run_numerics_with(chunked_ops, architecture, model, Accumulation::default())
+}
 
-            for (block_i, instr) in op.iter() {
-                depths[*block_i] = max_depth;
-                match instr {
-                    BicycleISA::Measure(_) | BicycleISA::JointMeasure(_) => {
-                        depths[*block_i] = max_depth + 1
-                    }
-                    _ => depths[*block_i] = max_depth,
+/// Like [`run_numerics`], but choose how the total error is accumulated
+pub fn run_numerics_with(
+    chunked_ops: impl Iterator<Item = Vec<Operation>>,
+    architecture: PathArchitecture,
+    model: impl InstructionCost,
+    accumulation: Accumulation,
+) -> impl Iterator<Item = Result<OutputData, NumericsError>> {
+    run_numerics_traced(chunked_ops, architecture, model, accumulation, |_| {})
+}
+
+/// Like [`run_numerics_with`], but pass each instruction with its start time to `trace`
+pub fn run_numerics_traced(
+    chunked_ops: impl Iterator<Item = Vec<Operation>>,
+    architecture: PathArchitecture,
+    model: impl InstructionCost,
+    accumulation: Accumulation,
+    mut trace: impl FnMut(TracedInstruction),
+) -> impl Iterator<Item = Result<OutputData, NumericsError>> {
+    // Instructions may also act on storage blocks, e.g., to consume a stored CCZ state
+    let blocks = architecture.total_blocks();
+    let mut architecture = architecture;
+
+    let mut depths: Vec<u64> = vec![0; blocks];
+    let mut scheduler = Scheduler::new();
+    let mut times: Vec<u64> = vec![0; blocks];
+    // Time that the factory next to each magic block is occupied by injections
+    let mut factory_times: Vec<u64> = vec![0; blocks];
+    // Magic states that the factory next to each magic block has stored
+    let mut storages: Vec<Option<MagicStorage>> = (0..blocks)
+        .map(|block| {
+            let storage_blocks = architecture.storage_of(block).len();
+            (storage_blocks > 0)
+                .then(|| MagicStorage::new(storage_blocks, model.cycles(InstructionClass::TGate)))
+        })
+        .collect();
+    let mut total_error = ErrorSum::new(accumulation);
+    let mut breakdown = ErrorBreakdown::default();
+    let mut failed = false;
+    chunked_ops.enumerate().map_while(move |(i, ops)| {
+        if failed {
+            return None;
+        }
+        let overflow = |quantity| NumericsError::Overflow {
+            line: i + 1,
+            quantity,
+        };
+        let mut step = || {
+            trace!("Ops: {ops:?}");
+            let mut counter = IsaCounter::of_chunk(&ops);
+            let mut storage_idles: u64 = 0;
+
+            // Compute the new depths and timing for each block
+            for op in &ops {
+                // A block that the compiler added to the path starts when it is initialized
+                if architecture.grow_for(op) {
+                    let now = *times.iter().max().unwrap_or(&0);
+                    depths.push(0);
+                    times.push(now);
+                    factory_times.push(0);
+                    storages.push(None);
+                }
+                scheduler.step(op);
+                // Find the max depth/time between blocks
+                let mut max_depth = 0;
+                let mut max_time = 0;
+                for (block_i, _) in op.iter() {
+                    max_depth = max_depth.max(depths[*block_i]);
+                    max_time = max_time.max(times[*block_i]);
                 }
 
-                // Insert idling noise
-                let time_diff = max_time - times[*block_i];
-                let (idle_cycles, idle_error) = model.idling_error(time_diff);
-                counter.idles += idle_cycles;
-                total_error += idle_error;
+                for (block_i, instr) in op.iter() {
+                    // Take a stored magic state for each T gate, which may have to wait for the factory
+                    let mut start = max_time;
+                    if let Some(storage) = &mut storages[*block_i] {
+                        for _ in 0..instr.t_count() {
+                            let (injection, stored) = storage.consume(max_time);
+                            start = start.max(injection);
+                            let (idle_cycles, idle_error) = model.idling_error(stored);
+                            storage_idles = storage_idles
+                                .checked_add(idle_cycles)
+                                .ok_or(overflow("number of storage idles"))?;
+                            total_error.add(idle_error).ok_or(overflow("total error"))?;
+                            breakdown
+                                .add(CostCategory::Idle, idle_error)
+                                .ok_or(overflow("error breakdown"))?;
+                        }
+                    }
+
+                    // Insert idling noise
+                    let time_diff = start - times[*block_i];
+                    trace(TracedInstruction {
+                        line: i + 1,
+                        block: *block_i,
+                        start,
+                        waited: time_diff,
+                        instruction: *instr,
+                    });
+
+                    depths[*block_i] = match instr {
+                        BicycleISA::Measure(_) | BicycleISA::JointMeasure(_) => max_depth
+                            .checked_add(1)
+                            .ok_or(overflow("measurement depth"))?,
+                        _ => max_depth,
+                    };
 
-                times[*block_i] = max_time + model.timing(instr);
+                    let (idle_cycles, idle_error) = model.idling_error(time_diff);
+                    counter.idles = counter
+                        .idles
+                        .checked_add(idle_cycles)
+                        .ok_or(overflow("number of idles"))?;
+                    total_error.add(idle_error).ok_or(overflow("total error"))?;
+                    breakdown
+                        .add(CostCategory::Idle, idle_error)
+                        .ok_or(overflow("error breakdown"))?;
+
+                    // A delay idles deliberately
+                    if let BicycleISA::Delay(cycles) = instr {
+                        counter.idles = counter
+                            .idles
+                            .checked_add(model.idling_error(*cycles).0)
+                            .ok_or(overflow("number of idles"))?;
+                    }
+
+                    times[*block_i] = start
+                        .checked_add(model.timing(instr))
+                        .ok_or(overflow("time"))?;
+                    if let BicycleISA::TGate(_) | BicycleISA::TGatePair(_) = instr {
+                        factory_times[*block_i] = factory_times[*block_i]
+                            .checked_add(model.timing(instr))
+                            .ok_or(overflow("factory time"))?;
+                    }
+                }
+
+                // Update error rate once per op
+                let (_, instr) = &op[0];
+                let error = model.instruction_error(instr);
+                total_error.add(error).ok_or(overflow("total error"))?;
+                breakdown
+                    .add(CostCategory::of(instr), error)
+                    .ok_or(overflow("error breakdown"))?;
             }
 
-            // Update error rate once per op
-            let (_, instr) = &op[0];
-            total_error += model.instruction_error(instr);
+            // Calculate the max depth currently
+            let measurement_depth = depths.iter().max().unwrap();
+            let end_time = times.iter().max().unwrap();
+
+            Ok(OutputData {
+                i: i + 1,
+                qubits: architecture.qubits(),
+                idles: counter.idles,
+                t_injs: counter.t_injs,
+                automorphisms: counter.automorphisms,
+                measurements: counter.measurements,
+                joint_measurements: counter.joint_measurements,
+                measurement_depth: *measurement_depth,
+                steps: scheduler.steps(),
+                end_time: *end_time,
+                factory_time: *factory_times.iter().max().unwrap(),
+                storage_idles,
+                total_error: total_error.total(),
+                breakdown,
+            })
+        };
+        let data = step();
+        failed = data.is_err();
+        Some(data)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{Pauli, TGateData, TGatePairData, TwoBases};
+    use bicycle_compiler::QUBITS_PER_BLOCK;
+    use model::{Cost, ErrorPrecision, Model};
+
+    use super::*;
+
+    #[test]
+    fn factories_at_both_ends() {
+        let architecture = PathArchitecture::new(3).two_sided();
+        let model = model::GROSS_1E3;
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let ops = vec![vec![(0, t_gate)], vec![(2, t_gate)], vec![(2, t_gate)]];
+
+        let data = run_numerics(std::iter::once(ops), architecture, model)
+            .last()
+            .unwrap()
+            .unwrap();
+
+        // Both factories inject in parallel
+        assert_eq!(3, data.t_injs);
+        assert_eq!(2 * model.timing(&t_gate), data.factory_time);
+        assert_eq!(2 * model.timing(&t_gate), data.end_time);
+    }
+
+    #[test]
+    fn paired_t_injections() {
+        let t = TGateData::new(Pauli::X, false, false).unwrap();
+        let t_gate = BicycleISA::TGate(t);
+        let pair = BicycleISA::TGatePair(TGatePairData::new(t, t));
+        let last = |ops: Vec<Operation>, model: Model| {
+            run_numerics(std::iter::once(ops), PathArchitecture::new(1), model)
+                .last()
+                .unwrap()
+                .unwrap()
+        };
+
+        // The built-in models cost a pair as two T gates
+        let single = last(vec![vec![(0, t_gate)]; 2], model::GROSS_1E3);
+        let paired = last(vec![vec![(0, pair)]], model::GROSS_1E3);
+        assert_eq!(2, single.t_injs);
+        assert_eq!(2, paired.t_injs);
+        assert_eq!(single.end_time, paired.end_time);
+        assert_eq!(single.total_error, paired.total_error);
+
+        // A model with a cost of pairs that prepares the |T> states once
+        let t_inj = Cost {
+            cycles: 471,
+            error: ErrorPrecision::lit("2.01e-3"),
+        };
+        let model = Model::new(
+            t_inj,
+            &[
+                (InstructionClass::TGate, t_inj),
+                (
+                    InstructionClass::TGatePair,
+                    Cost {
+                        cycles: 591,
+                        ..t_inj
+                    },
+                ),
+            ],
+        );
+        assert!(last(vec![vec![(0, pair)]], model).end_time < single.end_time);
+    }
+
+    #[test]
+    fn scheduled_operations_run_in_parallel() {
+        let model = model::GROSS_1E3;
+        let z = BicycleISA::Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let joint = BicycleISA::JointMeasure(TwoBases::new(Pauli::Z, Pauli::I).unwrap());
+        let ops = vec![
+            vec![(0, z)],
+            vec![(0, z)],
+            vec![(1, z)],
+            vec![(0, joint), (1, joint)],
+            vec![(2, z)],
+        ];
+        let last = |ops: Vec<Operation>| {
+            run_numerics(std::iter::once(ops), PathArchitecture::new(3), model)
+                .last()
+                .unwrap()
+                .unwrap()
+        };
+
+        let sequential = last(ops.clone());
+        let scheduled = last(Scheduler::new().schedule(ops));
+        assert_eq!(3, sequential.steps);
+        assert_eq!(sequential.steps, scheduled.steps);
+        assert_eq!(sequential.end_time, scheduled.end_time);
+        assert_eq!(sequential.total_error, scheduled.total_error);
+    }
+
+    #[test]
+    fn stored_magic_states_idle() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let chunks = || {
+            [
+                vec![vec![(0, BicycleISA::Measure(z))]; 10],
+                vec![vec![(0, t_gate)]; 3],
+            ]
+            .into_iter()
+        };
+        let last = |architecture| {
+            run_numerics(chunks(), architecture, model)
+                .last()
+                .unwrap()
+                .unwrap()
+        };
+
+        let direct = last(PathArchitecture::new(1));
+        let stored = last(PathArchitecture::new(1).with_storage_blocks(2));
+        assert_eq!(0, direct.storage_idles);
+        // The states are ready at 471 and 942 and taken at 1200 and 1671,
+        // and the third state is prepared from 1200 to 1671 and taken at 2142
+        assert_eq!(92 + 92 + 59, stored.storage_idles);
+        // The factory keeps up with the injections
+        assert_eq!(direct.end_time, stored.end_time);
+        assert!(stored.total_error > direct.total_error);
+    }
+
+    #[test]
+    fn empty_storage_delays_injections() {
+        let model = model::GROSS_1E3;
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let architecture = PathArchitecture::new(1).with_storage_blocks(1);
+        let data = run_numerics(
+            std::iter::once(vec![vec![(0, t_gate)]; 2]),
+            architecture,
+            model,
+        )
+        .last()
+        .unwrap()
+        .unwrap();
+        // Each injection waits for the factory to prepare its state
+        assert_eq!(0, data.storage_idles);
+        assert_eq!(471 + 471 + 471, data.end_time);
+    }
+
+    #[test]
+    fn barriers_and_delays() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let ops = vec![
+            vec![(0, BicycleISA::Measure(z))],
+            vec![(0, BicycleISA::Delay(0)), (2, BicycleISA::Delay(0))],
+            vec![(2, BicycleISA::Measure(z))],
+            vec![(1, BicycleISA::Delay(80))],
+        ];
+        let data = run_numerics(std::iter::once(ops), PathArchitecture::new(3), model)
+            .last()
+            .unwrap()
+            .unwrap();
+        // Block 2 waits for the measurement on block 0 and block 1 idles deliberately
+        assert_eq!(120 / 8 + 80 / 8, data.idles);
+        assert_eq!(2, data.measurement_depth);
+        assert_eq!(240, data.end_time);
+        let idling = model.idling_error(120).1 + model.idling_error(80).1;
+        let measurements = 2 * model.instruction_error(&BicycleISA::Measure(z));
+        assert_eq!((idling + measurements).to_num::<f64>(), data.total_error);
+    }
+
+    #[test]
+    fn compiler_report_agrees() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let aut = BicycleISA::Automorphism(bicycle_common::AutomorphismData::new(1, 2));
+        let joint = BicycleISA::JointMeasure(z);
+        let chunks = [
+            vec![
+                vec![(0, aut)],
+                vec![(1, t_gate)],
+                vec![(0, joint), (1, joint)],
+            ],
+            vec![
+                vec![(2, BicycleISA::Measure(z))],
+                vec![(1, joint), (2, joint)],
+            ],
+        ];
+        let architecture = PathArchitecture::new(3);
+
+        let rows: Vec<_> = run_numerics(chunks.clone().into_iter(), architecture.clone(), model)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut builder = bicycle_compiler::report::ReportBuilder::new(model, &architecture);
+        for chunk in &chunks {
+            builder.add(chunk);
+        }
+        let report = builder.finish(&architecture, 1.0);
+
+        let last = rows.last().unwrap();
+        assert_eq!(last.end_time, report.cycles);
+        assert_eq!(last.total_error, report.logical_error);
+        let total = |count: fn(&OutputData) -> u64| rows.iter().map(count).sum::<u64>();
+        assert_eq!(total(|row| row.t_injs), report.t_count);
+        assert_eq!(
+            total(|row| row.automorphisms),
+            report.automorphism_generators
+        );
+        assert_eq!(total(|row| row.measurements), report.local_measurements);
+        assert_eq!(
+            total(|row| row.joint_measurements),
+            report.joint_measurements
+        );
+    }
+
+    #[test]
+    fn error_breakdown() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let aut = BicycleISA::Automorphism(bicycle_common::AutomorphismData::new(1, 0));
+        let ops = vec![
+            vec![(0, aut)],
+            vec![(1, t_gate)],
+            vec![
+                (0, BicycleISA::JointMeasure(z)),
+                (1, BicycleISA::JointMeasure(z)),
+            ],
+        ];
+        let data = run_numerics(std::iter::once(ops), PathArchitecture::new(2), model)
+            .last()
+            .unwrap()
+            .unwrap();
+
+        let error = |category| data.breakdown.error(category);
+        assert_eq!(
+            model.instruction_error(&aut).to_num::<f64>(),
+            error(CostCategory::Shift)
+        );
+        assert_eq!(
+            model.instruction_error(&t_gate).to_num::<f64>(),
+            error(CostCategory::Magic)
+        );
+        // Block 0 waits for the T gate on block 1
+        let waited = model.timing(&t_gate) - model.timing(&aut);
+        assert_eq!(
+            model.idling_error(waited).1.to_num::<f64>(),
+            error(CostCategory::Idle)
+        );
+        assert_eq!(0.0, error(CostCategory::InModule));
+        let sum: f64 = CostCategory::ALL.into_iter().map(error).sum();
+        assert!((sum - data.total_error).abs() <= 1e-15 * data.total_error);
+    }
+
+    #[test]
+    fn instructions_on_storage_blocks() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let architecture = PathArchitecture::new(1).with_storage_blocks(1);
+        let ops = vec![
+            vec![
+                (0, BicycleISA::JointMeasure(z)),
+                (1, BicycleISA::JointMeasure(z)),
+            ],
+            vec![(1, BicycleISA::Measure(z))],
+        ];
+        let data = run_numerics(std::iter::once(ops), architecture, model)
+            .last()
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, data.measurement_depth);
+        assert_eq!(1, data.joint_measurements);
+        assert_eq!(1, data.measurements);
+    }
+
+    #[test]
+    fn grow_architecture() {
+        let model = model::GROSS_1E3;
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let chunks = vec![
+            vec![vec![(0, BicycleISA::Measure(z))]],
+            vec![
+                vec![(1, BicycleISA::CSSInitZero)],
+                vec![
+                    (0, BicycleISA::JointMeasure(z)),
+                    (1, BicycleISA::JointMeasure(z)),
+                ],
+            ],
+        ];
+        let data: Vec<_> = run_numerics(chunks.into_iter(), PathArchitecture::new(1), model)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![QUBITS_PER_BLOCK, 2 * QUBITS_PER_BLOCK],
+            data.iter().map(|data| data.qubits).collect::<Vec<_>>()
+        );
+        assert_eq!(2, data[1].measurement_depth);
+        // The new block is initialized after the measurement on the first block
+        let end = model.timing(&BicycleISA::Measure(z))
+            + model.timing(&BicycleISA::CSSInitZero)
+            + model.timing(&BicycleISA::JointMeasure(z));
+        assert_eq!(end, data[1].end_time);
+    }
+
+    #[test]
+    fn count_instructions() {
+        let z = TwoBases::new(Pauli::Z, Pauli::I).unwrap();
+        let t_gate = BicycleISA::TGate(TGateData::new(Pauli::X, false, false).unwrap());
+        let aut = BicycleISA::Automorphism(bicycle_common::AutomorphismData::new(1, 1));
+        let chunk = vec![
+            vec![(0, aut)],
+            vec![(0, BicycleISA::Measure(z))],
+            vec![
+                (0, BicycleISA::JointMeasure(z)),
+                (1, BicycleISA::JointMeasure(z)),
+            ],
+            vec![(1, t_gate)],
+            vec![(
+                1,
+                BicycleISA::SGate(TGateData::new(Pauli::X, true, true).unwrap()),
+            )],
+            vec![(1, BicycleISA::SyndromeCycle)],
+        ];
+
+        let counter = IsaCounter::of_chunk(&chunk);
+        let expected = IsaCounter {
+            idles: 0,
+            t_injs: 1,
+            s_gates: 1,
+            automorphisms: 2,
+            measurements: 1,
+            joint_measurements: 1,
+            other: 1,
+        };
+        assert_eq!(expected, counter);
+
+        let totals: Vec<_> = IsaCounter::cumulative([&chunk[..2], &chunk[2..], &[]]).collect();
+        assert_eq!(3, totals.len());
+        assert_eq!(2, totals[0].automorphisms);
+        assert_eq!(0, totals[0].joint_measurements);
+        assert_eq!(expected, totals[1]);
+        assert_eq!(expected, totals[2]);
+
+        let summed: IsaCounter = [counter, counter].into_iter().sum();
+        assert_eq!(2 * expected.automorphisms, summed.automorphisms);
+        assert_eq!(2 * expected.other, summed.other);
+    }
+
+    /// Every instruction takes the same time and has the same error
+    struct UniformModel {
+        cycles: u64,
+        error: model::ErrorPrecision,
+    }
+
+    impl InstructionCost for UniformModel {
+        fn cycles(&self, _: model::InstructionClass) -> u64 {
+            self.cycles
         }
 
-        // Calculate the max depth currently
-        let measurement_depth = depths.iter().max().unwrap();
-        let end_time = times.iter().max().unwrap();
-
-        OutputData {
-            i: i + 1,
-            qubits,
-            idles: counter.idles,
-            t_injs: counter.t_injs,
-            automorphisms: counter.automorphisms,
-            measurements: counter.measurements,
-            joint_measurements: counter.joint_measurements,
-            measurement_depth: *measurement_depth,
-            end_time: *end_time,
-            total_error: total_error.to_num(),
+        fn error(&self, _: model::InstructionClass) -> model::ErrorPrecision {
+            self.error
         }
-    })
+
+        fn idling_error(&self, _: u64) -> (u64, model::ErrorPrecision) {
+            (0, model::ErrorPrecision::ZERO)
+        }
+    }
+
+    fn measurements(count: usize) -> impl Iterator<Item = Vec<Operation>> {
+        let measure = BicycleISA::Measure(TwoBases::new(Pauli::X, Pauli::I).unwrap());
+        (0..count).map(move |_| vec![vec![(0, measure)]])
+    }
+
+    #[test]
+    fn trace_instructions() {
+        let model = UniformModel {
+            cycles: 2,
+            error: model::ErrorPrecision::ZERO,
+        };
+        let mut traced = vec![];
+        let data: Vec<_> = run_numerics_traced(
+            measurements(3),
+            PathArchitecture::new(1),
+            model,
+            Accumulation::default(),
+            |instruction| traced.push(instruction),
+        )
+        .collect();
+        assert_eq!(3, data.len());
+
+        let starts: Vec<_> = traced
+            .iter()
+            .map(|instruction| (instruction.line, instruction.block, instruction.start))
+            .collect();
+        assert_eq!(vec![(1, 0, 0), (2, 0, 2), (3, 0, 4)], starts);
+    }
+
+    #[test]
+    fn time_overflow() {
+        let model = UniformModel {
+            cycles: u64::MAX / 2,
+            error: model::ErrorPrecision::ZERO,
+        };
+        let data: Vec<_> = run_numerics(measurements(5), PathArchitecture::new(1), model).collect();
+
+        // The third measurement does not fit in the time, and the numerics stop there
+        assert_eq!(3, data.len());
+        assert_eq!(u64::MAX - 1, data[1].as_ref().unwrap().end_time);
+        assert_eq!(
+            Some(&NumericsError::Overflow {
+                line: 3,
+                quantity: "time"
+            }),
+            data[2].as_ref().err()
+        );
+    }
+
+    #[test]
+    fn total_error_overflow() {
+        // The fixed-point total error overflows at 2^32
+        let model = UniformModel {
+            cycles: 1,
+            error: model::ErrorPrecision::lit("1e9"),
+        };
+        let last = run_numerics(measurements(5), PathArchitecture::new(1), model)
+            .last()
+            .unwrap();
+        assert_eq!(
+            Some(NumericsError::Overflow {
+                line: 5,
+                quantity: "total error"
+            }),
+            last.err()
+        );
+    }
 }
@@ -12,19 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{env, error::Error, io};
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    error::Error,
+    fs::File,
+    io::{self, Write},
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+};
 
 use bicycle_numerics::{
     OutputData,
-    model::{FAKE_SLOW, GROSS_1E3, GROSS_1E4, Model, TWO_GROSS_1E3, TWO_GROSS_1E4},
+    accumulate::Accumulation,
+    bandwidth::BandwidthMeter,
+    breakdown::{CostCategory, TimeBreakdown},
+    model::{
+        FAKE_SLOW, GROSS_1E3, GROSS_1E4, InstructionClass, Model, ModelFile, TWO_GROSS_1E3,
+        TWO_GROSS_1E4,
+    },
+    predict::{Predictor, Verdict},
+    sim::FaultSampler,
+    trace::TraceFilter,
+    window::{RollingWindow, WindowStats},
 };
-use log::{debug, trace};
+use log::{debug, info, trace};
 
-use bicycle_compiler::operation::Operation;
+use bicycle_cliffords::{CompleteMeasurementTable, MeasurementChoices};
+use bicycle_common::alloc;
+use bicycle_compiler::{
+    Topology,
+    export::StimExporter,
+    language::{AnglePrecision, PbcOperation},
+    operation::{CompiledProgram, Operation, OutputFormat},
+    sweep,
+};
 use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 use serde_json::Deserializer;
 
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOC: bicycle_common::alloc::CountingAllocator = bicycle_common::alloc::CountingAllocator;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
 enum ModelChoices {
     /// Gross codes with physical noise rate p=10^-3
@@ -54,11 +84,22 @@ impl ModelChoices {
             Self::FakeSlow => FAKE_SLOW,
         }
     }
+
+    /// The code and physical noise rate that label the output
+    fn label(self) -> (&'static str, f64) {
+        match self {
+            Self::Gross1e3 => ("gross", 1e-3),
+            Self::Gross1e4 => ("gross", 1e-4),
+            Self::TwoGross1e3 => ("two-gross", 1e-3),
+            Self::TwoGross1e4 => ("two-gross", 1e-4),
+            Self::FakeSlow => ("fake", 0.0),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
-struct Output {
-    code: &'static str,
+struct Output<'a> {
+    code: &'a str,
     p: f64,
     i: usize,
     qubits: usize,
@@ -68,20 +109,47 @@ struct Output {
     measurements: u64,
     joint_measurements: u64,
     measurement_depth: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<u64>,
     end_time: u64,
     total_error: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_error_per_1k_ops: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_t_per_1k_cycles: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shift_error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_module_error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inter_module_error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    magic_error: Option<f64>,
+    /// The logical error rate that `--sample` estimates for the program up to the last line, which the other lines leave empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampled_error: Option<Option<f64>>,
+    /// The bounds of the 95% confidence interval of the sampled error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampled_error_low: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampled_error_high: Option<Option<f64>>,
+    /// Peak bytes allocated so far by the numerics, with the `memory-profile` feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak_memory_bytes: Option<usize>,
 }
 
-impl Output {
-    pub fn new(model: ModelChoices, data: OutputData) -> Self {
-        let (code, p) = match model {
-            ModelChoices::Gross1e3 => ("gross", 1e-3),
-            ModelChoices::Gross1e4 => ("gross", 1e-4),
-            ModelChoices::TwoGross1e3 => ("two-gross", 1e-3),
-            ModelChoices::TwoGross1e4 => ("two-gross", 1e-4),
-            ModelChoices::FakeSlow => ("fake", 0.0),
-        };
-
+impl<'a> Output<'a> {
+    pub fn new(
+        code: &'a str,
+        p: f64,
+        data: OutputData,
+        window: Option<WindowStats>,
+        steps: bool,
+        breakdown: bool,
+    ) -> Self {
+        let error = |category| breakdown.then(|| data.breakdown.error(category));
         Self {
             code,
             p,
@@ -93,25 +161,139 @@ impl Output {
             measurements: data.measurements,
             joint_measurements: data.joint_measurements,
             measurement_depth: data.measurement_depth,
+            steps: steps.then_some(data.steps),
             end_time: data.end_time,
             total_error: data.total_error,
+            window_error_per_1k_ops: window.map(|stats| stats.error_per_1k_ops),
+            window_t_per_1k_cycles: window.map(|stats| stats.t_per_1k_cycles),
+            idle_error: error(CostCategory::Idle),
+            shift_error: error(CostCategory::Shift),
+            in_module_error: error(CostCategory::InModule),
+            inter_module_error: error(CostCategory::InterModule),
+            magic_error: error(CostCategory::Magic),
+            sampled_error: None,
+            sampled_error_low: None,
+            sampled_error_high: None,
+            peak_memory_bytes: alloc::is_counting().then(alloc::peak_bytes),
         }
     }
 }
 
+/// The output format of --pareto
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, ValueEnum)]
+enum ParetoFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about=None)]
 struct Cli {
     /// Number of logical qubits in the input circuit (do not include pivot ancillas).
     qubits: usize,
     /// Choose which architecture the circuit is run on.
-    model: ModelChoices,
+    #[arg(required_unless_present_any = ["model_file", "compare", "pareto"])]
+    model: Option<ModelChoices>,
+    /// Read the cost of each instruction from a JSON or TOML model file instead of a built-in model.
+    #[arg(long, conflicts_with = "model")]
+    model_file: Option<PathBuf>,
     /// Set a limit to the error rate when the numerics should halt
     #[arg(short = 'e', long)]
     max_error: Option<f64>,
     /// Set a limit to the number of input lines (PBC gates) before halting.
     #[arg(short = 'i', long)]
     max_iter: Option<usize>,
+    /// Number of threads that parse the input. Values above 1 require newline-delimited input.
+    #[arg(short = 'j', long, default_value_t = 1)]
+    jobs: usize,
+    /// The encoding of the compiled program on stdin, as written by `bicycle_compiler --output-format`.
+    /// MessagePack and CBOR documents are read as a whole before the numerics start
+    #[arg(long, value_enum, default_value_t)]
+    input_format: OutputFormat,
+    /// How to accumulate the total error
+    #[arg(long, value_enum, default_value_t)]
+    accumulation: Accumulation,
+    /// Number of blocks next to the factory that store magic states prepared ahead of time
+    #[arg(long, default_value_t = 0)]
+    storage_blocks: usize,
+    /// How the data blocks are connected, as in `bicycle_compiler --topology`
+    #[arg(long, value_enum, default_value_t)]
+    topology: Topology,
+    /// Add the error per 1000 input lines and the T gates per 1000 cycles over the last WINDOW input lines
+    #[arg(short = 'w', long)]
+    window: Option<NonZeroUsize>,
+    /// Number of input lines of the whole circuit. Stop early once the extrapolated total error is clearly above or below the max error
+    #[arg(long, requires = "max_error")]
+    predict_lines: Option<usize>,
+    /// Width of the confidence band of the prediction in standard errors
+    #[arg(long, default_value_t = 3.0, requires = "predict_lines")]
+    prediction_sigmas: f64,
+    /// Read a PBC program instead, in the input format of bicycle_compiler, compile it for both codes,
+    /// and write the totals of every built-in model side by side, one row per model
+    #[arg(long, requires_all = ["gross_table", "two_gross_table"], conflicts_with = "model_file")]
+    compare: bool,
+    /// Read a PBC program like --compare, compile it for both codes and a range of synthesis accuracies,
+    /// and write the Pareto front of the total error, end time, T count, and physical qubits
+    #[arg(long, requires_all = ["gross_table", "two_gross_table"], conflicts_with_all = ["model_file", "compare"])]
+    pareto: bool,
+    /// The measurement table of the gross code for --compare or --pareto
+    #[arg(long, value_name = "FILE")]
+    gross_table: Option<PathBuf>,
+    /// The measurement table of the two-gross code for --compare or --pareto
+    #[arg(long, value_name = "FILE")]
+    two_gross_table: Option<PathBuf>,
+    /// The accuracy of small angle synthesis for --compare
+    #[arg(long, default_value_t = AnglePrecision::lit("1e-9"))]
+    accuracy: AnglePrecision,
+    /// The largest accuracy of --pareto, at most 0.1
+    #[arg(long, default_value_t = AnglePrecision::lit("1e-3"))]
+    accuracy_from: AnglePrecision,
+    /// The smallest accuracy of --pareto
+    #[arg(long, default_value_t = AnglePrecision::lit("1e-12"))]
+    accuracy_to: AnglePrecision,
+    /// Number of accuracies per factor of 10 for --pareto
+    #[arg(long, default_value_t = 1)]
+    per_decade: usize,
+    /// Write the Pareto front as CSV or as JSON
+    #[arg(long, value_enum, default_value_t)]
+    pareto_format: ParetoFormat,
+    /// Print the instructions on these blocks with their start time and input line to stderr
+    #[arg(long, value_delimiter = ',')]
+    trace_block: Vec<usize>,
+    /// Print the instructions of these kinds (e.g., TGate) with their start time and input line to stderr
+    #[arg(long, value_delimiter = ',')]
+    trace_instr: Vec<InstructionClass>,
+    /// Write the average and peak rates of control instructions and measurement outcomes of every block to a CSV file
+    #[arg(long, value_name = "FILE")]
+    bandwidth: Option<PathBuf>,
+    /// Number of cycles over which the peak rates of --bandwidth are measured
+    #[arg(long, default_value_t = NonZeroU64::new(1000).unwrap(), requires = "bandwidth")]
+    bandwidth_bin: NonZeroU64,
+    /// Model T injections that are checked by a majority vote over this odd number of measurements,
+    /// for programs compiled with the same `bicycle_compiler --verify-t`
+    #[arg(long, value_name = "REPETITIONS", conflicts_with_all = ["compare", "pareto"])]
+    verified_t: Option<usize>,
+    /// Add the number of time steps as a column, where operations on disjoint blocks share a step
+    #[arg(long, conflicts_with_all = ["compare", "pareto"])]
+    steps: bool,
+    /// Add the error of idles, shifts, in-module, inter-module, and magic instructions as columns
+    #[arg(long, conflicts_with_all = ["compare", "pareto"])]
+    breakdown: bool,
+    /// Write the time that each block spends in each kind of instruction to a JSON file
+    #[arg(long, value_name = "FILE", requires = "breakdown")]
+    breakdown_times: Option<PathBuf>,
+    /// Write the compiled program as a Stim circuit on the logical qubits, with the noise of the model
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["compare", "pareto"])]
+    stim: Option<PathBuf>,
+    /// Logical fault sampling: also estimate the logical error rate of the program from this many shots of sampled logical faults,
+    /// which does not assume that every fault fails the program, unlike the total error. Circuit-level noise is not sampled.
+    /// The estimate and its 95% confidence interval are written to the last line
+    #[arg(long, value_name = "SHOTS", conflicts_with_all = ["compare", "pareto"])]
+    sample: Option<NonZeroUsize>,
+    /// Seed of the sampled faults, such that runs with the same seed sample the same shots [default: random]
+    #[arg(long, requires = "sample")]
+    sample_seed: Option<u64>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -124,18 +306,182 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
     trace!("Number of qubits: {}", cli.qubits);
-    let model = cli.model.model();
+    if cli.compare {
+        return compare(&cli);
+    }
+    if cli.pareto {
+        return pareto(&cli);
+    }
+    let (code, p, model) = match (cli.model, &cli.model_file) {
+        (_, Some(path)) => {
+            let file = ModelFile::from_file(path)?;
+            (file.code.clone(), file.p, file.model())
+        }
+        (Some(choice), None) => {
+            let (code, p) = choice.label();
+            (code.to_string(), p, choice.model())
+        }
+        (None, None) => unreachable!("Either a model or a model file is required"),
+    };
+    let model = match cli.verified_t {
+        Some(repetitions) if repetitions % 2 == 0 => {
+            return Err(format!(
+                "A majority vote needs an odd number of repetitions, not {repetitions}"
+            )
+            .into());
+        }
+        Some(repetitions) => model.with_verified_t(repetitions),
+        None => model,
+    };
+
+    let ops: Box<dyn Iterator<Item = io::Result<Vec<Operation>>>> =
+        if cli.input_format != OutputFormat::Jsonl {
+            if cli.jobs > 1 {
+                return Err("Only JSON lines can be parsed by several threads".into());
+            }
+            let program = CompiledProgram::read(io::stdin().lock(), cli.input_format)?;
+            info!(
+                "Read version {} of a program for {} {} blocks",
+                program.version, program.architecture.data_blocks, program.architecture.code
+            );
+            Box::new(program.chunks.into_iter().map(Ok))
+        } else if cli.jobs > 1 {
+            let reader = io::BufReader::new(io::stdin());
+            Box::new(bicycle_numerics::parse::parse_lines_parallel(
+                reader, cli.jobs,
+            ))
+        } else {
+            let reader = io::stdin().lock();
+
+            // Support some streaming input from Stdin
+            // The following works for (a weird version of) JSON:
+            let de = Deserializer::from_reader(reader);
+            Box::new(de.into_iter::<Vec<Operation>>().map(|op| Ok(op?)))
+        };
+
+    let architecture = bicycle_compiler::PathArchitecture::for_qubits(cli.qubits)
+        .with_storage_blocks(cli.storage_blocks);
+
+    // Stop at the first instruction that does not fit the architecture,
+    // which grows when the compiler initializes a block after the last one
+    let invalid = Cell::new(None);
+    let mut grown = architecture.clone();
+    let mut connected = cli.topology.connect(grown.clone());
+    let ops = ops.enumerate().map_while(|(i, chunk)| {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                invalid.set(Some(format!("Input line {} cannot be read: {e}", i + 1)));
+                return None;
+            }
+        };
+        let invalid_op = chunk.iter().find(|op| {
+            if grown.grow_for(op) {
+                connected = cli.topology.connect(grown.clone());
+                return false;
+            }
+            !connected.validate_operation(op)
+        });
+        match invalid_op {
+            Some(op) => {
+                invalid.set(Some(format!(
+                    "Operation {op:?} of input line {} does not fit the architecture of {} blocks",
+                    i + 1,
+                    grown.data_blocks()
+                )));
+                None
+            }
+            None => Some(chunk),
+        }
+    });
 
-    let reader = io::stdin().lock();
+    // Write the operations that the numerics process to the Stim circuit
+    let stim_model = model;
+    let stim = match &cli.stim {
+        Some(path) => {
+            let choice = MeasurementChoices::from_str(&code, true).map_err(|_| {
+                format!("A Stim circuit needs a model of the gross or two-gross code, not {code}")
+            })?;
+            let exporter = StimExporter::new(choice.measurement(), architecture.total_blocks())
+                .with_noise(&stim_model);
+            Some((io::BufWriter::new(File::create(path)?), exporter))
+        }
+        None => None,
+    };
+    let stim = RefCell::new(stim);
+    let stim_error = RefCell::new(None);
+    let ops = ops.inspect(|chunk| {
+        if let Some((writer, exporter)) = stim.borrow_mut().as_mut() {
+            for op in chunk {
+                if let Err(e) = writer.write_all(exporter.export(op).as_bytes()) {
+                    stim_error.replace(Some(e));
+                }
+            }
+        }
+    });
 
-    // Support some streaming input from Stdin
-    // The following works for (a weird version of) JSON:
-    let de = Deserializer::from_reader(reader);
-    let ops = de.into_iter::<Vec<Operation>>().map(|op| op.unwrap());
+    // Keep the line that the numerics process until its output, with the cycles that its instructions waited,
+    // and then add it to the sampled program
+    let sampler = match cli.sample {
+        Some(shots) => {
+            let choice = MeasurementChoices::from_str(&code, true).map_err(|_| {
+                format!("Sampling faults needs a model of the gross or two-gross code, not {code}")
+            })?;
+            let seed = cli.sample_seed.unwrap_or_else(rand::random);
+            Some(RefCell::new(FaultSampler::new(
+                choice.measurement(),
+                shots.get(),
+                seed,
+            )))
+        }
+        None => None,
+    };
+    let pending = RefCell::new((vec![], vec![]));
+    let ops = ops.inspect(|chunk| {
+        if sampler.is_some() {
+            pending.replace((chunk.clone(), vec![]));
+        }
+    });
 
-    let architecture = bicycle_compiler::PathArchitecture::for_qubits(cli.qubits);
+    let times = cli
+        .breakdown_times
+        .as_ref()
+        .map(|_| RefCell::new(TimeBreakdown::default()));
+    let meter = cli.bandwidth.as_ref().map(|_| {
+        RefCell::new(BandwidthMeter::new(
+            architecture.total_blocks(),
+            cli.bandwidth_bin.get(),
+        ))
+    });
 
-    let output_data = bicycle_numerics::run_numerics(ops, architecture, model);
+    // Stop at the first overflow
+    let overflow = Cell::new(None);
+    let tracing = !cli.trace_block.is_empty() || !cli.trace_instr.is_empty();
+    let filter = TraceFilter {
+        blocks: cli.trace_block.clone(),
+        classes: cli.trace_instr.clone(),
+    };
+    let output_data = bicycle_numerics::run_numerics_traced(
+        ops,
+        architecture,
+        model,
+        cli.accumulation,
+        |traced| {
+            if tracing && filter.matches(&traced) {
+                eprintln!("{traced}");
+            }
+            if let Some(meter) = &meter {
+                meter.borrow_mut().record(&traced);
+            }
+            if let Some(times) = &times {
+                times.borrow_mut().record(&traced, &model);
+            }
+            if sampler.is_some() {
+                pending.borrow_mut().1.push(traced.waited);
+            }
+        },
+    )
+    .map_while(|data| data.inspect_err(|e| overflow.set(Some(*e))).ok());
 
     // Stop when error exceeds set value (if set) or iterations gets too large (if set)
     let short_data = output_data
@@ -145,10 +491,220 @@ fn main() -> Result<(), Box<dyn Error>> {
         })
         .take_while(|data| cli.max_iter.is_none_or(|max_iter| data.i <= max_iter));
 
-    let mut outputs = short_data.map(|data| Output::new(cli.model, data));
+    // Stop after the line that makes the outcome clear
+    let mut predictor = cli
+        .predict_lines
+        .map(|lines| Predictor::new(lines, cli.max_error.unwrap(), cli.prediction_sigmas));
+    let mut prediction = None;
+    let short_data = short_data.take_while(|data| {
+        if prediction.is_some() {
+            return false;
+        }
+        prediction = predictor
+            .as_mut()
+            .and_then(|predictor| predictor.push(data));
+        true
+    });
+
+    let factory_time = Cell::new(0);
+    let storage_idles = Cell::new(0);
+    let end_time = Cell::new(0);
+    let mut window = cli.window.map(|size| RollingWindow::new(size.get()));
+    let mut outputs = short_data
+        .inspect(|data| {
+            factory_time.set(data.factory_time);
+            end_time.set(data.end_time);
+            storage_idles.set(storage_idles.get() + data.storage_idles);
+            if let Some(sampler) = &sampler {
+                let (chunk, waits) = pending.take();
+                sampler.borrow_mut().push(&chunk, &waits, &model);
+            }
+        })
+        .map(|data| {
+            let stats = window.as_mut().map(|window| window.push(&data));
+            let mut output = Output::new(&code, p, data, stats, cli.steps, cli.breakdown);
+            if sampler.is_some() {
+                output.sampled_error = Some(None);
+                output.sampled_error_low = Some(None);
+                output.sampled_error_high = Some(None);
+            }
+            output
+        });
     let mut wtr = csv::Writer::from_writer(io::stdout());
-    let err = outputs.try_for_each(|output| wtr.serialize(output));
+    // The sampled error rate of the program goes into the last line, so hold back each line until the next one
+    let mut last = None;
+    let mut err = outputs.try_for_each(|output| match &sampler {
+        Some(_) => last
+            .replace(output)
+            .map_or(Ok(()), |line| wtr.serialize(line)),
+        None => wtr.serialize(output),
+    });
+    if let (Some(mut output), Some(shots), Some(sampler)) = (last, cli.sample, &sampler) {
+        let sampler = sampler.borrow();
+        let rate = sampler.rate();
+        let (low, high) = rate.confidence_interval(1.96);
+        info!(
+            "Sampled a logical error rate of {} in {shots} shots, with the 95% confidence interval [{low}, {high}], against the union bound {}",
+            rate.rate(),
+            sampler.union_bound()
+        );
+        output.sampled_error = Some(Some(rate.rate()));
+        output.sampled_error_low = Some(Some(low));
+        output.sampled_error_high = Some(Some(high));
+        err = err.and_then(|_| wtr.serialize(output));
+    }
     debug!("Exited with {err:?}");
+    info!(
+        "The busiest magic state factory was occupied for {} cycles",
+        factory_time.get()
+    );
+    if cli.storage_blocks > 0 {
+        info!(
+            "Stored magic states idled for {} idle periods",
+            storage_idles.get()
+        );
+    }
+    if let (Some(path), Some(meter)) = (&cli.bandwidth, &meter) {
+        let rows = meter.borrow().rows(end_time.get());
+        let total = rows.last().unwrap();
+        info!(
+            "Control bandwidth of all blocks per 1000 cycles: {:.2} instructions and {:.2} measurement outcomes on average, {:.2} and {:.2} at peak",
+            total.average_instructions_per_1k_cycles,
+            total.average_outcomes_per_1k_cycles,
+            total.peak_instructions_per_1k_cycles,
+            total.peak_outcomes_per_1k_cycles
+        );
+        let mut wtr = csv::Writer::from_path(path)?;
+        for row in rows {
+            wtr.serialize(row)?;
+        }
+        wtr.flush()?;
+    }
+    if let (Some(path), Some(times)) = (&cli.breakdown_times, &times) {
+        let writer = io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, times.borrow().blocks())?;
+    }
+    if let Some((mut writer, _)) = stim.take() {
+        writer.flush()?;
+    }
+    if let Some(e) = stim_error.take() {
+        return Err(e.into());
+    }
+    alloc::report_memory("numerics");
+    if let Some(prediction) = prediction {
+        let verdict = match prediction.verdict {
+            Verdict::Exceeds => "exceed",
+            Verdict::WithinBudget => "stay within",
+        };
+        info!(
+            "Stopped early: after {} of {} input lines, the total error is predicted to {verdict} the max error {}",
+            prediction.observed,
+            prediction.lines,
+            cli.max_error.unwrap()
+        );
+        info!(
+            "Predicted total error {} and end time {}",
+            prediction.total_error, prediction.end_time
+        );
+    }
+
+    if let Some(overflow) = overflow.get() {
+        return Err(overflow.into());
+    }
+    match invalid.take() {
+        Some(invalid) => Err(invalid.into()),
+        None => Ok(()),
+    }
+}
 
+/// Read the PBC program on stdin and the measurement tables of both codes
+fn read_program(
+    cli: &Cli,
+) -> Result<
+    (
+        Vec<PbcOperation>,
+        CompleteMeasurementTable,
+        CompleteMeasurementTable,
+    ),
+    Box<dyn Error>,
+> {
+    let ops = Deserializer::from_reader(io::stdin().lock())
+        .into_iter::<PbcOperation>()
+        .collect::<Result<Vec<_>, _>>()?;
+    let gross = bicycle_compiler::deserialize_table(cli.gross_table.as_ref().unwrap())?;
+    let two_gross = bicycle_compiler::deserialize_table(cli.two_gross_table.as_ref().unwrap())?;
+    alloc::report_memory("measurement tables");
+    Ok((ops, gross, two_gross))
+}
+
+/// Compile the PBC program on stdin for both codes and write the totals of every built-in model
+fn compare(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let (ops, gross, two_gross) = read_program(cli)?;
+    info!("Comparing the codes on {} operations", ops.len());
+
+    let comparisons = bicycle_numerics::compare::compare_codes(
+        &ops,
+        cli.qubits,
+        [
+            (MeasurementChoices::Gross, &gross),
+            (MeasurementChoices::TwoGross, &two_gross),
+        ],
+        cli.accuracy,
+    )?;
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    for comparison in comparisons {
+        wtr.serialize(comparison)?;
+    }
+    wtr.flush()?;
+    alloc::report_memory("comparison");
+    Ok(())
+}
+
+/// Compile the PBC program on stdin for both codes over a range of accuracies and write the Pareto front
+fn pareto(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let (from, to) = (cli.accuracy_from, cli.accuracy_to);
+    if !(AnglePrecision::ZERO < to && to <= from && from <= AnglePrecision::lit("0.1")) {
+        return Err(format!("The accuracies should satisfy 0 < {to} <= {from} <= 0.1").into());
+    }
+    if cli.per_decade == 0 {
+        return Err("There should be at least one accuracy per decade".into());
+    }
+    let (ops, gross, two_gross) = read_program(cli)?;
+    let accuracies = sweep::accuracies(from.to_num(), to.to_num(), cli.per_decade);
+    info!(
+        "Sweeping {} accuracies of both codes on {} operations",
+        accuracies.len(),
+        ops.len()
+    );
+
+    let points = bicycle_numerics::pareto::sweep_codes(
+        &ops,
+        cli.qubits,
+        [
+            (MeasurementChoices::Gross, &gross),
+            (MeasurementChoices::TwoGross, &two_gross),
+        ],
+        &accuracies,
+    )?;
+    let front = bicycle_numerics::pareto::pareto_front(&points);
+    info!(
+        "{} of {} points are on the Pareto front",
+        front.len(),
+        points.len()
+    );
+    match cli.pareto_format {
+        ParetoFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            for point in front {
+                wtr.serialize(point)?;
+            }
+            wtr.flush()?;
+        }
+        ParetoFormat::Json => {
+            serde_json::to_writer_pretty(io::stdout(), &front)?;
+            println!();
+        }
+    }
+    alloc::report_memory("Pareto sweep");
     Ok(())
 }
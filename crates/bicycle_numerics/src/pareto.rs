@@ -0,0 +1,152 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Trade-offs between the total error, the end time, the T count, and the qubit count of a program
+//! over the synthesis accuracy and the choice of code.
+
+use bicycle_cliffords::{CompleteMeasurementTable, MeasurementChoices};
+use bicycle_compiler::{
+    PathArchitecture,
+    language::{AnglePrecision, PbcOperation},
+};
+use serde::Serialize;
+
+use crate::{NumericsError, compare};
+
+/// The costs of a program for one accuracy under one built-in model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParetoPoint {
+    pub code: MeasurementChoices,
+    pub p: f64,
+    pub accuracy: f64,
+    pub total_error: f64,
+    pub end_time: u64,
+    pub t_count: u64,
+    pub blocks: usize,
+    /// The physical qubits of all blocks, without the factories
+    pub physical_qubits: usize,
+}
+
+impl ParetoPoint {
+    /// Whether this point is at most as costly as `other` in every objective and less costly in one
+    pub fn dominates(&self, other: &Self) -> bool {
+        let at_most = self.total_error <= other.total_error
+            && self.end_time <= other.end_time
+            && self.t_count <= other.t_count
+            && self.physical_qubits <= other.physical_qubits;
+        let less = self.total_error < other.total_error
+            || self.end_time < other.end_time
+            || self.t_count < other.t_count
+            || self.physical_qubits < other.physical_qubits;
+        at_most && less
+    }
+}
+
+/// Compile the program for every accuracy with the measurement table of each code,
+/// and compute its costs under each built-in model of the code.
+/// The tables are shared between the accuracies.
+pub fn sweep_codes(
+    ops: &[PbcOperation],
+    qubits: usize,
+    tables: [(MeasurementChoices, &CompleteMeasurementTable); 2],
+    accuracies: &[AnglePrecision],
+) -> Result<Vec<ParetoPoint>, NumericsError> {
+    let mut points = vec![];
+    for &accuracy in accuracies {
+        for comparison in compare::compare_codes(ops, qubits, tables, accuracy)? {
+            let blocks =
+                PathArchitecture::for_qubits_per_block(qubits, comparison.code.qubits_per_block())
+                    .total_blocks();
+            points.push(ParetoPoint {
+                code: comparison.code,
+                p: comparison.p,
+                accuracy: accuracy.to_num(),
+                total_error: comparison.total_error,
+                end_time: comparison.end_time,
+                t_count: comparison.t_injs,
+                blocks,
+                physical_qubits: blocks * comparison.code.physical_qubits_per_block(),
+            });
+        }
+    }
+    Ok(points)
+}
+
+/// The points that no other point dominates, in their original order
+pub fn pareto_front(points: &[ParetoPoint]) -> Vec<ParetoPoint> {
+    points
+        .iter()
+        .filter(|point| !points.iter().any(|other| other.dominates(point)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(accuracy: f64, total_error: f64, end_time: u64, t_count: u64) -> ParetoPoint {
+        ParetoPoint {
+            code: MeasurementChoices::Gross,
+            p: 1e-3,
+            accuracy,
+            total_error,
+            end_time,
+            t_count,
+            blocks: 2,
+            physical_qubits: 576,
+        }
+    }
+
+    #[test]
+    fn nondominated_points() {
+        let points = [
+            point(1e-3, 0.2, 100, 10),
+            // More accurate synthesis costs more T gates and time, but has a lower error
+            point(1e-6, 0.1, 200, 30),
+            // Dominated by the first point
+            point(1e-4, 0.3, 150, 20),
+            // Equal points do not dominate each other
+            point(1e-3, 0.2, 100, 10),
+        ];
+        assert!(points[0].dominates(&points[2]));
+        assert!(!points[0].dominates(&points[3]));
+        assert_eq!(
+            vec![points[0].clone(), points[1].clone(), points[3].clone()],
+            pareto_front(&points)
+        );
+    }
+
+    #[test]
+    fn qubits_of_codes() {
+        let table = CompleteMeasurementTable::from_shards(Default::default(), []);
+        let accuracies = [AnglePrecision::lit("1e-3"), AnglePrecision::lit("1e-6")];
+        let points = sweep_codes(
+            &[],
+            22,
+            [
+                (MeasurementChoices::Gross, &table),
+                (MeasurementChoices::TwoGross, &table),
+            ],
+            &accuracies,
+        )
+        .unwrap();
+        assert_eq!(8, points.len());
+        let gross = &points[0];
+        let two_gross = &points[2];
+        assert_eq!(MeasurementChoices::TwoGross, two_gross.code);
+        assert_eq!(2 * gross.physical_qubits, two_gross.physical_qubits);
+        assert!((points[4].accuracy - 1e-6).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread,
+};
+
+use log::debug;
+use serde::de::DeserializeOwned;
+
+/// Number of lines that a worker parses at a time
+const BATCH_SIZE: usize = 256;
+
+type Batch<T> = (usize, Vec<io::Result<T>>);
+
+/// Parse newline-delimited JSON values using a pool of `jobs` worker threads.
+///
+/// Lines are read and parsed in batches and the results are returned in input order,
+/// so the output can be consumed as a stream. Empty lines are skipped.
+/// A line that cannot be read or parsed is returned as an error, and reading stops at the first I/O error.
+/// At most `4 * jobs` batches are read ahead of the consumer,
+/// so the batches that wait for a slower one do not grow with the input.
+pub fn parse_lines_parallel<T, R>(reader: R, jobs: usize) -> impl Iterator<Item = io::Result<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+    R: BufRead + Send + 'static,
+{
+    assert!(jobs > 0, "Need at least one worker to parse input");
+    debug!("Parsing input with {jobs} workers");
+
+    // The reader takes a token for each batch, and the consumer returns it once the batch is consumed
+    let window = 4 * jobs;
+    let (token_tx, token_rx) = mpsc::sync_channel::<()>(window);
+    for _ in 0..window {
+        token_tx.send(()).unwrap();
+    }
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<(usize, Vec<io::Result<String>>)>(window);
+    let (parsed_tx, parsed_rx) = mpsc::sync_channel::<Batch<T>>(window);
+
+    thread::spawn(move || {
+        let mut lines = reader
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()));
+        let mut failed = false;
+        for batch_i in 0.. {
+            if failed || token_rx.recv().is_err() {
+                break;
+            }
+            let mut batch = vec![];
+            for line in lines.by_ref().take(BATCH_SIZE) {
+                failed = line.is_err();
+                batch.push(line);
+                if failed {
+                    break;
+                }
+            }
+            if batch.is_empty() || batch_tx.send((batch_i, batch)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let batch_rx = Arc::new(Mutex::new(batch_rx));
+    for _ in 0..jobs {
+        let batch_rx = Arc::clone(&batch_rx);
+        let parsed_tx = parsed_tx.clone();
+        thread::spawn(move || {
+            loop {
+                // Release the lock before parsing so other workers can take batches.
+                let received = batch_rx.lock().unwrap().recv();
+                let Ok((batch_i, lines)) = received else {
+                    break;
+                };
+                let parsed = lines
+                    .into_iter()
+                    .map(|line| Ok(serde_json::from_str(&line?)?))
+                    .collect();
+                if parsed_tx.send((batch_i, parsed)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(parsed_tx);
+
+    Reorder::new(parsed_rx, token_tx).flatten()
+}
+
+/// Return batches in order of their index, buffering those that arrive early.
+struct Reorder<T> {
+    receiver: Receiver<Batch<T>>,
+    /// Returns the token of each batch to the reader once the batch is consumed
+    tokens: SyncSender<()>,
+    pending: HashMap<usize, Vec<io::Result<T>>>,
+    next: usize,
+}
+
+impl<T> Reorder<T> {
+    fn new(receiver: Receiver<Batch<T>>, tokens: SyncSender<()>) -> Self {
+        Self {
+            receiver,
+            tokens,
+            pending: HashMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T> Iterator for Reorder<T> {
+    type Item = Vec<io::Result<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(batch) = self.pending.remove(&self.next) {
+                self.next += 1;
+                // The reader has stopped if it does not take tokens anymore
+                let _ = self.tokens.try_send(());
+                return Some(batch);
+            }
+            let (batch_i, batch) = self.receiver.recv().ok()?;
+            self.pending.insert(batch_i, batch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufReader, Cursor, Read},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Fails after its input, and counts the bytes that were read
+    struct Failing {
+        input: Cursor<String>,
+        read: Arc<AtomicUsize>,
+    }
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.input.read(buf)?;
+            if n == 0 && !buf.is_empty() {
+                return Err(io::Error::other("the input broke"));
+            }
+            self.read.fetch_add(n, Ordering::Relaxed);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn preserves_order() {
+        let n = 10 * BATCH_SIZE + 3;
+        let input: String = (0..n).map(|i| format!("[{i},{}]\n", 2 * i)).collect();
+
+        let parsed: Vec<Vec<usize>> = parse_lines_parallel(Cursor::new(input), 4)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(n, parsed.len());
+        for (i, line) in parsed.iter().enumerate() {
+            assert_eq!(&vec![i, 2 * i], line);
+        }
+    }
+
+    #[test]
+    fn skips_empty_lines_and_reports_errors() {
+        let input = "[1]\n\n[2]\nnot json\n";
+        let parsed: Vec<Result<Vec<usize>, _>> =
+            parse_lines_parallel(Cursor::new(input), 2).collect();
+
+        assert_eq!(3, parsed.len());
+        assert_eq!(vec![1], *parsed[0].as_ref().unwrap());
+        assert_eq!(vec![2], *parsed[1].as_ref().unwrap());
+        assert!(parsed[2].is_err());
+    }
+
+    #[test]
+    fn reports_read_errors() {
+        let input = Failing {
+            input: Cursor::new("[1]\n[2]\n".to_string()),
+            read: Arc::default(),
+        };
+        let parsed: Vec<io::Result<Vec<usize>>> =
+            parse_lines_parallel(BufReader::new(input), 2).collect();
+
+        assert_eq!(3, parsed.len());
+        assert_eq!(vec![2], *parsed[1].as_ref().unwrap());
+        assert_eq!(
+            "the input broke",
+            parsed[2].as_ref().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn bounds_read_ahead() {
+        let line = "[1]\n";
+        let read = Arc::new(AtomicUsize::new(0));
+        let input = Failing {
+            input: Cursor::new(line.repeat(100 * BATCH_SIZE)),
+            read: Arc::clone(&read),
+        };
+        let jobs = 2;
+        let mut parsed = parse_lines_parallel::<Vec<usize>, _>(BufReader::new(input), jobs);
+        assert_eq!(vec![1], parsed.next().unwrap().unwrap());
+        thread::sleep(Duration::from_millis(100));
+
+        // The consumed batch, the batches in flight, and the buffer of the reader
+        let bound = (4 * jobs + 1) * BATCH_SIZE * line.len() + 8 * 1024;
+        assert!(read.load(Ordering::Relaxed) <= bound);
+    }
+}
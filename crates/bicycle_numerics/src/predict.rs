@@ -0,0 +1,231 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extrapolate the total error and time of a program from its first input lines,
+//! to stop the numerics once it is clear whether the error budget will be exceeded.
+//!
+//! The error and time that each input line adds are treated as independent samples of a fixed distribution.
+//! The extrapolation adds the mean increment for every remaining line,
+//! and the confidence band is a number of standard errors of that mean, scaled to the remaining lines.
+//! Programs whose stages have very different costs violate this assumption,
+//! so the prediction is only as good as the first lines are representative.
+
+use std::fmt;
+
+use crate::OutputData;
+
+/// Do not predict from fewer input lines than this
+pub const MIN_LINES: usize = 100;
+
+/// Running mean and variance of a stream of samples (Welford's algorithm)
+#[derive(Debug, Clone, Copy, Default)]
+struct Trend {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Trend {
+    fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
+
+    /// Extrapolate `current` by `remaining` more samples, with a band of `sigmas` standard errors
+    fn extrapolate(&self, current: f64, remaining: usize, sigmas: f64) -> Estimate {
+        let remaining = remaining as f64;
+        let value = current + remaining * self.mean;
+        let half_width = sigmas * self.std_dev() * remaining / (self.count as f64).sqrt();
+        Estimate {
+            value,
+            // The totals never decrease
+            low: (value - half_width).max(current),
+            high: value + half_width,
+        }
+    }
+}
+
+/// An extrapolated value with its confidence band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.4e} [{:.4e}, {:.4e}]",
+            self.value, self.low, self.high
+        )
+    }
+}
+
+/// Whether the program will exceed the error budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Exceeds,
+    WithinBudget,
+}
+
+/// The extrapolated outcome of the whole program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    pub verdict: Verdict,
+    /// Number of input lines that the prediction is based on
+    pub observed: usize,
+    /// Number of input lines of the whole program
+    pub lines: usize,
+    pub total_error: Estimate,
+    pub end_time: Estimate,
+}
+
+/// Predicts the outcome of a program of `lines` input lines against an error `budget`
+#[derive(Debug, Clone)]
+pub struct Predictor {
+    lines: usize,
+    budget: f64,
+    sigmas: f64,
+    error: Trend,
+    time: Trend,
+    last: Option<(f64, u64)>,
+}
+
+impl Predictor {
+    /// Predict with a confidence band of `sigmas` standard errors
+    pub fn new(lines: usize, budget: f64, sigmas: f64) -> Self {
+        Self {
+            lines,
+            budget,
+            sigmas,
+            error: Trend::default(),
+            time: Trend::default(),
+            last: None,
+        }
+    }
+
+    /// Add the numerics of the next input line.
+    /// Returns a prediction once the whole confidence band of the total error is on one side of the budget.
+    pub fn push(&mut self, data: &OutputData) -> Option<Prediction> {
+        let (last_error, last_time) = self.last.unwrap_or_default();
+        self.error.push(data.total_error - last_error);
+        self.time.push((data.end_time - last_time) as f64);
+        self.last = Some((data.total_error, data.end_time));
+
+        let observed = self.error.count;
+        if observed < MIN_LINES || observed >= self.lines {
+            return None;
+        }
+        let remaining = self.lines - observed;
+        let total_error = self
+            .error
+            .extrapolate(data.total_error, remaining, self.sigmas);
+        let verdict = if total_error.low > self.budget {
+            Verdict::Exceeds
+        } else if total_error.high <= self.budget {
+            Verdict::WithinBudget
+        } else {
+            return None;
+        };
+        Some(Prediction {
+            verdict,
+            observed,
+            lines: self.lines,
+            total_error,
+            end_time: self
+                .time
+                .extrapolate(data.end_time as f64, remaining, self.sigmas),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(i: usize, end_time: u64, total_error: f64) -> OutputData {
+        OutputData {
+            i,
+            qubits: 11,
+            idles: 0,
+            t_injs: 0,
+            automorphisms: 0,
+            measurements: 0,
+            joint_measurements: 0,
+            measurement_depth: 0,
+            steps: 0,
+            end_time,
+            factory_time: 0,
+            storage_idles: 0,
+            total_error,
+            breakdown: Default::default(),
+        }
+    }
+
+    /// Feed lines that alternately add `low` and `high` error and 100 cycles until a prediction is made
+    fn predict(predictor: &mut Predictor, low: f64, high: f64) -> Option<Prediction> {
+        let mut total = 0.0;
+        (1..=predictor.lines).find_map(|i| {
+            total += if i % 2 == 0 { low } else { high };
+            predictor.push(&data(i, 100 * i as u64, total))
+        })
+    }
+
+    #[test]
+    fn predicts_exceeding_budget() {
+        // The mean error per line is 1e-6, so the total is 1e-2 after 10^4 lines
+        let mut predictor = Predictor::new(10_000, 1e-3, 3.0);
+        let prediction = predict(&mut predictor, 0.5e-6, 1.5e-6).unwrap();
+        assert_eq!(Verdict::Exceeds, prediction.verdict);
+        assert_eq!(MIN_LINES, prediction.observed);
+        assert!((prediction.total_error.value - 1e-2).abs() < 1e-8);
+        assert!(prediction.total_error.low > 1e-3);
+        assert_eq!(1_000_000.0, prediction.end_time.value);
+        assert_eq!(prediction.end_time.low, prediction.end_time.high);
+    }
+
+    #[test]
+    fn predicts_within_budget() {
+        let mut predictor = Predictor::new(10_000, 1.0, 3.0);
+        let prediction = predict(&mut predictor, 0.5e-6, 1.5e-6).unwrap();
+        assert_eq!(Verdict::WithinBudget, prediction.verdict);
+    }
+
+    #[test]
+    fn waits_for_a_clear_trend() {
+        // The budget is 1% above the extrapolated total error
+        let mut predictor = Predictor::new(10_000, 1.01e-2, 3.0);
+        let close = predict(&mut predictor, 0.5e-6, 1.5e-6).unwrap();
+        assert_eq!(Verdict::WithinBudget, close.verdict);
+        assert!(close.observed > 10 * MIN_LINES);
+
+        // Noisier increments need more lines before the band clears the budget
+        let steady = predict(&mut Predictor::new(10_000, 1.1e-2, 3.0), 0.9e-6, 1.1e-6).unwrap();
+        let noisy = predict(&mut Predictor::new(10_000, 1.1e-2, 3.0), 0.0, 2e-6).unwrap();
+        assert!(steady.observed < noisy.observed);
+        assert_eq!(Verdict::WithinBudget, noisy.verdict);
+    }
+}
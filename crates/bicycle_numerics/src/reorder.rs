@@ -0,0 +1,205 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reorder commuting PBC operations to reduce the time that blocks wait for each other.
+//!
+//! Within a window of upcoming operations, the next operation is the one that adds the fewest idle cycles
+//! under the timing model, among those that commute with all operations before it in the window.
+//! The idle cycles are estimated from the times of the blocks after the operations emitted so far,
+//! as in [`crate::run_numerics`] but without magic state storage and before duplicate measurements are removed.
+
+use std::collections::VecDeque;
+
+use bicycle_compiler::{language::PbcOperation, operation::Operation};
+
+use crate::model::InstructionCost;
+
+/// Reorders compiled operations within a window, see the [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct IdleReorderer<M> {
+    model: M,
+    window: usize,
+    /// Upcoming operations with their instructions
+    buffer: VecDeque<(PbcOperation, Vec<Operation>)>,
+    /// Number of times the first operation in the buffer was overtaken
+    overtaken: usize,
+    /// Compiled operations in the input order that the baseline has not reached yet
+    baseline: VecDeque<Vec<Operation>>,
+    times: Vec<u64>,
+    baseline_times: Vec<u64>,
+    idles: u64,
+    baseline_idles: u64,
+    moved: usize,
+}
+
+impl<M: InstructionCost> IdleReorderer<M> {
+    /// Reorder operations on the given number of blocks within a window of `window` upcoming operations
+    pub fn new(blocks: usize, model: M, window: usize) -> Self {
+        assert!(
+            window > 0,
+            "The window should contain at least one operation"
+        );
+        Self {
+            model,
+            window,
+            buffer: VecDeque::new(),
+            overtaken: 0,
+            baseline: VecDeque::new(),
+            times: vec![0; blocks],
+            baseline_times: vec![0; blocks],
+            idles: 0,
+            baseline_idles: 0,
+            moved: 0,
+        }
+    }
+
+    /// Check if the window is full, so that the next operation can be chosen
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= self.window
+    }
+
+    /// Add an operation with its compiled instructions to the window
+    pub fn push(&mut self, op: PbcOperation, compiled: Vec<Operation>) {
+        self.baseline.push_back(compiled.clone());
+        self.buffer.push_back((op, compiled));
+    }
+
+    /// Take the compiled instructions of the next operation from the window.
+    ///
+    /// The first operation in the window is taken once it was overtaken `window` times,
+    /// so that no operation is delayed indefinitely.
+    pub fn pop(&mut self) -> Option<Vec<Operation>> {
+        let candidates = if self.overtaken < self.window {
+            self.buffer.len()
+        } else {
+            1
+        };
+        let next = (0..candidates)
+            .filter(|&j| {
+                let (candidate, _) = &self.buffer[j];
+                self.buffer
+                    .range(..j)
+                    .all(|(before, _)| before.commutes_with(candidate))
+            })
+            // The first operation with the fewest idle cycles
+            .min_by_key(|&j| {
+                let mut times = self.times.clone();
+                (advance(&mut times, &self.buffer[j].1, &self.model), j)
+            })?;
+        if next == 0 {
+            self.overtaken = 0;
+        } else {
+            self.overtaken += 1;
+            self.moved += 1;
+        }
+        let (_, compiled) = self.buffer.remove(next)?;
+        self.idles += advance(&mut self.times, &compiled, &self.model);
+        let original = self.baseline.pop_front()?;
+        self.baseline_idles += advance(&mut self.baseline_times, &original, &self.model);
+        Some(compiled)
+    }
+
+    /// Number of operations that were moved ahead of an earlier operation
+    pub fn moved(&self) -> usize {
+        self.moved
+    }
+
+    /// Estimated idle cycles of the emitted operations
+    pub fn idles(&self) -> u64 {
+        self.idles
+    }
+
+    /// Estimated idle cycles of as many operations in the input order
+    pub fn baseline_idles(&self) -> u64 {
+        self.baseline_idles
+    }
+}
+
+/// Advance the times of the blocks over the instructions of an operation and return the number of idle cycles
+fn advance(times: &mut [u64], compiled: &[Operation], model: &impl InstructionCost) -> u64 {
+    let mut idles = 0;
+    for op in compiled {
+        let start = op
+            .iter()
+            .map(|(block_i, _)| times[*block_i])
+            .max()
+            .unwrap_or(0);
+        for (block_i, instr) in op {
+            idles += model.idling_error(start - times[*block_i]).0;
+            times[*block_i] = start + model.timing(instr);
+        }
+    }
+    idles
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{BicycleISA, Pauli, TwoBases};
+
+    use crate::model::{GROSS_1E3, Model};
+
+    use super::*;
+
+    use Pauli::{I, X, Z};
+
+    fn measurement(basis: Vec<Pauli>) -> PbcOperation {
+        PbcOperation::Measurement {
+            basis,
+            flip_result: false,
+        }
+    }
+
+    /// Reorder the operations and return the indices of the emitted operations
+    fn reorder(ops: Vec<(PbcOperation, Vec<Operation>)>) -> (Vec<usize>, IdleReorderer<Model>) {
+        let compiled: Vec<_> = ops.iter().map(|(_, compiled)| compiled.clone()).collect();
+        let mut reorderer = IdleReorderer::new(2, GROSS_1E3, 4);
+        for (op, compiled) in ops {
+            reorderer.push(op, compiled);
+        }
+        let order = std::iter::from_fn(|| reorderer.pop())
+            .map(|chunk| compiled.iter().position(|c| *c == chunk).unwrap())
+            .collect();
+        (order, reorderer)
+    }
+
+    #[test]
+    fn reorder_to_avoid_idling() {
+        let z1 = TwoBases::new(Z, I).unwrap();
+        let local = |block_i| vec![vec![(block_i, BicycleISA::Measure(z1))]; 3];
+        let joint = vec![vec![
+            (0, BicycleISA::JointMeasure(z1)),
+            (1, BicycleISA::JointMeasure(z1)),
+        ]];
+
+        // Block 1 waits for block 0 unless the measurement on block 1 moves ahead of the joint measurement
+        let (order, reorderer) = reorder(vec![
+            (measurement(vec![Z, I]), local(0)),
+            (measurement(vec![Z, Z]), joint.clone()),
+            (measurement(vec![I, Z]), local(1)),
+        ]);
+        assert_eq!(vec![0, 2, 1], order);
+        assert_eq!(1, reorderer.moved());
+        assert_eq!(0, reorderer.idles());
+        assert!(reorderer.baseline_idles() > 0);
+
+        // Anticommuting operations keep their order
+        let (order, reorderer) = reorder(vec![
+            (measurement(vec![Z, I]), local(0)),
+            (measurement(vec![Z, Z]), joint),
+            (measurement(vec![I, X]), local(1)),
+        ]);
+        assert_eq!(vec![0, 1, 2], order);
+        assert_eq!(reorderer.baseline_idles(), reorderer.idles());
+    }
+}
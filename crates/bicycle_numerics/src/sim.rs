@@ -0,0 +1,433 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logical fault sampling: Monte Carlo sampling of the logical faults of compiled programs,
+//! as an alternative to the union bound of the total error.
+//!
+//! The numerics add the error of every instruction and idle period, which bounds the probability that any of them fails.
+//! Instead, the sampler draws the faults of each shot and propagates them as a Pauli frame on the logical qubits of the blocks,
+//! numbered as in [`bicycle_compiler::export`], whose [`LogicalWalker`] tracks the automorphisms of the blocks
+//! and gives the logical Pauli that each measurement acts on.
+//!
+//! Each operation fails with the error of its first instruction, as in the numerics,
+//! and applies a uniformly random non-identity Pauli to the logical qubits of its blocks.
+//! Before an instruction, its block fails with the error of idling for the cycles that the numerics schedule it to wait.
+//! The idling of stored magic states is not sampled.
+//! S gates and transversal CX gates propagate the frame, T gates are Pauli twirled,
+//! and initializing a block or measuring it destructively clears its frame.
+//!
+//! All shots advance together, operation by operation, so the program is not stored
+//! and the memory of the sampler is bounded by the frames of the shots.
+//!
+//! A shot fails if the frame flips any measurement outcome,
+//! or if it leaves an error on the data qubits of a block that is not measured destructively at the end.
+//! This is conservative, because a flipped outcome may be harmless, e.g., when it prepares the pivot,
+//! but faults that commute with the rest of the program, cancel, or are cleared no longer count.
+//! The faults are logical faults of whole instructions, not circuit-level faults of the physical qubits,
+//! so the sampler refines the union bound of the model rather than simulating the codes.
+//! Sampling circuit-level noise on the physical qubits, with the decoding of the codes, is not implemented and remains open;
+//! the Stim circuits of [`bicycle_compiler::export`] are the starting point for it.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bicycle_cliffords::{PauliString, measurement::CodeMeasurement};
+use bicycle_common::{BicycleISA, Pauli, decoder::LogicalErrorRate};
+use bicycle_compiler::{
+    export::{LogicalGate, LogicalWalker},
+    operation::Operation,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::model::InstructionCost;
+
+/// Bits of the data qubits of a Pauli string, i.e., of its X and Z operators on all qubits except the pivot
+const DATA_BITS: u32 = PauliString::DATA_QUBITS | PauliString::DATA_QUBITS << 12;
+
+/// Samples the logical faults of a compiled program under the errors of a model.
+///
+/// All shots run side by side while the operations are pushed, and the program is not kept,
+/// so the memory holds the frames of the shots that carry an error and grows with the shots and blocks, not with the program.
+#[derive(Debug, Clone)]
+pub struct FaultSampler {
+    shots: usize,
+    /// The frame of each shot, which is empty while the shot carries no error
+    frames: Vec<Vec<PauliString>>,
+    /// The shots with a nonempty frame
+    active: Vec<usize>,
+    /// Whether each shot flipped a measurement outcome, after which it draws no more faults
+    failed: Vec<bool>,
+    failures: usize,
+    /// The sum of the hazards -ln(1 - p) of the fault locations so far
+    hazard: f64,
+    /// Each shot keyed by the sum of the hazards at which its next fault occurs.
+    /// Nonnegative floats are ordered like their bits.
+    next_faults: BinaryHeap<Reverse<(u64, usize)>>,
+    /// The sum of the fault probabilities
+    union_bound: f64,
+    /// Whether each block is measured destructively at the end, such that its frame does not matter
+    retired: Vec<bool>,
+    /// Tracks the automorphisms of the blocks after the operations so far
+    walker: LogicalWalker,
+    rng: StdRng,
+}
+
+impl FaultSampler {
+    /// A sampler of `shots` shots of a program on the blocks of a code, whose operations are added with [`Self::push`]
+    pub fn new(code: CodeMeasurement, shots: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let next_faults = (0..shots)
+            .map(|shot| Reverse((exponential(&mut rng).to_bits(), shot)))
+            .collect();
+        Self {
+            shots,
+            frames: vec![vec![]; shots],
+            active: vec![],
+            failed: vec![false; shots],
+            failures: 0,
+            hazard: 0.0,
+            next_faults,
+            union_bound: 0.0,
+            retired: vec![],
+            walker: LogicalWalker::new(code, 0),
+            rng,
+        }
+    }
+
+    /// Add the operations of a compiled chunk, e.g., a line of compiler output,
+    /// with the cycles that the numerics schedule each of its instructions to wait, see [`crate::run_numerics_traced`]
+    pub fn push(&mut self, chunk: &[Operation], waits: &[u64], model: &impl InstructionCost) {
+        assert_eq!(
+            chunk.iter().map(Vec::len).sum::<usize>(),
+            waits.len(),
+            "Each instruction should have waited"
+        );
+        let mut waits = waits.iter();
+        for op in chunk {
+            let gates = self.walker.gates(op);
+            self.retired.resize(self.walker.blocks(), false);
+            for ((block, instruction), waited) in op.iter().zip(&mut waits) {
+                let p = model.idling_error(*waited).1.to_num();
+                self.fault(&[*block], p);
+                if let Some(instruction) = retirement(instruction) {
+                    self.retired[*block] = instruction;
+                }
+            }
+            self.apply(&gates);
+            if let Some((_, instruction)) = op.first() {
+                let p = model.instruction_error(instruction).to_num();
+                let blocks: Vec<_> = op.iter().map(|(block, _)| *block).collect();
+                self.fault(&blocks, p);
+            }
+        }
+    }
+
+    /// Add a fault location on `blocks` with probability `p`, and inject the fault into the shots where it occurs
+    fn fault(&mut self, blocks: &[usize], p: f64) {
+        if p <= 0.0 {
+            return;
+        }
+        // A probability of 1 or more is an almost certain fault, which keeps the hazards finite
+        let p = p.min(1.0 - f64::EPSILON);
+        self.union_bound += p;
+        self.hazard -= (-p).ln_1p();
+        let blocks_len = self
+            .walker
+            .blocks()
+            .max(blocks.iter().max().map_or(0, |block| block + 1));
+        // The next fault is where the sum of the hazards exceeds an exponentially distributed time
+        while let Some(&Reverse((next, shot))) = self.next_faults.peek() {
+            if f64::from_bits(next) >= self.hazard {
+                break;
+            }
+            self.next_faults.pop();
+            if self.failed[shot] {
+                continue;
+            }
+            let next = self.hazard + exponential(&mut self.rng);
+            self.next_faults.push(Reverse((next.to_bits(), shot)));
+            let frames = &mut self.frames[shot];
+            if frames.is_empty() {
+                self.active.push(shot);
+            }
+            if frames.len() < blocks_len {
+                frames.resize(blocks_len, PauliString::new(0).unwrap());
+            }
+            inject(frames, blocks, &mut self.rng);
+        }
+    }
+
+    /// Apply the gates of an operation to the shots that carry an error, and drop the frames that fail or clear
+    fn apply(&mut self, gates: &[LogicalGate]) {
+        let blocks = self.walker.blocks();
+        let identity = PauliString::new(0).unwrap();
+        let mut active = std::mem::take(&mut self.active);
+        active.retain(|&shot| {
+            let frames = &mut self.frames[shot];
+            if frames.len() < blocks {
+                frames.resize(blocks, identity);
+            }
+            let failed = gates.iter().any(|gate| apply(frames, gate, &mut self.rng));
+            if failed {
+                self.failed[shot] = true;
+                self.failures += 1;
+            }
+            if failed || frames.iter().all(|frame| frame.bits() == 0) {
+                *frames = vec![];
+                return false;
+            }
+            true
+        });
+        self.active = active;
+    }
+
+    /// The sum of the fault probabilities, i.e., the union bound on the logical error rate
+    pub fn union_bound(&self) -> f64 {
+        self.union_bound
+    }
+
+    /// The logical error rate of the shots of the program so far
+    pub fn rate(&self) -> LogicalErrorRate {
+        let remaining = self
+            .active
+            .iter()
+            .filter(|&&shot| {
+                self.frames[shot].iter().enumerate().any(|(block, frame)| {
+                    !self.retired.get(block).copied().unwrap_or(false)
+                        && frame.bits() & DATA_BITS != 0
+                })
+            })
+            .count();
+        LogicalErrorRate {
+            shots: self.shots,
+            failures: self.failures + remaining,
+        }
+    }
+}
+
+/// An exponentially distributed time
+fn exponential<R: Rng>(rng: &mut R) -> f64 {
+    -(1.0 - rng.random::<f64>()).ln()
+}
+
+/// Whether an instruction leaves its block measured destructively, or `None` if it does not change that
+fn retirement(instruction: &BicycleISA) -> Option<bool> {
+    match instruction {
+        BicycleISA::DestructiveZ | BicycleISA::DestructiveX => Some(true),
+        BicycleISA::CSSInitZero
+        | BicycleISA::CSSInitPlus
+        | BicycleISA::InitT
+        | BicycleISA::JointBellInit => Some(false),
+        _ => None,
+    }
+}
+
+/// Multiply a uniformly random non-identity Pauli on the logical qubits of the blocks into their frames
+fn inject<R: Rng>(frames: &mut [PauliString], blocks: &[usize], rng: &mut R) {
+    loop {
+        let paulis: Vec<PauliString> = blocks.iter().map(|_| rng.random()).collect();
+        if paulis.iter().any(|pauli| pauli.bits() != 0) {
+            for (block, pauli) in blocks.iter().zip(paulis) {
+                frames[*block] = frames[*block] * pauli;
+            }
+            return;
+        }
+    }
+}
+
+/// Apply a gate to the frames, returning whether it flips a measurement outcome
+fn apply<R: Rng>(frames: &mut [PauliString], gate: &LogicalGate, rng: &mut R) -> bool {
+    let identity = PauliString::new(0).unwrap();
+    match gate {
+        LogicalGate::Init(block, _) | LogicalGate::InitT(block) => frames[*block] = identity,
+        LogicalGate::Measure(paulis) => {
+            let anticommuting = paulis
+                .iter()
+                .filter(|logical| !frames[logical.block].commutes_with(logical.pauli))
+                .count();
+            return anticommuting % 2 == 1;
+        }
+        LogicalGate::S { pauli, .. } => {
+            frames[pauli.block] = frames[pauli.block].conjugate_with(pauli.pauli)
+        }
+        LogicalGate::T { pauli, .. } => {
+            if rng.random_bool(0.5) {
+                frames[pauli.block] = frames[pauli.block].conjugate_with(pauli.pauli);
+            }
+        }
+        LogicalGate::Cx {
+            control, target, ..
+        } => {
+            let mask = PauliString::ALL_QUBITS;
+            let (c, t) = (frames[*control].bits(), frames[*target].bits());
+            // X spreads from the control to the target, and Z from the target to the control
+            let c = c ^ (t & mask << 12);
+            let t = t ^ (c & mask);
+            frames[*control] = PauliString::new(c).unwrap();
+            frames[*target] = PauliString::new(t).unwrap();
+        }
+        LogicalGate::Destructive(block, basis) => {
+            let bits = frames[*block].bits();
+            frames[*block] = identity;
+            // Z measurements detect X errors and X measurements detect Z errors
+            let detected = match basis {
+                Pauli::Z => bits & PauliString::ALL_QUBITS,
+                _ => bits >> 12,
+            };
+            return detected != 0;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_cliffords::measurement::GROSS_MEASUREMENT;
+    use bicycle_common::{TGateData, TwoBases};
+    use bicycle_compiler::{PathArchitecture, export::LogicalPauli, model::GROSS_1E3};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::accumulate::Accumulation;
+
+    use BicycleISA::{CSSInitPlus, CSSInitZero, JointMeasure, Measure, TGate};
+
+    fn z1() -> TwoBases {
+        TwoBases::new(Pauli::Z, Pauli::I).unwrap()
+    }
+
+    fn pauli(paulis: &[(usize, Pauli)]) -> PauliString {
+        let mut pauli = PauliString::new(0).unwrap();
+        for (qubit, p) in paulis {
+            pauli.set_pauli(*qubit, *p);
+        }
+        pauli
+    }
+
+    #[test]
+    fn frames_propagate() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let x0 = pauli(&[(0, Pauli::X)]);
+        let z0 = pauli(&[(0, Pauli::Z)]);
+        let on = |block: usize, pauli: PauliString| LogicalPauli {
+            block,
+            bases: z1(),
+            pauli,
+        };
+        let mut frames = vec![x0, PauliString::new(0).unwrap()];
+
+        // X on the control spreads to the target
+        let cx = LogicalGate::Cx {
+            control: 0,
+            target: 1,
+            aligned: true,
+        };
+        assert!(!apply(&mut frames, &cx, &mut rng));
+        assert_eq!(vec![x0, x0], frames);
+        // A joint Z measurement does not see the correlated X errors, but a single one does
+        assert!(!apply(
+            &mut frames,
+            &LogicalGate::Measure(vec![on(0, z0), on(1, z0)]),
+            &mut rng
+        ));
+        assert!(apply(
+            &mut frames,
+            &LogicalGate::Measure(vec![on(1, z0)]),
+            &mut rng
+        ));
+        // An S gate about Z turns X into Y
+        let s = LogicalGate::S {
+            pauli: on(0, z0),
+            adjoint: false,
+        };
+        assert!(!apply(&mut frames, &s, &mut rng));
+        assert_eq!(x0 * z0, frames[0]);
+        assert!(apply(
+            &mut frames,
+            &LogicalGate::Destructive(0, Pauli::X),
+            &mut rng
+        ));
+        assert_eq!(PauliString::new(0).unwrap(), frames[0]);
+    }
+
+    #[test]
+    fn certain_faults() {
+        let mut sampler = FaultSampler::new(GROSS_MEASUREMENT, 10_000, 2);
+        assert_eq!(0, sampler.rate().failures);
+
+        sampler.fault(&[0], 1.0);
+        sampler.fault(&[0], 0.0);
+        assert!((sampler.union_bound() - 1.0).abs() < 1e-9);
+        assert_eq!(10_000, sampler.active.len());
+        // A random Pauli on the block is harmless only if it acts on the pivot alone
+        let rate = sampler.rate().rate();
+        assert!((rate - (1.0 - 3.0 / 4095.0)).abs() < 0.01, "{rate}");
+
+        // Measuring the block destructively detects almost every error and clears the frames
+        sampler.apply(&[LogicalGate::Destructive(0, Pauli::Z)]);
+        assert!(sampler.active.is_empty());
+        assert_eq!(sampler.failures, sampler.rate().failures);
+        assert!(sampler.failures > 9_900);
+    }
+
+    #[test]
+    fn below_union_bound() {
+        let t = TGateData::new(Pauli::Z, false, false).unwrap();
+        let mut ops = vec![vec![vec![(0, CSSInitZero)], vec![(1, CSSInitPlus)]]];
+        for _ in 0..100 {
+            ops.push(vec![
+                vec![(0, JointMeasure(z1())), (1, JointMeasure(z1()))],
+                vec![(0, TGate(t))],
+                vec![(0, Measure(z1()))],
+            ]);
+        }
+        let architecture = PathArchitecture::new(2);
+        let mut sampler = FaultSampler::new(GROSS_MEASUREMENT, 2000, 3);
+        let mut waits = vec![];
+        let numerics = crate::run_numerics_traced(
+            ops.iter().cloned(),
+            architecture.clone(),
+            GROSS_1E3,
+            Accumulation::default(),
+            |traced| waits.push(traced.waited),
+        );
+        let lines = numerics.count();
+        // The instructions of each line wait in order, so split the waits by line
+        let mut waits = waits.as_slice();
+        for chunk in &ops[..lines] {
+            let (line, rest) = waits.split_at(chunk.iter().map(Vec::len).sum());
+            sampler.push(chunk, line, &GROSS_1E3);
+            waits = rest;
+        }
+        assert_eq!(vec![false, false], sampler.retired);
+
+        // The faults include the idling of the blocks that wait, as the total error does
+        let union_bound = sampler.union_bound();
+        let expected: f64 = numerics_total(&ops, architecture.clone());
+        assert!((union_bound - expected).abs() < 1e-9 * expected);
+
+        let rate = sampler.rate();
+        let (low, high) = rate.confidence_interval(3.0);
+        assert!(0.0 < low);
+        assert!(high < union_bound, "{} {union_bound}", rate.rate());
+    }
+
+    /// The total error of the numerics
+    fn numerics_total(ops: &[Vec<Operation>], architecture: PathArchitecture) -> f64 {
+        crate::run_numerics(ops.iter().cloned(), architecture, GROSS_1E3)
+            .last()
+            .unwrap()
+            .unwrap()
+            .total_error
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Magic states that a factory prepares ahead of time into storage blocks.
+//!
+//! Without storage, a factory prepares each magic state when it is injected.
+//! With storage, the factory prepares states back to back, one per preparation period, as long as a storage block is free.
+//! An injection takes the oldest stored state, waiting for the factory if the storage is empty,
+//! and the stored state idles from when it was prepared until it is injected.
+
+use std::collections::VecDeque;
+
+/// The stored magic states of a single factory
+#[derive(Debug, Clone)]
+pub struct MagicStorage {
+    capacity: usize,
+    /// Cycles that the factory takes to prepare a state
+    period: u64,
+    /// Times at which the stored states are ready, oldest first
+    ready: VecDeque<u64>,
+    /// Time at which the factory finished its last state
+    factory_free: u64,
+}
+
+impl MagicStorage {
+    /// Storage for `capacity` states of a factory that prepares a state every `period` cycles.
+    /// The factory starts preparing states at time 0.
+    pub fn new(capacity: usize, period: u64) -> Self {
+        assert!(capacity > 0, "The storage should hold at least one state");
+        let mut storage = Self {
+            capacity,
+            period,
+            ready: VecDeque::with_capacity(capacity),
+            factory_free: 0,
+        };
+        storage.fill(0);
+        storage
+    }
+
+    /// Prepare states until the storage is full, starting no earlier than `time`
+    fn fill(&mut self, time: u64) {
+        self.factory_free = self.factory_free.max(time);
+        while self.ready.len() < self.capacity {
+            self.factory_free += self.period;
+            self.ready.push_back(self.factory_free);
+        }
+    }
+
+    /// Take a state for an injection that is ready to start at `time`.
+    /// Returns when the injection starts and for how long the state was stored.
+    pub fn consume(&mut self, time: u64) -> (u64, u64) {
+        let ready = self.ready.pop_front().unwrap();
+        let start = time.max(ready);
+        // The freed block is filled as soon as the factory is done with the previous state
+        self.fill(start);
+        (start, start - ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_drain_the_storage() {
+        let mut storage = MagicStorage::new(2, 100);
+        // Two states are ready at times 100 and 200
+        assert_eq!((1000, 900), storage.consume(1000));
+        assert_eq!((1000, 800), storage.consume(1000));
+        // The factory was blocked until the first state was taken at time 1000
+        assert_eq!((1100, 0), storage.consume(1000));
+        assert_eq!((1200, 0), storage.consume(1100));
+    }
+
+    #[test]
+    fn waits_for_the_factory() {
+        let mut storage = MagicStorage::new(1, 100);
+        assert_eq!((100, 0), storage.consume(0));
+        assert_eq!((200, 0), storage.consume(150));
+        assert_eq!((500, 200), storage.consume(500));
+    }
+}
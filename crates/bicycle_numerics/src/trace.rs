@@ -0,0 +1,97 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracing of the instructions that the numerics schedule, e.g., to debug the behavior of a single block.
+
+use std::fmt;
+
+use bicycle_common::BicycleISA;
+
+use crate::model::InstructionClass;
+
+/// An instruction on one block with the time at which it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TracedInstruction {
+    /// The input line of the operation, counting from 1
+    pub line: usize,
+    pub block: usize,
+    pub start: u64,
+    /// Number of cycles that the block idled before the instruction
+    pub waited: u64,
+    pub instruction: BicycleISA,
+}
+
+impl fmt::Display for TracedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "t={} block={} line={}: {}",
+            self.start, self.block, self.line, self.instruction
+        )
+    }
+}
+
+/// Selects the traced instructions by their block and class.
+/// An empty list of blocks or classes matches all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub blocks: Vec<usize>,
+    pub classes: Vec<InstructionClass>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, traced: &TracedInstruction) -> bool {
+        (self.blocks.is_empty() || self.blocks.contains(&traced.block))
+            && (self.classes.is_empty()
+                || self
+                    .classes
+                    .contains(&InstructionClass::of(&traced.instruction)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_common::{Pauli, TwoBases};
+
+    use super::*;
+
+    #[test]
+    fn filter_traced_instructions() {
+        let traced = TracedInstruction {
+            line: 3,
+            block: 2,
+            start: 120,
+            waited: 0,
+            instruction: BicycleISA::Measure(TwoBases::new(Pauli::Z, Pauli::I).unwrap()),
+        };
+        assert_eq!("t=120 block=2 line=3: meas(Z,I)", traced.to_string());
+
+        assert!(TraceFilter::default().matches(&traced));
+        let filter = TraceFilter {
+            blocks: vec![1, 2],
+            classes: vec![],
+        };
+        assert!(filter.matches(&traced));
+        let filter = TraceFilter {
+            blocks: vec![2],
+            classes: vec![InstructionClass::TGate],
+        };
+        assert!(!filter.matches(&traced));
+        let filter = TraceFilter {
+            blocks: vec![],
+            classes: vec![InstructionClass::TGate, InstructionClass::Measure],
+        };
+        assert!(filter.matches(&traced));
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rates over a sliding window of operations, to reveal the phases of long circuits.
+
+use std::collections::VecDeque;
+
+use crate::OutputData;
+
+/// Rates over the operations in the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowStats {
+    /// Error added per 1000 operations
+    pub error_per_1k_ops: f64,
+    /// T gates per 1000 cycles of the end time
+    pub t_per_1k_cycles: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Snapshot {
+    total_error: f64,
+    end_time: u64,
+    t_injs: u64,
+}
+
+/// Tracks the numerics of the last `size` operations
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    size: usize,
+    /// Cumulative values before the window and after each operation in it
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl RollingWindow {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "The window should contain at least one operation");
+        Self {
+            size,
+            snapshots: VecDeque::from([Snapshot::default()]),
+        }
+    }
+
+    /// Add the numerics of the next operation and return the rates over the window.
+    /// Until the window is full, the rates are over all operations so far.
+    pub fn push(&mut self, data: &OutputData) -> WindowStats {
+        let last = self.snapshots.back().unwrap();
+        let snapshot = Snapshot {
+            total_error: data.total_error,
+            end_time: data.end_time,
+            t_injs: last.t_injs + data.t_injs,
+        };
+        self.snapshots.push_back(snapshot);
+        if self.snapshots.len() > self.size + 1 {
+            self.snapshots.pop_front();
+        }
+
+        let first = self.snapshots.front().unwrap();
+        let ops = (self.snapshots.len() - 1) as f64;
+        let time = snapshot.end_time - first.end_time;
+        WindowStats {
+            error_per_1k_ops: 1000.0 * (snapshot.total_error - first.total_error) / ops,
+            t_per_1k_cycles: if time == 0 {
+                0.0
+            } else {
+                1000.0 * (snapshot.t_injs - first.t_injs) as f64 / time as f64
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(i: usize, t_injs: u64, end_time: u64, total_error: f64) -> OutputData {
+        OutputData {
+            i,
+            qubits: 11,
+            idles: 0,
+            t_injs,
+            automorphisms: 0,
+            measurements: 0,
+            joint_measurements: 0,
+            measurement_depth: 0,
+            steps: 0,
+            end_time,
+            factory_time: 0,
+            storage_idles: 0,
+            total_error,
+            breakdown: Default::default(),
+        }
+    }
+
+    #[test]
+    fn rates_follow_phases() {
+        let mut window = RollingWindow::new(2);
+        // A phase of T gates followed by a phase of Clifford operations
+        let first = window.push(&data(1, 2, 100, 1e-6));
+        assert_eq!(1e-3, first.error_per_1k_ops);
+        assert_eq!(20.0, first.t_per_1k_cycles);
+
+        let second = window.push(&data(2, 2, 200, 2e-6));
+        assert_eq!(20.0, second.t_per_1k_cycles);
+
+        window.push(&data(3, 0, 300, 2.5e-6));
+        let clifford = window.push(&data(4, 0, 400, 3e-6));
+        assert_eq!(0.0, clifford.t_per_1k_cycles);
+        assert!((clifford.error_per_1k_ops - 0.5e-3).abs() < 1e-15);
+    }
+
+    #[test]
+    fn no_time_passed() {
+        let mut window = RollingWindow::new(10);
+        assert_eq!(0.0, window.push(&data(1, 0, 0, 0.0)).t_per_1k_cycles);
+    }
+}
@@ -1,17 +1,41 @@
-use std::{env, error::Error, io, path::Path};
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
 use bicycle_cliffords::{CompleteMeasurementTable, MeasurementChoices};
-use bicycle_common::{BicycleISA, Pauli, TwoBases};
+use bicycle_common::{BicycleISA, Pauli, TwoBases, alloc};
 use bicycle_numerics::{
-    model::{ErrorPrecision, GROSS_1E3, GROSS_1E4, TWO_GROSS_1E3, TWO_GROSS_1E4},
     OutputData,
+    model::{ErrorPrecision, GROSS_1E3, GROSS_1E4, InstructionCost, TWO_GROSS_1E3, TWO_GROSS_1E4},
+    reorder::IdleReorderer,
 };
 use fixed::traits::LosslessTryInto;
-use log::{debug, trace};
+use log::{debug, info, trace};
 
-use bicycle_compiler::language::AnglePrecision;
-use clap::{Parser, ValueEnum};
+use bicycle_compiler::{
+    language::{AnglePrecision, PbcOperation},
+    operation::Operation,
+};
+use clap::{Parser, Subcommand};
+use manifest::Manifest;
+use rayon::prelude::*;
+use scenario::{ErrorRate, PartialScenario, Scenario};
 use serde::{Deserialize, Serialize};
+use sweep::{Configuration, ModelChoice, QubitSweep};
+
+mod manifest;
+mod scenario;
+mod snapshot;
+mod sweep;
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOC: bicycle_common::alloc::CountingAllocator = bicycle_common::alloc::CountingAllocator;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Output {
@@ -26,6 +50,9 @@ struct Output {
     measurement_depth: u64,
     end_time: u64,
     total_error: f64,
+    /// Peak bytes allocated so far by the compile and numerics stage, with the `memory-profile` feature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peak_memory_bytes: Option<usize>,
 }
 
 impl Output {
@@ -45,23 +72,7 @@ impl Output {
             measurement_depth: data.measurement_depth,
             end_time: data.end_time,
             total_error: data.total_error,
-        }
-    }
-}
-
-#[derive(Debug, ValueEnum, Clone, Copy, Eq, PartialEq)]
-enum ErrorRate {
-    #[clap(name = "1e-3")]
-    E3,
-    #[clap(name = "1e-4")]
-    E4,
-}
-
-impl From<ErrorRate> for f64 {
-    fn from(value: ErrorRate) -> Self {
-        match value {
-            ErrorRate::E3 => 1e-3,
-            ErrorRate::E4 => 1e-4,
+            peak_memory_bytes: alloc::is_counting().then(alloc::peak_bytes),
         }
     }
 }
@@ -69,27 +80,90 @@ impl From<ErrorRate> for f64 {
 #[derive(Parser, Debug)]
 #[command(version, about, long_about=None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Read the experiment from a scenario TOML file. Flags override the values in the file.
+    #[arg(long)]
+    scenario: Option<PathBuf>,
     /// Number of logical qubits in the circuit
     #[arg(short, long)]
-    qubits: usize,
+    qubits: Option<usize>,
     /// What code to use
     #[arg(short, long)]
-    model: MeasurementChoices,
+    model: Option<MeasurementChoices>,
     /// The physical error rate
     #[arg(short, long)]
-    noise: ErrorRate,
-    /// The cumulative logical error rate at which to halt
-    #[arg(short = 'e', long, default_value_t = 1.0/3.0)]
-    max_error: f64,
-    /// The maximum number of gates to simulate and halt at
-    #[arg(short = 'i', long, default_value_t = 10_usize.pow(5))]
-    max_iter: usize,
+    noise: Option<ErrorRate>,
+    /// Run the experiment for each number of qubits of a range, given as `qubits=<START>..<END>:<STEP>` with an inclusive end,
+    /// for each model of `--models` in parallel, and write all rows to one CSV
+    #[arg(long, value_name = "RANGE", conflicts_with_all = ["qubits", "scenario", "manifest", "isa_output"])]
+    sweep: Option<QubitSweep>,
+    /// The codes and physical error rates of a sweep, e.g., `gross_1e-3,two-gross_1e-4`.
+    /// A `{code}` in `--measurement-table` is replaced by the name of each code.
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["model", "noise", "scenario", "manifest", "isa_output"])]
+    models: Vec<ModelChoice>,
+    /// The cumulative logical error rate at which to halt [default: 1/3]
+    #[arg(short = 'e', long)]
+    max_error: Option<f64>,
+    /// The maximum number of gates to simulate and halt at [default: 100000]
+    #[arg(short = 'i', long)]
+    max_iter: Option<usize>,
     /// A cache file name that stores a Clifford synthesis table. See bicycle_compiler generate subcommand.
     #[arg(long)]
-    measurement_table: String,
+    measurement_table: Option<String>,
     /// The small-angle synthesis precision
     #[arg(short, long)]
     accuracy: Option<AnglePrecision>,
+    /// The angle of the random rotations [default: π/4, which needs a single T gate]
+    #[arg(long, allow_hyphen_values = true)]
+    angle: Option<AnglePrecision>,
+    /// Seed of the random circuit [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Write the output to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Also write the compiled instructions to a file, in the output format of bicycle_compiler
+    #[arg(long)]
+    isa_output: Option<String>,
+    /// Place magic state factories at both ends of the path
+    #[arg(long)]
+    two_sided: bool,
+    /// Reorder commuting rotations within a window of this many upcoming rotations,
+    /// to reduce the time that blocks wait for each other
+    #[arg(long)]
+    reorder_window: Option<usize>,
+    /// Write a manifest of the run to a file, which `repro` runs again
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Instead of an experiment, check the snapshot rows of the code against the numerics of this version
+    #[arg(long, requires_all = ["model", "measurement_table"])]
+    check_snapshots: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Run the experiment of a manifest again, with the same configuration, seed, and measurement table
+    Repro { manifest: PathBuf },
+}
+
+impl Cli {
+    fn partial_scenario(&self) -> PartialScenario {
+        PartialScenario {
+            qubits: self.qubits,
+            code: self.model,
+            noise: self.noise,
+            measurement_table: self.measurement_table.clone(),
+            accuracy: self.accuracy,
+            angle: self.angle,
+            seed: self.seed,
+            max_error: self.max_error,
+            max_iter: self.max_iter,
+            output: self.output.clone(),
+            isa_output: self.isa_output.clone(),
+            reorder_window: self.reorder_window,
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -102,7 +176,208 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
     trace!("Cli arguments: {cli:?}");
-    let model = match (cli.model, cli.noise) {
+    if let Some(Commands::Repro { manifest }) = &cli.command {
+        let manifest = Manifest::from_file(manifest)?;
+        manifest.check()?;
+        debug!("Reproducing manifest: {manifest:?}");
+        return run(&manifest.scenario, manifest.two_sided, true, None);
+    }
+    if cli.check_snapshots {
+        return check_snapshots(cli.model.unwrap(), cli.measurement_table.as_ref().unwrap());
+    }
+    if cli.sweep.is_some() || !cli.models.is_empty() {
+        return sweep(&cli);
+    }
+    let from_file = cli
+        .scenario
+        .as_deref()
+        .map(PartialScenario::from_file)
+        .transpose()?
+        .unwrap_or_default();
+    let scenario = cli.partial_scenario().or(from_file).resolve()?;
+    debug!("Resolved scenario: {scenario:?}");
+    run(
+        &scenario,
+        cli.two_sided,
+        cli.scenario.is_some(),
+        cli.manifest.as_deref(),
+    )
+}
+
+/// Run the experiment of a scenario, recording the scenario if `provenance` is set:
+/// next to the output file as `<output>.scenario.toml`, or in the log if the output is written to stdout.
+/// The output itself stays plain CSV.
+fn run(
+    scenario: &Scenario,
+    two_sided: bool,
+    provenance: bool,
+    manifest: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let measurement_table = load_table(&scenario.measurement_table)?;
+
+    let out: Box<dyn Write> = match &scenario.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    if provenance {
+        match &scenario.output {
+            Some(path) => std::fs::write(format!("{path}.scenario.toml"), scenario.to_toml())?,
+            None => info!("Scenario of this run:\n{}", scenario.to_toml()),
+        }
+    }
+    let mut wtr = csv::Writer::from_writer(out);
+    simulate(
+        scenario,
+        two_sided,
+        &measurement_table,
+        manifest,
+        |output| wtr.serialize(output),
+    )
+}
+
+/// Run the experiment for every model of `--models` and number of qubits of `--sweep` in parallel,
+/// and write the rows of all configurations to one CSV, prefixed by the configuration
+fn sweep(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let models = match (&cli.models[..], cli.model, cli.noise) {
+        ([], Some(code), Some(noise)) => vec![ModelChoice { code, noise }],
+        ([], _, _) => return Err("A sweep needs --models, or --model and --noise".into()),
+        (models, _, _) => models.to_vec(),
+    };
+    let qubits: Vec<_> = match (cli.sweep, cli.qubits) {
+        (Some(sweep), _) => sweep.qubits().collect(),
+        (None, Some(qubits)) => vec![qubits],
+        (None, None) => return Err("A sweep needs --sweep or --qubits".into()),
+    };
+    let template = cli
+        .measurement_table
+        .as_deref()
+        .ok_or("A sweep needs --measurement-table")?;
+    if !template.contains("{code}") && models.iter().any(|model| model.code != models[0].code) {
+        return Err(
+            "A sweep over several codes needs a {code} placeholder in --measurement-table".into(),
+        );
+    }
+    let seed = cli.seed.unwrap_or_else(rand::random);
+
+    // Load the table of each code once
+    let mut tables: Vec<(MeasurementChoices, CompleteMeasurementTable)> = vec![];
+    for model in &models {
+        if tables.iter().all(|(code, _)| *code != model.code) {
+            let table = load_table(&sweep::table_path(template, model.code))?;
+            tables.push((model.code, table));
+        }
+    }
+    let scenarios = models
+        .iter()
+        .flat_map(|model| qubits.iter().map(move |qubits| (model, *qubits)))
+        .map(|(model, qubits)| {
+            PartialScenario {
+                qubits: Some(qubits),
+                code: Some(model.code),
+                noise: Some(model.noise),
+                measurement_table: Some(sweep::table_path(template, model.code)),
+                seed: Some(seed),
+                output: None,
+                isa_output: None,
+                ..cli.partial_scenario()
+            }
+            .resolve()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    info!(
+        "Sweeping {} configurations of {} models with seed {seed}",
+        scenarios.len(),
+        models.len()
+    );
+
+    let rows = scenarios
+        .par_iter()
+        .map(|scenario| {
+            let (_, table) = tables
+                .iter()
+                .find(|(code, _)| *code == scenario.code)
+                .expect("The table of every code is loaded");
+            let mut rows = vec![];
+            simulate(scenario, cli.two_sided, table, None, |output| {
+                rows.push(output);
+                Ok(())
+            })
+            .map_err(|e| format!("{} qubits of {}: {e}", scenario.qubits, scenario.code))?;
+            Ok(rows)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let out: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut wtr = csv::Writer::from_writer(out);
+    for (config, rows) in rows.into_iter().enumerate() {
+        for output in rows {
+            wtr.serialize((Configuration { config, seed }, output))?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Run the snapshot rows of a code and fail if any of them is off
+fn check_snapshots(
+    code: MeasurementChoices,
+    measurement_table: &str,
+) -> Result<(), Box<dyn Error>> {
+    let table = load_table(measurement_table)?;
+    let mut mismatches = vec![];
+    for row in snapshot::SNAPSHOT_ROWS
+        .iter()
+        .filter(|row| row.code == code)
+    {
+        let mut last = None;
+        simulate(
+            &row.scenario(measurement_table),
+            false,
+            &table,
+            None,
+            |output| {
+                last = Some(output);
+                Ok(())
+            },
+        )?;
+        let last = last.expect("Every run outputs at least one row");
+        match row.check(&last) {
+            Ok(()) => info!("{row} matches the snapshot"),
+            Err(mismatch) => mismatches.push(mismatch),
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} snapshot rows are off: {}",
+            mismatches.len(),
+            mismatches.join("; ")
+        )
+        .into())
+    }
+}
+
+/// Read a measurement table that is written by `bicycle_compiler generate`.
+/// A table in the raw layout is mapped into memory, such that only the looked-up pages are resident.
+fn load_table(path: &str) -> Result<CompleteMeasurementTable, Box<dyn Error>> {
+    let measurement_table = bicycle_compiler::deserialize_table(Path::new(path))?;
+    alloc::report_memory("measurement table");
+    Ok(measurement_table)
+}
+
+/// Compile and simulate the random circuit of a scenario, passing each output row to `emit` until it fails
+fn simulate(
+    scenario: &Scenario,
+    two_sided: bool,
+    measurement_table: &CompleteMeasurementTable,
+    manifest: Option<&Path>,
+    emit: impl FnMut(Output) -> csv::Result<()>,
+) -> Result<(), Box<dyn Error>> {
+    let model = match (scenario.code, scenario.noise) {
         (MeasurementChoices::Gross, ErrorRate::E3) => GROSS_1E3,
         (MeasurementChoices::Gross, ErrorRate::E4) => GROSS_1E4,
         (MeasurementChoices::TwoGross, ErrorRate::E3) => TWO_GROSS_1E3,
@@ -114,36 +389,117 @@ fn main() -> Result<(), Box<dyn Error>> {
         TwoBases::new(Pauli::X, Pauli::Z).unwrap(),
     ));
     let unsigned_measurement_error: AnglePrecision = measurement_error.lossless_try_into().unwrap();
-    let angle_precision: AnglePrecision = cli.accuracy.unwrap_or(unsigned_measurement_error);
+    let angle_precision: AnglePrecision = scenario.accuracy.unwrap_or(unsigned_measurement_error);
     debug!("Set angle precision: {angle_precision:?}");
+    if let Some(path) = manifest {
+        let scenario = Scenario {
+            accuracy: Some(angle_precision),
+            ..scenario.clone()
+        };
+        Manifest::new(scenario, two_sided)?.write(path)?;
+        info!("Wrote the manifest of the run to {}", path.display());
+    }
 
-    let cliff_angle = AnglePrecision::PI / AnglePrecision::lit("4.0");
-    let random_ops = bicycle_benchmark::random::random_rotations(cli.qubits, cliff_angle);
-
-    let cache_path = Path::new(&cli.measurement_table);
-    let read = std::fs::read(cache_path).expect("The measurement table file should be readable");
-    let measurement_table = bitcode::deserialize::<CompleteMeasurementTable>(&read)?;
+    let angle = scenario
+        .angle
+        .unwrap_or(AnglePrecision::PI / AnglePrecision::lit("4.0"));
+    let random_ops =
+        bicycle_benchmark::random::random_rotations_seeded(scenario.qubits, angle, scenario.seed);
 
-    let architecture = bicycle_compiler::PathArchitecture::for_qubits(cli.qubits);
-    let compiled =
-        random_ops.map(|op| op.compile(&architecture, &measurement_table, angle_precision));
+    let mut architecture = bicycle_compiler::PathArchitecture::for_qubits_per_block(
+        scenario.qubits,
+        scenario.code.qubits_per_block(),
+    );
+    if two_sided {
+        architecture = architecture.two_sided();
+    }
+    let compile = |op: &PbcOperation| op.compile(&architecture, measurement_table, angle_precision);
+    let reorderer = scenario.reorder_window.map(|window| {
+        RefCell::new(IdleReorderer::new(
+            architecture.total_blocks(),
+            model,
+            window,
+        ))
+    });
+    let compiled: Box<dyn Iterator<Item = Vec<Operation>>> = match &reorderer {
+        Some(reorderer) => {
+            let mut random_ops = random_ops;
+            Box::new(std::iter::from_fn(move || {
+                let mut reorderer = reorderer.borrow_mut();
+                while !reorderer.is_full() {
+                    let Some(op) = random_ops.next() else {
+                        break;
+                    };
+                    let compiled = compile(&op);
+                    reorderer.push(op, compiled);
+                }
+                reorderer.pop()
+            }))
+        }
+        None => Box::new(random_ops.map(|op| compile(&op))),
+    };
     let optimized_auts = compiled.map(bicycle_compiler::optimize::remove_trivial_automorphisms);
     let optimized_chunked_ops =
         bicycle_compiler::optimize::remove_duplicate_measurements_chunked(optimized_auts);
 
-    let output_data = bicycle_numerics::run_numerics(optimized_chunked_ops, architecture, model);
+    // Tee the instructions that the numerics consume, stopping at the first write error
+    let isa_out = RefCell::new(
+        scenario
+            .isa_output
+            .as_ref()
+            .map(|path| File::create(path).map(BufWriter::new))
+            .transpose()?,
+    );
+    let isa_err = RefCell::new(None);
+    let optimized_chunked_ops = optimized_chunked_ops.map_while(|chunk| {
+        if let Some(isa_out) = isa_out.borrow_mut().as_mut() {
+            let written = serde_json::to_writer(&mut *isa_out, &chunk)
+                .map_err(io::Error::from)
+                .and_then(|()| writeln!(isa_out));
+            if let Err(e) = written {
+                isa_err.replace(Some(e));
+                return None;
+            }
+        }
+        Some(chunk)
+    });
+
+    // Stop at the first overflow
+    let overflow = Cell::new(None);
+    let output_data =
+        bicycle_numerics::run_numerics(optimized_chunked_ops, architecture.clone(), model)
+            .map_while(|data| data.inspect_err(|e| overflow.set(Some(*e))).ok());
 
     // Stop when error exceeds 1/3 or iterations gets too large
     let short_data = output_data
         // Output at least one line.
         .take_while(|data| {
-            data.i == 1 || (data.total_error <= cli.max_error && data.i <= cli.max_iter)
+            data.i == 1 || (data.total_error <= scenario.max_error && data.i <= scenario.max_iter)
         });
 
-    let mut outputs = short_data.map(|data| Output::new(cli.model, cli.noise, data));
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    let err = outputs.try_for_each(|output| wtr.serialize(output));
+    let mut outputs = short_data.map(|data| Output::new(scenario.code, scenario.noise, data));
+    let err = outputs.try_for_each(emit);
     debug!("Exited with {err:?}");
+    alloc::report_memory("compile and numerics");
+    drop(outputs);
+    if let Some(reorderer) = reorderer {
+        let reorderer = reorderer.into_inner();
+        info!(
+            "Moved {} rotations ahead, changing the estimated idle cycles from {} to {}",
+            reorderer.moved(),
+            reorderer.baseline_idles(),
+            reorderer.idles()
+        );
+    }
 
-    Ok(())
+    if let Some(e) = isa_err.take() {
+        return Err(e.into());
+    }
+    if let Some(isa_out) = isa_out.borrow_mut().as_mut() {
+        isa_out.flush()?;
+    }
+    match overflow.get() {
+        Some(overflow) => Err(overflow.into()),
+        None => Ok(()),
+    }
 }
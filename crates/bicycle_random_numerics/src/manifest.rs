@@ -0,0 +1,145 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Manifests that record everything that determines the output of a run, to reproduce published numbers
+
+use std::{error::Error, fs::File, io, path::Path};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::scenario::Scenario;
+
+const TOOL: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_HASH: &str = env!("BICYCLE_GIT_HASH");
+
+/// The configuration of a run together with the build and the inputs that it used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    /// The git commit of the build, or `unknown` if it was not built from a git checkout
+    pub git_hash: String,
+    /// SHA-256 of the measurement table file
+    pub table_sha256: String,
+    /// Whether magic state factories are placed at both ends of the path
+    pub two_sided: bool,
+    /// The scenario with the seed and the accuracy that were used
+    pub scenario: Scenario,
+}
+
+impl Manifest {
+    /// The manifest of a run of this build, hashing the measurement table of the scenario
+    pub fn new(scenario: Scenario, two_sided: bool) -> io::Result<Self> {
+        Ok(Self {
+            tool: TOOL.to_string(),
+            version: VERSION.to_string(),
+            git_hash: GIT_HASH.to_string(),
+            table_sha256: sha256_file(Path::new(&scenario.measurement_table))?,
+            two_sided,
+            scenario,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Check that the run can be reproduced by this build.
+    /// A different measurement table is an error, a different build only a warning.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.tool != TOOL {
+            return Err(format!("The manifest is of a run of {}, not of {TOOL}", self.tool).into());
+        }
+        if self.version != VERSION || self.git_hash != GIT_HASH {
+            warn!(
+                "The manifest was produced by version {} at commit {}, this is version {VERSION} at commit {GIT_HASH}",
+                self.version, self.git_hash
+            );
+        }
+        let table_sha256 = sha256_file(Path::new(&self.scenario.measurement_table))?;
+        if table_sha256 != self.table_sha256 {
+            return Err(format!(
+                "The measurement table {} has SHA-256 {table_sha256}, but the manifest expects {}",
+                self.scenario.measurement_table, self.table_sha256
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The hexadecimal SHA-256 of a file
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycle_cliffords::MeasurementChoices;
+
+    use crate::scenario::ErrorRate;
+
+    use super::*;
+
+    #[test]
+    fn check_manifest() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("bicycle_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let table = dir.join("table");
+        std::fs::write(&table, b"abc")?;
+        let scenario = Scenario {
+            qubits: 22,
+            code: MeasurementChoices::Gross,
+            noise: ErrorRate::E3,
+            measurement_table: table.to_string_lossy().into_owned(),
+            accuracy: None,
+            angle: None,
+            seed: 7,
+            max_error: 0.5,
+            max_iter: 10,
+            output: None,
+            isa_output: None,
+            reorder_window: Some(8),
+        };
+        let manifest = Manifest::new(scenario, true)?;
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            manifest.table_sha256
+        );
+
+        let path = dir.join("manifest.toml");
+        manifest.write(&path)?;
+        let read = Manifest::from_file(&path)?;
+        assert_eq!(manifest, read);
+        read.check()?;
+
+        // A different table cannot reproduce the run
+        std::fs::write(&table, b"abd")?;
+        assert!(read.check().is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scenario files that fully describe a random numerics experiment
+
+use std::{error::Error, path::Path};
+
+use bicycle_cliffords::MeasurementChoices;
+use bicycle_compiler::language::AnglePrecision;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, ValueEnum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ErrorRate {
+    #[clap(name = "1e-3")]
+    #[serde(rename = "1e-3")]
+    E3,
+    #[clap(name = "1e-4")]
+    #[serde(rename = "1e-4")]
+    E4,
+}
+
+impl From<ErrorRate> for f64 {
+    fn from(value: ErrorRate) -> Self {
+        match value {
+            ErrorRate::E3 => 1e-3,
+            ErrorRate::E4 => 1e-4,
+        }
+    }
+}
+
+/// A scenario file as written by the user, where every field may be omitted
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialScenario {
+    pub qubits: Option<usize>,
+    pub code: Option<MeasurementChoices>,
+    pub noise: Option<ErrorRate>,
+    pub measurement_table: Option<String>,
+    pub accuracy: Option<AnglePrecision>,
+    pub angle: Option<AnglePrecision>,
+    pub seed: Option<u64>,
+    pub max_error: Option<f64>,
+    pub max_iter: Option<usize>,
+    pub output: Option<String>,
+    pub isa_output: Option<String>,
+    pub reorder_window: Option<usize>,
+}
+
+impl PartialScenario {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Fill in the fields that are not set from `other`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            qubits: self.qubits.or(other.qubits),
+            code: self.code.or(other.code),
+            noise: self.noise.or(other.noise),
+            measurement_table: self.measurement_table.or(other.measurement_table),
+            accuracy: self.accuracy.or(other.accuracy),
+            angle: self.angle.or(other.angle),
+            seed: self.seed.or(other.seed),
+            max_error: self.max_error.or(other.max_error),
+            max_iter: self.max_iter.or(other.max_iter),
+            output: self.output.or(other.output),
+            isa_output: self.isa_output.or(other.isa_output),
+            reorder_window: self.reorder_window.or(other.reorder_window),
+        }
+    }
+
+    /// Check that all required fields are set and apply defaults to the others.
+    /// A random seed is drawn if none is given so that the run can be reproduced.
+    pub fn resolve(self) -> Result<Scenario, String> {
+        let missing =
+            |field: &str| format!("The {field} should be set in the scenario or by a flag");
+        Ok(Scenario {
+            qubits: self.qubits.ok_or_else(|| missing("qubits"))?,
+            code: self.code.ok_or_else(|| missing("code"))?,
+            noise: self.noise.ok_or_else(|| missing("noise"))?,
+            measurement_table: self
+                .measurement_table
+                .ok_or_else(|| missing("measurement_table"))?,
+            accuracy: self.accuracy,
+            angle: self.angle,
+            seed: self.seed.unwrap_or_else(rand::random),
+            max_error: self.max_error.unwrap_or(1.0 / 3.0),
+            max_iter: self.max_iter.unwrap_or(10_usize.pow(5)),
+            output: self.output,
+            isa_output: self.isa_output,
+            reorder_window: self.reorder_window,
+        })
+    }
+}
+
+/// A fully resolved scenario
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Number of logical qubits in the circuit
+    pub qubits: usize,
+    /// What code to use
+    pub code: MeasurementChoices,
+    /// The physical error rate
+    pub noise: ErrorRate,
+    /// A cache file name that stores a Clifford synthesis table
+    pub measurement_table: String,
+    /// The small-angle synthesis precision, defaults to the in-module measurement error
+    pub accuracy: Option<AnglePrecision>,
+    /// The angle of the random rotations, defaults to π/4, which needs a single T gate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle: Option<AnglePrecision>,
+    /// Seed of the random circuit
+    pub seed: u64,
+    /// The cumulative logical error rate at which to halt
+    pub max_error: f64,
+    /// The maximum number of gates to simulate and halt at
+    pub max_iter: usize,
+    /// File to write the output to instead of stdout
+    pub output: Option<String>,
+    /// File to also write the compiled instructions to
+    pub isa_output: Option<String>,
+    /// Reorder commuting rotations within a window of this many upcoming rotations to reduce idling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reorder_window: Option<usize>,
+}
+
+impl Scenario {
+    /// The scenario as a scenario file, for provenance of the output
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("The scenario should be serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_resolve() -> Result<(), Box<dyn Error>> {
+        let scenario: PartialScenario = toml::from_str(
+            r#"
+            qubits = 22
+            code = "two-gross"
+            noise = "1e-4"
+            measurement_table = "table_two-gross"
+            seed = 7
+            max_iter = 100
+            angle = "0.1"
+            "#,
+        )?;
+        let overrides = PartialScenario {
+            qubits: Some(33),
+            ..Default::default()
+        };
+
+        let resolved = overrides.or(scenario).resolve()?;
+        assert_eq!(33, resolved.qubits);
+        assert_eq!(MeasurementChoices::TwoGross, resolved.code);
+        assert_eq!(ErrorRate::E4, resolved.noise);
+        assert_eq!(7, resolved.seed);
+        assert_eq!(100, resolved.max_iter);
+        assert_eq!(Some(AnglePrecision::lit("0.1")), resolved.angle);
+        assert_eq!(1.0 / 3.0, resolved.max_error);
+
+        // The provenance reproduces the scenario
+        assert_eq!(resolved, toml::from_str(&resolved.to_toml())?);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_field() {
+        let scenario = PartialScenario {
+            qubits: Some(11),
+            ..Default::default()
+        };
+        assert!(scenario.resolve().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshot rows of the random-circuit numerics, to catch changes that shift the results.
+//!
+//! The rows are the output of this tool with seed 1, not values of the paper.
+//! The random-circuit table of Tour de Gross (arXiv:2506.03094) averages eight runs of each configuration
+//! with unrecorded random circuits until the error budget is exhausted, see `scripts/run_random_numerics.py`,
+//! so a single seeded row cannot be compared to it. `scripts/reproduce_paper_tables.sh` runs those averages.
+//! The codes, error rates, and qubits of the rows follow the table, see `scripts/parameters.csv`,
+//! with few enough rotations to run in seconds.
+//! A change that moves a row on purpose should record the new output here.
+//!
+//! The search for the measurement table is deterministic, so every table that is generated by the same version
+//! gives the same end time. The total error is compared up to rounding.
+//! The row with a small angle needs a synthesizer and is only checked with the `rsgridsynth` feature,
+//! whose sequences it records.
+
+use std::fmt::Display;
+
+use bicycle_cliffords::MeasurementChoices;
+use bicycle_compiler::language::AnglePrecision;
+
+use crate::{
+    Output,
+    scenario::{ErrorRate, Scenario},
+};
+
+/// Relative tolerance of the total error
+pub const TOTAL_ERROR_TOLERANCE: f64 = 1e-9;
+
+/// The output row `i` of the random circuit of a seed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotRow {
+    pub code: MeasurementChoices,
+    pub noise: ErrorRate,
+    pub qubits: usize,
+    pub seed: u64,
+    /// The angle of the random rotations, or π/4 if not set
+    pub angle: Option<AnglePrecision>,
+    pub i: usize,
+    /// T injections of input line `i`
+    pub t_injs: u64,
+    pub end_time: u64,
+    pub total_error: f64,
+}
+
+pub const SNAPSHOT_ROWS: &[SnapshotRow] = {
+    use ErrorRate::{E3, E4};
+    use MeasurementChoices::{Gross, TwoGross};
+    const fn row(
+        code: MeasurementChoices,
+        noise: ErrorRate,
+        qubits: usize,
+        i: usize,
+        end_time: u64,
+        total_error: f64,
+    ) -> SnapshotRow {
+        SnapshotRow {
+            code,
+            noise,
+            qubits,
+            seed: 1,
+            angle: None,
+            i,
+            t_injs: 1,
+            end_time,
+            total_error,
+        }
+    }
+    &[
+        // The error budget of 1/3 is exhausted after the next rotation
+        row(Gross, E3, 121, 13, 44094, 0.32029935464),
+        row(Gross, E4, 110, 1000, 3145568, 0.00150827538773136),
+        row(TwoGross, E3, 704, 1000, 7172433, 9.642125989472747e-5),
+        row(TwoGross, E4, 440, 1000, 5542028, 4.7767759998468026e-14),
+        // A small angle is synthesized into many T gates
+        #[cfg(feature = "rsgridsynth")]
+        SnapshotRow {
+            angle: Some(AnglePrecision::lit("0.1")),
+            t_injs: 94,
+            ..row(Gross, E4, 110, 100, 2428504, 0.00832554631773604)
+        },
+    ]
+};
+
+impl SnapshotRow {
+    /// The scenario that outputs this row last
+    pub fn scenario(&self, measurement_table: &str) -> Scenario {
+        Scenario {
+            qubits: self.qubits,
+            code: self.code,
+            noise: self.noise,
+            measurement_table: measurement_table.to_string(),
+            accuracy: None,
+            angle: self.angle,
+            seed: self.seed,
+            max_error: 1.0 / 3.0,
+            max_iter: self.i,
+            output: None,
+            isa_output: None,
+            reorder_window: None,
+        }
+    }
+
+    /// Check an output row against this row, describing each value that is off
+    pub fn check(&self, output: &Output) -> Result<(), String> {
+        let mut errors = vec![];
+        if output.i != self.i {
+            errors.push(format!("the run stopped at row {}", output.i));
+        } else {
+            if output.t_injs != self.t_injs {
+                errors.push(format!(
+                    "t_injs {} instead of {}",
+                    output.t_injs, self.t_injs
+                ));
+            }
+            if output.end_time != self.end_time {
+                errors.push(format!(
+                    "end_time {} instead of {}",
+                    output.end_time, self.end_time
+                ));
+            }
+            if (output.total_error - self.total_error).abs()
+                > TOTAL_ERROR_TOLERANCE * self.total_error.abs()
+            {
+                errors.push(format!(
+                    "total_error {} instead of {}",
+                    output.total_error, self.total_error
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{self}: {}", errors.join(", ")))
+        }
+    }
+}
+
+impl Display for SnapshotRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} p={} on {} qubits",
+            self.code,
+            f64::from(self.noise),
+            self.qubits,
+        )?;
+        if let Some(angle) = self.angle {
+            write!(f, " with angle {angle}")?;
+        }
+        write!(f, " with seed {}, row {}", self.seed, self.i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_tolerances() {
+        let row = SNAPSHOT_ROWS[0];
+        let output = Output {
+            code: row.code.to_string(),
+            p: row.noise.into(),
+            i: row.i,
+            qubits: row.qubits,
+            t_injs: row.t_injs,
+            automorphisms: 0,
+            measurements: 0,
+            joint_measurements: 0,
+            measurement_depth: 0,
+            end_time: row.end_time,
+            total_error: row.total_error * (1.0 - TOTAL_ERROR_TOLERANCE / 2.0),
+            peak_memory_bytes: None,
+        };
+        assert_eq!(Ok(()), row.check(&output));
+
+        let shifted = Output {
+            end_time: row.end_time + 1,
+            t_injs: 0,
+            ..output.clone()
+        };
+        let err = row.check(&shifted).unwrap_err();
+        assert!(err.contains("end_time") && err.contains("t_injs"), "{err}");
+        assert!(!err.contains("total_error"), "{err}");
+
+        let stopped = Output { i: 12, ..output };
+        assert!(row.check(&stopped).unwrap_err().contains("stopped"));
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sweeps of random numerics experiments over a grid of qubit numbers and models
+
+use std::{fmt::Display, str::FromStr};
+
+use bicycle_cliffords::MeasurementChoices;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::scenario::ErrorRate;
+
+/// The numbers of qubits of a sweep, given as `qubits=<START>..<END>:<STEP>` with an inclusive end,
+/// where the step defaults to 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QubitSweep {
+    start: usize,
+    end: usize,
+    step: usize,
+}
+
+impl QubitSweep {
+    pub fn qubits(&self) -> impl Iterator<Item = usize> {
+        (self.start..=self.end).step_by(self.step)
+    }
+}
+
+impl FromStr for QubitSweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let range = match s.split_once('=') {
+            Some(("qubits", range)) => range,
+            Some((parameter, _)) => {
+                return Err(format!("Cannot sweep {parameter}, only the qubits"));
+            }
+            None => return Err(format!("Expected qubits=<START>..<END>:<STEP>, not {s}")),
+        };
+        let (range, step) = range.split_once(':').unwrap_or((range, "1"));
+        let (start, end) = range
+            .split_once("..")
+            .ok_or_else(|| format!("Expected a range <START>..<END>, not {range}"))?;
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("Invalid number {n}: {e}"))
+        };
+        let sweep = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+            step: parse(step)?,
+        };
+        if sweep.start == 0 || sweep.step == 0 || sweep.start > sweep.end {
+            return Err(format!(
+                "The range {s} should start at a positive number of qubits, not after its end, and have a positive step"
+            ));
+        }
+        Ok(sweep)
+    }
+}
+
+/// A code at a physical error rate, named like the models of `bicycle_numerics`, e.g., `gross_1e-3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelChoice {
+    pub code: MeasurementChoices,
+    pub noise: ErrorRate,
+}
+
+impl FromStr for ModelChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, noise) = s
+            .rsplit_once('_')
+            .ok_or_else(|| format!("Expected a model <CODE>_<NOISE>, e.g., gross_1e-3, not {s}"))?;
+        Ok(Self {
+            code: MeasurementChoices::from_str(code, false)?,
+            noise: ErrorRate::from_str(noise, false)?,
+        })
+    }
+}
+
+impl Display for ModelChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let noise = self.noise.to_possible_value().unwrap();
+        write!(f, "{}_{}", self.code, noise.get_name())
+    }
+}
+
+/// The columns that identify the configuration of a row of a sweep
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Configuration {
+    /// Index of the configuration, ordered by model and then by number of qubits
+    pub config: usize,
+    pub seed: u64,
+}
+
+/// The path of the measurement table of a code, where `{code}` in `template` is replaced by its name
+pub fn table_path(template: &str, code: MeasurementChoices) -> String {
+    template.replace("{code}", &code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sweep() {
+        let sweep: QubitSweep = "qubits=12..48:12".parse().unwrap();
+        assert_eq!(vec![12, 24, 36, 48], sweep.qubits().collect::<Vec<_>>());
+        let sweep: QubitSweep = "qubits=11..13".parse().unwrap();
+        assert_eq!(vec![11, 12, 13], sweep.qubits().collect::<Vec<_>>());
+
+        for invalid in [
+            "seed=1..3",
+            "12..48:12",
+            "qubits=48..12",
+            "qubits=0..12",
+            "qubits=1..2:0",
+        ] {
+            assert!(invalid.parse::<QubitSweep>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_models() {
+        for name in [
+            "gross_1e-3",
+            "gross_1e-4",
+            "two-gross_1e-3",
+            "two-gross_1e-4",
+        ] {
+            let model: ModelChoice = name.parse().unwrap();
+            assert_eq!(name, model.to_string());
+        }
+        assert_eq!(
+            ModelChoice {
+                code: MeasurementChoices::TwoGross,
+                noise: ErrorRate::E4
+            },
+            "two-gross_1e-4".parse().unwrap()
+        );
+        assert!("gross".parse::<ModelChoice>().is_err());
+        assert!("gross_1e-5".parse::<ModelChoice>().is_err());
+        assert_eq!(
+            "data/table_two-gross",
+            table_path("data/table_{code}", MeasurementChoices::TwoGross)
+        );
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright contributors to the Bicycle Architecture Compiler project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Python bindings of the compiler and the numerics, on top of the stable interface of the `bicycle` crate.
+//!
+//! Operations cross the boundary as the JSON values that the command line tools read and write:
+//! PBC operations as in the input of `bicycle_compiler`, and compiled operations as lists of `[block, instruction]` pairs.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bicycle::{
+    AnglePrecision, CompleteMeasurementTable, FAKE_SLOW, GROSS_1E3, GROSS_1E4, MeasurementChoices,
+    Model, Operation, OutputData, PbcBuilder, PbcOperation, TWO_GROSS_1E3, TWO_GROSS_1E4,
+    check_accuracy, check_barrier, check_operation, optimize,
+};
+use pyo3::{
+    exceptions::{PyIOError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Blocks on a path with a magic state factory next to the magic block
+#[pyclass(name = "PathArchitecture", module = "pyftir", frozen)]
+#[derive(Clone)]
+struct PyPathArchitecture(bicycle::PathArchitecture);
+
+#[pymethods]
+impl PyPathArchitecture {
+    #[new]
+    fn new(data_blocks: usize) -> Self {
+        Self(bicycle::PathArchitecture::new(data_blocks))
+    }
+
+    /// Enough blocks to hold the given number of logical qubits
+    #[staticmethod]
+    fn for_qubits(qubits: usize) -> Self {
+        Self(bicycle::PathArchitecture::for_qubits(qubits))
+    }
+
+    #[getter]
+    fn data_blocks(&self) -> usize {
+        self.0.data_blocks()
+    }
+
+    #[getter]
+    fn qubits(&self) -> usize {
+        self.0.qubits()
+    }
+
+    #[getter]
+    fn magic_block(&self) -> usize {
+        self.0.magic_block
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PathArchitecture(data_blocks={}, magic_block={})",
+            self.0.data_blocks(),
+            self.0.magic_block
+        )
+    }
+}
+
+/// The Clifford synthesis of every Pauli measurement on a block of a code
+#[pyclass(name = "MeasurementTable", module = "pyftir", frozen)]
+struct PyMeasurementTable(Arc<CompleteMeasurementTable>);
+
+#[pymethods]
+impl PyMeasurementTable {
+    /// Load a table that is written by `bicycle_compiler generate` or by `save`
+    #[staticmethod]
+    fn load(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let table = py
+            .allow_threads(|| bicycle::deserialize_table(&path).map_err(|err| err.to_string()))
+            .map_err(|err| PyIOError::new_err(format!("{}: {err}", path.display())))?;
+        Ok(Self(Arc::new(table)))
+    }
+
+    /// Write the table in the raw layout, which is mapped into memory when it is loaded
+    fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        py.allow_threads(|| {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            self.0.write_raw(&mut writer)?;
+            writer.flush()
+        })
+        .map_err(|err: io::Error| PyIOError::new_err(format!("{}: {err}", path.display())))
+    }
+}
+
+/// The tables that were built in this process, since building one takes minutes
+static TABLES: Mutex<Vec<(MeasurementChoices, Arc<CompleteMeasurementTable>)>> =
+    Mutex::new(Vec::new());
+
+fn parse_code(code: &str) -> PyResult<MeasurementChoices> {
+    serde_json::from_value(code.into()).map_err(|_| {
+        PyValueError::new_err(format!(
+            "Unknown code {code}, expected \"gross\" or \"two-gross\""
+        ))
+    })
+}
+
+/// Build the measurement table of a code ("gross" or "two-gross"), or reuse the table that was built before
+#[pyfunction]
+#[pyo3(signature = (code = "gross"))]
+fn build_measurement_table(py: Python<'_>, code: &str) -> PyResult<PyMeasurementTable> {
+    let code = parse_code(code)?;
+    if let Some((_, table)) = TABLES.lock().unwrap().iter().find(|(c, _)| *c == code) {
+        return Ok(PyMeasurementTable(Arc::clone(table)));
+    }
+    let table = Arc::new(py.allow_threads(|| bicycle::build_measurement_table(code)));
+    TABLES.lock().unwrap().push((code, Arc::clone(&table)));
+    Ok(PyMeasurementTable(table))
+}
+
+/// Convert a value to Python objects through its JSON representation
+fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json =
+        serde_json::to_string(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// Convert Python objects to a value through their JSON representation
+fn from_python<T: DeserializeOwned>(value: &Bound<'_, PyAny>) -> PyResult<T> {
+    let json: String = value
+        .py()
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&json).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+/// Parse PBC operations from a JSON list or from a stream of JSON values, e.g., JSON lines
+fn parse_operations(ops_json: &str) -> PyResult<Vec<PbcOperation>> {
+    let invalid =
+        |err: serde_json::Error| PyValueError::new_err(format!("Invalid operation: {err}"));
+    if ops_json.trim_start().starts_with('[') {
+        return serde_json::from_str(ops_json).map_err(invalid);
+    }
+    serde_json::Deserializer::from_str(ops_json)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .map_err(invalid)
+}
+
+/// Check the synthesis accuracy and the operations before they are compiled on `architecture`,
+/// like the input checks of `bicycle_compiler`, such that malformed input raises a `ValueError` instead of a panic
+fn check_operations(
+    ops: &[PbcOperation],
+    architecture: &bicycle::PathArchitecture,
+    accuracy: f64,
+) -> PyResult<AnglePrecision> {
+    let accuracy = AnglePrecision::checked_from_num(accuracy)
+        .ok_or_else(|| format!("The synthesis accuracy {accuracy} is out of range"))
+        .and_then(|precision| check_accuracy(precision).map(|()| precision))
+        .map_err(PyValueError::new_err)?;
+    for (index, op) in ops.iter().enumerate() {
+        let qubits = op.basis().len();
+        check_operation(op, architecture.qubits_per_block())
+            .and_then(|()| check_barrier(op, architecture.total_blocks()))
+            .and_then(|()| {
+                if qubits <= architecture.qubits() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "The operation acts on {qubits} qubits, but the architecture holds only {} qubits",
+                        architecture.qubits()
+                    ))
+                }
+            })
+            .map_err(|error| PyValueError::new_err(format!("Invalid operation {index}: {error}")))?;
+    }
+    Ok(accuracy)
+}
+
+/// Compile PBC operations in JSON to a list of operations of bicycle instructions.
+///
+/// Small-angle rotations are synthesized up to `accuracy`.
+/// The architecture defaults to enough blocks for the qubits of the first operation,
+/// and the measurement table defaults to the table of `code`, which is built on the first call.
+/// Bases that do not fill whole blocks are padded with identities,
+/// and malformed operations raise a `ValueError` before anything is compiled.
+#[pyfunction]
+#[pyo3(signature = (ops_json, code = "gross", accuracy = 1e-9, architecture = None, measurement_table = None))]
+fn compile<'py>(
+    py: Python<'py>,
+    ops_json: &str,
+    code: &str,
+    accuracy: f64,
+    architecture: Option<PyPathArchitecture>,
+    measurement_table: Option<&PyMeasurementTable>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let ops = parse_operations(ops_json)?;
+    let table = match measurement_table {
+        Some(table) => Arc::clone(&table.0),
+        None => build_measurement_table(py, code)?.0,
+    };
+    let architecture = match architecture {
+        Some(architecture) => architecture.0,
+        None => bicycle::PathArchitecture::for_qubits(ops.first().map_or(0, |op| op.basis().len())),
+    };
+    let accuracy = check_operations(&ops, &architecture, accuracy)?;
+    let compiled = compile_operations(py, &ops, &architecture, &table, accuracy);
+    to_python(py, &compiled)
+}
+
+fn compile_operations(
+    py: Python<'_>,
+    ops: &[PbcOperation],
+    architecture: &bicycle::PathArchitecture,
+    table: &CompleteMeasurementTable,
+    accuracy: AnglePrecision,
+) -> Vec<Operation> {
+    py.allow_threads(|| {
+        let chunks = ops.iter().map(|op| {
+            optimize::remove_trivial_automorphisms(bicycle::compile(
+                op,
+                architecture,
+                table,
+                accuracy,
+            ))
+            .collect::<Vec<_>>()
+        });
+        optimize::remove_duplicate_measurements_chunked(chunks)
+            .flatten()
+            .collect()
+    })
+}
+
+/// Add the instructions of a Qiskit circuit to `builder`, where `qubits` are the qubits of the builder
+/// that the qubits of the circuit map to.
+/// Gates that the builder does not support are replaced by their Qiskit definition.
+fn add_qiskit_circuit(
+    builder: &mut PbcBuilder,
+    circuit: &Bound<'_, PyAny>,
+    qubits: &[usize],
+) -> PyResult<()> {
+    for instruction in circuit.getattr("data")?.try_iter()? {
+        let instruction = instruction?;
+        let operation = instruction.getattr("operation")?;
+        let name: String = operation.getattr("name")?.extract()?;
+        let operands = instruction
+            .getattr("qubits")?
+            .try_iter()?
+            .map(|qubit| {
+                let index: usize = circuit
+                    .call_method1("find_bit", (qubit?,))?
+                    .getattr("index")?
+                    .extract()?;
+                Ok(qubits[index])
+            })
+            .collect::<PyResult<Vec<usize>>>()?;
+        match name.as_str() {
+            "barrier" | "delay" | "global_phase" => continue,
+            "measure" | "reset" => {
+                operands.iter().for_each(|&qubit| builder.measure(qubit));
+                continue;
+            }
+            // The parameters of control flow operations are their blocks, which are circuits and not gates
+            "if_else" | "while_loop" | "for_loop" | "switch_case" | "break_loop"
+            | "continue_loop" | "box" => {
+                return Err(PyValueError::new_err(format!(
+                    "Control flow is not supported: {name}"
+                )));
+            }
+            _ => {}
+        }
+        let params = operation
+            .getattr("params")?
+            .try_iter()?
+            .map(|param| {
+                param?.extract::<f64>().map_err(|_| {
+                    PyValueError::new_err(format!(
+                        "The parameters of {name} should be bound to numbers"
+                    ))
+                })
+            })
+            .collect::<PyResult<Vec<f64>>>()?;
+        if let Err(message) = builder.gate(&name, &operands, &params) {
+            let definition = operation.getattr("definition")?;
+            if definition.is_none() {
+                return Err(PyValueError::new_err(message));
+            }
+            add_qiskit_circuit(builder, &definition, &operands)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compile a Qiskit `QuantumCircuit` to bicycle instructions and estimate its resources in one call.
+///
+/// The gates are written as Pauli rotations whose Clifford part is absorbed into a frame,
+/// following Litinski's transformation, which leaves non-Clifford rotations and measurements.
+/// Returns a dictionary of the number of qubits, the PBC operations, the compiled operations,
+/// and the numerics of the compiled operations under `model`, which defaults to the `1e-3` model of `code`.
+#[pyfunction]
+#[pyo3(signature = (circuit, code = "gross", accuracy = 1e-9, model = None, measurement_table = None))]
+fn compile_qiskit<'py>(
+    circuit: &Bound<'py, PyAny>,
+    code: &str,
+    accuracy: f64,
+    model: Option<&str>,
+    measurement_table: Option<&PyMeasurementTable>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = circuit.py();
+    let qubits: usize = circuit.getattr("num_qubits")?.extract()?;
+    let mut builder = PbcBuilder::new(qubits);
+    add_qiskit_circuit(&mut builder, circuit, &(0..qubits).collect::<Vec<_>>())?;
+    let ops = builder.finish();
+
+    parse_code(code)?;
+    let model = parse_model(model.unwrap_or(&format!("{code}_1e-3")))?;
+    let table = match measurement_table {
+        Some(table) => Arc::clone(&table.0),
+        None => build_measurement_table(py, code)?.0,
+    };
+    let architecture = bicycle::PathArchitecture::for_qubits(qubits);
+    let accuracy = check_operations(&ops, &architecture, accuracy)?;
+    let compiled = compile_operations(py, &ops, &architecture, &table, accuracy);
+
+    let result = PyDict::new(py);
+    result.set_item("qubits", qubits)?;
+    result.set_item("pbc", to_python(py, &ops)?)?;
+    result.set_item("compiled", to_python(py, &compiled)?)?;
+    let estimates: Option<OutputData> = py
+        .allow_threads(|| {
+            bicycle::run_numerics(std::iter::once(compiled), architecture, model).last()
+        })
+        .transpose()
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    result.set_item("estimates", to_python(py, &estimates)?)?;
+    Ok(result.into_any())
+}
+
+fn parse_model(model: &str) -> PyResult<Model> {
+    Ok(match model {
+        "gross_1e-3" => GROSS_1E3,
+        "gross_1e-4" => GROSS_1E4,
+        "two-gross_1e-3" => TWO_GROSS_1E3,
+        "two-gross_1e-4" => TWO_GROSS_1E4,
+        "fake_slow" => FAKE_SLOW,
+        path => Model::from_path(path.as_ref())
+            .map_err(|err| PyValueError::new_err(format!("Invalid model {path}: {err}")))?,
+    })
+}
+
+/// Compute the numerics of compiled operations under a model, which is the name of a built-in model
+/// of `bicycle_numerics` or the path of a JSON or TOML model file.
+/// Returns the counts, the end time, and the total error of the program as a dictionary.
+#[pyfunction]
+#[pyo3(signature = (ops, model = "gross_1e-3", architecture = None))]
+fn run_numerics<'py>(
+    ops: &Bound<'py, PyAny>,
+    model: &str,
+    architecture: Option<PyPathArchitecture>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = ops.py();
+    let ops: Vec<Operation> = from_python(ops)?;
+    let model = parse_model(model)?;
+    let architecture = match architecture {
+        Some(architecture) => architecture.0,
+        None => {
+            let blocks = ops.iter().flatten().map(|(block, _)| block + 1).max();
+            bicycle::PathArchitecture::new(blocks.unwrap_or(1))
+        }
+    };
+
+    let data: Option<OutputData> = py
+        .allow_threads(|| bicycle::run_numerics(std::iter::once(ops), architecture, model).last())
+        .transpose()
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    to_python(py, &data)
+}
+
+#[pymodule]
+fn pyftir(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPathArchitecture>()?;
+    m.add_class::<PyMeasurementTable>()?;
+    m.add_function(wrap_pyfunction!(build_measurement_table, m)?)?;
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(compile_qiskit, m)?)?;
+    m.add_function(wrap_pyfunction!(run_numerics, m)?)?;
+    Ok(())
+}
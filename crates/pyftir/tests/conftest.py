@@ -0,0 +1,34 @@
+# Copyright contributors to the Bicycle Architecture Compiler project
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+"""Fixtures of the pyftir tests."""
+
+import os
+
+import pytest
+
+import pyftir
+
+
+@pytest.fixture(scope="session")
+def table():
+    """The measurement table of the gross code.
+
+    Building the table takes minutes, so a table that is written by
+    `bicycle_compiler gross generate` is loaded from $PYFTIR_TABLE if it is set.
+    """
+    path = os.environ.get("PYFTIR_TABLE")
+    if path:
+        return pyftir.MeasurementTable.load(path)
+    return pyftir.build_measurement_table("gross")
@@ -0,0 +1,140 @@
+# Copyright contributors to the Bicycle Architecture Compiler project
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+"""Tests of `compile`, `run_numerics`, and their checks of the input."""
+
+import json
+from pathlib import Path
+
+import pytest
+
+import pyftir
+
+MODELS = Path(__file__).parents[2] / "bicycle_numerics" / "models"
+
+
+def measurement(basis, flip_result=False):
+    return {"Measurement": {"basis": list(basis), "flip_result": flip_result}}
+
+
+# Measurements on both blocks of two, which need no gridsynth
+OPS = [
+    measurement("ZX" + "I" * 20),
+    measurement("I" * 11 + "Y" + "I" * 10, flip_result=True),
+]
+
+
+def test_compile_round_trip(table):
+    compiled = pyftir.compile(json.dumps(OPS), measurement_table=table)
+    assert compiled
+    for op in compiled:
+        for block, instruction in op:
+            assert block in (0, 1)
+            assert len(instruction) == 1
+
+    # JSON lines are the same program as a JSON list
+    lines = "\n".join(json.dumps(op) for op in OPS)
+    assert pyftir.compile(lines, measurement_table=table) == compiled
+
+    architecture = pyftir.PathArchitecture(2)
+    explicit = pyftir.compile(
+        json.dumps(OPS), architecture=architecture, measurement_table=table
+    )
+    assert explicit == compiled
+
+
+def test_run_numerics_round_trip(table):
+    compiled = pyftir.compile(json.dumps(OPS), measurement_table=table)
+    data = pyftir.run_numerics(compiled, model="gross_1e-3")
+    assert data["qubits"] == 22
+    assert data["t_injs"] == 0
+    assert data["measurements"] > 0
+    assert data["end_time"] > 0
+    assert 0 < data["total_error"] < 1
+
+    # The compiled operations are plain JSON values, so they survive a round trip through JSON
+    assert pyftir.run_numerics(json.loads(json.dumps(compiled))) == data
+    assert (
+        pyftir.run_numerics(compiled, architecture=pyftir.PathArchitecture(2)) == data
+    )
+
+    better = pyftir.run_numerics(compiled, model="gross_1e-4")
+    assert better["end_time"] == data["end_time"]
+    assert better["total_error"] < data["total_error"]
+
+
+def test_parse_model_from_path(table):
+    compiled = pyftir.compile(json.dumps(OPS), measurement_table=table)
+    for model in ["gross_1e-3.json", "gross_1e-3.toml"]:
+        data = pyftir.run_numerics(compiled, model=str(MODELS / model))
+        assert data == pyftir.run_numerics(compiled, model="gross_1e-3")
+
+
+@pytest.mark.parametrize("model", ["gross_1e-5", "does/not/exist.json"])
+def test_parse_model_unknown(model):
+    with pytest.raises(ValueError, match=f"Invalid model {model}"):
+        pyftir.run_numerics([], model=model)
+
+
+def test_parse_model_malformed(tmp_path):
+    path = tmp_path / "model.json"
+    path.write_text('{"code": "gross", "p": 0.001}')
+    with pytest.raises(ValueError, match="Invalid model"):
+        pyftir.run_numerics([], model=str(path))
+
+
+def test_run_numerics_malformed():
+    with pytest.raises(ValueError):
+        pyftir.run_numerics([[[0, {"Jump": {}}]]])
+
+
+@pytest.mark.parametrize(
+    "ops_json, message",
+    [
+        ("{", "Invalid operation: EOF"),
+        ('[{"Measurement": {"basis": ["Q"], "flip_result": false}}]', "unknown variant `Q`"),
+        ('[{"Rotate": {"basis": ["Z"]}}]', "Invalid operation"),
+        (
+            json.dumps([measurement("I" * 11)]),
+            "Invalid operation 0: The basis of the operation is the identity",
+        ),
+        (
+            json.dumps([measurement("Z" * 11), measurement("Z" * 23)]),
+            "Invalid operation 1: The operation acts on 23 qubits, but the architecture holds only 11 qubits",
+        ),
+    ],
+)
+def test_check_operations_malformed(table, ops_json, message):
+    with pytest.raises(ValueError, match=message):
+        pyftir.compile(ops_json, measurement_table=table)
+
+
+def test_check_operations_architecture(table):
+    with pytest.raises(ValueError, match="architecture holds only 11 qubits"):
+        pyftir.compile(
+            json.dumps(OPS),
+            architecture=pyftir.PathArchitecture(1),
+            measurement_table=table,
+        )
+
+
+@pytest.mark.parametrize("accuracy", [0.0, -1e-9, 0.5, float("nan")])
+def test_check_operations_accuracy(table, accuracy):
+    with pytest.raises(ValueError, match="accuracy"):
+        pyftir.compile(json.dumps(OPS), accuracy=accuracy, measurement_table=table)
+
+
+def test_unknown_code():
+    with pytest.raises(ValueError, match="Unknown code"):
+        pyftir.build_measurement_table("three-gross")
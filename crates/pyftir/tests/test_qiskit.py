@@ -0,0 +1,116 @@
+# Copyright contributors to the Bicycle Architecture Compiler project
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+"""Tests of `compile_qiskit`."""
+
+import pytest
+
+import pyftir
+
+qiskit = pytest.importorskip("qiskit")
+
+
+def measurement(basis, flip_result=False):
+    return {"Measurement": {"basis": list(basis), "flip_result": flip_result}}
+
+
+def test_compile_qiskit(table):
+    circuit = qiskit.QuantumCircuit(3)
+    circuit.h(0)
+    circuit.ccx(0, 1, 2)
+    circuit.t(2)
+    circuit.measure_all()
+    result = pyftir.compile_qiskit(circuit, measurement_table=table)
+    assert result["qubits"] == 3
+    assert all(len(op) == 1 for op in result["pbc"])
+    assert result["compiled"]
+    assert result["estimates"]["t_injs"] > 0
+    assert result["estimates"] == pyftir.run_numerics(result["compiled"])
+
+
+def test_measure_and_reset(table):
+    circuit = qiskit.QuantumCircuit(2, 2)
+    circuit.h(0)
+    circuit.measure(0, 0)
+    circuit.reset(1)
+    circuit.x(1)
+    circuit.measure(1, 1)
+    result = pyftir.compile_qiskit(circuit, measurement_table=table)
+    # Measuring Z after H measures X, and a reset is a Z measurement whose flip is left to the Pauli frame
+    assert result["pbc"] == [
+        measurement("XI"),
+        measurement("IZ"),
+        measurement("IZ", flip_result=True),
+    ]
+
+
+def test_definitions_are_applied(table):
+    bell = qiskit.QuantumCircuit(2, name="bell")
+    bell.h(0)
+    bell.cx(0, 1)
+    nested = qiskit.QuantumCircuit(3, name="nested")
+    nested.append(bell.to_gate(), [2, 0])
+    nested.t(1)
+
+    circuit = qiskit.QuantumCircuit(3)
+    circuit.append(nested.to_gate(), [1, 2, 0])
+    circuit.measure_all()
+
+    # The gates of the definitions act on the qubits that the gates are applied to
+    inline = qiskit.QuantumCircuit(3)
+    inline.h(0)
+    inline.cx(0, 1)
+    inline.t(2)
+    inline.measure_all()
+
+    result = pyftir.compile_qiskit(circuit, measurement_table=table)
+    expected = pyftir.compile_qiskit(inline, measurement_table=table)
+    assert result["pbc"] == expected["pbc"]
+
+
+def test_unbound_parameters(table):
+    theta = qiskit.circuit.Parameter("θ")
+    circuit = qiskit.QuantumCircuit(1)
+    circuit.rz(theta, 0)
+    with pytest.raises(ValueError, match="The parameters of rz should be bound to numbers"):
+        pyftir.compile_qiskit(circuit, measurement_table=table)
+
+    bound = circuit.assign_parameters({theta: 0.25 * 3.141592653589793})
+    result = pyftir.compile_qiskit(bound, measurement_table=table)
+    assert len(result["pbc"]) == 1
+
+
+def test_if_else(table):
+    circuit = qiskit.QuantumCircuit(2, 1)
+    circuit.h(0)
+    circuit.measure(0, 0)
+    with circuit.if_test((circuit.clbits[0], 1)):
+        circuit.x(1)
+    with pytest.raises(ValueError, match="Control flow is not supported: if_else"):
+        pyftir.compile_qiskit(circuit, measurement_table=table)
+
+
+def test_loops(table):
+    circuit = qiskit.QuantumCircuit(1, 1)
+    with circuit.for_loop(range(2)):
+        circuit.t(0)
+    with pytest.raises(ValueError, match="Control flow is not supported: for_loop"):
+        pyftir.compile_qiskit(circuit, measurement_table=table)
+
+    circuit = qiskit.QuantumCircuit(1, 1)
+    with circuit.while_loop((circuit.clbits[0], 0)):
+        circuit.t(0)
+        circuit.measure(0, 0)
+    with pytest.raises(ValueError, match="Control flow is not supported: while_loop"):
+        pyftir.compile_qiskit(circuit, measurement_table=table)
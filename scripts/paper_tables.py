@@ -0,0 +1,118 @@
+#!/usr/bin/env python3
+# Copyright contributors to the Bicycle Architecture Compiler project
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+import csv
+import sys
+from collections import defaultdict
+from pathlib import Path
+
+##
+## Summarize the output of `random_numerics` into the random-circuit table of
+## Tour de Gross (arXiv:2506.03094): for each code, physical error rate, and
+## number of qubits, the average instruction counts and time per rotation,
+## and the circuit volume that is reached before the error budget is exhausted.
+##
+## The columns are our own and do not follow the layout of the tables in the paper;
+## compare them to the paper by quantity rather than by column.
+## Writing the published columns to one file and the others to a second file
+## needs the column list of the published table, which has not been checked
+## against the paper, so this script does not guess it. Each row has
+##   code, p, qubits      the configuration of the trials
+##   trials               the number of trials, which restart at i = 1 in the input
+##   t_injs, automorphisms, measurements, joint_measurements
+##                        the average number of instructions per rotation
+##   time_per_rotation    the average time per rotation in syndrome cycles
+##   error_per_rotation   the average logical error per rotation
+##   rotations            the average number of rotations until the error budget is exhausted
+##   volume               qubits times rotations
+##   time                 the average end time of a trial in syndrome cycles
+##
+## Usage: paper_tables.py [INPUT_CSV] [OUTPUT_CSV]
+## The defaults are ../data/random_numerics_output.csv and ../data/paper_tables.csv
+##
+
+SCRIPT_DIR = Path(__file__).resolve().parent
+DEFAULT_INPUT = SCRIPT_DIR / ".." / "data" / "random_numerics_output.csv"
+DEFAULT_OUTPUT = SCRIPT_DIR / ".." / "data" / "paper_tables.csv"
+
+# Instruction counts that are reported per rotation
+COUNTS = ["t_injs", "automorphisms", "measurements", "joint_measurements"]
+
+COLUMNS = [
+    "code",
+    "p",
+    "qubits",
+    "trials",
+    *COUNTS,
+    "time_per_rotation",
+    "error_per_rotation",
+    "rotations",
+    "volume",
+    "time",
+]
+
+
+def read_trials(pathname):
+    """Split the concatenated output into trials, which restart at i = 1."""
+    trials = defaultdict(list)
+    with open(pathname, newline="") as f:
+        for row in csv.DictReader(f):
+            key = (row["code"], float(row["p"]), int(row["qubits"]))
+            if int(row["i"]) == 1:
+                trials[key].append([])
+            trials[key][-1].append(row)
+    return trials
+
+
+def summarize(key, trials):
+    code, p, qubits = key
+    rows = [row for trial in trials for row in trial]
+    last_rows = [trial[-1] for trial in trials]
+
+    rotations = sum(int(row["i"]) for row in last_rows) / len(trials)
+    time = sum(int(row["end_time"]) for row in last_rows) / len(trials)
+    error = sum(float(row["total_error"]) for row in last_rows) / len(trials)
+
+    summary = {
+        "code": code,
+        "p": p,
+        "qubits": qubits,
+        "trials": len(trials),
+        "time_per_rotation": time / rotations,
+        "error_per_rotation": error / rotations,
+        "rotations": rotations,
+        "volume": qubits * rotations,
+        "time": time,
+    }
+    for count in COUNTS:
+        summary[count] = sum(int(row[count]) for row in rows) / len(rows)
+    return summary
+
+
+def main():
+    input_path = Path(sys.argv[1]) if len(sys.argv) > 1 else DEFAULT_INPUT
+    output_path = Path(sys.argv[2]) if len(sys.argv) > 2 else DEFAULT_OUTPUT
+
+    trials = read_trials(input_path)
+    with open(output_path, "w", newline="") as f:
+        writer = csv.DictWriter(f, fieldnames=COLUMNS)
+        writer.writeheader()
+        for key in sorted(trials):
+            writer.writerow(summarize(key, trials[key]))
+    print(f"Wrote {len(trials)} rows to {output_path}")
+
+
+if __name__ == "__main__":
+    main()
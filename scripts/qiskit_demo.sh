@@ -33,5 +33,5 @@ MEASUREMENT_TABLE="${INPUT_DATA_DIR}/table_${CODE}" # Location of measurement ta
 # compiler to yield Gross code instruction, which are then consumed by the numerics to produce
 # error estimates.
 python qiskit_demo.py $N \
-    | ${COMPILER_PATH}/bicycle_compiler ${CODE} --measurement-table ${MEASUREMENT_TABLE} \
+    | ${COMPILER_PATH}/bicycle_compiler ${CODE} --measurement-table ${MEASUREMENT_TABLE} --pad \
     | ${COMPILER_PATH}/bicycle_numerics $N ${CODE}_$P
@@ -0,0 +1,29 @@
+#!/bin/sh
+# Copyright contributors to the Bicycle Architecture Compiler project
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -euo pipefail
+
+# Change to this script's directory
+cd "$(dirname "$0")" || exit
+
+# Reproduce the random-circuit table of Tour de Gross from the current code base.
+# Builds the binaries, generates the measurement tables and random numerics
+# for the parameters in parameters.csv, and summarizes them in ../data/paper_tables.csv
+
+cargo build --release
+
+./generate_tables_and_random_numerics.sh
+
+python3 ./paper_tables.py ../data/random_numerics_output.csv ../data/paper_tables.csv